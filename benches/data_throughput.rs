@@ -0,0 +1,140 @@
+//! Data endpoint throughput for large local files.
+//!
+//! Serves a sparse BAM-sized file through `LocalStorage` and the `/data`
+//! handler. The file size defaults to 2 GiB; override it with
+//! `HTSGETR_BENCH_SIZE_MB`:
+//!
+//! ```bash
+//! HTSGETR_BENCH_SIZE_MB=8192 cargo bench --bench data_throughput
+//! ```
+
+use axum::body::Body;
+use axum::http::Request;
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use htsgetr::handlers::{AppState, create_router};
+use htsgetr::storage::{ByteRange, LocalStorage, Storage};
+use htsgetr::types::Format;
+use http_body_util::BodyExt;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn file_size() -> u64 {
+    std::env::var("HTSGETR_BENCH_SIZE_MB")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(2048)
+        * 1024
+        * 1024
+}
+
+fn data_throughput(c: &mut Criterion) {
+    let size = file_size();
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::File::create(dir.path().join("large.bam"))
+        .unwrap()
+        .set_len(size)
+        .unwrap();
+
+    let base_url = "http://localhost:8080".to_string();
+    let storage = Arc::new(LocalStorage::new(
+        dir.path().to_path_buf(),
+        base_url.clone(),
+    ));
+    let app = create_router(AppState {
+        storage: storage.clone(),
+        base_url,
+        #[cfg(feature = "auth")]
+        url_signer: None,
+        #[cfg(feature = "refget")]
+        refget: None,
+        trusted_proxies: None,
+        cache_control: None,
+        data_mode: Default::default(),
+        endpoints: Default::default(),
+        path_prefix: None,
+        standalone_headers: None,
+        default_formats: Default::default(),
+        ticket_cache: None,
+        plain_json_tickets: false,
+        ticket_url_ttl: None,
+        ticket_limits: Default::default(),
+        index_readers: Default::default(),
+        reference_aliases: None,
+        observers: Default::default(),
+        #[cfg(feature = "jws")]
+        ticket_signer: None,
+        #[cfg(feature = "checksums")]
+        checksums: Default::default(),
+    });
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("data");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(size));
+
+    group.bench_function("read_stream", |b| {
+        b.to_async(&rt).iter(|| async {
+            let mut reader = storage
+                .read_stream("large", Format::Bam, None)
+                .await
+                .unwrap();
+            tokio::io::copy(&mut reader, &mut tokio::io::sink())
+                .await
+                .unwrap()
+        })
+    });
+
+    group.bench_function("get_data", |b| {
+        b.to_async(&rt).iter(|| async {
+            let request = Request::get("/data/reads/large")
+                .body(Body::empty())
+                .unwrap();
+            drain(app.clone().oneshot(request).await.unwrap().into_body()).await
+        })
+    });
+
+    // Half the file, starting mid-way, as a ticket URL would request it
+    let half = size / 2;
+    group.throughput(Throughput::Bytes(half));
+    group.bench_function("get_data_range", |b| {
+        let uri = format!(
+            "/data/reads/large?start={}&end={}",
+            size / 4,
+            size / 4 + half
+        );
+        b.to_async(&rt).iter(|| async {
+            let request = Request::get(uri.as_str()).body(Body::empty()).unwrap();
+            drain(app.clone().oneshot(request).await.unwrap().into_body()).await
+        })
+    });
+
+    group.bench_function("read_bytes_range", |b| {
+        let range = ByteRange {
+            start: size / 4,
+            end: Some(size / 4 + half),
+        };
+        b.to_async(&rt).iter(|| async {
+            storage
+                .read_bytes("large", Format::Bam, Some(range.clone()))
+                .await
+                .unwrap()
+                .len()
+        })
+    });
+
+    group.finish();
+}
+
+/// Consume a response body chunk by chunk, returning its length.
+async fn drain(mut body: Body) -> u64 {
+    let mut len = 0;
+    while let Some(frame) = body.frame().await {
+        if let Ok(data) = frame.unwrap().into_data() {
+            len += data.len() as u64;
+        }
+    }
+    len
+}
+
+criterion_group!(benches, data_throughput);
+criterion_main!(benches);
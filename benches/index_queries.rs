@@ -0,0 +1,194 @@
+//! Index query and ticket construction latency.
+//!
+//! Covers the bundled BAI (`mt.bam.bai`, ~750 KB over 3366 contigs), CRAI
+//! and a generated VCF whose TBI spans three contigs. The VCF holds 200k
+//! variants by default; override with `HTSGETR_BENCH_VARIANTS`:
+//!
+//! ```bash
+//! HTSGETR_BENCH_VARIANTS=2000000 cargo bench --bench index_queries
+//! ```
+//!
+//! CSI is not covered: no BCF fixture is bundled and `htsgetr index` can't
+//! build CSI indexes.
+
+use axum::body::Body;
+use axum::http::Request;
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use htsgetr::formats::{BamIndexReader, CramIndexReader, VcfIndexReader};
+use htsgetr::handlers::{AppState, create_router};
+use htsgetr::storage::{ByteRange, LocalStorage};
+use htsgetr::types::Region;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const CONTIGS: [&str; 3] = ["chr1", "chr2", "chr3"];
+
+fn variant_count() -> u64 {
+    std::env::var("HTSGETR_BENCH_VARIANTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(200_000)
+}
+
+fn region(name: &str, start: u64, end: u64) -> Vec<Region> {
+    vec![Region {
+        reference_name: name.to_string(),
+        start: Some(start),
+        end: Some(end),
+    }]
+}
+
+/// Write a BGZF-compressed VCF with `count` variants, 100 bp apart, spread
+/// evenly over [`CONTIGS`], and index it.
+async fn generate_vcf(dir: &Path, count: u64) -> PathBuf {
+    let path = dir.join("synthetic.vcf.gz");
+    let file = std::fs::File::create(&path).unwrap();
+    let mut writer = noodles::bgzf::io::Writer::new(file);
+
+    let per_contig = count.div_ceil(CONTIGS.len() as u64);
+    writeln!(writer, "##fileformat=VCFv4.3").unwrap();
+    for name in CONTIGS {
+        writeln!(
+            writer,
+            "##contig=<ID={},length={}>",
+            name,
+            per_contig * 100 + 100
+        )
+        .unwrap();
+    }
+    writeln!(writer, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO").unwrap();
+    for name in CONTIGS {
+        for i in 0..per_contig {
+            writeln!(writer, "{}\t{}\t.\tA\tG\t60\tPASS\t.", name, i * 100 + 1).unwrap();
+        }
+    }
+    writer.finish().unwrap();
+
+    htsgetr::indexer::index_file(&path, true).await.unwrap();
+    path
+}
+
+fn index_queries(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let data = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+
+    let dir = tempfile::tempdir().unwrap();
+    let variants = variant_count();
+    let vcf = rt.block_on(generate_vcf(dir.path(), variants));
+    let tbi = PathBuf::from(format!("{}.tbi", vcf.display()));
+    let span = variants.div_ceil(CONTIGS.len() as u64) * 100;
+
+    let mut group = c.benchmark_group("query_ranges");
+
+    let bam = data.join("mt.bam");
+    let bai = data.join("mt.bam.bai");
+    let header = rt.block_on(BamIndexReader::read_header(&bam)).unwrap();
+    for (name, regions) in [
+        ("1mb", region("chr1", 0, 1_000_000)),
+        ("contig", region("chr1", 0, 248_956_422)),
+        ("chrM", region("chrM", 0, 16_569)),
+    ] {
+        group.bench_with_input(BenchmarkId::new("bai", name), &regions, |b, regions| {
+            b.to_async(&rt).iter(|| async {
+                BamIndexReader::query_ranges(&bam, &bai, regions, &header)
+                    .await
+                    .unwrap()
+            })
+        });
+    }
+
+    let cram = data.join("sample.cram");
+    let crai = data.join("sample.cram.crai");
+    let regions = region("chr1", 0, 1_000);
+    group.bench_function("crai/sample", |b| {
+        b.to_async(&rt).iter(|| async {
+            CramIndexReader::query_ranges(&cram, &crai, &regions)
+                .await
+                .unwrap()
+        })
+    });
+
+    for (name, regions) in [
+        ("10kb", region("chr1", span / 2, span / 2 + 10_000)),
+        ("contig", region("chr2", 0, span)),
+    ] {
+        group.bench_with_input(BenchmarkId::new("tbi", name), &regions, |b, regions| {
+            b.to_async(&rt).iter(|| async {
+                VcfIndexReader::query_ranges(&vcf, &tbi, regions)
+                    .await
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+
+    // Scattered chunks, as many small regions produce
+    let ranges: Vec<ByteRange> = (0..10_000u64)
+        .map(|i| {
+            let start = (i * 7919) % 10_000 * 100_000;
+            ByteRange {
+                start,
+                end: Some(start + 50_000),
+            }
+        })
+        .collect();
+    c.bench_function("merge_ranges/10k", |b| {
+        b.iter(|| BamIndexReader::merge_ranges(ranges.clone()))
+    });
+
+    let base_url = "http://localhost:8080".to_string();
+    let storage = Arc::new(LocalStorage::new(data.clone(), base_url.clone()));
+    let app = create_router(AppState {
+        storage,
+        base_url,
+        #[cfg(feature = "auth")]
+        url_signer: None,
+        #[cfg(feature = "refget")]
+        refget: None,
+        trusted_proxies: None,
+        cache_control: None,
+        data_mode: Default::default(),
+        endpoints: Default::default(),
+        path_prefix: None,
+        standalone_headers: None,
+        default_formats: Default::default(),
+        ticket_cache: None,
+        plain_json_tickets: false,
+        ticket_url_ttl: None,
+        ticket_limits: Default::default(),
+        index_readers: Default::default(),
+        reference_aliases: None,
+        observers: Default::default(),
+        #[cfg(feature = "jws")]
+        ticket_signer: None,
+        #[cfg(feature = "checksums")]
+        checksums: Default::default(),
+    });
+
+    let mut group = c.benchmark_group("ticket");
+    for (name, uri) in [
+        (
+            "reads_region",
+            "/reads/mt?referenceName=chr1&start=0&end=1000000",
+        ),
+        ("reads_header", "/reads/mt?class=header"),
+        (
+            "variants_region",
+            "/variants/sample?referenceName=chr1&start=0&end=1000",
+        ),
+    ] {
+        group.bench_function(name, |b| {
+            b.to_async(&rt).iter(|| async {
+                let request = Request::get(uri).body(Body::empty()).unwrap();
+                let response = app.clone().oneshot(request).await.unwrap();
+                assert!(response.status().is_success());
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, index_queries);
+criterion_main!(benches);
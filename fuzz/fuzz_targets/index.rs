@@ -0,0 +1,63 @@
+//! Fuzzed BAI and tabix bytes read as the index of the bundled sample
+//! files, as a corrupt or hostile index on remote storage would be. Bad
+//! indexes must surface as errors, never panics.
+//!
+//! The first byte picks the format; the rest is the index.
+
+#![no_main]
+
+use htsgetr::{
+    formats::{BamIndexReader, VcfIndexReader, read_index},
+    types::{Format, Region},
+};
+use libfuzzer_sys::fuzz_target;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+fn runtime() -> &'static tokio::runtime::Runtime {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, bytes)) = data.split_first() else {
+        return;
+    };
+
+    let mut index = tempfile::NamedTempFile::new().unwrap();
+    index.write_all(bytes).unwrap();
+    let index = index.path();
+
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
+    let regions = [
+        Region {
+            reference_name: "chr1".to_string(),
+            start: Some(0),
+            end: Some(1000),
+        },
+        Region {
+            reference_name: "chr2".to_string(),
+            start: None,
+            end: None,
+        },
+    ];
+
+    runtime().block_on(async {
+        if selector % 2 == 0 {
+            let _ = read_index(Format::Bam, index).await;
+            let bam = data_dir.join("sample.bam");
+            let header = BamIndexReader::read_header(&bam).await.unwrap();
+            let _ = BamIndexReader::query_ranges(&bam, index, &regions, &header).await;
+        } else {
+            let _ = read_index(Format::Vcf, index).await;
+            let vcf = data_dir.join("sample.vcf.gz");
+            let _ = VcfIndexReader::query_ranges(&vcf, index, &regions).await;
+        }
+    });
+});
@@ -0,0 +1,86 @@
+//! Ticket requests with fuzzed GET query strings and POST bodies against
+//! the bundled test data. Bad input must be answered with a 4xx, never a
+//! panic or a 5xx.
+//!
+//! The first byte picks the endpoint and method; the rest is the query
+//! string (GET) or JSON body (POST).
+
+#![no_main]
+
+use axum::{Router, body::Body, http::Request};
+use htsgetr::{
+    handlers::{AppState, create_router},
+    storage::LocalStorage,
+};
+use libfuzzer_sys::fuzz_target;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use tower::ServiceExt;
+
+const ENDPOINTS: [&str; 4] = ["reads/mt", "reads/sample", "variants/sample", "sequences/x"];
+
+fn server() -> &'static (tokio::runtime::Runtime, Router) {
+    static SERVER: OnceLock<(tokio::runtime::Runtime, Router)> = OnceLock::new();
+    SERVER.get_or_init(|| {
+        let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
+        let base_url = "http://localhost:8080".to_string();
+
+        let state = AppState {
+            storage: Arc::new(LocalStorage::new(data_dir, base_url.clone())),
+            base_url,
+            url_signer: None,
+            trusted_proxies: None,
+            cache_control: None,
+            data_mode: Default::default(),
+            endpoints: Default::default(),
+            path_prefix: None,
+            standalone_headers: None,
+            default_formats: Default::default(),
+            ticket_cache: None,
+            plain_json_tickets: false,
+            ticket_url_ttl: None,
+            ticket_limits: Default::default(),
+            index_readers: Default::default(),
+            reference_aliases: None,
+            observers: Default::default(),
+        };
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        (runtime, create_router(state))
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, input)) = data.split_first() else {
+        return;
+    };
+    let Ok(input) = std::str::from_utf8(input) else {
+        return;
+    };
+
+    let endpoint = ENDPOINTS[usize::from(selector) % ENDPOINTS.len()];
+    let request = if selector & 0x80 == 0 {
+        Request::get(format!("/{}?{}", endpoint, input)).body(Body::empty())
+    } else {
+        Request::post(format!("/{}", endpoint))
+            .header("content-type", "application/json")
+            .body(Body::from(input.to_string()))
+    };
+    // Not a valid URI, so no request would reach the router
+    let Ok(request) = request else {
+        return;
+    };
+
+    let (runtime, router) = server();
+    let response = runtime.block_on(router.clone().oneshot(request)).unwrap();
+    assert!(
+        !response.status().is_server_error(),
+        "{} for {} {:?}",
+        response.status(),
+        endpoint,
+        input
+    );
+});
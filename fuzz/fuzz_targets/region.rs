@@ -0,0 +1,13 @@
+//! samtools-style region strings, as taken by the client and `htsgetr get`.
+
+#![no_main]
+
+use htsgetr::types::Region;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    if let Ok(region) = input.parse::<Region>() {
+        assert!(!region.reference_name.is_empty());
+        assert!(input.starts_with(&region.reference_name));
+    }
+});
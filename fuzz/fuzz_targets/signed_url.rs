@@ -0,0 +1,16 @@
+//! Signed data URLs as they arrive at `/data`. Parsing must not panic, and
+//! nothing the fuzzer makes up may pass validation.
+
+#![no_main]
+
+use htsgetr::auth::{UrlSigner, parse_signed_url};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    let Some((resource, expires, signature)) = parse_signed_url(input) else {
+        return;
+    };
+
+    let signer = UrlSigner::new(b"fuzz-secret".to_vec(), 3600);
+    assert!(signer.validate(&resource, expires, &signature).is_err());
+});
@@ -0,0 +1,78 @@
+#ifndef HTSGETR_H
+#define HTSGETR_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * `end` value meaning "to the end of the file"
+ */
+#define HTSGETR_END_OF_FILE UINT64_MAX
+
+/**
+ * Byte range `[start, end)`; `end` is [`HTSGETR_END_OF_FILE`] when open-ended
+ */
+typedef struct HtsgetrByteRange {
+  uint64_t start;
+  uint64_t end;
+} HtsgetrByteRange;
+
+/**
+ * Result of [`htsgetr_query_ranges`]; release with [`htsgetr_free_ranges`]
+ */
+typedef struct HtsgetrRanges {
+  struct HtsgetrByteRange header;
+  struct HtsgetrByteRange *ranges;
+  uintptr_t len;
+} HtsgetrRanges;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Resolve `region` of the file at `path` to byte ranges.
+ *
+ * `format` (e.g. `"BAM"`) and `region` (samtools-style, e.g. `"chr1:1-1000"`)
+ * may be NULL to auto-detect the format and select the whole file (reported
+ * as zero data ranges). The index is located next to the data file. On success
+ * returns 0 and stores a result in `*out` that must be released with
+ * [`htsgetr_free_ranges`]; on failure returns -1 and sets [`htsgetr_last_error`].
+ *
+ * # Safety
+ *
+ * `path` must be a valid NUL-terminated string, `format` and `region` must be
+ * NULL or valid NUL-terminated strings, and `out` must be a valid pointer.
+ */
+int htsgetr_query_ranges(const char *path,
+                         const char *format,
+                         const char *region,
+                         struct HtsgetrRanges **out);
+
+/**
+ * Release a result returned by [`htsgetr_query_ranges`]. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `ranges` must be NULL or a pointer returned by [`htsgetr_query_ranges`]
+ * that has not already been freed.
+ */
+void htsgetr_free_ranges(struct HtsgetrRanges *ranges);
+
+/**
+ * Message for the last error on this thread, or NULL if none.
+ *
+ * The string is owned by htsgetr and valid until the next failing call on
+ * the same thread.
+ */
+const char *htsgetr_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* HTSGETR_H */
@@ -11,9 +11,10 @@ Usage:
     # Or use the client
     client = HtsgetClient("http://localhost:8080")
     result = client.reads("sample1", reference_name="chr1", start=0, end=1000000)
+    data = client.reads_data("sample1", reference_name="chr1", start=0, end=1000000)
 """
 
-from htsgetr._htsgetr import HtsgetServer, HtsgetClient
+from htsgetr._htsgetr import AsyncHtsgetClient, HtsgetServer, HtsgetClient, query_ranges
 
 __version__ = "0.1.0"
-__all__ = ["HtsgetServer", "HtsgetClient", "__version__"]
+__all__ = ["AsyncHtsgetClient", "HtsgetServer", "HtsgetClient", "query_ranges", "__version__"]
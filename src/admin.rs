@@ -0,0 +1,337 @@
+//! The effective configuration, for operators.
+//!
+//! Settings come from the command line, `HTSGET_*` environment variables, a
+//! `--config` file and defaults, and in a container it isn't always clear
+//! which won.
+//! [`ConfigReport`] records every setting's resolved value and where it came
+//! from. It is shown in two forms:
+//!
+//! | Where | Format |
+//! |-------|--------|
+//! | `htsgetr serve --print-config` | `HTSGET_*=value` lines, usable as an env file |
+//! | `GET /admin/config` | JSON, keyed by setting |
+//!
+//! The endpoint is only served with an admin token, which it requires as
+//! `Authorization: Bearer <token>`. Secrets are redacted in both: settings
+//! marked `hide_env_values` (keys, tokens, HTTP origin headers), and the
+//! credentials and query strings (e.g. presigned signatures) of URLs in
+//! any setting.
+
+use crate::config::ConfigFile;
+use crate::handlers::AppState;
+use crate::{Config, Error, Result};
+use axum::{
+    Json, Router,
+    http::{HeaderMap, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgMatches, CommandFactory};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// Route of the configuration report
+pub const CONFIG_REPORT_PATH: &str = "/admin/config";
+
+/// What secret values are replaced with
+const REDACTED: &str = "<redacted>";
+
+/// Where a setting's value came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// A command-line flag
+    Cli,
+    /// An `HTSGET_*` environment variable
+    Env,
+    /// The `--config` file
+    File,
+    /// The built-in default
+    Default,
+}
+
+/// One resolved setting
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Setting {
+    /// Command-line flag, e.g. `--data-dir`
+    pub flag: String,
+    /// Environment variable, e.g. `HTSGET_DATA_DIR`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+    /// Value as given, redacted if secret; `None` if unset
+    pub value: Option<String>,
+    /// `None` if unset
+    pub source: Option<Source>,
+}
+
+/// Every server setting, as resolved at startup.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConfigReport {
+    pub settings: BTreeMap<String, Setting>,
+}
+
+impl ConfigReport {
+    /// Resolve the settings the way [`Config::load`] does from `args` (the
+    /// program name first), the environment and the `--config` file.
+    pub fn from_args<I, T>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let (matches, file) =
+            Config::matches_from(args).map_err(|e| Error::InvalidInput(e.to_string()))?;
+        Ok(Self::from_matches(&matches, &file))
+    }
+
+    /// The settings in parsed `matches` of [`Config`], with defaults from
+    /// `file`
+    pub fn from_matches(matches: &ArgMatches, file: &ConfigFile) -> Self {
+        let command = Config::command();
+        let settings = command
+            .get_arguments()
+            .filter_map(|arg| {
+                let id = arg.get_id().as_str();
+                if matches!(id, "help" | "version") {
+                    return None;
+                }
+                let flag = format!("--{}", arg.get_long()?);
+                let value = matches.try_get_raw(id).ok().flatten().map(|values| {
+                    let values: Vec<_> = values.map(|v| v.to_string_lossy()).collect();
+                    redact(arg, &values.join(","))
+                });
+                let source = value
+                    .as_ref()
+                    .and_then(|_| matches.value_source(id))
+                    .map(|source| match source {
+                        ValueSource::DefaultValue if file.settings.contains_key(id) => Source::File,
+                        ValueSource::DefaultValue => Source::Default,
+                        ValueSource::EnvVariable => Source::Env,
+                        _ => Source::Cli,
+                    });
+                let setting = Setting {
+                    flag,
+                    env: arg.get_env().map(|env| env.to_string_lossy().into_owned()),
+                    value,
+                    source,
+                };
+                Some((id.to_string(), setting))
+            })
+            .collect();
+        Self { settings }
+    }
+
+    /// The settings that have a value as `HTSGET_*=value` lines, grouped by
+    /// source. Settings without an environment variable are commented out.
+    pub fn to_env_file(&self) -> String {
+        let mut out = String::new();
+        for (source, heading) in [
+            (Source::Cli, "From the command line"),
+            (Source::Env, "From the environment"),
+            (Source::File, "From the config file"),
+            (Source::Default, "Defaults"),
+        ] {
+            let settings: Vec<_> = self
+                .settings
+                .values()
+                .filter(|setting| setting.source == Some(source))
+                .collect();
+            if settings.is_empty() {
+                continue;
+            }
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            let _ = writeln!(out, "# {}", heading);
+            for setting in settings {
+                let value = setting.value.as_deref().unwrap_or_default();
+                let _ = match &setting.env {
+                    Some(env) => writeln!(out, "{}={}", env, value),
+                    None => writeln!(out, "# {}={}", setting.flag, value),
+                };
+            }
+        }
+        out
+    }
+
+    /// The `GET /admin/config` route, requiring `admin_token`
+    pub fn routes(self: &Arc<Self>, admin_token: impl Into<String>) -> Router<AppState> {
+        let (report, token) = (self.clone(), admin_token.into());
+        Router::new().route(
+            CONFIG_REPORT_PATH,
+            get(move |headers: HeaderMap| {
+                let (report, token) = (report.clone(), token.clone());
+                async move { report.response(&headers, &token) }
+            }),
+        )
+    }
+
+    fn response(&self, headers: &HeaderMap, token: &str) -> Result<Response> {
+        if !bearer_matches(headers, token) {
+            return Err(Error::InvalidAuthentication);
+        }
+        Ok(Json(self).into_response())
+    }
+}
+
+/// `value` of `arg`, redacted if the argument is marked secret (with
+/// `hide_env_values`). URLs in any setting lose their credentials (e.g.
+/// `redis://:password@host`) and query strings.
+fn redact(arg: &Arg, value: &str) -> String {
+    if arg.is_hide_env_values_set() {
+        return REDACTED.to_string();
+    }
+
+    value
+        .split(',')
+        .map(|part| match part.split_once("://") {
+            Some((scheme, rest)) => {
+                let rest = match rest.split('/').next().unwrap_or_default().rfind('@') {
+                    Some(at) => format!("{}@{}", REDACTED, &rest[at + 1..]),
+                    None => rest.to_string(),
+                };
+                match rest.split_once('?') {
+                    Some((path, _)) => format!("{}://{}?{}", scheme, path, REDACTED),
+                    None => format!("{}://{}", scheme, rest),
+                }
+            }
+            None => part.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Whether `headers` carry `Authorization: Bearer <token>`
+pub(crate) fn bearer_matches(headers: &HeaderMap, token: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_args() {
+        let report = ConfigReport::from_args([
+            "htsgetr",
+            "serve",
+            "--port",
+            "9000",
+            "--admin-token",
+            "hunter2",
+            "--quota-store",
+            "redis://:pw@redis:6379/0",
+        ])
+        .unwrap();
+
+        let port = &report.settings["port"];
+        assert_eq!(port.value.as_deref(), Some("9000"));
+        assert_eq!(port.source, Some(Source::Cli));
+        assert_eq!(port.env.as_deref(), Some("HTSGET_PORT"));
+
+        let host = &report.settings["host"];
+        assert_eq!(host.source, Some(Source::Default));
+        assert_eq!(host.flag, "--host");
+
+        assert_eq!(
+            report.settings["admin_token"].value.as_deref(),
+            Some(REDACTED)
+        );
+        assert_eq!(
+            report.settings["quota_store"].value.as_deref(),
+            Some("redis://<redacted>@redis:6379/0")
+        );
+        assert!(!serde_json::to_string(&report).unwrap().contains("hunter2"));
+
+        assert!(ConfigReport::from_args(["htsgetr", "--port", "many"]).is_err());
+    }
+
+    #[test]
+    fn test_from_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("htsgetr.toml");
+        std::fs::write(&path, "port = 9000\nhost = \"127.0.0.1\"\n").unwrap();
+        let config = path.to_str().unwrap();
+
+        let report =
+            ConfigReport::from_args(["htsgetr", "--config", config, "--host", "::1"]).unwrap();
+
+        let port = &report.settings["port"];
+        assert_eq!(port.value.as_deref(), Some("9000"));
+        assert_eq!(port.source, Some(Source::File));
+        let host = &report.settings["host"];
+        assert_eq!(host.value.as_deref(), Some("::1"));
+        assert_eq!(host.source, Some(Source::Cli));
+    }
+
+    #[test]
+    fn test_to_env_file() {
+        let report = ConfigReport::from_args(["htsgetr", "--port", "9000"]).unwrap();
+        let env = report.to_env_file();
+        assert!(env.starts_with("# From the command line\nHTSGET_PORT=9000\n"));
+        assert!(env.contains("\n# Defaults\n"));
+        assert!(env.contains("\nHTSGET_HOST=0.0.0.0\n"));
+    }
+
+    #[test]
+    fn test_redact() {
+        let command = Config::command();
+        let arg = |id: &str| {
+            command
+                .get_arguments()
+                .find(|arg| arg.get_id() == id)
+                .unwrap()
+        };
+
+        assert_eq!(redact(arg("data_url_secret"), "s3cr3t"), REDACTED);
+        assert_eq!(redact(arg("data_url_previous_secret"), "old"), REDACTED);
+        assert_eq!(
+            redact(arg("http_headers"), "Authorization: Bearer x"),
+            REDACTED
+        );
+        assert_eq!(
+            redact(arg("data_url_secret_file"), "/run/secret"),
+            "/run/secret"
+        );
+        assert_eq!(
+            redact(arg("audit_log"), "https://user:pw@hooks.example.com/a@b"),
+            "https://<redacted>@hooks.example.com/a@b"
+        );
+        assert_eq!(
+            redact(
+                arg("http_url_template"),
+                "https://bucket.s3.amazonaws.com/{id}.{ext}?X-Amz-Signature=abc"
+            ),
+            "https://bucket.s3.amazonaws.com/{id}.{ext}?<redacted>"
+        );
+        assert_eq!(
+            redact(arg("http_base_url"), "https://u:p@origin/data?sig=abc"),
+            "https://<redacted>@origin/data?<redacted>"
+        );
+        assert_eq!(redact(arg("data_dir"), "/srv/data"), "/srv/data");
+    }
+
+    #[test]
+    fn test_secrets_are_marked() {
+        // Settings named like secrets must be marked as such
+        for arg in Config::command().get_arguments() {
+            let id = arg.get_id().as_str();
+            let named_secret = ["secret", "password", "token"]
+                .iter()
+                .any(|word| id.split('_').any(|part| part == *word));
+            let secret = id == "http_headers" || (named_secret && !id.ends_with("_file"));
+            assert_eq!(arg.is_hide_env_values_set(), secret, "{}", id);
+        }
+    }
+}
@@ -0,0 +1,420 @@
+//! Audit logging of data access.
+//!
+//! Controlled-access deployments must record who accessed which sample and
+//! region. With an [`AuditLog`] configured, every ticket and data request
+//! produces one [`AuditEvent`]: the authenticated subject, the dataset, the
+//! regions or bytes served, and whether access was allowed. Events go to a
+//! sink of their own, separate from the server log:
+//!
+//! | Sink | Spec | Output |
+//! |------|------|--------|
+//! | Log | `log` | `tracing` events with target `htsgetr::audit` |
+//! | File | `file:/var/log/htsgetr/audit.jsonl` | One JSON event per line |
+//! | Webhook | `https://audit.example.com/events` | A JSON POST per event (needs `reqwest`, on with `http` or `client`) |
+//!
+//! A background task writes the events, so a slow sink doesn't hold up
+//! requests; if its queue fills, events are dropped with a warning.
+//!
+//! Signed data URLs carry the subject of the ticket that issued them, so data
+//! events are attributed to the same caller as the ticket.
+
+use crate::handlers::{REFRESH_PATH, percent_decode, refresh_dataset};
+use crate::jobs::JOBS_PATH;
+use crate::types::{Format, Region};
+use crate::{Error, Result};
+use axum::{
+    extract::Request,
+    http::{Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+/// Events waiting to be written before new ones are dropped
+const QUEUE_SIZE: usize = 1024;
+
+/// Outcome of a request, as recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    /// Data or a ticket was served
+    Allow,
+    /// Authentication or authorization failed
+    Deny,
+    /// The request failed for another reason (not found, invalid input, ...)
+    Error,
+}
+
+impl Decision {
+    fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Decision::Deny,
+            s if s.is_success() || s.is_redirection() => Decision::Allow,
+            _ => Decision::Error,
+        }
+    }
+}
+
+/// One audited request
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEvent {
+    /// RFC 3339, UTC
+    pub timestamp: String,
+    /// Authenticated subject (`sub` claim or client certificate)
+    pub subject: Option<String>,
+    /// `reads`, `variants`, `sequences` or `data`
+    pub endpoint: String,
+    /// Dataset ID
+    pub dataset: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<Format>,
+    /// Regions of a ticket request; empty for the whole file
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub regions: Vec<Region>,
+    /// Bytes of data served
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    pub status: u16,
+    pub decision: Decision,
+}
+
+/// Authenticated subject, attached to requests and responses by the auth
+/// middleware
+#[derive(Debug, Clone)]
+pub struct Subject(pub String);
+
+/// What a ticket handler served, attached to its response
+#[derive(Debug, Clone)]
+pub struct Access {
+    pub format: Format,
+    pub regions: Vec<Region>,
+}
+
+impl Access {
+    pub fn new(format: Format, regions: Vec<Region>) -> Self {
+        Self { format, regions }
+    }
+
+    /// Attach to a handler's response
+    pub fn attach(self, response: impl IntoResponse) -> Response {
+        let mut response = response.into_response();
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+/// Where audit events are written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditSink {
+    /// `tracing` events with target `htsgetr::audit`
+    Log,
+    /// JSON lines appended to a file
+    File(PathBuf),
+    /// JSON POSTed to a URL
+    #[cfg(feature = "reqwest")]
+    Webhook(String),
+}
+
+/// Parse a sink spec: `log`, `file:<path>` or an `http(s)://` webhook URL.
+impl FromStr for AuditSink {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s == "log" {
+            return Ok(AuditSink::Log);
+        }
+        if let Some(path) = s.strip_prefix("file:").filter(|path| !path.is_empty()) {
+            return Ok(AuditSink::File(PathBuf::from(path)));
+        }
+        if s.starts_with("http://") || s.starts_with("https://") {
+            #[cfg(feature = "reqwest")]
+            return Ok(AuditSink::Webhook(s.to_string()));
+            #[cfg(not(feature = "reqwest"))]
+            return Err(Error::InvalidInput(
+                "audit webhooks require the http or client feature".to_string(),
+            ));
+        }
+
+        Err(Error::InvalidInput(format!(
+            "invalid audit sink: {} (expected log, file:<path> or a webhook URL)",
+            s
+        )))
+    }
+}
+
+/// Queue of events for the sink's writer task
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    sender: mpsc::Sender<AuditEvent>,
+}
+
+impl AuditLog {
+    /// Start writing events to `sink`. A file sink is opened (and created)
+    /// here, so a bad path fails at startup.
+    pub async fn new(sink: AuditSink) -> Result<Self> {
+        let mut writer = Writer::open(sink).await?;
+        let (sender, mut receiver) = mpsc::channel::<AuditEvent>(QUEUE_SIZE);
+
+        tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                if let Err(e) = writer.write(&event).await {
+                    tracing::error!("failed to write audit event: {}", e);
+                }
+            }
+        });
+
+        Ok(Self { sender })
+    }
+
+    /// Queue an event, dropping it if the writer has fallen behind.
+    pub fn record(&self, event: AuditEvent) {
+        if let Err(e) = self.sender.try_send(event) {
+            tracing::warn!("audit event dropped: {}", e);
+        }
+    }
+}
+
+enum Writer {
+    Log,
+    File(tokio::fs::File),
+    #[cfg(feature = "reqwest")]
+    Webhook(reqwest::Client, String),
+}
+
+impl Writer {
+    async fn open(sink: AuditSink) -> Result<Self> {
+        Ok(match sink {
+            AuditSink::Log => Writer::Log,
+            AuditSink::File(path) => {
+                let file = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .await
+                    .map_err(|e| {
+                        Error::InvalidInput(format!(
+                            "cannot open audit log {}: {}",
+                            path.display(),
+                            e
+                        ))
+                    })?;
+                Writer::File(file)
+            }
+            #[cfg(feature = "reqwest")]
+            AuditSink::Webhook(url) => Writer::Webhook(reqwest::Client::new(), url),
+        })
+    }
+
+    async fn write(&mut self, event: &AuditEvent) -> Result<()> {
+        let json = serde_json::to_string(event)
+            .map_err(|e| Error::Internal(format!("failed to serialize audit event: {}", e)))?;
+
+        match self {
+            Writer::Log => tracing::info!(target: "htsgetr::audit", "{}", json),
+            Writer::File(file) => {
+                file.write_all(format!("{}\n", json).as_bytes()).await?;
+                file.flush().await?;
+            }
+            #[cfg(feature = "reqwest")]
+            Writer::Webhook(client, url) => {
+                client
+                    .post(url.as_str())
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(json)
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .map_err(|e| Error::Internal(format!("audit webhook failed: {}", e)))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Record an [`AuditEvent`] for each ticket, ticket refresh, export job and
+/// data request. Sits outside authentication, so denied requests are recorded
+/// too; `path_prefix` is stripped before matching routes.
+pub async fn audit_middleware(
+    log: Arc<AuditLog>,
+    path_prefix: Option<String>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let path = req.uri().path();
+    let path = match &path_prefix {
+        Some(prefix) => path.strip_prefix(prefix.as_str()).unwrap_or(path),
+        None => path,
+    };
+    let (req, dataset) = if path == REFRESH_PATH || path == JOBS_PATH {
+        refresh_dataset(req).await
+    } else {
+        let dataset = dataset_request(path);
+        (req, dataset)
+    };
+    let Some((endpoint, dataset)) = dataset else {
+        return next.run(req).await;
+    };
+    let counts_bytes = matches!(endpoint, "data" | "download") && req.method() == Method::GET;
+
+    let response = next.run(req).await;
+
+    let access = response.extensions().get::<Access>().cloned();
+    let bytes = counts_bytes
+        .then(|| response.headers().get(header::CONTENT_LENGTH))
+        .flatten()
+        .and_then(|v| v.to_str().ok()?.parse().ok());
+
+    log.record(AuditEvent {
+        timestamp: rfc3339(SystemTime::now()),
+        subject: response
+            .extensions()
+            .get::<Subject>()
+            .map(|Subject(subject)| subject.clone()),
+        endpoint: endpoint.to_string(),
+        dataset,
+        format: access.as_ref().map(|access| access.format),
+        regions: access.map(|access| access.regions).unwrap_or_default(),
+        bytes,
+        status: response.status().as_u16(),
+        decision: Decision::from_status(response.status()),
+    });
+
+    response
+}
+
+/// Endpoint and dataset ID of a ticket (`/reads/:id`), data
+/// (`/data/:format/:id`) or download (`/download/:endpoint/:id`) request path. Service info, metadata and other
+/// routes aren't audited.
+fn dataset_request(path: &str) -> Option<(&'static str, String)> {
+    let (endpoint, rest) = path.strip_prefix('/')?.split_once('/')?;
+    let (endpoint, id) = match endpoint {
+        "reads" => ("reads", rest),
+        "variants" => ("variants", rest),
+        "sequences" => ("sequences", rest),
+        "data" => ("data", rest.split_once('/')?.1),
+        "download" => ("download", rest.split_once('/')?.1),
+        _ => return None,
+    };
+
+    // Sub-resources such as `/reads/:id/coverage` are metadata, not data
+    if id.is_empty() || id == "service-info" || id.contains('/') {
+        return None;
+    }
+    Some((endpoint, percent_decode(id)))
+}
+
+/// Format a time as RFC 3339 in UTC, to the millisecond.
+fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rfc3339() {
+        let at = |secs| rfc3339(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(at(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(at(951_782_400), "2000-02-29T00:00:00.000Z");
+        assert_eq!(at(1_700_000_000), "2023-11-14T22:13:20.000Z");
+    }
+
+    #[test]
+    fn test_dataset_request() {
+        assert_eq!(
+            dataset_request("/reads/sample1"),
+            Some(("reads", "sample1".to_string()))
+        );
+        assert_eq!(
+            dataset_request("/data/BAM/cohort%2Fsample1"),
+            Some(("data", "cohort/sample1".to_string()))
+        );
+        assert_eq!(
+            dataset_request("/download/reads/sample1"),
+            Some(("download", "sample1".to_string()))
+        );
+        assert_eq!(dataset_request("/reads/service-info"), None);
+        assert_eq!(dataset_request("/reads/sample1/coverage"), None);
+        assert_eq!(dataset_request("/service-info"), None);
+    }
+
+    #[test]
+    fn test_sink_from_str() {
+        assert_eq!("log".parse::<AuditSink>().unwrap(), AuditSink::Log);
+        assert_eq!(
+            "file:/tmp/audit.jsonl".parse::<AuditSink>().unwrap(),
+            AuditSink::File(PathBuf::from("/tmp/audit.jsonl"))
+        );
+        assert!("file:".parse::<AuditSink>().is_err());
+        assert!("syslog".parse::<AuditSink>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let log = AuditLog::new(AuditSink::File(path.clone())).await.unwrap();
+
+        log.record(AuditEvent {
+            timestamp: rfc3339(SystemTime::now()),
+            subject: Some("alice".to_string()),
+            endpoint: "reads".to_string(),
+            dataset: "sample1".to_string(),
+            format: Some(Format::Bam),
+            regions: vec!["chr1:1-1000".parse().unwrap()],
+            bytes: None,
+            status: 200,
+            decision: Decision::Allow,
+        });
+
+        let mut contents = String::new();
+        for _ in 0..100 {
+            contents = std::fs::read_to_string(&path).unwrap();
+            if !contents.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let event: serde_json::Value = serde_json::from_str(contents.trim()).unwrap();
+        assert_eq!(event["subject"], "alice");
+        assert_eq!(event["format"], "BAM");
+        assert_eq!(event["regions"][0]["referenceName"], "chr1");
+        assert_eq!(event["decision"], "allow");
+        assert!(event.get("bytes").is_none());
+    }
+}
@@ -159,6 +159,7 @@ async fn validate_token(token: &str, auth_config: &AuthConfig) -> Result<Authent
     let token_data = jwt::validate_token(
         token,
         &key,
+        &auth_config.algorithms,
         auth_config.issuer.as_deref(),
         auth_config.audience.as_deref(),
     )?;
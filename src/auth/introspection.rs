@@ -0,0 +1,221 @@
+//! OAuth 2.0 token introspection (RFC 7662).
+//!
+//! Lets deployments accept opaque access tokens: the token is posted to the
+//! IdP's introspection endpoint (authenticated with client credentials) and
+//! the `active` result is cached so repeat requests don't hit the IdP.
+
+use crate::Error;
+use moka::future::Cache;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::jwt::Aud;
+
+/// Introspection response fields used for authorization.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntrospectionResponse {
+    /// Whether the token is currently active.
+    pub active: bool,
+    /// Space-separated scopes.
+    pub scope: Option<String>,
+    /// Subject (user ID).
+    pub sub: Option<String>,
+    /// Client the token was issued to.
+    pub client_id: Option<String>,
+    /// Issuer.
+    pub iss: Option<String>,
+    /// Audience.
+    pub aud: Option<Aud>,
+    /// Expiration time (Unix timestamp).
+    pub exp: Option<u64>,
+    /// Remaining claims (e.g. `groups`), used by authorization policies.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl IntrospectionResponse {
+    fn expired(&self) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.exp.is_some_and(|exp| exp <= now)
+    }
+}
+
+/// Token introspection client with result caching.
+pub struct TokenIntrospector {
+    introspection_url: String,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    /// Results keyed by SHA-256 of the token, so raw tokens aren't held in memory
+    cache: Cache<String, Arc<IntrospectionResponse>>,
+    http_client: reqwest::Client,
+}
+
+impl TokenIntrospector {
+    /// Create a new introspection client.
+    ///
+    /// # Arguments
+    /// * `introspection_url` - RFC 7662 endpoint (e.g., `https://auth.example.com/oauth2/introspect`)
+    /// * `client_id` / `client_secret` - credentials sent with HTTP Basic auth
+    /// * `cache_ttl` - how long active and inactive results are reused
+    pub fn new(
+        introspection_url: String,
+        client_id: Option<String>,
+        client_secret: Option<String>,
+        cache_ttl: Duration,
+    ) -> Self {
+        let cache = Cache::builder()
+            .time_to_live(cache_ttl)
+            .max_capacity(10_000)
+            .build();
+
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("failed to create HTTP client");
+
+        Self {
+            introspection_url,
+            client_id,
+            client_secret,
+            cache,
+            http_client,
+        }
+    }
+
+    /// Introspect a token, using the cache if available.
+    pub async fn introspect(&self, token: &str) -> Result<Arc<IntrospectionResponse>, Error> {
+        let key = hex_sha256(token);
+
+        if let Some(response) = self.cache.get(&key).await {
+            return Ok(response);
+        }
+
+        let response = Arc::new(self.fetch(token).await?);
+        self.cache.insert(key, response.clone()).await;
+        Ok(response)
+    }
+
+    /// Validate a token: it must be active, unexpired, and match the
+    /// configured issuer and audience when the IdP reports them.
+    pub async fn validate(
+        &self,
+        token: &str,
+        issuer: Option<&str>,
+        audience: Option<&str>,
+    ) -> Result<Arc<IntrospectionResponse>, Error> {
+        let response = self.introspect(token).await?;
+
+        if !response.active || response.expired() {
+            tracing::debug!("introspected token is inactive or expired");
+            return Err(Error::InvalidAuthentication);
+        }
+
+        if let (Some(expected), Some(iss)) = (issuer, response.iss.as_deref()) {
+            if expected.trim_end_matches('/') != iss.trim_end_matches('/') {
+                tracing::debug!("introspected token has unexpected issuer: {}", iss);
+                return Err(Error::InvalidAuthentication);
+            }
+        }
+
+        if let Some(expected) = audience {
+            if !response
+                .aud
+                .as_ref()
+                .is_some_and(|aud| aud.contains(expected))
+            {
+                tracing::debug!("introspected token is missing audience: {}", expected);
+                return Err(Error::InvalidAuthentication);
+            }
+        }
+
+        Ok(response)
+    }
+
+    async fn fetch(&self, token: &str) -> Result<IntrospectionResponse, Error> {
+        tracing::debug!("introspecting token at {}", self.introspection_url);
+
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("token", token)
+            .append_pair("token_type_hint", "access_token")
+            .finish();
+
+        let mut request = self
+            .http_client
+            .post(&self.introspection_url)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(body);
+
+        if let Some(client_id) = &self.client_id {
+            request = request.basic_auth(client_id, self.client_secret.as_ref());
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| Error::Internal(format!("token introspection failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(Error::Internal(format!(
+                "token introspection failed with status: {}",
+                response.status()
+            )));
+        }
+
+        response
+            .json::<IntrospectionResponse>()
+            .await
+            .map_err(|e| Error::Internal(format!("failed to parse introspection response: {}", e)))
+    }
+}
+
+fn hex_sha256(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_inactive_response() {
+        let response: IntrospectionResponse = serde_json::from_str(r#"{"active":false}"#).unwrap();
+        assert!(!response.active);
+        assert!(response.scope.is_none());
+    }
+
+    #[test]
+    fn test_parse_active_response() {
+        let response: IntrospectionResponse = serde_json::from_str(
+            r#"{"active":true,"scope":"read","sub":"alice","aud":["htsget","web"],"exp":4102444800}"#,
+        )
+        .unwrap();
+        assert!(response.active);
+        assert!(!response.expired());
+        assert!(response.aud.unwrap().contains("htsget"));
+    }
+
+    #[test]
+    fn test_expired_response() {
+        let response: IntrospectionResponse =
+            serde_json::from_str(r#"{"active":true,"exp":1}"#).unwrap();
+        assert!(response.expired());
+    }
+
+    #[test]
+    fn test_cache_key_is_not_the_token() {
+        let key = hex_sha256("secret-token");
+        assert_eq!(key.len(), 64);
+        assert!(!key.contains("secret"));
+    }
+}
@@ -1,18 +1,24 @@
 //! JWKS (JSON Web Key Set) fetching and caching.
 
 use crate::Error;
+use crate::shared::{KEY_PREFIX, SharedCache};
 use jsonwebtoken::DecodingKey;
 use moka::future::Cache;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::time::Duration;
 
 use super::KeyProvider;
 
+/// How long a fetched JWKS is reused
+const JWKS_TTL: Duration = Duration::from_secs(3600);
+
 /// JWKS key provider with caching.
 pub struct JwksKeyProvider {
     jwks_url: String,
     cache: Cache<String, Arc<Jwks>>,
+    /// Cache shared with other replicas, behind `cache`
+    shared: Option<Arc<dyn SharedCache>>,
     http_client: reqwest::Client,
 }
 
@@ -23,7 +29,7 @@ impl JwksKeyProvider {
     /// * `jwks_url` - URL to fetch JWKS from (e.g., `https://auth.example.com/.well-known/jwks.json`)
     pub fn new(jwks_url: String) -> Self {
         let cache = Cache::builder()
-            .time_to_live(Duration::from_secs(3600)) // Cache for 1 hour
+            .time_to_live(JWKS_TTL)
             .max_capacity(10)
             .build();
 
@@ -35,10 +41,18 @@ impl JwksKeyProvider {
         Self {
             jwks_url,
             cache,
+            shared: None,
             http_client,
         }
     }
 
+    /// Share the fetched JWKS with other replicas through `cache`, so they
+    /// don't each fetch it.
+    pub fn with_shared_cache(mut self, cache: Arc<dyn SharedCache>) -> Self {
+        self.shared = Some(cache);
+        self
+    }
+
     /// Create a JWKS key provider from an issuer URL.
     ///
     /// Constructs the JWKS URL as `{issuer}/.well-known/jwks.json`.
@@ -80,7 +94,23 @@ impl JwksKeyProvider {
             return Ok(jwks);
         }
 
-        let jwks = Arc::new(self.fetch_jwks().await?);
+        let shared_key = format!("{}:jwks:{}", KEY_PREFIX, self.jwks_url);
+        let shared = match &self.shared {
+            Some(shared) => shared.get(&shared_key).await,
+            None => None,
+        };
+        let jwks = match shared.and_then(|bytes| serde_json::from_slice::<Jwks>(&bytes).ok()) {
+            Some(jwks) => jwks,
+            None => {
+                let jwks = self.fetch_jwks().await?;
+                if let (Some(shared), Ok(bytes)) = (&self.shared, serde_json::to_vec(&jwks)) {
+                    shared.set(&shared_key, bytes, JWKS_TTL).await;
+                }
+                jwks
+            }
+        };
+
+        let jwks = Arc::new(jwks);
         self.cache.insert(CACHE_KEY.to_string(), jwks.clone()).await;
         Ok(jwks)
     }
@@ -106,13 +136,13 @@ impl KeyProvider for JwksKeyProvider {
 }
 
 /// JSON Web Key Set.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Jwks {
     pub keys: Vec<Jwk>,
 }
 
 /// JSON Web Key.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Jwk {
     /// Key type (e.g., "RSA", "EC").
     pub kty: String,
@@ -213,4 +243,37 @@ mod tests {
         let result = jwk.to_decoding_key();
         assert!(result.is_err());
     }
+
+    #[derive(Default)]
+    struct MapCache(std::sync::Mutex<std::collections::HashMap<String, Vec<u8>>>);
+
+    #[async_trait::async_trait]
+    impl SharedCache for MapCache {
+        async fn get(&self, key: &str) -> Option<Vec<u8>> {
+            self.0.lock().unwrap().get(key).cloned()
+        }
+
+        async fn set(&self, key: &str, value: Vec<u8>, _ttl: Duration) {
+            self.0.lock().unwrap().insert(key.to_string(), value);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jwks_from_shared_cache() {
+        let url = "http://127.0.0.1:9/.well-known/jwks.json";
+        let cache = Arc::new(MapCache::default());
+        let jwks = r#"{"keys":[{"kty":"RSA","kid":"k1","n":"sXchDaQebHnPiGvyDOAT4saGEUetSyo9MKLOoWFsueri23bOdgWp4Dy1WlUzewbgBHod5pcM9H95GQRV3JDXboIRROSBigeC5yjU1hGzHHyXss8UDprecbAYxknTcQkhslANGRUZmdTOQ5qTRsLAt6BTYuyvVRdhS8exSZEy_c4gs_7svlJJQ4H9_NxsiIoLwAEk7-Q3UXERGYw_75IDrGA84-lA_-Ct4eTlXHBIY2EaV7t7LjJaynVJCpkv4LKjTTAumiGUIuQhrNhZLuF_RJLqHpM2kgWFLU7-VTdL1VbC2tejvcI2BlMkEpk1BzBZI0KQB0GaDWFLN-aEAw3vRw","e":"AQAB"}]}"#;
+        cache
+            .set(
+                &format!("{}:jwks:{}", KEY_PREFIX, url),
+                jwks.as_bytes().to_vec(),
+                JWKS_TTL,
+            )
+            .await;
+
+        // The JWKS URL is unreachable, so the key must come from the shared cache
+        let provider = JwksKeyProvider::new(url.to_string()).with_shared_cache(cache);
+        assert!(provider.get_key(Some("k1")).await.is_ok());
+        assert!(provider.get_key(Some("k2")).await.is_err());
+    }
 }
@@ -19,6 +19,11 @@ pub struct Claims {
     pub iat: Option<u64>,
     /// Not before (Unix timestamp).
     pub nbf: Option<u64>,
+    /// Space-separated scopes.
+    pub scope: Option<String>,
+    /// Remaining claims (e.g. `groups`), used by authorization policies.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 /// Audience can be a single string or array of strings.
@@ -47,17 +52,33 @@ pub fn decode_header(token: &str) -> Result<Header, Error> {
     })
 }
 
+/// Algorithms accepted when none are configured.
+pub const DEFAULT_ALGORITHMS: &[Algorithm] = &[Algorithm::RS256, Algorithm::ES256];
+
+/// Parse a list of algorithm names (e.g. `["RS256", "HS256"]`).
+pub fn parse_algorithms<S: AsRef<str>>(names: &[S]) -> Result<Vec<Algorithm>, Error> {
+    names
+        .iter()
+        .map(|name| {
+            name.as_ref().trim().parse::<Algorithm>().map_err(|_| {
+                Error::InvalidInput(format!("unknown JWT algorithm: {}", name.as_ref()))
+            })
+        })
+        .collect()
+}
+
 /// Validate a JWT token and extract claims.
+///
+/// Only tokens signed with one of `algorithms` are accepted.
 pub fn validate_token(
     token: &str,
     key: &DecodingKey,
+    algorithms: &[Algorithm],
     issuer: Option<&str>,
     audience: Option<&str>,
 ) -> Result<TokenData<Claims>, Error> {
     let mut validation = Validation::new(Algorithm::RS256);
-
-    // Also allow ES256 for EC keys
-    validation.algorithms = vec![Algorithm::RS256, Algorithm::ES256];
+    validation.algorithms = algorithms.to_vec();
 
     // Configure issuer validation
     if let Some(iss) = issuer {
@@ -112,6 +133,43 @@ mod tests {
         assert!(!aud.contains("other"));
     }
 
+    #[test]
+    fn test_parse_algorithms() {
+        assert_eq!(
+            parse_algorithms(&["RS256", " HS256"]).unwrap(),
+            vec![Algorithm::RS256, Algorithm::HS256]
+        );
+        assert!(parse_algorithms(&["none"]).is_err());
+    }
+
+    #[test]
+    fn test_validate_hs256() {
+        let secret = b"shared-secret";
+        let claims = Claims {
+            sub: Some("service".to_string()),
+            iss: None,
+            aud: None,
+            exp: Some(4102444800),
+            iat: None,
+            nbf: None,
+            scope: None,
+            extra: Default::default(),
+        };
+        let token = jsonwebtoken::encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(secret),
+        )
+        .unwrap();
+        let key = DecodingKey::from_secret(secret);
+
+        let data = validate_token(&token, &key, &[Algorithm::HS256], None, None).unwrap();
+        assert_eq!(data.claims.sub.as_deref(), Some("service"));
+
+        // Rejected unless HS256 is in the accepted list
+        assert!(validate_token(&token, &key, DEFAULT_ALGORITHMS, None, None).is_err());
+    }
+
     #[test]
     fn test_decode_header_invalid() {
         let result = decode_header("not-a-valid-jwt");
@@ -1,12 +1,15 @@
 //! Authentication middleware.
 
-use super::{AuthConfig, jwt, url_signing};
+use super::{AuthConfig, Principal, jwt, policy, url_signing};
 use crate::Error;
+use crate::audit::Subject;
+use crate::handlers::{REFRESH_PATH, refresh_dataset};
+use crate::jobs::JOBS_PATH;
 use axum::{
     body::Body,
     http::{Request, header::AUTHORIZATION},
     middleware::Next,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
 };
 use std::sync::Arc;
 
@@ -15,9 +18,19 @@ use std::sync::Arc;
 /// Checks requests against the auth configuration:
 /// - Public paths are allowed without authentication
 /// - `/data/` paths require a valid signed URL
-/// - All other paths require a valid Bearer token
+/// - All other paths require a valid Bearer token: a JWT validated locally, or
+///   (when introspection is configured) any token the IdP reports as active
+/// - With the `tls` feature, a verified client certificate authenticates
+///   requests that carry no Bearer token (subject becomes the `sub` claim)
+/// - With a policy, ticket endpoints (and ticket refreshes and export jobs)
+///   also require a grant for the requested ID, and refget sequences one for
+///   the FASTA file they come from
+///
+/// The authenticated [`Principal`] and its [`Subject`] are added to the
+/// request extensions, and the subject to the response's for the audit log.
+/// Data requests take their subject from the signed URL's `_sub` parameter.
 pub async fn auth_middleware(
-    request: axum::extract::Request,
+    mut request: axum::extract::Request,
     next: Next,
 ) -> axum::response::Response {
     // Get auth config from extensions
@@ -38,27 +51,136 @@ pub async fn auth_middleware(
         return next.run(request).await;
     }
 
-    // Handle /data/ paths with signed URLs
+    // Handle /data/ paths with signed URLs, attributed to the ticket's subject
     if path.starts_with("/data/") {
-        match validate_signed_data_url(&auth_config, &request) {
-            Ok(()) => return next.run(request).await,
-            Err(e) => return e.into_response(),
+        if let Err(e) = validate_signed_data_url(&auth_config, &request) {
+            return e.into_response();
         }
+        return match url_signing::signed_subject(&request.uri().to_string()) {
+            Some(subject) => {
+                request.extensions_mut().insert(Subject(subject.clone()));
+                let mut response = next.run(request).await;
+                response.extensions_mut().insert(Subject(subject));
+                response
+            }
+            None => next.run(request).await,
+        };
     }
 
     // All other paths require Bearer token - extract token synchronously
-    let token = match extract_bearer_token(&request) {
-        Ok(t) => t,
+    let token = extract_bearer_token(&request);
+
+    // Now we can drop the request borrow and do async work
+    let principal = match token {
+        Ok(token) => validate_bearer_token(&auth_config, token).await,
+        Err(e) => certificate_principal(&request).ok_or(e),
+    };
+    let principal = match principal {
+        Ok(principal) => principal,
         Err(e) => return e.into_response(),
     };
 
-    // Now we can drop the request borrow and do async work
-    match validate_jwt_token(&auth_config, token).await {
-        Ok(()) => next.run(request).await,
-        Err(e) => e.into_response(),
+    if let Some(policy) = &auth_config.policy {
+        // Refreshes and jobs name their dataset in the body
+        let dataset = if path == REFRESH_PATH || path == JOBS_PATH {
+            let (buffered, dataset) = refresh_dataset(request).await;
+            request = buffered;
+            dataset
+        } else if let Some(digest) = policy::refget_digest(&path) {
+            // Sequences nobody can place in a dataset are nobody's to fetch
+            match sequence_dataset(&auth_config, &digest) {
+                Some(dataset) => Some(dataset),
+                None => {
+                    return with_subject(Error::PermissionDenied.into_response(), &principal);
+                }
+            }
+        } else {
+            policy::dataset_request(&path)
+        };
+        if let Some((endpoint, id)) = dataset {
+            if let Err(e) = policy.authorize(&principal, endpoint, &id) {
+                return with_subject(e.into_response(), &principal);
+            }
+        }
+    }
+
+    let subject = principal.clone();
+    if let Some(sub) = &principal.subject {
+        request.extensions_mut().insert(Subject(sub.clone()));
+    }
+    request.extensions_mut().insert(principal);
+    with_subject(next.run(request).await, &subject)
+}
+
+/// The FASTA dataset serving a refget sequence, governed like `/sequences/:id`.
+#[cfg(feature = "refget")]
+fn sequence_dataset(auth_config: &AuthConfig, digest: &str) -> Option<(&'static str, String)> {
+    let entry = auth_config.refget.as_ref()?.lookup(digest)?;
+    Some(("sequences", entry.id.clone()))
+}
+
+/// The FASTA dataset serving a refget sequence (none without the `refget` feature).
+#[cfg(not(feature = "refget"))]
+fn sequence_dataset(_auth_config: &AuthConfig, _digest: &str) -> Option<(&'static str, String)> {
+    None
+}
+
+fn with_subject(mut response: Response, principal: &Principal) -> Response {
+    if let Some(subject) = &principal.subject {
+        response.extensions_mut().insert(Subject(subject.clone()));
+    }
+    response
+}
+
+/// Validate a Bearer token locally, falling back to introspection if configured.
+async fn validate_bearer_token(
+    auth_config: &AuthConfig,
+    token: String,
+) -> Result<Principal, Error> {
+    let local = validate_jwt_token(auth_config, &token).await;
+
+    let claims = match (&auth_config.introspector, local) {
+        (_, Ok(claims)) => serde_json::to_value(claims),
+        (Some(introspector), Err(_)) => {
+            let response = introspector
+                .validate(
+                    &token,
+                    auth_config.issuer.as_deref(),
+                    auth_config.audience.as_deref(),
+                )
+                .await?;
+            serde_json::to_value(&*response)
+        }
+        (None, Err(e)) => return Err(e),
+    };
+
+    match claims {
+        Ok(serde_json::Value::Object(claims)) => Ok(Principal::from_claims(claims)),
+        _ => Err(Error::Internal("failed to read token claims".to_string())),
     }
 }
 
+/// Principal for a client authenticated by a verified TLS certificate.
+#[cfg(feature = "tls")]
+fn certificate_principal(request: &Request<Body>) -> Option<Principal> {
+    let cert = request
+        .extensions()
+        .get::<crate::tls::ClientCertificate>()?;
+
+    let mut claims = serde_json::Map::new();
+    claims.insert(
+        "sub".to_string(),
+        serde_json::Value::String(cert.subject.clone()),
+    );
+    Some(Principal::from_claims(claims))
+}
+
+/// Principal for a client certificate (none without the `tls` feature).
+#[cfg(not(feature = "tls"))]
+fn certificate_principal(_request: &Request<Body>) -> Option<Principal> {
+    None
+}
+
 /// Extract Bearer token from Authorization header.
 fn extract_bearer_token(request: &Request<Body>) -> Result<String, Error> {
     let header = request
@@ -74,24 +196,25 @@ fn extract_bearer_token(request: &Request<Body>) -> Result<String, Error> {
         .ok_or(Error::InvalidAuthentication)
 }
 
-/// Validate a JWT token.
-async fn validate_jwt_token(auth_config: &AuthConfig, token: String) -> Result<(), Error> {
+/// Validate a JWT token and return its claims.
+async fn validate_jwt_token(auth_config: &AuthConfig, token: &str) -> Result<jwt::Claims, Error> {
     // Decode header to get key ID
-    let header = jwt::decode_header(&token)?;
+    let header = jwt::decode_header(token)?;
     let kid = header.kid.as_deref();
 
     // Get the decoding key
     let key = auth_config.key_provider.get_key(kid).await?;
 
     // Validate the token
-    jwt::validate_token(
-        &token,
+    let token_data = jwt::validate_token(
+        token,
         &key,
+        &auth_config.algorithms,
         auth_config.issuer.as_deref(),
         auth_config.audience.as_deref(),
     )?;
 
-    Ok(())
+    Ok(token_data.claims)
 }
 
 /// Validate a signed data URL.
@@ -104,23 +227,14 @@ fn validate_signed_data_url(
         .as_ref()
         .ok_or(Error::InvalidAuthentication)?;
 
-    // Get the full URI as a string
+    // Signatures cover the path and query only, so the scheme and host the
+    // request arrived on (directly or via a proxy) don't matter
     let uri = request.uri().to_string();
 
-    // For relative URIs, we need to construct the full URL
-    // The signature was computed on the full URL, so we need to match
-    let full_url = if uri.starts_with('/') {
-        // This is a relative URI, construct full URL
-        // We'll use a placeholder host since we just need the path and query
-        format!("http://localhost{}", uri)
-    } else {
-        uri
-    };
-
-    let (base_url, expires, sig) = url_signing::parse_signed_url(&full_url).ok_or_else(|| {
+    let (resource, expires, sig) = url_signing::parse_signed_url(&uri).ok_or_else(|| {
         tracing::debug!("missing signature parameters in data URL");
         Error::InvalidAuthentication
     })?;
 
-    signer.validate(&base_url, expires, &sig)
+    signer.validate(&resource, expires, &sig)
 }
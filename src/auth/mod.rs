@@ -1,22 +1,28 @@
 //! Authentication middleware for htsget.
 //!
 //! This module provides optional JWT/Bearer token authentication with:
-//! - JWKS and static public key providers
+//! - JWKS, static public key, and HMAC shared-secret key providers
+//! - RFC 7662 token introspection for opaque tokens
 //! - Path-based public endpoint configuration
+//! - Per-dataset authorization policies (scope/claim → ID patterns)
 //! - HMAC-signed data URLs for ticket fetching
 //!
 //! Enable with the `auth` feature flag.
 
 mod extractor;
+pub mod introspection;
 pub mod jwks;
 mod jwt;
 mod middleware;
+pub mod policy;
 mod url_signing;
 
 pub use extractor::{OptionalAuth, RequireAuth};
-pub use jwt::Claims;
+pub use introspection::TokenIntrospector;
+pub use jwt::{Claims, DEFAULT_ALGORITHMS, parse_algorithms};
 pub use middleware::auth_middleware;
-pub use url_signing::UrlSigner;
+pub use policy::{Policy, Principal};
+pub use url_signing::{UrlSigner, parse_signed_url, signed_subject};
 
 use crate::Error;
 use std::collections::HashSet;
@@ -29,6 +35,8 @@ pub struct AuthConfig {
     pub enabled: bool,
     /// Key provider for JWT validation.
     pub key_provider: Arc<dyn KeyProvider>,
+    /// Accepted JWT signing algorithms.
+    pub algorithms: Vec<jsonwebtoken::Algorithm>,
     /// Expected issuer claim.
     pub issuer: Option<String>,
     /// Expected audience claim.
@@ -37,6 +45,14 @@ pub struct AuthConfig {
     pub public_paths: HashSet<String>,
     /// URL signer for data endpoints.
     pub url_signer: Option<UrlSigner>,
+    /// Token introspection for tokens that can't be validated locally.
+    pub introspector: Option<Arc<TokenIntrospector>>,
+    /// Dataset authorization policy; any authenticated caller has full access if unset.
+    pub policy: Option<Arc<Policy>>,
+    /// Refget checksum index, resolving sequence digests to the FASTA file
+    /// whose dataset policy governs them.
+    #[cfg(feature = "refget")]
+    pub refget: Option<Arc<crate::refget::RefgetIndex>>,
 }
 
 impl AuthConfig {
@@ -98,6 +114,39 @@ impl KeyProvider for StaticKeyProvider {
     }
 }
 
+/// Shared-secret key provider for HMAC-signed (HS256/384/512) tokens.
+pub struct HmacKeyProvider {
+    key: jsonwebtoken::DecodingKey,
+}
+
+impl HmacKeyProvider {
+    /// Create a new HMAC key provider from a shared secret.
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            key: jsonwebtoken::DecodingKey::from_secret(secret),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl KeyProvider for HmacKeyProvider {
+    async fn get_key(&self, _kid: Option<&str>) -> Result<jsonwebtoken::DecodingKey, Error> {
+        Ok(self.key.clone())
+    }
+}
+
+/// Key provider that rejects every token.
+///
+/// Used when tokens are validated only via introspection.
+pub struct NoKeyProvider;
+
+#[async_trait::async_trait]
+impl KeyProvider for NoKeyProvider {
+    async fn get_key(&self, _kid: Option<&str>) -> Result<jsonwebtoken::DecodingKey, Error> {
+        Err(Error::InvalidAuthentication)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -107,6 +156,7 @@ mod tests {
         let config = AuthConfig {
             enabled: true,
             key_provider: Arc::new(MockKeyProvider),
+            algorithms: jwt::DEFAULT_ALGORITHMS.to_vec(),
             issuer: None,
             audience: None,
             public_paths: ["/", "/service-info"]
@@ -114,6 +164,10 @@ mod tests {
                 .map(|s| s.to_string())
                 .collect(),
             url_signer: None,
+            introspector: None,
+            policy: None,
+            #[cfg(feature = "refget")]
+            refget: None,
         };
 
         assert!(config.is_public_path("/"));
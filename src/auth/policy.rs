@@ -0,0 +1,426 @@
+//! Per-dataset authorization policies.
+//!
+//! A valid token on its own only proves who the caller is. A [`Policy`] maps
+//! token scopes or claims to the dataset IDs they may access, so a token with
+//! scope `dataset:prod/*` can be limited to `/reads/prod/*`. Policies are
+//! written in TOML:
+//!
+//! ```toml
+//! # Scope grants (patterns support `*` and `?`)
+//! [[rule]]
+//! scope = "dataset:prod/*"
+//! ids = ["prod/*"]
+//!
+//! # Claim grants: matches string or string-array claims such as `groups`
+//! [[rule]]
+//! claim = "groups"
+//! value = "cohort-a"
+//! ids = ["cohort_a_*"]
+//! endpoints = ["reads"]
+//! ```
+//!
+//! Requests to `/reads/:id`, `/variants/:id` and `/sequences/:id` are denied
+//! with `PermissionDenied` unless at least one rule matches both the caller
+//! and the requested ID.
+
+use crate::Error;
+#[cfg(feature = "drs")]
+use crate::types::Format;
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+
+/// Authenticated caller, as seen by the policy.
+#[derive(Debug, Clone, Default)]
+pub struct Principal {
+    /// Subject (user ID).
+    pub subject: Option<String>,
+    /// Granted scopes.
+    pub scopes: Vec<String>,
+    /// All token claims.
+    pub claims: serde_json::Map<String, Value>,
+}
+
+impl Principal {
+    /// Build a principal from a claim set (JWT claims or introspection response).
+    pub fn from_claims(claims: serde_json::Map<String, Value>) -> Self {
+        let subject = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+
+        // `scope` is space-separated (RFC 8693); `scp` is a common array form
+        let mut scopes: Vec<String> = claims
+            .get("scope")
+            .and_then(Value::as_str)
+            .map(|s| s.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default();
+        if let Some(Value::Array(scp)) = claims.get("scp") {
+            scopes.extend(scp.iter().filter_map(Value::as_str).map(str::to_string));
+        }
+
+        Self {
+            subject,
+            scopes,
+            claims,
+        }
+    }
+
+    /// String values of a claim; arrays are flattened.
+    fn claim_values(&self, name: &str) -> Vec<&str> {
+        match self.claims.get(name) {
+            Some(Value::String(s)) => vec![s.as_str()],
+            Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A single grant.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Scope pattern the token must hold.
+    pub scope: Option<String>,
+    /// Claim name to match `value` against.
+    pub claim: Option<String>,
+    /// Claim value pattern.
+    pub value: Option<String>,
+    /// Dataset ID patterns granted by this rule.
+    pub ids: Vec<String>,
+    /// Endpoints the grant applies to (`reads`, `variants`, `sequences`); all if unset.
+    pub endpoints: Option<Vec<String>>,
+}
+
+impl Rule {
+    fn matches_principal(&self, principal: &Principal) -> bool {
+        let scope_ok = self.scope.as_deref().is_none_or(|pattern| {
+            principal
+                .scopes
+                .iter()
+                .any(|scope| glob_match(pattern, scope))
+        });
+
+        let claim_ok = match (&self.claim, &self.value) {
+            (Some(claim), Some(pattern)) => principal
+                .claim_values(claim)
+                .iter()
+                .any(|value| glob_match(pattern, value)),
+            (Some(claim), None) => principal.claims.contains_key(claim),
+            (None, _) => true,
+        };
+
+        scope_ok && claim_ok
+    }
+
+    fn matches_request(&self, endpoint: &str, id: &str) -> bool {
+        let endpoint_ok = self
+            .endpoints
+            .as_ref()
+            .is_none_or(|endpoints| endpoints.iter().any(|e| e == endpoint));
+
+        endpoint_ok && self.ids.iter().any(|pattern| glob_match(pattern, id))
+    }
+}
+
+/// Authorization policy: a list of grants, denying anything not granted.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Grants, checked in order.
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<Rule>,
+}
+
+impl Policy {
+    /// Parse a policy from TOML.
+    pub fn from_toml(s: &str) -> Result<Self, Error> {
+        let policy: Self = toml::from_str(s)
+            .map_err(|e| Error::InvalidInput(format!("invalid auth policy: {}", e)))?;
+
+        for rule in &policy.rules {
+            if rule.scope.is_none() && rule.claim.is_none() {
+                return Err(Error::InvalidInput(
+                    "invalid auth policy: each rule needs a scope or claim".to_string(),
+                ));
+            }
+        }
+
+        Ok(policy)
+    }
+
+    /// Load a policy from a TOML file.
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::from_toml(&contents)
+    }
+
+    /// Check whether `principal` may access dataset `id` through `endpoint`.
+    pub fn authorize(&self, principal: &Principal, endpoint: &str, id: &str) -> Result<(), Error> {
+        let granted = self
+            .rules
+            .iter()
+            .any(|rule| rule.matches_principal(principal) && rule.matches_request(endpoint, id));
+
+        if granted {
+            Ok(())
+        } else {
+            tracing::debug!(
+                "policy denied {:?} access to {}/{}",
+                principal.subject,
+                endpoint,
+                id
+            );
+            Err(Error::PermissionDenied)
+        }
+    }
+}
+
+/// Split a request path into a policy-governed endpoint and dataset ID.
+/// File metadata (`/meta/reads/:id`, `/reads/:id/references`,
+/// `/variants/:id/stats`, `/reads/:id/coverage`) and features
+/// (`/reads/:id/features`) are governed like the endpoint itself, as are
+/// single-request downloads (`/download/reads/:id`) and checksums
+/// (`/checksums/reads/:id`), and DRS objects
+/// (`/ga4gh/drs/v1/objects/sample1.bam`) like the endpoint serving their
+/// format.
+pub(crate) fn dataset_request(path: &str) -> Option<(&'static str, String)> {
+    #[cfg(feature = "drs")]
+    if let Some(object) = path.strip_prefix("/ga4gh/drs/v1/objects/") {
+        let object_id =
+            crate::handlers::percent_decode(object.split('/').next().unwrap_or_default());
+        let (id, format) = crate::handlers::parse_drs_object_id(&object_id).ok()?;
+        let endpoint = match format {
+            Format::Bam | Format::Cram => "reads",
+            Format::Vcf | Format::Bcf => "variants",
+            Format::Fasta | Format::Fastq => "sequences",
+        };
+        return Some((endpoint, id.to_string()));
+    }
+
+    let path = ["/meta", "/download", "/checksums"]
+        .into_iter()
+        .find_map(|prefix| path.strip_prefix(prefix))
+        .unwrap_or(path);
+    let path = ["/references", "/stats", "/coverage", "/features"]
+        .into_iter()
+        .find_map(|suffix| path.strip_suffix(suffix))
+        .unwrap_or(path);
+    let (endpoint, id) = ["reads", "variants", "sequences"]
+        .into_iter()
+        .find_map(|endpoint| {
+            path.strip_prefix('/')?
+                .strip_prefix(endpoint)?
+                .strip_prefix('/')
+                .map(|id| (endpoint, id))
+        })?;
+
+    Some((endpoint, crate::handlers::percent_decode(id)))
+}
+
+/// The sequence digest of a refget request (`/sequence/:id`,
+/// `/sequence/:id/metadata`). Policies govern it like the FASTA file the
+/// sequence comes from, which only the refget index can tell.
+pub(crate) fn refget_digest(path: &str) -> Option<String> {
+    let digest = path.strip_prefix("/sequence/")?;
+    let digest = digest.strip_suffix("/metadata").unwrap_or(digest);
+
+    (!digest.is_empty() && !digest.contains('/') && digest != "service-info")
+        .then(|| crate::handlers::percent_decode(digest))
+}
+
+/// Match `text` against a pattern where `*` matches any run of characters
+/// (including `/`) and `?` matches a single character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: &str = r#"
+[[rule]]
+scope = "dataset:prod/*"
+ids = ["prod/*"]
+
+[[rule]]
+claim = "groups"
+value = "cohort-a"
+ids = ["cohort_a_*"]
+endpoints = ["reads"]
+"#;
+
+    fn principal(claims: Value) -> Principal {
+        match claims {
+            Value::Object(map) => Principal::from_claims(map),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("prod/*", "prod/sample1"));
+        assert!(glob_match("prod/*", "prod/a/b"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("sample?", "sample1"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("prod/*", "dev/sample1"));
+        assert!(!glob_match("sample?", "sample12"));
+    }
+
+    #[test]
+    fn test_scope_grant() {
+        let policy = Policy::from_toml(POLICY).unwrap();
+        let user = principal(serde_json::json!({"sub": "alice", "scope": "openid dataset:prod/*"}));
+
+        assert!(policy.authorize(&user, "reads", "prod/sample1").is_ok());
+        assert!(policy.authorize(&user, "variants", "prod/sample1").is_ok());
+        assert!(matches!(
+            policy.authorize(&user, "reads", "dev/sample1"),
+            Err(Error::PermissionDenied)
+        ));
+    }
+
+    #[test]
+    fn test_claim_grant_with_endpoints() {
+        let policy = Policy::from_toml(POLICY).unwrap();
+        let user = principal(serde_json::json!({"sub": "bob", "groups": ["staff", "cohort-a"]}));
+
+        assert!(policy.authorize(&user, "reads", "cohort_a_001").is_ok());
+        assert!(policy.authorize(&user, "variants", "cohort_a_001").is_err());
+        assert!(policy.authorize(&user, "reads", "prod/sample1").is_err());
+    }
+
+    #[test]
+    fn test_rule_requires_scope_or_claim() {
+        let err = Policy::from_toml("[[rule]]\nids = [\"*\"]\n").unwrap_err();
+        assert!(matches!(err, Error::InvalidInput(_)));
+    }
+
+    #[test]
+    fn test_dataset_request() {
+        assert_eq!(
+            dataset_request("/reads/prod%2Fsample1"),
+            Some(("reads", "prod/sample1".to_string()))
+        );
+        assert_eq!(
+            dataset_request("/variants/sample1"),
+            Some(("variants", "sample1".to_string()))
+        );
+        assert_eq!(
+            dataset_request("/meta/reads/sample1"),
+            Some(("reads", "sample1".to_string()))
+        );
+        assert_eq!(
+            dataset_request("/variants/sample1/references"),
+            Some(("variants", "sample1".to_string()))
+        );
+        assert_eq!(
+            dataset_request("/variants/sample1/stats"),
+            Some(("variants", "sample1".to_string()))
+        );
+        assert_eq!(
+            dataset_request("/reads/sample1/coverage"),
+            Some(("reads", "sample1".to_string()))
+        );
+        assert_eq!(
+            dataset_request("/variants/sample1/features"),
+            Some(("variants", "sample1".to_string()))
+        );
+        assert_eq!(
+            dataset_request("/download/reads/prod%2Fsample1"),
+            Some(("reads", "prod/sample1".to_string()))
+        );
+        assert_eq!(
+            dataset_request("/checksums/variants/sample1"),
+            Some(("variants", "sample1".to_string()))
+        );
+        assert_eq!(dataset_request("/data/BAM/sample1"), None);
+        assert_eq!(dataset_request("/readsx/sample1"), None);
+    }
+
+    #[test]
+    fn test_checksums_denied() {
+        let policy = Policy::from_toml(POLICY).unwrap();
+        let user = principal(serde_json::json!({"sub": "bob", "groups": ["cohort-a"]}));
+
+        // The cohort-a rule grants reads only
+        let (endpoint, id) = dataset_request("/checksums/variants/cohort_a_001").unwrap();
+        assert!(matches!(
+            policy.authorize(&user, endpoint, &id),
+            Err(Error::PermissionDenied)
+        ));
+        let (endpoint, id) = dataset_request("/checksums/reads/cohort_a_001").unwrap();
+        assert!(policy.authorize(&user, endpoint, &id).is_ok());
+    }
+
+    #[test]
+    fn test_download_denied() {
+        let policy = Policy::from_toml(POLICY).unwrap();
+        let user = principal(serde_json::json!({"sub": "alice", "scope": "dataset:prod/*"}));
+
+        let (endpoint, id) = dataset_request("/download/variants/dev%2Fsample1").unwrap();
+        assert!(matches!(
+            policy.authorize(&user, endpoint, &id),
+            Err(Error::PermissionDenied)
+        ));
+        let (endpoint, id) = dataset_request("/download/variants/prod%2Fsample1").unwrap();
+        assert!(policy.authorize(&user, endpoint, &id).is_ok());
+    }
+
+    #[cfg(feature = "drs")]
+    #[test]
+    fn test_refget_digest() {
+        assert_eq!(
+            refget_digest("/sequence/6aef897c3d6ff0c78aff06ac189178dd"),
+            Some("6aef897c3d6ff0c78aff06ac189178dd".to_string())
+        );
+        assert_eq!(
+            refget_digest("/sequence/ga4gh%3ASQ.aKF4/metadata"),
+            Some("ga4gh:SQ.aKF4".to_string())
+        );
+        assert_eq!(refget_digest("/sequence/service-info"), None);
+        assert_eq!(refget_digest("/sequences/sample1"), None);
+    }
+
+    #[test]
+    fn test_dataset_request_drs() {
+        assert_eq!(
+            dataset_request("/ga4gh/drs/v1/objects/prod%2Fsample1.vcf"),
+            Some(("variants", "prod/sample1".to_string()))
+        );
+        assert_eq!(
+            dataset_request("/ga4gh/drs/v1/objects/sample1.bam/access/https"),
+            Some(("reads", "sample1".to_string()))
+        );
+        assert_eq!(dataset_request("/ga4gh/drs/v1/service-info"), None);
+    }
+}
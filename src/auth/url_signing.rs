@@ -3,12 +3,32 @@
 //! When authentication is enabled, ticket URLs for `/data/` endpoints are signed
 //! with HMAC to prevent unauthorized access without requiring the client to
 //! re-authenticate when fetching data blocks.
+//!
+//! Signatures cover the canonical resource — the path relative to the server's
+//! base URL plus the query — rather than the full URL, so they validate no
+//! matter which scheme or host (e.g. a reverse proxy's public name) the
+//! request arrives on.
+//!
+//! Secrets can be rotated without invalidating outstanding tickets: configure
+//! the old secret with [`UrlSigner::with_previous_secret`] and signatures made
+//! with it are still accepted during the grace window.
+//!
+//! Replicas behind a load balancer must share a secret, or a ticket signed by
+//! one fails on another. Naming the secret with [`UrlSigner::with_key_id`]
+//! adds a `_kid` parameter to signed URLs, so during a rotation each URL is
+//! checked against the key that signed it rather than every key in turn, and
+//! URLs naming a retired key are refused outright.
+//!
+//! A signer bound to a caller with [`UrlSigner::with_subject`] adds a `_sub`
+//! parameter to the URLs it signs. It is covered by the signature, so the
+//! data endpoint can attribute fetches to the caller who requested the ticket.
 
 use crate::Error;
 use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 type HmacSha256 = Hmac<Sha256>;
 
@@ -17,6 +37,16 @@ type HmacSha256 = Hmac<Sha256>;
 pub struct UrlSigner {
     secret: Vec<u8>,
     expiry_secs: u64,
+    /// ID of `secret`, added to signed URLs as `_kid`
+    key_id: Option<String>,
+    /// Previous secret and the Unix time until which it is still accepted
+    previous: Option<(Vec<u8>, u64)>,
+    /// ID of the previous secret
+    previous_key_id: Option<String>,
+    /// Path of the base URL, stripped from signed URLs (e.g. `/htsget`)
+    base_path: String,
+    /// Subject added to signed URLs as `_sub`
+    subject: Option<String>,
 }
 
 impl UrlSigner {
@@ -29,35 +59,107 @@ impl UrlSigner {
         Self {
             secret: secret.into(),
             expiry_secs,
+            key_id: None,
+            previous: None,
+            previous_key_id: None,
+            base_path: String::new(),
+            subject: None,
         }
     }
 
-    /// Generate a random secret key.
+    /// Set the base URL ticket URLs are built from.
+    ///
+    /// Its path prefix (if any) is excluded from signatures, so URLs signed as
+    /// `https://example.com/htsget/data/...` validate when a proxy forwards
+    /// them to the server as `/data/...`.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_path = url::Url::parse(base_url)
+            .map(|url| url.path().trim_end_matches('/').to_string())
+            .unwrap_or_default();
+        self
+    }
+
+    /// Keep accepting signatures made with `secret` for `grace` from now.
+    ///
+    /// Use when rotating secrets; a grace of at least the URL expiry lets
+    /// every outstanding ticket finish.
+    pub fn with_previous_secret(mut self, secret: impl Into<Vec<u8>>, grace: Duration) -> Self {
+        self.previous = Some((secret.into(), unix_now() + grace.as_secs()));
+        self
+    }
+
+    /// Name the current secret; signed URLs carry the ID as `_kid`.
+    pub fn with_key_id(mut self, key_id: impl Into<String>) -> Self {
+        self.key_id = Some(key_id.into());
+        self
+    }
+
+    /// Name the previous secret, so URLs it signed with this ID are checked
+    /// against it.
+    pub fn with_previous_key_id(mut self, key_id: impl Into<String>) -> Self {
+        self.previous_key_id = Some(key_id.into());
+        self
+    }
+
+    /// Bind signed URLs to `subject`, the caller the ticket was issued to.
+    pub fn with_subject(mut self, subject: &str) -> Self {
+        self.subject = Some(subject.to_string());
+        self
+    }
+
+    /// How long signed URLs are valid.
+    pub fn expiry(&self) -> Duration {
+        Duration::from_secs(self.expiry_secs)
+    }
+
+    /// Generate a random 256-bit secret key from the OS CSPRNG.
     pub fn generate_secret() -> Vec<u8> {
-        use std::collections::hash_map::RandomState;
-        use std::hash::{BuildHasher, Hasher};
-
-        // Use RandomState to generate random bytes
-        let state = RandomState::new();
-        let mut bytes = Vec::with_capacity(32);
-        for _ in 0..4 {
-            let hasher = state.build_hasher();
-            bytes.extend_from_slice(&hasher.finish().to_le_bytes());
-        }
+        let mut bytes = vec![0u8; 32];
+        getrandom::getrandom(&mut bytes).expect("OS random number generator unavailable");
         bytes
     }
 
+    /// Read a secret from a file, ignoring surrounding whitespace.
+    pub fn secret_from_file(path: &Path) -> Result<Vec<u8>, Error> {
+        let contents = std::fs::read(path)?;
+        let secret = contents.trim_ascii().to_vec();
+
+        if secret.is_empty() {
+            return Err(Error::InvalidInput(format!(
+                "data URL secret file {} is empty",
+                path.display()
+            )));
+        }
+        Ok(secret)
+    }
+
     /// Sign a URL with an expiry timestamp.
     ///
-    /// Returns the URL with `_expires` and `_sig` query parameters appended.
+    /// Returns the URL with `_expires` and `_sig` query parameters appended,
+    /// after `_sub` when bound to a subject and `_kid` when the key is named.
     pub fn sign_url(&self, url: &str) -> String {
-        let expires = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("time went backwards")
-            .as_secs()
-            + self.expiry_secs;
+        let expires = unix_now() + self.expiry_secs;
+        let mut url = url.to_string();
+        for (name, value) in [("_sub", &self.subject), ("_kid", &self.key_id)] {
+            if let Some(value) = value {
+                let separator = if url.contains('?') { '&' } else { '?' };
+                let value: String =
+                    url::form_urlencoded::byte_serialize(value.as_bytes()).collect();
+                url = format!("{}{}{}={}", url, separator, name, value);
+            }
+        }
+        let url = url.as_str();
+
+        let resource = parse_url(url)
+            .map(|parsed| {
+                let path = parsed.path();
+                let path = path.strip_prefix(&self.base_path).unwrap_or(path);
+                canonical_resource(path, &parsed)
+            })
+            .unwrap_or_else(|| url.to_string());
 
-        let signature = self.compute_signature(url, expires);
+        let mac = Self::mac(&self.secret, &resource, expires);
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
 
         let separator = if url.contains('?') { '&' } else { '?' };
         format!(
@@ -69,24 +171,49 @@ impl UrlSigner {
     /// Validate a signed URL.
     ///
     /// # Arguments
-    /// * `base_url` - The URL without signature parameters
+    /// * `resource` - Canonical resource from [`parse_signed_url`]
     /// * `expires` - The expiry timestamp from `_expires` parameter
     /// * `signature` - The signature from `_sig` parameter
-    pub fn validate(&self, base_url: &str, expires: u64, signature: &str) -> Result<(), Error> {
+    pub fn validate(&self, resource: &str, expires: u64, signature: &str) -> Result<(), Error> {
         // Check expiry
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("time went backwards")
-            .as_secs();
+        let now = unix_now();
 
         if now > expires {
             tracing::debug!("signed URL expired: now={}, expires={}", now, expires);
             return Err(Error::InvalidAuthentication);
         }
 
-        // Verify signature
-        let expected = self.compute_signature(base_url, expires);
-        if signature != expected {
+        // Verify signature (constant time), falling back to the previous secret
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| Error::InvalidAuthentication)?;
+
+        let verify = |secret: &[u8]| {
+            Self::mac(secret, resource, expires)
+                .verify_slice(&signature)
+                .is_ok()
+        };
+
+        let previous = self
+            .previous
+            .as_ref()
+            .filter(|(_, until)| now <= *until)
+            .map(|(secret, _)| secret.as_slice());
+
+        // A named key is checked alone; unnamed URLs predate key IDs
+        let valid = match signed_param(resource, "_kid") {
+            Some(kid) if self.key_id.as_deref() == Some(kid.as_str()) => verify(&self.secret),
+            Some(kid) if self.previous_key_id.as_deref() == Some(kid.as_str()) => {
+                previous.is_some_and(verify)
+            }
+            Some(kid) => {
+                tracing::debug!("unknown data URL key id: {}", kid);
+                false
+            }
+            None => verify(&self.secret) || previous.is_some_and(verify),
+        };
+
+        if !valid {
             tracing::debug!("invalid URL signature");
             return Err(Error::InvalidAuthentication);
         }
@@ -94,67 +221,80 @@ impl UrlSigner {
         Ok(())
     }
 
-    /// Compute HMAC signature for a URL and expiry.
-    fn compute_signature(&self, url: &str, expires: u64) -> String {
+    /// HMAC over a URL and expiry.
+    fn mac(secret: &[u8], url: &str, expires: u64) -> HmacSha256 {
         let message = format!("{}:{}", url, expires);
 
-        let mut mac =
-            HmacSha256::new_from_slice(&self.secret).expect("HMAC can take key of any size");
+        let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC can take key of any size");
         mac.update(message.as_bytes());
-
-        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+        mac
     }
 }
 
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_secs()
+}
+
 /// Parse signature parameters from a URL.
 ///
-/// Extracts `_expires` and `_sig` query parameters and returns the base URL
-/// without these parameters.
+/// Accepts an absolute URL or a request URI (`/data/...?...`). Extracts the
+/// `_expires` and `_sig` query parameters and returns the canonical resource
+/// (path and remaining query) that the signature covers.
 pub fn parse_signed_url(url: &str) -> Option<(String, u64, String)> {
-    let url_obj = url::Url::parse(url).ok()?;
+    let url_obj = parse_url(url)?;
 
     let mut expires: Option<u64> = None;
     let mut sig: Option<String> = None;
-    let mut base_params = Vec::new();
 
     for (key, value) in url_obj.query_pairs() {
         match key.as_ref() {
             "_expires" => expires = value.parse().ok(),
             "_sig" => sig = Some(value.to_string()),
-            _ => base_params.push((key.to_string(), value.to_string())),
+            _ => {}
         }
     }
 
-    let expires = expires?;
-    let sig = sig?;
-
-    // Reconstruct base URL without signature params
-    let mut base_url = format!(
-        "{}://{}{}",
-        url_obj.scheme(),
-        url_obj.host_str().unwrap_or(""),
-        url_obj.path()
-    );
-
-    if let Some(port) = url_obj.port() {
-        base_url = format!(
-            "{}://{}:{}{}",
-            url_obj.scheme(),
-            url_obj.host_str().unwrap_or(""),
-            port,
-            url_obj.path()
-        );
-    }
+    Some((canonical_resource(url_obj.path(), &url_obj), expires?, sig?))
+}
 
-    if !base_params.is_empty() {
-        let params: Vec<String> = base_params
-            .iter()
-            .map(|(k, v)| format!("{}={}", k, v))
-            .collect();
-        base_url = format!("{}?{}", base_url, params.join("&"));
-    }
+/// The `_sub` parameter of a signed URL; only meaningful once the signature
+/// has been validated.
+pub fn signed_subject(url: &str) -> Option<String> {
+    signed_param(url, "_sub")
+}
+
+/// A query parameter of a signed URL or canonical resource.
+fn signed_param(url: &str, name: &str) -> Option<String> {
+    parse_url(url)?
+        .query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Parse an absolute URL or a host-relative URI.
+fn parse_url(url: &str) -> Option<url::Url> {
+    url::Url::parse(url).ok().or_else(|| {
+        // Placeholder origin; only the path and query are signed
+        url::Url::parse("http://localhost").ok()?.join(url).ok()
+    })
+}
 
-    Some((base_url, expires, sig))
+/// Canonical signed form: `path?query`, without signature parameters.
+fn canonical_resource(path: &str, url: &url::Url) -> String {
+    let params: Vec<String> = url
+        .query_pairs()
+        .filter(|(key, _)| key != "_expires" && key != "_sig")
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect();
+
+    if params.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}?{}", path, params.join("&"))
+    }
 }
 
 #[cfg(test)]
@@ -209,9 +349,12 @@ mod tests {
         let signed = signer.sign_url(url);
         let (_, expires, sig) = parse_signed_url(&signed).unwrap();
 
-        // Try to validate with different base URL
-        let tampered = "http://localhost:8080/data/BAM/other-sample";
-        assert!(signer.validate(tampered, expires, &sig).is_err());
+        // Try to validate with a different resource
+        assert!(
+            signer
+                .validate("/data/BAM/other-sample", expires, &sig)
+                .is_err()
+        );
     }
 
     #[test]
@@ -219,14 +362,54 @@ mod tests {
         let url = "http://localhost:8080/data/BAM/sample1?start=0&end=1000&_expires=1234567890&_sig=abc123";
         let (base, expires, sig) = parse_signed_url(url).unwrap();
 
-        assert_eq!(
-            base,
-            "http://localhost:8080/data/BAM/sample1?start=0&end=1000"
-        );
+        assert_eq!(base, "/data/BAM/sample1?start=0&end=1000");
         assert_eq!(expires, 1234567890);
         assert_eq!(sig, "abc123");
     }
 
+    #[test]
+    fn test_signature_independent_of_host() {
+        let signer = UrlSigner::new(b"test-secret".to_vec(), 3600);
+        let signed = signer.sign_url("http://0.0.0.0:8080/data/BAM/sample1?format=Bam");
+
+        // The request arrives via a proxy under a different scheme and host
+        let request_uri = signed.strip_prefix("http://0.0.0.0:8080").unwrap();
+        let (resource, expires, sig) = parse_signed_url(request_uri).unwrap();
+        assert_eq!(resource, "/data/BAM/sample1?format=Bam");
+        assert!(signer.validate(&resource, expires, &sig).is_ok());
+    }
+
+    #[test]
+    fn test_base_url_path_prefix() {
+        let signer = UrlSigner::new(b"test-secret".to_vec(), 3600)
+            .with_base_url("https://example.com/htsget/");
+        let signed = signer.sign_url("https://example.com/htsget/data/BAM/sample1?format=Bam");
+
+        // The proxy strips the /htsget prefix before forwarding
+        let request_uri = signed.strip_prefix("https://example.com/htsget").unwrap();
+        let (resource, expires, sig) = parse_signed_url(request_uri).unwrap();
+        assert!(signer.validate(&resource, expires, &sig).is_ok());
+    }
+
+    #[test]
+    fn test_subject_is_signed() {
+        let signer =
+            UrlSigner::new(b"test-secret".to_vec(), 3600).with_subject("alice@example.com");
+        let signed = signer.sign_url("http://localhost:8080/data/BAM/sample1?start=0");
+
+        assert_eq!(
+            signed_subject(&signed).as_deref(),
+            Some("alice@example.com")
+        );
+        let (resource, expires, sig) = parse_signed_url(&signed).unwrap();
+        assert!(signer.validate(&resource, expires, &sig).is_ok());
+
+        // Changing the subject invalidates the signature
+        let tampered = signed.replace("_sub=alice", "_sub=mallory");
+        let (resource, expires, sig) = parse_signed_url(&tampered).unwrap();
+        assert!(signer.validate(&resource, expires, &sig).is_err());
+    }
+
     #[test]
     fn test_generate_secret() {
         let secret1 = UrlSigner::generate_secret();
@@ -237,4 +420,63 @@ mod tests {
         // Secrets should be different (with very high probability)
         assert_ne!(secret1, secret2);
     }
+
+    #[test]
+    fn test_previous_secret_during_grace() {
+        let old = UrlSigner::new(b"old-secret".to_vec(), 3600);
+        let url = "http://localhost:8080/data/BAM/sample1";
+        let (base_url, expires, sig) = parse_signed_url(&old.sign_url(url)).unwrap();
+
+        let rotated = UrlSigner::new(b"new-secret".to_vec(), 3600);
+        assert!(rotated.validate(&base_url, expires, &sig).is_err());
+
+        let rotated = rotated.with_previous_secret(b"old-secret".to_vec(), Duration::from_secs(60));
+        assert!(rotated.validate(&base_url, expires, &sig).is_ok());
+
+        // Grace window over
+        let rotated = UrlSigner::new(b"new-secret".to_vec(), 3600)
+            .with_previous_secret(b"old-secret".to_vec(), Duration::ZERO);
+        std::thread::sleep(Duration::from_secs(2));
+        assert!(rotated.validate(&base_url, expires, &sig).is_err());
+    }
+
+    #[test]
+    fn test_key_id() {
+        let url = "http://localhost:8080/data/BAM/sample1";
+        let replica_a = UrlSigner::new(b"shared-secret".to_vec(), 3600).with_key_id("2025-03");
+        let replica_b = UrlSigner::new(b"shared-secret".to_vec(), 3600).with_key_id("2025-03");
+
+        let signed = replica_a.sign_url(url);
+        assert!(signed.contains("_kid=2025-03"));
+        let (resource, expires, sig) = parse_signed_url(&signed).unwrap();
+        assert!(replica_b.validate(&resource, expires, &sig).is_ok());
+
+        // After rotation the old key is found by its ID during the grace window
+        let rotated = UrlSigner::new(b"new-secret".to_vec(), 3600)
+            .with_key_id("2025-04")
+            .with_previous_secret(b"shared-secret".to_vec(), Duration::from_secs(60))
+            .with_previous_key_id("2025-03");
+        assert!(rotated.validate(&resource, expires, &sig).is_ok());
+
+        // A key ID the signer doesn't know is refused, even with a valid MAC
+        let retired = UrlSigner::new(b"shared-secret".to_vec(), 3600).with_key_id("2025-05");
+        assert!(retired.validate(&resource, expires, &sig).is_err());
+
+        // URLs signed before key IDs were configured still validate
+        let unnamed = UrlSigner::new(b"shared-secret".to_vec(), 3600).sign_url(url);
+        let (resource, expires, sig) = parse_signed_url(&unnamed).unwrap();
+        assert!(replica_a.validate(&resource, expires, &sig).is_ok());
+    }
+
+    #[test]
+    fn test_secret_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret");
+
+        std::fs::write(&path, "s3cret\n").unwrap();
+        assert_eq!(UrlSigner::secret_from_file(&path).unwrap(), b"s3cret");
+
+        std::fs::write(&path, "\n").unwrap();
+        assert!(UrlSigner::secret_from_file(&path).is_err());
+    }
 }
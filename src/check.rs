@@ -0,0 +1,321 @@
+//! Data directory validation.
+//!
+//! Scans a data directory and verifies that each recognized data file has a
+//! readable index and a parseable header, and that the index and header agree
+//! on reference sequences, so misconfigured deployments can be caught before
+//! clients hit them. Used by the `htsgetr check` subcommand and the optional
+//! startup preflight (`HTSGET_PREFLIGHT=true`).
+
+use crate::formats::{self, BamIndexReader, BcfIndexReader, CramIndexReader, VcfIndexReader};
+use crate::indexer::detect_format;
+use crate::storage::naming::Naming;
+use crate::types::Format;
+use crate::{Error, Result};
+use noodles::bam::bai;
+use noodles::cram::crai;
+use noodles::csi;
+use noodles::tabix;
+use std::path::{Path, PathBuf};
+
+/// Validation result for a single data file
+#[derive(Debug)]
+pub struct FileCheck {
+    pub path: PathBuf,
+    pub format: Format,
+    /// Index file used, if one was found
+    pub index: Option<PathBuf>,
+    /// Problems that will cause queries against this file to fail
+    pub errors: Vec<String>,
+    /// Problems that degrade behavior (e.g., whole-file responses)
+    pub warnings: Vec<String>,
+}
+
+impl FileCheck {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Totals across a set of file checks
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CheckSummary {
+    pub files: usize,
+    pub failed: usize,
+    pub warnings: usize,
+}
+
+impl CheckSummary {
+    pub fn from_checks(checks: &[FileCheck]) -> Self {
+        Self {
+            files: checks.len(),
+            failed: checks.iter().filter(|c| !c.is_ok()).count(),
+            warnings: checks.iter().map(|c| c.warnings.len()).sum(),
+        }
+    }
+}
+
+impl std::fmt::Display for CheckSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} file(s) checked, {} failed, {} warning(s)",
+            self.files, self.failed, self.warnings
+        )
+    }
+}
+
+/// Check every recognized data file in a directory (non-recursive).
+pub async fn check_directory(dir: &Path) -> Result<Vec<FileCheck>> {
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .map_err(|e| Error::Internal(format!("failed to read data dir {:?}: {}", dir, e)))?;
+
+    let mut paths = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.is_file() && detect_format(&path).is_some() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut checks = Vec::with_capacity(paths.len());
+    for path in paths {
+        if let Some(check) = check_file(&path).await {
+            checks.push(check);
+        }
+    }
+
+    Ok(checks)
+}
+
+/// Check a single data file. Returns `None` for unrecognized file types.
+pub async fn check_file(path: &Path) -> Option<FileCheck> {
+    let format = detect_format(path)?;
+
+    let mut check = FileCheck {
+        path: path.to_path_buf(),
+        format,
+        index: find_index(path, format),
+        errors: Vec::new(),
+        warnings: Vec::new(),
+    };
+
+    // Header must parse for header-class and region queries
+    if let Err(e) = check_header(path, format).await {
+        check.errors.push(format!("header: {}", e));
+    }
+
+    match &check.index {
+        Some(index) => match check_index(path, index, format).await {
+            Ok(()) if check.errors.is_empty() => {
+                match check_reference_names(path, index, format).await {
+                    Ok(warnings) => check.warnings.extend(warnings),
+                    Err(e) => check
+                        .warnings
+                        .push(format!("could not compare reference names: {}", e)),
+                }
+            }
+            Ok(()) => {}
+            Err(e) => check
+                .errors
+                .push(format!("index {}: {}", index.display(), e)),
+        },
+        None if format == Format::Fastq => {}
+        None => check
+            .warnings
+            .push("no index found; region queries will return the whole file".to_string()),
+    }
+
+    Some(check)
+}
+
+/// Locate an index using the appended (`file.bam.bai`) or replaced (`file.bai`) convention.
+pub fn find_index(path: &Path, format: Format) -> Option<PathBuf> {
+    let naming = Naming::default();
+    let name = path.file_name()?.to_str()?;
+    let (stem, ext) = naming.split(name, format)?;
+    let stem = path.with_file_name(stem);
+
+    naming
+        .index_names(&stem.to_string_lossy(), ext, format)
+        .into_iter()
+        .map(PathBuf::from)
+        .find(|p| p.exists())
+}
+
+async fn check_header(path: &Path, format: Format) -> Result<()> {
+    match format {
+        Format::Bam => BamIndexReader::read_header(path).await.map(|_| ()),
+        Format::Cram => CramIndexReader::read_header(path).await.map(|_| ()),
+        Format::Vcf => VcfIndexReader::header_range(path).await.map(|_| ()),
+        Format::Bcf => BcfIndexReader::read_header(path).await.map(|_| ()),
+        Format::Fasta | Format::Fastq => Ok(()),
+    }
+}
+
+/// Parse the index by running an empty region query through the format's reader.
+async fn check_index(path: &Path, index: &Path, format: Format) -> Result<()> {
+    formats::query_ranges(format, path, index, &[])
+        .await
+        .map(|_| ())
+}
+
+/// Compare reference sequences declared in the header against those in the index.
+///
+/// Mismatches usually mean the index was built from a different file (or an
+/// older version of it) and region queries will return wrong or empty ranges.
+async fn check_reference_names(path: &Path, index: &Path, format: Format) -> Result<Vec<String>> {
+    let mut warnings = Vec::new();
+
+    match format {
+        Format::Bam => {
+            let header = BamIndexReader::read_header(path).await?;
+            let index = bai::r#async::read(index)
+                .await
+                .map_err(|e| Error::Internal(format!("failed to read BAI index: {}", e)))?;
+
+            let (declared, indexed) = (
+                header.reference_sequences().len(),
+                index.reference_sequences().len(),
+            );
+            if declared != indexed {
+                warnings.push(format!(
+                    "header declares {} reference sequences but index has {}",
+                    declared, indexed
+                ));
+            }
+        }
+        Format::Cram => {
+            let header = CramIndexReader::read_header(path).await?;
+            let index = crai::r#async::read(index)
+                .await
+                .map_err(|e| Error::Internal(format!("failed to read CRAI index: {}", e)))?;
+
+            let declared = header.reference_sequences().len();
+            if let Some(max_id) = index.iter().filter_map(|r| r.reference_sequence_id()).max() {
+                if max_id >= declared {
+                    warnings.push(format!(
+                        "index references sequence #{} but header declares only {}",
+                        max_id, declared
+                    ));
+                }
+            }
+        }
+        Format::Vcf => {
+            let header = VcfIndexReader::read_header(path).await?;
+            let index = tabix::r#async::read(index)
+                .await
+                .map_err(|e| Error::Internal(format!("failed to read tabix index: {}", e)))?;
+
+            // Many VCFs omit ##contig lines; only compare when the header declares them
+            if let Some(tabix_header) = index.header() {
+                let contigs = header.contigs();
+                if !contigs.is_empty() {
+                    let missing: Vec<&str> = tabix_header
+                        .reference_sequence_names()
+                        .iter()
+                        .map(|name| name.as_str())
+                        .filter(|name| !contigs.contains_key(*name))
+                        .collect();
+
+                    if !missing.is_empty() {
+                        warnings.push(format!(
+                            "index reference names missing from header contigs: {}",
+                            missing.join(", ")
+                        ));
+                    }
+                }
+            }
+        }
+        Format::Bcf => {
+            let header = BcfIndexReader::read_header(path).await?;
+            let index = csi::r#async::read(index)
+                .await
+                .map_err(|e| Error::Internal(format!("failed to read CSI index: {}", e)))?;
+
+            let (declared, indexed) = (header.contigs().len(), index.reference_sequences().len());
+            if indexed > declared {
+                warnings.push(format!(
+                    "index has {} reference sequences but header declares only {} contigs",
+                    indexed, declared
+                ));
+            }
+        }
+        Format::Fasta | Format::Fastq => {}
+    }
+
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_test_data() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+        if !dir.join("mt.bam").exists() {
+            return;
+        }
+
+        let checks = check_directory(&dir).await.unwrap();
+        let mt = checks
+            .iter()
+            .find(|c| c.path.ends_with("mt.bam"))
+            .expect("mt.bam should be checked");
+
+        assert_eq!(mt.format, Format::Bam);
+        assert!(mt.index.is_some());
+        assert!(mt.is_ok(), "unexpected errors: {:?}", mt.errors);
+        assert!(
+            mt.warnings.is_empty(),
+            "unexpected warnings: {:?}",
+            mt.warnings
+        );
+    }
+
+    #[test]
+    fn test_check_summary() {
+        let ok = FileCheck {
+            path: PathBuf::from("a.bam"),
+            format: Format::Bam,
+            index: None,
+            errors: vec![],
+            warnings: vec!["no index".to_string()],
+        };
+        let failed = FileCheck {
+            path: PathBuf::from("b.bam"),
+            format: Format::Bam,
+            index: None,
+            errors: vec!["header".to_string()],
+            warnings: vec![],
+        };
+
+        let summary = CheckSummary::from_checks(&[ok, failed]);
+        assert_eq!(
+            summary,
+            CheckSummary {
+                files: 2,
+                failed: 1,
+                warnings: 1
+            }
+        );
+        assert_eq!(
+            summary.to_string(),
+            "2 file(s) checked, 1 failed, 1 warning(s)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_missing_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ref.fa");
+        std::fs::write(&path, ">chr1\nACGT\n").unwrap();
+
+        let check = check_file(&path).await.unwrap();
+        assert!(check.is_ok());
+        assert!(check.index.is_none());
+        assert_eq!(check.warnings.len(), 1);
+    }
+}
@@ -0,0 +1,552 @@
+//! htsget client.
+//!
+//! [`HtsgetClient`] performs both phases of the htsget protocol: it requests a
+//! ticket from a server (every page of it, if the server pages tickets), then
+//! follows every URL in the ticket (sending any `Range` or other headers the
+//! ticket specifies and decoding inline `data:` URIs) and concatenates the
+//! blocks into a single file. Transient failures (connection errors, 5xx, 429)
+//! are retried with exponential backoff, and the assembled file is checked
+//! against the ticket's `md5` when one is present.
+//!
+//! [`HtsgetClient`] requires the `client` feature. The request building,
+//! `data:` URI decoding and md5 checks are shared with the browser client
+//! in `wasm` (`wasm` feature), which follows tickets with `fetch` instead.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use htsgetr::client::{HtsgetClient, TicketRequest};
+//!
+//! # async fn example() -> htsgetr::Result<()> {
+//! let client = HtsgetClient::new("http://localhost:8080");
+//! let request = TicketRequest {
+//!     region: Some("chr1:1-100000".parse()?),
+//!     ..Default::default()
+//! };
+//!
+//! let mut output = tokio::fs::File::create("slice.bam").await?;
+//! client.download("reads", "NA12878", &request, &mut output).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::types::{DataClass, Format, HTSGET_MEDIA_TYPE, Region};
+#[cfg(feature = "client")]
+use crate::types::{HtsgetResponse, UrlEntry};
+use crate::{Error, Result};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use md5::{Digest, Md5};
+use serde::Deserialize;
+#[cfg(feature = "client")]
+use std::time::Duration;
+#[cfg(feature = "client")]
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Default number of retries for transient request failures
+#[cfg(feature = "client")]
+pub const DEFAULT_RETRIES: u32 = 3;
+
+/// Delay before the first retry; doubled for each subsequent attempt
+#[cfg(feature = "client")]
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(250);
+
+/// Longest delay between retries, however many there are
+#[cfg(feature = "client")]
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Parameters for a ticket request
+#[derive(Debug, Default)]
+pub struct TicketRequest {
+    pub format: Option<Format>,
+    pub class: Option<DataClass>,
+    pub region: Option<Region>,
+}
+
+impl TicketRequest {
+    /// Query string parameters for the ticket request.
+    ///
+    /// Independent of the HTTP transport, so other clients (e.g. a browser
+    /// `fetch`-based one) can build identical requests.
+    pub fn query_pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs = Vec::new();
+
+        if let Some(format) = self.format {
+            pairs.push(("format", format!("{:?}", format).to_uppercase()));
+        }
+        if self.class == Some(DataClass::Header) {
+            pairs.push(("class", "header".to_string()));
+        }
+        if let Some(region) = &self.region {
+            pairs.push(("referenceName", region.reference_name.clone()));
+            if let Some(start) = region.start {
+                pairs.push(("start", start.to_string()));
+            }
+            if let Some(end) = region.end {
+                pairs.push(("end", end.to_string()));
+            }
+        }
+
+        pairs
+    }
+}
+
+/// `base_url` (e.g. `http://localhost:8080`) as the base that ticket
+/// requests and relative ticket URLs are resolved against
+pub fn base_url(base_url: &str) -> Result<url::Url> {
+    // A trailing slash makes relative joins append rather than replace the last segment
+    let base_url = format!("{}/", base_url.trim_end_matches('/'));
+    url::Url::parse(&base_url)
+        .map_err(|e| Error::InvalidInput(format!("invalid base URL {}: {}", base_url, e)))
+}
+
+/// URL of the ticket request for `id` from `/{endpoint}/{id}`
+pub fn ticket_url(
+    base_url: &url::Url,
+    endpoint: &str,
+    id: &str,
+    request: &TicketRequest,
+) -> Result<url::Url> {
+    let mut url = base_url
+        .join(&format!("{}/{}", endpoint, id))
+        .map_err(|e| Error::InvalidInput(format!("invalid ticket URL: {}", e)))?;
+    url.query_pairs_mut().extend_pairs(request.query_pairs());
+    Ok(url)
+}
+
+/// A URL from a ticket (a data URL or the next page), which may be relative
+/// to the server
+pub fn resolve_url(base_url: &url::Url, url: &str) -> Result<url::Url> {
+    base_url
+        .join(url)
+        .map_err(|e| Error::Internal(format!("invalid ticket URL {}: {}", url, e)))
+}
+
+/// `Accept` header of ticket requests
+pub(crate) fn ticket_accept() -> String {
+    format!("{}, application/json;q=0.9", HTSGET_MEDIA_TYPE)
+}
+
+/// Check the bytes `hasher` has seen against a ticket's `md5`, if it has one
+pub(crate) fn check_md5(expected: Option<&str>, hasher: Md5) -> Result<()> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    let actual = format!("{:x}", hasher.finalize());
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(Error::Internal(format!(
+            "md5 mismatch: ticket says {}, received {}",
+            expected, actual
+        )));
+    }
+    Ok(())
+}
+
+/// Async client for htsget servers
+#[cfg(feature = "client")]
+#[derive(Debug, Clone)]
+pub struct HtsgetClient {
+    base_url: url::Url,
+    http: reqwest::Client,
+    bearer_token: Option<String>,
+    retries: u32,
+}
+
+#[cfg(feature = "client")]
+impl HtsgetClient {
+    /// Create a client for the server at `base_url` (e.g., `http://localhost:8080`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `base_url` is not a valid absolute URL; use [`HtsgetClient::try_new`]
+    /// to handle that case.
+    pub fn new(base_url: &str) -> Self {
+        Self::try_new(base_url).expect("invalid htsget base URL")
+    }
+
+    /// Create a client, returning an error for an invalid base URL.
+    pub fn try_new(base_url: &str) -> Result<Self> {
+        Ok(Self {
+            base_url: self::base_url(base_url)?,
+            http: reqwest::Client::new(),
+            bearer_token: None,
+            retries: DEFAULT_RETRIES,
+        })
+    }
+
+    /// Use a preconfigured reqwest client (timeouts, proxies, TLS settings).
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Send `Authorization: Bearer <token>` with ticket requests.
+    pub fn with_bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_token = Some(token.into());
+        self
+    }
+
+    /// Retry transient failures up to `retries` times (default [`DEFAULT_RETRIES`]).
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Request a ticket from `/{endpoint}/{id}`. A paged ticket's `next`
+    /// pages are requested in turn and their URLs appended, so the result
+    /// is the whole ticket.
+    pub async fn ticket(
+        &self,
+        endpoint: &str,
+        id: &str,
+        request: &TicketRequest,
+    ) -> Result<HtsgetResponse> {
+        let url = ticket_url(&self.base_url, endpoint, id, request)?;
+
+        let mut ticket = self.ticket_page(&url).await?;
+        while let Some(next) = ticket.htsget.next.take() {
+            let url = resolve_url(&self.base_url, &next)?;
+            let page = self.ticket_page(&url).await?;
+            ticket.htsget.urls.extend(page.htsget.urls);
+            ticket.htsget.next = page.htsget.next;
+        }
+        Ok(ticket)
+    }
+
+    async fn ticket_page(&self, url: &url::Url) -> Result<HtsgetResponse> {
+        let mut builder = self
+            .http
+            .get(url.clone())
+            .header(reqwest::header::ACCEPT, ticket_accept());
+        if let Some(token) = &self.bearer_token {
+            builder = builder.bearer_auth(token);
+        }
+
+        let response = self.send(builder, url).await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(server_error(status.as_u16(), &body));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| Error::Internal(format!("invalid ticket from {}: {}", url, e)))
+    }
+
+    /// Fetch the bytes for a single ticket URL entry.
+    pub async fn fetch_url(&self, entry: &UrlEntry) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write_url(entry, &mut buf, &mut Md5::new()).await?;
+        Ok(buf)
+    }
+
+    /// Fetch every URL in a ticket and return the concatenated bytes.
+    pub async fn fetch(&self, ticket: &HtsgetResponse) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.fetch_to(ticket, &mut buf).await?;
+        Ok(buf)
+    }
+
+    /// Stream every URL in a ticket, in order, to `writer`. Returns bytes written.
+    ///
+    /// If the ticket carries an `md5`, the streamed bytes are verified against
+    /// it after the last block is written.
+    pub async fn fetch_to<W>(&self, ticket: &HtsgetResponse, writer: &mut W) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut hasher = Md5::new();
+        let mut written = 0;
+        for entry in &ticket.htsget.urls {
+            written += self.write_url(entry, writer, &mut hasher).await?;
+        }
+        writer.flush().await?;
+
+        check_md5(ticket.htsget.md5.as_deref(), hasher)?;
+        Ok(written)
+    }
+
+    /// Request a ticket and stream the resulting data to `writer`. Returns bytes written.
+    pub async fn download<W>(
+        &self,
+        endpoint: &str,
+        id: &str,
+        request: &TicketRequest,
+        writer: &mut W,
+    ) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let ticket = self.ticket(endpoint, id, request).await?;
+        self.fetch_to(&ticket, writer).await
+    }
+
+    async fn write_url<W>(&self, entry: &UrlEntry, writer: &mut W, hasher: &mut Md5) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        if entry.url.starts_with("data:") {
+            let bytes = decode_data_uri(&entry.url)?;
+            hasher.update(&bytes);
+            writer.write_all(&bytes).await?;
+            return Ok(bytes.len() as u64);
+        }
+
+        let url = resolve_url(&self.base_url, &entry.url)?;
+
+        let mut builder = self.http.get(url.clone());
+        for (name, value) in entry.headers.iter().flatten() {
+            builder = builder.header(name, value);
+        }
+
+        let mut response = self.send(builder, &url).await?;
+
+        if !response.status().is_success() {
+            return Err(Error::Internal(format!(
+                "data request to {} returned {}",
+                url,
+                response.status()
+            )));
+        }
+
+        let mut written = 0;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| Error::Internal(format!("failed reading {}: {}", url, e)))?
+        {
+            hasher.update(&chunk);
+            writer.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+
+        Ok(written)
+    }
+
+    /// Send a request, retrying connection failures, timeouts, 5xx and 429.
+    ///
+    /// Only sending is retried; once a response body starts streaming to the
+    /// writer a failure is returned as-is, since bytes may already be written.
+    async fn send(
+        &self,
+        builder: reqwest::RequestBuilder,
+        url: &url::Url,
+    ) -> Result<reqwest::Response> {
+        let mut attempt = 0;
+
+        loop {
+            let request = builder
+                .try_clone()
+                .ok_or_else(|| Error::Internal(format!("request to {} cannot be retried", url)))?;
+
+            let failure = match request.send().await {
+                Ok(response) if !is_transient(response.status()) => return Ok(response),
+                Ok(response) if attempt >= self.retries => return Ok(response),
+                Ok(response) => response.status().to_string(),
+                Err(e) if attempt < self.retries && (e.is_connect() || e.is_timeout()) => {
+                    e.to_string()
+                }
+                Err(e) => {
+                    return Err(Error::Internal(format!("request to {} failed: {}", url, e)));
+                }
+            };
+
+            attempt += 1;
+            tracing::warn!(
+                "request to {} failed ({}); retry {}/{}",
+                url,
+                failure,
+                attempt,
+                self.retries
+            );
+            tokio::time::sleep(retry_delay(attempt)).await;
+        }
+    }
+}
+
+/// Delay before retry number `attempt` (1-based): exponential, capped at
+/// [`RETRY_MAX_DELAY`].
+#[cfg(feature = "client")]
+fn retry_delay(attempt: u32) -> Duration {
+    2u32.checked_pow(attempt.saturating_sub(1))
+        .and_then(|factor| RETRY_BASE_DELAY.checked_mul(factor))
+        .map_or(RETRY_MAX_DELAY, |delay| delay.min(RETRY_MAX_DELAY))
+}
+
+#[cfg(feature = "client")]
+fn is_transient(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Decode an RFC 2397 `data:` URI (base64 or percent-encoded).
+pub fn decode_data_uri(uri: &str) -> Result<Vec<u8>> {
+    let invalid = || Error::InvalidInput("invalid data URI".to_string());
+
+    let rest = uri.strip_prefix("data:").ok_or_else(invalid)?;
+    let (meta, data) = rest.split_once(',').ok_or_else(invalid)?;
+
+    if meta.ends_with(";base64") {
+        STANDARD
+            .decode(data)
+            .map_err(|e| Error::InvalidInput(format!("invalid base64 in data URI: {}", e)))
+    } else {
+        percent_decode(data).ok_or_else(invalid)
+    }
+}
+
+fn percent_decode(data: &str) -> Option<Vec<u8>> {
+    let bytes = data.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    Some(out)
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerError {
+    htsget: ServerErrorBody,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerErrorBody {
+    error: String,
+    message: String,
+}
+
+/// Map an htsget error response back onto [`Error`].
+pub(crate) fn server_error(status: u16, body: &str) -> Error {
+    let Ok(ServerError { htsget }) = serde_json::from_str::<ServerError>(body) else {
+        return Error::Internal(format!("server returned {}: {}", status, body));
+    };
+
+    match htsget.error.as_str() {
+        "InvalidAuthentication" => Error::InvalidAuthentication,
+        "PermissionDenied" => Error::PermissionDenied,
+        "NotFound" => Error::NotFound(htsget.message),
+        "PayloadTooLarge" => Error::PayloadTooLarge,
+        "UnsupportedFormat" => Error::UnsupportedFormat(htsget.message),
+        "InvalidInput" => Error::InvalidInput(htsget.message),
+        "InvalidRange" => Error::InvalidRange(htsget.message),
+        "Timeout" => Error::Timeout(htsget.message),
+        "Unavailable" => Error::Unavailable(htsget.message),
+        _ => Error::Internal(htsget.message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_data_uri() {
+        assert_eq!(
+            decode_data_uri("data:application/vnd.ga4gh.bam;base64,QkFNAQ==").unwrap(),
+            b"BAM\x01"
+        );
+        assert_eq!(decode_data_uri("data:,a%20b").unwrap(), b"a b");
+        assert!(decode_data_uri("data:;base64").is_err());
+        assert!(decode_data_uri("data:,%zz").is_err());
+    }
+
+    #[test]
+    fn test_query_pairs() {
+        let request = TicketRequest {
+            format: Some(Format::Bam),
+            class: None,
+            region: Some("chr1:1001-2000".parse().unwrap()),
+        };
+
+        assert_eq!(
+            request.query_pairs(),
+            vec![
+                ("format", "BAM".to_string()),
+                ("referenceName", "chr1".to_string()),
+                ("start", "1000".to_string()),
+                ("end", "2000".to_string()),
+            ]
+        );
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(reqwest::StatusCode::SERVICE_UNAVAILABLE));
+        assert!(is_transient(reqwest::StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_transient(reqwest::StatusCode::NOT_FOUND));
+        assert!(!is_transient(reqwest::StatusCode::PARTIAL_CONTENT));
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(3), RETRY_BASE_DELAY * 4);
+        assert_eq!(retry_delay(20), RETRY_MAX_DELAY);
+        assert_eq!(retry_delay(33), RETRY_MAX_DELAY);
+        assert_eq!(retry_delay(u32::MAX), RETRY_MAX_DELAY);
+    }
+
+    #[cfg(feature = "client")]
+    #[tokio::test]
+    async fn test_fetch_data_uris_verifies_md5() {
+        let ticket: HtsgetResponse = serde_json::from_str(
+            r#"{"htsget":{"format":"BAM","urls":[
+                {"url":"data:;base64,aGVs"},
+                {"url":"data:,lo"}
+            ],"md5":"5d41402abc4b2a76b9719d911017c592"}}"#,
+        )
+        .unwrap();
+
+        let client = HtsgetClient::new("http://localhost:8080");
+        assert_eq!(client.fetch(&ticket).await.unwrap(), b"hello");
+
+        let mut ticket = ticket;
+        ticket.htsget.md5 = Some("00000000000000000000000000000000".to_string());
+        assert!(client.fetch(&ticket).await.is_err());
+    }
+
+    #[test]
+    fn test_server_error() {
+        let body = r#"{"htsget":{"error":"NotFound","message":"not found: x"}}"#;
+        assert!(matches!(server_error(404, body), Error::NotFound(_)));
+        let body =
+            r#"{"htsget":{"error":"Unavailable","message":"unavailable: x","retryable":true}}"#;
+        assert!(matches!(server_error(503, body), Error::Unavailable(_)));
+        assert!(matches!(
+            server_error(502, "bad gateway"),
+            Error::Internal(_)
+        ));
+    }
+
+    #[test]
+    fn test_relative_ticket_url() {
+        let base_url = base_url("http://localhost:8080/htsget").unwrap();
+        let request = TicketRequest {
+            format: Some(Format::Bam),
+            ..Default::default()
+        };
+        let url = ticket_url(&base_url, "reads", "NA12878", &request).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://localhost:8080/htsget/reads/NA12878?format=BAM"
+        );
+        let url = resolve_url(&base_url, "data/reads/NA12878").unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://localhost:8080/htsget/data/reads/NA12878"
+        );
+    }
+}
@@ -13,24 +13,122 @@
 //! println!("Serving from: {:?}", config.data_dir);
 //! ```
 //!
+//! [`Config::validate`] checks a parsed configuration before the server
+//! starts, reporting every problem in one [`ConfigErrors`].
+//!
+//! Secret options (keys, tokens, origin headers) are marked
+//! `hide_env_values`, which keeps them out of `--help` and has
+//! [`ConfigReport`](crate::admin::ConfigReport) redact them.
+//!
+//! # Config File
+//!
+//! Settings can also be read from a TOML file named by `--config` (or
+//! `HTSGET_CONFIG`), keyed by option name, e.g. `data_dir = "/data"`.
+//! [`Config::load`] applies them as defaults, so flags and environment
+//! variables still override them. `htsgetr init` writes an example with
+//! every setting commented out.
+//!
 //! # Environment Variables
 //!
 //! All options can be set via environment variables:
 //!
 //! | Variable | Default | Description |
 //! |----------|---------|-------------|
+//! | `HTSGET_CONFIG` | - | TOML file of settings, overridden by flags and variables |
 //! | `HTSGET_HOST` | `0.0.0.0` | Bind address |
 //! | `HTSGET_PORT` | `8080` | Listen port |
 //! | `HTSGET_DATA_DIR` | `./data` | Data directory |
+//! | `HTSGET_STORAGE` | `local` | Storage backend: `local`, `s3` (`s3` feature) or `http` (`http` feature) |
+//! | `HTSGET_S3_BUCKET` | - | Bucket when `HTSGET_STORAGE=s3` |
+//! | `HTSGET_S3_PROFILE` | - | Named AWS profile for S3 credentials |
+//! | `HTSGET_S3_ROLE_ARN` | - | IAM role to assume for S3 (STS or web identity) |
+//! | `HTSGET_S3_BUCKET_ROUTES` | - | TOML file mapping ID prefixes to other buckets |
+//! | `HTSGET_S3_PUBLIC` | `false` | Read a public bucket anonymously, with unsigned ticket URLs |
+//! | `HTSGET_HTTP_BASE_URL` | - | Base URL for data files when `HTSGET_STORAGE=http` |
+//! | `HTSGET_HTTP_INDEX_BASE_URL` | `HTSGET_HTTP_BASE_URL` | Base URL for index files |
+//! | `HTSGET_HTTP_URL_TEMPLATE` | `{base}/{id}.{ext}` | Upstream URL of a file |
+//! | `HTSGET_HTTP_MAX_REDIRECTS` | `10` | Redirects followed per HTTP origin request |
+//! | `HTSGET_HTTP_HEADERS` | - | Headers sent to the HTTP origin (`Name: value,...`) |
+//! | `HTSGET_HTTP_FORWARD_HEADERS` | - | Client request headers forwarded to the HTTP origin |
+//! | `HTSGET_HTTP_TICKET_HEADERS` | - | Of `HTSGET_HTTP_HEADERS`, those put in tickets |
+//! | `HTSGET_DATA_EXTENSIONS` | - | Extra data file extensions (`format:ext,...`) |
+//! | `HTSGET_DECRYPTION_KEYS` | - | TOML file of Crypt4GH private keys per ID prefix (requires `crypt4gh` feature) |
 //! | `HTSGET_BASE_URL` | auto | Base URL for tickets |
 //! | `HTSGET_CORS` | `true` | Enable CORS |
+//! | `HTSGET_CORS_ORIGINS` | - | Allowed CORS origins (instead of `*`) |
+//! | `HTSGET_TRUSTED_PROXIES` | - | Proxies whose forwarding headers set ticket URLs |
+//! | `HTSGET_DETECT_FORMAT` | `false` | Serve IDs in whichever format exists when none is requested |
+//! | `HTSGET_PREFLIGHT` | `false` | Validate data files before serving |
+//! | `HTSGET_TLS_CERT` / `HTSGET_TLS_KEY` | - | Serve HTTPS (requires `tls` feature) |
+//! | `HTSGET_TLS_CLIENT_CA` | - | Verify client certificates (mTLS) |
+//! | `HTSGET_PRESIGNED_URL_EXPIRY_PER_URL` | `0` | Extra presigned URL seconds per URL in a ticket |
+//! | `HTSGET_PRESIGN_CLOCK_SKEW` | `0` | Seconds of clock skew tolerated by presigned URLs |
+//! | `HTSGET_ENABLE_TICKET_REFRESH` | `false` | Serve `POST /tickets/refresh` |
+//! | `HTSGET_ENABLE_SWAGGER_UI` | `false` | Serve a Swagger UI at `/swagger-ui/` (`openapi` feature) |
+//! | `HTSGET_PLAIN_JSON_TICKETS` | `false` | Send tickets as `application/json` |
+//! | `HTSGET_REFERENCE_ALIASES` | `false` | Match reference names ignoring case and through GRCh37/38 aliases |
+//! | `HTSGET_REFERENCE_ALIAS_FILE` | - | Extra aliases, one set of equivalent names per line |
+//! | `HTSGET_HEADER_PARSING` | `strict` | `lenient` serves files without the header lines that don't parse |
+//! | `HTSGET_TICKET_SIGNING_KEY` | - | PKCS#8 PEM key tickets are signed with (requires `jws` feature) |
+//! | `HTSGET_TICKET_SIGNATURE` | `detached` | Signature in a header (`detached`) or as the body (`compact`) |
+//! | `HTSGET_TICKET_CACHE_TTL` | `0` | Seconds to reuse index queries of identical tickets |
+//! | `HTSGET_TICKET_URL_TTL` | `0` | Most seconds tickets say their URLs are valid for |
+//! | `HTSGET_MAX_REGIONS` | `1000` | Most regions a ticket request may name |
+//! | `HTSGET_TICKET_PAGE_SIZE` | `0` | Most URLs per ticket page, with a `next` URL for the rest |
+//! | `HTSGET_RETRY_MAX_ATTEMPTS` | `3` | Attempts per S3/HTTP storage request |
+//! | `HTSGET_PREFETCH_INDEXES` | `false` | Warm the index cache in the background at startup |
+//! | `HTSGET_STORAGE_TIMEOUT` | `30` | Seconds per S3/HTTP storage request attempt |
+//! | `HTSGET_REQUEST_TIMEOUT` | `60` | Seconds before a request fails with `Timeout` |
+//! | `HTSGET_ERROR_DETAIL` | `false` | Add `retryable`, `detail` and `requestId` to error bodies |
+//! | `HTSGET_EXPOSE_INTERNAL_ERRORS` | `false` | Send internal error messages to clients (debugging) |
+//! | `HTSGET_AUDIT_LOG` | - | Audit log sink: `log`, `file:<path>` or a webhook URL |
+//! | `HTSGET_MULTI_REPLICA` | `false` | Require a shared data URL secret when auth is enabled |
+//! | `HTSGET_REDIS_URL` | - | Redis for caches shared between replicas (requires `redis` feature) |
+//! | `HTSGET_QUOTA_REQUESTS` / `HTSGET_QUOTA_BYTES` | - | Per-subject data quotas per window |
+//! | `HTSGET_QUOTA_WINDOW` | `86400` | Quota window in seconds |
+//! | `HTSGET_QUOTA_STORE` | `memory` | Quota counters: `memory` or a `redis://` URL |
+//! | `HTSGET_USAGE_REPORT` | `false` | Count bytes served per dataset and principal (`/metrics`, `/admin/usage`) |
+//! | `HTSGET_ADMIN_TOKEN` | - | Bearer token required by `/metrics`, `/admin/usage` and `/admin/config` |
+//! | `HTSGET_JOBS_DIR` | - | Directory of export jobs; serves `POST /jobs` |
+//! | `HTSGET_MAX_RUNNING_JOBS` | `2` | Export jobs run at once |
+//! | `HTSGET_JOBS_RETENTION` | `604800` | Seconds finished export jobs are kept (0 = forever) |
+//! | `HTSGET_JOBS_S3_BUCKET` / `HTSGET_JOBS_S3_PREFIX` | - | Upload export job results to S3 (requires `s3` feature) |
 //! | `RUST_LOG` | `info` | Log level |
 
-use clap::Parser;
-use std::path::PathBuf;
+use crate::audit::AuditSink;
+use crate::formats::{HeaderParsing, ReferenceAliases};
+use crate::handlers::{DataMode, DefaultFormats, Endpoints, HeaderMode, TicketSignature};
+#[cfg(feature = "jws")]
+use crate::jws::TicketSigner;
+use crate::quota::{QuotaBackend, QuotaLimits};
+use crate::server::{CorsOptions, normalize_path_prefix};
+#[cfg(feature = "s3")]
+use crate::storage::BucketRoute;
+#[cfg(feature = "crypt4gh")]
+use crate::storage::DecryptionRoute;
+use crate::storage::{ChunkedDownload, Naming, RetryPolicy};
+#[cfg(feature = "http")]
+use crate::storage::{parse_header_names, parse_headers};
+use crate::types::Format;
+use clap::error::ErrorKind;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+#[cfg(feature = "http")]
+use reqwest::header::{HeaderMap, HeaderName};
+use std::any::TypeId;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
 /// Storage backend type
+///
+/// Every variant parses in every build; backends whose cargo feature is off
+/// are rejected at startup, naming the feature to enable. A new backend
+/// (e.g. GCS or Azure Blob Storage) adds a variant with its
+/// [`names`](Self::names) and [`feature`](Self::feature), a
+/// [`Storage`](crate::storage::Storage) implementation behind that feature,
+/// and a branch where the server builds its storage.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum StorageType {
     #[default]
@@ -39,179 +137,1954 @@ pub enum StorageType {
     Http,
 }
 
+impl StorageType {
+    /// Every backend, in the order `--storage` lists them
+    pub const ALL: [StorageType; 3] = [StorageType::Local, StorageType::S3, StorageType::Http];
+
+    /// Names accepted for the backend (case-insensitive); the first is canonical
+    pub fn names(&self) -> &'static [&'static str] {
+        match self {
+            StorageType::Local => &["local"],
+            StorageType::S3 => &["s3"],
+            StorageType::Http => &["http", "https"],
+        }
+    }
+
+    /// Cargo feature the backend is built with, if it isn't always present
+    pub fn feature(&self) -> Option<&'static str> {
+        match self {
+            StorageType::Local => None,
+            StorageType::S3 => Some("s3"),
+            StorageType::Http => Some("http"),
+        }
+    }
+
+    /// Whether this build includes the backend
+    pub fn is_available(&self) -> bool {
+        match self {
+            StorageType::Local => true,
+            StorageType::S3 => cfg!(feature = "s3"),
+            StorageType::Http => cfg!(feature = "http"),
+        }
+    }
+}
+
 impl FromStr for StorageType {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "local" => Ok(StorageType::Local),
-            "s3" => Ok(StorageType::S3),
-            "http" | "https" => Ok(StorageType::Http),
-            _ => Err(format!(
-                "unknown storage type: {} (expected 'local', 's3', or 'http')",
-                s
-            )),
-        }
+        let name = s.to_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|storage| storage.names().contains(&name.as_str()))
+            .ok_or_else(|| {
+                let expected: Vec<_> = Self::ALL.iter().map(|t| format!("'{}'", t)).collect();
+                format!(
+                    "unknown storage type: {} (expected {})",
+                    s,
+                    expected.join(", ")
+                )
+            })
     }
 }
 
 impl std::fmt::Display for StorageType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            StorageType::Local => write!(f, "local"),
-            StorageType::S3 => write!(f, "s3"),
-            StorageType::Http => write!(f, "http"),
-        }
+        f.write_str(self.names()[0])
     }
 }
 
+/// Subcommands. Without a subcommand the server starts, as with `serve`.
+///
+/// Server options (`--data-dir`, `--storage`, ...) are global, so they apply
+/// to every subcommand.
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Start the htsget server (default)
+    Serve(ServeArgs),
+    /// Validate the data directory: indexes are present and readable, headers parse
+    Check(CheckArgs),
+    /// Generate missing index files (BAI, TBI, FAI) for a file or directory
+    Index(IndexArgs),
+    /// Measure ticket latency for a sample against the configured storage
+    Bench(BenchArgs),
+    /// Download a slice from an htsget server by following its ticket
+    Fetch(FetchArgs),
+    /// Run htsget spec conformance cases against a server (a local one by default)
+    Conformance(ConformanceArgs),
+    /// Write an example config file with every setting commented out
+    Init(InitArgs),
+    /// Generate shell completions
+    Completions(CompletionsArgs),
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct ServeArgs {
+    /// Print the effective configuration, with where each setting came
+    /// from and secrets redacted, and exit
+    #[arg(long)]
+    pub print_config: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct InitArgs {
+    /// Output file (defaults to stdout)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Overwrite the output file if it exists
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct CompletionsArgs {
+    /// Shell to generate completions for
+    #[arg(value_enum)]
+    pub shell: clap_complete::Shell,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct CheckArgs {
+    /// Directory to check (defaults to --data-dir)
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct IndexArgs {
+    /// Data file or directory to index
+    pub path: PathBuf,
+
+    /// Rebuild indexes that already exist
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct BenchArgs {
+    /// Sample ID to request tickets for
+    pub id: String,
+
+    /// Ticket endpoint: reads, variants, or sequences
+    #[arg(long, default_value = "reads")]
+    pub endpoint: String,
+
+    /// Region as `chr`, `chr:start-end` or `chr:start-` (1-based, inclusive)
+    #[arg(long)]
+    pub region: Option<String>,
+
+    /// Number of ticket requests to issue
+    #[arg(short = 'n', long, default_value = "100")]
+    pub iterations: usize,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct FetchArgs {
+    /// Base URL of the htsget server
+    #[arg(long)]
+    pub url: String,
+
+    /// Sample ID to fetch
+    #[arg(long)]
+    pub id: String,
+
+    /// Ticket endpoint: reads, variants, or sequences
+    #[arg(long, default_value = "reads")]
+    pub endpoint: String,
+
+    /// Region as `chr`, `chr:start-end` or `chr:start-` (1-based, inclusive)
+    #[arg(long)]
+    pub region: Option<String>,
+
+    /// Requested format (e.g., BAM, CRAM, VCF, BCF)
+    #[arg(long)]
+    pub format: Option<String>,
+
+    /// Fetch only the header
+    #[arg(long)]
+    pub header_only: bool,
+
+    /// Bearer token for the ticket request
+    #[arg(long, env = "HTSGET_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+
+    /// Retries for transient failures (connection errors, 5xx, 429)
+    #[arg(long, default_value = "3")]
+    pub retries: u32,
+
+    /// Output file (defaults to stdout)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ConformanceArgs {
+    /// Base URL of the server to check; defaults to a local server on the
+    /// configured storage, bound to an ephemeral port
+    #[arg(long)]
+    pub url: Option<String>,
+
+    /// Indexed BAM sample for reads cases
+    #[arg(long, default_value = "sample")]
+    pub reads_id: String,
+
+    /// Indexed CRAM sample for CRAM cases (skipped if unset)
+    #[arg(long)]
+    pub cram_id: Option<String>,
+
+    /// Indexed VCF sample for variants cases
+    #[arg(long, default_value = "sample")]
+    pub variants_id: String,
+
+    /// Reference sequence present in every sample
+    #[arg(long, default_value = "chr1")]
+    pub reference_name: String,
+
+    /// Bearer token for every request
+    #[arg(long, env = "HTSGET_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+}
+
 #[derive(Debug, Clone, Parser)]
 #[command(name = "htsgetr")]
 #[command(about = "htsget protocol server implementation")]
 pub struct Config {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// TOML file of settings keyed by option name (e.g. `data_dir =
+    /// "/data"`); flags and environment variables override it
+    #[arg(long, global = true, env = "HTSGET_CONFIG")]
+    pub config: Option<PathBuf>,
+
     /// Host address to bind to
-    #[arg(long, env = "HTSGET_HOST", default_value = "0.0.0.0")]
+    #[arg(long, global = true, env = "HTSGET_HOST", default_value = "0.0.0.0")]
     pub host: String,
 
     /// Port to listen on
-    #[arg(short, long, env = "HTSGET_PORT", default_value = "8080")]
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "HTSGET_PORT",
+        default_value = "8080"
+    )]
     pub port: u16,
 
+    /// Listen on this Unix domain socket instead of host and port (requires
+    /// `uds` feature)
+    #[arg(long, global = true, env = "HTSGET_UDS", conflicts_with = "tls_cert")]
+    pub uds: Option<PathBuf>,
+
     /// Base URL for ticket URLs (e.g., `https://example.com`)
-    #[arg(long, env = "HTSGET_BASE_URL")]
+    #[arg(long, global = true, env = "HTSGET_BASE_URL")]
     pub base_url: Option<String>,
 
+    /// Mount all routes under this prefix (e.g. `/htsget/v1`); ticket URLs
+    /// include it after the base URL
+    #[arg(long, global = true, env = "HTSGET_PATH_PREFIX")]
+    pub path_prefix: Option<String>,
+
     /// Directory containing data files
-    #[arg(long, env = "HTSGET_DATA_DIR", default_value = "./data")]
+    #[arg(long, global = true, env = "HTSGET_DATA_DIR", default_value = "./data")]
     pub data_dir: PathBuf,
 
+    /// Bgzip and tabix-index plain .vcf files into the cache directory on
+    /// first request (local storage), enabling header and region queries
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_CONVERT_VCF",
+        default_value = "false"
+    )]
+    pub convert_vcf: bool,
+
+    /// Extra data file extensions, as comma-separated `format:ext` pairs
+    /// (e.g. `fasta:fasta,fastq:fastq.gz,vcf:vcf.bgz`)
+    #[arg(long, global = true, env = "HTSGET_DATA_EXTENSIONS")]
+    pub data_extensions: Option<String>,
+
+    /// Extra index file extensions, as comma-separated `format:ext` pairs
+    /// (e.g. `bam:csi`)
+    #[arg(long, global = true, env = "HTSGET_INDEX_EXTENSIONS")]
+    pub index_extensions: Option<String>,
+
     /// Enable CORS for all origins
-    #[arg(long, env = "HTSGET_CORS", default_value = "true")]
+    #[arg(long, global = true, env = "HTSGET_CORS", default_value = "true")]
     pub cors: bool,
 
     /// Log level (trace, debug, info, warn, error)
-    #[arg(long, env = "RUST_LOG", default_value = "info")]
+    #[arg(long, global = true, env = "RUST_LOG", default_value = "info")]
     pub log_level: String,
 
+    /// Validate the data directory before serving and refuse to start on errors
+    #[arg(long, global = true, env = "HTSGET_PREFLIGHT", default_value = "false")]
+    pub preflight: bool,
+
+    /// Serve the /reads endpoint (BAM, CRAM)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_ENABLE_READS",
+        default_value = "true"
+    )]
+    pub enable_reads: bool,
+
+    /// Serve the /variants endpoint (VCF, BCF)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_ENABLE_VARIANTS",
+        default_value = "true"
+    )]
+    pub enable_variants: bool,
+
+    /// Serve the /sequences endpoint (FASTA, FASTQ)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_ENABLE_SEQUENCES",
+        default_value = "true"
+    )]
+    pub enable_sequences: bool,
+
+    /// Serve POST /tickets/refresh, which re-issues a ticket with fresh URLs
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_ENABLE_TICKET_REFRESH",
+        default_value = "false"
+    )]
+    pub enable_ticket_refresh: bool,
+
+    /// Serve a Swagger UI for /openapi.json at /swagger-ui/ (requires
+    /// `openapi` feature)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_ENABLE_SWAGGER_UI",
+        default_value = "false"
+    )]
+    pub enable_swagger_ui: bool,
+
+    /// Send tickets as plain `application/json` instead of the htsget media
+    /// type, for legacy clients that reject it
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_PLAIN_JSON_TICKETS",
+        default_value = "false"
+    )]
+    pub plain_json_tickets: bool,
+
+    /// Match requested reference names to the ones files declare ignoring
+    /// case and through the built-in GRCh37/GRCh38 aliases (`1`, `chr1`,
+    /// `NC_000001.11`; `MT`, `chrM`)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_REFERENCE_ALIASES",
+        default_value = "false"
+    )]
+    pub reference_aliases: bool,
+
+    /// File of extra reference name aliases, one set of equivalent names
+    /// per line separated by tabs or spaces (implies
+    /// `--reference-aliases`)
+    #[arg(long, global = true, env = "HTSGET_REFERENCE_ALIAS_FILE")]
+    pub reference_alias_file: Option<PathBuf>,
+
+    /// What to do with BAM, CRAM, VCF and BCF headers that don't parse:
+    /// "strict" rejects the request as an unsupported format, "lenient"
+    /// logs the offending lines and serves the file without them
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_HEADER_PARSING",
+        default_value = "strict"
+    )]
+    pub header_parsing: HeaderParsing,
+
+    /// PKCS#8 PEM file with an Ed25519 or P-256 private key to sign tickets
+    /// with as JWS; its public key is served at `/.well-known/jwks.json`
+    /// (requires `jws` feature)
+    #[arg(long, global = true, env = "HTSGET_TICKET_SIGNING_KEY")]
+    pub ticket_signing_key: Option<PathBuf>,
+
+    /// `kid` of the ticket signing key (defaults to its RFC 7638 thumbprint)
+    #[arg(long, global = true, env = "HTSGET_TICKET_SIGNING_KEY_ID")]
+    pub ticket_signing_key_id: Option<String>,
+
+    /// How signed tickets carry their signature: "detached" in an
+    /// `X-Htsget-Signature` header next to the JSON body, or "compact" as an
+    /// `application/jose` body
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_TICKET_SIGNATURE",
+        default_value = "detached"
+    )]
+    pub ticket_signature: TicketSignature,
+
+    /// Seconds index query results are reused for identical ticket requests
+    /// while the file is unchanged (0 disables the ticket cache)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_TICKET_CACHE_TTL",
+        default_value = "0"
+    )]
+    pub ticket_cache_ttl: u64,
+
+    /// Most index query results the ticket cache holds
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_TICKET_CACHE_SIZE",
+        default_value = "10000"
+    )]
+    pub ticket_cache_size: u64,
+
+    /// Most seconds a ticket says its URLs are valid for (`expiresAt`,
+    /// `expiresIn`), even when they don't expire; sooner-expiring presigned
+    /// or signed URLs still set it (0 for no limit)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_TICKET_URL_TTL",
+        default_value = "0"
+    )]
+    pub ticket_url_ttl: u64,
+
+    /// Most regions a ticket request may name; more are rejected with
+    /// InvalidInput
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_MAX_REGIONS",
+        default_value = "1000"
+    )]
+    pub max_regions: usize,
+
+    /// Most URLs per ticket; larger tickets are sent in pages linked by a
+    /// `next` URL (0 for no pages)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_TICKET_PAGE_SIZE",
+        default_value = "0"
+    )]
+    pub ticket_page_size: usize,
+
+    /// Format served by /reads when a request doesn't name one
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_READS_DEFAULT_FORMAT",
+        default_value = "BAM"
+    )]
+    pub reads_default_format: Format,
+
+    /// Format served by /variants when a request doesn't name one
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_VARIANTS_DEFAULT_FORMAT",
+        default_value = "VCF"
+    )]
+    pub variants_default_format: Format,
+
+    /// Format served by /sequences when a request doesn't name one
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_SEQUENCES_DEFAULT_FORMAT",
+        default_value = "FASTA"
+    )]
+    pub sequences_default_format: Format,
+
+    /// When a request doesn't name a format, serve whichever of the
+    /// endpoint's formats exists for the ID, trying the default first
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_DETECT_FORMAT",
+        default_value = "false"
+    )]
+    pub detect_format: bool,
+
+    /// Compress JSON tickets and text responses (gzip/br/zstd)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_COMPRESSION",
+        default_value = "true"
+    )]
+    pub compression: bool,
+
+    /// Cache-Control header for data responses (e.g. `public, max-age=3600`)
+    #[arg(long, global = true, env = "HTSGET_CACHE_CONTROL")]
+    pub cache_control: Option<String>,
+
+    /// Allowed CORS origins (comma-separated); replaces the permissive default
+    #[arg(long, global = true, env = "HTSGET_CORS_ORIGINS")]
+    pub cors_origins: Option<String>,
+
+    /// Allowed CORS methods (comma-separated, default `GET,HEAD,POST,OPTIONS`)
+    #[arg(long, global = true, env = "HTSGET_CORS_METHODS")]
+    pub cors_methods: Option<String>,
+
+    /// Allowed CORS request headers (comma-separated, default: as requested)
+    #[arg(long, global = true, env = "HTSGET_CORS_HEADERS")]
+    pub cors_headers: Option<String>,
+
+    /// Seconds browsers may cache CORS preflight responses
+    #[arg(long, global = true, env = "HTSGET_CORS_MAX_AGE")]
+    pub cors_max_age: Option<u64>,
+
+    /// Allow credentialed CORS requests (requires `--cors-origins`)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_CORS_ALLOW_CREDENTIALS",
+        default_value = "false"
+    )]
+    pub cors_allow_credentials: bool,
+
+    /// Proxies (IPs/CIDRs, comma-separated, or `*`) whose Forwarded/X-Forwarded-* headers
+    /// set the base URL of ticket URLs
+    #[arg(long, global = true, env = "HTSGET_TRUSTED_PROXIES")]
+    pub trusted_proxies: Option<String>,
+
+    // TLS options (requires `tls` feature)
+    /// TLS certificate chain (PEM); enables HTTPS
+    #[arg(long, global = true, env = "HTSGET_TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// TLS private key (PEM)
+    #[arg(long, global = true, env = "HTSGET_TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// CA bundle (PEM) for verifying client certificates; enables mTLS
+    #[arg(long, global = true, env = "HTSGET_TLS_CLIENT_CA")]
+    pub tls_client_ca: Option<PathBuf>,
+
+    /// Reject TLS connections that don't present a valid client certificate
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_TLS_REQUIRE_CLIENT_CERT",
+        default_value = "false"
+    )]
+    pub tls_require_client_cert: bool,
+
     /// Maximum payload size in bytes
-    #[arg(long, env = "HTSGET_MAX_PAYLOAD", default_value = "10485760")]
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_MAX_PAYLOAD",
+        default_value = "10485760"
+    )]
     pub max_payload: usize,
 
     /// Storage backend type: "local" or "s3"
-    #[arg(long, env = "HTSGET_STORAGE", default_value = "local")]
+    #[arg(long, global = true, env = "HTSGET_STORAGE", default_value = "local")]
     pub storage: StorageType,
 
     /// S3 bucket name (required when storage=s3)
-    #[arg(long, env = "HTSGET_S3_BUCKET")]
+    #[arg(long, global = true, env = "HTSGET_S3_BUCKET")]
     pub s3_bucket: Option<String>,
 
     /// S3 region (uses AWS_REGION/AWS_DEFAULT_REGION if not set)
-    #[arg(long, env = "HTSGET_S3_REGION")]
+    #[arg(long, global = true, env = "HTSGET_S3_REGION")]
     pub s3_region: Option<String>,
 
     /// S3 key prefix (e.g., "genomics/samples/")
-    #[arg(long, env = "HTSGET_S3_PREFIX", default_value = "")]
+    #[arg(long, global = true, env = "HTSGET_S3_PREFIX", default_value = "")]
     pub s3_prefix: String,
 
     /// S3 endpoint URL (for S3-compatible services like MinIO, LocalStack)
-    #[arg(long, env = "HTSGET_S3_ENDPOINT")]
+    #[arg(long, global = true, env = "HTSGET_S3_ENDPOINT")]
     pub s3_endpoint: Option<String>,
 
-    /// Local cache directory for index files (used with S3 storage)
-    #[arg(long, env = "HTSGET_CACHE_DIR", default_value = "/tmp/htsgetr-cache")]
+    /// Named AWS profile for S3 credentials (instead of AWS_PROFILE)
+    #[arg(long, global = true, env = "HTSGET_S3_PROFILE")]
+    pub s3_profile: Option<String>,
+
+    /// IAM role to assume for S3 access, e.g. for a bucket in another account
+    #[arg(long, global = true, env = "HTSGET_S3_ROLE_ARN")]
+    pub s3_role_arn: Option<String>,
+
+    /// Session name of the assumed S3 role
+    #[arg(long, global = true, env = "HTSGET_S3_ROLE_SESSION_NAME")]
+    pub s3_role_session_name: Option<String>,
+
+    /// External ID required by the S3 role's trust policy
+    #[arg(long, global = true, env = "HTSGET_S3_EXTERNAL_ID")]
+    pub s3_external_id: Option<String>,
+
+    /// OIDC token file to assume the S3 role with (AssumeRoleWithWebIdentity)
+    #[arg(long, global = true, env = "HTSGET_S3_WEB_IDENTITY_TOKEN_FILE")]
+    pub s3_web_identity_token_file: Option<PathBuf>,
+
+    /// TOML file of `[[route]]` tables serving ID prefixes from other buckets
+    #[arg(long, global = true, env = "HTSGET_S3_BUCKET_ROUTES")]
+    pub s3_bucket_routes: Option<PathBuf>,
+
+    /// Local cache directory for remote index files and converted VCFs
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_CACHE_DIR",
+        default_value = "/tmp/htsgetr-cache"
+    )]
     pub cache_dir: PathBuf,
 
+    /// TOML file of `[[route]]` tables giving the Crypt4GH private key the
+    /// IDs under each prefix are encrypted to, which are then served
+    /// decrypted (requires `crypt4gh` feature)
+    #[arg(long, global = true, env = "HTSGET_DECRYPTION_KEYS")]
+    pub decryption_keys: Option<PathBuf>,
+
+    /// Download and parse indexes of recently modified samples in the
+    /// background at startup, so first queries don't wait for them
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_PREFETCH_INDEXES",
+        default_value = "false"
+    )]
+    pub prefetch_indexes: bool,
+
+    /// Number of most recently modified samples whose indexes are prefetched
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_PREFETCH_LIMIT",
+        default_value = "100"
+    )]
+    pub prefetch_limit: usize,
+
+    /// Point S3 ticket URLs at this server's /data endpoint, which streams
+    /// from the bucket, instead of presigned URLs
+    #[arg(long, global = true, env = "HTSGET_S3_PROXY", default_value = "false")]
+    pub s3_proxy: bool,
+
+    /// The S3 bucket is public: read it without credentials and put plain,
+    /// unsigned object URLs in tickets
+    #[arg(long, global = true, env = "HTSGET_S3_PUBLIC", default_value = "false")]
+    pub s3_public: bool,
+
+    /// Data endpoint mode: "proxy" streams bytes, "redirect" sends clients to
+    /// presigned S3 URLs for whole-object requests
+    #[arg(long, global = true, env = "HTSGET_DATA_MODE", default_value = "proxy")]
+    pub data_mode: DataMode,
+
+    /// What header-class tickets point at: "range" for the header's bytes in
+    /// the file, "standalone" for a re-compressed copy (BAM, BCF, VCF.gz)
+    /// that ends on a block boundary
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_HEADER_MODE",
+        default_value = "range"
+    )]
+    pub header_mode: HeaderMode,
+
     /// Presigned URL expiration in seconds (used with S3 storage)
-    #[arg(long, env = "HTSGET_PRESIGNED_URL_EXPIRY", default_value = "3600")]
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_PRESIGNED_URL_EXPIRY",
+        default_value = "3600"
+    )]
     pub presigned_url_expiry: u64,
 
+    /// Seconds added to the presigned URL expiration for each data URL in a
+    /// ticket, so large tickets don't expire before slow clients finish them
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_PRESIGNED_URL_EXPIRY_PER_URL",
+        default_value = "0"
+    )]
+    pub presigned_url_expiry_per_url: u64,
+
+    /// Clock skew in seconds tolerated between this server and S3: presigned
+    /// URLs are dated this much early and stay valid this much longer
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_PRESIGN_CLOCK_SKEW",
+        default_value = "0"
+    )]
+    pub presign_clock_skew: u64,
+
     /// HTTP base URL for data files (required when storage=http)
-    #[arg(long, env = "HTSGET_HTTP_BASE_URL")]
+    #[arg(long, global = true, env = "HTSGET_HTTP_BASE_URL")]
     pub http_base_url: Option<String>,
 
     /// HTTP base URL for index files (optional, defaults to http_base_url)
-    #[arg(long, env = "HTSGET_HTTP_INDEX_BASE_URL")]
+    #[arg(long, global = true, env = "HTSGET_HTTP_INDEX_BASE_URL")]
     pub http_index_base_url: Option<String>,
 
-    // Authentication options (requires `auth` feature)
-    /// Enable authentication
-    #[arg(long, env = "HTSGET_AUTH_ENABLED", default_value = "false")]
-    pub auth_enabled: bool,
+    /// Upstream URL of a file, e.g. "{base}/{id}/{id}.{ext}" (defaults to
+    /// "{base}/{id}.{ext}")
+    #[arg(long, global = true, env = "HTSGET_HTTP_URL_TEMPLATE")]
+    pub http_url_template: Option<String>,
 
-    /// JWT issuer URL (e.g., `https://auth.example.com`)
-    #[arg(long, env = "HTSGET_AUTH_ISSUER")]
-    pub auth_issuer: Option<String>,
+    /// Redirects followed per request to the HTTP origin (0 follows none)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_HTTP_MAX_REDIRECTS",
+        default_value = "10"
+    )]
+    pub http_max_redirects: usize,
 
-    /// JWT audience claim to validate against
-    #[arg(long, env = "HTSGET_AUTH_AUDIENCE")]
-    pub auth_audience: Option<String>,
+    /// Headers sent on every request to the HTTP origin, e.g.
+    /// "Authorization: Bearer abc123,X-Api-Key: secret"
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_HTTP_HEADERS",
+        hide_env_values = true
+    )]
+    pub http_headers: Option<String>,
 
-    /// JWKS URL for fetching public keys (defaults to {issuer}/.well-known/jwks.json)
-    #[arg(long, env = "HTSGET_AUTH_JWKS_URL")]
-    pub auth_jwks_url: Option<String>,
+    /// Client request headers to forward to the HTTP origin (comma-separated names)
+    #[arg(long, global = true, env = "HTSGET_HTTP_FORWARD_HEADERS")]
+    pub http_forward_headers: Option<String>,
 
-    /// Static RSA/EC public key in PEM format (alternative to JWKS)
-    #[arg(long, env = "HTSGET_AUTH_PUBLIC_KEY")]
-    pub auth_public_key: Option<String>,
+    /// Of the HTTP headers, those clients need too, put in ticket URL entries
+    #[arg(long, global = true, env = "HTSGET_HTTP_TICKET_HEADERS")]
+    pub http_ticket_headers: Option<String>,
 
-    /// Endpoints that don't require auth (comma-separated paths)
+    /// Attempts per S3/HTTP storage request, including the first (1 disables retries)
     #[arg(
         long,
-        env = "HTSGET_AUTH_PUBLIC_ENDPOINTS",
-        default_value = "/,/service-info"
+        global = true,
+        env = "HTSGET_RETRY_MAX_ATTEMPTS",
+        default_value = "3"
     )]
-    pub auth_public_endpoints: String,
+    pub retry_max_attempts: u32,
 
-    /// Secret key for signing data URLs (generated if not provided)
-    #[arg(long, env = "HTSGET_DATA_URL_SECRET")]
-    pub data_url_secret: Option<String>,
+    /// Delay before the first storage retry in milliseconds; doubles per retry
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_RETRY_INITIAL_BACKOFF_MS",
+        default_value = "100"
+    )]
+    pub retry_initial_backoff_ms: u64,
 
-    /// Data URL signature expiry in seconds
-    #[arg(long, env = "HTSGET_DATA_URL_EXPIRY", default_value = "3600")]
-    pub data_url_expiry: u64,
-}
+    /// Upper bound on a single storage retry delay in milliseconds
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_RETRY_MAX_BACKOFF_MS",
+        default_value = "5000"
+    )]
+    pub retry_max_backoff_ms: u64,
 
-impl Config {
-    /// Returns the effective base URL for ticket responses.
-    ///
-    /// If `base_url` is set, returns that value. Otherwise, constructs
-    /// a URL from the host and port (e.g., `http://0.0.0.0:8080`).
-    pub fn effective_base_url(&self) -> String {
-        self.base_url
-            .clone()
-            .unwrap_or_else(|| format!("http://{}:{}", self.host, self.port))
-    }
-}
+    /// Failures to retry (comma-separated: connect, timeout, throttled, server);
+    /// defaults to all
+    #[arg(long, global = true, env = "HTSGET_RETRY_ON")]
+    pub retry_on: Option<String>,
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// MiB per ranged request when downloading remote index files (0 downloads
+    /// each index in a single request)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_INDEX_CHUNK_SIZE_MB",
+        default_value = "8"
+    )]
+    pub index_chunk_size_mb: u64,
 
-    fn make_test_config() -> Config {
-        Config {
-            host: "0.0.0.0".to_string(),
-            port: 8080,
+    /// Ranged requests in flight per remote index download
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_INDEX_FETCH_CONCURRENCY",
+        default_value = "8"
+    )]
+    pub index_fetch_concurrency: usize,
+
+    /// Seconds to wait for each S3/HTTP storage request before retrying it
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_STORAGE_TIMEOUT",
+        default_value = "30"
+    )]
+    pub storage_timeout: u64,
+
+    /// Seconds a request may take before it fails with a Timeout error (0 disables)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_REQUEST_TIMEOUT",
+        default_value = "60"
+    )]
+    pub request_timeout: u64,
+
+    /// Add `retryable`, `detail` and `requestId` to error response bodies
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_ERROR_DETAIL",
+        default_value = "false"
+    )]
+    pub error_detail: bool,
+
+    /// Send internal error messages (storage paths, bucket names, backend
+    /// errors) to clients instead of a generic one; for debugging
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_EXPOSE_INTERNAL_ERRORS",
+        default_value = "false"
+    )]
+    pub expose_internal_errors: bool,
+
+    /// Audit log of ticket and data access: `log`, `file:<path>`, or a
+    /// webhook URL events are POSTed to
+    #[arg(long, global = true, env = "HTSGET_AUDIT_LOG")]
+    pub audit_log: Option<String>,
+
+    /// Running as one of several replicas behind a load balancer: startup
+    /// fails if auth is enabled without a shared data URL secret. Implied by
+    /// `--redis-url`
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_MULTI_REPLICA",
+        default_value = "false"
+    )]
+    pub multi_replica: bool,
+
+    /// Redis server whose caches replicas share: S3 object metadata and JWKS
+    /// (requires `redis` feature)
+    #[arg(long, global = true, env = "HTSGET_REDIS_URL")]
+    pub redis_url: Option<String>,
+
+    /// Data requests each authenticated subject may make per quota window
+    #[arg(long, global = true, env = "HTSGET_QUOTA_REQUESTS")]
+    pub quota_requests: Option<u64>,
+
+    /// Bytes each authenticated subject may fetch from the data endpoint per
+    /// quota window
+    #[arg(long, global = true, env = "HTSGET_QUOTA_BYTES")]
+    pub quota_bytes: Option<u64>,
+
+    /// Quota window in seconds
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_QUOTA_WINDOW",
+        default_value = "86400"
+    )]
+    pub quota_window: u64,
+
+    /// Where quota usage is counted: `memory`, or a `redis://` URL shared by
+    /// all replicas (requires `redis` feature)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_QUOTA_STORE",
+        default_value = "memory"
+    )]
+    pub quota_store: String,
+
+    /// Count the requests and bytes served per dataset and per principal,
+    /// served at `/metrics` (Prometheus) and `/admin/usage` (JSON)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_USAGE_REPORT",
+        default_value = "false"
+    )]
+    pub usage_report: bool,
+
+    /// Bearer token `/metrics` and `/admin/usage` require instead of the
+    /// server's authentication; also enables `/admin/config`, which
+    /// requires it
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_ADMIN_TOKEN",
+        hide_env_values = true
+    )]
+    pub admin_token: Option<String>,
+
+    /// Directory of export jobs; serves `POST /jobs`, which assembles a
+    /// ticket's data on the server (off when unset)
+    #[arg(long, global = true, env = "HTSGET_JOBS_DIR")]
+    pub jobs_dir: Option<PathBuf>,
+
+    /// Export jobs run at once; the rest wait their turn
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_MAX_RUNNING_JOBS",
+        default_value = "2"
+    )]
+    pub max_running_jobs: usize,
+
+    /// Seconds finished export jobs and their results are kept before
+    /// they're removed (0 keeps them forever)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_JOBS_RETENTION",
+        default_value = "604800"
+    )]
+    pub jobs_retention: u64,
+
+    /// S3 bucket export job results are uploaded to, rather than served
+    /// from the jobs directory (requires `s3` feature)
+    #[arg(long, global = true, env = "HTSGET_JOBS_S3_BUCKET")]
+    pub jobs_s3_bucket: Option<String>,
+
+    /// Key prefix of uploaded export job results
+    #[arg(long, global = true, env = "HTSGET_JOBS_S3_PREFIX", default_value = "")]
+    pub jobs_s3_prefix: String,
+
+    // Authentication options (requires `auth` feature)
+    /// Enable authentication
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_AUTH_ENABLED",
+        default_value = "false"
+    )]
+    pub auth_enabled: bool,
+
+    /// JWT issuer URL (e.g., `https://auth.example.com`)
+    #[arg(long, global = true, env = "HTSGET_AUTH_ISSUER")]
+    pub auth_issuer: Option<String>,
+
+    /// JWT audience claim to validate against
+    #[arg(long, global = true, env = "HTSGET_AUTH_AUDIENCE")]
+    pub auth_audience: Option<String>,
+
+    /// JWKS URL for fetching public keys (defaults to {issuer}/.well-known/jwks.json)
+    #[arg(long, global = true, env = "HTSGET_AUTH_JWKS_URL")]
+    pub auth_jwks_url: Option<String>,
+
+    /// Static RSA/EC public key in PEM format (alternative to JWKS)
+    #[arg(long, global = true, env = "HTSGET_AUTH_PUBLIC_KEY")]
+    pub auth_public_key: Option<String>,
+
+    /// Shared secret for validating HMAC-signed (HS256) JWTs
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_AUTH_HMAC_SECRET",
+        hide_env_values = true
+    )]
+    pub auth_hmac_secret: Option<String>,
+
+    /// Accepted JWT algorithms (comma-separated, e.g. `RS256,ES256,HS256`)
+    #[arg(long, global = true, env = "HTSGET_AUTH_ALGORITHMS")]
+    pub auth_algorithms: Option<String>,
+
+    /// Endpoints that don't require auth (comma-separated paths)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_AUTH_PUBLIC_ENDPOINTS",
+        default_value = "/,/service-info,/reads/service-info,/variants/service-info,/sequences/service-info,/ui,/openapi.json,/swagger-ui,/.well-known/jwks.json"
+    )]
+    pub auth_public_endpoints: String,
+
+    /// RFC 7662 token introspection endpoint (enables opaque tokens)
+    #[arg(long, global = true, env = "HTSGET_AUTH_INTROSPECTION_URL")]
+    pub auth_introspection_url: Option<String>,
+
+    /// Client ID used to authenticate to the introspection endpoint
+    #[arg(long, global = true, env = "HTSGET_AUTH_CLIENT_ID")]
+    pub auth_client_id: Option<String>,
+
+    /// Client secret used to authenticate to the introspection endpoint
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_AUTH_CLIENT_SECRET",
+        hide_env_values = true
+    )]
+    pub auth_client_secret: Option<String>,
+
+    /// Seconds to cache introspection results
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_AUTH_INTROSPECTION_CACHE_TTL",
+        default_value = "60"
+    )]
+    pub auth_introspection_cache_ttl: u64,
+
+    /// Authorization policy file (TOML) mapping token scopes/claims to dataset IDs
+    #[arg(long, global = true, env = "HTSGET_AUTH_POLICY")]
+    pub auth_policy: Option<PathBuf>,
+
+    /// Secret key for signing data URLs (generated if not provided)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_DATA_URL_SECRET",
+        hide_env_values = true
+    )]
+    pub data_url_secret: Option<String>,
+
+    /// File containing the data URL signing secret (alternative to `--data-url-secret`)
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_DATA_URL_SECRET_FILE",
+        conflicts_with = "data_url_secret"
+    )]
+    pub data_url_secret_file: Option<PathBuf>,
+
+    /// ID of the data URL secret, added to signed URLs so replicas and
+    /// rotations pick the right key
+    #[arg(long, global = true, env = "HTSGET_DATA_URL_KEY_ID")]
+    pub data_url_key_id: Option<String>,
+
+    /// Previous data URL secret, accepted during the rotation grace window
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_DATA_URL_PREVIOUS_SECRET",
+        hide_env_values = true
+    )]
+    pub data_url_previous_secret: Option<String>,
+
+    /// ID of the previous data URL secret
+    #[arg(long, global = true, env = "HTSGET_DATA_URL_PREVIOUS_KEY_ID")]
+    pub data_url_previous_key_id: Option<String>,
+
+    /// Seconds after startup that signatures from the previous secret are accepted
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_DATA_URL_ROTATION_GRACE",
+        default_value = "3600"
+    )]
+    pub data_url_rotation_grace: u64,
+
+    /// Data URL signature expiry in seconds
+    #[arg(
+        long,
+        global = true,
+        env = "HTSGET_DATA_URL_EXPIRY",
+        default_value = "3600"
+    )]
+    pub data_url_expiry: u64,
+}
+
+/// A configuration problem found by [`Config::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// Environment variable of the offending option (e.g. `HTSGET_S3_BUCKET`)
+    pub option: &'static str,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn new(option: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            option,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.option, self.message)
+    }
+}
+
+/// Every problem [`Config::validate`] found, reported together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl ConfigErrors {
+    /// Whether an error names `option`
+    pub fn contains(&self, option: &str) -> bool {
+        self.0.iter().any(|error| error.option == option)
+    }
+}
+
+impl std::fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid configuration ({} problem(s)):", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// Settings read from a `--config` TOML file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigFile {
+    /// Option name (e.g. `data_dir`) to value, as its flag would take it
+    pub settings: BTreeMap<String, String>,
+}
+
+impl ConfigFile {
+    /// Parse a config file. Keys are option names, with `_` or `-`; lists
+    /// are joined with commas, as comma-separated options take them.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let table: toml::Table = s.parse().map_err(|e| format!("invalid TOML: {}", e))?;
+        let command = Config::command();
+
+        let mut settings = BTreeMap::new();
+        for (key, value) in table {
+            let id = key.replace('-', "_");
+            if id == "config"
+                || !command
+                    .get_arguments()
+                    .any(|arg| arg.get_id() == id.as_str())
+            {
+                return Err(format!("unknown setting: {}", key));
+            }
+            let value = match value {
+                toml::Value::Array(values) => values
+                    .into_iter()
+                    .map(|value| scalar(&key, value))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join(","),
+                value => scalar(&key, value)?,
+            };
+            settings.insert(id, value);
+        }
+        Ok(Self { settings })
+    }
+
+    /// Read and parse the config file at `path`
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        Self::parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The file `args` and the environment name with `--config`, if any
+    pub fn from_args(args: &[OsString]) -> Result<Self, clap::Error> {
+        // Parse leniently to find --config; the full parse reports errors
+        let path = Config::command()
+            .ignore_errors(true)
+            .try_get_matches_from(args)
+            .ok()
+            .and_then(|matches| matches.get_one::<PathBuf>("config").cloned());
+        match path {
+            Some(path) => Self::read(&path).map_err(|e| {
+                Config::command().error(ErrorKind::InvalidValue, format!("--config: {}", e))
+            }),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// `command` with these settings as its defaults
+    pub fn apply(&self, command: clap::Command) -> clap::Command {
+        self.settings.iter().fold(command, |command, (id, value)| {
+            command.mut_arg(id, |arg| arg.default_value(value.clone()))
+        })
+    }
+
+    /// An example config file: every setting with its description,
+    /// commented out at its default
+    pub fn example() -> String {
+        let mut out = String::from(
+            "# htsgetr configuration\n\
+             #\n\
+             # Use with `htsgetr --config <file>` or HTSGET_CONFIG. Every setting is\n\
+             # optional; flags and HTSGET_* environment variables override this file.\n",
+        );
+        for arg in Config::command().get_arguments() {
+            let id = arg.get_id().as_str();
+            if matches!(id, "help" | "version" | "config") {
+                continue;
+            }
+
+            out.push('\n');
+            let help = arg.get_long_help().or(arg.get_help());
+            for line in wrap(&help.map(ToString::to_string).unwrap_or_default(), 76) {
+                if line.is_empty() {
+                    out.push_str("#\n");
+                } else {
+                    out.push_str(&format!("# {}\n", line));
+                }
+            }
+
+            let type_id = arg.get_value_parser().type_id();
+            let numeric = [
+                TypeId::of::<u16>(),
+                TypeId::of::<u32>(),
+                TypeId::of::<u64>(),
+                TypeId::of::<usize>(),
+            ]
+            .into_iter()
+            .any(|id| type_id == id);
+            let boolean = type_id == TypeId::of::<bool>();
+            let default = arg
+                .get_default_values()
+                .first()
+                .map(|value| value.to_string_lossy().into_owned());
+            let value = match (default, boolean || numeric) {
+                (Some(value), true) => value,
+                (Some(value), false) => toml::Value::String(value).to_string(),
+                (None, _) if boolean => "false".to_string(),
+                (None, _) if numeric => "0".to_string(),
+                (None, _) => "\"\"".to_string(),
+            };
+            out.push_str(&format!("# {} = {}\n", id, value));
+        }
+        out
+    }
+}
+
+/// A config file value as a flag would take it
+fn scalar(key: &str, value: toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s),
+        toml::Value::Integer(n) => Ok(n.to_string()),
+        toml::Value::Float(n) => Ok(n.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        _ => Err(format!(
+            "{}: expected a string, number, boolean or list",
+            key
+        )),
+    }
+}
+
+/// `text` broken into lines of at most `width` characters where it can be
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.len() + 1 + word.len() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+impl Config {
+    /// Parse the command line and environment over the `--config` file, if
+    /// any, exiting with usage on error as [`Parser::parse`] does.
+    pub fn load() -> Self {
+        Self::try_load_from(std::env::args_os()).unwrap_or_else(|e| e.exit())
+    }
+
+    /// Parse `args` (the program name first) and the environment over the
+    /// `--config` file they name, if any
+    pub fn try_load_from<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let (matches, _) = Self::matches_from(args)?;
+        Self::from_arg_matches(&matches)
+    }
+
+    /// Matches of `args` and the environment over the `--config` file they
+    /// name, and that file's settings
+    pub fn matches_from<I, T>(args: I) -> Result<(ArgMatches, ConfigFile), clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let file = ConfigFile::from_args(&args)?;
+        let matches = file.apply(Self::command()).try_get_matches_from(&args)?;
+        Ok((matches, file))
+    }
+
+    /// Explicit CORS policy, if any CORS option beyond `--cors` is set.
+    pub fn cors_options(&self) -> Option<CorsOptions> {
+        if self.cors_origins.is_none()
+            && self.cors_methods.is_none()
+            && self.cors_headers.is_none()
+            && self.cors_max_age.is_none()
+            && !self.cors_allow_credentials
+        {
+            return None;
+        }
+
+        let list = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|s| {
+                    s.split(',')
+                        .map(str::trim)
+                        .filter(|s| !s.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        Some(CorsOptions {
+            origins: list(&self.cors_origins),
+            methods: list(&self.cors_methods),
+            headers: list(&self.cors_headers),
+            max_age: self.cors_max_age,
+            allow_credentials: self.cors_allow_credentials,
+        })
+    }
+
+    /// Endpoints enabled for this deployment.
+    pub fn endpoints(&self) -> Endpoints {
+        Endpoints {
+            reads: self.enable_reads,
+            variants: self.enable_variants,
+            sequences: self.enable_sequences,
+            refresh: self.enable_ticket_refresh,
+            swagger_ui: self.enable_swagger_ui,
+        }
+    }
+
+    /// Formats ticket endpoints serve when a request doesn't name one.
+    /// Fails if a default belongs to another endpoint's datatype.
+    pub fn default_formats(&self) -> crate::Result<DefaultFormats> {
+        for (_, format, valid, endpoint) in self.default_format_checks() {
+            if !valid(&format) {
+                return Err(crate::Error::InvalidInput(format!(
+                    "{:?} is not a {} format",
+                    format, endpoint
+                )));
+            }
+        }
+
+        Ok(DefaultFormats {
+            reads: self.reads_default_format,
+            variants: self.variants_default_format,
+            sequences: self.sequences_default_format,
+            detect: self.detect_format,
+        })
+    }
+
+    /// Each endpoint's default format, with its option and datatype check.
+    fn default_format_checks(
+        &self,
+    ) -> [(&'static str, Format, fn(&Format) -> bool, &'static str); 3] {
+        [
+            (
+                "HTSGET_READS_DEFAULT_FORMAT",
+                self.reads_default_format,
+                Format::is_reads,
+                "reads",
+            ),
+            (
+                "HTSGET_VARIANTS_DEFAULT_FORMAT",
+                self.variants_default_format,
+                Format::is_variants,
+                "variants",
+            ),
+            (
+                "HTSGET_SEQUENCES_DEFAULT_FORMAT",
+                self.sequences_default_format,
+                Format::is_sequences,
+                "sequences",
+            ),
+        ]
+    }
+
+    /// Audit log sink, if one is configured.
+    pub fn audit_sink(&self) -> crate::Result<Option<AuditSink>> {
+        self.audit_log.as_deref().map(str::parse).transpose()
+    }
+
+    /// Per-subject quota limits, if any are configured.
+    pub fn quota_limits(&self) -> Option<QuotaLimits> {
+        (self.quota_requests.is_some() || self.quota_bytes.is_some()).then(|| QuotaLimits {
+            requests: self.quota_requests,
+            bytes: self.quota_bytes,
+            window: Duration::from_secs(self.quota_window.max(1)),
+        })
+    }
+
+    /// Whether this server is one of several replicas.
+    pub fn is_multi_replica(&self) -> bool {
+        self.multi_replica || self.redis_url.is_some()
+    }
+
+    /// Ticket signer, if a signing key is configured.
+    #[cfg(feature = "jws")]
+    pub fn ticket_signer(&self) -> crate::Result<Option<TicketSigner>> {
+        self.ticket_signing_key
+            .as_deref()
+            .map(|path| {
+                Ok(
+                    TicketSigner::from_file(path, self.ticket_signing_key_id.clone())?
+                        .with_signature(self.ticket_signature),
+                )
+            })
+            .transpose()
+    }
+
+    /// Where quota usage is counted.
+    pub fn quota_backend(&self) -> crate::Result<QuotaBackend> {
+        self.quota_store.parse()
+    }
+
+    /// Retry policy for remote storage backends.
+    pub fn retry_policy(&self) -> crate::Result<RetryPolicy> {
+        let mut policy = RetryPolicy::default();
+        policy.max_attempts = self.retry_max_attempts.max(1);
+        policy.initial_backoff = Duration::from_millis(self.retry_initial_backoff_ms);
+        policy.max_backoff = Duration::from_millis(self.retry_max_backoff_ms);
+        if let Some(classes) = &self.retry_on {
+            policy.retry_on = classes
+                .split(',')
+                .filter(|s| !s.trim().is_empty())
+                .map(str::parse)
+                .collect::<crate::Result<_>>()?;
+        }
+        Ok(policy)
+    }
+
+    /// Data and index file extensions for the storage backends.
+    pub fn naming(&self) -> crate::Result<Naming> {
+        let mut naming = Naming::default();
+        if let Some(spec) = &self.data_extensions {
+            naming = naming.with_data_extensions(spec)?;
+        }
+        if let Some(spec) = &self.index_extensions {
+            naming = naming.with_index_extensions(spec)?;
+        }
+        Ok(naming)
+    }
+
+    /// Headers HTTP storage sends on every request to the origin.
+    #[cfg(feature = "http")]
+    pub fn upstream_headers(&self) -> crate::Result<HeaderMap> {
+        match &self.http_headers {
+            Some(spec) => parse_headers(spec),
+            None => Ok(HeaderMap::new()),
+        }
+    }
+
+    /// Client request headers HTTP storage forwards to the origin.
+    #[cfg(feature = "http")]
+    pub fn forwarded_headers(&self) -> crate::Result<Vec<HeaderName>> {
+        parse_header_names(self.http_forward_headers.as_deref().unwrap_or_default())
+    }
+
+    /// Upstream headers HTTP storage puts in tickets; each must be one of
+    /// [`upstream_headers`](Self::upstream_headers).
+    #[cfg(feature = "http")]
+    pub fn ticket_headers(&self) -> crate::Result<Vec<HeaderName>> {
+        let names = parse_header_names(self.http_ticket_headers.as_deref().unwrap_or_default())?;
+        let headers = self.upstream_headers()?;
+        match names.iter().find(|name| !headers.contains_key(*name)) {
+            Some(name) => Err(crate::Error::InvalidInput(format!(
+                "{} is not in HTSGET_HTTP_HEADERS",
+                name
+            ))),
+            None => Ok(names),
+        }
+    }
+
+    /// Routes from ID prefixes to other S3 buckets, if configured.
+    #[cfg(feature = "s3")]
+    pub fn bucket_routes(&self) -> crate::Result<Vec<BucketRoute>> {
+        match &self.s3_bucket_routes {
+            Some(path) => BucketRoute::from_file(path),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Routes from ID prefixes to the private keys their objects are
+    /// encrypted to, if configured.
+    #[cfg(feature = "crypt4gh")]
+    pub fn decryption_routes(&self) -> crate::Result<Vec<DecryptionRoute>> {
+        let routes = match &self.decryption_keys {
+            Some(path) => DecryptionRoute::from_file(path)?,
+            None => return Ok(Vec::new()),
+        };
+        for route in &routes {
+            route.key()?;
+        }
+        Ok(routes)
+    }
+
+    /// Reference name aliases, if enabled: the built-in ones plus those in
+    /// the alias file.
+    pub fn reference_alias_table(&self) -> crate::Result<Option<ReferenceAliases>> {
+        if !self.reference_aliases && self.reference_alias_file.is_none() {
+            return Ok(None);
+        }
+        let aliases = ReferenceAliases::builtin();
+        match &self.reference_alias_file {
+            Some(path) => aliases.with_file(path).map(Some),
+            None => Ok(Some(aliases)),
+        }
+    }
+
+    /// How remote storage backends download index files.
+    pub fn index_download(&self) -> ChunkedDownload {
+        ChunkedDownload {
+            chunk_size: self.index_chunk_size_mb * 1024 * 1024,
+            concurrency: self.index_fetch_concurrency.max(1),
+        }
+    }
+
+    /// Check the options against each other and the environment, reporting
+    /// every problem at once rather than the first one startup runs into.
+    ///
+    /// Checks that the storage backend is built in and has its location, that
+    /// URLs parse, that auth has a key source, that TLS has both halves, that
+    /// the cache directory is writable when a backend uses it, and that the
+    /// options parsed by other accessors (default formats, extensions, retry
+    /// classes, audit sink, quota store) are valid.
+    pub fn validate(&self) -> Result<(), ConfigErrors> {
+        let mut errors = Vec::new();
+        let mut check = |option, result: crate::Result<()>| {
+            if let Err(e) = result {
+                errors.push(ConfigError::new(option, e.to_string()));
+            }
+        };
+        if let Some(spec) = &self.data_extensions {
+            check(
+                "HTSGET_DATA_EXTENSIONS",
+                Naming::default().with_data_extensions(spec).map(drop),
+            );
+        }
+        if let Some(spec) = &self.index_extensions {
+            check(
+                "HTSGET_INDEX_EXTENSIONS",
+                Naming::default().with_index_extensions(spec).map(drop),
+            );
+        }
+        check("HTSGET_RETRY_ON", self.retry_policy().map(drop));
+        check("HTSGET_AUDIT_LOG", self.audit_sink().map(drop));
+        check("HTSGET_QUOTA_STORE", self.quota_backend().map(drop));
+        check(
+            "HTSGET_REFERENCE_ALIAS_FILE",
+            self.reference_alias_table().map(drop),
+        );
+        #[cfg(feature = "jws")]
+        check("HTSGET_TICKET_SIGNING_KEY", self.ticket_signer().map(drop));
+        #[cfg(feature = "s3")]
+        check("HTSGET_S3_BUCKET_ROUTES", self.bucket_routes().map(drop));
+        #[cfg(feature = "crypt4gh")]
+        check("HTSGET_DECRYPTION_KEYS", self.decryption_routes().map(drop));
+        #[cfg(feature = "http")]
+        {
+            check("HTSGET_HTTP_HEADERS", self.upstream_headers().map(drop));
+            check(
+                "HTSGET_HTTP_FORWARD_HEADERS",
+                self.forwarded_headers().map(drop),
+            );
+            if self.upstream_headers().is_ok() {
+                check(
+                    "HTSGET_HTTP_TICKET_HEADERS",
+                    self.ticket_headers().map(drop),
+                );
+            }
+        }
+
+        for (option, format, valid, endpoint) in self.default_format_checks() {
+            if !valid(&format) {
+                errors.push(ConfigError::new(
+                    option,
+                    format!("{:?} is not a {} format", format, endpoint),
+                ));
+            }
+        }
+
+        if let Some(feature) = self
+            .storage
+            .feature()
+            .filter(|_| !self.storage.is_available())
+        {
+            errors.push(ConfigError::new(
+                "HTSGET_STORAGE",
+                format!(
+                    "{} storage requires the '{}' feature",
+                    self.storage, feature
+                ),
+            ));
+        }
+        match self.storage {
+            StorageType::Local if !self.data_dir.is_dir() => errors.push(ConfigError::new(
+                "HTSGET_DATA_DIR",
+                format!("{} is not a directory", self.data_dir.display()),
+            )),
+            StorageType::S3 if self.s3_bucket.as_deref().is_none_or(str::is_empty) => errors.push(
+                ConfigError::new("HTSGET_S3_BUCKET", "required for S3 storage"),
+            ),
+            StorageType::Http if self.http_base_url.is_none() => errors.push(ConfigError::new(
+                "HTSGET_HTTP_BASE_URL",
+                "required for HTTP storage",
+            )),
+            _ => {}
+        }
+
+        if self.s3_role_arn.is_none() {
+            let role_options = [
+                (
+                    "HTSGET_S3_ROLE_SESSION_NAME",
+                    self.s3_role_session_name.is_some(),
+                ),
+                ("HTSGET_S3_EXTERNAL_ID", self.s3_external_id.is_some()),
+                (
+                    "HTSGET_S3_WEB_IDENTITY_TOKEN_FILE",
+                    self.s3_web_identity_token_file.is_some(),
+                ),
+            ];
+            for (option, _) in role_options.into_iter().filter(|(_, set)| *set) {
+                errors.push(ConfigError::new(option, "requires HTSGET_S3_ROLE_ARN"));
+            }
+        }
+        if let Some(template) = &self.http_url_template {
+            for placeholder in ["{id}", "{ext}"] {
+                if !template.contains(placeholder) {
+                    errors.push(ConfigError::new(
+                        "HTSGET_HTTP_URL_TEMPLATE",
+                        format!("{} has no {} placeholder", template, placeholder),
+                    ));
+                }
+            }
+        }
+
+        if self.s3_public {
+            let credentials = [
+                ("HTSGET_S3_PROFILE", self.s3_profile.is_some()),
+                ("HTSGET_S3_ROLE_ARN", self.s3_role_arn.is_some()),
+            ];
+            for (option, _) in credentials.into_iter().filter(|(_, set)| *set) {
+                errors.push(ConfigError::new(option, "not used with HTSGET_S3_PUBLIC"));
+            }
+        }
+        if self.s3_external_id.is_some() && self.s3_web_identity_token_file.is_some() {
+            errors.push(ConfigError::new(
+                "HTSGET_S3_EXTERNAL_ID",
+                "not used with HTSGET_S3_WEB_IDENTITY_TOKEN_FILE",
+            ));
+        }
+        if let Some(path) = self
+            .s3_web_identity_token_file
+            .as_ref()
+            .filter(|path| !path.is_file())
+        {
+            errors.push(ConfigError::new(
+                "HTSGET_S3_WEB_IDENTITY_TOKEN_FILE",
+                format!("{} is not a file", path.display()),
+            ));
+        }
+
+        let urls = [
+            ("HTSGET_BASE_URL", &self.base_url),
+            ("HTSGET_HTTP_BASE_URL", &self.http_base_url),
+            ("HTSGET_HTTP_INDEX_BASE_URL", &self.http_index_base_url),
+            ("HTSGET_S3_ENDPOINT", &self.s3_endpoint),
+            ("HTSGET_AUTH_JWKS_URL", &self.auth_jwks_url),
+            (
+                "HTSGET_AUTH_INTROSPECTION_URL",
+                &self.auth_introspection_url,
+            ),
+        ];
+        for (option, url) in urls {
+            if let Some(message) = url.as_deref().and_then(invalid_url) {
+                errors.push(ConfigError::new(option, message));
+            }
+        }
+
+        if self.storage != StorageType::Local || self.convert_vcf {
+            if let Err(e) = check_writable(&self.cache_dir) {
+                errors.push(ConfigError::new(
+                    "HTSGET_CACHE_DIR",
+                    format!("{} is not writable: {}", self.cache_dir.display(), e),
+                ));
+            }
+        }
+
+        if self.auth_enabled {
+            if !cfg!(feature = "auth") {
+                errors.push(ConfigError::new(
+                    "HTSGET_AUTH_ENABLED",
+                    "auth requires the 'auth' feature",
+                ));
+            }
+            let key_sources = [
+                &self.auth_issuer,
+                &self.auth_jwks_url,
+                &self.auth_public_key,
+                &self.auth_hmac_secret,
+                &self.auth_introspection_url,
+            ];
+            if key_sources.iter().all(|source| source.is_none()) {
+                errors.push(ConfigError::new(
+                    "HTSGET_AUTH_ENABLED",
+                    "no key source; set HTSGET_AUTH_ISSUER, HTSGET_AUTH_JWKS_URL, \
+                     HTSGET_AUTH_PUBLIC_KEY, HTSGET_AUTH_HMAC_SECRET or \
+                     HTSGET_AUTH_INTROSPECTION_URL",
+                ));
+            }
+            if self.is_multi_replica()
+                && self.data_url_secret.is_none()
+                && self.data_url_secret_file.is_none()
+            {
+                errors.push(ConfigError::new(
+                    "HTSGET_DATA_URL_SECRET",
+                    "required with auth on multiple replicas, so every replica accepts the \
+                     others' signed data URLs",
+                ));
+            }
+        }
+
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(_), None) => errors.push(ConfigError::new(
+                "HTSGET_TLS_KEY",
+                "required with HTSGET_TLS_CERT",
+            )),
+            (None, Some(_)) => errors.push(ConfigError::new(
+                "HTSGET_TLS_CERT",
+                "required with HTSGET_TLS_KEY",
+            )),
+            (Some(_), Some(_)) if !cfg!(feature = "tls") => errors.push(ConfigError::new(
+                "HTSGET_TLS_CERT",
+                "TLS requires the 'tls' feature",
+            )),
+            _ => {}
+        }
+
+        if self.ticket_signing_key.is_some() && !cfg!(feature = "jws") {
+            errors.push(ConfigError::new(
+                "HTSGET_TICKET_SIGNING_KEY",
+                "requires the 'jws' feature",
+            ));
+        }
+
+        if self.decryption_keys.is_some() && !cfg!(feature = "crypt4gh") {
+            errors.push(ConfigError::new(
+                "HTSGET_DECRYPTION_KEYS",
+                "requires the 'crypt4gh' feature",
+            ));
+        }
+
+        if self.redis_url.is_some() && !cfg!(feature = "redis") {
+            errors.push(ConfigError::new(
+                "HTSGET_REDIS_URL",
+                "requires the 'redis' feature",
+            ));
+        }
+
+        if let Some(dir) = &self.jobs_dir {
+            if let Err(e) = check_writable(dir) {
+                errors.push(ConfigError::new(
+                    "HTSGET_JOBS_DIR",
+                    format!("{} is not writable: {}", dir.display(), e),
+                ));
+            }
+        }
+        if self.jobs_s3_bucket.is_some() {
+            if self.jobs_dir.is_none() {
+                errors.push(ConfigError::new(
+                    "HTSGET_JOBS_S3_BUCKET",
+                    "requires HTSGET_JOBS_DIR",
+                ));
+            }
+            if !cfg!(feature = "s3") {
+                errors.push(ConfigError::new(
+                    "HTSGET_JOBS_S3_BUCKET",
+                    "requires the 's3' feature",
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigErrors(errors))
+        }
+    }
+
+    /// Returns the effective base URL for ticket responses.
+    ///
+    /// If `base_url` is set, returns that value. Otherwise, constructs
+    /// a URL from the host and port (e.g., `http://0.0.0.0:8080`, or
+    /// `https://` when a TLS certificate is configured). The path prefix,
+    /// if any, is appended.
+    pub fn effective_base_url(&self) -> String {
+        let scheme = if self.tls_cert.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+        let base_url = self
+            .base_url
+            .clone()
+            .unwrap_or_else(|| format!("{}://{}:{}", scheme, self.host, self.port));
+
+        match self.path_prefix.as_deref().and_then(normalize_path_prefix) {
+            Some(prefix) => format!("{}{}", base_url.trim_end_matches('/'), prefix),
+            None => base_url,
+        }
+    }
+}
+
+/// Why `url` isn't an absolute HTTP(S) URL, if it isn't.
+fn invalid_url(url: &str) -> Option<String> {
+    match url::Url::parse(url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => None,
+        Ok(url) => Some(format!("unsupported URL scheme: {}", url.scheme())),
+        Err(e) => Some(format!("invalid URL {:?}: {}", url, e)),
+    }
+}
+
+/// Create `dir` if needed and check a file can be written to it.
+fn check_writable(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let probe = dir.join(format!(".htsgetr-write-test-{}", std::process::id()));
+    std::fs::write(&probe, b"")?;
+    std::fs::remove_file(&probe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::RetryClass;
+
+    fn make_test_config() -> Config {
+        Config {
+            command: None,
+            config: None,
+            host: "0.0.0.0".to_string(),
+            port: 8080,
+            uds: None,
             base_url: None,
+            path_prefix: None,
             data_dir: PathBuf::from("./data"),
+            convert_vcf: false,
             cors: true,
+            compression: true,
+            cache_control: None,
+            cors_origins: None,
+            cors_methods: None,
+            cors_headers: None,
+            cors_max_age: None,
+            cors_allow_credentials: false,
             log_level: "info".to_string(),
+            preflight: false,
+            enable_reads: true,
+            enable_variants: true,
+            enable_sequences: true,
+            enable_ticket_refresh: false,
+            enable_swagger_ui: false,
+            plain_json_tickets: false,
+            reference_aliases: false,
+            reference_alias_file: None,
+            header_parsing: HeaderParsing::Strict,
+            ticket_signing_key: None,
+            ticket_signing_key_id: None,
+            ticket_signature: TicketSignature::Detached,
+            ticket_cache_ttl: 0,
+            ticket_cache_size: 10000,
+            ticket_url_ttl: 0,
+            max_regions: 1000,
+            ticket_page_size: 0,
+            reads_default_format: Format::Bam,
+            variants_default_format: Format::Vcf,
+            sequences_default_format: Format::Fasta,
+            detect_format: false,
+            trusted_proxies: None,
+            tls_cert: None,
+            tls_key: None,
+            tls_client_ca: None,
+            tls_require_client_cert: false,
             max_payload: 10485760,
             storage: StorageType::Local,
             s3_bucket: None,
             s3_region: None,
             s3_prefix: String::new(),
             s3_endpoint: None,
+            s3_profile: None,
+            s3_role_arn: None,
+            s3_role_session_name: None,
+            s3_external_id: None,
+            s3_web_identity_token_file: None,
+            s3_bucket_routes: None,
             cache_dir: PathBuf::from("/tmp/htsgetr-cache"),
+            decryption_keys: None,
             presigned_url_expiry: 3600,
+            presigned_url_expiry_per_url: 0,
+            presign_clock_skew: 0,
+            prefetch_indexes: false,
+            prefetch_limit: 100,
+            s3_proxy: false,
+            s3_public: false,
+            data_mode: DataMode::Proxy,
+            header_mode: HeaderMode::Range,
             http_base_url: None,
             http_index_base_url: None,
+            http_url_template: None,
+            http_max_redirects: 10,
+            http_headers: None,
+            http_forward_headers: None,
+            http_ticket_headers: None,
+            retry_max_attempts: 3,
+            retry_initial_backoff_ms: 100,
+            retry_max_backoff_ms: 5000,
+            retry_on: None,
+            data_extensions: None,
+            index_extensions: None,
+            index_chunk_size_mb: 8,
+            index_fetch_concurrency: 8,
+            storage_timeout: 30,
+            request_timeout: 60,
+            error_detail: false,
+            expose_internal_errors: false,
+            audit_log: None,
+            multi_replica: false,
+            redis_url: None,
+            quota_requests: None,
+            quota_bytes: None,
+            quota_window: 86400,
+            quota_store: "memory".to_string(),
+            usage_report: false,
+            admin_token: None,
+            jobs_dir: None,
+            max_running_jobs: 2,
+            jobs_retention: 604800,
+            jobs_s3_bucket: None,
+            jobs_s3_prefix: String::new(),
             auth_enabled: false,
             auth_issuer: None,
             auth_audience: None,
             auth_jwks_url: None,
             auth_public_key: None,
-            auth_public_endpoints: "/,/service-info".to_string(),
+            auth_hmac_secret: None,
+            auth_algorithms: None,
+            auth_public_endpoints:
+                "/,/service-info,/reads/service-info,/variants/service-info,/sequences/service-info,/ui,/openapi.json,/swagger-ui,/.well-known/jwks.json"
+                    .to_string(),
+            auth_introspection_url: None,
+            auth_client_id: None,
+            auth_client_secret: None,
+            auth_introspection_cache_ttl: 60,
+            auth_policy: None,
             data_url_secret: None,
+            data_url_secret_file: None,
+            data_url_key_id: None,
+            data_url_previous_secret: None,
+            data_url_previous_key_id: None,
+            data_url_rotation_grace: 3600,
             data_url_expiry: 3600,
         }
     }
@@ -222,6 +2095,29 @@ mod tests {
         assert_eq!(config.effective_base_url(), "http://0.0.0.0:8080");
     }
 
+    #[test]
+    fn test_cors_options() {
+        let mut config = make_test_config();
+        assert!(config.cors_options().is_none());
+
+        config.cors_origins = Some("https://a.example.com, https://b.example.com".to_string());
+        config.cors_max_age = Some(600);
+        let options = config.cors_options().unwrap();
+        assert_eq!(
+            options.origins,
+            vec!["https://a.example.com", "https://b.example.com"]
+        );
+        assert!(options.methods.is_empty());
+        assert_eq!(options.max_age, Some(600));
+    }
+
+    #[test]
+    fn test_effective_base_url_tls() {
+        let mut config = make_test_config();
+        config.tls_cert = Some(PathBuf::from("cert.pem"));
+        assert_eq!(config.effective_base_url(), "https://0.0.0.0:8080");
+    }
+
     #[test]
     fn test_effective_base_url_custom() {
         let mut config = make_test_config();
@@ -229,6 +2125,19 @@ mod tests {
         assert_eq!(config.effective_base_url(), "https://example.com/htsget");
     }
 
+    #[test]
+    fn test_effective_base_url_path_prefix() {
+        let mut config = make_test_config();
+        config.path_prefix = Some("htsget/v1/".to_string());
+        assert_eq!(config.effective_base_url(), "http://0.0.0.0:8080/htsget/v1");
+
+        config.base_url = Some("https://example.com/".to_string());
+        assert_eq!(config.effective_base_url(), "https://example.com/htsget/v1");
+
+        config.path_prefix = Some("/".to_string());
+        assert_eq!(config.effective_base_url(), "https://example.com/");
+    }
+
     #[test]
     fn test_effective_base_url_custom_port() {
         let mut config = make_test_config();
@@ -247,5 +2156,694 @@ mod tests {
         assert_eq!(StorageType::from_str("HTTP").unwrap(), StorageType::Http);
         assert_eq!(StorageType::from_str("https").unwrap(), StorageType::Http);
         assert!(StorageType::from_str("invalid").is_err());
+        assert!(StorageType::from_str("gcs").is_err());
+    }
+
+    #[test]
+    fn test_storage_type_round_trip() {
+        for storage in StorageType::ALL {
+            assert_eq!(storage.to_string().parse::<StorageType>(), Ok(storage));
+            for name in storage.names() {
+                assert_eq!(name.parse::<StorageType>(), Ok(storage));
+            }
+        }
+
+        let config = Config::parse_from(["htsgetr", "--storage", "https"]);
+        assert_eq!(config.storage, StorageType::Http);
+        assert!(StorageType::Local.is_available());
+        assert_eq!(StorageType::Http.feature(), Some("http"));
+    }
+
+    #[test]
+    fn test_data_mode_parsing() {
+        assert_eq!(DataMode::from_str("proxy").unwrap(), DataMode::Proxy);
+        assert_eq!(DataMode::from_str("Redirect").unwrap(), DataMode::Redirect);
+        assert!(DataMode::from_str("invalid").is_err());
+
+        let config = Config::parse_from(["htsgetr", "--data-mode", "redirect"]);
+        assert_eq!(config.data_mode, DataMode::Redirect);
+        assert_eq!(Config::parse_from(["htsgetr"]).data_mode, DataMode::Proxy);
+    }
+
+    #[test]
+    fn test_header_mode_parsing() {
+        assert_eq!(
+            HeaderMode::from_str("Standalone").unwrap(),
+            HeaderMode::Standalone
+        );
+        assert!(HeaderMode::from_str("invalid").is_err());
+
+        let config = Config::parse_from(["htsgetr", "--header-mode", "standalone"]);
+        assert_eq!(config.header_mode, HeaderMode::Standalone);
+        assert_eq!(
+            Config::parse_from(["htsgetr"]).header_mode,
+            HeaderMode::Range
+        );
+    }
+
+    #[test]
+    fn test_header_parsing() {
+        let config = Config::parse_from(["htsgetr", "--header-parsing", "lenient"]);
+        assert_eq!(config.header_parsing, HeaderParsing::Lenient);
+        assert_eq!(
+            Config::parse_from(["htsgetr"]).header_parsing,
+            HeaderParsing::Strict
+        );
+    }
+
+    #[test]
+    fn test_default_formats() {
+        assert_eq!(
+            make_test_config().default_formats().unwrap(),
+            DefaultFormats::default()
+        );
+
+        let config = Config::parse_from([
+            "htsgetr",
+            "--reads-default-format",
+            "cram",
+            "--detect-format",
+            "true",
+        ]);
+        let formats = config.default_formats().unwrap();
+        assert_eq!(formats.reads, Format::Cram);
+        assert_eq!(formats.variants, Format::Vcf);
+        assert!(formats.detect);
+
+        let config = Config {
+            variants_default_format: Format::Bam,
+            ..make_test_config()
+        };
+        assert!(config.default_formats().is_err());
+    }
+
+    #[test]
+    fn test_audit_sink() {
+        assert_eq!(make_test_config().audit_sink().unwrap(), None);
+
+        let config = Config {
+            audit_log: Some("file:/var/log/htsgetr/audit.jsonl".to_string()),
+            ..make_test_config()
+        };
+        assert_eq!(
+            config.audit_sink().unwrap(),
+            Some(AuditSink::File(PathBuf::from(
+                "/var/log/htsgetr/audit.jsonl"
+            )))
+        );
+
+        let config = Config {
+            audit_log: Some("syslog".to_string()),
+            ..make_test_config()
+        };
+        assert!(config.audit_sink().is_err());
+    }
+
+    #[test]
+    fn test_multi_replica() {
+        assert!(!make_test_config().is_multi_replica());
+        let config = Config {
+            redis_url: Some("redis://redis:6379".to_string()),
+            ..make_test_config()
+        };
+        assert!(config.is_multi_replica());
+    }
+
+    #[test]
+    fn test_quota_limits() {
+        assert_eq!(make_test_config().quota_limits(), None);
+        assert_eq!(
+            make_test_config().quota_backend().unwrap(),
+            QuotaBackend::Memory
+        );
+
+        let config = Config {
+            quota_bytes: Some(10 * 1024 * 1024 * 1024),
+            quota_window: 3600,
+            ..make_test_config()
+        };
+        assert_eq!(
+            config.quota_limits(),
+            Some(QuotaLimits {
+                requests: None,
+                bytes: Some(10 * 1024 * 1024 * 1024),
+                window: Duration::from_secs(3600),
+            })
+        );
+
+        let config = Config {
+            quota_store: "memcached://localhost".to_string(),
+            ..make_test_config()
+        };
+        assert!(config.quota_backend().is_err());
+    }
+
+    #[test]
+    fn test_endpoints() {
+        assert_eq!(make_test_config().endpoints(), Endpoints::default());
+
+        let config = Config {
+            enable_variants: false,
+            ..make_test_config()
+        };
+        let endpoints = config.endpoints();
+        assert!(endpoints.reads && endpoints.sequences && !endpoints.variants);
+    }
+
+    #[test]
+    fn test_validate_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            data_dir: dir.path().to_path_buf(),
+            ..make_test_config()
+        };
+        assert_eq!(config.validate(), Ok(()));
+
+        let config = Config {
+            data_dir: dir.path().join("missing"),
+            ..make_test_config()
+        };
+        assert!(config.validate().unwrap_err().contains("HTSGET_DATA_DIR"));
+    }
+
+    #[test]
+    fn test_validate_s3_bucket() {
+        let config = Config {
+            storage: StorageType::S3,
+            ..make_test_config()
+        };
+        let errors = config.validate().unwrap_err();
+        assert!(errors.contains("HTSGET_S3_BUCKET"));
+        assert_eq!(errors.contains("HTSGET_STORAGE"), !cfg!(feature = "s3"));
+    }
+
+    #[test]
+    fn test_validate_http_base_url() {
+        let config = Config {
+            storage: StorageType::Http,
+            ..make_test_config()
+        };
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("HTSGET_HTTP_BASE_URL")
+        );
+
+        let config = Config {
+            storage: StorageType::Http,
+            http_base_url: Some("ftp://example.com/data".to_string()),
+            ..make_test_config()
+        };
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("HTSGET_HTTP_BASE_URL")
+        );
+    }
+
+    #[test]
+    fn test_validate_base_url() {
+        let config = Config {
+            base_url: Some("example.com".to_string()),
+            ..make_test_config()
+        };
+        assert!(config.validate().unwrap_err().contains("HTSGET_BASE_URL"));
+    }
+
+    #[test]
+    fn test_validate_auth_key_source() {
+        let config = Config {
+            auth_enabled: true,
+            ..make_test_config()
+        };
+        let errors = config.validate().unwrap_err();
+        assert!(
+            errors
+                .0
+                .iter()
+                .any(|e| e.option == "HTSGET_AUTH_ENABLED" && e.message.contains("key source"))
+        );
+
+        let config = Config {
+            auth_enabled: true,
+            auth_hmac_secret: Some("secret".to_string()),
+            multi_replica: true,
+            ..make_test_config()
+        };
+        let errors = config.validate().unwrap_err();
+        assert!(errors.contains("HTSGET_DATA_URL_SECRET"));
+        assert!(!errors.0.iter().any(|e| e.message.contains("key source")));
+    }
+
+    #[test]
+    fn test_validate_cache_dir() {
+        // A directory can't be created under a file
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let config = Config {
+            convert_vcf: true,
+            cache_dir: file.path().join("cache"),
+            ..make_test_config()
+        };
+        assert!(config.validate().unwrap_err().contains("HTSGET_CACHE_DIR"));
+
+        // Local storage doesn't use the cache directory
+        let config = Config {
+            cache_dir: file.path().join("cache"),
+            ..make_test_config()
+        };
+        assert!(
+            !config
+                .validate()
+                .is_err_and(|errors| errors.contains("HTSGET_CACHE_DIR"))
+        );
+    }
+
+    #[test]
+    fn test_validate_jobs() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let config = Config {
+            jobs_dir: Some(file.path().join("jobs")),
+            ..make_test_config()
+        };
+        assert!(config.validate().unwrap_err().contains("HTSGET_JOBS_DIR"));
+
+        let config = Config {
+            jobs_s3_bucket: Some("exports".to_string()),
+            ..make_test_config()
+        };
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("HTSGET_JOBS_S3_BUCKET")
+        );
+    }
+
+    #[test]
+    fn test_validate_tls_pair() {
+        let config = Config {
+            tls_cert: Some(PathBuf::from("cert.pem")),
+            ..make_test_config()
+        };
+        assert!(config.validate().unwrap_err().contains("HTSGET_TLS_KEY"));
+    }
+
+    #[test]
+    fn test_validate_ticket_signing_key() {
+        // Missing file with the jws feature, or no jws feature
+        let config = Config {
+            ticket_signing_key: Some(PathBuf::from("/no/such/key.pem")),
+            ..make_test_config()
+        };
+        let errors = config.validate().unwrap_err();
+        assert!(errors.contains("HTSGET_TICKET_SIGNING_KEY"));
+    }
+
+    #[test]
+    fn test_validate_decryption_keys() {
+        // Missing file with the crypt4gh feature, or no crypt4gh feature
+        let config = Config {
+            decryption_keys: Some(PathBuf::from("/no/such/keys.toml")),
+            ..make_test_config()
+        };
+        let errors = config.validate().unwrap_err();
+        assert!(errors.contains("HTSGET_DECRYPTION_KEYS"));
+    }
+
+    #[test]
+    fn test_reference_alias_table() {
+        assert!(
+            make_test_config()
+                .reference_alias_table()
+                .unwrap()
+                .is_none()
+        );
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"ctg1\tscaffold_1\n").unwrap();
+        let config = Config {
+            reference_alias_file: Some(file.path().to_path_buf()),
+            ..make_test_config()
+        };
+        let aliases = config.reference_alias_table().unwrap().unwrap();
+        let names = ["chr1".to_string(), "ctg1".to_string()];
+        assert_eq!(aliases.resolve("1", &names), Some("chr1"));
+        assert_eq!(aliases.resolve("scaffold_1", &names), Some("ctg1"));
+
+        let config = Config {
+            reference_alias_file: Some(PathBuf::from("/no/such/aliases.txt")),
+            ..make_test_config()
+        };
+        let errors = config.validate().unwrap_err();
+        assert!(errors.contains("HTSGET_REFERENCE_ALIAS_FILE"));
+    }
+
+    #[test]
+    fn test_validate_s3_role() {
+        let config = Config {
+            s3_external_id: Some("partner".to_string()),
+            s3_web_identity_token_file: Some(PathBuf::from("/no/such/token")),
+            ..make_test_config()
+        };
+        let errors = config.validate().unwrap_err();
+        assert!(errors.contains("HTSGET_S3_EXTERNAL_ID"));
+        assert!(errors.contains("HTSGET_S3_WEB_IDENTITY_TOKEN_FILE"));
+
+        let token = tempfile::NamedTempFile::new().unwrap();
+        let config = Config {
+            s3_role_arn: Some("arn:aws:iam::123456789012:role/htsget".to_string()),
+            s3_web_identity_token_file: Some(token.path().to_path_buf()),
+            ..make_test_config()
+        };
+        assert!(
+            !config
+                .validate()
+                .is_err_and(|errors| errors.0.iter().any(|e| e.option.starts_with("HTSGET_S3_")))
+        );
+    }
+
+    #[test]
+    fn test_validate_parsed_options() {
+        let config = Config {
+            reads_default_format: Format::Vcf,
+            data_extensions: Some("bam".to_string()),
+            retry_on: Some("sometimes".to_string()),
+            quota_store: "memcached://localhost".to_string(),
+            ..make_test_config()
+        };
+        let errors = config.validate().unwrap_err();
+        for option in [
+            "HTSGET_READS_DEFAULT_FORMAT",
+            "HTSGET_DATA_EXTENSIONS",
+            "HTSGET_RETRY_ON",
+            "HTSGET_QUOTA_STORE",
+        ] {
+            assert!(errors.contains(option), "{} not reported", option);
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_all_errors() {
+        let config = Config {
+            storage: StorageType::S3,
+            base_url: Some("not a url".to_string()),
+            auth_enabled: true,
+            ..make_test_config()
+        };
+        let errors = config.validate().unwrap_err();
+        assert!(errors.0.len() >= 3);
+        let message = errors.to_string();
+        assert!(message.contains("HTSGET_S3_BUCKET: required for S3 storage"));
+        assert!(message.contains("HTSGET_BASE_URL"));
+    }
+
+    #[test]
+    fn test_validate_s3_public() {
+        let config = Config {
+            s3_public: true,
+            s3_profile: Some("research".to_string()),
+            ..make_test_config()
+        };
+        let errors = config.validate().unwrap_err();
+        assert!(errors.contains("HTSGET_S3_PROFILE"));
+        assert!(!errors.contains("HTSGET_S3_PUBLIC"));
+    }
+
+    #[test]
+    fn test_validate_http_url_template() {
+        let config = Config {
+            http_url_template: Some("{base}/{id}/data".to_string()),
+            ..make_test_config()
+        };
+        let errors = config.validate().unwrap_err();
+        assert_eq!(
+            errors
+                .0
+                .iter()
+                .filter(|e| e.option == "HTSGET_HTTP_URL_TEMPLATE")
+                .count(),
+            1
+        );
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_validate_http_headers() {
+        let config = Config {
+            http_headers: Some("Authorization: Bearer abc123".to_string()),
+            http_forward_headers: Some("X-Request-Id".to_string()),
+            http_ticket_headers: Some("Authorization".to_string()),
+            ..make_test_config()
+        };
+        assert!(!config.validate().is_err_and(|errors| {
+            errors
+                .0
+                .iter()
+                .any(|e| e.option.starts_with("HTSGET_HTTP_"))
+        }));
+        assert_eq!(config.ticket_headers().unwrap(), ["authorization"]);
+
+        let config = Config {
+            http_headers: Some("Authorization".to_string()),
+            http_forward_headers: Some("Bad Name".to_string()),
+            http_ticket_headers: Some("X-Api-Key".to_string()),
+            ..make_test_config()
+        };
+        let errors = config.validate().unwrap_err();
+        assert!(errors.contains("HTSGET_HTTP_HEADERS"));
+        assert!(errors.contains("HTSGET_HTTP_FORWARD_HEADERS"));
+        assert!(!errors.contains("HTSGET_HTTP_TICKET_HEADERS"));
+        assert!(
+            Config {
+                http_headers: None,
+                ..config
+            }
+            .ticket_headers()
+            .is_err()
+        );
+    }
+
+    #[cfg(feature = "s3")]
+    #[test]
+    fn test_validate_bucket_routes() {
+        let mut routes = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut routes, b"[[route]]\nid_prefix = \"1000g/\"\n").unwrap();
+        let config = Config {
+            s3_bucket_routes: Some(routes.path().to_path_buf()),
+            ..make_test_config()
+        };
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .contains("HTSGET_S3_BUCKET_ROUTES")
+        );
+    }
+
+    #[test]
+    fn test_naming() {
+        let naming = make_test_config().naming().unwrap();
+        assert_eq!(naming, Naming::default());
+
+        let mut config = make_test_config();
+        config.data_extensions = Some("fasta:fasta,vcf:vcf.bgz".to_string());
+        config.index_extensions = Some("bam:csi".to_string());
+        let naming = config.naming().unwrap();
+        assert_eq!(
+            naming.data_extensions(Format::Fasta),
+            ["fa", "fa.gz", "fasta"]
+        );
+        assert_eq!(naming.index_extensions(Format::Bam), ["bai", "csi"]);
+
+        config.data_extensions = Some("fasta".to_string());
+        assert!(config.naming().is_err());
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = make_test_config().retry_policy().unwrap();
+        assert_eq!(policy.max_attempts, 3);
+        assert_eq!(policy.retry_on.len(), 4);
+
+        let mut config = make_test_config();
+        config.retry_max_attempts = 0;
+        config.retry_on = Some("timeout, throttled".to_string());
+        let policy = config.retry_policy().unwrap();
+        assert_eq!(policy.max_attempts, 1);
+        assert!(policy.retry_on.contains(&RetryClass::Throttled));
+        assert!(!policy.retry_on.contains(&RetryClass::ServerError));
+
+        config.retry_on = Some("sometimes".to_string());
+        assert!(config.retry_policy().is_err());
+    }
+
+    #[test]
+    fn test_index_subcommand_parsing() {
+        let config = Config::parse_from(["htsgetr", "index", "./data", "--force"]);
+        match config.command {
+            Some(Command::Index(args)) => {
+                assert_eq!(args.path, PathBuf::from("./data"));
+                assert!(args.force);
+            }
+            _ => panic!("expected index subcommand"),
+        }
+
+        let config = Config::parse_from(["htsgetr"]);
+        assert!(config.command.is_none());
+    }
+
+    #[test]
+    fn test_subcommands_share_global_options() {
+        let config = Config::parse_from(["htsgetr", "check", "--data-dir", "/srv/data"]);
+        assert!(matches!(config.command, Some(Command::Check(_))));
+        assert_eq!(config.data_dir, PathBuf::from("/srv/data"));
+
+        let config = Config::parse_from(["htsgetr", "serve", "--port", "9000"]);
+        assert!(matches!(config.command, Some(Command::Serve(_))));
+        assert_eq!(config.port, 9000);
+
+        let config = Config::parse_from(["htsgetr", "serve", "--print-config"]);
+        assert!(matches!(
+            config.command,
+            Some(Command::Serve(ServeArgs { print_config: true }))
+        ));
+
+        let config = Config::parse_from([
+            "htsgetr",
+            "bench",
+            "mt",
+            "--region",
+            "chr1:1-1000",
+            "-n",
+            "10",
+        ]);
+        match config.command {
+            Some(Command::Bench(args)) => {
+                assert_eq!(args.id, "mt");
+                assert_eq!(args.region.as_deref(), Some("chr1:1-1000"));
+                assert_eq!(args.iterations, 10);
+            }
+            _ => panic!("expected bench subcommand"),
+        }
+    }
+
+    #[test]
+    fn test_init_and_completions_subcommand_parsing() {
+        let config = Config::parse_from(["htsgetr", "init", "-o", "htsgetr.toml"]);
+        match config.command {
+            Some(Command::Init(args)) => {
+                assert_eq!(args.output, Some(PathBuf::from("htsgetr.toml")));
+                assert!(!args.force);
+            }
+            _ => panic!("expected init subcommand"),
+        }
+
+        let config = Config::parse_from(["htsgetr", "completions", "zsh"]);
+        assert!(matches!(
+            config.command,
+            Some(Command::Completions(CompletionsArgs {
+                shell: clap_complete::Shell::Zsh
+            }))
+        ));
+        assert!(Config::try_parse_from(["htsgetr", "completions", "cmd"]).is_err());
+    }
+
+    #[test]
+    fn test_config_file_parse() {
+        let file = ConfigFile::parse(
+            "data-dir = \"/srv/data\"\nport = 9000\nusage_report = true\ncors_origins = [\"https://a.example\", \"https://b.example\"]\n",
+        )
+        .unwrap();
+        assert_eq!(file.settings["data_dir"], "/srv/data");
+        assert_eq!(file.settings["port"], "9000");
+        assert_eq!(file.settings["usage_report"], "true");
+        assert_eq!(
+            file.settings["cors_origins"],
+            "https://a.example,https://b.example"
+        );
+
+        assert!(ConfigFile::parse("prot = 9000").is_err());
+        assert!(ConfigFile::parse("config = \"other.toml\"").is_err());
+        assert!(ConfigFile::parse("[server]\nport = 9000").is_err());
+        assert!(ConfigFile::parse("port = ").is_err());
+    }
+
+    #[test]
+    fn test_config_file_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("htsgetr.toml");
+        std::fs::write(
+            &path,
+            "port = 9000\ndata_dir = \"/srv/data\"\nusage_report = true\n",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+
+        let config = Config::try_load_from(["htsgetr", "--config", path]).unwrap();
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.data_dir, PathBuf::from("/srv/data"));
+        assert!(config.usage_report);
+
+        // Flags override the file, in subcommands too
+        let config =
+            Config::try_load_from(["htsgetr", "check", "--config", path, "--port", "7000"])
+                .unwrap();
+        assert_eq!(config.port, 7000);
+        assert_eq!(config.data_dir, PathBuf::from("/srv/data"));
+
+        assert!(Config::try_load_from(["htsgetr", "--config", "/nonexistent.toml"]).is_err());
+    }
+
+    #[test]
+    fn test_config_file_example() {
+        let example = ConfigFile::example();
+        assert!(example.contains("\n# Port to listen on\n# port = 8080\n"));
+        assert!(example.contains("\n# data_dir = \"./data\"\n"));
+        assert!(example.contains("\n# usage_report = false\n"));
+
+        // Uncommented, the example parses and changes nothing
+        let uncommented: String = example
+            .lines()
+            .filter_map(|line| line.strip_prefix("# "))
+            .filter(|line| {
+                line.split_once(" = ").is_some_and(|(key, _)| {
+                    key.chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+                })
+            })
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let file = ConfigFile::parse(&uncommented).unwrap();
+        assert_eq!(file.settings["port"], "8080");
+        assert_eq!(file.settings["host"], "0.0.0.0");
+    }
+
+    #[test]
+    fn test_fetch_subcommand_parsing() {
+        let config = Config::parse_from([
+            "htsgetr",
+            "fetch",
+            "--url",
+            "http://localhost:8080",
+            "--id",
+            "NA12878",
+            "--region",
+            "chr1:1-100000",
+            "-o",
+            "slice.bam",
+        ]);
+        match config.command {
+            Some(Command::Fetch(args)) => {
+                assert_eq!(args.url, "http://localhost:8080");
+                assert_eq!(args.id, "NA12878");
+                assert_eq!(args.endpoint, "reads");
+                assert_eq!(args.retries, 3);
+                assert!(!args.header_only);
+                assert_eq!(args.output, Some(PathBuf::from("slice.bam")));
+            }
+            _ => panic!("expected fetch subcommand"),
+        }
     }
 }
@@ -0,0 +1,514 @@
+//! htsget protocol conformance checks.
+//!
+//! A Rust port of the request/response cases of the GA4GH
+//! [htsget-compliance](https://github.com/ga4gh/htsget-compliance) suite.
+//! [`Conformance`] requests tickets from a running server, follows them with
+//! [`HtsgetClient`], and checks that the concatenated blocks parse as a valid
+//! file of the requested format, or that errors carry the status and error
+//! type the spec requires. Each case names the requirement it covers.
+//!
+//! The default [`Fixtures`] match the bundled `tests/data` files; `htsgetr
+//! conformance` runs the cases against a local server on them.
+
+use crate::Result;
+use crate::client::HtsgetClient;
+use crate::types::{DataClass, Format, HtsgetResponse};
+use noodles::{bam, bcf, bgzf, cram, vcf};
+use serde_json::{Value, json};
+
+/// The empty block that ends every BGZF file
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Samples the cases request. Cases for an absent sample are skipped.
+#[derive(Debug, Clone)]
+pub struct Fixtures {
+    /// Indexed BAM
+    pub reads_id: Option<String>,
+    /// Indexed CRAM
+    pub cram_id: Option<String>,
+    /// Indexed VCF
+    pub variants_id: Option<String>,
+    /// Reference sequence present in every sample
+    pub reference_name: String,
+}
+
+impl Default for Fixtures {
+    /// The bundled `tests/data` samples
+    fn default() -> Self {
+        Self {
+            reads_id: Some("sample".to_string()),
+            cram_id: Some("sample".to_string()),
+            variants_id: Some("sample".to_string()),
+            reference_name: "chr1".to_string(),
+        }
+    }
+}
+
+/// What a case's response must be
+#[derive(Debug)]
+enum Expect {
+    /// A service-info document for htsget
+    ServiceInfo,
+    /// A ticket whose blocks form a valid file of `format`
+    Ticket { format: Format, header_only: bool },
+    /// An htsget error
+    Error { status: u16, error: &'static str },
+}
+
+#[derive(Debug)]
+struct Case {
+    name: &'static str,
+    requirement: &'static str,
+    path: String,
+    /// POST body; GET when absent
+    body: Option<Value>,
+    expect: Expect,
+}
+
+/// Outcome of one case
+#[derive(Debug)]
+pub struct CaseResult {
+    pub name: &'static str,
+    /// The spec requirement the case covers
+    pub requirement: &'static str,
+    /// Why the case failed
+    pub failure: Option<String>,
+}
+
+/// Outcome of a conformance run
+#[derive(Debug, Default)]
+pub struct ConformanceReport {
+    pub results: Vec<CaseResult>,
+}
+
+impl ConformanceReport {
+    pub fn passed(&self) -> usize {
+        self.results.len() - self.failed()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.iter().filter(|r| r.failure.is_some()).count()
+    }
+}
+
+impl std::fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for result in &self.results {
+            match &result.failure {
+                None => writeln!(f, "PASS  {:<32} {}", result.name, result.requirement)?,
+                Some(failure) => {
+                    writeln!(f, "FAIL  {:<32} {}", result.name, result.requirement)?;
+                    writeln!(f, "      {}", failure)?;
+                }
+            }
+        }
+        write!(f, "{} passed, {} failed", self.passed(), self.failed())
+    }
+}
+
+/// Runs the conformance cases against an htsget server
+#[derive(Debug, Clone)]
+pub struct Conformance {
+    base_url: String,
+    http: reqwest::Client,
+    client: HtsgetClient,
+    token: Option<String>,
+    fixtures: Fixtures,
+}
+
+impl Conformance {
+    /// Check the server at `base_url` (e.g., `http://localhost:8080`).
+    pub fn new(base_url: &str, fixtures: Fixtures) -> Result<Self> {
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+            client: HtsgetClient::try_new(base_url)?,
+            token: None,
+            fixtures,
+        })
+    }
+
+    /// Send `token` as a bearer token with every request.
+    pub fn with_bearer_token(mut self, token: impl Into<String>) -> Self {
+        let token = token.into();
+        self.client = self.client.with_bearer_token(token.clone());
+        self.token = Some(token);
+        self
+    }
+
+    /// Run every case, in order.
+    pub async fn run(&self) -> ConformanceReport {
+        let mut report = ConformanceReport::default();
+        for case in self.cases() {
+            let failure = self.check(&case).await.err();
+            report.results.push(CaseResult {
+                name: case.name,
+                requirement: case.requirement,
+                failure,
+            });
+        }
+        report
+    }
+
+    fn cases(&self) -> Vec<Case> {
+        let reference_name = &self.fixtures.reference_name;
+        let region = format!("referenceName={}&start=0&end=100000", reference_name);
+        let regions = json!([{"referenceName": reference_name, "start": 0, "end": 100000}]);
+        let ticket = |format, header_only| Expect::Ticket {
+            format,
+            header_only,
+        };
+        let error = |status, error| Expect::Error { status, error };
+
+        let mut cases = vec![
+            Case {
+                name: "service-info",
+                requirement: "service-info describes an htsget service",
+                path: "/service-info".to_string(),
+                body: None,
+                expect: Expect::ServiceInfo,
+            },
+            Case {
+                name: "not-found",
+                requirement: "unknown IDs are NotFound (404)",
+                path: "/reads/htsget-conformance-missing".to_string(),
+                body: None,
+                expect: error(404, "NotFound"),
+            },
+        ];
+
+        if let Some(id) = &self.fixtures.reads_id {
+            cases.extend([
+                Case {
+                    name: "reads-whole-file",
+                    requirement: "a request without regions returns the whole file",
+                    path: format!("/reads/{}", id),
+                    body: None,
+                    expect: ticket(Format::Bam, false),
+                },
+                Case {
+                    name: "reads-region",
+                    requirement: "a region request returns a valid BAM",
+                    path: format!("/reads/{}?format=BAM&{}", id, region),
+                    body: None,
+                    expect: ticket(Format::Bam, false),
+                },
+                Case {
+                    name: "reads-header",
+                    requirement: "class=header returns only the header, ending with EOF",
+                    path: format!("/reads/{}?class=header", id),
+                    body: None,
+                    expect: ticket(Format::Bam, true),
+                },
+                Case {
+                    name: "reads-post-regions",
+                    requirement: "POST accepts a regions array",
+                    path: format!("/reads/{}", id),
+                    body: Some(json!({"format": "BAM", "regions": regions})),
+                    expect: ticket(Format::Bam, false),
+                },
+                Case {
+                    name: "reads-post-empty-regions",
+                    requirement: "POST with empty regions returns the whole file",
+                    path: format!("/reads/{}", id),
+                    body: Some(json!({"regions": []})),
+                    expect: ticket(Format::Bam, false),
+                },
+                Case {
+                    name: "reads-unsupported-format",
+                    requirement: "a variants format for reads is UnsupportedFormat (400)",
+                    path: format!("/reads/{}?format=VCF", id),
+                    body: None,
+                    expect: error(400, "UnsupportedFormat"),
+                },
+                Case {
+                    name: "reads-header-with-region",
+                    requirement: "class=header with a region is InvalidInput (400)",
+                    path: format!(
+                        "/reads/{}?class=header&referenceName={}",
+                        id, reference_name
+                    ),
+                    body: None,
+                    expect: error(400, "InvalidInput"),
+                },
+                Case {
+                    name: "reads-unknown-class",
+                    requirement: "class other than header/body is InvalidInput (400)",
+                    path: format!("/reads/{}?class=all", id),
+                    body: None,
+                    expect: error(400, "InvalidInput"),
+                },
+                Case {
+                    name: "reads-start-without-reference",
+                    requirement: "start without referenceName is InvalidInput (400)",
+                    path: format!("/reads/{}?start=100", id),
+                    body: None,
+                    expect: error(400, "InvalidInput"),
+                },
+            ]);
+        }
+
+        if let Some(id) = &self.fixtures.cram_id {
+            cases.extend([
+                Case {
+                    name: "cram-whole-file",
+                    requirement: "format=CRAM returns a valid CRAM",
+                    path: format!("/reads/{}?format=CRAM", id),
+                    body: None,
+                    expect: ticket(Format::Cram, false),
+                },
+                Case {
+                    name: "cram-region",
+                    requirement: "a CRAM region request returns a valid CRAM",
+                    path: format!("/reads/{}?format=CRAM&{}", id, region),
+                    body: None,
+                    expect: ticket(Format::Cram, false),
+                },
+            ]);
+        }
+
+        if let Some(id) = &self.fixtures.variants_id {
+            cases.extend([
+                Case {
+                    name: "variants-whole-file",
+                    requirement: "a request without regions returns the whole file",
+                    path: format!("/variants/{}", id),
+                    body: None,
+                    expect: ticket(Format::Vcf, false),
+                },
+                Case {
+                    name: "variants-region",
+                    requirement: "a region request returns a valid VCF",
+                    path: format!("/variants/{}?format=VCF&{}", id, region),
+                    body: None,
+                    expect: ticket(Format::Vcf, false),
+                },
+                Case {
+                    name: "variants-header",
+                    requirement: "class=header returns only the header, ending with EOF",
+                    path: format!("/variants/{}?class=header", id),
+                    body: None,
+                    expect: ticket(Format::Vcf, true),
+                },
+                Case {
+                    name: "variants-post-regions",
+                    requirement: "POST accepts a regions array",
+                    path: format!("/variants/{}", id),
+                    body: Some(json!({"format": "VCF", "regions": regions})),
+                    expect: ticket(Format::Vcf, false),
+                },
+                Case {
+                    name: "variants-unsupported-format",
+                    requirement: "a reads format for variants is UnsupportedFormat (400)",
+                    path: format!("/variants/{}?format=BAM", id),
+                    body: None,
+                    expect: error(400, "UnsupportedFormat"),
+                },
+            ]);
+        }
+
+        cases
+    }
+
+    async fn check(&self, case: &Case) -> std::result::Result<(), String> {
+        let url = format!("{}{}", self.base_url, case.path);
+        let mut request = match &case.body {
+            Some(body) => self.http.post(&url).json(body),
+            None => self.http.get(&url),
+        };
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("request to {} failed: {}", url, e))?;
+        let status = response.status().as_u16();
+        let body: Value = response
+            .json()
+            .await
+            .map_err(|e| format!("response is not JSON: {}", e))?;
+
+        match &case.expect {
+            Expect::ServiceInfo => {
+                expect_status(status, 200, &body)?;
+                if body["type"]["artifact"] != "htsget" {
+                    return Err(format!(
+                        "type.artifact is {}, not htsget",
+                        body["type"]["artifact"]
+                    ));
+                }
+                Ok(())
+            }
+            Expect::Error {
+                status: expected,
+                error,
+            } => {
+                expect_status(status, *expected, &body)?;
+                if body["htsget"]["error"] != *error {
+                    return Err(format!(
+                        "error is {}, not {}",
+                        body["htsget"]["error"], error
+                    ));
+                }
+                Ok(())
+            }
+            Expect::Ticket {
+                format,
+                header_only,
+            } => {
+                expect_status(status, 200, &body)?;
+                let ticket: HtsgetResponse =
+                    serde_json::from_value(body).map_err(|e| format!("invalid ticket: {}", e))?;
+                self.check_ticket(&ticket, *format, *header_only).await
+            }
+        }
+    }
+
+    async fn check_ticket(
+        &self,
+        ticket: &HtsgetResponse,
+        format: Format,
+        header_only: bool,
+    ) -> std::result::Result<(), String> {
+        let urls = &ticket.htsget.urls;
+        if ticket.htsget.format != format {
+            return Err(format!(
+                "ticket format is {:?}, not {:?}",
+                ticket.htsget.format, format
+            ));
+        }
+        if urls.is_empty() {
+            return Err("ticket has no URLs".to_string());
+        }
+        // Either every URL has a class or none does
+        let classed = urls.iter().filter(|url| url.class.is_some()).count();
+        if classed != 0 && classed != urls.len() {
+            return Err("only some ticket URLs have a class".to_string());
+        }
+        if header_only && urls.iter().any(|url| url.class != Some(DataClass::Header)) {
+            return Err("header-only ticket has non-header URLs".to_string());
+        }
+
+        let data = self
+            .client
+            .fetch(ticket)
+            .await
+            .map_err(|e| format!("fetching ticket URLs failed: {}", e))?;
+        validate(format, &data, header_only)
+    }
+}
+
+fn expect_status(status: u16, expected: u16, body: &Value) -> std::result::Result<(), String> {
+    if status == expected {
+        return Ok(());
+    }
+    Err(format!(
+        "status {}, expected {}: {}",
+        status, expected, body
+    ))
+}
+
+/// Check that `data` is a complete file of `format`: the header parses, as
+/// does every record, and BGZF files end with the EOF marker, as `samtools
+/// quickcheck` requires. A header-only file must hold no records.
+pub fn validate(format: Format, data: &[u8], header_only: bool) -> std::result::Result<(), String> {
+    let records = match format {
+        Format::Bam => {
+            let mut reader = bam::io::Reader::new(data);
+            reader
+                .read_header()
+                .map_err(|e| format!("invalid BAM header: {}", e))?;
+            count(reader.records()).map_err(|e| format!("invalid BAM record: {}", e))?
+        }
+        Format::Cram => {
+            let mut reader = cram::io::Reader::new(data);
+            reader
+                .read_header()
+                .map_err(|e| format!("invalid CRAM header: {}", e))?;
+            0
+        }
+        Format::Vcf => {
+            let mut reader = vcf::io::Reader::new(bgzf::io::Reader::new(data));
+            reader
+                .read_header()
+                .map_err(|e| format!("invalid VCF header: {}", e))?;
+            count(reader.records()).map_err(|e| format!("invalid VCF record: {}", e))?
+        }
+        Format::Bcf => {
+            let mut reader = bcf::io::Reader::new(data);
+            reader
+                .read_header()
+                .map_err(|e| format!("invalid BCF header: {}", e))?;
+            count(reader.records()).map_err(|e| format!("invalid BCF record: {}", e))?
+        }
+        Format::Fasta | Format::Fastq => {
+            return Err(format!("{:?} has no conformance checks", format));
+        }
+    };
+
+    if header_only && records > 0 {
+        return Err(format!("header-only file holds {} records", records));
+    }
+    if format != Format::Cram && !data.ends_with(&BGZF_EOF) {
+        return Err("file does not end with the BGZF EOF marker".to_string());
+    }
+
+    Ok(())
+}
+
+fn count<T>(records: impl Iterator<Item = std::io::Result<T>>) -> std::io::Result<usize> {
+    records.try_fold(0, |n, record| record.map(|_| n + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn data(name: &str) -> Vec<u8> {
+        std::fs::read(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/data")
+                .join(name),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        let bam = data("sample.bam");
+        assert!(validate(Format::Bam, &bam, false).is_ok());
+        // The whole file holds records
+        assert!(validate(Format::Bam, &bam, true).is_err());
+        assert!(validate(Format::Bam, &bam[..bam.len() / 2], false).is_err());
+        // Records without the EOF marker
+        assert!(validate(Format::Bam, &bam[..bam.len() - BGZF_EOF.len()], false).is_err());
+
+        assert!(validate(Format::Vcf, &data("sample.vcf.gz"), false).is_ok());
+        assert!(validate(Format::Cram, &data("sample.cram"), false).is_ok());
+        assert!(validate(Format::Vcf, &bam, false).is_err());
+    }
+
+    #[test]
+    fn test_cases_skip_absent_samples() {
+        let all = Conformance::new("http://localhost:8080", Fixtures::default()).unwrap();
+        let reads_only = Conformance::new(
+            "http://localhost:8080",
+            Fixtures {
+                cram_id: None,
+                variants_id: None,
+                ..Fixtures::default()
+            },
+        )
+        .unwrap();
+
+        let cases = reads_only.cases();
+        assert!(cases.len() < all.cases().len());
+        assert!(cases.iter().all(|case| !case.path.starts_with("/variants")));
+    }
+}
@@ -1,8 +1,8 @@
 //! Error types for the htsget protocol.
 //!
 //! This module defines error types that map directly to the htsget specification's
-//! error response format. All errors are automatically converted to appropriate
-//! HTTP responses with JSON bodies.
+//! error response format. With the `server` feature, all errors are
+//! automatically converted to appropriate HTTP responses with JSON bodies.
 //!
 //! # Error Types
 //!
@@ -15,8 +15,12 @@
 //! | `NotFound` | 404 | Resource doesn't exist |
 //! | `PayloadTooLarge` | 413 | Request exceeds limits |
 //! | `UnsupportedFormat` | 400 | Requested format unavailable |
+//! | `UnsupportedFormat` | 406 | `Accept` names an unsupported protocol version ([`Error::NotAcceptable`]) |
 //! | `InvalidInput` | 400 | Malformed request |
 //! | `InvalidRange` | 400 | Invalid genomic coordinates |
+//! | `InvalidRange` | 416 | Data byte range outside the file ([`Error::RangeNotSatisfiable`]) |
+//! | `Timeout` | 504 | Storage or request took too long |
+//! | `Unavailable` | 503 | Storage throttled or timed out after retries; sent with `Retry-After` |
 //!
 //! # Response Format
 //!
@@ -30,11 +34,27 @@
 //!   }
 //! }
 //! ```
+//!
+//! With the server's error detail option on, bodies also carry `retryable`
+//! (whether the same request may succeed later), `detail` (the message
+//! without its category prefix) and, when the request had an `X-Request-Id`
+//! header, `requestId`. The option is off by default so that strict clients
+//! see only the fields the spec defines.
+//!
+//! Internal and `Unavailable` errors can carry storage paths, bucket names
+//! and raw backend errors. They are logged in full and sent to clients with
+//! a generic message, unless the server is set to expose internal errors
+//! for debugging.
 
-use axum::http::StatusCode;
+#[cfg(feature = "server")]
+use axum::http::{HeaderValue, StatusCode, header};
+#[cfg(feature = "server")]
 use axum::response::{IntoResponse, Response};
 use serde::Serialize;
 
+/// Seconds clients are asked to wait before retrying an `Unavailable` request
+pub const RETRY_AFTER_SECS: u64 = 5;
+
 /// Result type alias using [`Error`].
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -55,12 +75,29 @@ pub enum Error {
     #[error("unsupported format: {0}")]
     UnsupportedFormat(String),
 
+    /// No media type in `Accept` can be served. Reported as htsget
+    /// `UnsupportedFormat`, with HTTP status 406.
+    #[error("not acceptable: {0}")]
+    NotAcceptable(String),
+
     #[error("invalid input: {0}")]
     InvalidInput(String),
 
     #[error("invalid range: {0}")]
     InvalidRange(String),
 
+    /// A byte range the data endpoint can't serve. Reported as htsget
+    /// `InvalidRange`, with HTTP status 416.
+    #[error("range not satisfiable: {0}")]
+    RangeNotSatisfiable(String),
+
+    #[error("timed out: {0}")]
+    Timeout(String),
+
+    /// Storage is throttling or not responding, even after retries
+    #[error("unavailable: {0}")]
+    Unavailable(String),
+
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -68,15 +105,50 @@ pub enum Error {
     Internal(String),
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct HtsgetError {
     pub htsget: HtsgetErrorBody,
 }
 
-#[derive(Debug, Serialize)]
+/// The htsget error body. `error` and `message` are the spec's; the rest
+/// are only sent with error detail on (see the module docs).
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct HtsgetErrorBody {
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
     pub error: &'static str,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retryable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(rename = "requestId", skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl HtsgetErrorBody {
+    /// The spec's fields only
+    pub fn new(error: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            error,
+            message: message.into(),
+            retryable: None,
+            detail: None,
+            request_id: None,
+        }
+    }
+}
+
+/// An error response's body with the optional fields filled in, attached to
+/// the response for the server's error layer to send instead of the
+/// spec-only body
+#[derive(Debug, Clone)]
+pub struct ErrorDetail {
+    /// The body as clients may see it, with `retryable` and `detail` set
+    pub error: HtsgetError,
+    /// Unsanitized message and detail, for errors sent with generic ones
+    pub internal: Option<(String, String)>,
 }
 
 impl Error {
@@ -86,13 +158,68 @@ impl Error {
             Error::PermissionDenied => "PermissionDenied",
             Error::NotFound(_) => "NotFound",
             Error::PayloadTooLarge => "PayloadTooLarge",
-            Error::UnsupportedFormat(_) => "UnsupportedFormat",
+            Error::UnsupportedFormat(_) | Error::NotAcceptable(_) => "UnsupportedFormat",
             Error::InvalidInput(_) => "InvalidInput",
-            Error::InvalidRange(_) => "InvalidRange",
+            Error::InvalidRange(_) | Error::RangeNotSatisfiable(_) => "InvalidRange",
+            Error::Timeout(_) => "Timeout",
+            Error::Unavailable(_) => "Unavailable",
             Error::Io(_) | Error::Internal(_) => "InternalError",
         }
     }
 
+    /// Whether the same request may succeed if retried later
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Error::Timeout(_) | Error::Unavailable(_))
+    }
+
+    /// The message without its category prefix, e.g. the ID of a
+    /// `NotFound` error
+    pub fn detail(&self) -> String {
+        match self {
+            Error::NotFound(detail)
+            | Error::UnsupportedFormat(detail)
+            | Error::NotAcceptable(detail)
+            | Error::InvalidInput(detail)
+            | Error::InvalidRange(detail)
+            | Error::RangeNotSatisfiable(detail)
+            | Error::Timeout(detail)
+            | Error::Unavailable(detail)
+            | Error::Internal(detail) => detail.clone(),
+            Error::Io(e) => e.to_string(),
+            Error::InvalidAuthentication | Error::PermissionDenied | Error::PayloadTooLarge => {
+                self.to_string()
+            }
+        }
+    }
+
+    /// Whether the message may reveal storage internals (paths, bucket
+    /// names, backend errors), so clients get a generic one
+    pub fn is_internal(&self) -> bool {
+        matches!(
+            self,
+            Error::Io(_) | Error::Internal(_) | Error::Unavailable(_)
+        )
+    }
+
+    /// [`detail`](Self::detail), or a generic one for internal errors
+    pub fn public_detail(&self) -> String {
+        match self {
+            Error::Io(_) | Error::Internal(_) => "see the server log".to_string(),
+            Error::Unavailable(_) => "storage is busy or not responding".to_string(),
+            _ => self.detail(),
+        }
+    }
+
+    /// The message clients are sent
+    pub fn public_message(&self) -> String {
+        match self {
+            Error::Io(_) | Error::Internal(_) => Error::Internal(self.public_detail()).to_string(),
+            Error::Unavailable(_) => Error::Unavailable(self.public_detail()).to_string(),
+            _ => self.to_string(),
+        }
+    }
+
+    #[cfg(feature = "server")]
     fn status_code(&self) -> StatusCode {
         match self {
             Error::InvalidAuthentication => StatusCode::UNAUTHORIZED,
@@ -100,26 +227,54 @@ impl Error {
             Error::NotFound(_) => StatusCode::NOT_FOUND,
             Error::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
             Error::UnsupportedFormat(_) => StatusCode::BAD_REQUEST,
+            Error::NotAcceptable(_) => StatusCode::NOT_ACCEPTABLE,
             Error::InvalidInput(_) => StatusCode::BAD_REQUEST,
             Error::InvalidRange(_) => StatusCode::BAD_REQUEST,
+            Error::RangeNotSatisfiable(_) => StatusCode::RANGE_NOT_SATISFIABLE,
+            Error::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            Error::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
             Error::Io(_) | Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
+#[cfg(feature = "server")]
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
+        match self {
+            Error::Unavailable(_) => tracing::warn!("{}", self),
+            Error::Io(_) | Error::Internal(_) => tracing::error!("{}", self),
+            _ => {}
+        }
+
         let body = HtsgetError {
-            htsget: HtsgetErrorBody {
-                error: self.error_type(),
-                message: self.to_string(),
+            htsget: HtsgetErrorBody::new(self.error_type(), self.public_message()),
+        };
+        let detail = ErrorDetail {
+            error: HtsgetError {
+                htsget: HtsgetErrorBody {
+                    retryable: Some(self.is_retryable()),
+                    detail: Some(self.public_detail()),
+                    ..body.htsget.clone()
+                },
             },
+            internal: self
+                .is_internal()
+                .then(|| (self.to_string(), self.detail())),
         };
-        (self.status_code(), axum::Json(body)).into_response()
+
+        let mut response = (self.status_code(), axum::Json(body)).into_response();
+        if let Error::Unavailable(_) = self {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+        }
+        response.extensions_mut().insert(detail);
+        response
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "server"))]
 mod tests {
     use super::*;
 
@@ -136,6 +291,10 @@ mod tests {
             Error::UnsupportedFormat("BAM".into()).error_type(),
             "UnsupportedFormat"
         );
+        assert_eq!(
+            Error::NotAcceptable("v2".into()).error_type(),
+            "UnsupportedFormat"
+        );
         assert_eq!(
             Error::InvalidInput("bad".into()).error_type(),
             "InvalidInput"
@@ -144,6 +303,15 @@ mod tests {
             Error::InvalidRange("0-100".into()).error_type(),
             "InvalidRange"
         );
+        assert_eq!(
+            Error::RangeNotSatisfiable("10-5".into()).error_type(),
+            "InvalidRange"
+        );
+        assert_eq!(Error::Timeout("GetObject".into()).error_type(), "Timeout");
+        assert_eq!(
+            Error::Unavailable("SlowDown".into()).error_type(),
+            "Unavailable"
+        );
         assert_eq!(Error::Internal("oops".into()).error_type(), "InternalError");
     }
 
@@ -166,6 +334,10 @@ mod tests {
             Error::UnsupportedFormat("x".into()).status_code(),
             StatusCode::BAD_REQUEST
         );
+        assert_eq!(
+            Error::NotAcceptable("x".into()).status_code(),
+            StatusCode::NOT_ACCEPTABLE
+        );
         assert_eq!(
             Error::InvalidInput("x".into()).status_code(),
             StatusCode::BAD_REQUEST
@@ -174,6 +346,18 @@ mod tests {
             Error::InvalidRange("x".into()).status_code(),
             StatusCode::BAD_REQUEST
         );
+        assert_eq!(
+            Error::RangeNotSatisfiable("x".into()).status_code(),
+            StatusCode::RANGE_NOT_SATISFIABLE
+        );
+        assert_eq!(
+            Error::Timeout("x".into()).status_code(),
+            StatusCode::GATEWAY_TIMEOUT
+        );
+        assert_eq!(
+            Error::Unavailable("x".into()).status_code(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
         assert_eq!(
             Error::Internal("x".into()).status_code(),
             StatusCode::INTERNAL_SERVER_ERROR
@@ -194,15 +378,73 @@ mod tests {
 
     #[test]
     fn test_htsget_error_serialization() {
+        let error = HtsgetError {
+            htsget: HtsgetErrorBody::new("NotFound", "not found: sample1"),
+        };
+        let json = serde_json::to_string(&error).unwrap();
+        assert_eq!(
+            json,
+            r#"{"htsget":{"error":"NotFound","message":"not found: sample1"}}"#
+        );
+
         let error = HtsgetError {
             htsget: HtsgetErrorBody {
-                error: "NotFound",
-                message: "not found: sample1".to_string(),
+                retryable: Some(false),
+                detail: Some("sample1".to_string()),
+                request_id: Some("abc".to_string()),
+                ..error.htsget
             },
         };
         let json = serde_json::to_string(&error).unwrap();
-        assert!(json.contains("\"error\":\"NotFound\""));
-        assert!(json.contains("\"message\":\"not found: sample1\""));
+        assert!(json.contains("\"retryable\":false"));
+        assert!(json.contains("\"detail\":\"sample1\""));
+        assert!(json.contains("\"requestId\":\"abc\""));
+    }
+
+    #[test]
+    fn test_error_response() {
+        let response = Error::Unavailable("S3 SlowDown".into()).into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "5");
+
+        let detail = response.extensions().get::<ErrorDetail>().unwrap();
+        assert_eq!(detail.error.htsget.error, "Unavailable");
+        assert_eq!(detail.error.htsget.retryable, Some(true));
+        assert_eq!(
+            detail.internal,
+            Some((
+                "unavailable: S3 SlowDown".to_string(),
+                "S3 SlowDown".to_string()
+            ))
+        );
+
+        let response = Error::NotFound("sample1".into()).into_response();
+        assert!(!response.headers().contains_key(header::RETRY_AFTER));
+        let detail = response.extensions().get::<ErrorDetail>().unwrap();
+        assert_eq!(detail.error.htsget.retryable, Some(false));
+        assert_eq!(detail.error.htsget.detail.as_deref(), Some("sample1"));
+        assert_eq!(detail.internal, None);
+    }
+
+    #[tokio::test]
+    async fn test_internal_errors_are_sanitized() {
+        let error = Error::Internal("S3 get_object failed for s3://bucket/key".into());
+        assert_eq!(error.public_message(), "internal error: see the server log");
+
+        let response = error.into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("\"error\":\"InternalError\""));
+        assert!(!body.contains("bucket"), "{}", body);
+
+        let error = Error::Io(std::io::Error::other("/srv/data/sample.bam"));
+        assert!(!error.public_message().contains("/srv"));
+        assert_eq!(
+            Error::NotFound("sample1".into()).public_message(),
+            "not found: sample1"
+        );
     }
 
     #[test]
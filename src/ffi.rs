@@ -0,0 +1,233 @@
+//! C ABI for index resolution.
+//!
+//! Lets htslib-based C/C++ tools resolve a region of a local file to byte
+//! ranges using htsgetr's index readers, without running a server. Enable with
+//! the `ffi` feature; the header is generated with cbindgen:
+//!
+//! ```bash
+//! cbindgen --config cbindgen.toml --crate htsgetr --output include/htsgetr.h
+//! ```
+//!
+//! ```c
+//! HtsgetrRanges *ranges = NULL;
+//! if (htsgetr_query_ranges("sample.bam", NULL, "chr1:1-100000", &ranges) != 0) {
+//!     fprintf(stderr, "%s\n", htsgetr_last_error());
+//!     return 1;
+//! }
+//! for (size_t i = 0; i < ranges->len; i++) { /* ranges->ranges[i] */ }
+//! htsgetr_free_ranges(ranges);
+//! ```
+
+use crate::formats::query_file;
+use crate::storage::ByteRange;
+use crate::types::{Format, Region};
+use crate::{Error, Result};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::path::Path;
+
+/// `end` value meaning "to the end of the file"
+pub const HTSGETR_END_OF_FILE: u64 = u64::MAX;
+
+/// Byte range `[start, end)`; `end` is [`HTSGETR_END_OF_FILE`] when open-ended
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HtsgetrByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl From<&ByteRange> for HtsgetrByteRange {
+    fn from(range: &ByteRange) -> Self {
+        Self {
+            start: range.start,
+            end: range.end.unwrap_or(HTSGETR_END_OF_FILE),
+        }
+    }
+}
+
+/// Result of [`htsgetr_query_ranges`]; release with [`htsgetr_free_ranges`]
+#[repr(C)]
+#[derive(Debug)]
+pub struct HtsgetrRanges {
+    pub header: HtsgetrByteRange,
+    pub ranges: *mut HtsgetrByteRange,
+    pub len: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Resolve `region` of the file at `path` to byte ranges.
+///
+/// `format` (e.g. `"BAM"`) and `region` (samtools-style, e.g. `"chr1:1-1000"`)
+/// may be NULL to auto-detect the format and select the whole file (reported
+/// as zero data ranges). The index is located next to the data file. On success
+/// returns 0 and stores a result in `*out` that must be released with
+/// [`htsgetr_free_ranges`]; on failure returns -1 and sets [`htsgetr_last_error`].
+///
+/// # Safety
+///
+/// `path` must be a valid NUL-terminated string, `format` and `region` must be
+/// NULL or valid NUL-terminated strings, and `out` must be a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn htsgetr_query_ranges(
+    path: *const c_char,
+    format: *const c_char,
+    region: *const c_char,
+    out: *mut *mut HtsgetrRanges,
+) -> c_int {
+    if path.is_null() || out.is_null() {
+        set_last_error("path and out must not be NULL".to_string());
+        return -1;
+    }
+
+    // SAFETY: pointers are non-null (or checked for null) and NUL-terminated per the contract
+    let (path, format, region) = unsafe {
+        (
+            CStr::from_ptr(path),
+            (!format.is_null()).then(|| CStr::from_ptr(format)),
+            (!region.is_null()).then(|| CStr::from_ptr(region)),
+        )
+    };
+
+    match query(path, format, region) {
+        Ok(ranges) => {
+            // SAFETY: out is non-null and valid for writes per the contract
+            unsafe { *out = Box::into_raw(Box::new(ranges)) };
+            0
+        }
+        Err(e) => {
+            set_last_error(e.to_string());
+            -1
+        }
+    }
+}
+
+/// Release a result returned by [`htsgetr_query_ranges`]. NULL is ignored.
+///
+/// # Safety
+///
+/// `ranges` must be NULL or a pointer returned by [`htsgetr_query_ranges`]
+/// that has not already been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn htsgetr_free_ranges(ranges: *mut HtsgetrRanges) {
+    if ranges.is_null() {
+        return;
+    }
+
+    // SAFETY: ranges and its array were allocated by htsgetr_query_ranges
+    unsafe {
+        let ranges = Box::from_raw(ranges);
+        if !ranges.ranges.is_null() {
+            drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+                ranges.ranges,
+                ranges.len,
+            )));
+        }
+    }
+}
+
+/// Message for the last error on this thread, or NULL if none.
+///
+/// The string is owned by htsgetr and valid until the next failing call on
+/// the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn htsgetr_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| {
+        e.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+fn query(path: &CStr, format: Option<&CStr>, region: Option<&CStr>) -> Result<HtsgetrRanges> {
+    let to_str = |s: &CStr| {
+        s.to_str()
+            .map_err(|_| Error::InvalidInput("argument is not valid UTF-8".to_string()))
+    };
+
+    let path = Path::new(to_str(path)?);
+    let format: Option<Format> = format
+        .map(to_str)
+        .transpose()?
+        .map(str::parse)
+        .transpose()?;
+    let regions: Vec<Region> = region
+        .map(to_str)
+        .transpose()?
+        .map(str::parse)
+        .transpose()?
+        .into_iter()
+        .collect();
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let (_, indexed) = runtime.block_on(query_file(path, format, None, &regions))?;
+
+    let ranges: Box<[HtsgetrByteRange]> = indexed.data_ranges.iter().map(Into::into).collect();
+    let len = ranges.len();
+
+    Ok(HtsgetrRanges {
+        header: (&indexed.header_range).into(),
+        ranges: Box::into_raw(ranges).cast(),
+        len,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_and_free() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/mt.bam\0");
+        if !Path::new(path.trim_end_matches('\0')).exists() {
+            return;
+        }
+
+        let mut out: *mut HtsgetrRanges = std::ptr::null_mut();
+        let status = unsafe {
+            htsgetr_query_ranges(
+                path.as_ptr().cast(),
+                std::ptr::null(),
+                std::ptr::null(),
+                &mut out,
+            )
+        };
+        assert_eq!(status, 0);
+        assert!(!out.is_null());
+
+        // Without a region no data ranges are returned; callers read the whole file
+        let ranges = unsafe { &*out };
+        assert_eq!(ranges.len, 0);
+        assert!(ranges.header.end > ranges.header.start);
+
+        unsafe { htsgetr_free_ranges(out) };
+    }
+
+    #[test]
+    fn test_error_reporting() {
+        let mut out: *mut HtsgetrRanges = std::ptr::null_mut();
+        let status = unsafe {
+            htsgetr_query_ranges(
+                c"missing.txt".as_ptr(),
+                std::ptr::null(),
+                std::ptr::null(),
+                &mut out,
+            )
+        };
+        assert_eq!(status, -1);
+        assert!(out.is_null());
+
+        let message = unsafe { CStr::from_ptr(htsgetr_last_error()) };
+        assert!(message.to_str().unwrap().contains("cannot detect format"));
+    }
+}
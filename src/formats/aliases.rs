@@ -0,0 +1,233 @@
+//! Reference name aliases: `1` and `chr1`, `MT` and `chrM`.
+//!
+//! Files name the same reference sequence differently depending on where
+//! they came from (Ensembl, UCSC, RefSeq). [`ReferenceAliases`] matches a
+//! requested name to the one a file declares: exactly if it can, then
+//! ignoring case, then through sets of equivalent names. The built-in sets
+//! cover the GRCh37 and GRCh38 chromosomes; more can be read from an alias
+//! file with one set of equivalent names per line.
+
+use crate::types::Region;
+use crate::{Error, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// RefSeq accession versions of chromosomes 1-22, X and Y in GRCh37
+const GRCH37_VERSIONS: [u8; 24] = [
+    10, 11, 11, 11, 9, 11, 13, 10, 11, 10, 9, 11, 10, 8, 9, 9, 10, 9, 9, 10, 8, 10, 10, 9,
+];
+
+/// RefSeq accession versions of chromosomes 1-22, X and Y in GRCh38
+const GRCH38_VERSIONS: [u8; 24] = [
+    11, 12, 12, 12, 10, 12, 14, 11, 12, 11, 10, 12, 11, 9, 10, 10, 11, 10, 10, 11, 9, 11, 11, 10,
+];
+
+/// Sets of equivalent reference names, matched ignoring case.
+#[derive(Debug, Clone, Default)]
+pub struct ReferenceAliases {
+    sets: Vec<Vec<String>>,
+    /// Lowercase name to its set
+    index: HashMap<String, usize>,
+}
+
+impl ReferenceAliases {
+    /// Case-insensitive matching only, with no aliases
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The GRCh37 and GRCh38 chromosomes: `1`, `chr1`, `NC_000001.10` and
+    /// `NC_000001.11` are one set, as are `MT`, `M`, `chrM` and `chrMT`.
+    pub fn builtin() -> Self {
+        let chromosomes = (1..=24).map(|n: usize| {
+            let name = match n {
+                23 => "X".to_string(),
+                24 => "Y".to_string(),
+                n => n.to_string(),
+            };
+            vec![
+                format!("chr{}", name),
+                format!("NC_{:06}.{}", n, GRCH37_VERSIONS[n - 1]),
+                format!("NC_{:06}.{}", n, GRCH38_VERSIONS[n - 1]),
+                name,
+            ]
+        });
+        let mitochondrion = ["MT", "M", "chrM", "chrMT", "NC_012920.1"].map(String::from);
+
+        chromosomes
+            .chain([mitochondrion.to_vec()])
+            .fold(Self::new(), Self::with_aliases)
+    }
+
+    /// Add a set of equivalent names. Sets sharing a name are merged.
+    pub fn with_aliases<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let names: Vec<String> = names.into_iter().map(Into::into).collect();
+        let set = names
+            .iter()
+            .find_map(|name| self.index.get(&name.to_lowercase()).copied())
+            .unwrap_or_else(|| {
+                self.sets.push(Vec::new());
+                self.sets.len() - 1
+            });
+
+        for name in names {
+            match self.index.get(&name.to_lowercase()).copied() {
+                Some(existing) if existing == set => {}
+                Some(existing) => {
+                    // Merge the other set into this one
+                    for moved in std::mem::take(&mut self.sets[existing]) {
+                        self.index.insert(moved.to_lowercase(), set);
+                        self.sets[set].push(moved);
+                    }
+                }
+                None => {
+                    self.index.insert(name.to_lowercase(), set);
+                    self.sets[set].push(name);
+                }
+            }
+        }
+        self
+    }
+
+    /// Add the sets in an alias file: one set of equivalent names per line,
+    /// separated by tabs or spaces. Blank lines and `#` comments are skipped.
+    pub fn parse(self, s: &str) -> Result<Self> {
+        s.lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .try_fold(self, |aliases, (i, line)| {
+                let names: Vec<&str> = line.split_whitespace().collect();
+                if names.len() < 2 {
+                    return Err(Error::InvalidInput(format!(
+                        "invalid reference alias line {}: expected at least two names",
+                        i + 1
+                    )));
+                }
+                Ok(aliases.with_aliases(names))
+            })
+    }
+
+    /// Add the sets in the alias file at `path` (see [`parse`](Self::parse))
+    pub fn with_file(self, path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        self.parse(&contents)
+    }
+
+    /// Of a file's reference sequence `names`, the one `name` refers to:
+    /// an exact match, else one differing only in case, else an alias.
+    pub fn resolve<'a>(&self, name: &str, names: &'a [String]) -> Option<&'a str> {
+        let lower = name.to_lowercase();
+        let aliases = self
+            .index
+            .get(&lower)
+            .map(|&set| self.sets[set].as_slice())
+            .unwrap_or_default();
+
+        names
+            .iter()
+            .find(|candidate| *candidate == name)
+            .or_else(|| {
+                names
+                    .iter()
+                    .find(|candidate| candidate.to_lowercase() == lower)
+            })
+            .or_else(|| {
+                names.iter().find(|candidate| {
+                    aliases
+                        .iter()
+                        .any(|alias| alias.eq_ignore_ascii_case(candidate))
+                })
+            })
+            .map(String::as_str)
+    }
+
+    /// `regions` with their reference names replaced by those the file
+    /// declares. Names that match nothing are left for the index lookup to
+    /// report as not found.
+    pub fn resolve_regions(&self, regions: &[Region], names: &[String]) -> Vec<Region> {
+        regions
+            .iter()
+            .map(|region| match self.resolve(&region.reference_name, names) {
+                Some(name) => Region {
+                    reference_name: name.to_string(),
+                    ..region.clone()
+                },
+                None => region.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_resolve_builtin() {
+        let aliases = ReferenceAliases::builtin();
+        let ucsc = names(&["chr1", "chr2", "chrX", "chrM"]);
+        let ensembl = names(&["1", "2", "X", "MT"]);
+        let refseq = names(&["NC_000001.11", "NC_000023.11", "NC_012920.1"]);
+
+        assert_eq!(aliases.resolve("chr1", &ucsc), Some("chr1"));
+        assert_eq!(aliases.resolve("1", &ucsc), Some("chr1"));
+        assert_eq!(aliases.resolve("CHRX", &ucsc), Some("chrX"));
+        assert_eq!(aliases.resolve("MT", &ucsc), Some("chrM"));
+        assert_eq!(aliases.resolve("chrM", &ensembl), Some("MT"));
+        assert_eq!(aliases.resolve("chr2", &ensembl), Some("2"));
+        assert_eq!(aliases.resolve("NC_000001.10", &ensembl), Some("1"));
+        assert_eq!(aliases.resolve("x", &refseq), Some("NC_000023.11"));
+        assert_eq!(aliases.resolve("chrM", &refseq), Some("NC_012920.1"));
+
+        assert_eq!(aliases.resolve("chr3", &ucsc), None);
+        assert_eq!(aliases.resolve("scaffold_1", &ensembl), None);
+    }
+
+    #[test]
+    fn test_exact_match_wins() {
+        let aliases = ReferenceAliases::builtin();
+        let both = names(&["1", "chr1"]);
+        assert_eq!(aliases.resolve("1", &both), Some("1"));
+        assert_eq!(aliases.resolve("chr1", &both), Some("chr1"));
+
+        let cased = names(&["chrun_1", "chrUn_1"]);
+        assert_eq!(
+            ReferenceAliases::new().resolve("chrUn_1", &cased),
+            Some("chrUn_1")
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let aliases = ReferenceAliases::new()
+            .parse("# contig aliases\nctg1\tscaffold_1  \n\nctg2 scaffold_2 # second\nscaffold_2 seq2\n")
+            .unwrap();
+        let file = names(&["seq2", "ctg1"]);
+        assert_eq!(aliases.resolve("scaffold_1", &file), Some("ctg1"));
+        // Merged through scaffold_2
+        assert_eq!(aliases.resolve("ctg2", &file), Some("seq2"));
+
+        assert!(ReferenceAliases::new().parse("lonely\n").is_err());
+    }
+
+    #[test]
+    fn test_resolve_regions() {
+        let regions = vec![
+            Region::new("1", Some(10), None).unwrap(),
+            Region::new("unknown", None, Some(10)).unwrap(),
+        ];
+        let resolved = ReferenceAliases::builtin().resolve_regions(&regions, &names(&["chr1"]));
+        assert_eq!(resolved[0].reference_name, "chr1");
+        assert_eq!(resolved[0].start, Some(10));
+        assert_eq!(resolved[1].reference_name, "unknown");
+    }
+}
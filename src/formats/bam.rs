@@ -1,18 +1,29 @@
-use super::IndexedRanges;
+use super::header::{self, HeaderParsing, RawHeader};
+use super::{IndexReader, IndexedRanges, blocks, region_interval};
 use crate::storage::ByteRange;
-use crate::types::Region;
+use crate::types::{Format, Region};
 use crate::{Error, Result};
+use async_trait::async_trait;
 use noodles::bam;
 use noodles::bam::bai;
-use noodles::core::Position;
-use noodles::core::region::Interval;
+use noodles::bgzf;
+use noodles::bgzf::VirtualPosition;
 use noodles::csi::binning_index::BinningIndex;
 use noodles::csi::binning_index::index::reference_sequence::bin::Chunk;
 use noodles::sam;
+use noodles::sam::header::ReferenceSequences;
+use noodles::sam::header::record::value::{Map, map::ReferenceSequence};
+use std::num::NonZeroUsize;
 use std::path::Path;
 use tokio::fs::File;
+use tokio::io::AsyncReadExt;
 
-pub struct BamIndexReader;
+/// BAM index reader. Its static functions parse headers strictly; as an
+/// [`IndexReader`] it parses them as `header_parsing` says.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BamIndexReader {
+    pub header_parsing: HeaderParsing,
+}
 
 impl BamIndexReader {
     /// Read BAI/CSI index and compute byte ranges for given regions
@@ -21,6 +32,16 @@ impl BamIndexReader {
         index_path: &Path,
         regions: &[Region],
         header: &sam::Header,
+    ) -> Result<IndexedRanges> {
+        Self::query(bam_path, index_path, regions, header, HeaderParsing::Strict).await
+    }
+
+    async fn query(
+        bam_path: &Path,
+        index_path: &Path,
+        regions: &[Region],
+        header: &sam::Header,
+        parsing: HeaderParsing,
     ) -> Result<IndexedRanges> {
         // Read the BAI index
         let index = bai::r#async::read(index_path)
@@ -28,7 +49,7 @@ impl BamIndexReader {
             .map_err(|e| Error::Internal(format!("failed to read BAI index: {}", e)))?;
 
         // Compute header byte range
-        let header_range = Self::header_range(bam_path).await?;
+        let header_range = Self::header_range_with(bam_path, parsing).await?;
 
         // If no regions specified, return empty data_ranges (caller should serve whole file)
         if regions.is_empty() {
@@ -53,23 +74,11 @@ impl BamIndexReader {
                     ))
                 })?;
 
-            // Build interval from region coordinates
-            // htsget uses 0-based half-open coordinates, noodles uses 1-based closed
-            let start = region
-                .start
-                .map(|s| Position::try_from(s as usize + 1))
-                .transpose()
-                .map_err(|e| Error::InvalidRange(format!("invalid start position: {}", e)))?
-                .unwrap_or(Position::MIN);
-
-            let end = region
-                .end
-                .map(|e| Position::try_from(e as usize))
-                .transpose()
-                .map_err(|e| Error::InvalidRange(format!("invalid end position: {}", e)))?
-                .unwrap_or(Position::MAX);
-
-            let interval = Interval::from(start..=end);
+            // An empty region (e.g. `end=0`) covers no records
+            if region.is_empty() {
+                continue;
+            }
+            let interval = region_interval(region)?;
 
             // Query the index for chunks overlapping this region
             let region_chunks = index
@@ -79,17 +88,15 @@ impl BamIndexReader {
             chunks.extend(region_chunks);
         }
 
-        // Convert chunks to byte ranges
-        let mut data_ranges: Vec<ByteRange> = chunks
-            .into_iter()
-            .map(|chunk| ByteRange {
-                start: chunk.start().compressed(),
-                end: Some(chunk.end().compressed()),
-            })
-            .collect();
+        // Convert chunks to block-aligned byte ranges
+        let mut data_ranges = blocks::chunk_ranges(bam_path, &chunks).await?;
 
         // Merge overlapping/adjacent ranges for efficiency
         data_ranges = Self::merge_ranges(data_ranges);
+        // Records sharing the header's last block come with the header
+        if let Some(header_end) = header_range.end {
+            data_ranges = blocks::after_header(data_ranges, header_end);
+        }
 
         Ok(IndexedRanges {
             header_range,
@@ -97,8 +104,35 @@ impl BamIndexReader {
         })
     }
 
-    /// Compute the header byte range by reading the BAM file
+    /// Virtual position just past the BAM header
+    pub async fn header_end(bam_path: &Path) -> Result<VirtualPosition> {
+        let (_, end) = Self::parse_header(bam_path, HeaderParsing::Strict).await?;
+        Ok(end)
+    }
+
+    /// Compute the header byte range by reading the BAM file. The range
+    /// runs to the end of the header's last block so it decompresses standalone
     pub async fn header_range(bam_path: &Path) -> Result<ByteRange> {
+        Self::header_range_with(bam_path, HeaderParsing::Strict).await
+    }
+
+    async fn header_range_with(bam_path: &Path, parsing: HeaderParsing) -> Result<ByteRange> {
+        let (_, header_end) = Self::parse_header(bam_path, parsing).await?;
+        blocks::header_range(bam_path, header_end).await
+    }
+
+    /// Read the BAM header from a file
+    pub async fn read_header(bam_path: &Path) -> Result<sam::Header> {
+        let (header, _) = Self::parse_header(bam_path, HeaderParsing::Strict).await?;
+        Ok(header)
+    }
+
+    /// Read the BAM header, and the virtual position just past it. A header
+    /// noodles can't parse is diagnosed, or recovered, per `parsing`.
+    pub async fn parse_header(
+        bam_path: &Path,
+        parsing: HeaderParsing,
+    ) -> Result<(sam::Header, VirtualPosition)> {
         let file = File::open(bam_path)
             .await
             .map_err(|e| Error::Internal(format!("failed to open BAM file: {}", e)))?;
@@ -106,38 +140,87 @@ impl BamIndexReader {
         // bam::Reader::new wraps the file in a BGZF reader internally - don't double-wrap
         let mut reader = bam::r#async::io::Reader::new(file);
 
-        // Read header to advance position past it
-        reader
-            .read_header()
-            .await
-            .map_err(|e| Error::Internal(format!("failed to read BAM header: {}", e)))?;
-
-        // Get the virtual position after the header
-        let header_end = reader.get_ref().virtual_position();
-
-        Ok(ByteRange {
-            start: 0,
-            end: Some(header_end.compressed()),
-        })
+        match reader.read_header().await {
+            Ok(header) => Ok((header, reader.get_ref().virtual_position())),
+            Err(e) if header::is_parse_error(&e) => {
+                let (raw, reference_sequences) = Self::raw_header(bam_path).await?;
+                let mut header = header::recover(
+                    Format::Bam,
+                    bam_path,
+                    parsing,
+                    &raw.text,
+                    header::parse_sam_lines,
+                    e,
+                )?;
+                // Records refer to the binary dictionary, whatever the text says
+                if !header
+                    .reference_sequences()
+                    .keys()
+                    .eq(reference_sequences.keys())
+                {
+                    *header.reference_sequences_mut() = reference_sequences;
+                }
+                Ok((header, raw.end))
+            }
+            Err(e) => Err(header::read_error(Format::Bam, bam_path, "header", e)),
+        }
     }
 
-    /// Read the BAM header from a file
-    pub async fn read_header(bam_path: &Path) -> Result<sam::Header> {
+    /// The header as stored: its SAM text, unparsed, and the binary
+    /// reference sequence dictionary
+    async fn raw_header(bam_path: &Path) -> Result<(RawHeader, ReferenceSequences)> {
+        let error = |e| header::read_error(Format::Bam, bam_path, "header", e);
+        let invalid = |message: &str| {
+            error(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                message,
+            ))
+        };
+
         let file = File::open(bam_path)
             .await
             .map_err(|e| Error::Internal(format!("failed to open BAM file: {}", e)))?;
+        let mut reader = bgzf::r#async::Reader::new(file);
 
-        // bam::Reader::new wraps the file in a BGZF reader internally - don't double-wrap
-        let mut reader = bam::r#async::io::Reader::new(file);
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic).await.map_err(error)?;
+        if &magic != b"BAM\x01" {
+            return Err(invalid("invalid BAM magic number"));
+        }
 
-        reader
-            .read_header()
+        let l_text = reader.read_u32_le().await.map_err(error)?;
+        let mut text = Vec::new();
+        (&mut reader)
+            .take(u64::from(l_text))
+            .read_to_end(&mut text)
             .await
-            .map_err(|e| Error::Internal(format!("failed to read BAM header: {}", e)))
+            .map_err(error)?;
+
+        let n_ref = reader.read_u32_le().await.map_err(error)?;
+        let mut reference_sequences = ReferenceSequences::default();
+        for _ in 0..n_ref {
+            let l_name = reader.read_u32_le().await.map_err(error)?;
+            let mut name = Vec::new();
+            (&mut reader)
+                .take(u64::from(l_name))
+                .read_to_end(&mut name)
+                .await
+                .map_err(error)?;
+            if name.last() == Some(&0) {
+                name.pop();
+            }
+            let l_ref = reader.read_u32_le().await.map_err(error)?;
+            let length = NonZeroUsize::new(l_ref as usize)
+                .ok_or_else(|| invalid("reference sequence of length 0"))?;
+            reference_sequences.insert(name.into(), Map::<ReferenceSequence>::new(length));
+        }
+
+        let end = reader.virtual_position();
+        Ok((RawHeader { text, end }, reference_sequences))
     }
 
     /// Merge overlapping or adjacent byte ranges
-    fn merge_ranges(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    pub fn merge_ranges(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
         if ranges.is_empty() {
             return ranges;
         }
@@ -169,6 +252,37 @@ impl BamIndexReader {
     }
 }
 
+#[async_trait]
+impl IndexReader for BamIndexReader {
+    type Header = sam::Header;
+
+    async fn read_header(&self, path: &Path) -> Result<Option<sam::Header>> {
+        let (header, _) = BamIndexReader::parse_header(path, self.header_parsing).await?;
+        Ok(Some(header))
+    }
+
+    async fn header_range(&self, path: &Path) -> Result<ByteRange> {
+        BamIndexReader::header_range_with(path, self.header_parsing).await
+    }
+
+    async fn query_ranges(
+        &self,
+        path: &Path,
+        index_path: &Path,
+        regions: &[Region],
+        header: Option<&sam::Header>,
+    ) -> Result<IndexedRanges> {
+        let parsing = self.header_parsing;
+        match header {
+            Some(header) => BamIndexReader::query(path, index_path, regions, header, parsing).await,
+            None => {
+                let (header, _) = BamIndexReader::parse_header(path, parsing).await?;
+                BamIndexReader::query(path, index_path, regions, &header, parsing).await
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,18 +1,25 @@
-use super::IndexedRanges;
+use super::header::{self, HeaderParsing, RawHeader};
+use super::{IndexReader, IndexedRanges, blocks, region_interval};
 use crate::storage::ByteRange;
-use crate::types::Region;
+use crate::types::{Format, Region};
 use crate::{Error, Result};
+use async_trait::async_trait;
 use noodles::bcf;
 use noodles::bgzf;
-use noodles::core::Position;
-use noodles::core::region::Interval;
+use noodles::bgzf::VirtualPosition;
 use noodles::csi;
 use noodles::csi::binning_index::BinningIndex;
 use noodles::csi::binning_index::index::reference_sequence::bin::Chunk;
 use std::path::Path;
 use tokio::fs::File;
+use tokio::io::AsyncReadExt;
 
-pub struct BcfIndexReader;
+/// BCF index reader. Its static functions parse headers strictly; as an
+/// [`IndexReader`] it parses them as `header_parsing` says.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BcfIndexReader {
+    pub header_parsing: HeaderParsing,
+}
 
 impl BcfIndexReader {
     /// Read CSI index and compute byte ranges for given regions
@@ -20,14 +27,24 @@ impl BcfIndexReader {
         bcf_path: &Path,
         index_path: &Path,
         regions: &[Region],
+    ) -> Result<IndexedRanges> {
+        Self::query(bcf_path, index_path, regions, HeaderParsing::Strict).await
+    }
+
+    async fn query(
+        bcf_path: &Path,
+        index_path: &Path,
+        regions: &[Region],
+        parsing: HeaderParsing,
     ) -> Result<IndexedRanges> {
         // Read the CSI index
         let index = csi::r#async::read(index_path)
             .await
             .map_err(|e| Error::Internal(format!("failed to read CSI index: {}", e)))?;
 
-        // Compute header byte range
-        let header_range = Self::header_range(bcf_path).await?;
+        // Read header for the byte range and the reference sequence mapping
+        let (header, header_end) = Self::parse_header(bcf_path, parsing).await?;
+        let header_range = blocks::header_range(bcf_path, header_end).await?;
 
         // If no regions specified, return empty data_ranges (caller should serve whole file)
         if regions.is_empty() {
@@ -37,9 +54,6 @@ impl BcfIndexReader {
             });
         }
 
-        // Read header to get reference sequence mapping
-        let header = Self::read_header(bcf_path).await?;
-
         // Query index for each region
         let mut chunks: Vec<Chunk> = Vec::new();
 
@@ -55,22 +69,11 @@ impl BcfIndexReader {
                     ))
                 })?;
 
-            // Build interval from region coordinates
-            let start = region
-                .start
-                .map(|s| Position::try_from(s as usize + 1))
-                .transpose()
-                .map_err(|e| Error::InvalidRange(format!("invalid start position: {}", e)))?
-                .unwrap_or(Position::MIN);
-
-            let end = region
-                .end
-                .map(|e| Position::try_from(e as usize))
-                .transpose()
-                .map_err(|e| Error::InvalidRange(format!("invalid end position: {}", e)))?
-                .unwrap_or(Position::MAX);
-
-            let interval = Interval::from(start..=end);
+            // An empty region (e.g. `end=0`) covers no records
+            if region.is_empty() {
+                continue;
+            }
+            let interval = region_interval(region)?;
 
             // Query the index for chunks overlapping this region
             let region_chunks = index
@@ -80,17 +83,15 @@ impl BcfIndexReader {
             chunks.extend(region_chunks);
         }
 
-        // Convert chunks to byte ranges
-        let mut data_ranges: Vec<ByteRange> = chunks
-            .into_iter()
-            .map(|chunk| ByteRange {
-                start: chunk.start().compressed(),
-                end: Some(chunk.end().compressed()),
-            })
-            .collect();
+        // Convert chunks to block-aligned byte ranges
+        let mut data_ranges = blocks::chunk_ranges(bcf_path, &chunks).await?;
 
         // Merge overlapping/adjacent ranges
         data_ranges = Self::merge_ranges(data_ranges);
+        // Records sharing the header's last block come with the header
+        if let Some(header_end) = header_range.end {
+            data_ranges = blocks::after_header(data_ranges, header_end);
+        }
 
         Ok(IndexedRanges {
             header_range,
@@ -98,41 +99,88 @@ impl BcfIndexReader {
         })
     }
 
-    /// Compute the header byte range by reading the BCF file
+    /// Virtual position just past the BCF header
+    pub async fn header_end(bcf_path: &Path) -> Result<VirtualPosition> {
+        let (_, end) = Self::parse_header(bcf_path, HeaderParsing::Strict).await?;
+        Ok(end)
+    }
+
+    /// Compute the header byte range by reading the BCF file. The range
+    /// runs to the end of the header's last block so it decompresses standalone
     pub async fn header_range(bcf_path: &Path) -> Result<ByteRange> {
+        Self::header_range_with(bcf_path, HeaderParsing::Strict).await
+    }
+
+    async fn header_range_with(bcf_path: &Path, parsing: HeaderParsing) -> Result<ByteRange> {
+        let (_, header_end) = Self::parse_header(bcf_path, parsing).await?;
+        blocks::header_range(bcf_path, header_end).await
+    }
+
+    /// Read the BCF header
+    pub async fn read_header(bcf_path: &Path) -> Result<noodles::vcf::Header> {
+        let (header, _) = Self::parse_header(bcf_path, HeaderParsing::Strict).await?;
+        Ok(header)
+    }
+
+    /// Read the BCF header, and the virtual position just past it. A header
+    /// noodles can't parse is diagnosed, or recovered, per `parsing`.
+    pub async fn parse_header(
+        bcf_path: &Path,
+        parsing: HeaderParsing,
+    ) -> Result<(noodles::vcf::Header, VirtualPosition)> {
         let file = File::open(bcf_path)
             .await
             .map_err(|e| Error::Internal(format!("failed to open BCF file: {}", e)))?;
 
         let mut reader = bcf::r#async::io::Reader::new(bgzf::r#async::Reader::new(file));
 
-        // Read header to advance position past it
-        reader
-            .read_header()
-            .await
-            .map_err(|e| Error::Internal(format!("failed to read BCF header: {}", e)))?;
-
-        // Get the virtual position after the header
-        let header_end = reader.get_ref().virtual_position();
-
-        Ok(ByteRange {
-            start: 0,
-            end: Some(header_end.compressed()),
-        })
+        match reader.read_header().await {
+            Ok(header) => Ok((header, reader.get_ref().virtual_position())),
+            Err(e) if header::is_parse_error(&e) => {
+                let raw = Self::raw_header(bcf_path).await?;
+                let header = header::recover(
+                    Format::Bcf,
+                    bcf_path,
+                    parsing,
+                    &raw.text,
+                    header::parse_vcf_lines,
+                    e,
+                )?;
+                Ok((header, raw.end))
+            }
+            Err(e) => Err(header::read_error(Format::Bcf, bcf_path, "header", e)),
+        }
     }
 
-    /// Read the BCF header
-    pub async fn read_header(bcf_path: &Path) -> Result<noodles::vcf::Header> {
+    /// The header's VCF text as stored, unparsed
+    async fn raw_header(bcf_path: &Path) -> Result<RawHeader> {
+        let error = |e| header::read_error(Format::Bcf, bcf_path, "header", e);
+
         let file = File::open(bcf_path)
             .await
             .map_err(|e| Error::Internal(format!("failed to open BCF file: {}", e)))?;
+        let mut reader = bgzf::r#async::Reader::new(file);
+
+        // Magic number and format version
+        let mut magic = [0; 5];
+        reader.read_exact(&mut magic).await.map_err(error)?;
+        if &magic[..4] != b"BCF\x02" {
+            return Err(error(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "invalid BCF magic number",
+            )));
+        }
 
-        let mut reader = bcf::r#async::io::Reader::new(bgzf::r#async::Reader::new(file));
-
-        reader
-            .read_header()
+        let l_text = reader.read_u32_le().await.map_err(error)?;
+        let mut text = Vec::new();
+        (&mut reader)
+            .take(u64::from(l_text))
+            .read_to_end(&mut text)
             .await
-            .map_err(|e| Error::Internal(format!("failed to read BCF header: {}", e)))
+            .map_err(error)?;
+
+        let end = reader.virtual_position();
+        Ok(RawHeader { text, end })
     }
 
     /// Merge overlapping or adjacent byte ranges
@@ -167,3 +215,22 @@ impl BcfIndexReader {
         merged
     }
 }
+
+#[async_trait]
+impl IndexReader for BcfIndexReader {
+    type Header = ();
+
+    async fn header_range(&self, path: &Path) -> Result<ByteRange> {
+        BcfIndexReader::header_range_with(path, self.header_parsing).await
+    }
+
+    async fn query_ranges(
+        &self,
+        path: &Path,
+        index_path: &Path,
+        regions: &[Region],
+        _header: Option<&()>,
+    ) -> Result<IndexedRanges> {
+        BcfIndexReader::query(path, index_path, regions, self.header_parsing).await
+    }
+}
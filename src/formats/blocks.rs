@@ -0,0 +1,266 @@
+//! BGZF block boundaries for byte ranges built from virtual positions.
+//!
+//! A virtual position's compressed offset is the start of the block it falls
+//! in, so a range ending there stops short of the bytes before the position
+//! within that block. Slices must end on block boundaries to decompress.
+//!
+//! Headers can also be re-compressed into blocks of their own (see
+//! [`standalone_header`]), which end exactly where the header does.
+
+use crate::storage::ByteRange;
+use crate::{Error, Result};
+use noodles::bgzf::{self, VirtualPosition};
+use noodles::csi::binning_index::index::reference_sequence::bin::Chunk;
+use std::collections::HashMap;
+use std::io::{SeekFrom, Write};
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt};
+
+/// Size of a BGZF block header up to and including `BSIZE`
+const BLOCK_HEADER_LEN: usize = 18;
+
+/// Compressed offset just past the BGZF block starting at `block_start`.
+pub(crate) async fn block_end(path: &Path, block_start: u64) -> Result<u64> {
+    let mut file = File::open(path).await?;
+    read_block_end(&mut file, block_start).await
+}
+
+async fn read_block_end(file: &mut File, block_start: u64) -> Result<u64> {
+    file.seek(SeekFrom::Start(block_start)).await?;
+
+    let mut header = [0; BLOCK_HEADER_LEN];
+    file.read_exact(&mut header).await?;
+    block_size(&header)
+        .map(|size| block_start + size)
+        .ok_or_else(|| Error::Internal(format!("no BGZF block at offset {}", block_start)))
+}
+
+/// Byte ranges for index chunks, aligned to block boundaries.
+///
+/// A chunk starts at a record, whose block start is its compressed offset,
+/// but its end usually falls partway into a block: the range then runs to
+/// the end of that block so the chunk's last records are whole. Block
+/// starts are record boundaries in files written by htslib and noodles,
+/// which don't split records across blocks.
+pub(crate) async fn chunk_ranges(path: &Path, chunks: &[Chunk]) -> Result<Vec<ByteRange>> {
+    let mut file = File::open(path).await?;
+    // Chunks of overlapping regions often end in the same block
+    let mut block_ends = HashMap::new();
+    let mut ranges = Vec::with_capacity(chunks.len());
+
+    for chunk in chunks {
+        let end = chunk.end();
+        let end = if end.uncompressed() == 0 {
+            end.compressed()
+        } else if let Some(&block_end) = block_ends.get(&end.compressed()) {
+            block_end
+        } else {
+            let block_end = read_block_end(&mut file, end.compressed()).await?;
+            block_ends.insert(end.compressed(), block_end);
+            block_end
+        };
+
+        ranges.push(ByteRange {
+            start: chunk.start().compressed(),
+            end: Some(end),
+        });
+    }
+
+    Ok(ranges)
+}
+
+/// Byte range of a header ending at virtual position `end`, extended to the
+/// end of the block the header ends in. That block may also hold the first
+/// records, which the range then carries (see [`after_header`]).
+pub(crate) async fn header_range(path: &Path, end: VirtualPosition) -> Result<ByteRange> {
+    let end = if end.uncompressed() == 0 {
+        end.compressed()
+    } else {
+        block_end(path, end.compressed()).await?
+    };
+
+    Ok(ByteRange {
+        start: 0,
+        end: Some(end),
+    })
+}
+
+/// Trim sorted data ranges to start after the header range, which already
+/// carries the records sharing the header's last block.
+pub(crate) fn after_header(ranges: Vec<ByteRange>, header_end: u64) -> Vec<ByteRange> {
+    ranges
+        .into_iter()
+        .filter(|range| range.end.is_none_or(|end| end > header_end))
+        .map(|range| ByteRange {
+            start: range.start.max(header_end),
+            end: range.end,
+        })
+        .collect()
+}
+
+/// The uncompressed bytes before virtual position `end`, re-compressed into
+/// BGZF blocks that hold nothing else. No EOF marker is appended, so body
+/// slices can follow.
+pub(crate) async fn standalone_header(path: &Path, end: VirtualPosition) -> Result<Vec<u8>> {
+    let mut reader = bgzf::r#async::Reader::new(File::open(path).await?);
+    let mut header = Vec::new();
+
+    loop {
+        // Position of the loaded block, so it is read after filling
+        let available = reader.fill_buf().await?.len();
+        let position = reader.virtual_position();
+        let last = position.compressed() >= end.compressed();
+
+        let len = if last {
+            usize::from(end.uncompressed()).saturating_sub(usize::from(position.uncompressed()))
+        } else {
+            available
+        };
+        if len > available || (available == 0 && !last) {
+            return Err(Error::Internal(format!(
+                "file ends before header end at {}",
+                end.compressed()
+            )));
+        }
+
+        header.extend_from_slice(&reader.fill_buf().await?[..len]);
+        reader.consume(len);
+
+        if last {
+            break;
+        }
+    }
+
+    let mut writer = bgzf::io::Writer::new(Vec::new());
+    writer.write_all(&header)?;
+    writer.flush()?;
+    Ok(writer.get_ref().clone())
+}
+
+/// Total block size from a block header: the gzip magic with the `BC` extra
+/// subfield first, as every BGZF writer emits it.
+fn block_size(header: &[u8; BLOCK_HEADER_LEN]) -> Option<u64> {
+    let is_bgzf = header[..4] == [0x1f, 0x8b, 0x08, 0x04] && header[12..14] == *b"BC";
+    is_bgzf.then(|| u64::from(u16::from_le_bytes([header[16], header[17]])) + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use noodles::bgzf;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_after_header() {
+        let ranges = vec![
+            ByteRange {
+                start: 0,
+                end: Some(100),
+            },
+            ByteRange {
+                start: 150,
+                end: Some(300),
+            },
+            ByteRange {
+                start: 400,
+                end: None,
+            },
+        ];
+
+        let ranges = after_header(ranges, 200);
+        assert_eq!(ranges.len(), 2);
+        assert_eq!((ranges[0].start, ranges[0].end), (200, Some(300)));
+        assert_eq!((ranges[1].start, ranges[1].end), (400, None));
+    }
+
+    #[tokio::test]
+    async fn test_header_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocks.gz");
+
+        let mut writer = bgzf::io::Writer::new(std::fs::File::create(&path).unwrap());
+        writer.write_all(b"header").unwrap();
+        writer.flush().unwrap();
+        let first_block_end = writer.virtual_position().compressed();
+        writer.write_all(b"more").unwrap();
+        writer.finish().unwrap();
+
+        // Mid-block: rounded up to the block end
+        let position = VirtualPosition::try_from((0, 3)).unwrap();
+        let range = header_range(&path, position).await.unwrap();
+        assert_eq!(range.end, Some(first_block_end));
+
+        // On a boundary: unchanged
+        let position = VirtualPosition::try_from((first_block_end, 0)).unwrap();
+        let range = header_range(&path, position).await.unwrap();
+        assert_eq!(range.end, Some(first_block_end));
+
+        assert!(matches!(block_end(&path, 1).await, Err(Error::Internal(_))));
+    }
+
+    #[tokio::test]
+    async fn test_chunk_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocks.gz");
+
+        let mut writer = bgzf::io::Writer::new(std::fs::File::create(&path).unwrap());
+        writer.write_all(b"first").unwrap();
+        writer.flush().unwrap();
+        let first_block_end = writer.virtual_position().compressed();
+        writer.write_all(b"second").unwrap();
+        writer.flush().unwrap();
+        let second_block_end = writer.virtual_position().compressed();
+        writer.finish().unwrap();
+
+        let position = |compressed, uncompressed| {
+            VirtualPosition::try_from((compressed, uncompressed)).unwrap()
+        };
+        let chunks = [
+            // Ends partway into the second block
+            Chunk::new(position(0, 2), position(first_block_end, 3)),
+            // Ends on a block boundary
+            Chunk::new(position(first_block_end, 0), position(second_block_end, 0)),
+        ];
+
+        let ranges = chunk_ranges(&path, &chunks).await.unwrap();
+        assert_eq!(
+            (ranges[0].start, ranges[0].end),
+            (0, Some(second_block_end))
+        );
+        assert_eq!(
+            (ranges[1].start, ranges[1].end),
+            (first_block_end, Some(second_block_end))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_standalone_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocks.gz");
+
+        let mut writer = bgzf::io::Writer::new(std::fs::File::create(&path).unwrap());
+        writer.write_all(b"head").unwrap();
+        writer.flush().unwrap();
+        let first_block_end = writer.virtual_position().compressed();
+        writer.write_all(b"er|record").unwrap();
+        writer.finish().unwrap();
+
+        // The header ends partway into the second block
+        let end = VirtualPosition::try_from((first_block_end, 2)).unwrap();
+        let compressed = standalone_header(&path, end).await.unwrap();
+
+        let mut header = Vec::new();
+        bgzf::io::Reader::new(&compressed[..])
+            .read_to_end(&mut header)
+            .unwrap();
+        assert_eq!(header, b"header");
+
+        // Past the end of the file
+        let end = VirtualPosition::try_from((first_block_end, 100)).unwrap();
+        assert!(matches!(
+            standalone_header(&path, end).await,
+            Err(Error::Internal(_))
+        ));
+    }
+}
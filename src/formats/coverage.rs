@@ -0,0 +1,250 @@
+//! Approximate read coverage over a region.
+//!
+//! Small regions are decoded: every primary, non-duplicate, QC-passing
+//! alignment adds its reference span to the windows it overlaps. Regions
+//! whose index chunks exceed [`MAX_DECODE_BYTES`] are estimated instead, from
+//! the share of the reference's compressed bytes each window maps to (see
+//! [`super::stats`]) and the mean span of the region's first reads.
+
+use super::stats::{covered_bytes, estimate_records, reference_not_found, windows};
+use super::{BamIndexReader, interval};
+use crate::types::{Coverage, CoverageBin, CoverageMethod, Format, Region};
+use crate::{Error, Result};
+use futures::TryStreamExt;
+use noodles::bam;
+use noodles::bam::bai;
+use noodles::csi::binning_index::BinningIndex;
+use noodles::sam;
+use noodles::sam::alignment::Record as _;
+use std::path::Path;
+use tokio::fs::File;
+
+/// Compressed bytes of index chunks up to which a region is decoded rather
+/// than estimated from the index
+pub const MAX_DECODE_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Reads sampled for the mean read span of an estimate
+const SPAN_SAMPLE_SIZE: usize = 1000;
+
+/// Coverage of `region` in a BAM file across `bins` equal windows.
+pub async fn read_coverage(
+    path: &Path,
+    index_path: &Path,
+    region: &Region,
+    bins: usize,
+) -> Result<Coverage> {
+    let index = bai::r#async::read(index_path)
+        .await
+        .map_err(|e| Error::Internal(format!("failed to read BAI index: {}", e)))?;
+    let header = BamIndexReader::read_header(path).await?;
+
+    let (reference_sequence_id, _, reference_sequence) = header
+        .reference_sequences()
+        .get_full(region.reference_name.as_bytes())
+        .ok_or_else(|| reference_not_found(region))?;
+    let length = usize::from(reference_sequence.length()) as u64;
+
+    let windows = windows(region, Some(length), bins)?;
+    let (start, end) = (windows[0].0, windows[windows.len() - 1].1);
+    let chunks = index
+        .query(reference_sequence_id, interval(start, Some(end))?)
+        .map_err(|e| Error::Internal(format!("index query failed: {}", e)))?;
+
+    let (method, bins) = if covered_bytes(chunks) <= MAX_DECODE_BYTES {
+        let counts = decode(path, &header, &index, region, &windows, None).await?;
+        (CoverageMethod::Records, counts.bins(&windows))
+    } else {
+        let (_, reads) = estimate_records(&index, reference_sequence_id, &windows)?;
+        let sample = decode(
+            path,
+            &header,
+            &index,
+            region,
+            &windows,
+            Some(SPAN_SAMPLE_SIZE),
+        )
+        .await?;
+        let mean_span = sample.mean_span();
+
+        let bins = windows
+            .iter()
+            .zip(reads)
+            .map(|(&(start, end), reads)| CoverageBin {
+                start,
+                end,
+                reads,
+                mean_depth: reads as f64 * mean_span / (end - start) as f64,
+            })
+            .collect();
+        (CoverageMethod::Index, bins)
+    };
+
+    Ok(Coverage {
+        format: Format::Bam,
+        reference_name: region.reference_name.clone(),
+        method,
+        bins,
+    })
+}
+
+/// Reads and aligned bases per window
+#[derive(Debug, Default)]
+struct Counts {
+    reads: Vec<u64>,
+    bases: Vec<u64>,
+    /// Reads seen and the sum of their reference spans
+    total_reads: u64,
+    total_span: u64,
+}
+
+impl Counts {
+    fn new(windows: usize) -> Self {
+        Self {
+            reads: vec![0; windows],
+            bases: vec![0; windows],
+            ..Default::default()
+        }
+    }
+
+    /// Count an alignment covering the 0-based half-open `read_start..read_end`.
+    fn add(&mut self, windows: &[(u64, u64)], read_start: u64, read_end: u64) {
+        self.total_reads += 1;
+        self.total_span += read_end - read_start;
+
+        // Windows are sorted and contiguous
+        let first = windows.partition_point(|&(_, end)| end <= read_start);
+        for (i, &(start, end)) in windows.iter().enumerate().skip(first) {
+            if start >= read_end {
+                break;
+            }
+            self.reads[i] += 1;
+            self.bases[i] += read_end.min(end) - read_start.max(start);
+        }
+    }
+
+    fn mean_span(&self) -> f64 {
+        if self.total_reads == 0 {
+            0.0
+        } else {
+            self.total_span as f64 / self.total_reads as f64
+        }
+    }
+
+    fn bins(self, windows: &[(u64, u64)]) -> Vec<CoverageBin> {
+        windows
+            .iter()
+            .zip(self.reads.into_iter().zip(self.bases))
+            .map(|(&(start, end), (reads, bases))| CoverageBin {
+                start,
+                end,
+                reads,
+                mean_depth: bases as f64 / (end - start) as f64,
+            })
+            .collect()
+    }
+}
+
+/// Whether an alignment counts toward depth, as in `samtools depth`
+fn counted(flags: sam::alignment::record::Flags) -> bool {
+    !(flags.is_unmapped() || flags.is_secondary() || flags.is_qc_fail() || flags.is_duplicate())
+}
+
+/// Decode the alignments overlapping `windows`, stopping after `limit`
+/// counted reads.
+async fn decode(
+    path: &Path,
+    header: &sam::Header,
+    index: &bai::Index,
+    region: &Region,
+    windows: &[(u64, u64)],
+    limit: Option<usize>,
+) -> Result<Counts> {
+    let file = File::open(path)
+        .await
+        .map_err(|e| Error::Internal(format!("failed to open BAM file: {}", e)))?;
+    // bam::Reader::new wraps the file in a BGZF reader internally - don't double-wrap
+    let mut reader = bam::r#async::io::Reader::new(file);
+
+    let (start, end) = (windows[0].0, windows[windows.len() - 1].1);
+    let query_region =
+        noodles::core::Region::new(region.reference_name.as_str(), interval(start, Some(end))?);
+    let mut records = reader
+        .query(header, index, &query_region)
+        .map_err(|e| Error::Internal(format!("BAM query failed: {}", e)))?;
+
+    let decode_error = |e: std::io::Error| Error::Internal(format!("failed to decode BAM: {}", e));
+    let mut counts = Counts::new(windows.len());
+    while let Some(record) = records.try_next().await.map_err(decode_error)? {
+        if !counted(record.flags()) {
+            continue;
+        }
+        let (Some(alignment_start), Some(span)) =
+            (record.alignment_start(), record.alignment_span())
+        else {
+            continue;
+        };
+        let read_start = usize::from(alignment_start.map_err(decode_error)?) as u64 - 1;
+        let span = span.map_err(decode_error)? as u64;
+
+        counts.add(windows, read_start, read_start + span);
+        if limit.is_some_and(|limit| counts.total_reads as usize >= limit) {
+            break;
+        }
+    }
+
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts() {
+        let windows = [(0, 100), (100, 200), (200, 250)];
+        let mut counts = Counts::new(windows.len());
+        counts.add(&windows, 50, 150);
+        counts.add(&windows, 180, 230);
+        counts.add(&windows, 0, 10);
+
+        assert_eq!(counts.reads, vec![2, 2, 1]);
+        assert_eq!(counts.bases, vec![60, 70, 30]);
+        assert_eq!(counts.mean_span(), 160.0 / 3.0);
+
+        let bins = counts.bins(&windows);
+        assert_eq!(bins[0].mean_depth, 0.6);
+        assert_eq!(bins[2].mean_depth, 0.6);
+    }
+
+    #[tokio::test]
+    async fn test_read_coverage() {
+        let data = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+        let path = data.join("sample.bam");
+        let header = BamIndexReader::read_header(&path).await.unwrap();
+        let name = header
+            .reference_sequences()
+            .keys()
+            .next()
+            .unwrap()
+            .to_string();
+
+        let region = Region {
+            reference_name: name,
+            start: None,
+            end: None,
+        };
+        let coverage = read_coverage(&path, &data.join("sample.bam.bai"), &region, 10)
+            .await
+            .unwrap();
+
+        assert_eq!(coverage.method, CoverageMethod::Records);
+        assert_eq!(coverage.bins.len(), 10);
+        assert_eq!(coverage.bins[0].start, 0);
+        assert!(
+            coverage
+                .bins
+                .iter()
+                .all(|bin| bin.mean_depth >= 0.0 && bin.end > bin.start)
+        );
+    }
+}
@@ -1,9 +1,10 @@
-use super::IndexedRanges;
+use super::header::{self, HeaderParsing};
+use super::{IndexReader, IndexedRanges, region_interval};
 use crate::storage::ByteRange;
-use crate::types::Region;
+use crate::types::{Format, Region};
 use crate::{Error, Result};
+use async_trait::async_trait;
 use noodles::core::Position;
-use noodles::core::region::Interval;
 use noodles::cram;
 use noodles::cram::crai;
 use noodles::sam;
@@ -11,7 +12,12 @@ use std::path::Path;
 use tokio::fs::File;
 use tokio::io::AsyncSeekExt;
 
-pub struct CramIndexReader;
+/// CRAM index reader. Its static functions parse headers strictly; as an
+/// [`IndexReader`] it parses them as `header_parsing` says.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CramIndexReader {
+    pub header_parsing: HeaderParsing,
+}
 
 impl CramIndexReader {
     /// Read CRAI index and compute byte ranges for given regions
@@ -19,6 +25,15 @@ impl CramIndexReader {
         cram_path: &Path,
         index_path: &Path,
         regions: &[Region],
+    ) -> Result<IndexedRanges> {
+        Self::query(cram_path, index_path, regions, HeaderParsing::Strict).await
+    }
+
+    async fn query(
+        cram_path: &Path,
+        index_path: &Path,
+        regions: &[Region],
+        parsing: HeaderParsing,
     ) -> Result<IndexedRanges> {
         // Read the CRAI index
         let index = crai::r#async::read(index_path)
@@ -37,7 +52,7 @@ impl CramIndexReader {
         }
 
         // Read header to get reference sequence mapping
-        let header = Self::read_header(cram_path).await?;
+        let header = Self::parse_header(cram_path, parsing).await?;
         let ref_seqs = header.reference_sequences();
 
         // Query index for each region
@@ -54,22 +69,12 @@ impl CramIndexReader {
                     ))
                 })?;
 
-            // Build interval from region coordinates
-            let start = region
-                .start
-                .map(|s| Position::try_from(s as usize + 1))
-                .transpose()
-                .map_err(|e| Error::InvalidRange(format!("invalid start position: {}", e)))?
-                .unwrap_or(Position::MIN);
-
-            let end = region
-                .end
-                .map(|e| Position::try_from(e as usize))
-                .transpose()
-                .map_err(|e| Error::InvalidRange(format!("invalid end position: {}", e)))?
-                .unwrap_or(Position::MAX);
-
-            let _interval = Interval::from(start..=end);
+            if region.is_empty() {
+                continue;
+            }
+            let interval = region_interval(region)?;
+            let start = interval.start().unwrap_or(Position::MIN);
+            let end = interval.end().unwrap_or(Position::MAX);
 
             // Find containers that overlap the region
             // CRAI records have: reference_sequence_id, alignment_start, alignment_span, offset, slice_offset, slice_length
@@ -137,13 +142,13 @@ impl CramIndexReader {
         reader
             .read_file_definition()
             .await
-            .map_err(|e| Error::Internal(format!("failed to read CRAM file definition: {}", e)))?;
+            .map_err(|e| header::read_error(Format::Cram, cram_path, "file definition", e))?;
 
         // Read header container to find where data starts
         reader
             .read_file_header()
             .await
-            .map_err(|e| Error::Internal(format!("failed to read CRAM header: {}", e)))?;
+            .map_err(|e| header::read_error(Format::Cram, cram_path, "header container", e))?;
 
         // Get actual byte position after reading header
         let header_end =
@@ -159,6 +164,12 @@ impl CramIndexReader {
 
     /// Read the CRAM header (SAM header)
     pub async fn read_header(cram_path: &Path) -> Result<sam::Header> {
+        Self::parse_header(cram_path, HeaderParsing::Strict).await
+    }
+
+    /// Read the CRAM header. A header noodles can't parse is diagnosed, or
+    /// recovered, per `parsing`.
+    pub async fn parse_header(cram_path: &Path, parsing: HeaderParsing) -> Result<sam::Header> {
         let file = File::open(cram_path)
             .await
             .map_err(|e| Error::Internal(format!("failed to open CRAM file: {}", e)))?;
@@ -169,18 +180,26 @@ impl CramIndexReader {
         reader
             .read_file_definition()
             .await
-            .map_err(|e| Error::Internal(format!("failed to read CRAM file definition: {}", e)))?;
+            .map_err(|e| header::read_error(Format::Cram, cram_path, "file definition", e))?;
 
         // Read SAM header as string and parse it
         let header_str = reader
             .read_file_header()
             .await
-            .map_err(|e| Error::Internal(format!("failed to read CRAM header: {}", e)))?;
+            .map_err(|e| header::read_error(Format::Cram, cram_path, "header container", e))?;
 
         // Parse the header string into a sam::Header
-        header_str
-            .parse()
-            .map_err(|e| Error::Internal(format!("failed to parse CRAM header: {}", e)))
+        match header_str.parse() {
+            Ok(header) => Ok(header),
+            Err(e) => header::recover(
+                Format::Cram,
+                cram_path,
+                parsing,
+                header_str.as_bytes(),
+                header::parse_sam_lines,
+                std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+            ),
+        }
     }
 
     /// Merge overlapping or adjacent byte ranges
@@ -215,3 +234,22 @@ impl CramIndexReader {
         merged
     }
 }
+
+#[async_trait]
+impl IndexReader for CramIndexReader {
+    type Header = ();
+
+    async fn header_range(&self, path: &Path) -> Result<ByteRange> {
+        CramIndexReader::header_range(path).await
+    }
+
+    async fn query_ranges(
+        &self,
+        path: &Path,
+        index_path: &Path,
+        regions: &[Region],
+        _header: Option<&()>,
+    ) -> Result<IndexedRanges> {
+        CramIndexReader::query(path, index_path, regions, self.header_parsing).await
+    }
+}
@@ -1,9 +1,10 @@
-use super::IndexedRanges;
+use super::{GziIndex, IndexReader, IndexedRanges};
 use crate::storage::ByteRange;
 use crate::types::Region;
 use crate::{Error, Result};
+use async_trait::async_trait;
 use noodles::fasta::fai;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub struct FastaIndexReader;
 
@@ -65,8 +66,12 @@ impl FastaIndexReader {
             let line_width = record.line_width() as u64;
 
             // Calculate byte range for the requested region
-            let start_base = region.start.unwrap_or(0);
-            let end_base = region.end.unwrap_or(seq_length).min(seq_length);
+            let (start_base, end_base) = region.bounds();
+            let end_base = end_base.unwrap_or(seq_length).min(seq_length);
+            if start_base >= end_base {
+                // Empty, or past the end of the sequence
+                continue;
+            }
 
             // Convert base coordinates to byte offsets
             // Each line has line_bases bases and line_width bytes
@@ -91,6 +96,32 @@ impl FastaIndexReader {
         })
     }
 
+    /// Compute compressed byte ranges for regions of a BGZF-compressed FASTA.
+    ///
+    /// FAI offsets of a `.fa.gz` refer to the uncompressed stream, so the
+    /// uncompressed ranges are translated through the GZI index into ranges
+    /// covering whole BGZF blocks.
+    pub async fn query_ranges_bgzf(
+        fasta_path: &Path,
+        index_path: &Path,
+        gzi_path: &Path,
+        regions: &[Region],
+    ) -> Result<IndexedRanges> {
+        let gzi = GziIndex::read(gzi_path).await?;
+        let uncompressed = Self::query_ranges(fasta_path, index_path, regions).await?;
+
+        let data_ranges = uncompressed
+            .data_ranges
+            .into_iter()
+            .map(|r| gzi.compressed_range(r.start, r.end.unwrap_or(u64::MAX)))
+            .collect();
+
+        Ok(IndexedRanges {
+            header_range: uncompressed.header_range,
+            data_ranges: Self::merge_ranges(data_ranges),
+        })
+    }
+
     /// Get header byte range for FASTA (there is no header)
     pub async fn header_range(_fasta_path: &Path) -> Result<ByteRange> {
         // FASTA files don't have a header in the htsget sense
@@ -117,7 +148,7 @@ impl FastaIndexReader {
             let current_end = current.end.unwrap_or(u64::MAX);
 
             // Check if ranges overlap or are adjacent
-            if range.start <= current_end + 1 {
+            if range.start <= current_end.saturating_add(1) {
                 // Extend current range
                 current.end = match (current.end, range.end) {
                     (Some(a), Some(b)) => Some(a.max(b)),
@@ -133,3 +164,46 @@ impl FastaIndexReader {
         merged
     }
 }
+
+#[async_trait]
+impl IndexReader for FastaIndexReader {
+    type Header = ();
+
+    /// Regions are located through the FAI; there is no header
+    fn has_header(&self) -> bool {
+        false
+    }
+
+    async fn header_range(&self, path: &Path) -> Result<ByteRange> {
+        FastaIndexReader::header_range(path).await
+    }
+
+    /// BGZF-compressed FASTA (`.fa.gz`) needs the `.gzi` next to it to map
+    /// FAI offsets to blocks
+    fn can_query(&self, path: &Path) -> bool {
+        !is_bgzf(path) || gzi_path(path).exists()
+    }
+
+    async fn query_ranges(
+        &self,
+        path: &Path,
+        index_path: &Path,
+        regions: &[Region],
+        _header: Option<&()>,
+    ) -> Result<IndexedRanges> {
+        if is_bgzf(path) {
+            FastaIndexReader::query_ranges_bgzf(path, index_path, &gzi_path(path), regions).await
+        } else {
+            FastaIndexReader::query_ranges(path, index_path, regions).await
+        }
+    }
+}
+
+fn is_bgzf(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+/// Path of the GZI index of a BGZF FASTA
+fn gzi_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.gzi", path.display()))
+}
@@ -1,9 +1,17 @@
-use super::IndexedRanges;
+use super::{IndexReader, IndexedRanges};
 use crate::storage::ByteRange;
 use crate::types::Region;
 use crate::{Error, Result};
-use std::path::Path;
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use tokio::fs;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// Number of records between offset checkpoints in the `.fqi` sidecar index
+pub const RECORD_INDEX_STRIDE: u64 = 1000;
+
+const RECORD_INDEX_MAGIC: &[u8; 4] = b"FQI\x01";
 
 /// FASTQ format reader.
 ///
@@ -42,6 +50,58 @@ impl FastqIndexReader {
         })
     }
 
+    /// Compute the byte range for a record-count slice (`?records=start-end`).
+    ///
+    /// Uses the `.fqi` sidecar offset index, building it on demand. Ranges are
+    /// widened to [`RECORD_INDEX_STRIDE`] boundaries so they always start and end
+    /// on a record, returning a superset of the requested records.
+    pub async fn record_range(fastq_path: &Path, records: &RecordRange) -> Result<ByteRange> {
+        let index = Self::record_index(fastq_path).await?;
+        index.byte_range(records)
+    }
+
+    /// Load the `.fqi` sidecar index, (re)building it if missing or stale.
+    pub async fn record_index(fastq_path: &Path) -> Result<FastqRecordIndex> {
+        if fastq_path.extension().is_some_and(|ext| ext == "gz") {
+            return Err(Error::InvalidInput(
+                "record slicing requires an uncompressed FASTQ file".to_string(),
+            ));
+        }
+
+        let sidecar = Self::record_index_path(fastq_path);
+
+        if let (Ok(data_meta), Ok(idx_meta)) =
+            (fs::metadata(fastq_path).await, fs::metadata(&sidecar).await)
+        {
+            let fresh = match (data_meta.modified(), idx_meta.modified()) {
+                (Ok(data), Ok(idx)) => idx >= data,
+                _ => false,
+            };
+
+            if fresh {
+                let bytes = fs::read(&sidecar).await?;
+                match FastqRecordIndex::from_bytes(&bytes) {
+                    Ok(index) => return Ok(index),
+                    Err(e) => tracing::warn!("rebuilding invalid FASTQ index {:?}: {}", sidecar, e),
+                }
+            }
+        }
+
+        let index = FastqRecordIndex::build(fastq_path, RECORD_INDEX_STRIDE).await?;
+
+        // A read-only data directory shouldn't fail the request
+        if let Err(e) = fs::write(&sidecar, index.to_bytes()).await {
+            tracing::warn!("failed to write FASTQ index {:?}: {}", sidecar, e);
+        }
+
+        Ok(index)
+    }
+
+    /// Path of the sidecar record index (e.g., `sample.fq.fqi`)
+    pub fn record_index_path(fastq_path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.fqi", fastq_path.display()))
+    }
+
     /// Get whole file as the only data range
     pub async fn whole_file_range(fastq_path: &Path) -> Result<ByteRange> {
         let metadata = fs::metadata(fastq_path)
@@ -55,6 +115,220 @@ impl FastqIndexReader {
     }
 }
 
+#[async_trait]
+impl IndexReader for FastqIndexReader {
+    type Header = ();
+
+    /// FASTQ has no header
+    fn has_header(&self) -> bool {
+        false
+    }
+
+    async fn header_range(&self, path: &Path) -> Result<ByteRange> {
+        FastqIndexReader::header_range(path).await
+    }
+
+    async fn record_range(&self, path: &Path, records: &RecordRange) -> Result<ByteRange> {
+        FastqIndexReader::record_range(path, records).await
+    }
+
+    /// FASTQ has no index; `index_path` is ignored
+    async fn query_ranges(
+        &self,
+        path: &Path,
+        _index_path: &Path,
+        regions: &[Region],
+        _header: Option<&()>,
+    ) -> Result<IndexedRanges> {
+        FastqIndexReader::query_ranges(path, regions).await
+    }
+}
+
+/// Half-open, 0-based range of FASTQ records (e.g., `0-1000000` or `500-`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordRange {
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+impl FromStr for RecordRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidInput(format!("invalid records range: {}", s));
+
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let start = start.trim().parse().map_err(|_| invalid())?;
+        let end = match end.trim() {
+            "" => None,
+            e => Some(e.parse().map_err(|_| invalid())?),
+        };
+
+        if end.is_some_and(|e| e <= start) {
+            return Err(Error::InvalidRange(format!(
+                "records end must be greater than start: {}",
+                s
+            )));
+        }
+
+        Ok(Self { start, end })
+    }
+}
+
+/// Sidecar offset index for uncompressed FASTQ files.
+///
+/// Stores the byte offset of every `stride`-th record plus the file length.
+/// Serialized as the magic `FQI\x01` followed by little-endian `u64` stride,
+/// record count, offset count and offsets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FastqRecordIndex {
+    stride: u64,
+    record_count: u64,
+    /// Checkpoint offsets; the final entry is the end of the last record
+    offsets: Vec<u64>,
+}
+
+impl FastqRecordIndex {
+    /// Scan a FASTQ file and record checkpoint offsets.
+    ///
+    /// Assumes standard four-line records.
+    pub async fn build(fastq_path: &Path, stride: u64) -> Result<Self> {
+        let file = fs::File::open(fastq_path)
+            .await
+            .map_err(|e| Error::Internal(format!("failed to open FASTQ file: {}", e)))?;
+        let mut reader = BufReader::new(file);
+
+        let stride = stride.max(1);
+        let mut offsets = Vec::new();
+        let mut record_count = 0u64;
+        let mut offset = 0u64;
+        let mut line = Vec::new();
+
+        loop {
+            let record_start = offset;
+            let mut lines = 0;
+
+            while lines < 4 {
+                line.clear();
+                let n = reader.read_until(b'\n', &mut line).await?;
+                if n == 0 {
+                    break;
+                }
+                offset += n as u64;
+                lines += 1;
+            }
+
+            match lines {
+                0 => break,
+                4 => {
+                    if record_count % stride == 0 {
+                        offsets.push(record_start);
+                    }
+                    record_count += 1;
+                }
+                _ => {
+                    return Err(Error::InvalidInput(format!(
+                        "truncated FASTQ record at byte {}",
+                        record_start
+                    )));
+                }
+            }
+        }
+
+        offsets.push(offset);
+
+        Ok(Self {
+            stride,
+            record_count,
+            offsets,
+        })
+    }
+
+    /// Total number of records in the file
+    pub fn record_count(&self) -> u64 {
+        self.record_count
+    }
+
+    /// Byte range covering the requested records, widened to checkpoint boundaries.
+    pub fn byte_range(&self, records: &RecordRange) -> Result<ByteRange> {
+        let end = records
+            .end
+            .unwrap_or(self.record_count)
+            .min(self.record_count);
+
+        if records.start >= end {
+            return Err(Error::InvalidRange(format!(
+                "records {}-{} outside file with {} records",
+                records.start, end, self.record_count
+            )));
+        }
+
+        let first = (records.start / self.stride) as usize;
+        let last = (end.div_ceil(self.stride) as usize).min(self.offsets.len().saturating_sub(1));
+
+        match (self.offsets.get(first), self.offsets.get(last)) {
+            (Some(&start), Some(&end)) => Ok(ByteRange {
+                start,
+                end: Some(end),
+            }),
+            _ => Err(Error::InvalidInput(format!(
+                "FASTQ record index has no checkpoint for records {}-{}",
+                records.start, end
+            ))),
+        }
+    }
+
+    /// Serialize to the `.fqi` on-disk format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(28 + self.offsets.len() * 8);
+        bytes.extend_from_slice(RECORD_INDEX_MAGIC);
+        bytes.extend_from_slice(&self.stride.to_le_bytes());
+        bytes.extend_from_slice(&self.record_count.to_le_bytes());
+        bytes.extend_from_slice(&(self.offsets.len() as u64).to_le_bytes());
+        for offset in &self.offsets {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Parse the `.fqi` on-disk format
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let invalid = || Error::Internal("invalid FASTQ record index".to_string());
+
+        if bytes.get(..4) != Some(RECORD_INDEX_MAGIC.as_slice()) {
+            return Err(invalid());
+        }
+
+        let mut values = bytes[4..]
+            .chunks(8)
+            .map(|c| c.try_into().map(u64::from_le_bytes).map_err(|_| invalid()));
+        let mut next = || values.next().unwrap_or_else(|| Err(invalid()));
+
+        let stride = next()?;
+        let record_count = next()?;
+        let n = next()?;
+
+        // One checkpoint per `stride` records, then the end of the file
+        if stride == 0 || record_count.div_ceil(stride).checked_add(1) != Some(n) {
+            return Err(invalid());
+        }
+        if n.checked_mul(8).and_then(|len| len.checked_add(28)) != Some(bytes.len() as u64) {
+            return Err(invalid());
+        }
+
+        let offsets = (0..n).map(|_| next()).collect::<Result<Vec<_>>>()?;
+        if !offsets.is_sorted() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            stride,
+            record_count,
+            offsets,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -68,4 +342,89 @@ mod tests {
         assert_eq!(range.start, 0);
         assert_eq!(range.end, Some(0));
     }
+
+    #[test]
+    fn test_record_range_parsing() {
+        assert_eq!(
+            "0-1000".parse::<RecordRange>().unwrap(),
+            RecordRange {
+                start: 0,
+                end: Some(1000)
+            }
+        );
+        assert_eq!(
+            "500-".parse::<RecordRange>().unwrap(),
+            RecordRange {
+                start: 500,
+                end: None
+            }
+        );
+        assert!("abc".parse::<RecordRange>().is_err());
+        assert!("10-5".parse::<RecordRange>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_record_index_build_and_slice() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reads.fq");
+
+        // 5 records of 15 bytes each
+        let record = "@r\nACGT\n+\nIIII\n";
+        std::fs::write(&path, record.repeat(5)).unwrap();
+
+        let index = FastqRecordIndex::build(&path, 2).await.unwrap();
+        assert_eq!(index.record_count(), 5);
+
+        let range = index
+            .byte_range(&RecordRange {
+                start: 1,
+                end: Some(3),
+            })
+            .unwrap();
+        assert_eq!(range.start, 0);
+        assert_eq!(range.end, Some(60));
+
+        let range = index
+            .byte_range(&RecordRange {
+                start: 4,
+                end: None,
+            })
+            .unwrap();
+        assert_eq!(range.start, 60);
+        assert_eq!(range.end, Some(75));
+
+        assert!(
+            index
+                .byte_range(&RecordRange {
+                    start: 5,
+                    end: None
+                })
+                .is_err()
+        );
+
+        let round_trip = FastqRecordIndex::from_bytes(&index.to_bytes()).unwrap();
+        assert_eq!(round_trip, index);
+    }
+
+    #[test]
+    fn test_record_index_rejects_corrupt_sidecar() {
+        let sidecar = |stride: u64, record_count: u64, n: u64, offsets: &[u64]| {
+            let mut bytes = RECORD_INDEX_MAGIC.to_vec();
+            for value in [stride, record_count, n].iter().chain(offsets) {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes
+        };
+
+        assert!(FastqRecordIndex::from_bytes(&sidecar(2, 5, 4, &[0, 30, 60, 75])).is_ok());
+        // No offsets at all
+        assert!(FastqRecordIndex::from_bytes(&sidecar(2, 5, 0, &[])).is_err());
+        // Stale: fewer checkpoints than the record count needs
+        assert!(FastqRecordIndex::from_bytes(&sidecar(2, 5, 2, &[0, 75])).is_err());
+        // `28 + n * 8` overflows
+        let n = u64::MAX.div_ceil(2) + 1;
+        assert!(FastqRecordIndex::from_bytes(&sidecar(2, u64::MAX, n, &[0])).is_err());
+        // Offsets out of order
+        assert!(FastqRecordIndex::from_bytes(&sidecar(2, 5, 4, &[0, 60, 30, 75])).is_err());
+    }
 }
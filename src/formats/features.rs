@@ -0,0 +1,257 @@
+//! Records of a region as genome browser features (requires `explore`
+//! feature).
+//!
+//! Features follow igv.js's JSON feature format: 0-based, half-open `start`
+//! and `end` on reference `chr`, plus the fields a track needs to draw
+//! alignments or variants. Records are decoded up to a limit, so a browser
+//! zoomed out too far gets the first features of the region rather than a
+//! response the size of the file.
+
+use super::region_interval;
+use super::stats::reference_not_found;
+use super::{BamIndexReader, VcfIndexReader};
+use crate::types::{ReadFeature, Region, VariantFeature};
+use crate::{Error, Result};
+use futures::TryStreamExt;
+use noodles::bam;
+use noodles::bam::bai;
+use noodles::bgzf;
+use noodles::sam::alignment::Record as _;
+use noodles::sam::alignment::record::cigar::op::Kind;
+use noodles::tabix;
+use noodles::vcf;
+use std::path::Path;
+use tokio::fs::File;
+
+/// Upper bound on features per request
+pub const MAX_FEATURES: usize = 10_000;
+
+/// Alignments overlapping `region` of a BAM file, up to `limit`.
+pub async fn read_features(
+    path: &Path,
+    index_path: &Path,
+    region: &Region,
+    limit: usize,
+) -> Result<Vec<ReadFeature>> {
+    let index = bai::r#async::read(index_path)
+        .await
+        .map_err(|e| Error::Internal(format!("failed to read BAI index: {}", e)))?;
+    let header = BamIndexReader::read_header(path).await?;
+    if !header
+        .reference_sequences()
+        .contains_key(region.reference_name.as_bytes())
+    {
+        return Err(reference_not_found(region));
+    }
+    if region.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let file = File::open(path)
+        .await
+        .map_err(|e| Error::Internal(format!("failed to open BAM file: {}", e)))?;
+    let mut reader = bam::r#async::io::Reader::new(file);
+    let query_region = query_region(region)?;
+    let mut records = reader
+        .query(&header, &index, &query_region)
+        .map_err(|e| Error::Internal(format!("BAM query failed: {}", e)))?;
+
+    let decode_error = |e: std::io::Error| Error::Internal(format!("failed to decode BAM: {}", e));
+    let mut features = Vec::new();
+    while features.len() < limit {
+        let Some(record) = records.try_next().await.map_err(decode_error)? else {
+            break;
+        };
+        let (Some(alignment_start), Some(span)) =
+            (record.alignment_start(), record.alignment_span())
+        else {
+            continue;
+        };
+        let start = usize::from(alignment_start.map_err(decode_error)?) as u64 - 1;
+        let span = span.map_err(decode_error)? as u64;
+
+        let mut cigar = String::new();
+        for op in record.cigar().iter() {
+            let op = op.map_err(decode_error)?;
+            cigar.push_str(&format!("{}{}", op.len(), cigar_code(op.kind())));
+        }
+
+        let flags = record.flags();
+        features.push(ReadFeature {
+            chr: region.reference_name.clone(),
+            start,
+            end: start + span,
+            name: record.name().map(|name| name.to_string()),
+            strand: if flags.is_reverse_complemented() {
+                '-'
+            } else {
+                '+'
+            },
+            mapq: record.mapping_quality().map(|mapq| mapq.get()),
+            cigar,
+            flags: flags.bits(),
+        });
+    }
+
+    Ok(features)
+}
+
+/// Variants overlapping `region` of a bgzipped, tabix-indexed VCF file, up
+/// to `limit`.
+pub async fn variant_features(
+    path: &Path,
+    index_path: &Path,
+    region: &Region,
+    limit: usize,
+) -> Result<Vec<VariantFeature>> {
+    let index = tabix::r#async::read(index_path)
+        .await
+        .map_err(|e| Error::Internal(format!("failed to read tabix index: {}", e)))?;
+    let known = index.header().is_some_and(|header| {
+        header
+            .reference_sequence_names()
+            .contains(&region.reference_name)
+    });
+    if !known {
+        return Err(reference_not_found(region));
+    }
+    if region.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let header = VcfIndexReader::read_header(path).await?;
+    let file = File::open(path)
+        .await
+        .map_err(|e| Error::Internal(format!("failed to open VCF file: {}", e)))?;
+    let mut reader = vcf::r#async::io::Reader::new(bgzf::r#async::Reader::new(file));
+    let query_region = query_region(region)?;
+    let mut records = reader
+        .query(&header, &index, &query_region)
+        .map_err(|e| Error::Internal(format!("VCF query failed: {}", e)))?;
+
+    let decode_error = |e: std::io::Error| Error::Internal(format!("failed to decode VCF: {}", e));
+    let mut features = Vec::new();
+    while features.len() < limit {
+        let Some(record) = records.try_next().await.map_err(decode_error)? else {
+            break;
+        };
+        let Some(variant_start) = record.variant_start() else {
+            continue;
+        };
+        let start = usize::from(variant_start.map_err(decode_error)?) as u64 - 1;
+        let reference_bases = record.reference_bases().to_string();
+
+        let ids: &str = record.ids().as_ref();
+        let alternate_bases: &str = record.alternate_bases().as_ref();
+        let filters: &str = record.filters().as_ref();
+        features.push(VariantFeature {
+            chr: region.reference_name.clone(),
+            start,
+            end: start + reference_bases.len().max(1) as u64,
+            name: Some(ids.to_string()).filter(|ids| !ids.is_empty() && ids != "."),
+            alt: alternate_bases
+                .split(',')
+                .filter(|alt| !alt.is_empty() && *alt != ".")
+                .map(str::to_string)
+                .collect(),
+            reference_bases,
+            qual: record.quality_score().transpose().map_err(decode_error)?,
+            filter: Some(filters.to_string()).filter(|filters| !filters.is_empty()),
+        });
+    }
+
+    Ok(features)
+}
+
+fn query_region(region: &Region) -> Result<noodles::core::Region> {
+    Ok(noodles::core::Region::new(
+        region.reference_name.as_str(),
+        region_interval(region)?,
+    ))
+}
+
+/// SAM CIGAR operation code
+fn cigar_code(kind: Kind) -> char {
+    match kind {
+        Kind::Match => 'M',
+        Kind::Insertion => 'I',
+        Kind::Deletion => 'D',
+        Kind::Skip => 'N',
+        Kind::SoftClip => 'S',
+        Kind::HardClip => 'H',
+        Kind::Pad => 'P',
+        Kind::SequenceMatch => '=',
+        Kind::SequenceMismatch => 'X',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(file: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/data")
+            .join(file)
+    }
+
+    #[tokio::test]
+    async fn test_variant_features() {
+        let region = Region {
+            reference_name: "chr1".to_string(),
+            start: Some(0),
+            end: Some(150),
+        };
+        let features = variant_features(
+            &data("sample.vcf.gz"),
+            &data("sample.vcf.gz.tbi"),
+            &region,
+            MAX_FEATURES,
+        )
+        .await
+        .unwrap();
+
+        // chr1:100 A>G
+        assert_eq!(features.len(), 1);
+        assert_eq!((features[0].start, features[0].end), (99, 100));
+        assert_eq!(features[0].reference_bases, "A");
+        assert_eq!(features[0].alt, vec!["G"]);
+        assert_eq!(features[0].name, None);
+    }
+
+    #[tokio::test]
+    async fn test_read_features_limit() {
+        let path = data("sample.bam");
+        let header = BamIndexReader::read_header(&path).await.unwrap();
+        let name = header
+            .reference_sequences()
+            .keys()
+            .next()
+            .unwrap()
+            .to_string();
+        let region = Region {
+            reference_name: name,
+            start: None,
+            end: None,
+        };
+
+        let features = read_features(&path, &data("sample.bam.bai"), &region, 2)
+            .await
+            .unwrap();
+        assert!(features.len() <= 2);
+        assert!(
+            features
+                .iter()
+                .all(|f| f.end > f.start && !f.cigar.is_empty())
+        );
+
+        let region = Region {
+            reference_name: "no-such-contig".to_string(),
+            ..region
+        };
+        assert!(matches!(
+            read_features(&path, &data("sample.bam.bai"), &region, 2).await,
+            Err(Error::NotFound(_))
+        ));
+    }
+}
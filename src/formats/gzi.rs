@@ -0,0 +1,141 @@
+use crate::storage::ByteRange;
+use crate::{Error, Result};
+use std::path::Path;
+
+/// GZI index for BGZF-compressed FASTA/FASTQ files.
+///
+/// A GZI file (as written by `bgzip -i` / `samtools faidx`) maps the
+/// uncompressed offset at the start of each BGZF block to that block's
+/// compressed offset, letting uncompressed FAI offsets be translated into
+/// compressed byte ranges.
+///
+/// Layout: a little-endian `u64` entry count followed by that many
+/// `(compressed_offset, uncompressed_offset)` `u64` pairs. The first block
+/// `(0, 0)` is implicit.
+#[derive(Debug, Clone)]
+pub struct GziIndex {
+    /// (compressed, uncompressed) block start offsets, sorted, including (0, 0)
+    entries: Vec<(u64, u64)>,
+}
+
+impl GziIndex {
+    /// Read a GZI index from disk.
+    pub async fn read(path: &Path) -> Result<Self> {
+        let bytes = tokio::fs::read(path)
+            .await
+            .map_err(|e| Error::Internal(format!("failed to read GZI index: {}", e)))?;
+
+        Self::from_bytes(&bytes)
+    }
+
+    /// Parse a GZI index from its raw bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let read_u64 = |offset: usize| -> Result<u64> {
+            bytes
+                .get(offset..offset + 8)
+                .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+                .ok_or_else(|| Error::Internal("truncated GZI index".to_string()))
+        };
+
+        let count = read_u64(0)? as usize;
+        if bytes.len() != 8 + count.saturating_mul(16) {
+            return Err(Error::Internal(format!(
+                "invalid GZI index: expected {} entries",
+                count
+            )));
+        }
+
+        let mut entries = Vec::with_capacity(count + 1);
+        entries.push((0, 0));
+
+        for i in 0..count {
+            let offset = 8 + i * 16;
+            entries.push((read_u64(offset)?, read_u64(offset + 8)?));
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Map an uncompressed byte range `[start, end)` to the compressed byte
+    /// range covering every BGZF block it touches.
+    ///
+    /// The returned range starts and ends on block boundaries, so it
+    /// decompresses standalone to a superset of the requested bytes. An open
+    /// `end` means the range extends to the end of the file.
+    pub fn compressed_range(&self, start: u64, end: u64) -> ByteRange {
+        let first = self.block_index(start);
+        let last = self.block_index(end.saturating_sub(1).max(start));
+
+        ByteRange {
+            start: self.entries[first].0,
+            end: self
+                .entries
+                .get(last + 1)
+                .map(|(compressed, _)| *compressed),
+        }
+    }
+
+    /// Uncompressed offset of the start of the block containing
+    /// `uncompressed`, where [`Self::compressed_range`] starts decompressing.
+    pub fn block_start(&self, uncompressed: u64) -> u64 {
+        self.entries[self.block_index(uncompressed)].1
+    }
+
+    /// Index of the block containing the given uncompressed offset.
+    fn block_index(&self, uncompressed: u64) -> usize {
+        self.entries
+            .partition_point(|(_, u)| *u <= uncompressed)
+            .saturating_sub(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_index() -> GziIndex {
+        // Blocks: [0, 65280) @ 0, [65280, 130560) @ 1000, [130560, ..) @ 2000
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&2u64.to_le_bytes());
+        for (c, u) in [(1000u64, 65280u64), (2000, 130560)] {
+            bytes.extend_from_slice(&c.to_le_bytes());
+            bytes.extend_from_slice(&u.to_le_bytes());
+        }
+        GziIndex::from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_compressed_range_single_block() {
+        let range = test_index().compressed_range(10, 100);
+        assert_eq!(range.start, 0);
+        assert_eq!(range.end, Some(1000));
+    }
+
+    #[test]
+    fn test_compressed_range_spanning_blocks() {
+        let range = test_index().compressed_range(65000, 70000);
+        assert_eq!(range.start, 0);
+        assert_eq!(range.end, Some(2000));
+    }
+
+    #[test]
+    fn test_block_start() {
+        let index = test_index();
+        assert_eq!(index.block_start(10), 0);
+        assert_eq!(index.block_start(65280), 65280);
+        assert_eq!(index.block_start(140000), 130560);
+    }
+
+    #[test]
+    fn test_compressed_range_last_block() {
+        let range = test_index().compressed_range(140000, 150000);
+        assert_eq!(range.start, 2000);
+        assert_eq!(range.end, None);
+    }
+
+    #[test]
+    fn test_invalid_gzi() {
+        assert!(GziIndex::from_bytes(&[1, 0, 0]).is_err());
+        assert!(GziIndex::from_bytes(&5u64.to_le_bytes()).is_err());
+    }
+}
@@ -0,0 +1,293 @@
+//! Header parsing failures, reported clearly or worked around.
+//!
+//! noodles rejects a header as a whole when any line of it is malformed,
+//! which used to surface as an internal error. When a header fails to parse,
+//! its raw text is read again and parsed line by line to find the offending
+//! lines, which are logged. With [`HeaderParsing::Strict`] the request then
+//! fails with `UnsupportedFormat`, naming the file and line; with
+//! [`HeaderParsing::Lenient`] those lines are dropped and the rest of the
+//! header is used.
+
+use crate::types::Format;
+use crate::{Error, Result};
+use noodles::bgzf::VirtualPosition;
+use noodles::{sam, vcf};
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Bytes of an offending header line that are logged
+const MAX_LOGGED_LINE: usize = 200;
+
+/// How headers noodles can't parse are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeaderParsing {
+    /// Fail the request with `UnsupportedFormat`
+    #[default]
+    Strict,
+    /// Drop the header lines that don't parse, logging them, and serve the
+    /// file with the rest
+    Lenient,
+}
+
+impl FromStr for HeaderParsing {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "strict" => Ok(HeaderParsing::Strict),
+            "lenient" => Ok(HeaderParsing::Lenient),
+            _ => Err(format!(
+                "unknown header parsing: {} (expected 'strict' or 'lenient')",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for HeaderParsing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeaderParsing::Strict => write!(f, "strict"),
+            HeaderParsing::Lenient => write!(f, "lenient"),
+        }
+    }
+}
+
+/// A header as stored, read without parsing it
+pub(super) struct RawHeader {
+    /// Header text, one record per line
+    pub text: Vec<u8>,
+    /// Position just past the header
+    pub end: VirtualPosition,
+}
+
+/// A header line that failed to parse
+#[derive(Debug)]
+pub(super) struct InvalidLine {
+    /// 1-based line number
+    pub number: usize,
+    pub error: String,
+}
+
+/// Whether noodles failed on the header's contents rather than on reading
+/// the file
+pub(super) fn is_parse_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+    )
+}
+
+/// The error for a failure reading the `section` of the file at `path`:
+/// `UnsupportedFormat` if its contents didn't parse, otherwise internal
+pub(super) fn read_error(format: Format, path: &Path, section: &str, e: io::Error) -> Error {
+    if is_parse_error(&e) {
+        unsupported(format, path, section, e)
+    } else {
+        Error::Internal(format!("failed to read {:?} {}: {}", format, section, e))
+    }
+}
+
+fn unsupported(
+    format: Format,
+    path: &Path,
+    section: &str,
+    detail: impl std::fmt::Display,
+) -> Error {
+    Error::UnsupportedFormat(format!(
+        "{:?} {} of {} could not be parsed: {}",
+        format,
+        section,
+        file_name(path),
+        detail
+    ))
+}
+
+/// The file name alone; clients shouldn't see storage paths
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Parse a header noodles rejected with `cause` line by line, logging the
+/// lines that fail. Strict parsing then fails with the first of them;
+/// lenient parsing returns the header without them.
+pub(super) fn recover<H>(
+    format: Format,
+    path: &Path,
+    parsing: HeaderParsing,
+    text: &[u8],
+    parse_lines: fn(&[u8]) -> std::result::Result<(H, Vec<InvalidLine>), String>,
+    cause: io::Error,
+) -> Result<H> {
+    let (header, invalid) =
+        parse_lines(text).map_err(|e| unsupported(format, path, "header", e))?;
+
+    let lines: Vec<&[u8]> = text.split(|&b| b == b'\n').collect();
+    for line in &invalid {
+        let raw = lines.get(line.number - 1).copied().unwrap_or_default();
+        tracing::warn!(
+            "{:?} header of {} line {}: {}: {}",
+            format,
+            path.display(),
+            line.number,
+            line.error,
+            String::from_utf8_lossy(&raw[..raw.len().min(MAX_LOGGED_LINE)])
+        );
+    }
+
+    match (parsing, invalid.first()) {
+        (HeaderParsing::Lenient, _) => {
+            if !invalid.is_empty() {
+                tracing::warn!(
+                    "serving {} without {} header line(s) that failed to parse ({})",
+                    path.display(),
+                    invalid.len(),
+                    cause
+                );
+            }
+            Ok(header)
+        }
+        (HeaderParsing::Strict, Some(line)) => Err(unsupported(
+            format,
+            path,
+            "header",
+            format_args!("line {}: {}", line.number, line.error),
+        )),
+        (HeaderParsing::Strict, None) => Err(unsupported(format, path, "header", cause)),
+    }
+}
+
+/// Lines of header text, without line endings or NUL padding
+fn lines(text: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    text.split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !line.is_empty() && line[0] != 0)
+}
+
+/// Parse SAM header text, skipping the lines that fail
+pub(super) fn parse_sam_lines(
+    text: &[u8],
+) -> std::result::Result<(sam::Header, Vec<InvalidLine>), String> {
+    let mut parser = sam::header::Parser::default();
+    let mut invalid = Vec::new();
+    for (number, line) in lines(text) {
+        if let Err(e) = parser.parse_partial(line) {
+            invalid.push(InvalidLine {
+                number,
+                error: e.to_string(),
+            });
+        }
+    }
+    Ok((parser.finish(), invalid))
+}
+
+/// Parse VCF header text, skipping the meta lines that fail. The
+/// `##fileformat` and `#CHROM` lines can't be skipped.
+pub(super) fn parse_vcf_lines(
+    text: &[u8],
+) -> std::result::Result<(vcf::Header, Vec<InvalidLine>), String> {
+    let mut parser = vcf::header::Parser::default();
+    let mut invalid = Vec::new();
+    for (number, line) in lines(text) {
+        if let Err(e) = parser.parse_partial(line) {
+            if number == 1 || !line.starts_with(b"##") {
+                return Err(format!("line {}: {}", number, e));
+            }
+            invalid.push(InvalidLine {
+                number,
+                error: e.to_string(),
+            });
+        }
+    }
+    let header = parser.finish().map_err(|e| e.to_string())?;
+    Ok((header, invalid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAM: &[u8] = b"@HD\tVN:1.6\n@SQ\tSN:chr1\tLN:1000\n@SQ\tSN:chr2\tLN:zero\n@RG\tID:rg1\n";
+    const VCF: &[u8] = b"##fileformat=VCFv4.3\n##contig=<ID=chr1,length=1000>\n##INFO=<ID=DP,Number=1,Type=Integer\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n";
+
+    #[test]
+    fn test_parse_sam_lines() {
+        let (header, invalid) = parse_sam_lines(SAM).unwrap();
+        assert_eq!(header.reference_sequences().len(), 1);
+        assert_eq!(header.read_groups().len(), 1);
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].number, 3);
+    }
+
+    #[test]
+    fn test_parse_vcf_lines() {
+        let (header, invalid) = parse_vcf_lines(VCF).unwrap();
+        assert!(header.contigs().contains_key("chr1"));
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].number, 3);
+
+        assert!(parse_vcf_lines(b"##fileformat=bogus\n#CHROM\n").is_err());
+    }
+
+    #[test]
+    fn test_recover() {
+        let path = Path::new("/data/private/sample.bam");
+        let cause = || io::Error::new(io::ErrorKind::InvalidData, "invalid record");
+
+        let header = recover(
+            Format::Bam,
+            path,
+            HeaderParsing::Lenient,
+            SAM,
+            parse_sam_lines,
+            cause(),
+        )
+        .unwrap();
+        assert_eq!(header.reference_sequences().len(), 1);
+
+        let e = recover(
+            Format::Bam,
+            path,
+            HeaderParsing::Strict,
+            SAM,
+            parse_sam_lines,
+            cause(),
+        )
+        .unwrap_err();
+        let Error::UnsupportedFormat(message) = e else {
+            panic!("unexpected error: {:?}", e);
+        };
+        assert!(message.contains("sample.bam"));
+        assert!(message.contains("line 3"));
+        assert!(!message.contains("/data/private"));
+    }
+
+    #[test]
+    fn test_read_error() {
+        let path = Path::new("sample.vcf.gz");
+        let invalid = io::Error::new(io::ErrorKind::InvalidData, "bad");
+        assert!(matches!(
+            read_error(Format::Vcf, path, "header", invalid),
+            Error::UnsupportedFormat(_)
+        ));
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        assert!(matches!(
+            read_error(Format::Vcf, path, "header", denied),
+            Error::Internal(_)
+        ));
+    }
+
+    #[test]
+    fn test_header_parsing_from_str() {
+        assert_eq!(
+            "Lenient".parse::<HeaderParsing>().unwrap(),
+            HeaderParsing::Lenient
+        );
+        assert!("loose".parse::<HeaderParsing>().is_err());
+    }
+}
@@ -11,32 +11,293 @@
 //! - [`CramIndexReader`] - CRAM index files (`.crai`)
 //! - [`FastaIndexReader`] - FASTA index files (`.fai`)
 //! - [`FastqIndexReader`] - FASTQ files (no index, returns whole file)
+//! - [`GziIndex`] - BGZF block offsets for compressed FASTA/FASTQ (`.gzi`)
 //!
 //! # Index-Based Queries
 //!
 //! The htsget protocol returns byte ranges that clients can fetch directly.
 //! Index readers translate genomic coordinates (chr:start-end) into file
 //! byte offsets using the index files.
+//!
+//! Every reader implements [`IndexReader`]. The ticket endpoints look
+//! readers up by format in an [`IndexReaders`] registry, where embedders can
+//! put their own (e.g. a reader for indexes kept in a database), or add
+//! readers for formats of their own under other keys.
+//!
+//! Requested reference names can be matched to the ones a file declares
+//! through [`ReferenceAliases`] (`1` for `chr1`, `MT` for `chrM`).
+//!
+//! Headers noodles can't parse are reported as `UnsupportedFormat`, naming
+//! the offending line, or with [`HeaderParsing::Lenient`] served without it.
+//!
+//! [`PreciseSlice`] sends only the BAM records overlapping a region, for
+//! clients that can't drop the extra records byte-range slices carry.
 
+mod aliases;
 mod bam;
 mod bcf;
+mod blocks;
+mod coverage;
 mod cram;
 mod fasta;
 mod fastq;
+#[cfg(feature = "explore")]
+mod features;
+mod gzi;
+mod header;
+mod precise;
+mod reader;
+mod stats;
 mod vcf;
 
+pub use aliases::ReferenceAliases;
 pub use bam::BamIndexReader;
 pub use bcf::BcfIndexReader;
+pub use coverage::{MAX_DECODE_BYTES, read_coverage};
 pub use cram::CramIndexReader;
 pub use fasta::FastaIndexReader;
-pub use fastq::FastqIndexReader;
+pub use fastq::{FastqIndexReader, FastqRecordIndex, RECORD_INDEX_STRIDE, RecordRange};
+#[cfg(feature = "explore")]
+pub use features::{MAX_FEATURES, read_features, variant_features};
+pub use gzi::GziIndex;
+pub use header::HeaderParsing;
+pub use precise::PreciseSlice;
+pub use reader::{AnyIndexReader, IndexReader, IndexReaders, QueryHeader};
+pub use stats::{MAX_STATS_BINS, variant_stats};
 pub use vcf::VcfIndexReader;
 
 use crate::storage::ByteRange;
+use crate::types::{Format, ReferenceSequence, Region};
+use crate::{Error, Result};
+use noodles::core::Position;
+use noodles::core::region::Interval;
+use std::path::{Path, PathBuf};
 
 /// Result of querying an index for byte ranges
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IndexedRanges {
     pub header_range: ByteRange,
     pub data_ranges: Vec<ByteRange>,
 }
+
+/// Interval for a 0-based half-open window (noodles positions are 1-based,
+/// closed). An open or out-of-range `end` runs to the end of the sequence.
+pub(crate) fn interval(start: u64, end: Option<u64>) -> Result<Interval> {
+    let position = |value: u64| {
+        usize::try_from(value)
+            .ok()
+            .and_then(|value| Position::try_from(value).ok())
+    };
+    let start = start
+        .checked_add(1)
+        .and_then(position)
+        .ok_or_else(|| Error::InvalidRange(format!("invalid start position: {}", start)))?;
+    let end = end.and_then(position).unwrap_or(Position::MAX);
+
+    Ok(Interval::from(start..=end))
+}
+
+/// Interval covering `region`, whichever of `start` and `end` it gives.
+/// Callers skip [empty](Region::is_empty) regions, which cover no records.
+pub(crate) fn region_interval(region: &Region) -> Result<Interval> {
+    let (start, end) = region.bounds();
+    interval(start, end)
+}
+
+/// Query a local data file's index for the byte ranges covering `regions`,
+/// with the built-in reader for `format`.
+///
+/// `index_path` is ignored for FASTQ. BGZF-compressed FASTA (`.fa.gz`) is
+/// resolved through its `.gzi` when one sits next to the file.
+pub async fn query_ranges(
+    format: Format,
+    path: &Path,
+    index_path: &Path,
+    regions: &[Region],
+) -> Result<IndexedRanges> {
+    let reader = IndexReaders::default().get(format)?;
+    let header = reader.read_header(path).await?;
+    reader
+        .query_ranges(path, index_path, regions, header.as_ref())
+        .await
+}
+
+/// A local file's header re-compressed into BGZF blocks of its own, without
+/// any records that share its last block, for BAM, BCF and bgzipped VCF.
+///
+/// Unlike the header's byte range, it ends on a block boundary wherever the
+/// header ends in the original file.
+pub async fn standalone_header(format: Format, path: &Path) -> Result<Vec<u8>> {
+    let end = match format {
+        Format::Bam => BamIndexReader::header_end(path).await?,
+        Format::Vcf => VcfIndexReader::header_end(path).await?,
+        Format::Bcf => BcfIndexReader::header_end(path).await?,
+        _ => {
+            return Err(Error::UnsupportedFormat(format!(
+                "{:?} has no BGZF header",
+                format
+            )));
+        }
+    };
+    blocks::standalone_header(path, end).await
+}
+
+/// Query a local data file, detecting its format from the file name and
+/// locating its index next to it unless either is given explicitly.
+///
+/// Returns the resolved format alongside the ranges.
+pub async fn query_file(
+    path: &Path,
+    format: Option<Format>,
+    index_path: Option<&Path>,
+    regions: &[Region],
+) -> Result<(Format, IndexedRanges)> {
+    let format = match format {
+        Some(format) => format,
+        None => crate::indexer::detect_format(path).ok_or_else(|| {
+            Error::UnsupportedFormat(format!("cannot detect format of {}", path.display()))
+        })?,
+    };
+
+    // FASTQ has no index; other formats need one
+    let index_path = match index_path.map(Path::to_path_buf) {
+        Some(index_path) => index_path,
+        None => match crate::check::find_index(path, format) {
+            Some(index_path) => index_path,
+            None if format == Format::Fastq => PathBuf::new(),
+            None => {
+                return Err(Error::NotFound(format!("index for {}", path.display())));
+            }
+        },
+    };
+
+    let ranges = query_ranges(format, path, &index_path, regions).await?;
+    Ok((format, ranges))
+}
+
+/// Parse an index file on its own, without the data file it belongs to.
+///
+/// Used to verify that a downloaded or cached index is readable. FASTQ has
+/// no index, so this is a no-op for it.
+pub async fn read_index(format: Format, index_path: &Path) -> Result<()> {
+    let invalid = |kind: &str, e: std::io::Error| {
+        Error::Internal(format!("failed to read {} index: {}", kind, e))
+    };
+
+    match format {
+        Format::Bam => noodles::bam::bai::r#async::read(index_path)
+            .await
+            .map(|_| ())
+            .map_err(|e| invalid("BAI", e)),
+        Format::Cram => noodles::cram::crai::r#async::read(index_path)
+            .await
+            .map(|_| ())
+            .map_err(|e| invalid("CRAI", e)),
+        Format::Vcf => noodles::tabix::r#async::read(index_path)
+            .await
+            .map(|_| ())
+            .map_err(|e| invalid("tabix", e)),
+        Format::Bcf => noodles::csi::r#async::read(index_path)
+            .await
+            .map(|_| ())
+            .map_err(|e| invalid("CSI", e)),
+        Format::Fasta => {
+            let path = index_path.to_path_buf();
+            tokio::task::spawn_blocking(move || noodles::fasta::fai::read(path))
+                .await
+                .map_err(|e| Error::Internal(format!("failed to read FAI index: {}", e)))?
+                .map(|_| ())
+                .map_err(|e| invalid("FAI", e))
+        }
+        Format::Fastq => Ok(()),
+    }
+}
+
+/// Reference sequence names and lengths declared by a local data file's
+/// header, or by its FAI index for FASTA. FASTQ declares none.
+pub async fn reference_sequences(
+    format: Format,
+    path: &Path,
+    index_path: Option<&Path>,
+) -> Result<Vec<ReferenceSequence>> {
+    let sam = |header: noodles::sam::Header| -> Vec<ReferenceSequence> {
+        header
+            .reference_sequences()
+            .iter()
+            .map(|(name, sequence)| ReferenceSequence {
+                name: name.to_string(),
+                length: Some(usize::from(sequence.length()) as u64),
+            })
+            .collect()
+    };
+    let vcf = |header: noodles::vcf::Header| -> Vec<ReferenceSequence> {
+        header
+            .contigs()
+            .iter()
+            .map(|(name, contig)| ReferenceSequence {
+                name: name.clone(),
+                length: contig.length().map(|length| length as u64),
+            })
+            .collect()
+    };
+
+    match format {
+        Format::Bam => Ok(sam(BamIndexReader::read_header(path).await?)),
+        Format::Cram => Ok(sam(CramIndexReader::read_header(path).await?)),
+        Format::Vcf => Ok(vcf(VcfIndexReader::read_header(path).await?)),
+        Format::Bcf => Ok(vcf(BcfIndexReader::read_header(path).await?)),
+        Format::Fasta => {
+            let Some(index_path) = index_path else {
+                return Ok(Vec::new());
+            };
+            let path = index_path.to_path_buf();
+            let index = tokio::task::spawn_blocking(move || noodles::fasta::fai::read(path))
+                .await
+                .map_err(|e| Error::Internal(format!("failed to read FAI index: {}", e)))?
+                .map_err(|e| Error::Internal(format!("failed to read FAI index: {}", e)))?;
+
+            Ok(index
+                .as_ref()
+                .iter()
+                .map(|record| ReferenceSequence {
+                    name: String::from_utf8_lossy(record.name()).into_owned(),
+                    length: Some(record.length() as u64),
+                })
+                .collect())
+        }
+        Format::Fastq => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(interval: Interval) -> (Option<usize>, Option<usize>) {
+        (
+            interval.start().map(usize::from),
+            interval.end().map(usize::from),
+        )
+    }
+
+    #[test]
+    fn test_region_interval() {
+        let interval = |start, end| {
+            let region = Region::new("chr1", start, end).unwrap();
+            bounds(region_interval(&region).unwrap())
+        };
+        let max = Some(usize::from(Position::MAX));
+
+        assert_eq!(interval(None, None), (Some(1), max));
+        assert_eq!(interval(Some(1000), None), (Some(1001), max));
+        assert_eq!(interval(None, Some(5000)), (Some(1), Some(5000)));
+        assert_eq!(interval(Some(1000), Some(5000)), (Some(1001), Some(5000)));
+        assert_eq!(interval(Some(0), Some(1)), (Some(1), Some(1)));
+        assert_eq!(interval(None, Some(u64::MAX)).1, max);
+
+        assert!(matches!(
+            super::interval(u64::MAX, None),
+            Err(Error::InvalidRange(_))
+        ));
+    }
+}
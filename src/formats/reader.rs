@@ -0,0 +1,347 @@
+//! The index reader interface and the registry of readers per format.
+//!
+//! Readers implement [`IndexReader`], with the header their queries need
+//! as an associated type. The registry holds them as [`AnyIndexReader`]s,
+//! which carry that header between `read_header` and `query_ranges` as an
+//! opaque [`QueryHeader`].
+//!
+//! Readers are registered by key: the built-in formats under their names
+//! (`BAM`, `CRAM`, ...), which the ticket endpoints serve, and any other
+//! key for formats an embedder serves from routes of its own.
+
+use super::{
+    BamIndexReader, BcfIndexReader, CramIndexReader, FastaIndexReader, FastqIndexReader,
+    HeaderParsing, IndexedRanges, RecordRange, VcfIndexReader,
+};
+use crate::storage::ByteRange;
+use crate::types::{Format, Region};
+use crate::{Error, Result};
+use async_trait::async_trait;
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Index queries of a format, as ticket building needs them.
+#[async_trait]
+pub trait IndexReader: Send + Sync + 'static {
+    /// What queries need from a file's header (e.g. BAM reference names),
+    /// read ahead of the query by [`read_header`](Self::read_header); `()`
+    /// for readers that need nothing
+    type Header: Send + Sync + 'static;
+
+    /// Whether sliced tickets start with a header block. FASTA and FASTQ
+    /// have none.
+    fn has_header(&self) -> bool {
+        true
+    }
+
+    /// Read what queries need from the header, if anything. Ticket building
+    /// reads it alongside the index lookup and passes it to
+    /// [`query_ranges`](Self::query_ranges).
+    async fn read_header(&self, _path: &Path) -> Result<Option<Self::Header>> {
+        Ok(None)
+    }
+
+    /// Byte range of the header
+    async fn header_range(&self, path: &Path) -> Result<ByteRange>;
+
+    /// Check that a header-only ticket can be served for the file at `path`
+    fn check_header(&self, _path: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether regions of the file at `path` can be located through its
+    /// index. Tickets for files that can't be (e.g. BGZF FASTA without a
+    /// GZI) are the whole file.
+    fn can_query(&self, _path: &Path) -> bool {
+        true
+    }
+
+    /// Byte range of a slice of records (the FASTQ `records` extension)
+    async fn record_range(&self, _path: &Path, _records: &RecordRange) -> Result<ByteRange> {
+        Err(Error::UnsupportedFormat(
+            "record slices are only supported for FASTQ".to_string(),
+        ))
+    }
+
+    /// Byte ranges covering `regions`, with the header's range. `header` is
+    /// what [`read_header`](Self::read_header) returned, when it was read.
+    async fn query_ranges(
+        &self,
+        path: &Path,
+        index_path: &Path,
+        regions: &[Region],
+        header: Option<&Self::Header>,
+    ) -> Result<IndexedRanges>;
+}
+
+/// The header an [`AnyIndexReader`] read, for its own queries
+pub struct QueryHeader(Box<dyn Any + Send + Sync>);
+
+impl QueryHeader {
+    /// The header, if it's a `T` (e.g. the `sam::Header` the BAM reader
+    /// reads)
+    pub fn downcast<T: 'static>(self) -> Option<T> {
+        self.0.downcast().ok().map(|header| *header)
+    }
+}
+
+impl std::fmt::Debug for QueryHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("QueryHeader")
+    }
+}
+
+/// An [`IndexReader`] of any header type, as [`IndexReaders`] holds them.
+/// Implemented for every `IndexReader`.
+#[async_trait]
+pub trait AnyIndexReader: Send + Sync {
+    fn has_header(&self) -> bool;
+
+    async fn read_header(&self, path: &Path) -> Result<Option<QueryHeader>>;
+
+    async fn header_range(&self, path: &Path) -> Result<ByteRange>;
+
+    fn check_header(&self, path: &Path) -> Result<()>;
+
+    fn can_query(&self, path: &Path) -> bool;
+
+    async fn record_range(&self, path: &Path, records: &RecordRange) -> Result<ByteRange>;
+
+    /// As [`IndexReader::query_ranges`]. Fails if `header` was read by
+    /// another reader.
+    async fn query_ranges(
+        &self,
+        path: &Path,
+        index_path: &Path,
+        regions: &[Region],
+        header: Option<&QueryHeader>,
+    ) -> Result<IndexedRanges>;
+}
+
+#[async_trait]
+impl<R: IndexReader> AnyIndexReader for R {
+    fn has_header(&self) -> bool {
+        IndexReader::has_header(self)
+    }
+
+    async fn read_header(&self, path: &Path) -> Result<Option<QueryHeader>> {
+        let header = IndexReader::read_header(self, path).await?;
+        Ok(header.map(|header| QueryHeader(Box::new(header))))
+    }
+
+    async fn header_range(&self, path: &Path) -> Result<ByteRange> {
+        IndexReader::header_range(self, path).await
+    }
+
+    fn check_header(&self, path: &Path) -> Result<()> {
+        IndexReader::check_header(self, path)
+    }
+
+    fn can_query(&self, path: &Path) -> bool {
+        IndexReader::can_query(self, path)
+    }
+
+    async fn record_range(&self, path: &Path, records: &RecordRange) -> Result<ByteRange> {
+        IndexReader::record_range(self, path, records).await
+    }
+
+    async fn query_ranges(
+        &self,
+        path: &Path,
+        index_path: &Path,
+        regions: &[Region],
+        header: Option<&QueryHeader>,
+    ) -> Result<IndexedRanges> {
+        let header = header
+            .map(|header| {
+                header.0.downcast_ref::<R::Header>().ok_or_else(|| {
+                    Error::Internal("header was read by another index reader".to_string())
+                })
+            })
+            .transpose()?;
+        IndexReader::query_ranges(self, path, index_path, regions, header).await
+    }
+}
+
+/// The [`IndexReader`] for each format, by key. The default has the
+/// built-in readers, under the formats' names; embedders may replace any
+/// of them, or add readers under keys of their own.
+#[derive(Clone)]
+pub struct IndexReaders {
+    readers: HashMap<String, Arc<dyn AnyIndexReader>>,
+    /// Keys whose built-in reader was replaced
+    custom: HashSet<String>,
+}
+
+impl Default for IndexReaders {
+    fn default() -> Self {
+        let readers: [(Format, Arc<dyn AnyIndexReader>); 2] = [
+            (Format::Fasta, Arc::new(FastaIndexReader)),
+            (Format::Fastq, Arc::new(FastqIndexReader)),
+        ];
+        Self {
+            readers: readers
+                .into_iter()
+                .map(|(format, reader)| (format.to_string(), reader))
+                .collect(),
+            custom: HashSet::new(),
+        }
+        .with_header_parsing(HeaderParsing::default())
+    }
+}
+
+impl IndexReaders {
+    /// Use `reader` for `format`, replacing the built-in one
+    pub fn with_reader(self, format: Format, reader: impl IndexReader) -> Self {
+        self.with_keyed_reader(&format.to_string(), reader)
+    }
+
+    /// Use `reader` for the format named `key` (case-insensitive), which
+    /// may be one the ticket endpoints don't serve. Readers for such keys
+    /// are for embedders' own routes, which look them up with
+    /// [`get_key`](Self::get_key).
+    pub fn with_keyed_reader(mut self, key: &str, reader: impl IndexReader) -> Self {
+        let key = key.to_ascii_uppercase();
+        self.readers.insert(key.clone(), Arc::new(reader));
+        self.custom.insert(key);
+        self
+    }
+
+    /// Parse BAM, CRAM, VCF and BCF headers as `parsing` says. Readers
+    /// added with [`with_reader`](Self::with_reader) are kept.
+    pub fn with_header_parsing(mut self, parsing: HeaderParsing) -> Self {
+        let header_parsing = parsing;
+        let readers: [(Format, Arc<dyn AnyIndexReader>); 4] = [
+            (Format::Bam, Arc::new(BamIndexReader { header_parsing })),
+            (Format::Cram, Arc::new(CramIndexReader { header_parsing })),
+            (Format::Vcf, Arc::new(VcfIndexReader { header_parsing })),
+            (Format::Bcf, Arc::new(BcfIndexReader { header_parsing })),
+        ];
+        for (format, reader) in readers {
+            let key = format.to_string();
+            if !self.custom.contains(&key) {
+                self.readers.insert(key, reader);
+            }
+        }
+        self
+    }
+
+    /// The reader for `format`
+    pub fn get(&self, format: Format) -> Result<Arc<dyn AnyIndexReader>> {
+        self.get_key(&format.to_string())
+    }
+
+    /// The reader for the format named `key` (case-insensitive)
+    pub fn get_key(&self, key: &str) -> Result<Arc<dyn AnyIndexReader>> {
+        self.readers
+            .get(&key.to_ascii_uppercase())
+            .cloned()
+            .ok_or_else(|| Error::UnsupportedFormat(format!("no index reader for {}", key)))
+    }
+}
+
+impl std::fmt::Debug for IndexReaders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.readers.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct EmptyReader;
+
+    #[async_trait]
+    impl IndexReader for EmptyReader {
+        type Header = ();
+
+        async fn header_range(&self, _path: &Path) -> Result<ByteRange> {
+            Ok(ByteRange {
+                start: 0,
+                end: Some(0),
+            })
+        }
+
+        async fn query_ranges(
+            &self,
+            path: &Path,
+            _index_path: &Path,
+            _regions: &[Region],
+            _header: Option<&()>,
+        ) -> Result<IndexedRanges> {
+            Ok(IndexedRanges {
+                header_range: IndexReader::header_range(self, path).await?,
+                data_ranges: vec![],
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_index_readers() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/mt.bam");
+        let readers = IndexReaders::default();
+        for format in [Format::Bam, Format::Fastq] {
+            assert!(readers.get(format).is_ok(), "{:?}", format);
+        }
+        assert!(!readers.get(Format::Fasta).unwrap().has_header());
+        let records: RecordRange = "0-10".parse().unwrap();
+        assert!(matches!(
+            readers
+                .get(Format::Bam)
+                .unwrap()
+                .record_range(&path, &records)
+                .await,
+            Err(Error::UnsupportedFormat(_))
+        ));
+
+        let bam = readers.get(Format::Bam).unwrap();
+        let header = bam.read_header(&path).await.unwrap();
+        let index_path = path.with_extension("bam.bai");
+        let regions = [Region {
+            reference_name: "chr1".to_string(),
+            start: None,
+            end: None,
+        }];
+        let indexed = bam
+            .query_ranges(&path, &index_path, &regions, header.as_ref())
+            .await
+            .unwrap();
+        assert!(!indexed.data_ranges.is_empty());
+
+        // Headers are only for the reader that read them
+        let cram = readers.get(Format::Cram).unwrap();
+        assert!(matches!(
+            cram.query_ranges(&path, &index_path, &regions, header.as_ref())
+                .await,
+            Err(Error::Internal(_))
+        ));
+
+        let readers = readers.with_reader(Format::Bam, EmptyReader);
+        let indexed = readers
+            .get(Format::Bam)
+            .unwrap()
+            .query_ranges(&path, &index_path, &regions, None)
+            .await
+            .unwrap();
+        assert!(indexed.data_ranges.is_empty());
+
+        // Custom readers survive a change of header parsing
+        let readers = readers.with_header_parsing(HeaderParsing::Lenient);
+        let indexed = readers
+            .get(Format::Bam)
+            .unwrap()
+            .query_ranges(&path, &index_path, &regions, None)
+            .await
+            .unwrap();
+        assert!(indexed.data_ranges.is_empty());
+
+        // Other formats are registered by key
+        assert!(readers.get_key("gff3").is_err());
+        let readers = readers.with_keyed_reader("gff3", EmptyReader);
+        assert!(readers.get_key("GFF3").unwrap().has_header());
+        assert!(readers.get(Format::Cram).is_ok());
+    }
+}
@@ -0,0 +1,262 @@
+//! Approximate variant counts from index metadata.
+//!
+//! Tabix and CSI indexes record how many records each reference sequence
+//! holds, but not how they are distributed. A window's count is estimated
+//! from the share of the reference's compressed bytes that the index maps
+//! to it, so no data is read. Resolution is limited to BGZF blocks (~64 KiB
+//! uncompressed): narrow windows in sparse regions are overestimated.
+
+use super::{BcfIndexReader, VcfIndexReader, interval};
+use crate::types::{Format, Region, StatsBin, VariantStats};
+use crate::{Error, Result};
+use noodles::csi::binning_index::index::reference_sequence::bin::Chunk;
+use noodles::csi::binning_index::index::reference_sequence::{Index as ReferenceIndex, Metadata};
+use noodles::csi::binning_index::{BinningIndex, Index};
+use std::path::Path;
+
+/// Upper bound on windows per stats or coverage request
+pub const MAX_STATS_BINS: usize = 10_000;
+
+/// Estimate record counts across `bins` equal windows of `region` in a VCF
+/// (tabix) or BCF (CSI) file.
+pub async fn variant_stats(
+    format: Format,
+    path: &Path,
+    index_path: &Path,
+    region: &Region,
+    bins: usize,
+) -> Result<VariantStats> {
+    match format {
+        Format::Vcf => {
+            let index = noodles::tabix::r#async::read(index_path)
+                .await
+                .map_err(|e| Error::Internal(format!("failed to read tabix index: {}", e)))?;
+            let names = index.header().ok_or_else(|| {
+                Error::Internal("tabix index missing header with reference names".to_string())
+            })?;
+            let reference_sequence_id = names
+                .reference_sequence_names()
+                .iter()
+                .position(|name| name == &region.reference_name)
+                .ok_or_else(|| reference_not_found(region))?;
+            let header = VcfIndexReader::read_header(path).await?;
+            let length = contig_length(&header, region);
+
+            estimate(format, &index, reference_sequence_id, region, length, bins)
+        }
+        Format::Bcf => {
+            let index = noodles::csi::r#async::read(index_path)
+                .await
+                .map_err(|e| Error::Internal(format!("failed to read CSI index: {}", e)))?;
+            let header = BcfIndexReader::read_header(path).await?;
+            let reference_sequence_id = header
+                .contigs()
+                .get_index_of(&region.reference_name)
+                .ok_or_else(|| reference_not_found(region))?;
+            let length = contig_length(&header, region);
+
+            estimate(format, &index, reference_sequence_id, region, length, bins)
+        }
+        _ => Err(Error::UnsupportedFormat(format!(
+            "{:?} has no variant index",
+            format
+        ))),
+    }
+}
+
+/// Contig length declared in the header, if any
+fn contig_length(header: &noodles::vcf::Header, region: &Region) -> Option<u64> {
+    header
+        .contigs()
+        .get(&region.reference_name)
+        .and_then(|contig| contig.length())
+        .map(|length| length as u64)
+}
+
+pub(super) fn reference_not_found(region: &Region) -> Error {
+    Error::NotFound(format!(
+        "reference sequence not found: {}",
+        region.reference_name
+    ))
+}
+
+fn estimate<I>(
+    format: Format,
+    index: &Index<I>,
+    reference_sequence_id: usize,
+    region: &Region,
+    reference_length: Option<u64>,
+    bins: usize,
+) -> Result<VariantStats>
+where
+    I: ReferenceIndex,
+    Index<I>: BinningIndex,
+{
+    let windows = windows(region, reference_length, bins)?;
+    let (total_records, estimates) = estimate_records(index, reference_sequence_id, &windows)?;
+
+    Ok(VariantStats {
+        format,
+        reference_name: region.reference_name.clone(),
+        total_records,
+        bins: windows
+            .into_iter()
+            .zip(estimates)
+            .map(|((start, end), estimated_records)| StatsBin {
+                start,
+                end: (end != u64::MAX).then_some(end),
+                estimated_records,
+            })
+            .collect(),
+    })
+}
+
+/// Split `region` into at most `bins` equal 0-based half-open windows. The
+/// end defaults to `reference_length`; when that is unknown too, a single
+/// window runs to `u64::MAX`.
+pub(super) fn windows(
+    region: &Region,
+    reference_length: Option<u64>,
+    bins: usize,
+) -> Result<Vec<(u64, u64)>> {
+    if bins == 0 || bins > MAX_STATS_BINS {
+        return Err(Error::InvalidInput(format!(
+            "bins must be between 1 and {}",
+            MAX_STATS_BINS
+        )));
+    }
+
+    let (start, end) = region.bounds();
+    let end = match (end, reference_length) {
+        (Some(end), _) => end,
+        (None, Some(length)) => length,
+        (None, None) if bins == 1 => u64::MAX,
+        (None, None) => {
+            return Err(Error::InvalidInput(format!(
+                "end is required for bins > 1: length of {} is unknown",
+                region.reference_name
+            )));
+        }
+    };
+    if end <= start {
+        return Err(Error::InvalidRange(format!("{}-{}", start, end)));
+    }
+
+    let width = (end - start).div_ceil(bins as u64);
+    Ok((0..bins as u64)
+        .map(|i| start + i * width)
+        .take_while(|&window_start| window_start < end)
+        .map(|window_start| (window_start, window_start.saturating_add(width).min(end)))
+        .collect())
+}
+
+/// Records the index maps to a reference sequence, and the estimated share
+/// of them in each window.
+pub(super) fn estimate_records<I>(
+    index: &Index<I>,
+    reference_sequence_id: usize,
+    windows: &[(u64, u64)],
+) -> Result<(u64, Vec<u64>)>
+where
+    I: ReferenceIndex,
+    Index<I>: BinningIndex,
+{
+    let metadata = index
+        .reference_sequences()
+        .get(reference_sequence_id)
+        .and_then(|reference_sequence| reference_sequence.metadata());
+    let Some(metadata) = metadata else {
+        // No records were indexed for this reference
+        return Ok((0, vec![0; windows.len()]));
+    };
+    let records = metadata.mapped_record_count();
+    let span = compressed_span(metadata);
+
+    let estimates = windows
+        .iter()
+        .map(|&(start, end)| {
+            let chunks = index
+                .query(reference_sequence_id, interval(start, Some(end))?)
+                .map_err(|e| Error::Internal(format!("index query failed: {}", e)))?;
+            // A window inside a single BGZF block maps to chunks covering no
+            // compressed bytes; any overlapping chunk counts as one record
+            let overlapping = !chunks.is_empty();
+            Ok(share(records, covered_bytes(chunks), span)
+                .max(u64::from(overlapping && records > 0)))
+        })
+        .collect::<Result<_>>()?;
+
+    Ok((records, estimates))
+}
+
+fn compressed_span(metadata: &Metadata) -> u64 {
+    metadata
+        .end_position()
+        .compressed()
+        .saturating_sub(metadata.start_position().compressed())
+}
+
+/// Compressed bytes covered by `chunks`, counting overlaps once
+pub(super) fn covered_bytes(mut chunks: Vec<Chunk>) -> u64 {
+    chunks.sort_by_key(|chunk| chunk.start());
+
+    let mut covered = 0;
+    let mut current: Option<(u64, u64)> = None;
+    for chunk in chunks {
+        let (start, end) = (chunk.start().compressed(), chunk.end().compressed());
+        match &mut current {
+            Some((_, current_end)) if start <= *current_end => {
+                *current_end = (*current_end).max(end);
+            }
+            _ => {
+                if let Some((s, e)) = current.replace((start, end)) {
+                    covered += e - s;
+                }
+            }
+        }
+    }
+    if let Some((s, e)) = current {
+        covered += e - s;
+    }
+
+    covered
+}
+
+/// `records` scaled by the fraction `covered / span`, capped at `records`
+fn share(records: u64, covered: u64, span: u64) -> u64 {
+    if span == 0 || covered >= span {
+        return records;
+    }
+    (records as u128 * covered as u128 / span as u128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use noodles::bgzf::VirtualPosition;
+
+    fn chunk(start: u64, end: u64) -> Chunk {
+        Chunk::new(
+            VirtualPosition::try_from((start, 0)).unwrap(),
+            VirtualPosition::try_from((end, 0)).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_covered_bytes() {
+        assert_eq!(covered_bytes(vec![]), 0);
+        assert_eq!(
+            covered_bytes(vec![chunk(100, 200), chunk(0, 50), chunk(150, 300)]),
+            250
+        );
+    }
+
+    #[test]
+    fn test_share() {
+        assert_eq!(share(1000, 250, 1000), 250);
+        assert_eq!(share(1000, 2000, 1000), 1000);
+        assert_eq!(share(1000, 0, 1000), 0);
+        assert_eq!(share(3, 1, 1000), 0);
+        assert_eq!(share(10, 0, 0), 10);
+    }
+}
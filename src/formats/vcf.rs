@@ -1,18 +1,25 @@
-use super::IndexedRanges;
+use super::header::{self, HeaderParsing, RawHeader};
+use super::{IndexReader, IndexedRanges, blocks, region_interval};
 use crate::storage::ByteRange;
-use crate::types::Region;
+use crate::types::{Format, Region};
 use crate::{Error, Result};
+use async_trait::async_trait;
 use noodles::bgzf;
-use noodles::core::Position;
-use noodles::core::region::Interval;
+use noodles::bgzf::VirtualPosition;
 use noodles::csi::binning_index::BinningIndex;
 use noodles::csi::binning_index::index::reference_sequence::bin::Chunk;
 use noodles::tabix;
 use noodles::vcf;
 use std::path::Path;
 use tokio::fs::File;
+use tokio::io::AsyncBufReadExt;
 
-pub struct VcfIndexReader;
+/// VCF index reader. Its static functions parse headers strictly; as an
+/// [`IndexReader`] it parses them as `header_parsing` says.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VcfIndexReader {
+    pub header_parsing: HeaderParsing,
+}
 
 impl VcfIndexReader {
     /// Read tabix index and compute byte ranges for given regions
@@ -20,6 +27,15 @@ impl VcfIndexReader {
         vcf_path: &Path,
         index_path: &Path,
         regions: &[Region],
+    ) -> Result<IndexedRanges> {
+        Self::query(vcf_path, index_path, regions, HeaderParsing::Strict).await
+    }
+
+    async fn query(
+        vcf_path: &Path,
+        index_path: &Path,
+        regions: &[Region],
+        parsing: HeaderParsing,
     ) -> Result<IndexedRanges> {
         // Read the tabix index
         let index = tabix::r#async::read(index_path)
@@ -27,7 +43,7 @@ impl VcfIndexReader {
             .map_err(|e| Error::Internal(format!("failed to read tabix index: {}", e)))?;
 
         // Compute header byte range
-        let header_range = Self::header_range(vcf_path).await?;
+        let header_range = Self::header_range_with(vcf_path, parsing).await?;
 
         // If no regions specified, return empty data_ranges (caller should serve whole file)
         if regions.is_empty() {
@@ -58,23 +74,11 @@ impl VcfIndexReader {
                     ))
                 })?;
 
-            // Build interval from region coordinates
-            // htsget uses 0-based half-open coordinates, noodles uses 1-based closed
-            let start = region
-                .start
-                .map(|s| Position::try_from(s as usize + 1))
-                .transpose()
-                .map_err(|e| Error::InvalidRange(format!("invalid start position: {}", e)))?
-                .unwrap_or(Position::MIN);
-
-            let end = region
-                .end
-                .map(|e| Position::try_from(e as usize))
-                .transpose()
-                .map_err(|e| Error::InvalidRange(format!("invalid end position: {}", e)))?
-                .unwrap_or(Position::MAX);
-
-            let interval = Interval::from(start..=end);
+            // An empty region (e.g. `end=0`) covers no records
+            if region.is_empty() {
+                continue;
+            }
+            let interval = region_interval(region)?;
 
             // Query the index for chunks overlapping this region
             let region_chunks = index
@@ -84,17 +88,15 @@ impl VcfIndexReader {
             chunks.extend(region_chunks);
         }
 
-        // Convert chunks to byte ranges
-        let mut data_ranges: Vec<ByteRange> = chunks
-            .into_iter()
-            .map(|chunk| ByteRange {
-                start: chunk.start().compressed(),
-                end: Some(chunk.end().compressed()),
-            })
-            .collect();
+        // Convert chunks to block-aligned byte ranges
+        let mut data_ranges = blocks::chunk_ranges(vcf_path, &chunks).await?;
 
         // Merge overlapping/adjacent ranges for efficiency
         data_ranges = Self::merge_ranges(data_ranges);
+        // Records sharing the header's last block come with the header
+        if let Some(header_end) = header_range.end {
+            data_ranges = blocks::after_header(data_ranges, header_end);
+        }
 
         Ok(IndexedRanges {
             header_range,
@@ -102,27 +104,81 @@ impl VcfIndexReader {
         })
     }
 
-    /// Compute the header byte range by reading the VCF file
+    /// Virtual position just past the VCF header
+    pub async fn header_end(vcf_path: &Path) -> Result<VirtualPosition> {
+        let (_, end) = Self::parse_header(vcf_path, HeaderParsing::Strict).await?;
+        Ok(end)
+    }
+
+    /// Compute the header byte range by reading the VCF file. The range
+    /// runs to the end of the header's last block so it decompresses standalone
     pub async fn header_range(vcf_path: &Path) -> Result<ByteRange> {
+        Self::header_range_with(vcf_path, HeaderParsing::Strict).await
+    }
+
+    async fn header_range_with(vcf_path: &Path, parsing: HeaderParsing) -> Result<ByteRange> {
+        let (_, header_end) = Self::parse_header(vcf_path, parsing).await?;
+        blocks::header_range(vcf_path, header_end).await
+    }
+
+    /// Read the VCF header
+    pub async fn read_header(vcf_path: &Path) -> Result<vcf::Header> {
+        let (header, _) = Self::parse_header(vcf_path, HeaderParsing::Strict).await?;
+        Ok(header)
+    }
+
+    /// Read the VCF header, and the virtual position just past it. A header
+    /// noodles can't parse is diagnosed, or recovered, per `parsing`.
+    pub async fn parse_header(
+        vcf_path: &Path,
+        parsing: HeaderParsing,
+    ) -> Result<(vcf::Header, VirtualPosition)> {
         let file = File::open(vcf_path)
             .await
             .map_err(|e| Error::Internal(format!("failed to open VCF file: {}", e)))?;
 
         let mut reader = vcf::r#async::io::Reader::new(bgzf::r#async::Reader::new(file));
 
-        // Read header to advance position past it
-        reader
-            .read_header()
+        match reader.read_header().await {
+            Ok(header) => Ok((header, reader.get_ref().virtual_position())),
+            Err(e) if header::is_parse_error(&e) => {
+                let raw = Self::raw_header(vcf_path).await?;
+                let header = header::recover(
+                    Format::Vcf,
+                    vcf_path,
+                    parsing,
+                    &raw.text,
+                    header::parse_vcf_lines,
+                    e,
+                )?;
+                Ok((header, raw.end))
+            }
+            Err(e) => Err(header::read_error(Format::Vcf, vcf_path, "header", e)),
+        }
+    }
+
+    /// The header text as stored, up to and including the `#CHROM` line
+    async fn raw_header(vcf_path: &Path) -> Result<RawHeader> {
+        let error = |e| header::read_error(Format::Vcf, vcf_path, "header", e);
+
+        let file = File::open(vcf_path)
             .await
-            .map_err(|e| Error::Internal(format!("failed to read VCF header: {}", e)))?;
+            .map_err(|e| Error::Internal(format!("failed to open VCF file: {}", e)))?;
+        let mut reader = bgzf::r#async::Reader::new(file);
 
-        // Get the virtual position after the header
-        let header_end = reader.get_ref().virtual_position();
+        let mut text = Vec::new();
+        loop {
+            let start = text.len();
+            if reader.read_until(b'\n', &mut text).await.map_err(error)? == 0 {
+                return Err(error(std::io::ErrorKind::UnexpectedEof.into()));
+            }
+            if text[start..].starts_with(b"#CHROM") {
+                break;
+            }
+        }
 
-        Ok(ByteRange {
-            start: 0,
-            end: Some(header_end.compressed()),
-        })
+        let end = reader.virtual_position();
+        Ok(RawHeader { text, end })
     }
 
     /// Merge overlapping or adjacent byte ranges
@@ -157,3 +213,32 @@ impl VcfIndexReader {
         merged
     }
 }
+
+#[async_trait]
+impl IndexReader for VcfIndexReader {
+    type Header = ();
+
+    async fn header_range(&self, path: &Path) -> Result<ByteRange> {
+        VcfIndexReader::header_range_with(path, self.header_parsing).await
+    }
+
+    /// Plain VCF has no blocks to slice; it is only served whole
+    fn check_header(&self, path: &Path) -> Result<()> {
+        if path.extension().is_some_and(|ext| ext == "vcf") {
+            return Err(Error::UnsupportedFormat(
+                "header-only requests need a bgzipped VCF".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    async fn query_ranges(
+        &self,
+        path: &Path,
+        index_path: &Path,
+        regions: &[Region],
+        _header: Option<&()>,
+    ) -> Result<IndexedRanges> {
+        VcfIndexReader::query(path, index_path, regions, self.header_parsing).await
+    }
+}
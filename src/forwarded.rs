@@ -0,0 +1,269 @@
+//! Reverse-proxy awareness.
+//!
+//! Behind nginx or a load balancer the server only sees its own bind address,
+//! so tickets would point at `http://0.0.0.0:8080`. When requests come from a
+//! trusted proxy, [`forwarded_base_url`] derives the public base URL from the
+//! `Forwarded` (RFC 7239) or `X-Forwarded-Proto/Host/Port/Prefix` headers
+//! instead. Headers from untrusted peers are ignored, since anyone could
+//! otherwise point ticket URLs at a host of their choosing.
+
+use crate::{Error, Result};
+use axum::http::HeaderMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Peers whose forwarding headers are honored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    any: bool,
+    networks: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    /// Check whether `addr` is a trusted proxy.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let addr = canonical_ip(addr);
+        self.any
+            || self
+                .networks
+                .iter()
+                .any(|&(network, prefix)| in_network(addr, network, prefix))
+    }
+
+    /// True if no proxies are trusted.
+    pub fn is_empty(&self) -> bool {
+        !self.any && self.networks.is_empty()
+    }
+}
+
+/// Parses a comma-separated list of IPs and CIDR ranges, or `*` for any peer
+/// (e.g. `127.0.0.1,10.0.0.0/8`).
+impl FromStr for TrustedProxies {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut proxies = Self::default();
+
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            if entry == "*" {
+                proxies.any = true;
+                continue;
+            }
+
+            let invalid = || Error::InvalidInput(format!("invalid trusted proxy: {}", entry));
+            let (addr, prefix) = match entry.split_once('/') {
+                Some((addr, prefix)) => (addr, Some(prefix)),
+                None => (entry, None),
+            };
+
+            let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+            let max = if addr.is_ipv4() { 32 } else { 128 };
+            let prefix = match prefix {
+                Some(p) => p
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|&p| p <= max)
+                    .ok_or_else(invalid)?,
+                None => max,
+            };
+
+            proxies.networks.push((addr, prefix));
+        }
+
+        Ok(proxies)
+    }
+}
+
+/// Public base URL described by forwarding headers, if any are present.
+///
+/// `Forwarded` takes precedence over the `X-Forwarded-*` headers; the `Host`
+/// header fills in a missing host. Only the first (client-facing) entry of
+/// multi-valued headers is used.
+pub fn forwarded_base_url(headers: &HeaderMap) -> Option<String> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    };
+
+    let forwarded = header("forwarded").map(parse_forwarded).unwrap_or_default();
+
+    let proto = forwarded.proto.as_deref().or(header("x-forwarded-proto"));
+    let forwarded_host = forwarded.host.as_deref().or(header("x-forwarded-host"));
+    let prefix = header("x-forwarded-prefix");
+
+    if proto.is_none() && forwarded_host.is_none() && prefix.is_none() {
+        return None;
+    }
+
+    let proto = proto.unwrap_or("http");
+    let mut host = forwarded_host.or(header("host"))?.to_string();
+    if let Some(port) = header("x-forwarded-port") {
+        if forwarded_host.is_some() && !host.contains(':') {
+            host = format!("{}:{}", host, port);
+        }
+    }
+    let prefix = prefix.unwrap_or("").trim_end_matches('/');
+
+    let candidate = format!("{}://{}", proto, host);
+    let url = url::Url::parse(&candidate).ok()?;
+
+    // Reject anything that isn't a plain scheme://host[:port]
+    if !matches!(url.scheme(), "http" | "https")
+        || url.path() != "/"
+        || url.query().is_some()
+        || url.fragment().is_some()
+        || !url.username().is_empty()
+        || url.password().is_some()
+        || (!prefix.is_empty() && !prefix.starts_with('/'))
+        || prefix.contains(['?', '#'])
+    {
+        tracing::debug!("ignoring invalid forwarding headers: {}", candidate);
+        return None;
+    }
+
+    Some(format!("{}{}", url.as_str().trim_end_matches('/'), prefix))
+}
+
+#[derive(Debug, Default)]
+struct ForwardedElement {
+    proto: Option<String>,
+    host: Option<String>,
+}
+
+fn parse_forwarded(element: &str) -> ForwardedElement {
+    let mut parsed = ForwardedElement::default();
+
+    for pair in element.split(';') {
+        if let Some((key, value)) = pair.split_once('=') {
+            let value = value.trim().trim_matches('"').to_string();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "proto" => parsed.proto = Some(value),
+                "host" => parsed.host = Some(value),
+                _ => {}
+            }
+        }
+    }
+
+    parsed
+}
+
+/// Map IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`) to IPv4.
+fn canonical_ip(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+        v4 => v4,
+    }
+}
+
+fn in_network(addr: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (addr, network) {
+        (IpAddr::V4(a), IpAddr::V4(n)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(a) & mask == u32::from(n) & mask
+        }
+        (IpAddr::V6(a), IpAddr::V6(n)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(a) & mask == u128::from(n) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, HeaderValue::from_static(value));
+        }
+        map
+    }
+
+    #[test]
+    fn test_trusted_proxies() {
+        let proxies: TrustedProxies = "127.0.0.1, 10.0.0.0/8, fd00::/8".parse().unwrap();
+
+        assert!(proxies.contains("127.0.0.1".parse().unwrap()));
+        assert!(proxies.contains("10.1.2.3".parse().unwrap()));
+        assert!(proxies.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(proxies.contains("fd12::1".parse().unwrap()));
+        assert!(!proxies.contains("192.168.1.1".parse().unwrap()));
+        assert!(!proxies.contains("127.0.0.2".parse().unwrap()));
+
+        let any: TrustedProxies = "*".parse().unwrap();
+        assert!(any.contains("203.0.113.9".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<TrustedProxies>().is_err());
+        assert!("proxy.local".parse::<TrustedProxies>().is_err());
+        assert!("".parse::<TrustedProxies>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_x_forwarded_headers() {
+        let map = headers(&[
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-host", "example.com"),
+            ("x-forwarded-prefix", "/htsget/"),
+        ]);
+        assert_eq!(
+            forwarded_base_url(&map).as_deref(),
+            Some("https://example.com/htsget")
+        );
+    }
+
+    #[test]
+    fn test_forwarded_header_precedence() {
+        let map = headers(&[
+            (
+                "forwarded",
+                "for=192.0.2.60;proto=https;host=\"data.example.org\", for=10.0.0.1",
+            ),
+            ("x-forwarded-host", "ignored.example.com"),
+        ]);
+        assert_eq!(
+            forwarded_base_url(&map).as_deref(),
+            Some("https://data.example.org")
+        );
+    }
+
+    #[test]
+    fn test_host_header_fallback_and_port() {
+        let map = headers(&[("x-forwarded-proto", "https"), ("host", "example.com:8443")]);
+        assert_eq!(
+            forwarded_base_url(&map).as_deref(),
+            Some("https://example.com:8443")
+        );
+
+        let map = headers(&[
+            ("x-forwarded-host", "example.com"),
+            ("x-forwarded-port", "8443"),
+        ]);
+        assert_eq!(
+            forwarded_base_url(&map).as_deref(),
+            Some("http://example.com:8443")
+        );
+    }
+
+    #[test]
+    fn test_no_or_invalid_headers() {
+        assert_eq!(
+            forwarded_base_url(&headers(&[("host", "example.com")])),
+            None
+        );
+        assert_eq!(
+            forwarded_base_url(&headers(&[("x-forwarded-host", "evil.com/path")])),
+            None
+        );
+        assert_eq!(
+            forwarded_base_url(&headers(&[("x-forwarded-proto", "javascript")])),
+            None
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! HTTP caching and conditional requests: entity tags, `Last-Modified`,
+//! `If-None-Match`, `If-Modified-Since`, `If-Range`, and `Cache-Control`.
+
+use super::AppState;
+use crate::Result;
+use crate::types::{Format, HtsgetResponse};
+use axum::{
+    Json,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Strong entity tag over `parts` (FNV-1a, stable across restarts).
+pub(crate) fn entity_tag(parts: &[&str]) -> HeaderValue {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    HeaderValue::from_str(&format!("\"{:016x}\"", hash)).expect("hex is a valid header value")
+}
+
+/// True if the request's `If-None-Match` matches `etag` (weak comparison).
+pub(crate) fn if_none_match(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let Some(value) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+    let etag = etag.to_str().unwrap_or_default();
+
+    value.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.trim_start_matches("W/") == etag.trim_start_matches("W/")
+    })
+}
+
+/// `Last-Modified` value for a modification time.
+pub(crate) fn last_modified(modified: SystemTime) -> HeaderValue {
+    HeaderValue::from_str(&httpdate::fmt_http_date(modified))
+        .expect("HTTP date is a valid header value")
+}
+
+/// True if `If-Modified-Since` shows the client's copy is current.
+///
+/// Ignored when `If-None-Match` is present, which takes precedence.
+pub(crate) fn not_modified_since(headers: &HeaderMap, modified: Option<SystemTime>) -> bool {
+    if headers.contains_key(header::IF_NONE_MATCH) {
+        return false;
+    }
+
+    match (modified, http_date(headers, header::IF_MODIFIED_SINCE)) {
+        (Some(modified), Some(since)) => whole_seconds(modified) <= since,
+        _ => false,
+    }
+}
+
+/// True if a `Range` request may be served as a partial response.
+///
+/// Without `If-Range` this is always the case. Otherwise the validator must
+/// match the current representation: an entity tag by strong comparison, or
+/// a date exactly equal to the modification time. A mismatch means the
+/// client's partial copy is stale and the full representation is sent.
+pub(crate) fn if_range(
+    headers: &HeaderMap,
+    etag: &HeaderValue,
+    modified: Option<SystemTime>,
+) -> bool {
+    let Some(value) = headers.get(header::IF_RANGE) else {
+        return true;
+    };
+
+    if value.as_bytes().starts_with(b"\"") || value.as_bytes().starts_with(b"W/") {
+        return value == etag;
+    }
+
+    match (modified, http_date(headers, header::IF_RANGE)) {
+        (Some(modified), Some(date)) => whole_seconds(modified) == date,
+        _ => false,
+    }
+}
+
+fn http_date(headers: &HeaderMap, name: header::HeaderName) -> Option<SystemTime> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+}
+
+/// HTTP dates have one-second resolution.
+fn whole_seconds(time: SystemTime) -> SystemTime {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(time, |d| UNIX_EPOCH + Duration::from_secs(d.as_secs()))
+}
+
+impl AppState {
+    /// Serve a GET ticket with an ETag, or `304 Not Modified` if the client's
+    /// copy is current.
+    ///
+    /// The tag covers the file version, so tickets revalidate when the file
+    /// changes. Tickets containing signed URLs expire and are never cached.
+    pub(crate) async fn cached_ticket(
+        &self,
+        request_headers: &HeaderMap,
+        id: &str,
+        format: Format,
+        request_key: &str,
+        ticket: Json<HtsgetResponse>,
+    ) -> Result<Response> {
+        if self.signs_urls() {
+            return Ok((
+                [(header::CACHE_CONTROL, "no-store")],
+                self.ticket_response(ticket),
+            )
+                .into_response());
+        }
+
+        let file = self.storage.file_info(id, format).await?;
+        let urls: Vec<&str> = ticket.htsget.urls.iter().map(|u| u.url.as_str()).collect();
+        let etag = entity_tag(&[
+            id,
+            &format!("{:?}", format),
+            request_key,
+            &file.version(),
+            &urls.join(" "),
+        ]);
+
+        let mut response = if if_none_match(request_headers, &etag) {
+            StatusCode::NOT_MODIFIED.into_response()
+        } else {
+            self.ticket_response(ticket).into_response()
+        };
+
+        let headers = response.headers_mut();
+        headers.insert(header::ETAG, etag);
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+        Ok(response)
+    }
+
+    /// `Cache-Control` value for data responses, if configured.
+    pub(crate) fn data_cache_control(&self) -> Option<HeaderValue> {
+        self.cache_control
+            .as_deref()
+            .and_then(|v| HeaderValue::from_str(v).ok())
+    }
+
+    #[cfg(feature = "auth")]
+    fn signs_urls(&self) -> bool {
+        self.url_signer.is_some()
+    }
+
+    #[cfg(not(feature = "auth"))]
+    fn signs_urls(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entity_tag_is_stable() {
+        let a = entity_tag(&["sample1", "Bam", "1024-0"]);
+        let b = entity_tag(&["sample1", "Bam", "1024-0"]);
+        let c = entity_tag(&["sample1", "Bam", "2048-0"]);
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        // Part boundaries matter
+        assert_ne!(entity_tag(&["ab", "c"]), entity_tag(&["a", "bc"]));
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let etag = entity_tag(&["sample1"]);
+        let mut headers = HeaderMap::new();
+        assert!(!if_none_match(&headers, &etag));
+
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+        assert!(!if_none_match(&headers, &etag));
+
+        let list = format!("\"other\", W/{}", etag.to_str().unwrap());
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_str(&list).unwrap());
+        assert!(if_none_match(&headers, &etag));
+
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("*"));
+        assert!(if_none_match(&headers, &etag));
+    }
+
+    #[test]
+    fn test_if_modified_since() {
+        let modified = UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        let mut headers = HeaderMap::new();
+        assert!(!not_modified_since(&headers, Some(modified)));
+
+        headers.insert(header::IF_MODIFIED_SINCE, last_modified(modified));
+        assert!(not_modified_since(&headers, Some(modified)));
+        assert!(!not_modified_since(
+            &headers,
+            Some(modified + Duration::from_secs(1))
+        ));
+        assert!(!not_modified_since(&headers, None));
+
+        // If-None-Match takes precedence
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+        assert!(!not_modified_since(&headers, Some(modified)));
+    }
+
+    #[test]
+    fn test_if_range() {
+        let etag = entity_tag(&["sample1"]);
+        let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut headers = HeaderMap::new();
+        assert!(if_range(&headers, &etag, Some(modified)));
+
+        headers.insert(header::IF_RANGE, etag.clone());
+        assert!(if_range(&headers, &etag, Some(modified)));
+
+        headers.insert(header::IF_RANGE, HeaderValue::from_static("\"stale\""));
+        assert!(!if_range(&headers, &etag, Some(modified)));
+
+        // Weak tags never match
+        let weak = format!("W/{}", etag.to_str().unwrap());
+        headers.insert(header::IF_RANGE, HeaderValue::from_str(&weak).unwrap());
+        assert!(!if_range(&headers, &etag, Some(modified)));
+
+        headers.insert(header::IF_RANGE, last_modified(modified));
+        assert!(if_range(&headers, &etag, Some(modified)));
+        assert!(!if_range(
+            &headers,
+            &etag,
+            Some(modified + Duration::from_secs(1))
+        ));
+        assert!(!if_range(&headers, &etag, None));
+    }
+}
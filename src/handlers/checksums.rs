@@ -0,0 +1,163 @@
+//! Whole-file checksums (extension): `GET /checksums/:endpoint/:id` reports
+//! the MD5, SHA-256 and CRC32C of a file, so clients can verify full-file
+//! downloads. Checksums the backend stores (an MD5 ETag, S3 checksum
+//! metadata) are used as they are; the rest are computed by reading the
+//! file once and kept until it changes.
+
+use super::AppState;
+use super::meta::{MetaQuery, endpoint_format};
+use crate::storage::Storage;
+use crate::types::{Checksums, ChecksumsResponse, FileChecksums, Format};
+use crate::{Error, Result};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use md5::{Digest, Md5};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncReadExt;
+
+/// Bytes read from storage at a time while hashing
+const READ_SIZE: usize = 1024 * 1024;
+
+/// Computed checksums, kept per file until its version changes
+#[derive(Debug, Clone, Default)]
+pub struct ChecksumCache {
+    computed: Arc<Mutex<HashMap<(String, Format), (String, Checksums)>>>,
+}
+
+impl ChecksumCache {
+    /// Checksums computed earlier for this `version` of a file
+    pub(crate) fn get(&self, id: &str, format: Format, version: &str) -> Option<Checksums> {
+        self.computed
+            .lock()
+            .unwrap()
+            .get(&(id.to_string(), format))
+            .filter(|(cached, _)| cached == version)
+            .map(|(_, checksums)| checksums.clone())
+    }
+
+    /// Checksums of this `version` of a file, computed unless cached
+    async fn get_or_compute(
+        &self,
+        state: &AppState,
+        id: &str,
+        format: Format,
+        version: &str,
+    ) -> Result<Checksums> {
+        if let Some(checksums) = self.get(id, format, version) {
+            return Ok(checksums);
+        }
+
+        let checksums = compute(state.storage.as_ref(), id, format).await?;
+        self.computed.lock().unwrap().insert(
+            (id.to_string(), format),
+            (version.to_string(), checksums.clone()),
+        );
+        Ok(checksums)
+    }
+}
+
+/// Read a whole file through storage, hashing it
+async fn compute(storage: &dyn Storage, id: &str, format: Format) -> Result<Checksums> {
+    tracing::info!("computing checksums of {}", id);
+    let mut reader = storage.read_stream(id, format, None).await?;
+    let (mut md5, mut sha256, mut crc32c) = (Md5::new(), Sha256::new(), 0u32);
+
+    let mut buf = vec![0; READ_SIZE];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        md5.update(&buf[..n]);
+        sha256.update(&buf[..n]);
+        crc32c = crc32c::crc32c_append(crc32c, &buf[..n]);
+    }
+
+    Ok(Checksums {
+        md5: Some(encode_hex(&md5.finalize())),
+        sha256: Some(encode_hex(&sha256.finalize())),
+        crc32c: Some(encode_hex(&crc32c.to_be_bytes())),
+        etag: None,
+    })
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `GET /checksums/:endpoint/:id`
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/checksums/{endpoint}/{id}",
+        params(("endpoint" = String, Path, description = "`reads`, `variants` or `sequences`"), ("id" = String, Path, description = "Dataset ID"), MetaQuery),
+        responses(
+            (status = 200, description = "Whole-file checksums", body = crate::types::ChecksumsResponse),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "extensions"
+    )
+)]
+pub async fn get_checksums(
+    State(state): State<AppState>,
+    Path((endpoint, id)): Path<(String, String)>,
+    Query(query): Query<MetaQuery>,
+) -> Result<Json<ChecksumsResponse>> {
+    let format = endpoint_format(&endpoint, query.format)?;
+    if !state.endpoints.serves(format) {
+        return Err(Error::NotFound(format!("endpoint {}", endpoint)));
+    }
+
+    let (info, stored) = tokio::join!(
+        state.storage.file_info(&id, format),
+        state.storage.stored_checksums(&id, format),
+    );
+    let info = info?;
+    let mut checksums = stored?;
+    if !checksums.is_complete() {
+        let computed = state
+            .checksums
+            .get_or_compute(&state, &id, format, &info.version())
+            .await?;
+        checksums = checksums.or(&computed);
+    }
+
+    Ok(Json(ChecksumsResponse {
+        htsget: FileChecksums {
+            id,
+            format,
+            size: info.size,
+            checksums,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalStorage;
+
+    #[tokio::test]
+    async fn test_compute() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("hello.fa"), "hello world").unwrap();
+        let storage = LocalStorage::new(dir.path().to_path_buf(), "http://localhost".to_string());
+
+        let checksums = compute(&storage, "hello", Format::Fasta).await.unwrap();
+        assert_eq!(
+            checksums.md5.as_deref(),
+            Some("5eb63bbbe01eeed093cb22bb8f5acdc3")
+        );
+        assert_eq!(
+            checksums.sha256.as_deref(),
+            Some("b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9")
+        );
+        assert_eq!(checksums.crc32c.as_deref(), Some("c99465aa"));
+    }
+}
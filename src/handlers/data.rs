@@ -1,81 +1,731 @@
-use super::AppState;
-use crate::storage::ByteRange;
-use crate::{Error, Result, types::Format};
+use super::caching::{entity_tag, if_none_match, if_range, last_modified, not_modified_since};
+use super::{AppState, DataServed, RequestSubject};
+use crate::formats::PreciseSlice;
+use crate::storage::{ByteRange, data_endpoint_url};
+use crate::types::{DataClass, Format, Region};
+use crate::{Error, Result};
 use axum::{
     body::Body,
     extract::{Path, Query, State},
-    http::{StatusCode, header},
-    response::Response,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
 };
+use bytes::Bytes;
+use moka::future::Cache;
 use serde::Deserialize;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
+use tokio_util::io::ReaderStream;
+
+/// Read size for streamed data responses; large chunks keep per-frame
+/// overhead low on multi-GB files.
+pub(super) const STREAM_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Response extension marking a body that is already compressed (BGZF or a
+/// binary format), so the compression layer leaves it alone.
+#[derive(Debug, Clone, Copy)]
+pub struct Precompressed;
+
+/// How the data endpoint delivers bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DataMode {
+    /// Read from storage and stream through the server
+    #[default]
+    Proxy,
+    /// Redirect (302) to a direct storage URL when the backend has one, such
+    /// as a presigned S3 URL, keeping bandwidth off the server
+    Redirect,
+}
+
+impl FromStr for DataMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "proxy" => Ok(DataMode::Proxy),
+            "redirect" => Ok(DataMode::Redirect),
+            _ => Err(format!(
+                "unknown data mode: {} (expected 'proxy' or 'redirect')",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for DataMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataMode::Proxy => write!(f, "proxy"),
+            DataMode::Redirect => write!(f, "redirect"),
+        }
+    }
+}
+
+/// What `class=header` tickets point at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeaderMode {
+    /// The header's byte range in the stored file
+    #[default]
+    Range,
+    /// A copy of the header re-compressed into BGZF blocks of its own
+    /// (BAM, BCF, VCF), served by the data endpoint. It ends on a block
+    /// boundary even when records share the header's last block, so body
+    /// slices can always follow it.
+    Standalone,
+}
+
+impl FromStr for HeaderMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "range" => Ok(HeaderMode::Range),
+            "standalone" => Ok(HeaderMode::Standalone),
+            _ => Err(format!(
+                "unknown header mode: {} (expected 'range' or 'standalone')",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for HeaderMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeaderMode::Range => write!(f, "range"),
+            HeaderMode::Standalone => write!(f, "standalone"),
+        }
+    }
+}
+
+/// Standalone headers for [`HeaderMode::Standalone`], materialized on first
+/// request and kept per file version, up to 256 MiB of them for an hour
+#[derive(Debug, Clone)]
+pub struct HeaderCache {
+    headers: Cache<(String, Format, String), Bytes>,
+}
+
+/// Total size of the standalone headers kept
+const HEADER_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// How long a standalone header is kept
+const HEADER_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+impl Default for HeaderCache {
+    fn default() -> Self {
+        Self {
+            headers: Cache::builder()
+                .weigher(|_, header: &Bytes| u32::try_from(header.len()).unwrap_or(u32::MAX))
+                .max_capacity(HEADER_CACHE_BYTES)
+                .time_to_live(HEADER_CACHE_TTL)
+                .build(),
+        }
+    }
+}
+
+impl HeaderCache {
+    async fn get(
+        &self,
+        state: &AppState,
+        id: &str,
+        format: Format,
+        version: &str,
+    ) -> Result<Bytes> {
+        let key = (id.to_string(), format, version.to_string());
+        if let Some(header) = self.headers.get(&key).await {
+            return Ok(header);
+        }
+
+        let path = state.storage.file_path(id, format);
+        let header = Bytes::from(crate::formats::standalone_header(format, &path).await?);
+        self.headers.insert(key, header.clone()).await;
+        Ok(header)
+    }
+}
+
+impl AppState {
+    /// Data endpoint URL of the standalone header for a `class=header`
+    /// ticket, or `None` to use the header's byte range.
+    pub(crate) fn standalone_header_url(&self, id: &str, format: Format) -> Option<String> {
+        let bgzf = matches!(format, Format::Bam | Format::Vcf | Format::Bcf);
+        (self.standalone_headers.is_some() && bgzf).then(|| {
+            format!(
+                "{}&class=header",
+                data_endpoint_url(&self.base_url, id, format, None)
+            )
+        })
+    }
+}
 
 #[derive(Debug, Deserialize)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
 pub struct DataQuery {
+    /// Byte offset of the block, or with `precise` the region's 0-based start
     pub start: Option<u64>,
+    /// Exclusive byte offset of the block's end, or with `precise` the
+    /// region's end
     pub end: Option<u64>,
-    /// Explicit format override (BAM, CRAM, VCF, BCF, FASTA, FASTQ)
+    /// Explicit format override (BAM, CRAM, VCF, BCF, FASTA, FASTQ; any case)
+    #[serde(default, deserialize_with = "deserialize_format")]
     pub format: Option<Format>,
+    /// `header` for the standalone header (see [`HeaderMode::Standalone`])
+    pub class: Option<DataClass>,
+    /// Reference sequence of a `precise` slice
+    #[serde(rename = "referenceName")]
+    pub reference_name: Option<String>,
+    /// `true` for the records overlapping a region rather than a byte range
+    /// (BAM only), as in `precise` tickets
+    #[serde(default)]
+    pub precise: bool,
 }
 
 /// Serve raw data blocks - this is what the ticket URLs point to
+///
+/// In [`DataMode::Redirect`], whole-object requests are redirected to the
+/// storage backend's direct URL when it has one.
+///
+/// Responses carry a strong ETag over the file version and requested block,
+/// plus `Last-Modified` when the backend reports it; `If-None-Match` and
+/// `If-Modified-Since` are answered with `304 Not Modified`. A `Range` header
+/// selects bytes within the block, so interrupted downloads can resume, and is
+/// ignored if `If-Range` shows the object changed since the partial copy.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/data/{format}/{id}",
+        params(("format" = String, Path, description = "Endpoint (`reads`, `variants`, `sequences`) or format"), ("id" = String, Path, description = "Dataset ID"), DataQuery),
+        responses(
+            (status = 200, description = "Data block", content_type = "application/octet-stream"),
+            (status = 206, description = "Part of a data block selected by `Range`"),
+            (status = 302, description = "Redirect to the storage backend"),
+            (status = 304, description = "Not modified"),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "data"
+    )
+)]
 pub async fn get_data(
     State(state): State<AppState>,
+    subject: RequestSubject,
+    headers: HeaderMap,
     Path((format_str, id)): Path<(String, String)>,
     Query(query): Query<DataQuery>,
+) -> Result<Response> {
+    serve_data(&state, subject, &headers, &format_str, id, query, true).await
+}
+
+/// `HEAD` for data blocks: the same status and headers as [`get_data`],
+/// including `Content-Length`, computed from file metadata without reading
+/// the data.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        head,
+        path = "/data/{format}/{id}",
+        params(("format" = String, Path, description = "Endpoint (`reads`, `variants`, `sequences`) or format"), ("id" = String, Path, description = "Dataset ID"), DataQuery),
+        responses(
+            (status = 200, description = "Data block headers"),
+            (status = 304, description = "Not modified"),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "data"
+    )
+)]
+pub async fn head_data(
+    State(state): State<AppState>,
+    subject: RequestSubject,
+    headers: HeaderMap,
+    Path((format_str, id)): Path<(String, String)>,
+    Query(query): Query<DataQuery>,
+) -> Result<Response> {
+    serve_data(&state, subject, &headers, &format_str, id, query, false).await
+}
+
+async fn serve_data(
+    state: &AppState,
+    subject: RequestSubject,
+    headers: &HeaderMap,
+    format_str: &str,
+    id: String,
+    query: DataQuery,
+    with_body: bool,
 ) -> Result<Response> {
     // Use explicit format if provided, otherwise infer from path
+    let path_format = parse_format(format_str)?;
     let format = match query.format {
+        Some(f) if !path_format.alternatives().contains(&f) => {
+            return Err(Error::InvalidInput(format!(
+                "format {} doesn't match data path {}",
+                f, format_str
+            )));
+        }
         Some(f) => f,
-        None => parse_format(&format_str)?,
+        None => path_format,
     };
 
-    if !state.storage.exists(&id, format).await? {
+    if !state.endpoints.serves(format) || !state.storage.exists(&id, format).await? {
         return Err(Error::NotFound(id));
     }
 
+    if query.class == Some(DataClass::Header) {
+        return serve_standalone_header(state, subject, headers, &id, format, with_body).await;
+    }
+    if query.precise {
+        return serve_precise(state, subject, headers, id, format, query, with_body).await;
+    }
+
     let range = match (query.start, query.end) {
+        (Some(start), Some(end)) if end < start => {
+            return Err(Error::RangeNotSatisfiable(format!(
+                "end {} is before start {}",
+                end, start
+            )));
+        }
         (Some(start), end) => Some(ByteRange { start, end }),
-        _ => None,
+        (None, Some(end)) => {
+            return Err(Error::RangeNotSatisfiable(format!(
+                "end {} without start",
+                end
+            )));
+        }
+        (None, None) => None,
     };
 
-    let bytes = state.storage.read_bytes(&id, format, range.clone()).await?;
+    // Presigned URLs can't carry a block range (it would have to be sent as
+    // a header), so only whole-object GETs are redirected. The client's own
+    // Range and conditional headers then go to storage with the redirect.
+    let redirect = if state.data_mode == DataMode::Redirect && with_body && range.is_none() {
+        state.storage.redirect_url(&id, format).await?
+    } else {
+        None
+    };
+    if let Some(url) = redirect {
+        let location = HeaderValue::from_str(&url)
+            .map_err(|_| Error::Internal(format!("invalid redirect URL for {}", id)))?;
+        let mut response = StatusCode::FOUND.into_response();
+        response.headers_mut().insert(header::LOCATION, location);
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        return Ok(response);
+    }
 
-    // Determine response status and headers based on whether range was requested
-    let (status, content_range) = if let Some(ref r) = range {
-        // Get total file size for Content-Range header
-        let file_info = state.storage.file_info(&id, format).await?;
-        let total_size = file_info.size;
+    let file_info = state.storage.file_info(&id, format).await?;
+    let etag = entity_tag(&[
+        &id,
+        &format!("{:?}", format),
+        &file_info.version(),
+        &format!("{:?}", range.as_ref().map(|r| (r.start, r.end))),
+    ]);
 
-        // Calculate actual byte range returned
-        let start = r.start;
-        let actual_end = start + bytes.len() as u64;
+    let mut validators = HeaderMap::new();
+    validators.insert(header::ETAG, etag.clone());
+    if let Some(modified) = file_info.modified {
+        validators.insert(header::LAST_MODIFIED, last_modified(modified));
+    }
+    if let Some(cache_control) = state.data_cache_control() {
+        validators.insert(header::CACHE_CONTROL, cache_control);
+    }
 
-        // Content-Range: bytes start-end/total
-        let content_range = format!("bytes {}-{}/{}", start, actual_end - 1, total_size);
+    if if_none_match(headers, &etag) || not_modified_since(headers, file_info.modified) {
+        let mut response = StatusCode::NOT_MODIFIED.into_response();
+        response.headers_mut().extend(validators);
+        return Ok(response);
+    }
 
-        (StatusCode::PARTIAL_CONTENT, Some(content_range))
+    // Bytes selected by the URL, with the end clamped to the file; a Range
+    // header addresses bytes within them
+    let block_start = range.as_ref().map_or(0, |r| r.start);
+    let block_end = range
+        .as_ref()
+        .and_then(|r| r.end)
+        .unwrap_or(file_info.size)
+        .min(file_info.size);
+    let block_len = block_end.saturating_sub(block_start);
+
+    if range.is_some() && block_start >= file_info.size {
+        let message = format!("start {} is past the end of {}", block_start, id);
+        return Ok(range_not_satisfiable(message, file_info.size, validators));
+    }
+    let range = range.map(|r| ByteRange {
+        start: r.start,
+        end: Some(block_end),
+    });
+
+    let requested = match headers.get(header::RANGE) {
+        Some(value) if if_range(headers, &etag, file_info.modified) => {
+            match parse_range(value, block_len) {
+                Some(Some(requested)) => Some(requested),
+                Some(None) => {
+                    let message = format!("{} for {}", value.to_str().unwrap_or_default(), id);
+                    return Ok(range_not_satisfiable(message, block_len, validators));
+                }
+                None => None,
+            }
+        }
+        _ => None,
+    };
+
+    // Absolute bytes to read, plus the offset and total reported in Content-Range
+    let (status, read_range, reported) = match (requested, range) {
+        (Some((first, last)), _) => (
+            StatusCode::PARTIAL_CONTENT,
+            Some(ByteRange {
+                start: block_start + first,
+                end: Some(block_start + last + 1),
+            }),
+            Some((first, block_len)),
+        ),
+        (None, Some(r)) => {
+            let start = r.start;
+            (
+                StatusCode::PARTIAL_CONTENT,
+                Some(r),
+                Some((start, file_info.size)),
+            )
+        }
+        (None, None) => (StatusCode::OK, None, None),
+    };
+
+    let len = match &read_range {
+        Some(r) => r
+            .end
+            .unwrap_or(file_info.size)
+            .min(file_info.size)
+            .saturating_sub(r.start),
+        None => file_info.size,
+    };
+
+    // Stream the body, peeking at its first bytes to spot BGZF
+    let (body, gzip) = if with_body {
+        let reader = state
+            .storage
+            .read_stream(&id, format, read_range.clone())
+            .await?;
+        let mut reader = BufReader::with_capacity(STREAM_CHUNK_SIZE, reader);
+        let gzip = reader.fill_buf().await?.starts_with(&[0x1f, 0x8b]);
+        let stream = ReaderStream::with_capacity(reader, STREAM_CHUNK_SIZE);
+        (Body::from_stream(stream), gzip)
     } else {
-        (StatusCode::OK, None)
+        (Body::empty(), false)
     };
 
+    // BAM/CRAM/BCF are always compressed; text formats may be stored as BGZF.
+    // HEAD responses have no body, so their Content-Length must be left as is.
+    let precompressed =
+        matches!(format, Format::Bam | Format::Cram | Format::Bcf) || gzip || !with_body;
+
     let mut builder = Response::builder()
         .status(status)
         .header(header::CONTENT_TYPE, format.content_type())
-        .header(header::CONTENT_LENGTH, bytes.len())
+        .header(header::CONTENT_LENGTH, len)
         .header(header::ACCEPT_RANGES, "bytes");
 
-    if let Some(cr) = content_range {
-        builder = builder.header(header::CONTENT_RANGE, cr);
+    // Content-Range: bytes start-end/total
+    if let Some((first, total)) = reported {
+        let last = first + len.max(1) - 1;
+        let content_range = format!("bytes {}-{}/{}", first, last, total);
+        builder = builder.header(header::CONTENT_RANGE, content_range);
+    }
+
+    if precompressed {
+        builder = builder.extension(Precompressed);
+    }
+
+    let mut response = builder.body(body).unwrap();
+    response.headers_mut().extend(validators);
+
+    if with_body {
+        state
+            .observe_data_served(DataServed {
+                id,
+                format,
+                range: read_range,
+                bytes: len,
+                subject: subject.0,
+            })
+            .await;
+    }
+    Ok(response)
+}
+
+/// A 416 response with an htsget `InvalidRange` body and the size of the
+/// addressable bytes in `Content-Range`.
+fn range_not_satisfiable(message: String, len: u64, validators: HeaderMap) -> Response {
+    let mut response = Error::RangeNotSatisfiable(message).into_response();
+    response.headers_mut().extend(validators);
+    response.headers_mut().insert(
+        header::CONTENT_RANGE,
+        HeaderValue::from_str(&format!("bytes */{}", len)).unwrap(),
+    );
+    response
+}
+
+/// Serve a file's standalone header, materializing it on first request.
+/// Headers are small, so `Range` requests get the whole of it. Without
+/// [`HeaderMode::Standalone`] they are only linked from `precise` tickets,
+/// and aren't cached.
+async fn serve_standalone_header(
+    state: &AppState,
+    subject: RequestSubject,
+    headers: &HeaderMap,
+    id: &str,
+    format: Format,
+    with_body: bool,
+) -> Result<Response> {
+    if state.standalone_headers.is_none() && format != Format::Bam {
+        return Err(Error::NotFound(format!("standalone header for {}", id)));
     }
 
-    Ok(builder.body(Body::from(bytes)).unwrap())
+    let file_info = state.storage.file_info(id, format).await?;
+    let version = file_info.version();
+    let etag = entity_tag(&[id, &format!("{:?}", format), &version, "header"]);
+
+    let mut response = if if_none_match(headers, &etag) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        let header = match &state.standalone_headers {
+            Some(cache) => cache.get(state, id, format, &version).await?,
+            None => {
+                let path = state.storage.file_path(id, format);
+                Bytes::from(crate::formats::standalone_header(format, &path).await?)
+            }
+        };
+        let len = header.len();
+        let body = if with_body {
+            state
+                .observe_data_served(DataServed {
+                    id: id.to_string(),
+                    format,
+                    range: None,
+                    bytes: len as u64,
+                    subject: subject.0,
+                })
+                .await;
+            Body::from(header)
+        } else {
+            Body::empty()
+        };
+        Response::builder()
+            .header(header::CONTENT_TYPE, format.content_type())
+            .header(header::CONTENT_LENGTH, len)
+            .extension(Precompressed)
+            .body(body)
+            .unwrap()
+    };
+
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::ETAG, etag);
+    if let Some(cache_control) = state.data_cache_control() {
+        response_headers.insert(header::CACHE_CONTROL, cache_control);
+    }
+    Ok(response)
 }
 
+/// Serve the records of a BAM file overlapping the region in `query`,
+/// decoded and re-compressed as they are read. The length isn't known up
+/// front, so there's no `Content-Length` and `Range` is ignored.
+async fn serve_precise(
+    state: &AppState,
+    subject: RequestSubject,
+    headers: &HeaderMap,
+    id: String,
+    format: Format,
+    query: DataQuery,
+    with_body: bool,
+) -> Result<Response> {
+    if format != Format::Bam {
+        return Err(Error::UnsupportedFormat(format!(
+            "precise slicing is only supported for BAM, not {:?}",
+            format
+        )));
+    }
+    let Some(reference_name) = query.reference_name else {
+        return Err(Error::InvalidInput(
+            "precise requires referenceName".to_string(),
+        ));
+    };
+    let region = Region::new(reference_name, query.start, query.end)?;
+
+    let Some(index_path) = state.locate(&id, format, true).await? else {
+        return Err(Error::NotFound(format!("index for {}", id)));
+    };
+    let path = state.storage.file_path(&id, format);
+    let region = state
+        .resolve_regions(format, &path, &index_path, std::slice::from_ref(&region))
+        .await?
+        .into_owned()
+        .remove(0);
+
+    let file_info = state.storage.file_info(&id, format).await?;
+    let etag = entity_tag(&[
+        &id,
+        &format!("{:?}", format),
+        &file_info.version(),
+        &format!("{:?}", region),
+        "precise",
+    ]);
+    let mut validators = HeaderMap::new();
+    validators.insert(header::ETAG, etag.clone());
+    if let Some(cache_control) = state.data_cache_control() {
+        validators.insert(header::CACHE_CONTROL, cache_control);
+    }
+    if if_none_match(headers, &etag) {
+        let mut response = StatusCode::NOT_MODIFIED.into_response();
+        response.headers_mut().extend(validators);
+        return Ok(response);
+    }
+
+    // Open before responding, so a missing reference is a 404
+    let header = state.index_readers.get(format)?.read_header(&path).await?;
+    let slice = PreciseSlice::open(&path, &index_path, region, header).await?;
+
+    let body = if with_body {
+        let (sender, mut receiver) = mpsc::channel(4);
+        let state = state.clone();
+        tokio::spawn(async move {
+            match slice.send(&sender).await {
+                Ok(bytes) => {
+                    state
+                        .observe_data_served(DataServed {
+                            id,
+                            format,
+                            range: None,
+                            bytes,
+                            subject: subject.0,
+                        })
+                        .await;
+                }
+                Err(e) => {
+                    tracing::warn!("precise slice of {} failed: {}", id, e);
+                    let _ = sender.send(Err(std::io::Error::other(e.to_string()))).await;
+                }
+            }
+        });
+        Body::from_stream(futures::stream::poll_fn(move |cx| receiver.poll_recv(cx)))
+    } else {
+        Body::empty()
+    };
+
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, format.content_type())
+        .extension(Precompressed)
+        .body(body)
+        .unwrap();
+    response.headers_mut().extend(validators);
+    Ok(response)
+}
+
+/// Parse a single `bytes=` range against a block of `len` bytes into
+/// inclusive offsets. `None` means the header is ignored (other units,
+/// multiple ranges, or malformed); `Some(None)` means it is unsatisfiable.
+fn parse_range(value: &HeaderValue, len: u64) -> Option<Option<(u64, u64)>> {
+    let spec = value.to_str().ok()?.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+
+    if first.is_empty() {
+        // Suffix range: the final N bytes
+        let suffix: u64 = last.parse().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(None);
+        }
+        return Some(Some((len.saturating_sub(suffix), len - 1)));
+    }
+
+    let first: u64 = first.parse().ok()?;
+    let last = match last {
+        "" => u64::MAX,
+        last => last.parse().ok()?,
+    };
+    if last < first {
+        return None;
+    }
+
+    if first >= len {
+        return Some(None);
+    }
+    Some(Some((first, last.min(len - 1))))
+}
+
+/// Format named by a data path segment: a datatype (`reads` is BAM,
+/// `variants` VCF, `sequences` FASTA) or a concrete format (`CRAM`).
 fn parse_format(s: &str) -> Result<Format> {
     match s {
         "reads" => Ok(Format::Bam),
         "variants" => Ok(Format::Vcf),
         "sequences" => Ok(Format::Fasta),
-        _ => Err(Error::InvalidInput(format!("unknown format path: {}", s))),
+        _ => s
+            .parse()
+            .map_err(|_| Error::InvalidInput(format!("unknown format path: {}", s))),
+    }
+}
+
+/// Case-insensitive `format` parameter, so URLs written as `format=Cram`
+/// still resolve.
+fn deserialize_format<'de, D>(deserializer: D) -> std::result::Result<Option<Format>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| s.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(value: &'static str, len: u64) -> Option<Option<(u64, u64)>> {
+        parse_range(&HeaderValue::from_static(value), len)
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(parse_format("reads").unwrap(), Format::Bam);
+        assert_eq!(parse_format("variants").unwrap(), Format::Vcf);
+        assert_eq!(parse_format("CRAM").unwrap(), Format::Cram);
+        assert_eq!(parse_format("fastq").unwrap(), Format::Fastq);
+        assert!(parse_format("alignments").is_err());
+
+        let query = |q: &str| {
+            let uri: axum::http::Uri = format!("/data/reads/x?{}", q).parse().unwrap();
+            Query::<DataQuery>::try_from_uri(&uri).map(|Query(query)| query.format)
+        };
+        assert_eq!(query("format=BCF").unwrap(), Some(Format::Bcf));
+        assert_eq!(query("format=Cram&start=0").unwrap(), Some(Format::Cram));
+        assert_eq!(query("start=0").unwrap(), None);
+        assert!(query("format=SAM").is_err());
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(range("bytes=0-99", 1000), Some(Some((0, 99))));
+        assert_eq!(range("bytes=500-", 1000), Some(Some((500, 999))));
+        assert_eq!(range("bytes=900-2000", 1000), Some(Some((900, 999))));
+        assert_eq!(range("bytes=-100", 1000), Some(Some((900, 999))));
+        assert_eq!(range("bytes=-5000", 1000), Some(Some((0, 999))));
+
+        // Unsatisfiable
+        assert_eq!(range("bytes=1000-", 1000), Some(None));
+        assert_eq!(range("bytes=-0", 1000), Some(None));
+
+        // Ignored
+        assert_eq!(range("items=0-1", 1000), None);
+        assert_eq!(range("bytes=0-1,5-6", 1000), None);
+        assert_eq!(range("bytes=10-5", 1000), None);
+        assert_eq!(range("bytes=abc", 1000), None);
     }
 }
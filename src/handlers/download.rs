@@ -0,0 +1,396 @@
+//! Single-request downloads (extension, not part of the htsget spec).
+//!
+//! `GET /download/{endpoint}/{id}` takes the ticket endpoint's query
+//! parameters, builds the ticket server-side and streams its blocks (header,
+//! data slices, EOF marker) back to back, so clients that can't follow the
+//! two-step protocol get a valid slice in one request:
+//!
+//! ```text
+//! curl -o slice.bam 'http://localhost:8080/download/reads/NA12878?referenceName=chr20&start=0&end=100000'
+//! ```
+
+use super::data::STREAM_CHUNK_SIZE;
+use super::{
+    AppState, DataServed, Precompressed, RequestBaseUrl, RequestSubject, TicketPage, get_reads,
+    get_sequences, get_variants,
+};
+use crate::audit::Access;
+use crate::storage::{ByteRange, DataReader, FileInfo, ListedFile, Storage};
+use crate::types::{Format, HtsgetResponse, ReadsQuery};
+use crate::{Error, Result};
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, Uri, header},
+    response::Response,
+};
+use base64::Engine;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio_util::io::ReaderStream;
+
+/// Scheme of the placeholder URLs standing for byte ranges while the ticket
+/// is built
+const BLOCK_URL: &str = "block:";
+
+/// Storage whose data URLs stand for the byte ranges asked for, so the
+/// ticket built with it can be read back as ranges of the real storage.
+struct BlockRecorder {
+    storage: Arc<dyn Storage>,
+    ranges: Mutex<Vec<Option<ByteRange>>>,
+}
+
+#[async_trait]
+impl Storage for BlockRecorder {
+    async fn exists(&self, id: &str, format: Format) -> Result<bool> {
+        self.storage.exists(id, format).await
+    }
+
+    async fn file_info(&self, id: &str, format: Format) -> Result<FileInfo> {
+        self.storage.file_info(id, format).await
+    }
+
+    async fn data_url(
+        &self,
+        _id: &str,
+        _format: Format,
+        range: Option<ByteRange>,
+    ) -> Result<String> {
+        let mut ranges = self.ranges.lock().unwrap();
+        ranges.push(range);
+        Ok(format!("{}{}", BLOCK_URL, ranges.len() - 1))
+    }
+
+    async fn read_bytes(
+        &self,
+        id: &str,
+        format: Format,
+        range: Option<ByteRange>,
+    ) -> Result<Bytes> {
+        self.storage.read_bytes(id, format, range).await
+    }
+
+    async fn read_stream(
+        &self,
+        id: &str,
+        format: Format,
+        range: Option<ByteRange>,
+    ) -> Result<DataReader> {
+        self.storage.read_stream(id, format, range).await
+    }
+
+    async fn list(&self) -> Result<Vec<ListedFile>> {
+        self.storage.list().await
+    }
+
+    async fn index_path(&self, id: &str, format: Format) -> Result<Option<PathBuf>> {
+        self.storage.index_path(id, format).await
+    }
+
+    async fn gzi_path(&self, id: &str, format: Format) -> Result<Option<PathBuf>> {
+        self.storage.gzi_path(id, format).await
+    }
+
+    fn file_path(&self, id: &str, format: Format) -> PathBuf {
+        self.storage.file_path(id, format)
+    }
+}
+
+/// A piece of the download: a byte range of the file, or inline bytes
+enum Block {
+    Range(Option<ByteRange>),
+    Inline(Bytes),
+}
+
+/// The block a ticket URL stands for
+fn block(url: &str, ranges: &[Option<ByteRange>]) -> Result<Block> {
+    if let Some(index) = url.strip_prefix(BLOCK_URL) {
+        let range = index.parse::<usize>().ok().and_then(|i| ranges.get(i));
+        return range
+            .cloned()
+            .map(Block::Range)
+            .ok_or_else(|| Error::Internal(format!("unknown block {}", url)));
+    }
+
+    // e.g. the BGZF EOF marker
+    match url
+        .strip_prefix("data:")
+        .and_then(|uri| uri.split_once(";base64,"))
+    {
+        Some((_, data)) => base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .map(|bytes| Block::Inline(bytes.into()))
+            .map_err(|e| Error::Internal(format!("invalid data URL in ticket: {}", e))),
+        None => Err(Error::Internal(format!("unexpected ticket URL {}", url))),
+    }
+}
+
+fn query<T: serde::de::DeserializeOwned>(uri: &Uri) -> Result<Query<T>> {
+    Query::try_from_uri(uri).map_err(|e| Error::InvalidInput(e.body_text()))
+}
+
+/// `GET /download/{endpoint}/{id}` - a ticket's data as one file
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/download/{endpoint}/{id}",
+        params(("endpoint" = String, Path, description = "`reads`, `variants` or `sequences`"), ("id" = String, Path, description = "Dataset ID")),
+        responses(
+            (status = 200, description = "Header, data blocks and EOF marker of the ticket, concatenated", content_type = "application/octet-stream"),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "extensions"
+    )
+)]
+pub async fn get_download(
+    State(state): State<AppState>,
+    subject: RequestSubject,
+    Path((endpoint, id)): Path<(String, String)>,
+    uri: Uri,
+) -> Result<Response> {
+    let endpoints = state.endpoints;
+    let path = Path(id.clone());
+    let download = assemble(&state, &id, subject.clone(), |ticket_state| async move {
+        let (base_url, headers) = (RequestBaseUrl(None), HeaderMap::new());
+        let page = TicketPage::default();
+        match endpoint.as_str() {
+            "reads" if endpoints.reads => {
+                let query: Query<ReadsQuery> = query(&uri)?;
+                // Precise slices aren't byte ranges of the file
+                if query.precise == Some(true) {
+                    return Err(Error::InvalidInput(
+                        "precise is not supported for downloads".to_string(),
+                    ));
+                }
+                get_reads(
+                    State(ticket_state),
+                    base_url,
+                    subject,
+                    headers,
+                    page,
+                    path,
+                    query,
+                )
+                .await
+            }
+            "variants" if endpoints.variants => {
+                let query = query(&uri)?;
+                get_variants(
+                    State(ticket_state),
+                    base_url,
+                    subject,
+                    headers,
+                    page,
+                    path,
+                    query,
+                )
+                .await
+            }
+            "sequences" if endpoints.sequences => {
+                let query = query(&uri)?;
+                get_sequences(
+                    State(ticket_state),
+                    base_url,
+                    subject,
+                    headers,
+                    page,
+                    path,
+                    query,
+                )
+                .await
+            }
+            _ => Err(Error::NotFound(format!("endpoint {}", endpoint))),
+        }
+    })
+    .await?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, download.format.content_type())
+        .header(header::CONTENT_LENGTH, download.len)
+        .header(
+            header::CONTENT_DISPOSITION,
+            super::attachment(&download.filename),
+        )
+        // The blocks are sent as stored; compressing them again would hand
+        // `curl -o` users a gzipped file
+        .extension(Precompressed)
+        .body(Body::from_stream(download.stream))
+        .map_err(|e| Error::Internal(format!("failed to build download response: {}", e)))?;
+
+    Ok(match download.access {
+        Some(access) => access.attach(response),
+        None => response,
+    })
+}
+
+/// A ticket's data, assembled into one stream
+pub(crate) struct Assembled {
+    pub format: Format,
+    /// Bytes in `stream`
+    pub len: u64,
+    /// e.g. `NA12878.bam`
+    pub filename: String,
+    pub stream: BoxStream<'static, std::io::Result<Bytes>>,
+    /// What the ticket handler served, for the audit log
+    pub access: Option<Access>,
+}
+
+/// Assemble the ticket of dataset `id` that `ticket` builds with the state
+/// it is given: its blocks (header, data slices, EOF marker) back to back.
+/// The data is counted as served to `subject` once the ticket is built.
+pub(crate) async fn assemble<F, Fut>(
+    state: &AppState,
+    id: &str,
+    subject: RequestSubject,
+    ticket: F,
+) -> Result<Assembled>
+where
+    F: FnOnce(AppState) -> Fut,
+    Fut: Future<Output = Result<Response>>,
+{
+    let recorder = Arc::new(BlockRecorder {
+        storage: state.storage.clone(),
+        ranges: Mutex::default(),
+    });
+    let mut ticket_state = state.clone();
+    ticket_state.storage = recorder.clone();
+    // Header tickets then point at the header's range rather than a
+    // standalone copy, and URLs aren't signed
+    ticket_state.standalone_headers = None;
+    // The whole ticket is needed, in one page
+    ticket_state.ticket_limits.page_size = None;
+    #[cfg(feature = "auth")]
+    {
+        ticket_state.url_signer = None;
+    }
+
+    let response = ticket(ticket_state).await?;
+    let access = response.extensions().get::<Access>().cloned();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .map_err(|e| Error::Internal(format!("failed to read ticket: {}", e)))?;
+    let ticket: HtsgetResponse = serde_json::from_slice(&body)
+        .map_err(|e| Error::Internal(format!("failed to parse ticket: {}", e)))?;
+    let format = ticket.htsget.format;
+
+    let ranges = std::mem::take(&mut *recorder.ranges.lock().unwrap());
+    let blocks = ticket
+        .htsget
+        .urls
+        .iter()
+        .map(|entry| block(&entry.url, &ranges))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Clamp ranges to the file, so Content-Length is exact
+    let size = state.storage.file_info(id, format).await?.size;
+    let mut len = 0;
+    let blocks: Vec<Block> = blocks
+        .into_iter()
+        .map(|block| match block {
+            Block::Range(range) => {
+                let start = range.as_ref().map_or(0, |r| r.start).min(size);
+                let end = range.and_then(|r| r.end).unwrap_or(size).min(size);
+                len += end.saturating_sub(start);
+                Block::Range(Some(ByteRange {
+                    start,
+                    end: Some(end.max(start)),
+                }))
+            }
+            Block::Inline(bytes) => {
+                len += bytes.len() as u64;
+                Block::Inline(bytes)
+            }
+        })
+        .collect();
+
+    state
+        .observe_data_served(DataServed {
+            id: id.to_string(),
+            format,
+            range: None,
+            bytes: len,
+            subject: subject.0,
+        })
+        .await;
+
+    // Open each block only once the previous one has been sent
+    let storage = state.storage.clone();
+    let stream_id = id.to_string();
+    let stream = futures::stream::iter(blocks)
+        .then(move |block| {
+            let (storage, id) = (storage.clone(), stream_id.clone());
+            async move {
+                let reader: DataReader = match block {
+                    Block::Range(range) => storage
+                        .read_stream(&id, format, range)
+                        .await
+                        .map_err(|e| std::io::Error::other(e.to_string()))?,
+                    Block::Inline(bytes) => Box::pin(std::io::Cursor::new(bytes)),
+                };
+                Ok::<_, std::io::Error>(ReaderStream::with_capacity(reader, STREAM_CHUNK_SIZE))
+            }
+        })
+        .try_flatten()
+        .boxed();
+
+    let name = id.rsplit('/').next().unwrap_or(id);
+    Ok(Assembled {
+        format,
+        len,
+        filename: format!("{}.{}", name, format.data_extensions()[0]),
+        stream,
+        access,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attachment() {
+        use crate::handlers::attachment;
+
+        assert_eq!(
+            attachment("sample.bam"),
+            "attachment; filename=\"sample.bam\"; filename*=UTF-8''sample.bam"
+        );
+        let value = attachment("a\"b\r\nc é.bam");
+        assert_eq!(
+            value,
+            "attachment; filename=\"a_b__c _.bam\"; filename*=UTF-8''a%22b%0D%0Ac%20%C3%A9.bam"
+        );
+        assert!(axum::http::HeaderValue::from_str(&value).is_ok());
+    }
+
+    #[test]
+    fn test_block() {
+        let ranges = vec![
+            Some(ByteRange {
+                start: 0,
+                end: Some(100),
+            }),
+            None,
+        ];
+        assert!(matches!(
+            block("block:0", &ranges),
+            Ok(Block::Range(Some(ByteRange {
+                start: 0,
+                end: Some(100)
+            })))
+        ));
+        assert!(matches!(block("block:1", &ranges), Ok(Block::Range(None))));
+        assert!(block("block:2", &ranges).is_err());
+
+        let eof = "data:;base64,H4sIBAAAAAAA/wYAQkMCABsAAwAAAAAAAAAAAA==";
+        assert!(matches!(block(eof, &ranges), Ok(Block::Inline(bytes)) if bytes.len() == 28));
+        assert!(block("https://example.com/a.bam", &ranges).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! GA4GH DRS v1 bridge (requires `drs` feature).
+//!
+//! Every dataset is also a DRS object whose ID is the dataset ID plus the
+//! lowercase format name, e.g. `NA12878.bam` or `NA12878.vcf`, so
+//! workflow engines can resolve `drs://host/NA12878.bam` against this server.
+//! Access URLs are the same ones tickets use: this server's `/data` endpoint
+//! or, for S3 without a proxy, a presigned URL.
+
+use super::{AppState, RequestBaseUrl, RequestSubject, rfc3339};
+use crate::{
+    Error, Result,
+    types::{Format, Organization, ServiceType},
+};
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use serde::Serialize;
+use std::time::UNIX_EPOCH;
+
+/// The one access method; its URL is minted per request since presigned
+/// URLs expire
+const HTTPS_ACCESS_ID: &str = "https";
+
+/// DRS object (`GET /ga4gh/drs/v1/objects/:object_id`)
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DrsObject {
+    pub id: String,
+    pub name: String,
+    pub self_uri: String,
+    pub size: u64,
+    pub created_time: String,
+    pub updated_time: String,
+    pub mime_type: String,
+    pub checksums: Vec<DrsChecksum>,
+    pub access_methods: Vec<AccessMethod>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DrsChecksum {
+    pub checksum: String,
+    pub r#type: String,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AccessMethod {
+    pub r#type: String,
+    pub access_id: String,
+    pub access_url: AccessUrl,
+}
+
+/// `GET /ga4gh/drs/v1/objects/:object_id/access/:access_id`
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AccessUrl {
+    pub url: String,
+}
+
+/// DRS service-info response
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DrsServiceInfo {
+    pub id: String,
+    pub name: String,
+    pub r#type: ServiceType,
+    pub organization: Organization,
+    pub version: String,
+}
+
+/// `GET /ga4gh/drs/v1/objects/:object_id` - a dataset as a DRS object
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/ga4gh/drs/v1/objects/{object_id}",
+        params(("object_id" = String, Path, description = "Dataset ID plus lowercase format, e.g. `sample1.bam`")),
+        responses(
+            (status = 200, description = "DRS object", body = DrsObject),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "drs"
+    )
+)]
+pub async fn get_drs_object(
+    State(state): State<AppState>,
+    base_url: RequestBaseUrl,
+    subject: RequestSubject,
+    Path(object_id): Path<String>,
+) -> Result<Json<DrsObject>> {
+    let state = subject.bind(state);
+    let (id, format) = locate(&state, &object_id).await?;
+    let info = state.storage.file_info(id, format).await?;
+
+    let host = base_url
+        .0
+        .as_deref()
+        .unwrap_or(&state.base_url)
+        .split_once("://")
+        .map_or("", |(_, rest)| rest)
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let modified = rfc3339(info.modified.unwrap_or(UNIX_EPOCH));
+
+    // DRS requires a checksum; without an MD5 ETag, fall back to the file
+    // version the data endpoint's ETag is derived from
+    let checksum = match info.md5() {
+        Some(md5) => DrsChecksum {
+            checksum: md5,
+            r#type: "md5".to_string(),
+        },
+        None => DrsChecksum {
+            checksum: info.version(),
+            r#type: "etag".to_string(),
+        },
+    };
+
+    Ok(Json(DrsObject {
+        name: object_id.rsplit('/').next().unwrap_or_default().to_string(),
+        self_uri: format!("drs://{}/{}", host, object_id),
+        size: info.size,
+        created_time: modified.clone(),
+        updated_time: modified,
+        mime_type: format.content_type().to_string(),
+        checksums: vec![checksum],
+        access_methods: vec![AccessMethod {
+            r#type: "https".to_string(),
+            access_id: HTTPS_ACCESS_ID.to_string(),
+            access_url: access_url(&state, &base_url, id, format).await?,
+        }],
+        id: object_id,
+    }))
+}
+
+/// `GET /ga4gh/drs/v1/objects/:object_id/access/:access_id` - a fresh
+/// access URL
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/ga4gh/drs/v1/objects/{object_id}/access/{access_id}",
+        params(("object_id" = String, Path, description = "Dataset ID plus lowercase format, e.g. `sample1.bam`"), ("access_id" = String, Path, description = "Access method ID (`https`)")),
+        responses(
+            (status = 200, description = "Access URL", body = AccessUrl),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "drs"
+    )
+)]
+pub async fn get_drs_access(
+    State(state): State<AppState>,
+    base_url: RequestBaseUrl,
+    subject: RequestSubject,
+    Path((object_id, access_id)): Path<(String, String)>,
+) -> Result<Json<AccessUrl>> {
+    let state = subject.bind(state);
+    if access_id != HTTPS_ACCESS_ID {
+        return Err(Error::NotFound(format!("access method {}", access_id)));
+    }
+    let (id, format) = locate(&state, &object_id).await?;
+
+    Ok(Json(access_url(&state, &base_url, id, format).await?))
+}
+
+/// `GET /ga4gh/drs/v1/service-info`
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/ga4gh/drs/v1/service-info",
+        responses(
+            (status = 200, description = "DRS service-info", body = DrsServiceInfo),
+        ),
+        tag = "drs"
+    )
+)]
+pub async fn drs_service_info() -> Json<DrsServiceInfo> {
+    Json(DrsServiceInfo {
+        id: "org.example.htsgetr.drs".to_string(),
+        name: "htsgetr DRS".to_string(),
+        r#type: ServiceType {
+            group: "org.ga4gh".to_string(),
+            artifact: "drs".to_string(),
+            version: "1.4.0".to_string(),
+        },
+        organization: Organization {
+            name: "Example Organization".to_string(),
+            url: "https://example.org".to_string(),
+        },
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    })
+}
+
+/// Split a DRS object ID into the dataset ID and format, e.g.
+/// `NA12878.bam` into `("NA12878", Format::Bam)`.
+pub(crate) fn parse_object_id(object_id: &str) -> Result<(&str, Format)> {
+    object_id
+        .rsplit_once('.')
+        .filter(|(id, _)| !id.is_empty())
+        .and_then(|(id, format)| Some((id, format.parse().ok()?)))
+        .ok_or_else(|| Error::NotFound(object_id.to_string()))
+}
+
+/// Dataset ID and format of an object that exists and whose datatype the
+/// deployment serves.
+async fn locate<'a>(state: &AppState, object_id: &'a str) -> Result<(&'a str, Format)> {
+    let (id, format) = parse_object_id(object_id)?;
+    if !state.endpoints.serves(format) || !state.storage.exists(id, format).await? {
+        return Err(Error::NotFound(object_id.to_string()));
+    }
+    Ok((id, format))
+}
+
+async fn access_url(
+    state: &AppState,
+    base_url: &RequestBaseUrl,
+    id: &str,
+    format: Format,
+) -> Result<AccessUrl> {
+    let url = state.sign_data_url(state.storage.data_url(id, format, None).await?);
+    Ok(AccessUrl {
+        url: base_url.rebase_url(state, url),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_object_id() {
+        assert_eq!(
+            parse_object_id("NA12878.bam").unwrap(),
+            ("NA12878", Format::Bam)
+        );
+        assert_eq!(
+            parse_object_id("cohort/v1.2.vcf").unwrap(),
+            ("cohort/v1.2", Format::Vcf)
+        );
+
+        for object_id in ["NA12878", ".bam", "NA12878.txt"] {
+            assert!(matches!(
+                parse_object_id(object_id),
+                Err(Error::NotFound(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::from_secs(951_827_696)),
+            "2000-02-29T12:34:56Z"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Genome browser features (requires `explore` feature).
+//!
+//! `/reads/:id/features` and `/variants/:id/features` decode a region into
+//! igv.js JSON features, so a browser track can be backed by this server
+//! directly instead of parsing BAM or VCF slices client-side.
+
+use super::AppState;
+use super::meta::endpoint_format;
+use crate::{
+    Error, Result, formats,
+    types::{Format, ReadFeature, Region, VariantFeature},
+};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use serde::Deserialize;
+
+/// Query of the features endpoints
+#[derive(Debug, Deserialize)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct FeaturesQuery {
+    pub format: Option<Format>,
+    #[serde(rename = "referenceName")]
+    pub reference_name: String,
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+    /// Most features to return (default and maximum
+    /// [`MAX_FEATURES`](formats::MAX_FEATURES))
+    pub limit: Option<usize>,
+}
+
+impl FeaturesQuery {
+    fn region(self) -> Region {
+        Region {
+            reference_name: self.reference_name,
+            start: self.start,
+            end: self.end,
+        }
+    }
+
+    fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(formats::MAX_FEATURES)
+            .min(formats::MAX_FEATURES)
+    }
+}
+
+/// Alignments of a BAM region as igv.js features.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/reads/{id}/features",
+        params(("id" = String, Path, description = "Dataset ID"), FeaturesQuery),
+        responses(
+            (status = 200, description = "igv.js alignment features", body = [ReadFeature]),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "extensions"
+    )
+)]
+pub async fn get_read_features(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<FeaturesQuery>,
+) -> Result<Json<Vec<ReadFeature>>> {
+    let format = endpoint_format("reads", query.format)?;
+    if format != Format::Bam {
+        return Err(Error::UnsupportedFormat(format!(
+            "features require a BAM file, not {:?}",
+            format
+        )));
+    }
+
+    let index_path = state
+        .locate(&id, format, true)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("index for {}", id)))?;
+    let file_path = state.storage.file_path(&id, format);
+    let limit = query.limit();
+
+    let features = formats::read_features(&file_path, &index_path, &query.region(), limit).await?;
+    Ok(Json(features))
+}
+
+/// Variants of a VCF region as igv.js features.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/variants/{id}/features",
+        params(("id" = String, Path, description = "Dataset ID"), FeaturesQuery),
+        responses(
+            (status = 200, description = "igv.js variant features", body = [VariantFeature]),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "extensions"
+    )
+)]
+pub async fn get_variant_features(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<FeaturesQuery>,
+) -> Result<Json<Vec<VariantFeature>>> {
+    let format = endpoint_format("variants", query.format)?;
+    if format != Format::Vcf {
+        return Err(Error::UnsupportedFormat(format!(
+            "features require a bgzipped VCF file, not {:?}",
+            format
+        )));
+    }
+
+    let index_path = state
+        .locate(&id, format, true)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("index for {}", id)))?;
+    let file_path = state.storage.file_path(&id, format);
+    let limit = query.limit();
+
+    let features =
+        formats::variant_features(&file_path, &index_path, &query.region(), limit).await?;
+    Ok(Json(features))
+}
@@ -0,0 +1,17 @@
+//! Public key of signed tickets (extension): `GET /.well-known/jwks.json`
+//! serves the ticket signing key as a JWK set, for consumers verifying
+//! tickets. Served only when tickets are signed.
+
+use super::AppState;
+use crate::{Error, Result};
+use axum::{Json, extract::State};
+use serde_json::Value;
+
+/// `GET /.well-known/jwks.json`
+pub async fn get_ticket_jwks(State(state): State<AppState>) -> Result<Json<Value>> {
+    state
+        .ticket_signer
+        .as_ref()
+        .map(|signer| Json(signer.jwks()))
+        .ok_or_else(|| Error::NotFound("tickets are not signed".to_string()))
+}
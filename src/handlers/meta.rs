@@ -0,0 +1,289 @@
+use super::AppState;
+use crate::{
+    Error, Result, formats,
+    types::{
+        CoverageResponse, FileMetadata, Format, MetadataResponse, References, ReferencesResponse,
+        Region, VariantStatsResponse,
+    },
+};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, Default)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct MetaQuery {
+    pub format: Option<Format>,
+}
+
+/// Query of the stats and coverage endpoints
+#[derive(Debug, Deserialize)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct StatsQuery {
+    pub format: Option<Format>,
+    #[serde(rename = "referenceName")]
+    pub reference_name: String,
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+    /// Equal-width windows to split `start..end` into (default 1)
+    pub bins: Option<usize>,
+}
+
+/// Extension endpoint describing a file before it's sliced (not part of
+/// htsget spec): size, modification time, available indexes, reference
+/// sequences and checksums.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/meta/{endpoint}/{id}",
+        params(("endpoint" = String, Path, description = "`reads`, `variants` or `sequences`"), ("id" = String, Path, description = "Dataset ID"), MetaQuery),
+        responses(
+            (status = 200, description = "File metadata", body = crate::types::MetadataResponse),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "extensions"
+    )
+)]
+pub async fn get_meta(
+    State(state): State<AppState>,
+    Path((endpoint, id)): Path<(String, String)>,
+    Query(query): Query<MetaQuery>,
+) -> Result<Json<MetadataResponse>> {
+    let format = endpoint_format(&endpoint, query.format)?;
+    if !state.endpoints.serves(format) {
+        return Err(Error::NotFound(format!("endpoint {}", endpoint)));
+    }
+
+    let (info, checksums, index_path, gzi_path) = tokio::join!(
+        state.storage.file_info(&id, format),
+        state.storage.stored_checksums(&id, format),
+        state.storage.index_path(&id, format),
+        state.storage.gzi_path(&id, format),
+    );
+    let info = info?;
+    let index_path = index_path?;
+
+    let indexes = index_path
+        .iter()
+        .chain(gzi_path?.iter())
+        .filter_map(|path| path.extension())
+        .map(|ext| ext.to_string_lossy().to_uppercase())
+        .collect();
+
+    // Remote backends only hold the header locally once a ticket needed it
+    let file_path = state.storage.file_path(&id, format);
+    let reference_sequences = if file_path.exists() {
+        formats::reference_sequences(format, &file_path, index_path.as_deref()).await?
+    } else {
+        Vec::new()
+    };
+
+    Ok(Json(MetadataResponse {
+        htsget: FileMetadata {
+            id,
+            format,
+            size: info.size,
+            last_modified: info.modified.map(httpdate::fmt_http_date),
+            indexes,
+            reference_sequences,
+            checksums: checksums?,
+        },
+    }))
+}
+
+/// `GET /reads/:id/references` (extension): contig names and lengths from
+/// the header, for region pickers.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/reads/{id}/references",
+        params(("id" = String, Path, description = "Dataset ID"), MetaQuery),
+        responses(
+            (status = 200, description = "Reference sequences", body = crate::types::ReferencesResponse),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "extensions"
+    )
+)]
+pub async fn get_read_references(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<MetaQuery>,
+) -> Result<Json<ReferencesResponse>> {
+    references(&state, "reads", id, query.format).await
+}
+
+/// `GET /variants/:id/references` (extension): contig names and lengths
+/// from the header, for region pickers.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/variants/{id}/references",
+        params(("id" = String, Path, description = "Dataset ID"), MetaQuery),
+        responses(
+            (status = 200, description = "Reference sequences", body = crate::types::ReferencesResponse),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "extensions"
+    )
+)]
+pub async fn get_variant_references(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<MetaQuery>,
+) -> Result<Json<ReferencesResponse>> {
+    references(&state, "variants", id, query.format).await
+}
+
+async fn references(
+    state: &AppState,
+    endpoint: &str,
+    id: String,
+    format: Option<Format>,
+) -> Result<Json<ReferencesResponse>> {
+    let format = endpoint_format(endpoint, format)?;
+
+    if !state.storage.exists(&id, format).await? {
+        return Err(Error::NotFound(id));
+    }
+
+    let file_path = state.storage.file_path(&id, format);
+    let reference_sequences = formats::reference_sequences(format, &file_path, None).await?;
+
+    Ok(Json(ReferencesResponse {
+        htsget: References {
+            format,
+            reference_sequences,
+        },
+    }))
+}
+
+/// `GET /variants/:id/stats` (extension): approximate record counts for a
+/// region, split into `bins` windows for density tracks. Counts are derived
+/// from the index alone, so the data file is never scanned.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/variants/{id}/stats",
+        params(("id" = String, Path, description = "Dataset ID"), StatsQuery),
+        responses(
+            (status = 200, description = "Estimated record counts", body = crate::types::VariantStatsResponse),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "extensions"
+    )
+)]
+pub async fn get_variant_stats(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<VariantStatsResponse>> {
+    let format = endpoint_format("variants", query.format)?;
+
+    let index_path = state
+        .locate(&id, format, true)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("index for {}", id)))?;
+
+    let region = Region {
+        reference_name: query.reference_name,
+        start: query.start,
+        end: query.end,
+    };
+    let file_path = state.storage.file_path(&id, format);
+    let stats = formats::variant_stats(
+        format,
+        &file_path,
+        &index_path,
+        &region,
+        query.bins.unwrap_or(1),
+    )
+    .await?;
+
+    Ok(Json(VariantStatsResponse { htsget: stats }))
+}
+
+/// `GET /reads/:id/coverage` (extension): read depth over a region, split
+/// into `bins` windows for coverage tracks. Small regions are decoded;
+/// large ones are estimated from the BAI.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/reads/{id}/coverage",
+        params(("id" = String, Path, description = "Dataset ID"), StatsQuery),
+        responses(
+            (status = 200, description = "Read depth", body = crate::types::CoverageResponse),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "extensions"
+    )
+)]
+pub async fn get_read_coverage(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<CoverageResponse>> {
+    let format = endpoint_format("reads", query.format)?;
+    if format != Format::Bam {
+        return Err(Error::UnsupportedFormat(format!(
+            "coverage requires a BAM index, not {:?}",
+            format
+        )));
+    }
+
+    let index_path = state
+        .locate(&id, format, true)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("index for {}", id)))?;
+
+    let region = Region {
+        reference_name: query.reference_name,
+        start: query.start,
+        end: query.end,
+    };
+    let file_path = state.storage.file_path(&id, format);
+    let coverage =
+        formats::read_coverage(&file_path, &index_path, &region, query.bins.unwrap_or(1)).await?;
+
+    Ok(Json(CoverageResponse { htsget: coverage }))
+}
+
+/// The requested format for a ticket endpoint, defaulting as the endpoint does.
+pub(super) fn endpoint_format(endpoint: &str, format: Option<Format>) -> Result<Format> {
+    let (default_format, accepts): (Format, fn(&Format) -> bool) = match endpoint {
+        "reads" => (Format::Bam, Format::is_reads),
+        "variants" => (Format::Vcf, Format::is_variants),
+        "sequences" => (Format::Fasta, Format::is_sequences),
+        _ => return Err(Error::NotFound(format!("endpoint {}", endpoint))),
+    };
+
+    let format = format.unwrap_or(default_format);
+    if !accepts(&format) {
+        return Err(Error::UnsupportedFormat(format!(
+            "{:?} is not a {} format",
+            format, endpoint
+        )));
+    }
+
+    Ok(format)
+}
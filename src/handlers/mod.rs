@@ -4,14 +4,37 @@
 //!
 //! - [`get_reads`] / [`post_reads`] - `GET/POST /reads/:id`
 //! - [`get_variants`] / [`post_variants`] - `GET/POST /variants/:id`
-//! - [`get_sequences`] - `GET /sequences/:id` (extension)
-//! - [`get_data`] - `GET /data/:format/:id` (data serving)
-//! - [`service_info()`] - `GET /service-info`
+//! - [`get_sequences`] / [`post_sequences`] - `GET/POST /sequences/:id` (extension)
+//! - [`get_data`] / [`head_data`] - `GET/HEAD /data/:format/:id` (data serving)
+//! - [`get_meta`] - `GET /meta/:endpoint/:id` file metadata (extension)
+//! - `GET /checksums/:endpoint/:id` - whole-file MD5, SHA-256 and CRC32C
+//!   (extension, requires `checksums` feature)
+//! - [`get_read_references`] / [`get_variant_references`] -
+//!   `GET /reads/:id/references`, `GET /variants/:id/references` header contigs (extension)
+//! - [`get_variant_stats`] - `GET /variants/:id/stats` index-estimated record counts (extension)
+//! - [`get_read_coverage`] - `GET /reads/:id/coverage` read depth in windows (extension)
+//! - `GET /reads/:id/features`, `GET /variants/:id/features` - igv.js features for a
+//!   region (extension, requires `explore` feature)
+//! - [`refresh_ticket`] - `POST /tickets/refresh` re-issues an expiring ticket
+//!   (extension, off by default)
+//! - [`service_info()`] - `GET /service-info`, and per datatype
+//!   `GET /reads/service-info`, `GET /variants/service-info`, `GET /sequences/service-info`
+//! - `GET /openapi.json` - OpenAPI 3 document of the served routes, and a
+//!   Swagger UI at `/swagger-ui/` when enabled (requires `openapi` feature)
+//! - `GET /ui/` - demo page for requesting tickets and downloading slices
+//!   (requires `ui` feature)
+//! - `GET /sequence/:id` - refget v2 sequence retrieval (requires `refget` feature)
+//! - `GET /ga4gh/drs/v1/objects/:object_id` - datasets as GA4GH DRS objects
+//!   (requires `drs` feature)
+//! - `GET /.well-known/jwks.json` - public key of signed tickets, when a ticket
+//!   signing key is configured (requires `jws` feature)
 //!
 //! # Protocol Flow
 //!
 //! 1. Client calls `/reads/:id` or `/variants/:id` with optional region params
-//! 2. Server returns a JSON ticket with URLs pointing to `/data/:format/:id`
+//! 2. Server returns a JSON ticket with URLs pointing to `/data/:format/:id`,
+//!    as `application/vnd.ga4gh.htsget.v1.3.0+json; charset=utf-8` (clients
+//!    asking for another protocol version in `Accept` get a 406)
 //! 3. Client fetches data blocks from the ticket URLs
 //!
 //! # Example
@@ -22,29 +45,128 @@
 //! use std::sync::Arc;
 //!
 //! let storage = Arc::new(LocalStorage::new(data_dir, base_url.clone()));
-//! let state = AppState { storage, base_url };
+//! let state = AppState {
+//!     storage,
+//!     base_url,
+//!     trusted_proxies: None,
+//!     cache_control: None,
+//!     data_mode: Default::default(),
+//!     endpoints: Default::default(),
+//!     path_prefix: None,
+//!     standalone_headers: None,
+//!     default_formats: Default::default(),
+//!     ticket_cache: None,
+//!     plain_json_tickets: false,
+//!     ticket_url_ttl: None,
+//!     ticket_limits: Default::default(),
+//!     index_readers: Default::default(),
+//!     reference_aliases: None,
+//!     observers: Default::default(),
+//!     #[cfg(feature = "jws")]
+//!     ticket_signer: None,
+//!     #[cfg(feature = "checksums")]
+//!     checksums: Default::default(),
+//! };
 //! let app = create_router(state);
 //! ```
+//!
+//! Embedders can serve their own endpoints next to these with
+//! [`create_router_with`], or through
+//! [`ServerBuilder::routes`](crate::server::ServerBuilder::routes).
 
+mod caching;
+#[cfg(feature = "checksums")]
+mod checksums;
 mod data;
+mod download;
+#[cfg(feature = "drs")]
+mod drs;
+#[cfg(feature = "explore")]
+mod explore;
+#[cfg(feature = "jws")]
+mod jwks;
+mod meta;
+mod negotiate;
+mod observer;
+#[cfg(feature = "openapi")]
+mod openapi;
+mod pages;
 mod reads;
+#[cfg(feature = "refget")]
+mod refget;
+mod refresh;
 mod sequences;
 mod service_info;
+mod ticket;
+#[cfg(feature = "ui")]
+mod ui;
 mod variants;
 
-pub use data::get_data;
+#[cfg(feature = "checksums")]
+pub use checksums::{ChecksumCache, get_checksums};
+pub use data::{DataMode, HeaderCache, HeaderMode, Precompressed, get_data, head_data};
+pub(crate) use download::assemble;
+pub use download::get_download;
+#[cfg(feature = "drs")]
+pub(crate) use drs::parse_object_id as parse_drs_object_id;
+#[cfg(feature = "drs")]
+pub use drs::{
+    AccessMethod, AccessUrl, DrsChecksum, DrsObject, DrsServiceInfo, drs_service_info,
+    get_drs_access, get_drs_object,
+};
+#[cfg(feature = "explore")]
+pub use explore::{FeaturesQuery, get_read_features, get_variant_features};
+#[cfg(feature = "jws")]
+pub use jwks::get_ticket_jwks;
+pub use meta::{
+    get_meta, get_read_coverage, get_read_references, get_variant_references, get_variant_stats,
+};
+pub use negotiate::{HTSGET_MEDIA_TYPE, negotiate};
+pub use observer::{DataServed, RequestObserver, TicketRequestInfo};
+#[cfg(feature = "openapi")]
+pub use openapi::{api_doc, get_openapi};
+pub use pages::{DEFAULT_MAX_REGIONS, TicketLimits, TicketPage};
 pub use reads::{get_reads, post_reads};
-pub use sequences::get_sequences;
-pub use service_info::service_info;
+#[cfg(feature = "refget")]
+pub use refget::{get_refget_metadata, get_refget_sequence, refget_service_info};
+pub(crate) use refresh::{REFRESH_PATH, refresh_dataset};
+pub use refresh::{RefreshRequest, TicketEndpoint, refresh_ticket};
+pub use sequences::{SequencesPostBody, get_sequences, post_sequences};
+pub use service_info::{
+    reads_service_info, sequences_service_info, service_info, variants_service_info,
+};
+pub use ticket::{TicketCache, TicketSignature};
+#[cfg(feature = "ui")]
+pub use ui::{get_ui_asset, get_ui_index, get_ui_root};
 pub use variants::{get_variants, post_variants};
 
-use crate::storage::Storage;
-use axum::{Router, routing::get};
+use crate::audit::Subject;
+use crate::formats::{IndexReaders, ReferenceAliases};
+use crate::forwarded::{TrustedProxies, forwarded_base_url};
+use crate::storage::{ByteRange, Storage};
+use crate::types::{DataClass, Format, HtsgetResponse, UrlEntry};
+use crate::{Error, Result};
+use axum::{
+    Json, Router,
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+    middleware,
+    routing::{get, post},
+};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[cfg(feature = "auth")]
 use crate::auth::UrlSigner;
 
+#[cfg(feature = "refget")]
+use crate::refget::RefgetIndex;
+
+#[cfg(feature = "jws")]
+use crate::jws::{JWKS_PATH, TicketSigner};
+
 /// Shared application state
 #[derive(Clone)]
 pub struct AppState {
@@ -53,6 +175,234 @@ pub struct AppState {
     /// URL signer for data endpoints (when auth is enabled)
     #[cfg(feature = "auth")]
     pub url_signer: Option<UrlSigner>,
+    /// Checksum lookup for refget endpoints
+    #[cfg(feature = "refget")]
+    pub refget: Option<Arc<RefgetIndex>>,
+    /// Proxies whose forwarding headers may override `base_url` in tickets
+    pub trusted_proxies: Option<Arc<TrustedProxies>>,
+    /// `Cache-Control` for data responses (e.g. `public, max-age=3600`)
+    pub cache_control: Option<String>,
+    /// Whether `/data` streams bytes or redirects to storage
+    pub data_mode: DataMode,
+    /// Endpoints this deployment serves
+    pub endpoints: Endpoints,
+    /// Prefix the router is mounted under (e.g. `/htsget/v1`), which
+    /// forwarded base URLs don't include
+    pub path_prefix: Option<String>,
+    /// Re-compressed headers for `class=header` tickets, when
+    /// [`HeaderMode::Standalone`] is on
+    pub standalone_headers: Option<HeaderCache>,
+    /// Formats served when a ticket request doesn't name one
+    pub default_formats: DefaultFormats,
+    /// Recent index query results, reused while files are unchanged
+    pub ticket_cache: Option<TicketCache>,
+    /// Send tickets as plain `application/json` rather than the htsget
+    /// media type, for legacy clients
+    pub plain_json_tickets: bool,
+    /// Longest a ticket's URLs are said to be valid for, even when they
+    /// don't expire, so clients re-request tickets at least this often
+    pub ticket_url_ttl: Option<Duration>,
+    /// Most regions per request, and URLs per ticket page
+    pub ticket_limits: TicketLimits,
+    /// Index reader for each format ticket endpoints serve
+    pub index_readers: Arc<IndexReaders>,
+    /// Aliases requested reference names are matched through, if any
+    pub reference_aliases: Option<Arc<ReferenceAliases>>,
+    /// Hooks called around ticket and data requests, in order
+    pub observers: Arc<[Arc<dyn RequestObserver>]>,
+    /// Key tickets are signed with, if any
+    #[cfg(feature = "jws")]
+    pub ticket_signer: Option<Arc<TicketSigner>>,
+    /// Whole-file checksums computed by the checksums endpoint
+    #[cfg(feature = "checksums")]
+    pub checksums: ChecksumCache,
+}
+
+/// Endpoints a deployment serves. A disabled datatype has no ticket,
+/// references or service-info routes, and its files aren't served through
+/// the data, metadata or DRS endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Endpoints {
+    /// `/reads` (BAM, CRAM)
+    pub reads: bool,
+    /// `/variants` (VCF, BCF)
+    pub variants: bool,
+    /// `/sequences` (FASTA, FASTQ)
+    pub sequences: bool,
+    /// `POST /tickets/refresh`, for the enabled datatypes
+    pub refresh: bool,
+    /// Swagger UI at `/swagger-ui/` (requires `openapi` feature)
+    pub swagger_ui: bool,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            reads: true,
+            variants: true,
+            sequences: true,
+            refresh: false,
+            swagger_ui: false,
+        }
+    }
+}
+
+impl Endpoints {
+    /// Whether the endpoint for `format`'s datatype is enabled
+    pub fn serves(&self, format: Format) -> bool {
+        match format {
+            Format::Bam | Format::Cram => self.reads,
+            Format::Vcf | Format::Bcf => self.variants,
+            Format::Fasta | Format::Fastq => self.sequences,
+        }
+    }
+}
+
+/// Format each ticket endpoint serves when a request doesn't name one.
+/// With `detect`, an ID stored only in the datatype's other format (e.g. a
+/// CRAM with no BAM) is served in that format instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefaultFormats {
+    /// `/reads` (BAM or CRAM)
+    pub reads: Format,
+    /// `/variants` (VCF or BCF)
+    pub variants: Format,
+    /// `/sequences` (FASTA or FASTQ)
+    pub sequences: Format,
+    /// Probe storage for the format an ID exists in, trying the default first
+    pub detect: bool,
+}
+
+impl Default for DefaultFormats {
+    fn default() -> Self {
+        Self {
+            reads: Format::Bam,
+            variants: Format::Vcf,
+            sequences: Format::Fasta,
+            detect: false,
+        }
+    }
+}
+
+/// Public base URL for this request, when it arrived via a trusted proxy
+/// that sent forwarding headers.
+///
+/// Requires the server to be run with connect info
+/// (`into_make_service_with_connect_info::<SocketAddr>()`); without a known
+/// peer address forwarding headers are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct RequestBaseUrl(pub Option<String>);
+
+impl RequestBaseUrl {
+    /// Point ticket URLs built from the configured base URL at the
+    /// forwarded one. URLs elsewhere (e.g. presigned S3 URLs) are unchanged,
+    /// as is `next`, which [`endpoint_url`](Self::endpoint_url) builds.
+    pub fn rebase(
+        &self,
+        state: &AppState,
+        mut ticket: Json<HtsgetResponse>,
+    ) -> Json<HtsgetResponse> {
+        for entry in &mut ticket.htsget.urls {
+            entry.url = self.rebase_url(state, std::mem::take(&mut entry.url));
+        }
+        ticket
+    }
+
+    /// URL of the ticket endpoint `/{endpoint}/{id}` as this request
+    /// reached it, for `next` links
+    pub fn endpoint_url(&self, state: &AppState, endpoint: &str, id: &str) -> String {
+        let base_url = self.0.as_deref().unwrap_or(&state.base_url);
+        format!(
+            "{}/{}/{}",
+            base_url.trim_end_matches('/'),
+            endpoint,
+            percent_encode(id)
+        )
+    }
+
+    /// Point a single URL built from the configured base URL at the
+    /// forwarded one.
+    pub fn rebase_url(&self, state: &AppState, url: String) -> String {
+        let Some(base_url) = &self.0 else {
+            return url;
+        };
+        let configured = state.base_url.trim_end_matches('/');
+
+        match url.strip_prefix(configured) {
+            Some(rest) if rest.is_empty() || rest.starts_with(['/', '?']) => {
+                format!("{}{}", base_url, rest)
+            }
+            _ => url,
+        }
+    }
+}
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for RequestBaseUrl {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        let trusted = match (
+            &state.trusted_proxies,
+            parts.extensions.get::<ConnectInfo<SocketAddr>>(),
+        ) {
+            (Some(proxies), Some(ConnectInfo(peer))) => proxies.contains(peer.ip()),
+            _ => false,
+        };
+
+        if !trusted {
+            return Ok(Self(None));
+        }
+
+        let prefix = state.path_prefix.as_deref().unwrap_or_default();
+        Ok(Self(
+            forwarded_base_url(&parts.headers).map(|base_url| format!("{}{}", base_url, prefix)),
+        ))
+    }
+}
+
+/// Authenticated subject of a ticket request, if any. Bound into the ticket's
+/// signed data URLs so data fetches are attributed to the same caller, for
+/// quotas and the audit log.
+#[derive(Debug, Clone, Default)]
+pub struct RequestSubject(pub Option<String>);
+
+impl RequestSubject {
+    /// State whose signed data URLs carry this subject.
+    #[cfg(feature = "auth")]
+    pub fn bind(&self, mut state: AppState) -> AppState {
+        if let Some(subject) = &self.0 {
+            state.url_signer = state.url_signer.map(|signer| signer.with_subject(subject));
+        }
+        state
+    }
+
+    /// State whose signed data URLs carry this subject (unchanged without
+    /// the auth feature).
+    #[cfg(not(feature = "auth"))]
+    pub fn bind(&self, state: AppState) -> AppState {
+        state
+    }
+}
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestSubject {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        Ok(Self(
+            parts
+                .extensions
+                .get::<Subject>()
+                .map(|Subject(subject)| subject.clone()),
+        ))
+    }
 }
 
 impl AppState {
@@ -70,19 +420,321 @@ impl AppState {
     pub fn sign_data_url(&self, url: String) -> String {
         url
     }
+
+    /// Ticket entry for `range` of a file, as one of the `ticket_urls` data
+    /// URLs of a ticket: the signed URL and any headers the storage backend
+    /// needs clients to send with it. Fails, failing the ticket, if the
+    /// backend can't make a URL.
+    pub(crate) async fn ticket_entry(
+        &self,
+        id: &str,
+        format: Format,
+        range: Option<ByteRange>,
+        ticket_urls: usize,
+        class: Option<DataClass>,
+    ) -> Result<UrlEntry> {
+        let headers = self.storage.ticket_url_headers(id, format, range.as_ref());
+        let url = self
+            .storage
+            .ticket_data_url(id, format, range, ticket_urls)
+            .await?;
+        Ok(UrlEntry {
+            url: self.sign_data_url(url),
+            headers,
+            class,
+        })
+    }
+
+    /// How long a ticket's `urls` stay valid: until the first of them
+    /// expires, or for the ticket URL TTL if that's sooner; `None` if
+    /// neither applies. Inline `data:` URLs don't count toward the ticket's
+    /// size.
+    pub(crate) fn ticket_ttl(&self, urls: &[UrlEntry]) -> Option<Duration> {
+        let ticket_urls = urls.iter().filter(|u| !u.url.starts_with("data:")).count();
+        [
+            self.storage.url_expiry(ticket_urls),
+            self.signed_url_expiry(),
+            self.ticket_url_ttl,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    #[cfg(feature = "auth")]
+    fn signed_url_expiry(&self) -> Option<Duration> {
+        self.url_signer.as_ref().map(UrlSigner::expiry)
+    }
+
+    #[cfg(not(feature = "auth"))]
+    fn signed_url_expiry(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Check that a file exists and, if `needs_index`, find its index. The
+    /// lookups run concurrently since each is a round trip on remote storage.
+    pub(crate) async fn locate(
+        &self,
+        id: &str,
+        format: Format,
+        needs_index: bool,
+    ) -> Result<Option<PathBuf>> {
+        let (exists, index_path) = tokio::join!(self.storage.exists(id, format), async {
+            if needs_index {
+                self.storage.index_path(id, format).await
+            } else {
+                Ok(None)
+            }
+        });
+
+        if !exists? {
+            return Err(self.not_found(id, format).await);
+        }
+        index_path
+    }
+
+    /// The format to serve `id` in: `requested` if the request named one,
+    /// otherwise `default`, or with detection on, the first of `default`'s
+    /// datatype's formats that exists.
+    pub(crate) async fn request_format(
+        &self,
+        id: &str,
+        requested: Option<Format>,
+        default: Format,
+    ) -> Result<Format> {
+        if let Some(format) = requested {
+            return Ok(format);
+        }
+        if self.default_formats.detect {
+            for format in default.alternatives() {
+                if self.storage.exists(id, format).await? {
+                    return Ok(format);
+                }
+            }
+        }
+        Ok(default)
+    }
+
+    /// `NotFound` for `id` in `format`, listing the datatype's other formats
+    /// the ID is available in, if any.
+    pub(crate) async fn not_found(&self, id: &str, format: Format) -> Error {
+        let mut available = Vec::new();
+        for other in format.alternatives().into_iter().skip(1) {
+            if matches!(self.storage.exists(id, other).await, Ok(true)) {
+                available.push(format!("{:?}", other));
+            }
+        }
+
+        if available.is_empty() {
+            Error::NotFound(id.to_string())
+        } else {
+            Error::NotFound(format!(
+                "{} as {:?} (available: {})",
+                id,
+                format,
+                available.join(", ")
+            ))
+        }
+    }
+}
+
+/// Decode `%XX` escapes in a request path segment, matching what the router
+/// passes to handlers. Used by middleware that sees paths before routing.
+pub(crate) fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(byte) = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Encode `s` as one URL path segment, escaping everything but unreserved
+/// characters, so that IDs like `1000g/NA12878` route as one `:id`.
+pub(crate) fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+/// `Content-Disposition` value saving a download as `filename`, which comes
+/// from a dataset ID. `filename*` (RFC 6266) carries it exactly; the quoted
+/// `filename`, for clients without RFC 6266 support, replaces anything but
+/// printable ASCII, `"` and `\` included, with `_`.
+pub(crate) fn attachment(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback,
+        percent_encode(filename)
+    )
+}
+
+/// Format a time as an RFC 3339 UTC timestamp, e.g. `2024-01-31T12:00:00Z`.
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
 }
 
 /// Create the htsget router with all endpoints configured
 pub fn create_router(state: AppState) -> Router {
-    Router::new()
-        // htsget ticket endpoints
-        .route("/reads/:id", get(get_reads).post(post_reads))
-        .route("/variants/:id", get(get_variants).post(post_variants))
-        .route("/sequences/:id", get(get_sequences))
-        // Data serving endpoints (ticket URLs point here)
-        .route("/data/:format/:id", get(get_data))
+    create_router_with(state, Router::new())
+}
+
+/// Create the htsget router with `routes` served alongside the built-in
+/// endpoints, sharing their [`AppState`]. Paths must not overlap the
+/// built-in ones.
+pub fn create_router_with(state: AppState, routes: Router<AppState>) -> Router {
+    let mut router = Router::new();
+
+    // htsget ticket endpoints, per enabled datatype, with protocol version
+    // negotiation
+    if state.endpoints.reads {
+        router = router.route("/reads/:id", get(get_reads).post(post_reads));
+    }
+    if state.endpoints.variants {
+        router = router.route("/variants/:id", get(get_variants).post(post_variants));
+    }
+    if state.endpoints.sequences {
+        router = router.route("/sequences/:id", get(get_sequences).post(post_sequences));
+    }
+    if state.endpoints.refresh {
+        router = router.route(REFRESH_PATH, post(refresh_ticket));
+    }
+    router = router.route_layer(middleware::from_fn(negotiate));
+
+    if state.endpoints.reads {
+        router = router
+            .route("/reads/service-info", get(reads_service_info))
+            .route("/reads/:id/references", get(get_read_references))
+            .route("/reads/:id/coverage", get(get_read_coverage));
+    }
+    if state.endpoints.variants {
+        router = router
+            .route("/variants/service-info", get(variants_service_info))
+            .route("/variants/:id/references", get(get_variant_references))
+            .route("/variants/:id/stats", get(get_variant_stats));
+    }
+    if state.endpoints.sequences {
+        router = router.route("/sequences/service-info", get(sequences_service_info));
+    }
+    // Genome browser features
+    #[cfg(feature = "explore")]
+    if state.endpoints.reads {
+        router = router.route("/reads/:id/features", get(get_read_features));
+    }
+    #[cfg(feature = "explore")]
+    if state.endpoints.variants {
+        router = router.route("/variants/:id/features", get(get_variant_features));
+    }
+
+    let router = router
+        // Data serving endpoints (ticket URLs point here). HEAD on other
+        // routes runs the GET handler and drops the body.
+        .route("/data/:format/:id", get(get_data).head(head_data))
+        // File metadata for clients planning their slices
+        .route("/meta/:endpoint/:id", get(get_meta))
+        // Ticket and data in one response, for clients without htsget support
+        .route("/download/:endpoint/:id", get(get_download))
         // Service info
         .route("/", get(service_info))
-        .route("/service-info", get(service_info))
-        .with_state(state)
+        .route("/service-info", get(service_info));
+
+    // Whole-file checksums for verifying downloads
+    #[cfg(feature = "checksums")]
+    let router = router.route("/checksums/:endpoint/:id", get(get_checksums));
+
+    // refget v2 endpoints
+    #[cfg(feature = "refget")]
+    let router = router
+        .route("/sequence/service-info", get(refget_service_info))
+        .route("/sequence/:id", get(get_refget_sequence))
+        .route("/sequence/:id/metadata", get(get_refget_metadata));
+
+    // GA4GH DRS v1 objects
+    #[cfg(feature = "drs")]
+    let router = router
+        .route("/ga4gh/drs/v1/service-info", get(drs_service_info))
+        .route("/ga4gh/drs/v1/objects/:object_id", get(get_drs_object))
+        .route(
+            "/ga4gh/drs/v1/objects/:object_id/access/:access_id",
+            get(get_drs_access),
+        );
+
+    // Public key of signed tickets
+    #[cfg(feature = "jws")]
+    let router = if state.ticket_signer.is_some() {
+        router.route(JWKS_PATH, get(get_ticket_jwks))
+    } else {
+        router
+    };
+
+    // OpenAPI document and Swagger UI
+    #[cfg(feature = "openapi")]
+    let router = router.route(openapi::OPENAPI_PATH, get(get_openapi));
+    #[cfg(feature = "openapi")]
+    let router = if state.endpoints.swagger_ui {
+        // Relative to `/swagger-ui/`, so a path prefix is kept
+        let config = utoipa_swagger_ui::Config::from("../openapi.json");
+        router.merge(utoipa_swagger_ui::SwaggerUi::new("/swagger-ui").config(config))
+    } else {
+        router
+    };
+
+    // Demo UI
+    #[cfg(feature = "ui")]
+    let router = router
+        .route("/ui", get(get_ui_root))
+        .route("/ui/", get(get_ui_index))
+        .route("/ui/*path", get(get_ui_asset));
+
+    router.merge(routes).with_state(state)
 }
@@ -0,0 +1,127 @@
+//! htsget protocol version negotiation for ticket endpoints.
+//!
+//! Clients may name the protocol version they speak in `Accept`, e.g.
+//! `application/vnd.ga4gh.htsget.v1.3.0+json`. Any 1.x version is served
+//! (minor versions are backward compatible), as are `application/json` and
+//! wildcards; a request accepting none of these gets a 406 with an
+//! `UnsupportedFormat` error. Tickets are sent with the htsget media type
+//! (or plain JSON, when the server is configured for legacy clients).
+
+use crate::Error;
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+pub use crate::types::HTSGET_MEDIA_TYPE;
+
+/// Major protocol version served
+const HTSGET_MAJOR_VERSION: u32 = 1;
+
+/// Whether a media range from `Accept` can be answered with a ticket
+fn accepts_ticket(media_range: &str) -> bool {
+    let media_range = media_range.trim().to_ascii_lowercase();
+    match media_range.as_str() {
+        "*/*" | "application/*" | "application/json" => return true,
+        _ => {}
+    }
+
+    // application/vnd.ga4gh.htsget.v{major}.{minor}.{patch}+json
+    let Some(version) = media_range
+        .strip_prefix("application/vnd.ga4gh.htsget.v")
+        .and_then(|rest| rest.strip_suffix("+json"))
+    else {
+        return false;
+    };
+    let mut parts = version.split('.');
+    let major = parts.next().and_then(|major| major.parse::<u32>().ok());
+    major == Some(HTSGET_MAJOR_VERSION) && parts.all(|part| part.parse::<u32>().is_ok())
+}
+
+/// Check that the request's `Accept` header, if any, allows a ticket
+/// response. Media ranges with `q=0` are excluded, other weights ignored.
+pub(crate) fn check_accept(headers: &HeaderMap) -> crate::Result<()> {
+    let values: Vec<&str> = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect();
+    if values.is_empty() {
+        return Ok(());
+    }
+
+    let acceptable = values.iter().any(|value| {
+        let mut params = value.split(';');
+        let media_range = params.next().unwrap_or_default();
+        let excluded = params.any(|param| {
+            param
+                .split_once('=')
+                .filter(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .and_then(|(_, q)| q.trim().parse::<f32>().ok())
+                .is_some_and(|q| q == 0.0)
+        });
+        !excluded && accepts_ticket(media_range)
+    });
+
+    if acceptable {
+        Ok(())
+    } else {
+        Err(Error::NotAcceptable(format!(
+            "{} (supported: {})",
+            values.join(", "),
+            HTSGET_MEDIA_TYPE
+        )))
+    }
+}
+
+/// Reject ticket requests for unsupported protocol versions.
+pub async fn negotiate(req: Request, next: Next) -> Response {
+    if let Err(e) = check_accept(req.headers()) {
+        return e.into_response();
+    }
+
+    let mut response = next.run(req).await;
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_accepts_ticket() {
+        assert!(accepts_ticket(HTSGET_MEDIA_TYPE));
+        assert!(accepts_ticket("application/vnd.ga4gh.htsget.v1.2.0+json"));
+        assert!(accepts_ticket("Application/JSON"));
+        assert!(accepts_ticket("*/*"));
+        assert!(!accepts_ticket("application/vnd.ga4gh.htsget.v2.0.0+json"));
+        assert!(!accepts_ticket("application/vnd.ga4gh.htsget.vx+json"));
+        assert!(!accepts_ticket("text/html"));
+    }
+
+    #[test]
+    fn test_check_accept() {
+        assert!(check_accept(&HeaderMap::new()).is_ok());
+        assert!(check_accept(&accept("application/vnd.ga4gh.htsget.v1.3.0+json")).is_ok());
+        assert!(check_accept(&accept("text/html, application/json;q=0.9")).is_ok());
+        assert!(matches!(
+            check_accept(&accept("application/vnd.ga4gh.htsget.v2.0.0+json")),
+            Err(Error::NotAcceptable(_))
+        ));
+        assert!(check_accept(&accept("application/json;q=0")).is_err());
+    }
+}
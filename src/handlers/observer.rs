@@ -0,0 +1,96 @@
+//! Hooks for embedders: [`RequestObserver`]s see each ticket request before
+//! it is served, each ticket before it is sent, and each data response, for
+//! custom auditing, billing or ticket rewriting without changing handlers.
+//!
+//! Observers run in the order they were registered, inside the request, so
+//! slow work should be handed off to a task of the observer's own.
+
+use super::AppState;
+use crate::Result;
+use crate::storage::ByteRange;
+use crate::types::{DataClass, Format, HtsgetResponse, Region};
+use async_trait::async_trait;
+use axum::Json;
+
+/// A ticket request, as observers see it
+#[derive(Debug, Clone, Copy)]
+pub struct TicketRequestInfo<'a> {
+    /// Dataset ID
+    pub id: &'a str,
+    pub format: Format,
+    pub class: DataClass,
+    /// Requested regions; empty for the whole file
+    pub regions: &'a [Region],
+    /// Authenticated subject, if any
+    pub subject: Option<&'a str>,
+}
+
+/// Data sent by the data or download endpoint
+#[derive(Debug, Clone)]
+pub struct DataServed {
+    /// Dataset ID
+    pub id: String,
+    pub format: Format,
+    /// Bytes of the file read; `None` for the whole file or a download
+    pub range: Option<ByteRange>,
+    /// Length of the response body
+    pub bytes: u64,
+    /// Authenticated subject, if any
+    pub subject: Option<String>,
+}
+
+/// Callbacks around ticket and data requests. Every method has a default
+/// that does nothing, so observers implement only what they need.
+///
+/// Tickets built for `/download` are observed like any other; their URLs
+/// must be left in place for the download to be served.
+#[async_trait]
+pub trait RequestObserver: Send + Sync {
+    /// Before a ticket is built. An error rejects the request with it.
+    async fn on_ticket_request(&self, _request: &TicketRequestInfo<'_>) -> Result<()> {
+        Ok(())
+    }
+
+    /// After a ticket is built, before it is sent. The ticket may be
+    /// rewritten (e.g. URLs pointed at a CDN); an error rejects the request.
+    async fn on_ticket_response(
+        &self,
+        _request: &TicketRequestInfo<'_>,
+        _ticket: &mut HtsgetResponse,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// When a data response starts streaming. `HEAD` requests, redirects to
+    /// storage and `304 Not Modified` responses aren't reported.
+    async fn on_data_served(&self, _served: &DataServed) {}
+}
+
+impl AppState {
+    pub(crate) async fn observe_ticket_request(
+        &self,
+        request: &TicketRequestInfo<'_>,
+    ) -> Result<()> {
+        for observer in self.observers.iter() {
+            observer.on_ticket_request(request).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn observe_ticket_response(
+        &self,
+        request: &TicketRequestInfo<'_>,
+        mut ticket: Json<HtsgetResponse>,
+    ) -> Result<Json<HtsgetResponse>> {
+        for observer in self.observers.iter() {
+            observer.on_ticket_response(request, &mut ticket).await?;
+        }
+        Ok(ticket)
+    }
+
+    pub(crate) async fn observe_data_served(&self, served: DataServed) {
+        for observer in self.observers.iter() {
+            observer.on_data_served(&served).await;
+        }
+    }
+}
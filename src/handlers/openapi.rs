@@ -0,0 +1,213 @@
+//! OpenAPI 3 document (requires `openapi` feature).
+//!
+//! `GET /openapi.json` describes every route the deployment serves,
+//! extensions included: disabled datatypes and endpoints are left out, and
+//! the server URL is the path prefix, so the document matches what clients
+//! can call. With [`Endpoints::swagger_ui`](super::Endpoints::swagger_ui), a
+//! Swagger UI at `/swagger-ui/` renders it.
+
+use super::{AppState, Endpoints};
+use crate::error::{HtsgetError, HtsgetErrorBody};
+use crate::types::*;
+use axum::{Json, extract::State};
+use utoipa::OpenApi;
+use utoipa::openapi::{self, Server};
+
+/// Route of the OpenAPI document
+pub(crate) const OPENAPI_PATH: &str = "/openapi.json";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "htsgetr",
+        description = "htsget 1.3 server with FASTA/FASTQ and file metadata extensions"
+    ),
+    paths(
+        super::reads::get_reads,
+        super::reads::post_reads,
+        super::variants::get_variants,
+        super::variants::post_variants,
+        super::sequences::get_sequences,
+        super::sequences::post_sequences,
+        super::data::get_data,
+        super::data::head_data,
+        super::meta::get_meta,
+        super::meta::get_read_references,
+        super::meta::get_variant_references,
+        super::meta::get_variant_stats,
+        super::meta::get_read_coverage,
+        super::download::get_download,
+        super::refresh::refresh_ticket,
+        super::service_info::service_info,
+        super::service_info::reads_service_info,
+        super::service_info::variants_service_info,
+        super::service_info::sequences_service_info,
+    ),
+    components(schemas(
+        HtsgetResponse,
+        HtsgetResponseBody,
+        UrlEntry,
+        Format,
+        DataClass,
+        ReadsPostBody,
+        VariantsPostBody,
+        super::SequencesPostBody,
+        Region,
+        MetadataResponse,
+        FileMetadata,
+        ReferencesResponse,
+        References,
+        ReferenceSequence,
+        VariantStatsResponse,
+        VariantStats,
+        StatsBin,
+        CoverageResponse,
+        Coverage,
+        CoverageMethod,
+        CoverageBin,
+        Checksums,
+        ServiceInfo,
+        ServiceType,
+        Organization,
+        HtsgetCapabilities,
+        HtsgetError,
+        HtsgetErrorBody,
+        super::RefreshRequest,
+        super::TicketEndpoint,
+    )),
+    tags(
+        (name = "htsget", description = "htsget 1.3 ticket endpoints"),
+        (name = "data", description = "Data blocks that ticket URLs point to"),
+        (name = "extensions", description = "Endpoints beyond the htsget spec"),
+        (name = "service-info", description = "GA4GH service-info")
+    )
+)]
+struct ApiDoc;
+
+#[cfg(feature = "explore")]
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        super::explore::get_read_features,
+        super::explore::get_variant_features
+    ),
+    components(schemas(ReadFeature, VariantFeature))
+)]
+struct ExploreApi;
+
+#[cfg(feature = "checksums")]
+#[derive(OpenApi)]
+#[openapi(
+    paths(super::checksums::get_checksums),
+    components(schemas(ChecksumsResponse, FileChecksums))
+)]
+struct ChecksumsApi;
+
+#[cfg(feature = "drs")]
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        super::drs::get_drs_object,
+        super::drs::get_drs_access,
+        super::drs::drs_service_info
+    ),
+    components(schemas(
+        super::DrsObject,
+        super::DrsChecksum,
+        super::AccessMethod,
+        super::AccessUrl,
+        super::DrsServiceInfo
+    )),
+    tags((name = "drs", description = "GA4GH DRS v1 objects"))
+)]
+struct DrsApi;
+
+#[cfg(feature = "refget")]
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        super::refget::get_refget_sequence,
+        super::refget::get_refget_metadata,
+        super::refget::refget_service_info
+    ),
+    components(schemas(
+        super::refget::RefgetServiceInfo,
+        super::refget::RefgetCapabilities,
+        crate::refget::RefgetMetadataResponse,
+        crate::refget::RefgetMetadata,
+        crate::refget::RefgetAlias
+    )),
+    tags((name = "refget", description = "refget v2 sequences"))
+)]
+struct RefgetApi;
+
+/// The OpenAPI document for a deployment serving `endpoints` under
+/// `path_prefix`.
+pub fn api_doc(endpoints: Endpoints, path_prefix: Option<&str>) -> openapi::OpenApi {
+    let mut doc = ApiDoc::openapi();
+    #[cfg(feature = "explore")]
+    doc.merge(ExploreApi::openapi());
+    #[cfg(feature = "checksums")]
+    doc.merge(ChecksumsApi::openapi());
+    #[cfg(feature = "drs")]
+    doc.merge(DrsApi::openapi());
+    #[cfg(feature = "refget")]
+    doc.merge(RefgetApi::openapi());
+
+    doc.paths.paths.retain(|path, _| serves(endpoints, path));
+    doc.servers = Some(vec![Server::new(path_prefix.unwrap_or("/"))]);
+    doc
+}
+
+/// Whether a documented path is routed with `endpoints`
+fn serves(endpoints: Endpoints, path: &str) -> bool {
+    let under = |root: &str| {
+        path.strip_prefix(root)
+            .is_some_and(|rest| rest.starts_with('/'))
+    };
+
+    if under("/reads") {
+        endpoints.reads
+    } else if under("/variants") {
+        endpoints.variants
+    } else if under("/sequences") {
+        endpoints.sequences
+    } else if path == super::REFRESH_PATH {
+        endpoints.refresh
+    } else {
+        true
+    }
+}
+
+/// `GET /openapi.json` - this deployment's OpenAPI document
+pub async fn get_openapi(State(state): State<AppState>) -> Json<openapi::OpenApi> {
+    Json(api_doc(state.endpoints, state.path_prefix.as_deref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_paths() {
+        let doc = api_doc(Endpoints::default(), None);
+        let paths = &doc.paths.paths;
+        assert!(paths.contains_key("/reads/{id}"));
+        assert!(paths.contains_key("/data/{format}/{id}"));
+        assert!(!paths.contains_key("/tickets/refresh"));
+
+        let doc = api_doc(
+            Endpoints {
+                variants: false,
+                refresh: true,
+                ..Default::default()
+            },
+            Some("/htsget"),
+        );
+        let paths = &doc.paths.paths;
+        assert!(paths.contains_key("/tickets/refresh"));
+        assert!(paths.contains_key("/reads/{id}/coverage"));
+        assert!(!paths.keys().any(|path| path.starts_with("/variants")));
+        assert_eq!(doc.servers.unwrap()[0].url, "/htsget");
+    }
+}
@@ -0,0 +1,185 @@
+//! Ticket size limits and pages (extension).
+//!
+//! A POST with hundreds of regions can produce a ticket with thousands of
+//! URLs. [`TicketLimits`] caps the regions a request may name and, with a
+//! page size, splits tickets into pages: each page holds up to that many
+//! URLs, and all but the last carry a `next` URL for the following one.
+//! Pages are cut before their URLs are made, so a page signs only its own.
+//! GET requests are paged by repeating the query with `page=N`; POST
+//! requests by sending the same body to the `next` URL.
+
+use super::AppState;
+use crate::types::Region;
+use crate::{Error, Result};
+use axum::{extract::FromRequestParts, http::request::Parts};
+
+/// Regions a request may name by default
+pub const DEFAULT_MAX_REGIONS: usize = 1000;
+
+/// How many regions a ticket request may name, and how many URLs a ticket
+/// page holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TicketLimits {
+    /// Most regions in a request
+    pub max_regions: usize,
+    /// Most URLs in a ticket page; `None` sends every URL in one ticket
+    pub page_size: Option<usize>,
+}
+
+impl Default for TicketLimits {
+    fn default() -> Self {
+        Self {
+            max_regions: DEFAULT_MAX_REGIONS,
+            page_size: None,
+        }
+    }
+}
+
+impl TicketLimits {
+    /// Reject requests naming more than `max_regions` regions
+    pub fn check_regions(&self, regions: &[Region]) -> Result<()> {
+        if regions.len() > self.max_regions {
+            return Err(Error::InvalidInput(format!(
+                "too many regions: {} (at most {})",
+                regions.len(),
+                self.max_regions
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The page of a ticket a request asks for with the `page` query parameter
+/// (1-based, the first by default), and the rest of its query for the
+/// `next` URL.
+#[derive(Debug, Clone, Default)]
+pub struct TicketPage {
+    page: Option<usize>,
+    query: Vec<(String, String)>,
+}
+
+impl TicketPage {
+    /// From a query string, e.g. `referenceName=chr1&page=2`
+    pub fn from_query(query: &str) -> Result<Self> {
+        let mut page = None;
+        let mut rest = Vec::new();
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            if key == "page" {
+                let number = value.parse().ok().filter(|&number| number > 0);
+                page = Some(number.ok_or_else(|| {
+                    Error::InvalidInput(format!("page must be a positive integer: {}", value))
+                })?);
+            } else {
+                rest.push((key.into_owned(), value.into_owned()));
+            }
+        }
+        Ok(Self { page, query: rest })
+    }
+
+    /// The requested page of `items`, `page_size` to a page, and the
+    /// number of the page after it if there is one
+    pub fn select<T>(
+        &self,
+        page_size: Option<usize>,
+        mut items: Vec<T>,
+    ) -> Result<(Vec<T>, Option<usize>)> {
+        let page = self.page.unwrap_or(1);
+        let len = items.len();
+        let size = page_size.unwrap_or(len).max(1);
+        let pages = len.div_ceil(size).max(1);
+        if page > pages {
+            return Err(Error::InvalidInput(format!(
+                "page {} is past the end of the ticket ({} pages)",
+                page, pages
+            )));
+        }
+
+        let start = (page - 1) * size;
+        items.truncate(start + size);
+        items.drain(..start);
+        Ok((items, (page < pages).then_some(page + 1)))
+    }
+
+    /// URL of page `page` of the ticket endpoint at `endpoint_url`, with
+    /// the rest of this request's query
+    pub fn page_url(&self, endpoint_url: &str, page: usize) -> String {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        query.extend_pairs(&self.query);
+        query.append_pair("page", &page.to_string());
+        format!("{}?{}", endpoint_url, query.finish())
+    }
+}
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for TicketPage {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &AppState,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        Self::from_query(parts.uri.query().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENDPOINT: &str = "http://localhost:8080/reads/sample";
+
+    #[test]
+    fn test_select() {
+        let page = TicketPage::from_query("referenceName=chr1&start=0").unwrap();
+        let (first, next) = page.select(Some(2), (0..5).collect()).unwrap();
+        assert_eq!(first, [0, 1]);
+        assert_eq!(next, Some(2));
+        assert_eq!(
+            page.page_url(ENDPOINT, 2),
+            "http://localhost:8080/reads/sample?referenceName=chr1&start=0&page=2"
+        );
+
+        let page = TicketPage::from_query("page=3&referenceName=chr1").unwrap();
+        let (last, next) = page.select(Some(2), (0..5).collect()).unwrap();
+        assert_eq!(last, [4]);
+        assert!(next.is_none());
+        assert_eq!(
+            page.page_url(ENDPOINT, 4),
+            "http://localhost:8080/reads/sample?referenceName=chr1&page=4"
+        );
+
+        let past = TicketPage::from_query("page=4")
+            .unwrap()
+            .select(Some(2), (0..5).collect::<Vec<_>>());
+        assert!(matches!(past, Err(Error::InvalidInput(_))));
+        assert!(TicketPage::from_query("page=0").is_err());
+
+        // Without a page size the whole ticket is the only page
+        let (whole, next) = TicketPage::default()
+            .select(None, (0..5).collect::<Vec<_>>())
+            .unwrap();
+        assert_eq!(whole.len(), 5);
+        assert!(next.is_none());
+        let (empty, next) = TicketPage::default()
+            .select(Some(2), Vec::<usize>::new())
+            .unwrap();
+        assert!(empty.is_empty() && next.is_none());
+    }
+    #[test]
+    fn test_check_regions() {
+        let limits = TicketLimits {
+            max_regions: 2,
+            page_size: None,
+        };
+        let region = Region::new("chr1", None, None).unwrap();
+        assert!(
+            limits
+                .check_regions(&[region.clone(), region.clone()])
+                .is_ok()
+        );
+        assert!(matches!(
+            limits.check_regions(&[region.clone(), region.clone(), region]),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+}
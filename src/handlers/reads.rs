@@ -1,25 +1,46 @@
-use super::AppState;
+use super::ticket::{TicketBuilder, TicketRequest};
+use super::{AppState, RequestBaseUrl, RequestSubject, TicketPage};
 use crate::{
     Error, Result,
-    formats::{BamIndexReader, CramIndexReader},
-    types::{
-        DataClass, Format, HtsgetResponse, HtsgetResponseBody, ReadsPostBody, ReadsQuery, Region,
-        UrlEntry,
-    },
+    audit::Access,
+    types::{Format, HtsgetResponse, ReadsPostBody, ReadsQuery},
 };
 use axum::{
     Json,
     extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
 };
 
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/reads/{id}",
+        params(("id" = String, Path, description = "Dataset ID"), crate::types::ReadsQuery, ("page" = Option<usize>, Query, description = "1-based ticket page, when tickets are paged (extension)")),
+        responses(
+            (status = 200, description = "htsget ticket", body = crate::types::HtsgetResponse),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "htsget"
+    )
+)]
 pub async fn get_reads(
     State(state): State<AppState>,
+    base_url: RequestBaseUrl,
+    subject: RequestSubject,
+    headers: HeaderMap,
+    page: TicketPage,
     Path(id): Path<String>,
     Query(query): Query<ReadsQuery>,
-) -> Result<Json<HtsgetResponse>> {
+) -> Result<Response> {
+    let state = subject.bind(state);
     tracing::debug!("get_reads: id={}, query={:?}", id, query);
 
-    let format = query.format.unwrap_or(Format::Bam);
+    let format = state
+        .request_format(&id, query.format, state.default_formats.reads)
+        .await?;
     tracing::debug!("get_reads: format={:?}", format);
 
     if !format.is_reads() {
@@ -37,29 +58,60 @@ pub async fn get_reads(
         file_path.exists()
     );
 
-    if !state.storage.exists(&id, format).await? {
-        return Err(Error::NotFound(id));
+    let request = TicketRequest::from_query(
+        query.class.as_deref(),
+        query.reference_name.as_deref(),
+        query.start,
+        query.end,
+    )?;
+    let precise = query.precise.unwrap_or(false);
+
+    let observed = request.observed(&id, format, &subject);
+    state.observe_ticket_request(&observed).await?;
+    let endpoint_url = base_url.endpoint_url(&state, "reads", &id);
+    let ticket =
+        build_reads_response(&state, &id, format, &request, precise, &page, endpoint_url).await?;
+    let ticket = state
+        .observe_ticket_response(&observed, base_url.rebase(&state, ticket))
+        .await?;
+
+    let mut cache_key = request.cache_key();
+    if precise {
+        cache_key.push_str(":precise");
     }
-
-    let class = query.class.unwrap_or_default();
-    let regions = match (&query.reference_name, query.start, query.end) {
-        (Some(ref_name), start, end) => vec![Region {
-            reference_name: ref_name.clone(),
-            start,
-            end,
-        }],
-        _ => vec![],
-    };
-
-    build_reads_response(&state, &id, format, class, &regions).await
+    let response = state
+        .cached_ticket(&headers, &id, format, &cache_key, ticket)
+        .await?;
+    Ok(Access::new(format, request.regions).attach(response))
 }
 
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/reads/{id}",
+        params(("id" = String, Path, description = "Dataset ID"), ("page" = Option<usize>, Query, description = "1-based ticket page, when tickets are paged (extension)")),
+        request_body = crate::types::ReadsPostBody,
+        responses(
+            (status = 200, description = "htsget ticket", body = crate::types::HtsgetResponse),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "htsget"
+    )
+)]
 pub async fn post_reads(
     State(state): State<AppState>,
+    base_url: RequestBaseUrl,
+    subject: RequestSubject,
+    page: TicketPage,
     Path(id): Path<String>,
     Json(body): Json<ReadsPostBody>,
-) -> Result<Json<HtsgetResponse>> {
-    let format = body.format.unwrap_or(Format::Bam);
+) -> Result<Response> {
+    let state = subject.bind(state);
+    let format = state
+        .request_format(&id, body.format, state.default_formats.reads)
+        .await?;
 
     if !format.is_reads() {
         return Err(Error::UnsupportedFormat(format!(
@@ -68,116 +120,35 @@ pub async fn post_reads(
         )));
     }
 
-    if !state.storage.exists(&id, format).await? {
-        return Err(Error::NotFound(id));
-    }
-
-    let class = body.class.unwrap_or_default();
-    let regions = body.regions.unwrap_or_default();
-
-    build_reads_response(&state, &id, format, class, &regions).await
+    let request = TicketRequest::from_body(body.class.as_deref(), body.regions)?;
+    state.ticket_limits.check_regions(&request.regions)?;
+    let precise = body.precise.unwrap_or(false);
+
+    let observed = request.observed(&id, format, &subject);
+    state.observe_ticket_request(&observed).await?;
+    let endpoint_url = base_url.endpoint_url(&state, "reads", &id);
+    let ticket =
+        build_reads_response(&state, &id, format, &request, precise, &page, endpoint_url).await?;
+    let ticket = state
+        .observe_ticket_response(&observed, base_url.rebase(&state, ticket))
+        .await?;
+    Ok(Access::new(format, request.regions).attach(state.ticket_response(ticket)))
 }
 
+/// Ticket for a reads request, from the format's index reader. A `precise`
+/// ticket points region slices at the data endpoint's record filtering.
 async fn build_reads_response(
     state: &AppState,
     id: &str,
     format: Format,
-    class: DataClass,
-    regions: &[Region],
+    request: &TicketRequest,
+    precise: bool,
+    page: &TicketPage,
+    endpoint_url: String,
 ) -> Result<Json<HtsgetResponse>> {
-    let mut urls = Vec::new();
-    let file_path = state.storage.file_path(id, format);
-
-    match class {
-        DataClass::Header => {
-            // Return only the header block - dispatch based on format
-            let header_range = match format {
-                Format::Bam => BamIndexReader::header_range(&file_path).await?,
-                Format::Cram => CramIndexReader::header_range(&file_path).await?,
-                _ => return Err(Error::UnsupportedFormat(format!("{:?}", format))),
-            };
-            urls.push(UrlEntry {
-                url: state.sign_data_url(state.storage.data_url(id, format, Some(header_range))),
-                headers: None,
-                class: Some(DataClass::Header),
-            });
-        }
-        DataClass::Body => {
-            if regions.is_empty() {
-                // No regions - return entire file
-                urls.push(UrlEntry {
-                    url: state.sign_data_url(state.storage.data_url(id, format, None)),
-                    headers: None,
-                    class: None,
-                });
-            } else {
-                // Check if index is available
-                let index_path = state.storage.index_path(id, format).await?;
-
-                if let Some(idx_path) = index_path {
-                    // Query index for byte ranges - dispatch based on format
-                    let indexed = match format {
-                        Format::Bam => {
-                            let header = BamIndexReader::read_header(&file_path).await?;
-                            BamIndexReader::query_ranges(&file_path, &idx_path, regions, &header)
-                                .await?
-                        }
-                        Format::Cram => {
-                            CramIndexReader::query_ranges(&file_path, &idx_path, regions).await?
-                        }
-                        _ => return Err(Error::UnsupportedFormat(format!("{:?}", format))),
-                    };
-
-                    // Add header block first
-                    urls.push(UrlEntry {
-                        url: state.sign_data_url(state.storage.data_url(
-                            id,
-                            format,
-                            Some(indexed.header_range),
-                        )),
-                        headers: None,
-                        class: Some(DataClass::Header),
-                    });
-
-                    // Add data blocks
-                    if indexed.data_ranges.is_empty() {
-                        // Index query returned no specific ranges - return whole file body
-                        // This shouldn't happen if index was properly queried
-                        urls.push(UrlEntry {
-                            url: state.sign_data_url(state.storage.data_url(id, format, None)),
-                            headers: None,
-                            class: Some(DataClass::Body),
-                        });
-                    } else {
-                        for range in indexed.data_ranges {
-                            urls.push(UrlEntry {
-                                url: state.sign_data_url(state.storage.data_url(
-                                    id,
-                                    format,
-                                    Some(range),
-                                )),
-                                headers: None,
-                                class: Some(DataClass::Body),
-                            });
-                        }
-                    }
-                } else {
-                    // No index available - return whole file
-                    urls.push(UrlEntry {
-                        url: state.sign_data_url(state.storage.data_url(id, format, None)),
-                        headers: None,
-                        class: None,
-                    });
-                }
-            }
-        }
-    }
-
-    Ok(Json(HtsgetResponse {
-        htsget: HtsgetResponseBody {
-            format,
-            urls,
-            md5: None,
-        },
-    }))
+    TicketBuilder::new(state, id, format)?
+        .precise(precise)?
+        .page(page, endpoint_url)
+        .build(request.class, &request.regions)
+        .await
 }
@@ -0,0 +1,214 @@
+use super::AppState;
+use crate::{
+    Error, Result,
+    formats::{FastaIndexReader, GziIndex},
+    refget::{RefgetIndex, RefgetMetadataResponse},
+    storage::ByteRange,
+    types::{Format, Region},
+};
+use axum::{
+    Json,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::Response,
+};
+use noodles::bgzf;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::sync::Arc;
+
+/// Content type for refget sequence responses
+const REFGET_SEQUENCE_CONTENT_TYPE: &str = "text/vnd.ga4gh.refget.v2.0.0+plain; charset=us-ascii";
+
+#[derive(Debug, Deserialize, Default)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct RefgetSequenceQuery {
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+}
+
+/// Refget service-info response
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefgetServiceInfo {
+    pub refget: RefgetCapabilities,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefgetCapabilities {
+    pub circular_supported: bool,
+    #[cfg_attr(feature = "openapi", schema(value_type = Vec<String>))]
+    pub algorithms: Vec<&'static str>,
+    #[cfg_attr(feature = "openapi", schema(value_type = Vec<String>))]
+    pub identifier_types: Vec<&'static str>,
+    pub subsequence_limit: Option<u64>,
+}
+
+/// `GET /sequence/:id` - retrieve a (sub)sequence by checksum
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/sequence/{id}",
+        params(("id" = String, Path, description = "Sequence checksum (MD5, `ga4gh` or TRUNC512)"), RefgetSequenceQuery),
+        responses(
+            (status = 200, description = "Sequence", content_type = "text/vnd.ga4gh.refget.v2.0.0+plain"),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "refget"
+    )
+)]
+pub async fn get_refget_sequence(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<RefgetSequenceQuery>,
+) -> Result<Response> {
+    let index = refget_index(&state)?;
+    let entry = index
+        .lookup(&id)
+        .ok_or_else(|| Error::NotFound(id.clone()))?;
+
+    let start = query.start.unwrap_or(0);
+    let end = query.end.unwrap_or(entry.length);
+
+    if start > end {
+        return Err(Error::InvalidRange(
+            "start greater than end (circular sequences not supported)".to_string(),
+        ));
+    }
+    if end > entry.length {
+        return Err(Error::InvalidRange(format!(
+            "end {} exceeds sequence length {}",
+            end, entry.length
+        )));
+    }
+
+    let mut sequence = Vec::with_capacity((end - start) as usize);
+
+    if start < end {
+        let index_path = state
+            .storage
+            .index_path(&entry.id, Format::Fasta)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("FASTA index for {}", entry.id)))?;
+
+        let region = Region {
+            reference_name: entry.name.clone(),
+            start: Some(start),
+            end: Some(end),
+        };
+        let fasta_path = state.storage.file_path(&entry.id, Format::Fasta);
+        let gzi = match state.storage.gzi_path(&entry.id, Format::Fasta).await? {
+            Some(gzi_path) => Some(GziIndex::read(&gzi_path).await?),
+            None => None,
+        };
+        // FAI offsets, into the decompressed stream of a bgzipped file
+        let indexed = FastaIndexReader::query_ranges(&fasta_path, &index_path, &[region]).await?;
+
+        for range in indexed.data_ranges {
+            let bytes = match &gzi {
+                Some(gzi) => read_bgzf_range(&state, &entry.id, gzi, range).await?,
+                None => state
+                    .storage
+                    .read_bytes(&entry.id, Format::Fasta, Some(range))
+                    .await?
+                    .to_vec(),
+            };
+            sequence.extend(bytes.iter().filter(|b| !b.is_ascii_whitespace()));
+        }
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, REFGET_SEQUENCE_CONTENT_TYPE)
+        .header(header::CONTENT_LENGTH, sequence.len())
+        .body(Body::from(sequence))
+        .unwrap())
+}
+
+/// `GET /sequence/:id/metadata` - checksums, length and aliases for a sequence
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/sequence/{id}/metadata",
+        params(("id" = String, Path, description = "Sequence checksum (MD5, `ga4gh` or TRUNC512)")),
+        responses(
+            (status = 200, description = "Sequence metadata", body = crate::refget::RefgetMetadataResponse),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "refget"
+    )
+)]
+pub async fn get_refget_metadata(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<RefgetMetadataResponse>> {
+    let index = refget_index(&state)?;
+    let entry = index.lookup(&id).ok_or(Error::NotFound(id))?;
+
+    Ok(Json(RefgetMetadataResponse::from(entry)))
+}
+
+/// `GET /sequence/service-info` - refget capabilities
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/sequence/service-info",
+        responses(
+            (status = 200, description = "refget service-info", body = RefgetServiceInfo),
+        ),
+        tag = "refget"
+    )
+)]
+pub async fn refget_service_info() -> Json<RefgetServiceInfo> {
+    Json(RefgetServiceInfo {
+        refget: RefgetCapabilities {
+            circular_supported: false,
+            algorithms: vec!["md5", "ga4gh", "trunc512"],
+            identifier_types: vec![],
+            subsequence_limit: None,
+        },
+    })
+}
+
+/// Decompressed bytes `range` of a bgzipped FASTA, read from the blocks
+/// its GZI maps them to
+async fn read_bgzf_range(
+    state: &AppState,
+    id: &str,
+    gzi: &GziIndex,
+    range: ByteRange,
+) -> Result<Vec<u8>> {
+    let end = range.end.unwrap_or(u64::MAX);
+    let blocks = gzi.compressed_range(range.start, end);
+    let compressed = state
+        .storage
+        .read_bytes(id, Format::Fasta, Some(blocks))
+        .await?;
+
+    let mut decompressed = Vec::new();
+    bgzf::io::Reader::new(&compressed[..])
+        .read_to_end(&mut decompressed)
+        .map_err(|e| Error::Internal(format!("failed to decompress FASTA: {}", e)))?;
+
+    let skip = (range.start - gzi.block_start(range.start)) as usize;
+    let len = (end - range.start).min(usize::MAX as u64) as usize;
+    Ok(decompressed.into_iter().skip(skip).take(len).collect())
+}
+
+fn refget_index(state: &AppState) -> Result<&Arc<RefgetIndex>> {
+    state
+        .refget
+        .as_ref()
+        .ok_or_else(|| Error::NotFound("refget is not configured".to_string()))
+}
@@ -0,0 +1,148 @@
+//! Ticket refresh (extension): `POST /tickets/refresh` re-issues a ticket,
+//! with fresh URLs, from the query that produced it. For clients that can't
+//! consume a large ticket before its URLs expire.
+
+use super::sequences::SequencesQuery;
+use super::{
+    AppState, RequestBaseUrl, RequestSubject, TicketPage, get_reads, get_sequences, get_variants,
+};
+use crate::{
+    Error, Result,
+    types::{ReadsQuery, VariantsQuery},
+};
+use axum::{
+    Json,
+    body::Body,
+    extract::{Path, Query, Request, State},
+    http::{HeaderMap, Uri},
+    response::Response,
+};
+use serde::Deserialize;
+
+/// Route of the refresh endpoint
+pub(crate) const REFRESH_PATH: &str = "/tickets/refresh";
+
+/// Largest refresh request body read by middleware looking for its dataset
+const MAX_REFRESH_BODY: usize = 64 * 1024;
+
+/// Ticket endpoint a refreshed ticket was issued by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum TicketEndpoint {
+    Reads,
+    Variants,
+    Sequences,
+}
+
+impl TicketEndpoint {
+    /// Path segment of the endpoint (`reads`, `variants` or `sequences`)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TicketEndpoint::Reads => "reads",
+            TicketEndpoint::Variants => "variants",
+            TicketEndpoint::Sequences => "sequences",
+        }
+    }
+}
+
+/// Body of `POST /tickets/refresh`: the original ticket request.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefreshRequest {
+    pub endpoint: TicketEndpoint,
+    pub id: String,
+    /// Query string of the original `GET` request, e.g.
+    /// `referenceName=chr1&start=0`
+    #[serde(default)]
+    pub query: String,
+}
+
+/// Re-issue a ticket as the `GET` request it describes would.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/tickets/refresh",
+        request_body = RefreshRequest,
+        responses(
+            (status = 200, description = "htsget ticket", body = crate::types::HtsgetResponse),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "extensions"
+    )
+)]
+pub async fn refresh_ticket(
+    State(state): State<AppState>,
+    base_url: RequestBaseUrl,
+    subject: RequestSubject,
+    headers: HeaderMap,
+    Json(request): Json<RefreshRequest>,
+) -> Result<Response> {
+    let uri: Uri = format!("/?{}", request.query)
+        .parse()
+        .map_err(|_| Error::InvalidInput(format!("invalid query: {}", request.query)))?;
+    let page = TicketPage::from_query(&request.query)?;
+    let endpoints = state.endpoints;
+    let id = Path(request.id);
+
+    match request.endpoint {
+        TicketEndpoint::Reads if endpoints.reads => {
+            let query = parse_query::<ReadsQuery>(&uri)?;
+            get_reads(State(state), base_url, subject, headers, page, id, query).await
+        }
+        TicketEndpoint::Variants if endpoints.variants => {
+            let query = parse_query::<VariantsQuery>(&uri)?;
+            get_variants(State(state), base_url, subject, headers, page, id, query).await
+        }
+        TicketEndpoint::Sequences if endpoints.sequences => {
+            let query = parse_query::<SequencesQuery>(&uri)?;
+            get_sequences(State(state), base_url, subject, headers, page, id, query).await
+        }
+        endpoint => Err(Error::NotFound(format!("/{}", endpoint.as_str()))),
+    }
+}
+
+fn parse_query<T: serde::de::DeserializeOwned>(uri: &Uri) -> Result<Query<T>> {
+    Query::try_from_uri(uri).map_err(|e| Error::InvalidInput(e.body_text()))
+}
+
+/// Endpoint and dataset ID a refresh request (or export job, which names
+/// them the same way) is for, for middleware that sees it before routing.
+/// The body is buffered and put back; a body that is too large or not a
+/// refresh request yields `None` (and is rejected by the handler).
+pub(crate) async fn refresh_dataset(request: Request) -> (Request, Option<(&'static str, String)>) {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, MAX_REFRESH_BODY)
+        .await
+        .unwrap_or_default();
+    let dataset = serde_json::from_slice::<RefreshRequest>(&bytes)
+        .ok()
+        .map(|refresh| (refresh.endpoint.as_str(), refresh.id));
+
+    (Request::from_parts(parts, Body::from(bytes)), dataset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_refresh_dataset() {
+        let body = r#"{"endpoint":"reads","id":"NA12878","query":"referenceName=chr1"}"#;
+        let request = Request::post(REFRESH_PATH).body(Body::from(body)).unwrap();
+
+        let (request, dataset) = refresh_dataset(request).await;
+        assert_eq!(dataset, Some(("reads", "NA12878".to_string())));
+
+        // The body is still there for the handler
+        let bytes = axum::body::to_bytes(request.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(bytes, body.as_bytes());
+
+        let request = Request::post(REFRESH_PATH).body(Body::from("{}")).unwrap();
+        assert_eq!(refresh_dataset(request).await.1, None);
+    }
+}
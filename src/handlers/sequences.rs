@@ -1,30 +1,160 @@
-use super::AppState;
+use super::ticket::{TicketBuilder, TicketRequest};
+use super::{AppState, RequestBaseUrl, RequestSubject, TicketPage};
 use crate::{
     Error, Result,
-    types::{Format, HtsgetResponse, HtsgetResponseBody, UrlEntry},
+    audit::Access,
+    formats::RecordRange,
+    types::{Format, HtsgetResponse, Region},
 };
 use axum::{
     Json,
     extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
 };
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize, Default)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
 pub struct SequencesQuery {
     pub format: Option<Format>,
+    /// Only `body`: FASTA and FASTQ have no header
+    pub class: Option<String>,
     #[serde(rename = "referenceName")]
     pub reference_name: Option<String>,
     pub start: Option<u64>,
     pub end: Option<u64>,
+    /// FASTQ record slice extension (e.g., `0-1000000`)
+    pub records: Option<String>,
+}
+
+/// POST request body for multiple regions
+#[derive(Debug, Deserialize, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SequencesPostBody {
+    pub format: Option<Format>,
+    /// Only `body`: FASTA and FASTQ have no header
+    pub class: Option<String>,
+    pub regions: Option<Vec<Region>>,
+    /// FASTQ record slice extension (e.g., `0-1000000`)
+    pub records: Option<String>,
 }
 
 /// Extension endpoint for FASTA/FASTQ access (not part of htsget spec)
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/sequences/{id}",
+        params(("id" = String, Path, description = "Dataset ID"), SequencesQuery, ("page" = Option<usize>, Query, description = "1-based ticket page, when tickets are paged (extension)")),
+        responses(
+            (status = 200, description = "htsget ticket", body = crate::types::HtsgetResponse),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "extensions"
+    )
+)]
 pub async fn get_sequences(
     State(state): State<AppState>,
+    base_url: RequestBaseUrl,
+    subject: RequestSubject,
+    headers: HeaderMap,
+    page: TicketPage,
     Path(id): Path<String>,
     Query(query): Query<SequencesQuery>,
-) -> Result<Json<HtsgetResponse>> {
-    let format = query.format.unwrap_or(Format::Fasta);
+) -> Result<Response> {
+    let state = subject.bind(state);
+    let format = sequences_format(&state, &id, query.format).await?;
+
+    let request = TicketRequest::from_query(
+        query.class.as_deref(),
+        query.reference_name.as_deref(),
+        query.start,
+        query.end,
+    )?;
+
+    let observed = request.observed(&id, format, &subject);
+    state.observe_ticket_request(&observed).await?;
+    let endpoint_url = base_url.endpoint_url(&state, "sequences", &id);
+    let ticket = build_sequences_response(
+        &state,
+        &id,
+        format,
+        &request,
+        query.records.as_deref(),
+        &page,
+        endpoint_url,
+    )
+    .await?;
+    let ticket = state
+        .observe_ticket_response(&observed, base_url.rebase(&state, ticket))
+        .await?;
+
+    let request_key = format!("{:?}", (request.cache_key(), &query.records));
+    let response = state
+        .cached_ticket(&headers, &id, format, &request_key, ticket)
+        .await?;
+    Ok(Access::new(format, request.regions).attach(response))
+}
+
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/sequences/{id}",
+        params(("id" = String, Path, description = "Dataset ID"), ("page" = Option<usize>, Query, description = "1-based ticket page, when tickets are paged (extension)")),
+        request_body = SequencesPostBody,
+        responses(
+            (status = 200, description = "htsget ticket", body = crate::types::HtsgetResponse),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "extensions"
+    )
+)]
+pub async fn post_sequences(
+    State(state): State<AppState>,
+    base_url: RequestBaseUrl,
+    subject: RequestSubject,
+    page: TicketPage,
+    Path(id): Path<String>,
+    Json(body): Json<SequencesPostBody>,
+) -> Result<Response> {
+    let state = subject.bind(state);
+    let format = sequences_format(&state, &id, body.format).await?;
+
+    let request = TicketRequest::from_body(body.class.as_deref(), body.regions)?;
+    state.ticket_limits.check_regions(&request.regions)?;
+
+    let observed = request.observed(&id, format, &subject);
+    state.observe_ticket_request(&observed).await?;
+    let endpoint_url = base_url.endpoint_url(&state, "sequences", &id);
+    let ticket = build_sequences_response(
+        &state,
+        &id,
+        format,
+        &request,
+        body.records.as_deref(),
+        &page,
+        endpoint_url,
+    )
+    .await?;
+    let ticket = state
+        .observe_ticket_response(&observed, base_url.rebase(&state, ticket))
+        .await?;
+    Ok(Access::new(format, request.regions).attach(state.ticket_response(ticket)))
+}
+
+/// The format to serve `id` in, which must be a sequence format
+async fn sequences_format(state: &AppState, id: &str, requested: Option<Format>) -> Result<Format> {
+    let format = state
+        .request_format(id, requested, state.default_formats.sequences)
+        .await?;
 
     if !format.is_sequences() {
         return Err(Error::UnsupportedFormat(format!(
@@ -32,24 +162,26 @@ pub async fn get_sequences(
             format
         )));
     }
+    Ok(format)
+}
 
-    if !state.storage.exists(&id, format).await? {
-        return Err(Error::NotFound(id));
-    }
-
-    // For FASTA with .fai index, we could support region queries
-    // For now, return the whole file
-    let urls = vec![UrlEntry {
-        url: state.sign_data_url(state.storage.data_url(&id, format, None)),
-        headers: None,
-        class: None,
-    }];
-
-    Ok(Json(HtsgetResponse {
-        htsget: HtsgetResponseBody {
-            format,
-            urls,
-            md5: None,
-        },
-    }))
+/// Ticket for a GET or POST sequences request. FASTA and FASTQ have no
+/// header, so unlike reads and variants these tickets are only the blocks
+/// covering the request. FASTQ `records` take precedence over regions,
+/// which FASTQ has no index for.
+async fn build_sequences_response(
+    state: &AppState,
+    id: &str,
+    format: Format,
+    request: &TicketRequest,
+    records: Option<&str>,
+    page: &TicketPage,
+    endpoint_url: String,
+) -> Result<Json<HtsgetResponse>> {
+    let records = records.map(str::parse::<RecordRange>).transpose()?;
+    TicketBuilder::new(state, id, format)?
+        .records(records)
+        .page(page, endpoint_url)
+        .build(request.class, &request.regions)
+        .await
 }
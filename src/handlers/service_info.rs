@@ -1,8 +1,82 @@
+use super::AppState;
 use crate::types::{Format, HtsgetCapabilities, Organization, ServiceInfo, ServiceType};
-use axum::Json;
+use axum::{Json, extract::State};
 
-pub async fn service_info() -> Json<ServiceInfo> {
-    Json(ServiceInfo {
+/// `GET /service-info` - describes the reads endpoint, or the first enabled
+/// of variants and sequences when reads are disabled, since a service-info
+/// document advertises a single datatype.
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/service-info",
+        responses(
+            (status = 200, description = "GA4GH service-info", body = crate::types::ServiceInfo),
+        ),
+        tag = "service-info"
+    )
+)]
+pub async fn service_info(State(state): State<AppState>) -> Json<ServiceInfo> {
+    let endpoints = state.endpoints;
+    if !endpoints.reads && endpoints.variants {
+        variants_service_info().await
+    } else if !endpoints.reads && endpoints.sequences {
+        sequences_service_info().await
+    } else {
+        reads_service_info().await
+    }
+}
+
+/// `GET /reads/service-info`
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/reads/service-info",
+        responses(
+            (status = 200, description = "GA4GH service-info", body = crate::types::ServiceInfo),
+        ),
+        tag = "service-info"
+    )
+)]
+pub async fn reads_service_info() -> Json<ServiceInfo> {
+    Json(describe("reads", vec![Format::Bam, Format::Cram]))
+}
+
+/// `GET /variants/service-info`
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/variants/service-info",
+        responses(
+            (status = 200, description = "GA4GH service-info", body = crate::types::ServiceInfo),
+        ),
+        tag = "service-info"
+    )
+)]
+pub async fn variants_service_info() -> Json<ServiceInfo> {
+    Json(describe("variants", vec![Format::Vcf, Format::Bcf]))
+}
+
+/// `GET /sequences/service-info` (extension datatype)
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/sequences/service-info",
+        responses(
+            (status = 200, description = "GA4GH service-info", body = crate::types::ServiceInfo),
+        ),
+        tag = "service-info"
+    )
+)]
+pub async fn sequences_service_info() -> Json<ServiceInfo> {
+    Json(describe("sequences", vec![Format::Fasta, Format::Fastq]))
+}
+
+fn describe(datatype: &str, formats: Vec<Format>) -> ServiceInfo {
+    ServiceInfo {
         id: "org.example.htsgetr".to_string(),
         name: "htsgetr".to_string(),
         r#type: ServiceType {
@@ -17,10 +91,10 @@ pub async fn service_info() -> Json<ServiceInfo> {
         },
         version: env!("CARGO_PKG_VERSION").to_string(),
         htsget: HtsgetCapabilities {
-            datatype: "reads".to_string(),
-            formats: vec![Format::Bam, Format::Cram, Format::Vcf, Format::Bcf],
+            datatype: datatype.to_string(),
+            formats,
             fields_parameter_effective: false,
             tags_parameters_effective: false,
         },
-    })
+    }
 }
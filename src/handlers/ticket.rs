@@ -0,0 +1,791 @@
+//! Request validation and ticket building shared by the ticket endpoints.
+
+use super::{AppState, RequestSubject, TicketPage, TicketRequestInfo, rfc3339};
+use crate::formats::{self, AnyIndexReader, IndexedRanges, RecordRange};
+use crate::storage::{ByteRange, data_endpoint_url};
+use crate::types::{DataClass, Format, HtsgetResponse, HtsgetResponseBody, Region, UrlEntry};
+use crate::{Error, Result};
+use axum::{
+    Json,
+    http::{HeaderValue, header},
+    response::{IntoResponse, Response},
+};
+use moka::future::Cache;
+use std::borrow::Cow;
+use std::future::Future;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "jws")]
+use crate::jws::{JOSE_MEDIA_TYPE, SIGNATURE_HEADER, TicketSigner};
+#[cfg(feature = "jws")]
+use axum::http::HeaderName;
+
+/// The 28-byte empty BGZF block that terminates BGZF files, as a `data:` URI
+const BGZF_EOF_URL: &str = "data:;base64,H4sIBAAAAAAA/wYAQkMCABsAAwAAAAAAAAAAAA==";
+
+/// Class and regions of a ticket request, validated per the htsget spec.
+#[derive(Debug)]
+pub(crate) struct TicketRequest {
+    pub class: DataClass,
+    /// Empty for the whole file
+    pub regions: Vec<Region>,
+}
+
+impl TicketRequest {
+    /// From GET query parameters. `start` and `end` require `referenceName`.
+    pub fn from_query(
+        class: Option<&str>,
+        reference_name: Option<&str>,
+        start: Option<u64>,
+        end: Option<u64>,
+    ) -> Result<Self> {
+        let regions = match reference_name {
+            Some(reference_name) => vec![Region::new(reference_name, start, end)?],
+            None if start.is_some() || end.is_some() => {
+                return Err(Error::InvalidInput(
+                    "start and end require referenceName".to_string(),
+                ));
+            }
+            None => Vec::new(),
+        };
+
+        Self::new(class, regions)
+    }
+
+    /// From a POST body. An empty `regions` array requests the whole file,
+    /// as an absent one does.
+    pub fn from_body(class: Option<&str>, regions: Option<Vec<Region>>) -> Result<Self> {
+        Self::new(class, regions.unwrap_or_default())
+    }
+
+    fn new(class: Option<&str>, regions: Vec<Region>) -> Result<Self> {
+        let class = class.map(str::parse).transpose()?.unwrap_or_default();
+
+        if class == DataClass::Header && !regions.is_empty() {
+            return Err(Error::InvalidInput(
+                "class=header cannot be combined with regions".to_string(),
+            ));
+        }
+        for region in &regions {
+            region.validate()?;
+        }
+
+        Ok(Self { class, regions })
+    }
+
+    /// Key distinguishing cached tickets of the same file
+    pub fn cache_key(&self) -> String {
+        format!("{:?}", (self.class, &self.regions))
+    }
+
+    /// This request as [`RequestObserver`](super::RequestObserver)s see it
+    pub fn observed<'a>(
+        &'a self,
+        id: &'a str,
+        format: Format,
+        subject: &'a RequestSubject,
+    ) -> TicketRequestInfo<'a> {
+        TicketRequestInfo {
+            id,
+            format,
+            class: self.class,
+            regions: &self.regions,
+            subject: subject.0.as_deref(),
+        }
+    }
+}
+
+/// Index query results of recent tickets, so repeated requests for the same
+/// regions (a genome browser panning back and forth) skip the index.
+///
+/// Entries are keyed by the file's version as well as the query, so a
+/// changed file is queried afresh; stale entries age out with the TTL.
+#[derive(Clone)]
+pub struct TicketCache {
+    ranges: Cache<TicketKey, Arc<IndexedRanges>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct TicketKey {
+    id: String,
+    format: Format,
+    /// Regions with defaults filled in, so equivalent queries share entries
+    regions: Vec<(String, u64, Option<u64>)>,
+    version: String,
+}
+
+impl TicketCache {
+    /// Keep up to `capacity` query results for `ttl` each.
+    pub fn new(ttl: Duration, capacity: u64) -> Self {
+        Self {
+            ranges: Cache::builder()
+                .max_capacity(capacity)
+                .time_to_live(ttl)
+                .build(),
+        }
+    }
+}
+
+impl AppState {
+    /// Byte ranges covering `regions` of a file: from the ticket cache if
+    /// it holds them for the file's current version, otherwise from `query`.
+    pub(crate) async fn indexed_ranges<F>(
+        &self,
+        id: &str,
+        format: Format,
+        regions: &[Region],
+        query: F,
+    ) -> Result<IndexedRanges>
+    where
+        F: Future<Output = Result<IndexedRanges>>,
+    {
+        let Some(cache) = &self.ticket_cache else {
+            return query.await;
+        };
+
+        let key = TicketKey {
+            id: id.to_string(),
+            format,
+            regions: regions
+                .iter()
+                .map(|r| (r.reference_name.clone(), r.start.unwrap_or(0), r.end))
+                .collect(),
+            version: self.storage.file_info(id, format).await?.version(),
+        };
+        if let Some(ranges) = cache.ranges.get(&key).await {
+            return Ok(ranges.as_ref().clone());
+        }
+
+        let ranges = query.await?;
+        cache.ranges.insert(key, Arc::new(ranges.clone())).await;
+        Ok(ranges)
+    }
+
+    /// `regions` named as the local file at `path` names its reference
+    /// sequences, when reference aliases are configured. Reads the header
+    /// (or FAI index) to find the names, so it's only done on cache misses.
+    pub(crate) async fn resolve_regions<'r>(
+        &self,
+        format: Format,
+        path: &Path,
+        index_path: &Path,
+        regions: &'r [Region],
+    ) -> Result<Cow<'r, [Region]>> {
+        let Some(aliases) = &self.reference_aliases else {
+            return Ok(Cow::Borrowed(regions));
+        };
+        if regions.is_empty() {
+            return Ok(Cow::Borrowed(regions));
+        }
+
+        let names: Vec<String> = formats::reference_sequences(format, path, Some(index_path))
+            .await?
+            .into_iter()
+            .map(|sequence| sequence.name)
+            .collect();
+        Ok(Cow::Owned(aliases.resolve_regions(regions, &names)))
+    }
+}
+
+/// A URL of a ticket, made into a [`UrlEntry`] only if it's on the
+/// requested page, so other pages' URLs aren't signed.
+enum TicketUrl {
+    /// Inline data, e.g. the BGZF EOF marker
+    Inline(UrlEntry),
+    /// A byte range of the file (all of it if `None`), from the storage
+    /// backend
+    Range(Option<ByteRange>, Option<DataClass>),
+    /// A data endpoint URL
+    Data(String, DataClass),
+}
+
+/// Builds tickets from the [`IndexReader`](formats::IndexReader) registered
+/// for a format: the whole file, the header alone, the header (for formats
+/// with one) followed by the blocks covering the requested regions, or a
+/// slice of records.
+pub(crate) struct TicketBuilder<'a> {
+    state: &'a AppState,
+    id: &'a str,
+    format: Format,
+    reader: Arc<dyn AnyIndexReader>,
+    /// Whether region slices hold only the records overlapping them
+    precise: bool,
+    /// Records to send instead of regions
+    records: Option<RecordRange>,
+    /// The page to build, and the URL of the endpoint serving the pages
+    page: Option<(&'a TicketPage, String)>,
+}
+
+impl<'a> TicketBuilder<'a> {
+    pub fn new(state: &'a AppState, id: &'a str, format: Format) -> Result<Self> {
+        Ok(Self {
+            state,
+            id,
+            format,
+            reader: state.index_readers.get(format)?,
+            precise: false,
+            records: None,
+            page: None,
+        })
+    }
+
+    /// Build only `page` of the ticket, per the configured page size,
+    /// linking the next page of the ticket endpoint at `endpoint_url`
+    pub fn page(mut self, page: &'a TicketPage, endpoint_url: String) -> Self {
+        self.page = Some((page, endpoint_url));
+        self
+    }
+
+    /// Send a slice of records rather than regions, for formats whose
+    /// reader supports it (FASTQ)
+    pub fn records(mut self, records: Option<RecordRange>) -> Self {
+        self.records = records;
+        self
+    }
+
+    /// Point region slices at the data endpoint, which decodes the blocks
+    /// covering each region and sends only the records overlapping it.
+    /// BAM only.
+    pub fn precise(mut self, precise: bool) -> Result<Self> {
+        if precise && self.format != Format::Bam {
+            return Err(Error::UnsupportedFormat(format!(
+                "precise slicing is only supported for BAM, not {:?}",
+                self.format
+            )));
+        }
+        self.precise = precise;
+        Ok(self)
+    }
+
+    /// Ticket for `class` and `regions` of the file (empty for all of it)
+    pub async fn build(
+        &self,
+        class: DataClass,
+        regions: &[Region],
+    ) -> Result<Json<HtsgetResponse>> {
+        let urls = match class {
+            DataClass::Header if !self.reader.has_header() => {
+                return Err(Error::InvalidInput(format!(
+                    "{:?} has no header",
+                    self.format
+                )));
+            }
+            DataClass::Header => {
+                self.state.locate(self.id, self.format, false).await?;
+                // Resolved after the lookup, which may convert a plain VCF
+                let path = self.state.storage.file_path(self.id, self.format);
+                self.reader.check_header(&path)?;
+                self.header_urls().await?
+            }
+            DataClass::Body => match &self.records {
+                Some(records) => self.record_urls(records).await?,
+                None => self.body_urls(regions).await?,
+            },
+        };
+
+        let (urls, next) = match &self.page {
+            Some((page, endpoint_url)) => {
+                let (urls, next) = page.select(self.state.ticket_limits.page_size, urls)?;
+                (urls, next.map(|next| page.page_url(endpoint_url, next)))
+            }
+            None => (urls, None),
+        };
+        let mut ticket = self.state.ticket(self.format, self.entries(urls).await?);
+        ticket.htsget.next = next;
+        if class == DataClass::Body && regions.is_empty() && self.records.is_none() {
+            // A single URL for the whole file, which the MD5 can verify
+            ticket.htsget.md5 = self.state.whole_file_md5(self.id, self.format).await;
+        }
+        Ok(ticket)
+    }
+
+    /// The entries of a page's `urls`, signed
+    async fn entries(&self, urls: Vec<TicketUrl>) -> Result<Vec<UrlEntry>> {
+        let (state, id, format) = (self.state, self.id, self.format);
+        let ticket_urls = urls
+            .iter()
+            .filter(|url| matches!(url, TicketUrl::Range(..)))
+            .count();
+        let mut entries = Vec::with_capacity(urls.len());
+        for url in urls {
+            entries.push(match url {
+                TicketUrl::Inline(entry) => entry,
+                TicketUrl::Range(range, class) => {
+                    state
+                        .ticket_entry(id, format, range, ticket_urls, class)
+                        .await?
+                }
+                TicketUrl::Data(url, class) => UrlEntry {
+                    url: state.sign_data_url(url),
+                    headers: None,
+                    class: Some(class),
+                },
+            });
+        }
+        Ok(entries)
+    }
+
+    /// The header block (or its standalone copy) and the EOF marker
+    async fn header_urls(&self) -> Result<Vec<TicketUrl>> {
+        let (state, id, format) = (self.state, self.id, self.format);
+        let header = match state.standalone_header_url(id, format) {
+            Some(url) => TicketUrl::Data(url, DataClass::Header),
+            None => {
+                let path = state.storage.file_path(id, format);
+                let header_range = self.reader.header_range(&path).await?;
+                TicketUrl::Range(Some(header_range), Some(DataClass::Header))
+            }
+        };
+        Ok([header]
+            .into_iter()
+            .chain(bgzf_eof(format).map(TicketUrl::Inline))
+            .collect())
+    }
+
+    /// The whole file when there are no regions or no index for them,
+    /// otherwise the header then the blocks covering `regions`
+    async fn body_urls(&self, regions: &[Region]) -> Result<Vec<TicketUrl>> {
+        let (state, id, format) = (self.state, self.id, self.format);
+        let needs_index = !regions.is_empty();
+
+        // The header doesn't depend on the index, so read it alongside,
+        // unless the ticket cache may make it unnecessary
+        let read_header = needs_index && state.ticket_cache.is_none();
+        let header_path = state.storage.file_path(id, format);
+        let (index_path, header) = tokio::join!(state.locate(id, format, needs_index), async {
+            if read_header {
+                self.reader.read_header(&header_path).await
+            } else {
+                Ok(None)
+            }
+        });
+        let index_path = index_path?;
+        // Resolved after the lookup, which may convert a plain VCF
+        let file_path = state.storage.file_path(id, format);
+        let Some(index_path) = index_path.filter(|_| self.reader.can_query(&file_path)) else {
+            // No regions, or no index for them - return the whole file
+            return Ok(vec![TicketUrl::Range(None, None)]);
+        };
+
+        let query = async {
+            let header = match header? {
+                Some(header) => Some(header),
+                None => self.reader.read_header(&file_path).await?,
+            };
+            let regions = state
+                .resolve_regions(format, &file_path, &index_path, regions)
+                .await?;
+            self.reader
+                .query_ranges(&file_path, &index_path, &regions, header.as_ref())
+                .await
+        };
+        let indexed = state.indexed_ranges(id, format, regions, query).await?;
+        if self.precise {
+            // The query still checks the regions' reference sequences, but
+            // records in the header's last block have no range of their own
+            return Ok(self.precise_urls(regions));
+        }
+
+        let mut urls = Vec::new();
+        if self.reader.has_header() {
+            urls.push(TicketUrl::Range(
+                Some(indexed.header_range),
+                Some(DataClass::Header),
+            ));
+        }
+        if indexed.data_ranges.is_empty() {
+            match bgzf_eof(format) {
+                // Every overlapping record shares the header's last block
+                // (or there are none), so the header is all there is
+                Some(eof) => urls.push(TicketUrl::Inline(eof)),
+                // Headerless formats have no records in the regions
+                None if !self.reader.has_header() => {}
+                // Index query returned no specific ranges - return whole file body
+                None => urls.push(TicketUrl::Range(None, Some(DataClass::Body))),
+            }
+        } else {
+            for range in indexed.data_ranges {
+                urls.push(TicketUrl::Range(Some(range), Some(DataClass::Body)));
+            }
+            // Slices stop at their last record, short of the EOF marker
+            urls.extend(bgzf_eof(format).map(|eof| {
+                TicketUrl::Inline(UrlEntry {
+                    class: Some(DataClass::Body),
+                    ..eof
+                })
+            }));
+        }
+        Ok(urls)
+    }
+
+    /// The byte range of `records`, found by the reader
+    async fn record_urls(&self, records: &RecordRange) -> Result<Vec<TicketUrl>> {
+        let (state, id, format) = (self.state, self.id, self.format);
+        state.locate(id, format, false).await?;
+        let path = state.storage.file_path(id, format);
+        let range = self.reader.record_range(&path, records).await?;
+        Ok(vec![TicketUrl::Range(Some(range), Some(DataClass::Body))])
+    }
+
+    /// The standalone header, a data endpoint URL per non-empty region, and
+    /// the EOF marker. The header's byte range can't be used, as records
+    /// sharing its last block would come with it.
+    fn precise_urls(&self, regions: &[Region]) -> Vec<TicketUrl> {
+        let (state, id, format) = (self.state, self.id, self.format);
+        let base = data_endpoint_url(&state.base_url, id, format, None);
+
+        let mut urls = vec![TicketUrl::Data(
+            format!("{}&class=header", base),
+            DataClass::Header,
+        )];
+        for region in regions.iter().filter(|region| !region.is_empty()) {
+            let mut url = format!(
+                "{}&precise=true&referenceName={}",
+                base,
+                url::form_urlencoded::byte_serialize(region.reference_name.as_bytes())
+                    .collect::<String>()
+            );
+            if let Some(start) = region.start {
+                url.push_str(&format!("&start={}", start));
+            }
+            if let Some(end) = region.end {
+                url.push_str(&format!("&end={}", end));
+            }
+            urls.push(TicketUrl::Data(url, DataClass::Body));
+        }
+        urls.extend(bgzf_eof(format).map(|eof| {
+            TicketUrl::Inline(UrlEntry {
+                class: Some(DataClass::Body),
+                ..eof
+            })
+        }));
+        urls
+    }
+}
+
+impl AppState {
+    /// MD5 for a whole-file ticket: stored by the backend, or computed
+    /// earlier by the checksums endpoint. Never computed here, as that
+    /// would read the whole file before the ticket is sent.
+    pub(crate) async fn whole_file_md5(&self, id: &str, format: Format) -> Option<String> {
+        let stored = self.storage.stored_checksums(id, format).await.ok()?;
+        if stored.md5.is_some() {
+            return stored.md5;
+        }
+
+        #[cfg(feature = "checksums")]
+        {
+            let version = self.storage.file_info(id, format).await.ok()?.version();
+            self.checksums.get(id, format, &version)?.md5
+        }
+        #[cfg(not(feature = "checksums"))]
+        None
+    }
+
+    /// A ticket of `urls`, expiring with the first of them
+    pub(crate) fn ticket(&self, format: Format, urls: Vec<UrlEntry>) -> Json<HtsgetResponse> {
+        let ttl = self.ticket_ttl(&urls);
+        Json(HtsgetResponse {
+            htsget: HtsgetResponseBody {
+                format,
+                expires_at: ttl.map(|ttl| rfc3339(SystemTime::now() + ttl)),
+                expires_in: ttl.map(|ttl| ttl.as_secs()),
+                urls,
+                md5: None,
+                next: None,
+            },
+        })
+    }
+}
+
+/// `Content-Type` of tickets, per the htsget spec
+const TICKET_CONTENT_TYPE: &str = "application/vnd.ga4gh.htsget.v1.3.0+json; charset=utf-8";
+
+/// How signed tickets carry their signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TicketSignature {
+    /// The usual JSON body, with a JWS over it (payload detached) in the
+    /// `X-Htsget-Signature` header
+    #[default]
+    Detached,
+    /// The body is a compact JWS (`application/jose`) with the ticket JSON
+    /// as its payload
+    Compact,
+}
+
+impl FromStr for TicketSignature {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "detached" => Ok(TicketSignature::Detached),
+            "compact" => Ok(TicketSignature::Compact),
+            _ => Err(format!(
+                "unknown ticket signature: {} (expected 'detached' or 'compact')",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for TicketSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TicketSignature::Detached => write!(f, "detached"),
+            TicketSignature::Compact => write!(f, "compact"),
+        }
+    }
+}
+
+/// A ticket as a response: the htsget media type, or plain
+/// `application/json` for legacy clients that reject it, signed if the
+/// server has a ticket signing key.
+pub(crate) struct TicketResponse {
+    pub ticket: Json<HtsgetResponse>,
+    pub plain_json: bool,
+    #[cfg(feature = "jws")]
+    pub signer: Option<Arc<TicketSigner>>,
+}
+
+impl IntoResponse for TicketResponse {
+    fn into_response(self) -> Response {
+        #[cfg(feature = "jws")]
+        if let Some(signer) = &self.signer {
+            let content_type = if self.plain_json {
+                "application/json"
+            } else {
+                TICKET_CONTENT_TYPE
+            };
+            return signed_ticket(signer, &self.ticket, content_type);
+        }
+
+        let mut response = self.ticket.into_response();
+        if !self.plain_json && response.status().is_success() {
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(TICKET_CONTENT_TYPE),
+            );
+        }
+        response
+    }
+}
+
+impl AppState {
+    /// `ticket` with the content type this server sends tickets as
+    pub(crate) fn ticket_response(&self, ticket: Json<HtsgetResponse>) -> TicketResponse {
+        TicketResponse {
+            ticket,
+            plain_json: self.plain_json_tickets,
+            #[cfg(feature = "jws")]
+            signer: self.ticket_signer.clone(),
+        }
+    }
+}
+
+/// `ticket` signed over its exact JSON bytes, as `signer` is configured to
+/// send it
+#[cfg(feature = "jws")]
+fn signed_ticket(signer: &TicketSigner, ticket: &HtsgetResponse, content_type: &str) -> Response {
+    let body = match serde_json::to_vec(ticket) {
+        Ok(body) => body,
+        Err(e) => {
+            return Error::Internal(format!("failed to serialize ticket: {}", e)).into_response();
+        }
+    };
+
+    match signer.signature() {
+        TicketSignature::Detached => {
+            let signature = signer.sign_detached(&body, content_type);
+            (
+                [
+                    (header::CONTENT_TYPE, content_type.to_string()),
+                    (HeaderName::from_static(SIGNATURE_HEADER), signature),
+                ],
+                body,
+            )
+                .into_response()
+        }
+        TicketSignature::Compact => (
+            [(header::CONTENT_TYPE, JOSE_MEDIA_TYPE)],
+            signer.sign(&body, content_type),
+        )
+            .into_response(),
+    }
+}
+
+/// The BGZF end-of-file marker for `format`, which a header-only ticket must
+/// end with so that its concatenated blocks form a valid file.
+pub(crate) fn bgzf_eof(format: Format) -> Option<UrlEntry> {
+    matches!(format, Format::Bam | Format::Vcf | Format::Bcf).then(|| UrlEntry {
+        url: BGZF_EOF_URL.to_string(),
+        headers: None,
+        class: Some(DataClass::Header),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::HTSGET_MEDIA_TYPE;
+    use base64::Engine;
+
+    #[test]
+    fn test_from_query() {
+        let request = TicketRequest::from_query(None, None, None, None).unwrap();
+        assert_eq!(request.class, DataClass::Body);
+        assert!(request.regions.is_empty());
+
+        // Spec example: GET /reads/NA12878?format=BAM&referenceName=chr1&start=10000&end=20000
+        let request =
+            TicketRequest::from_query(None, Some("chr1"), Some(10000), Some(20000)).unwrap();
+        assert_eq!(request.regions.len(), 1);
+        assert_eq!(request.regions[0].start, Some(10000));
+
+        // Spec example: GET /reads/NA12878?class=header
+        let request = TicketRequest::from_query(Some("header"), None, None, None).unwrap();
+        assert_eq!(request.class, DataClass::Header);
+
+        for (class, reference_name, start) in [
+            (Some("header"), Some("chr1"), None),
+            (Some("Header"), None, None),
+            (Some("all"), None, None),
+            (None, None, Some(100)),
+            (None, Some(""), None),
+        ] {
+            assert!(
+                matches!(
+                    TicketRequest::from_query(class, reference_name, start, None),
+                    Err(Error::InvalidInput(_))
+                ),
+                "{:?} {:?} {:?}",
+                class,
+                reference_name,
+                start
+            );
+        }
+
+        assert!(matches!(
+            TicketRequest::from_query(None, Some("chr1"), Some(200), Some(100)),
+            Err(Error::InvalidRange(_))
+        ));
+        assert!(TicketRequest::from_query(None, Some("chr1"), Some(u64::MAX), None).is_ok());
+    }
+
+    #[test]
+    fn test_from_query_bounds() {
+        // Every combination of start and end, with start defaulting to 0 and
+        // end to the end of the reference sequence
+        for (start, end, bounds) in [
+            (None, None, (0, None)),
+            (Some(1000), None, (1000, None)),
+            (None, Some(5000), (0, Some(5000))),
+            (Some(1000), Some(5000), (1000, Some(5000))),
+            (None, Some(0), (0, Some(0))),
+            (Some(5000), Some(5000), (5000, Some(5000))),
+        ] {
+            let request = TicketRequest::from_query(None, Some("chr1"), start, end).unwrap();
+            assert_eq!(request.regions.len(), 1);
+            assert_eq!(request.regions[0].bounds(), bounds, "{:?}-{:?}", start, end);
+        }
+
+        for (start, end) in [(Some(5001), Some(5000)), (Some(1), Some(0))] {
+            assert!(matches!(
+                TicketRequest::from_query(None, Some("chr1"), start, end),
+                Err(Error::InvalidRange(_))
+            ));
+        }
+        for (start, end) in [(None, Some(5000)), (Some(1000), None)] {
+            assert!(matches!(
+                TicketRequest::from_query(None, None, start, end),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_from_body() {
+        let region = || Region {
+            reference_name: "chr1".to_string(),
+            start: None,
+            end: None,
+        };
+
+        let request = TicketRequest::from_body(Some("header"), Some(vec![])).unwrap();
+        assert_eq!(request.class, DataClass::Header);
+        assert!(request.regions.is_empty());
+
+        let request = TicketRequest::from_body(Some("body"), Some(vec![region()])).unwrap();
+        assert_eq!(request.regions.len(), 1);
+
+        assert!(matches!(
+            TicketRequest::from_body(Some("header"), Some(vec![region()])),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_ticket_response() {
+        let ticket = || {
+            Json(HtsgetResponse {
+                htsget: HtsgetResponseBody {
+                    format: Format::Bam,
+                    urls: vec![],
+                    md5: None,
+                    expires_at: None,
+                    expires_in: None,
+                    next: None,
+                },
+            })
+        };
+
+        let response = TicketResponse {
+            ticket: ticket(),
+            plain_json: false,
+            #[cfg(feature = "jws")]
+            signer: None,
+        }
+        .into_response();
+        let content_type = response.headers()[header::CONTENT_TYPE].to_str().unwrap();
+        assert_eq!(content_type, TICKET_CONTENT_TYPE);
+        assert!(content_type.starts_with(HTSGET_MEDIA_TYPE));
+
+        let response = TicketResponse {
+            ticket: ticket(),
+            plain_json: true,
+            #[cfg(feature = "jws")]
+            signer: None,
+        }
+        .into_response();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    }
+
+    #[test]
+    fn test_ticket_signature_from_str() {
+        assert_eq!(
+            "Detached".parse::<TicketSignature>().unwrap(),
+            TicketSignature::Detached
+        );
+        assert_eq!(
+            "compact".parse::<TicketSignature>().unwrap(),
+            TicketSignature::Compact
+        );
+        assert!("jwt".parse::<TicketSignature>().is_err());
+    }
+
+    #[test]
+    fn test_bgzf_eof() {
+        assert!(bgzf_eof(Format::Bam).is_some());
+        assert!(bgzf_eof(Format::Cram).is_none());
+
+        let url = bgzf_eof(Format::Vcf).unwrap().url;
+        let encoded = url.strip_prefix("data:;base64,").unwrap();
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .unwrap();
+        assert_eq!(bytes.len(), 28);
+        assert_eq!(&bytes[..4], &[0x1f, 0x8b, 0x08, 0x04]);
+    }
+}
@@ -0,0 +1,77 @@
+//! Demo UI (requires `ui` feature).
+//!
+//! A single page served from `/ui/` that requests a ticket for an ID and
+//! region, shows it, and downloads the slice by fetching the ticket URLs.
+//! The assets in `ui/` are embedded at build time, so the binary serves
+//! them without any files on disk. The page calls the API relative to its
+//! own location and therefore works under a path prefix.
+
+use crate::{Error, Result};
+use axum::{
+    body::Body,
+    extract::Path,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use include_dir::{Dir, include_dir};
+
+static ASSETS: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/ui");
+
+/// `GET /ui` - redirect to `/ui/`, so the page's relative asset links resolve
+pub async fn get_ui_root() -> Response {
+    let mut response = StatusCode::MOVED_PERMANENTLY.into_response();
+    // Relative to `/ui`, so a path prefix is kept
+    response
+        .headers_mut()
+        .insert(header::LOCATION, HeaderValue::from_static("ui/"));
+    response
+}
+
+/// `GET /ui/` - the page itself
+pub async fn get_ui_index() -> Result<Response> {
+    asset("index.html")
+}
+
+/// `GET /ui/*path` - page scripts and styles
+pub async fn get_ui_asset(Path(path): Path<String>) -> Result<Response> {
+    asset(&path)
+}
+
+fn asset(path: &str) -> Result<Response> {
+    let file = ASSETS
+        .get_file(path)
+        .ok_or_else(|| Error::NotFound(format!("ui/{}", path)))?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type(path))
+        // Assets change with the binary, not with a cache lifetime
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::from(file.contents()))
+        .unwrap())
+}
+
+fn content_type(path: &str) -> &'static str {
+    match path.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assets_embedded() {
+        for path in ["index.html", "app.js", "style.css"] {
+            assert!(ASSETS.get_file(path).is_some(), "missing ui/{}", path);
+        }
+        assert_eq!(content_type("app.js"), "text/javascript; charset=utf-8");
+        assert_eq!(content_type("README"), "application/octet-stream");
+    }
+}
@@ -1,23 +1,44 @@
-use super::AppState;
+use super::ticket::{TicketBuilder, TicketRequest};
+use super::{AppState, RequestBaseUrl, RequestSubject, TicketPage};
 use crate::{
     Error, Result,
-    formats::VcfIndexReader,
-    types::{
-        DataClass, Format, HtsgetResponse, HtsgetResponseBody, Region, UrlEntry, VariantsPostBody,
-        VariantsQuery,
-    },
+    audit::Access,
+    types::{Format, HtsgetResponse, VariantsPostBody, VariantsQuery},
 };
 use axum::{
     Json,
     extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
 };
 
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        get,
+        path = "/variants/{id}",
+        params(("id" = String, Path, description = "Dataset ID"), crate::types::VariantsQuery, ("page" = Option<usize>, Query, description = "1-based ticket page, when tickets are paged (extension)")),
+        responses(
+            (status = 200, description = "htsget ticket", body = crate::types::HtsgetResponse),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "htsget"
+    )
+)]
 pub async fn get_variants(
     State(state): State<AppState>,
+    base_url: RequestBaseUrl,
+    subject: RequestSubject,
+    headers: HeaderMap,
+    page: TicketPage,
     Path(id): Path<String>,
     Query(query): Query<VariantsQuery>,
-) -> Result<Json<HtsgetResponse>> {
-    let format = query.format.unwrap_or(Format::Vcf);
+) -> Result<Response> {
+    let state = subject.bind(state);
+    let format = state
+        .request_format(&id, query.format, state.default_formats.variants)
+        .await?;
 
     if !format.is_variants() {
         return Err(Error::UnsupportedFormat(format!(
@@ -27,28 +48,58 @@ pub async fn get_variants(
     }
 
     if !state.storage.exists(&id, format).await? {
-        return Err(Error::NotFound(id));
+        return Err(state.not_found(&id, format).await);
     }
 
-    let class = query.class.unwrap_or_default();
-    let regions = match (&query.reference_name, query.start, query.end) {
-        (Some(ref_name), start, end) => vec![Region {
-            reference_name: ref_name.clone(),
-            start,
-            end,
-        }],
-        _ => vec![],
-    };
-
-    build_variants_response(&state, &id, format, class, &regions).await
+    let request = TicketRequest::from_query(
+        query.class.as_deref(),
+        query.reference_name.as_deref(),
+        query.start,
+        query.end,
+    )?;
+
+    let observed = request.observed(&id, format, &subject);
+    state.observe_ticket_request(&observed).await?;
+    let endpoint_url = base_url.endpoint_url(&state, "variants", &id);
+    let ticket =
+        build_variants_response(&state, &id, format, &request, &page, endpoint_url).await?;
+    let ticket = state
+        .observe_ticket_response(&observed, base_url.rebase(&state, ticket))
+        .await?;
+
+    let response = state
+        .cached_ticket(&headers, &id, format, &request.cache_key(), ticket)
+        .await?;
+    Ok(Access::new(format, request.regions).attach(response))
 }
 
+#[cfg_attr(
+    feature = "openapi",
+    utoipa::path(
+        post,
+        path = "/variants/{id}",
+        params(("id" = String, Path, description = "Dataset ID"), ("page" = Option<usize>, Query, description = "1-based ticket page, when tickets are paged (extension)")),
+        request_body = crate::types::VariantsPostBody,
+        responses(
+            (status = 200, description = "htsget ticket", body = crate::types::HtsgetResponse),
+            (status = 400, description = "Invalid request", body = crate::error::HtsgetError),
+            (status = 404, description = "Dataset not found", body = crate::error::HtsgetError),
+        ),
+        tag = "htsget"
+    )
+)]
 pub async fn post_variants(
     State(state): State<AppState>,
+    base_url: RequestBaseUrl,
+    subject: RequestSubject,
+    page: TicketPage,
     Path(id): Path<String>,
     Json(body): Json<VariantsPostBody>,
-) -> Result<Json<HtsgetResponse>> {
-    let format = body.format.unwrap_or(Format::Vcf);
+) -> Result<Response> {
+    let state = subject.bind(state);
+    let format = state
+        .request_format(&id, body.format, state.default_formats.variants)
+        .await?;
 
     if !format.is_variants() {
         return Err(Error::UnsupportedFormat(format!(
@@ -57,102 +108,31 @@ pub async fn post_variants(
         )));
     }
 
-    if !state.storage.exists(&id, format).await? {
-        return Err(Error::NotFound(id));
-    }
-
-    let class = body.class.unwrap_or_default();
-    let regions = body.regions.unwrap_or_default();
-
-    build_variants_response(&state, &id, format, class, &regions).await
+    let request = TicketRequest::from_body(body.class.as_deref(), body.regions)?;
+    state.ticket_limits.check_regions(&request.regions)?;
+
+    let observed = request.observed(&id, format, &subject);
+    state.observe_ticket_request(&observed).await?;
+    let endpoint_url = base_url.endpoint_url(&state, "variants", &id);
+    let ticket =
+        build_variants_response(&state, &id, format, &request, &page, endpoint_url).await?;
+    let ticket = state
+        .observe_ticket_response(&observed, base_url.rebase(&state, ticket))
+        .await?;
+    Ok(Access::new(format, request.regions).attach(state.ticket_response(ticket)))
 }
 
+/// Ticket for a variants request, from the format's index reader
 async fn build_variants_response(
     state: &AppState,
     id: &str,
     format: Format,
-    class: DataClass,
-    regions: &[Region],
+    request: &TicketRequest,
+    page: &TicketPage,
+    endpoint_url: String,
 ) -> Result<Json<HtsgetResponse>> {
-    let mut urls = Vec::new();
-    let vcf_path = state.storage.file_path(id, format);
-
-    match class {
-        DataClass::Header => {
-            // Return only the header block
-            let header_range = VcfIndexReader::header_range(&vcf_path).await?;
-            urls.push(UrlEntry {
-                url: state.sign_data_url(state.storage.data_url(id, format, Some(header_range))),
-                headers: None,
-                class: Some(DataClass::Header),
-            });
-        }
-        DataClass::Body => {
-            if regions.is_empty() {
-                // No regions - return entire file
-                urls.push(UrlEntry {
-                    url: state.sign_data_url(state.storage.data_url(id, format, None)),
-                    headers: None,
-                    class: None,
-                });
-            } else {
-                // Check if index is available
-                let index_path = state.storage.index_path(id, format).await?;
-
-                if let Some(idx_path) = index_path {
-                    // Query tabix index for byte ranges
-                    let indexed =
-                        VcfIndexReader::query_ranges(&vcf_path, &idx_path, regions).await?;
-
-                    // Add header block first
-                    urls.push(UrlEntry {
-                        url: state.sign_data_url(state.storage.data_url(
-                            id,
-                            format,
-                            Some(indexed.header_range),
-                        )),
-                        headers: None,
-                        class: Some(DataClass::Header),
-                    });
-
-                    // Add data blocks
-                    if indexed.data_ranges.is_empty() {
-                        // Index query returned no specific ranges - return whole file body
-                        urls.push(UrlEntry {
-                            url: state.sign_data_url(state.storage.data_url(id, format, None)),
-                            headers: None,
-                            class: Some(DataClass::Body),
-                        });
-                    } else {
-                        for range in indexed.data_ranges {
-                            urls.push(UrlEntry {
-                                url: state.sign_data_url(state.storage.data_url(
-                                    id,
-                                    format,
-                                    Some(range),
-                                )),
-                                headers: None,
-                                class: Some(DataClass::Body),
-                            });
-                        }
-                    }
-                } else {
-                    // No index available - return whole file
-                    urls.push(UrlEntry {
-                        url: state.sign_data_url(state.storage.data_url(id, format, None)),
-                        headers: None,
-                        class: None,
-                    });
-                }
-            }
-        }
-    }
-
-    Ok(Json(HtsgetResponse {
-        htsget: HtsgetResponseBody {
-            format,
-            urls,
-            md5: None,
-        },
-    }))
+    TicketBuilder::new(state, id, format)?
+        .page(page, endpoint_url)
+        .build(request.class, &request.regions)
+        .await
 }
@@ -0,0 +1,366 @@
+//! Index generation for data files.
+//!
+//! Builds missing index files with noodles so a data directory can be prepared
+//! without samtools/bcftools installed. Used by the `htsgetr index` subcommand.
+//!
+//! | Data file | Index |
+//! |-----------|-------|
+//! | `.bam` | `.bam.bai` |
+//! | `.vcf.gz` | `.vcf.gz.tbi` |
+//! | `.vcf` | bgzip with [`compress_vcf`] first |
+//! | `.bcf` | `.bcf.csi` |
+//! | `.fa` | `.fa.fai` |
+//! | `.fq` | `.fq.fqi` (record offsets, see [`FastqRecordIndex`]) |
+//!
+//! CRAM (`.crai`) indexes are not generated yet.
+
+use crate::formats::{FastqIndexReader, FastqRecordIndex, RECORD_INDEX_STRIDE};
+use crate::storage::naming::Naming;
+use crate::types::Format;
+use crate::{Error, Result};
+use noodles::bgzf;
+use noodles::csi::binning_index::{
+    Indexer,
+    index::reference_sequence::{bin::Chunk, index::BinnedIndex},
+};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Uncompressed bytes a BGZF block holds
+const BLOCK_DATA_SIZE: usize = 65280;
+
+/// Result of indexing a single data file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexOutcome {
+    /// A new index was written
+    Created(PathBuf),
+    /// An index already exists and `force` was not set
+    Exists(PathBuf),
+    /// The file's format cannot be indexed by htsgetr
+    Unsupported(String),
+}
+
+/// Detect the data format of a file from its name, using the default
+/// extensions (see [`Naming`]).
+pub fn detect_format(path: &Path) -> Option<Format> {
+    Naming::default().detect(path)
+}
+
+/// Generate the index for a single data file.
+pub async fn index_file(path: &Path, force: bool) -> Result<IndexOutcome> {
+    let Some(format) = detect_format(path) else {
+        return Ok(IndexOutcome::Unsupported(format!(
+            "unrecognized file type: {}",
+            path.display()
+        )));
+    };
+
+    let compressed = path.extension().is_some_and(|ext| ext == "gz");
+
+    let index_ext = match format {
+        Format::Bam => "bai",
+        Format::Vcf if compressed => "tbi",
+        Format::Vcf => {
+            return Ok(IndexOutcome::Unsupported(
+                "plain VCF must be bgzipped before indexing; see --convert-vcf".to_string(),
+            ));
+        }
+        Format::Fasta if !compressed => "fai",
+        Format::Fastq if !compressed => "fqi",
+        Format::Fasta | Format::Fastq => {
+            return Ok(IndexOutcome::Unsupported(
+                "compressed FASTA/FASTQ must be indexed with samtools faidx / bgzip -i".to_string(),
+            ));
+        }
+        Format::Cram => {
+            return Ok(IndexOutcome::Unsupported(
+                "CRAI generation is not supported; use samtools index".to_string(),
+            ));
+        }
+        Format::Bcf => "csi",
+    };
+
+    let dst = PathBuf::from(format!("{}.{}", path.display(), index_ext));
+    if dst.exists() && !force {
+        return Ok(IndexOutcome::Exists(dst));
+    }
+
+    if format == Format::Fastq {
+        let index = FastqRecordIndex::build(path, RECORD_INDEX_STRIDE).await?;
+        tokio::fs::write(FastqIndexReader::record_index_path(path), index.to_bytes()).await?;
+        return Ok(IndexOutcome::Created(dst));
+    }
+
+    // noodles indexers are synchronous
+    let src = path.to_path_buf();
+    let out = dst.clone();
+    tokio::task::spawn_blocking(move || build_index(&src, &out, format))
+        .await
+        .map_err(|e| Error::Internal(format!("indexing task failed: {}", e)))??;
+
+    Ok(IndexOutcome::Created(dst))
+}
+
+/// Bgzip a plain VCF into `dst` and write its tabix index (`dst.tbi`).
+///
+/// Blocks break between lines, with the header in blocks of its own, as
+/// htslib does for BAM records, so byte ranges from the index hold whole
+/// records. The file and its index are written under temporary names and
+/// renamed into place, the file first, so readers never see a new index
+/// next to an old or missing file.
+pub async fn compress_vcf(src: &Path, dst: &Path) -> Result<()> {
+    let (src, dst) = (src.to_path_buf(), dst.to_path_buf());
+    tokio::task::spawn_blocking(move || bgzip_vcf(&src, &dst))
+        .await
+        .map_err(|e| Error::Internal(format!("VCF conversion task failed: {}", e)))?
+}
+
+fn bgzip_vcf(src: &Path, dst: &Path) -> Result<()> {
+    static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+    let map_err = |e: std::io::Error| Error::Internal(format!("failed to index {:?}: {}", src, e));
+
+    if let Some(parent) = dst.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = PathBuf::from(format!(
+        "{}.{}-{}.tmp",
+        dst.display(),
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut reader = std::io::BufReader::new(std::fs::File::open(src)?);
+    let mut writer = bgzf::io::Writer::new(std::fs::File::create(&tmp)?);
+    let mut line = Vec::new();
+    let mut in_header = true;
+
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        let used = usize::from(writer.virtual_position().uncompressed());
+        let ends_header = in_header && !line.starts_with(b"#");
+        if used > 0 && (ends_header || used + line.len() > BLOCK_DATA_SIZE) {
+            writer.flush()?;
+        }
+        in_header &= !ends_header;
+
+        writer.write_all(&line)?;
+        line.clear();
+    }
+    writer.finish()?;
+
+    // The data file goes into place first, so no reader pairs a new index
+    // with an old or missing file; the index follows, also renamed in
+    let tbi = PathBuf::from(format!("{}.tbi", dst.display()));
+    let tbi_tmp = PathBuf::from(format!("{}.tbi", tmp.display()));
+    let result = noodles::vcf::fs::index(&tmp)
+        .and_then(|index| noodles::tabix::write(&tbi_tmp, &index))
+        .map_err(map_err)
+        .and_then(|()| std::fs::rename(&tmp, dst).map_err(Error::from))
+        .and_then(|()| std::fs::rename(&tbi_tmp, &tbi).map_err(Error::from));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+        let _ = std::fs::remove_file(&tbi_tmp);
+    }
+    result
+}
+
+/// Generate indexes for every recognized data file in a directory (non-recursive).
+pub async fn index_directory(
+    dir: &Path,
+    force: bool,
+) -> Result<Vec<(PathBuf, Result<IndexOutcome>)>> {
+    let mut paths = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.is_file() && detect_format(&path).is_some() {
+            paths.push(path);
+        }
+    }
+
+    paths.sort();
+
+    let mut results = Vec::with_capacity(paths.len());
+    for path in paths {
+        let outcome = index_file(&path, force).await;
+        results.push((path, outcome));
+    }
+
+    Ok(results)
+}
+
+fn build_index(src: &Path, dst: &Path, format: Format) -> Result<()> {
+    let map_err = |e: std::io::Error| Error::Internal(format!("failed to index {:?}: {}", src, e));
+
+    match format {
+        Format::Bam => {
+            let index = noodles::bam::fs::index(src).map_err(map_err)?;
+            noodles::bam::bai::write(dst, &index).map_err(map_err)?;
+        }
+        Format::Vcf => {
+            let index = noodles::vcf::fs::index(src).map_err(map_err)?;
+            noodles::tabix::write(dst, &index).map_err(map_err)?;
+        }
+        Format::Bcf => {
+            let index = index_bcf(src).map_err(map_err)?;
+            noodles::csi::write(dst, &index).map_err(map_err)?;
+        }
+        Format::Fasta => {
+            let index = noodles::fasta::fs::index(src).map_err(map_err)?;
+            let mut writer = std::io::BufWriter::new(std::fs::File::create(dst)?);
+
+            for record in index.as_ref() {
+                writeln!(
+                    writer,
+                    "{}\t{}\t{}\t{}\t{}",
+                    String::from_utf8_lossy(record.name()),
+                    record.length(),
+                    record.offset(),
+                    record.line_bases(),
+                    record.line_width()
+                )?;
+            }
+
+            writer.flush()?;
+        }
+        _ => {
+            return Err(Error::UnsupportedFormat(format!(
+                "cannot index {:?} files",
+                format
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Build a CSI index for a BCF file, with bcftools' default bins (a
+/// minimum shift of 14 and a depth of 5). Records must be sorted.
+fn index_bcf(src: &Path) -> std::io::Result<noodles::csi::Index> {
+    let mut reader = noodles::bcf::io::Reader::new(std::fs::File::open(src)?);
+    let header = reader.read_header()?;
+
+    let mut indexer = Indexer::<BinnedIndex>::default();
+    let mut record = noodles::bcf::Record::default();
+    let mut start = reader.get_ref().virtual_position();
+
+    while reader.read_record(&mut record)? != 0 {
+        let end = reader.get_ref().virtual_position();
+        let context = match record.variant_start().transpose()? {
+            Some(position) => Some((
+                record.reference_sequence_id()?,
+                position,
+                record.end()?.max(position),
+                true,
+            )),
+            None => None,
+        };
+        indexer.add_record(context, Chunk::new(start, end))?;
+        start = end;
+    }
+
+    Ok(indexer.build(header.contigs().len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(detect_format(Path::new("a.bam")), Some(Format::Bam));
+        assert_eq!(detect_format(Path::new("a.vcf.gz")), Some(Format::Vcf));
+        assert_eq!(detect_format(Path::new("a.vcf")), Some(Format::Vcf));
+        assert_eq!(detect_format(Path::new("a.fa")), Some(Format::Fasta));
+        assert_eq!(detect_format(Path::new("a.fq.gz")), Some(Format::Fastq));
+        assert_eq!(detect_format(Path::new("a.bam.bai")), None);
+        assert_eq!(detect_format(Path::new("a.txt")), None);
+    }
+
+    #[tokio::test]
+    async fn test_index_fasta() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ref.fa");
+        std::fs::write(&path, ">chr1\nACGT\nAC\n>chr2\nGG\n").unwrap();
+
+        let outcome = index_file(&path, false).await.unwrap();
+        let fai = dir.path().join("ref.fa.fai");
+        assert_eq!(outcome, IndexOutcome::Created(fai.clone()));
+
+        let contents = std::fs::read_to_string(&fai).unwrap();
+        assert_eq!(contents, "chr1\t6\t6\t4\t5\nchr2\t2\t20\t2\t3\n");
+
+        // Existing index is left alone without force
+        let outcome = index_file(&path, false).await.unwrap();
+        assert_eq!(outcome, IndexOutcome::Exists(fai));
+    }
+
+    #[tokio::test]
+    async fn test_index_bcf() {
+        use noodles::vcf::{self, variant::io::Write as _};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sample.bcf");
+
+        let header = vcf::Header::builder()
+            .add_contig("chr1", Default::default())
+            .add_contig("chr2", Default::default())
+            .build();
+        let mut writer = noodles::bcf::io::Writer::new(std::fs::File::create(&path).unwrap());
+        writer.write_header(&header).unwrap();
+        for (name, start) in [("chr1", 10), ("chr1", 20), ("chr2", 5)] {
+            let record = vcf::variant::RecordBuf::builder()
+                .set_reference_sequence_name(name)
+                .set_variant_start(noodles::core::Position::try_from(start).unwrap())
+                .set_reference_bases("A")
+                .build();
+            writer.write_variant_record(&header, &record).unwrap();
+        }
+        writer.try_finish().unwrap();
+        drop(writer);
+
+        let outcome = index_file(&path, false).await.unwrap();
+        let csi = dir.path().join("sample.bcf.csi");
+        assert_eq!(outcome, IndexOutcome::Created(csi.clone()));
+
+        let index = noodles::csi::read(&csi).unwrap();
+        let mut reader = noodles::bcf::io::Reader::new(std::fs::File::open(&path).unwrap());
+        let header = reader.read_header().unwrap();
+        let region = "chr2".parse().unwrap();
+        let records = reader.query(&header, &index, &region).unwrap().count();
+        assert_eq!(records, 1);
+    }
+
+    #[tokio::test]
+    async fn test_index_unsupported() {
+        let outcome = index_file(Path::new("sample.cram"), false).await.unwrap();
+        assert!(matches!(outcome, IndexOutcome::Unsupported(_)));
+    }
+
+    #[tokio::test]
+    async fn test_compress_vcf() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("small.vcf");
+        let dst = dir.path().join("cache/small.vcf.gz");
+        let text = "##fileformat=VCFv4.3\n##contig=<ID=chr1>\n\
+            #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+            chr1\t10\t.\tA\tG\t.\t.\t.\n";
+        std::fs::write(&src, text).unwrap();
+
+        compress_vcf(&src, &dst).await.unwrap();
+        assert!(dir.path().join("cache/small.vcf.gz.tbi").exists());
+
+        let mut reader = bgzf::io::Reader::new(std::fs::File::open(&dst).unwrap());
+        // The header fills the first block on its own
+        let header_len = text.find("chr1\t10").unwrap();
+        assert_eq!(reader.fill_buf().unwrap(), &text.as_bytes()[..header_len]);
+
+        let mut decompressed = String::new();
+        std::io::Read::read_to_string(&mut reader, &mut decompressed).unwrap();
+        assert_eq!(decompressed, text);
+
+        let outcome = index_file(&src, false).await.unwrap();
+        assert!(matches!(outcome, IndexOutcome::Unsupported(_)));
+    }
+}
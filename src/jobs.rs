@@ -0,0 +1,853 @@
+//! Export jobs (extension): slices assembled on the server.
+//!
+//! Some users want a materialized slice rather than a ticket's URL list.
+//! With a jobs directory configured, `POST /jobs` takes a ticket request,
+//! builds its ticket and assembles the data in the background, as
+//! `/download` would in one request, optionally converting BAM to SAM and
+//! BGZF-compressing the result:
+//!
+//! ```text
+//! curl -X POST http://localhost:8080/jobs \
+//!   -d '{"endpoint": "reads", "id": "NA12878", "regions": [{"referenceName": "chr20"}], "output": "SAM", "compress": true}'
+//! ```
+//!
+//! | Route | Description |
+//! |-------|-------------|
+//! | `POST /jobs` | Submit a job: `endpoint`, `id`, the endpoint's POST body fields, `output`, `compress` |
+//! | `GET /jobs/:id` | Job status: `queued`, `running`, `succeeded` or `failed` |
+//! | `GET /jobs/:id/result` | The result of a job that succeeded |
+//!
+//! Each job's status is kept as JSON next to its result in the jobs
+//! directory, so both survive restarts; jobs a restart interrupted are
+//! marked failed. With an S3 upload target (`s3` feature), results are
+//! uploaded instead: the status's `resultUrl` is a URL presigned when the
+//! status is fetched, and `/result` redirects to one. Finished jobs are
+//! removed with their results once the retention period passes.
+//! Jobs are only visible to the principal that submitted them.
+
+use crate::handlers::{
+    AppState, Precompressed, RequestBaseUrl, RequestSubject, SequencesPostBody, TicketEndpoint,
+    TicketPage, assemble, attachment, post_reads, post_sequences, post_variants, rfc3339,
+};
+#[cfg(feature = "s3")]
+use crate::storage::S3Upload;
+use crate::types::{Format, ReadsPostBody, VariantsPostBody};
+use crate::{Error, Result};
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::{Path, State},
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use bytes::Bytes;
+use futures::TryStreamExt;
+use futures::stream::BoxStream;
+use noodles::sam::alignment::io::Write as _;
+use noodles::{bam, bgzf, sam};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+use tokio_util::io::ReaderStream;
+
+/// Route jobs are submitted to
+pub const JOBS_PATH: &str = "/jobs";
+
+/// Jobs run at once by default; the rest wait their turn
+pub const DEFAULT_MAX_RUNNING_JOBS: usize = 2;
+
+/// How long finished jobs and their results are kept by default
+pub const DEFAULT_JOBS_RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Longest wait between sweeps for expired jobs
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Where a job is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// Format a job's slice is converted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum JobOutput {
+    /// SAM text, from BAM reads
+    Sam,
+}
+
+/// Body of `POST /jobs`
+#[derive(Debug, Deserialize)]
+pub struct JobRequest {
+    pub endpoint: TicketEndpoint,
+    /// Dataset ID
+    pub id: String,
+    /// Convert the slice, rather than keeping the stored format
+    pub output: Option<JobOutput>,
+    /// BGZF-compress the result, unless it already is (e.g. BAM)
+    #[serde(default)]
+    pub compress: bool,
+    /// The rest of the body, read as the endpoint's POST body (`format`,
+    /// `class`, `regions`, ...)
+    #[serde(flatten)]
+    pub ticket: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A job's status, as `GET /jobs/:id` reports it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub id: String,
+    pub status: JobStatus,
+    /// Dataset ID
+    pub dataset: String,
+    /// Format of the slice before any conversion
+    pub format: Format,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<JobOutput>,
+    /// File name the result is served as, e.g. `NA12878.sam.gz`
+    pub filename: String,
+    /// RFC 3339 timestamps
+    pub created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+    /// Size of the result
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Principal that submitted the job
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+}
+
+impl Job {
+    fn content_type(&self) -> &'static str {
+        match self.output {
+            Some(JobOutput::Sam) => "text/x-sam",
+            None => self.format.content_type(),
+        }
+    }
+}
+
+/// What a finished job produced
+struct Export {
+    bytes: u64,
+    compressed: bool,
+    url: String,
+}
+
+/// Export jobs, persisted in a directory.
+pub struct Jobs {
+    dir: PathBuf,
+    jobs: Mutex<HashMap<String, Job>>,
+    running: Semaphore,
+    /// How long finished jobs are kept; forever when `None`
+    retention: Option<Duration>,
+    #[cfg(feature = "s3")]
+    upload: Option<S3Upload>,
+}
+
+impl Jobs {
+    /// Keep jobs in `dir`, created if need be, running up to `max_running`
+    /// at once. Jobs from earlier runs are loaded; any still queued or
+    /// running were interrupted, and are marked failed.
+    pub async fn open(dir: impl Into<PathBuf>, max_running: usize) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| Error::Internal(format!("failed to create jobs dir: {}", e)))?;
+
+        let jobs = Self {
+            dir,
+            jobs: Mutex::default(),
+            running: Semaphore::new(max_running.max(1)),
+            retention: Some(DEFAULT_JOBS_RETENTION),
+            #[cfg(feature = "s3")]
+            upload: None,
+        };
+
+        let mut entries = fs::read_dir(&jobs.dir)
+            .await
+            .map_err(|e| Error::Internal(format!("failed to read jobs dir: {}", e)))?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let job = fs::read(&path)
+                .await
+                .ok()
+                .and_then(|json| serde_json::from_slice::<Job>(&json).ok());
+            let Some(mut job) = job else {
+                tracing::warn!("Skipping unreadable job {}", path.display());
+                continue;
+            };
+            if matches!(job.status, JobStatus::Queued | JobStatus::Running) {
+                job.status = JobStatus::Failed;
+                job.error = Some("interrupted by a server restart".to_string());
+                job.finished_at = Some(rfc3339(SystemTime::now()));
+                let _ = fs::remove_file(jobs.file(&job.id, "part")).await;
+                jobs.save(&job).await?;
+            }
+            jobs.jobs.lock().unwrap().insert(job.id.clone(), job);
+        }
+        Ok(jobs)
+    }
+
+    /// Keep finished jobs and their results for `retention`, or forever
+    /// if it's zero.
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = Some(retention).filter(|retention| !retention.is_zero());
+        self
+    }
+
+    /// Upload results to S3 rather than serving them from the jobs
+    /// directory.
+    #[cfg(feature = "s3")]
+    pub fn with_s3_upload(mut self, upload: S3Upload) -> Self {
+        self.upload = Some(upload);
+        self
+    }
+
+    /// Remove expired jobs now and then in the background, until the jobs
+    /// are dropped.
+    pub fn spawn_cleanup(self: &Arc<Self>) {
+        let Some(retention) = self.retention else {
+            return;
+        };
+        let jobs = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(retention.min(CLEANUP_INTERVAL));
+            loop {
+                interval.tick().await;
+                let Some(jobs) = jobs.upgrade() else {
+                    return;
+                };
+                let removed = jobs.remove_expired().await;
+                if removed > 0 {
+                    tracing::info!("Removed {} expired export job(s)", removed);
+                }
+            }
+        });
+    }
+
+    /// Remove jobs that finished more than the retention period ago, with
+    /// their results. Returns how many were removed.
+    pub async fn remove_expired(&self) -> usize {
+        let Some(retention) = self.retention else {
+            return 0;
+        };
+        let finished: Vec<Job> = self
+            .jobs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|job| matches!(job.status, JobStatus::Succeeded | JobStatus::Failed))
+            .cloned()
+            .collect();
+
+        let mut removed = 0;
+        for job in finished {
+            // A job's status is saved last when it finishes
+            let finished_at = fs::metadata(self.file(&job.id, "json"))
+                .await
+                .and_then(|metadata| metadata.modified());
+            let expired = finished_at
+                .ok()
+                .and_then(|finished_at| finished_at.elapsed().ok())
+                .is_some_and(|age| age > retention);
+            if !expired {
+                continue;
+            }
+
+            #[cfg(feature = "s3")]
+            {
+                if let (Some(upload), Some(url)) = (&self.upload, &job.result_url) {
+                    if url.starts_with("s3://") {
+                        if let Err(e) = upload.delete(url).await {
+                            tracing::warn!("Failed to delete result of job {}: {}", job.id, e);
+                            continue;
+                        }
+                    }
+                }
+            }
+            self.jobs.lock().unwrap().remove(&job.id);
+            let _ = fs::remove_file(self.file(&job.id, "result")).await;
+            if let Err(e) = fs::remove_file(self.file(&job.id, "json")).await {
+                tracing::warn!("Failed to remove job {}: {}", job.id, e);
+            }
+            removed += 1;
+        }
+        removed
+    }
+
+    /// The `/jobs` routes
+    pub fn routes(self: &Arc<Self>) -> Router<AppState> {
+        let (submit, status, result) = (self.clone(), self.clone(), self.clone());
+        Router::new()
+            .route(
+                JOBS_PATH,
+                post(
+                    move |State(state): State<AppState>,
+                          base_url: RequestBaseUrl,
+                          subject: RequestSubject,
+                          Json(request): Json<JobRequest>| {
+                        submit.clone().submit(state, base_url, subject, request)
+                    },
+                ),
+            )
+            .route(
+                &format!("{}/:id", JOBS_PATH),
+                get(
+                    move |subject: RequestSubject, Path(id): Path<String>| async move {
+                        status.status(&id, &subject).await.map(Json)
+                    },
+                ),
+            )
+            .route(
+                &format!("{}/:id/result", JOBS_PATH),
+                get(
+                    move |subject: RequestSubject, Path(id): Path<String>| async move {
+                        result.result(&id, &subject).await
+                    },
+                ),
+            )
+    }
+
+    /// Build the request's ticket, so that invalid requests fail here
+    /// rather than in the job, and queue the job to assemble it.
+    async fn submit(
+        self: Arc<Self>,
+        state: AppState,
+        base_url: RequestBaseUrl,
+        subject: RequestSubject,
+        request: JobRequest,
+    ) -> Result<Response> {
+        let JobRequest {
+            endpoint,
+            id: dataset,
+            output,
+            compress,
+            ticket,
+        } = request;
+        if output == Some(JobOutput::Sam) && endpoint != TicketEndpoint::Reads {
+            return Err(Error::InvalidInput(
+                "SAM output is only for reads".to_string(),
+            ));
+        }
+
+        let endpoints = state.endpoints;
+        let body = serde_json::Value::Object(ticket);
+        let path = Path(dataset.clone());
+        let ticket_subject = subject.clone();
+        let assembled = assemble(
+            &state,
+            &dataset,
+            subject.clone(),
+            |ticket_state| async move {
+                let (base_url, page) = (RequestBaseUrl(None), TicketPage::default());
+                match endpoint {
+                    TicketEndpoint::Reads if endpoints.reads => {
+                        let body: ReadsPostBody = post_body(body)?;
+                        // Precise slices aren't byte ranges of the file
+                        if body.precise == Some(true) {
+                            return Err(Error::InvalidInput(
+                                "precise is not supported for jobs".to_string(),
+                            ));
+                        }
+                        post_reads(
+                            State(ticket_state),
+                            base_url,
+                            ticket_subject,
+                            page,
+                            path,
+                            Json(body),
+                        )
+                        .await
+                    }
+                    TicketEndpoint::Variants if endpoints.variants => {
+                        let body: VariantsPostBody = post_body(body)?;
+                        post_variants(
+                            State(ticket_state),
+                            base_url,
+                            ticket_subject,
+                            page,
+                            path,
+                            Json(body),
+                        )
+                        .await
+                    }
+                    TicketEndpoint::Sequences if endpoints.sequences => {
+                        let body: SequencesPostBody = post_body(body)?;
+                        post_sequences(
+                            State(ticket_state),
+                            base_url,
+                            ticket_subject,
+                            page,
+                            path,
+                            Json(body),
+                        )
+                        .await
+                    }
+                    endpoint => Err(Error::NotFound(format!("/{}", endpoint.as_str()))),
+                }
+            },
+        )
+        .await?;
+        if output == Some(JobOutput::Sam) && assembled.format != Format::Bam {
+            return Err(Error::UnsupportedFormat(format!(
+                "SAM output needs BAM, not {:?}",
+                assembled.format
+            )));
+        }
+
+        let filename = match output {
+            Some(JobOutput::Sam) => {
+                let name = dataset.rsplit('/').next().unwrap_or(&dataset);
+                format!("{}.sam", name)
+            }
+            None => assembled.filename,
+        };
+        let job = Job {
+            id: job_id(),
+            status: JobStatus::Queued,
+            dataset,
+            format: assembled.format,
+            output,
+            filename,
+            created_at: rfc3339(SystemTime::now()),
+            finished_at: None,
+            bytes: None,
+            result_url: None,
+            error: None,
+            subject: subject.0,
+        };
+        self.save(&job).await?;
+        self.jobs
+            .lock()
+            .unwrap()
+            .insert(job.id.clone(), job.clone());
+
+        let location = base_url.rebase_url(
+            &state,
+            format!(
+                "{}{}/{}",
+                state.base_url.trim_end_matches('/'),
+                JOBS_PATH,
+                job.id
+            ),
+        );
+        tracing::info!("Queued job {} for {}", job.id, job.dataset);
+        let runner = self.clone();
+        let (id, result_url) = (job.id.clone(), format!("{}/result", location));
+        tokio::spawn(async move {
+            runner
+                .run(id, assembled.stream, output, compress, result_url)
+                .await
+        });
+
+        let response = (
+            StatusCode::ACCEPTED,
+            [(header::LOCATION, location)],
+            Json(job),
+        )
+            .into_response();
+        Ok(match assembled.access {
+            Some(access) => access.attach(response),
+            None => response,
+        })
+    }
+
+    /// Job `id`, if `subject` submitted it
+    fn job(&self, id: &str, subject: &RequestSubject) -> Result<Job> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(id)
+            .filter(|job| job.subject == subject.0)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("job {}", id)))
+    }
+
+    /// Job `id` as `GET /jobs/:id` reports it, with an uploaded result's
+    /// `s3://` URL presigned
+    async fn status(&self, id: &str, subject: &RequestSubject) -> Result<Job> {
+        let mut job = self.job(id, subject)?;
+        if let Some(url) = job.result_url.take() {
+            job.result_url = Some(self.result_url(url).await?);
+        }
+        Ok(job)
+    }
+
+    /// A URL clients can fetch a result at `url` from
+    async fn result_url(&self, url: String) -> Result<String> {
+        #[cfg(feature = "s3")]
+        {
+            if let Some(upload) = &self.upload {
+                if url.starts_with("s3://") {
+                    return upload.presign(&url).await;
+                }
+            }
+        }
+        Ok(url)
+    }
+
+    async fn result(&self, id: &str, subject: &RequestSubject) -> Result<Response> {
+        let job = self.job(id, subject)?;
+        if job.status != JobStatus::Succeeded {
+            return Err(Error::NotFound(format!("result of job {}", id)));
+        }
+        // Uploaded results are only in S3
+        if let Some(url) = job.result_url.filter(|url| url.starts_with("s3://")) {
+            let url = self.result_url(url).await?;
+            let location = HeaderValue::from_str(&url)
+                .map_err(|_| Error::Internal(format!("invalid result URL for job {}", id)))?;
+            let mut response = StatusCode::FOUND.into_response();
+            response.headers_mut().insert(header::LOCATION, location);
+            response
+                .headers_mut()
+                .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+            return Ok(response);
+        }
+        let file = fs::File::open(self.file(id, "result"))
+            .await
+            .map_err(|_| Error::NotFound(format!("result of job {}", id)))?;
+        let len = file.metadata().await?.len();
+
+        Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, job.content_type())
+            .header(header::CONTENT_LENGTH, len)
+            .header(header::CONTENT_DISPOSITION, attachment(&job.filename))
+            .extension(Precompressed)
+            .body(Body::from_stream(ReaderStream::new(file)))
+            .map_err(|e| Error::Internal(format!("failed to build job result response: {}", e)))?)
+    }
+
+    /// Wait for a turn, then assemble the job's result
+    async fn run(
+        self: Arc<Self>,
+        id: String,
+        stream: BoxStream<'static, io::Result<Bytes>>,
+        output: Option<JobOutput>,
+        compress: bool,
+        result_url: String,
+    ) {
+        let _permit = self.running.acquire().await;
+        self.update(&id, |job| job.status = JobStatus::Running)
+            .await;
+
+        let export = self.export(&id, stream, output, compress, result_url).await;
+        self.update(&id, |job| {
+            job.finished_at = Some(rfc3339(SystemTime::now()));
+            match export {
+                Ok(export) => {
+                    job.status = JobStatus::Succeeded;
+                    job.bytes = Some(export.bytes);
+                    job.result_url = Some(export.url);
+                    if export.compressed {
+                        job.filename.push_str(".gz");
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("Job {} failed: {}", job.id, e);
+                    job.status = JobStatus::Failed;
+                    job.error = Some(e.to_string());
+                }
+            }
+        })
+        .await;
+    }
+
+    /// Write the assembled slice, then convert, compress and upload it
+    async fn export(
+        &self,
+        id: &str,
+        mut stream: BoxStream<'static, io::Result<Bytes>>,
+        output: Option<JobOutput>,
+        compress: bool,
+        result_url: String,
+    ) -> Result<Export> {
+        let part = self.file(id, "part");
+        let mut file = fs::File::create(&part).await?;
+        while let Some(chunk) = stream
+            .try_next()
+            .await
+            .map_err(|e| Error::Internal(format!("failed to read data: {}", e)))?
+        {
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        drop(file);
+
+        let result = self.file(id, "result");
+        let (from, to) = (part.clone(), result.clone());
+        let compressed = tokio::task::spawn_blocking(move || finish(&from, &to, output, compress))
+            .await
+            .map_err(|e| Error::Internal(format!("job task failed: {}", e)))?
+            .map_err(|e| Error::Internal(format!("failed to write result: {}", e)));
+        if compressed.is_err() {
+            let _ = fs::remove_file(&part).await;
+        }
+        let compressed = compressed?;
+        let bytes = fs::metadata(&result).await?.len();
+
+        #[cfg(feature = "s3")]
+        {
+            if let Some(upload) = &self.upload {
+                let job = self.jobs.lock().unwrap().get(id).cloned();
+                let mut filename = job.map(|job| job.filename).unwrap_or_default();
+                if compressed {
+                    filename.push_str(".gz");
+                }
+                let url = upload.put(&format!("{}/{}", id, filename), &result).await?;
+                let _ = fs::remove_file(&result).await;
+                return Ok(Export {
+                    bytes,
+                    compressed,
+                    url,
+                });
+            }
+        }
+
+        Ok(Export {
+            bytes,
+            compressed,
+            url: result_url,
+        })
+    }
+
+    /// Change job `id` and save it
+    async fn update(&self, id: &str, change: impl FnOnce(&mut Job)) {
+        let job = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let Some(job) = jobs.get_mut(id) else {
+                return;
+            };
+            change(job);
+            job.clone()
+        };
+        if let Err(e) = self.save(&job).await {
+            tracing::warn!("Failed to save job {}: {}", id, e);
+        }
+    }
+
+    /// Write a job's status, replacing the previous one in one step
+    async fn save(&self, job: &Job) -> Result<()> {
+        let json = serde_json::to_vec_pretty(job)
+            .map_err(|e| Error::Internal(format!("failed to serialize job: {}", e)))?;
+        let tmp = self.file(&job.id, "json.tmp");
+        fs::write(&tmp, json).await?;
+        fs::rename(&tmp, self.file(&job.id, "json")).await?;
+        Ok(())
+    }
+
+    fn file(&self, id: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", id, extension))
+    }
+}
+
+/// The endpoint's POST body, from the job request's other fields
+fn post_body<T: serde::de::DeserializeOwned>(body: serde_json::Value) -> Result<T> {
+    serde_json::from_value(body).map_err(|e| Error::InvalidInput(format!("invalid job: {}", e)))
+}
+
+/// A random job ID: 32 hex digits
+fn job_id() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("OS random number generator unavailable");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Convert and compress the assembled slice at `part` into `result`,
+/// removing `part`. Returns whether the result was compressed here.
+fn finish(
+    part: &std::path::Path,
+    result: &std::path::Path,
+    output: Option<JobOutput>,
+    compress: bool,
+) -> io::Result<bool> {
+    // Converted output is text; otherwise the slice may be BGZF already
+    let compress = compress && (output.is_some() || !is_gzip(part)?);
+    if output.is_none() && !compress {
+        std::fs::rename(part, result)?;
+        return Ok(false);
+    }
+
+    let file = io::BufWriter::new(std::fs::File::create(result)?);
+    if compress {
+        let mut writer = bgzf::io::Writer::new(file);
+        convert(part, output, &mut writer)?;
+        writer.finish()?.flush()?;
+    } else {
+        let mut writer = file;
+        convert(part, output, &mut writer)?;
+        writer.flush()?;
+    }
+    std::fs::remove_file(part)?;
+    Ok(compress)
+}
+
+/// Write the slice at `part` to `out`, converted to `output`
+fn convert(
+    part: &std::path::Path,
+    output: Option<JobOutput>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let file = std::fs::File::open(part)?;
+    match output {
+        None => {
+            io::copy(&mut io::BufReader::new(file), out)?;
+        }
+        Some(JobOutput::Sam) => {
+            let mut reader = bam::io::Reader::new(file);
+            let header = reader.read_header()?;
+            let mut writer = sam::io::Writer::new(out);
+            writer.write_header(&header)?;
+            for record in reader.records() {
+                writer.write_alignment_record(&header, &record?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_gzip(path: &std::path::Path) -> io::Result<bool> {
+    let mut magic = [0; 2];
+    let mut file = std::fs::File::open(path)?;
+    Ok(file.read(&mut magic)? == 2 && magic == [0x1f, 0x8b])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_bam() -> PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/sample.bam")
+    }
+
+    #[test]
+    fn test_finish() {
+        let dir = tempfile::tempdir().unwrap();
+        let (part, result) = (dir.path().join("a.part"), dir.path().join("a.result"));
+
+        // BAM is BGZF already, so it's kept as it is
+        std::fs::copy(sample_bam(), &part).unwrap();
+        assert!(!finish(&part, &result, None, true).unwrap());
+        assert!(!part.exists());
+        assert_eq!(
+            std::fs::read(&result).unwrap(),
+            std::fs::read(sample_bam()).unwrap()
+        );
+
+        std::fs::copy(sample_bam(), &part).unwrap();
+        assert!(!finish(&part, &result, Some(JobOutput::Sam), false).unwrap());
+        let sam = std::fs::read_to_string(&result).unwrap();
+        assert!(sam.starts_with('@'));
+        assert_eq!(sam.lines().filter(|line| !line.starts_with('@')).count(), 4);
+
+        std::fs::copy(sample_bam(), &part).unwrap();
+        assert!(finish(&part, &result, Some(JobOutput::Sam), true).unwrap());
+        let mut decompressed = String::new();
+        bgzf::io::Reader::new(std::fs::File::open(&result).unwrap())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, sam);
+    }
+
+    #[tokio::test]
+    async fn test_open_fails_interrupted_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let jobs = Jobs::open(dir.path(), 1).await.unwrap();
+        let job = Job {
+            id: job_id(),
+            status: JobStatus::Running,
+            dataset: "sample".to_string(),
+            format: Format::Bam,
+            output: None,
+            filename: "sample.bam".to_string(),
+            created_at: rfc3339(SystemTime::now()),
+            finished_at: None,
+            bytes: None,
+            result_url: None,
+            error: None,
+            subject: Some("alice".to_string()),
+        };
+        jobs.save(&job).await.unwrap();
+        std::fs::write(jobs.file(&job.id, "part"), b"partial").unwrap();
+        assert_eq!(job.id.len(), 32);
+        assert_ne!(job.id, job_id());
+
+        let jobs = Jobs::open(dir.path(), 1).await.unwrap();
+        let alice = RequestSubject(Some("alice".to_string()));
+        let reopened = jobs.job(&job.id, &alice).unwrap();
+        assert_eq!(reopened.status, JobStatus::Failed);
+        assert!(reopened.error.is_some());
+        assert!(!jobs.file(&job.id, "part").exists());
+
+        // Only the submitter sees a job
+        assert!(matches!(
+            jobs.job(&job.id, &RequestSubject(None)),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_remove_expired() {
+        let dir = tempfile::tempdir().unwrap();
+        let jobs = Jobs::open(dir.path(), 1).await.unwrap();
+        let job = |status| Job {
+            id: job_id(),
+            status,
+            dataset: "sample".to_string(),
+            format: Format::Bam,
+            output: None,
+            filename: "sample.bam".to_string(),
+            created_at: rfc3339(SystemTime::now()),
+            finished_at: None,
+            bytes: None,
+            result_url: None,
+            error: None,
+            subject: None,
+        };
+        let (done, running) = (job(JobStatus::Succeeded), job(JobStatus::Running));
+        for job in [&done, &running] {
+            jobs.save(job).await.unwrap();
+            jobs.jobs
+                .lock()
+                .unwrap()
+                .insert(job.id.clone(), job.clone());
+        }
+        std::fs::write(jobs.file(&done.id, "result"), b"result").unwrap();
+
+        // Nothing has been kept a day yet
+        let jobs = jobs.with_retention(Duration::from_secs(86400));
+        assert_eq!(jobs.remove_expired().await, 0);
+
+        let jobs = jobs.with_retention(Duration::from_millis(1));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(jobs.remove_expired().await, 1);
+        assert!(jobs.job(&done.id, &RequestSubject(None)).is_err());
+        assert!(!jobs.file(&done.id, "json").exists());
+        assert!(!jobs.file(&done.id, "result").exists());
+        // Running jobs are kept whatever their age
+        assert!(jobs.job(&running.id, &RequestSubject(None)).is_ok());
+
+        let jobs = jobs.with_retention(Duration::ZERO);
+        assert_eq!(jobs.retention, None);
+    }
+}
@@ -33,14 +33,34 @@
 //!
 //! ## Architecture
 //!
-//! The crate is organized into the following modules:
+//! The crate is organized into the following modules. All but `error`,
+//! `types`, `client` and `wasm` require the `server` feature (on by default).
 //!
 //! - [`config`] - Server configuration and CLI arguments
+//! - [`check`] - Data directory validation
+//! - `client` - htsget client that follows tickets (requires `client` feature)
+//! - `wasm` - the client for browsers, as WebAssembly (requires `wasm` feature)
+//! - `conformance` - htsget spec conformance cases (requires `client` feature)
 //! - [`error`] - Error types mapping to htsget protocol errors
 //! - [`types`] - Request/response types per the htsget spec
 //! - [`handlers`] - HTTP endpoint handlers
+//! - [`forwarded`] - Reverse-proxy (`Forwarded`/`X-Forwarded-*`) base URL handling
+//! - [`server`] - Router assembly shared by the CLI and Python bindings
 //! - [`storage`] - Storage backend abstraction
 //! - [`formats`] - Format-specific index readers
+//! - [`indexer`] - Index generation for data files
+//! - [`prefetch`] - Background index cache warming
+//! - [`audit`] - Audit log of ticket and data access
+//! - [`quota`] - Per-subject request and byte quotas on the data endpoint
+//! - [`usage`] - Bytes served per dataset and principal, for usage reporting
+//! - [`admin`] - The effective configuration, for `--print-config` and `/admin/config`
+//! - [`jobs`] - Export jobs that assemble slices on the server (`/jobs`)
+//! - [`shared`] - Caches shared between replicas (Redis with the `redis` feature)
+//! - `refget` - GA4GH refget checksum lookup (requires `refget` feature)
+//! - `ffi` - C ABI for index resolution (requires `ffi` feature)
+//! - `jws` - Signed ticket responses (requires `jws` feature)
+//! - `tls` - HTTPS serving and mTLS client certificates (requires `tls` feature)
+//! - `uds` - Serving on a Unix domain socket (requires `uds` feature, Unix only)
 //!
 //! ## Protocol
 //!
@@ -116,6 +136,32 @@
 //! ticket = client.variants("sample1", reference_name="chr1")
 //! ```
 //!
+//! ### Example: Background Server
+//!
+//! ```python
+//! import htsgetr
+//!
+//! # start() returns once the listener is bound; port=0 picks a free port
+//! with htsgetr.HtsgetServer("./data", host="127.0.0.1", port=0) as server:
+//!     client = htsgetr.HtsgetClient(server.url())
+//!     print(client.reads("sample1"))
+//! # The server is stopped when the block exits
+//! ```
+//!
+//! ### Example: asyncio Client
+//!
+//! ```python
+//! import asyncio
+//! import htsgetr
+//!
+//! async def main():
+//!     client = htsgetr.AsyncHtsgetClient("http://localhost:8080")
+//!     ticket = await client.reads("NA12878", reference_name="chr1")
+//!     data = await client.reads_data("NA12878", reference_name="chr1")
+//!
+//! asyncio.run(main())
+//! ```
+//!
 //! ### Example: Background Server with Threading
 //!
 //! ```python
@@ -162,33 +208,105 @@
 //! ### Python API Reference
 //!
 //! **`HtsgetServer`**
-//! - `HtsgetServer(data_dir, host="0.0.0.0", port=8080)` - Create server with local storage
+//! - `HtsgetServer(data_dir, host="0.0.0.0", port=8080, issuer=None, jwks_url=None, public_key_pem=None, audience=None, public_endpoints=None, data_url_secret=None)` - Create server with local storage (auth kwargs require the `auth` feature)
 //! - `HtsgetServer.with_s3(bucket, host="0.0.0.0", port=8080, region=None, prefix="", endpoint=None, cache_dir="/tmp/htsgetr-cache", presigned_url_expiry=3600)` - Create server with S3 storage
 //! - `server.url()` - Get the server URL
+//! - `server.enable_auth(issuer=None, jwks_url=None, public_key_pem=None, audience=None, public_endpoints=None, data_url_secret=None)` - Enable JWT auth and data URL signing
 //! - `server.run()` - Start the server (blocking)
+//! - `server.start()` / `server.stop()` - Start in the background / shut down gracefully
+//! - `server.port` - Listening port (the bound port after `start()` with `port=0`)
+//! - `with HtsgetServer(...) as server:` - Start on enter, stop on exit
 //! - `server.is_s3()` - Check if using S3 storage
 //!
+//! **`query_ranges(path, format=None, region=None, index_path=None)`**
+//! - Compute the header and data byte ranges for a region of a local file without a server;
+//!   returns `{"format": ..., "header": (start, end), "ranges": [(start, end), ...]}`
+//!
 //! **`HtsgetClient`**
 //! - `HtsgetClient(base_url)` - Create a client for an htsget server
 //! - `client.reads(id, reference_name=None, start=None, end=None, format=None)` - Fetch reads ticket
 //! - `client.variants(id, reference_name=None, start=None, end=None, format=None)` - Fetch variants ticket
+//! - `client.reads_data(id, reference_name=None, start=None, end=None, format=None, output_path=None)` - Follow the reads ticket; returns bytes, or writes to `output_path`
+//! - `client.variants_data(id, reference_name=None, start=None, end=None, format=None, output_path=None)` - Follow the variants ticket; returns bytes, or writes to `output_path`
+//!
+//! **`AsyncHtsgetClient`**
+//! - `AsyncHtsgetClient(base_url)` - Create an asyncio-compatible client
+//! - `await client.reads(...)` / `await client.variants(...)` - Fetch a ticket (JSON string)
+//! - `await client.reads_data(...)` / `await client.variants_data(...)` - Follow the ticket and return bytes
+//!
+//! ## WebAssembly Client
+//!
+//! With the `wasm` feature (and without the default `server` feature),
+//! `wasm-pack build --target web --no-default-features --features wasm`
+//! builds a browser client that follows tickets over `fetch`; see `wasm`.
 //!
 //! ## Roadmap
 //!
 #![doc = include_str!("../docs/roadmap.md")]
 
+#[cfg(feature = "server")]
+pub mod admin;
+#[cfg(feature = "server")]
+pub mod audit;
+#[cfg(feature = "server")]
+pub mod check;
+#[cfg(feature = "server")]
 pub mod config;
 pub mod error;
+#[cfg(feature = "server")]
 pub mod formats;
+#[cfg(feature = "server")]
+pub mod forwarded;
+#[cfg(feature = "server")]
 pub mod handlers;
+#[cfg(feature = "server")]
+pub mod indexer;
+#[cfg(feature = "server")]
+pub mod jobs;
+#[cfg(feature = "server")]
+pub mod prefetch;
+#[cfg(feature = "server")]
+pub mod quota;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "server")]
+pub mod shared;
+#[cfg(feature = "server")]
 pub mod storage;
 pub mod types;
+#[cfg(feature = "server")]
+pub mod usage;
+
+#[cfg(any(feature = "client", feature = "wasm"))]
+pub mod client;
+
+#[cfg(all(feature = "client", feature = "server"))]
+pub mod conformance;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
 
 #[cfg(feature = "python")]
 pub mod python;
 
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
 #[cfg(feature = "auth")]
 pub mod auth;
 
+#[cfg(feature = "refget")]
+pub mod refget;
+
+#[cfg(feature = "jws")]
+pub mod jws;
+
+#[cfg(feature = "tls")]
+pub mod tls;
+
+#[cfg(all(unix, feature = "uds"))]
+pub mod uds;
+
+#[cfg(feature = "server")]
 pub use config::Config;
 pub use error::{Error, Result};
@@ -1,27 +1,38 @@
-use clap::Parser;
+use axum::Router;
+use clap::CommandFactory;
 use std::sync::Arc;
-use tower_http::{cors::CorsLayer, trace::TraceLayer};
+use std::time::Duration;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use htsgetr::{
     Config,
-    config::StorageType,
-    handlers::{AppState, create_router},
+    admin::ConfigReport,
+    audit::AuditLog,
+    config::{
+        BenchArgs, CheckArgs, Command, CompletionsArgs, ConfigFile, ConformanceArgs, FetchArgs,
+        IndexArgs, InitArgs, ServeArgs, StorageType,
+    },
+    handlers::DataMode,
+    jobs::Jobs,
+    quota::Quota,
+    server::ServerBuilder,
+    shared::SharedCache,
     storage::{LocalStorage, Storage},
+    usage::UsageStats,
 };
 
 #[cfg(feature = "s3")]
-use htsgetr::storage::S3Storage;
+use htsgetr::storage::{S3Credentials, S3Storage, S3Upload};
 
 #[cfg(feature = "http")]
 use htsgetr::storage::HttpStorage;
 
 #[cfg(feature = "auth")]
-use htsgetr::auth::{AuthConfig, UrlSigner, auth_middleware};
+use htsgetr::server::AuthOptions;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let config = Config::parse();
+    let config = Config::load();
 
     // Initialize tracing
     tracing_subscriber::registry()
@@ -32,14 +43,153 @@ async fn main() -> anyhow::Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    // Create storage backend
+    match &config.command {
+        None => serve(&config, &ServeArgs::default()).await,
+        Some(Command::Serve(args)) => serve(&config, args).await,
+        Some(Command::Check(args)) => run_check(&config, args).await,
+        Some(Command::Index(args)) => run_index(args).await,
+        Some(Command::Bench(args)) => run_bench(&config, args).await,
+        Some(Command::Fetch(args)) => run_fetch(args).await,
+        Some(Command::Conformance(args)) => run_conformance(&config, args).await,
+        Some(Command::Init(args)) => run_init(args),
+        Some(Command::Completions(args)) => run_completions(args),
+    }
+}
+
+/// Start the htsget server (`htsgetr serve`).
+async fn serve(config: &Config, args: &ServeArgs) -> anyhow::Result<()> {
+    if args.print_config {
+        let report = ConfigReport::from_args(std::env::args_os())?;
+        print!("{}", report.to_env_file());
+        return Ok(());
+    }
+
+    config.validate()?;
+
+    if config.preflight {
+        preflight(config).await?;
+    }
+
+    let app = build_app(config).await?;
+
+    if let Some(path) = &config.uds {
+        return serve_uds(config, path, app).await;
+    }
+
+    let addr = format!("{}:{}", config.host, config.port);
+    tracing::info!("Starting htsgetr server on {}", addr);
+    tracing::info!("Data directory: {:?}", config.data_dir);
+
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+
+    if let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key) {
+        return serve_tls(config, cert, key, listener, app).await;
+    }
+
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Serve over HTTPS, verifying client certificates if a CA is configured.
+#[cfg(feature = "tls")]
+async fn serve_tls(
+    config: &Config,
+    cert: &std::path::Path,
+    key: &std::path::Path,
+    listener: tokio::net::TcpListener,
+    app: Router,
+) -> anyhow::Result<()> {
+    let options = htsgetr::tls::TlsOptions {
+        cert: cert.to_path_buf(),
+        key: key.to_path_buf(),
+        client_ca: config.tls_client_ca.clone(),
+        require_client_cert: config.tls_require_client_cert,
+    };
+
+    if let Some(ca) = &options.client_ca {
+        tracing::info!("Verifying client certificates against {:?}", ca);
+    }
+
+    htsgetr::tls::serve(listener, app, options.server_config()?).await?;
+    Ok(())
+}
+
+#[cfg(not(feature = "tls"))]
+async fn serve_tls(
+    _config: &Config,
+    _cert: &std::path::Path,
+    _key: &std::path::Path,
+    _listener: tokio::net::TcpListener,
+    _app: Router,
+) -> anyhow::Result<()> {
+    anyhow::bail!("TLS requires the 'tls' feature to be enabled")
+}
+
+/// Serve on a Unix domain socket behind a local reverse proxy.
+#[cfg(all(unix, feature = "uds"))]
+async fn serve_uds(config: &Config, path: &std::path::Path, app: Router) -> anyhow::Result<()> {
+    if config.base_url.is_none() && config.trusted_proxies.is_none() {
+        tracing::warn!(
+            "serving on a socket without --base-url or --trusted-proxies; ticket URLs will use {}",
+            config.effective_base_url()
+        );
+    }
+
+    let listener = htsgetr::uds::bind(path)?;
+    tracing::info!("Starting htsgetr server on unix:{}", path.display());
+    tracing::info!("Data directory: {:?}", config.data_dir);
+
+    htsgetr::uds::serve(listener, app).await?;
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "uds")))]
+async fn serve_uds(_config: &Config, _path: &std::path::Path, _app: Router) -> anyhow::Result<()> {
+    anyhow::bail!(
+        "Unix domain sockets require the 'uds' feature on a Unix platform. Rebuild with: cargo build --features uds"
+    )
+}
+
+/// Connect to the cache shared between replicas, if one is configured.
+#[cfg(feature = "redis")]
+async fn shared_cache(config: &Config) -> anyhow::Result<Option<Arc<dyn SharedCache>>> {
+    let Some(url) = &config.redis_url else {
+        return Ok(None);
+    };
+    tracing::info!("Sharing caches through Redis");
+    Ok(Some(Arc::new(htsgetr::shared::Redis::connect(url).await?)))
+}
+
+#[cfg(not(feature = "redis"))]
+async fn shared_cache(config: &Config) -> anyhow::Result<Option<Arc<dyn SharedCache>>> {
+    if config.redis_url.is_some() {
+        anyhow::bail!(
+            "--redis-url requires the 'redis' feature. Rebuild with: cargo build --features redis"
+        );
+    }
+    Ok(None)
+}
+
+/// Create the configured storage backend.
+async fn build_storage(
+    config: &Config,
+    #[cfg_attr(not(feature = "s3"), allow(unused_variables))] shared: Option<Arc<dyn SharedCache>>,
+) -> anyhow::Result<Arc<dyn Storage>> {
     let storage: Arc<dyn Storage> = match config.storage {
         StorageType::Local => {
             tracing::info!("Using local storage backend");
-            Arc::new(LocalStorage::new(
-                config.data_dir.clone(),
-                config.effective_base_url(),
-            ))
+            let storage = LocalStorage::new(config.data_dir.clone(), config.effective_base_url())
+                .with_naming(config.naming()?);
+            if config.convert_vcf {
+                Arc::new(storage.with_vcf_conversion(config.cache_dir.join("vcf")))
+            } else {
+                Arc::new(storage)
+            }
         }
         #[cfg(feature = "s3")]
         StorageType::S3 => {
@@ -50,24 +200,51 @@ async fn main() -> anyhow::Result<()> {
 
             tracing::info!("Using S3 storage backend: bucket={}", bucket);
 
-            Arc::new(
-                S3Storage::new(
-                    bucket,
-                    config.s3_prefix.clone(),
-                    config.cache_dir.clone(),
-                    config.presigned_url_expiry,
-                    config.s3_region.clone(),
-                    config.s3_endpoint.clone(),
-                )
-                .await?,
+            let credentials = S3Credentials {
+                profile: config.s3_profile.clone(),
+                role_arn: config.s3_role_arn.clone(),
+                role_session_name: config.s3_role_session_name.clone(),
+                external_id: config.s3_external_id.clone(),
+                web_identity_token_file: config.s3_web_identity_token_file.clone(),
+                anonymous: config.s3_public,
+            };
+            let storage = S3Storage::with_credentials(
+                bucket,
+                config.s3_prefix.clone(),
+                config.cache_dir.clone(),
+                config.presigned_url_expiry,
+                config.s3_region.clone(),
+                config.s3_endpoint.clone(),
+                &credentials,
             )
+            .await?
+            .with_retry(config.retry_policy()?)
+            .with_timeout(Duration::from_secs(config.storage_timeout))
+            .with_index_download(config.index_download())
+            .with_naming(config.naming()?)
+            .with_presign_expiry_per_url(Duration::from_secs(config.presigned_url_expiry_per_url))
+            .with_clock_skew(Duration::from_secs(config.presign_clock_skew))
+            .with_bucket_routes(config.bucket_routes()?);
+            let storage = match shared {
+                Some(cache) => storage.with_shared_cache(cache),
+                None => storage,
+            };
+            let storage = if config.s3_public {
+                tracing::info!("Serving a public bucket with unsigned URLs");
+                storage.with_public_urls()
+            } else {
+                storage
+            };
+
+            if config.s3_proxy {
+                tracing::info!("Proxying S3 data through /data");
+                Arc::new(storage.with_proxy(config.effective_base_url()))
+            } else {
+                Arc::new(storage)
+            }
         }
         #[cfg(not(feature = "s3"))]
-        StorageType::S3 => {
-            anyhow::bail!(
-                "S3 storage requires the 's3' feature to be enabled. Rebuild with: cargo build --features s3"
-            )
-        }
+        StorageType::S3 => return Err(missing_feature(config.storage)),
         #[cfg(feature = "http")]
         StorageType::Http => {
             let base_url = config.http_base_url.clone().ok_or_else(|| {
@@ -76,124 +253,588 @@ async fn main() -> anyhow::Result<()> {
 
             tracing::info!("Using HTTP storage backend: base_url={}", base_url);
 
-            Arc::new(
-                HttpStorage::new(
-                    base_url,
-                    config.http_index_base_url.clone(),
-                    config.cache_dir.clone(),
-                )
-                .await?,
+            let storage = HttpStorage::new(
+                base_url,
+                config.http_index_base_url.clone(),
+                config.cache_dir.clone(),
             )
+            .await?
+            .with_retry(config.retry_policy()?)
+            .with_timeout(Duration::from_secs(config.storage_timeout))
+            .with_index_download(config.index_download())
+            .with_naming(config.naming()?)
+            .with_max_redirects(config.http_max_redirects)?
+            .with_headers(config.upstream_headers()?)
+            .with_forwarded_headers(config.forwarded_headers()?)
+            .with_ticket_headers(config.ticket_headers()?);
+
+            Arc::new(match &config.http_url_template {
+                Some(template) => storage.with_url_template(template),
+                None => storage,
+            })
         }
         #[cfg(not(feature = "http"))]
-        StorageType::Http => {
-            anyhow::bail!(
-                "HTTP storage requires the 'http' feature to be enabled. Rebuild with: cargo build --features http"
-            )
-        }
+        StorageType::Http => return Err(missing_feature(config.storage)),
     };
 
-    // Create URL signer if auth is enabled
-    #[cfg(feature = "auth")]
-    let url_signer = if config.auth_enabled {
-        let secret = config
-            .data_url_secret
-            .as_ref()
-            .map(|s| s.as_bytes().to_vec())
-            .unwrap_or_else(|| {
-                tracing::info!("Generating random data URL signing secret");
-                UrlSigner::generate_secret()
-            });
-        Some(UrlSigner::new(secret, config.data_url_expiry))
-    } else {
-        None
-    };
+    Ok(storage)
+}
 
-    let state = AppState {
+/// Error for a storage backend this build doesn't include.
+#[cfg(any(not(feature = "s3"), not(feature = "http")))]
+fn missing_feature(storage: StorageType) -> anyhow::Error {
+    let feature = storage.feature().unwrap_or_default();
+    anyhow::anyhow!(
+        "{} storage requires the '{}' feature to be enabled. Rebuild with: cargo build --features {}",
         storage,
-        base_url: config.effective_base_url(),
-        #[cfg(feature = "auth")]
-        url_signer: url_signer.clone(),
+        feature,
+        feature
+    )
+}
+
+/// Build the fully layered application router from configuration.
+async fn build_app(config: &Config) -> anyhow::Result<Router> {
+    let shared = shared_cache(config).await?;
+    let storage = build_storage(config, shared.clone()).await?;
+
+    #[cfg(feature = "crypt4gh")]
+    let storage: Arc<dyn Storage> = {
+        let routes = config.decryption_routes()?;
+        if routes.is_empty() {
+            storage
+        } else {
+            tracing::info!("decrypting {} Crypt4GH ID prefix(es)", routes.len());
+            Arc::new(htsgetr::storage::DecryptingStorage::new(
+                storage,
+                &routes,
+                config.effective_base_url(),
+                config.cache_dir.join("decrypted"),
+            )?)
+        }
     };
 
-    // Build router
-    let app = create_router(state);
+    if config.prefetch_indexes {
+        let storage = storage.clone();
+        let limit = config.prefetch_limit;
+        tokio::spawn(async move {
+            match htsgetr::prefetch::prefetch_indexes(storage.as_ref(), limit).await {
+                Ok(summary) => tracing::info!("index prefetch: {}", summary),
+                Err(e) => tracing::warn!("index prefetch failed: {}", e),
+            }
+        });
+    }
+    #[cfg(feature = "refget")]
+    let refget_storage = storage.clone();
+    let mut builder = ServerBuilder::new(storage, config.effective_base_url())
+        .cors(config.cors)
+        .compression(config.compression)
+        .data_mode(config.data_mode)
+        .header_mode(config.header_mode)
+        .header_parsing(config.header_parsing)
+        .endpoints(config.endpoints())
+        .default_formats(config.default_formats()?)
+        .plain_json_tickets(config.plain_json_tickets)
+        .error_detail(config.error_detail)
+        .expose_internal_errors(config.expose_internal_errors);
+
+    if let Some(aliases) = config.reference_alias_table()? {
+        tracing::info!("Reference name aliases enabled");
+        builder = builder.reference_aliases(aliases);
+    }
+
+    if let Some(sink) = config.audit_sink()? {
+        tracing::info!("Audit logging enabled");
+        builder = builder.audit(AuditLog::new(sink).await?);
+    }
+
+    if let Some(limits) = config.quota_limits() {
+        if !config.auth_enabled {
+            tracing::warn!(
+                "quotas are counted per authenticated subject; without --auth-enabled nothing is limited"
+            );
+        }
+        tracing::info!("Data quotas enabled ({:?})", limits);
+        builder = builder.quota(Quota::new(config.quota_backend()?, limits).await?);
+    }
+
+    if config.usage_report {
+        let mut stats = UsageStats::new();
+        match &config.admin_token {
+            Some(token) => stats = stats.with_admin_token(token),
+            None if !config.auth_enabled => tracing::warn!(
+                "/metrics and /admin/usage are open to anyone; set --admin-token to protect them"
+            ),
+            None => {}
+        }
+        tracing::info!("Usage reporting enabled");
+        builder = builder.usage_stats(Arc::new(stats));
+    }
+
+    if let Some(token) = &config.admin_token {
+        let report = ConfigReport::from_args(std::env::args_os())?;
+        builder = builder.config_report(Arc::new(report), token);
+    }
+
+    if let Some(dir) = &config.jobs_dir {
+        let jobs = Jobs::open(dir, config.max_running_jobs)
+            .await?
+            .with_retention(Duration::from_secs(config.jobs_retention));
+        #[cfg(feature = "s3")]
+        let jobs = match &config.jobs_s3_bucket {
+            Some(bucket) => {
+                tracing::info!("Uploading export job results to s3://{}", bucket);
+                let credentials = S3Credentials {
+                    profile: config.s3_profile.clone(),
+                    role_arn: config.s3_role_arn.clone(),
+                    role_session_name: config.s3_role_session_name.clone(),
+                    external_id: config.s3_external_id.clone(),
+                    web_identity_token_file: config.s3_web_identity_token_file.clone(),
+                    anonymous: false,
+                };
+                let upload = S3Upload::new(
+                    bucket.clone(),
+                    config.jobs_s3_prefix.clone(),
+                    config.s3_region.clone(),
+                    config.s3_endpoint.clone(),
+                    &credentials,
+                    config.presigned_url_expiry,
+                )
+                .await;
+                jobs.with_s3_upload(upload)
+            }
+            None => jobs,
+        };
+        tracing::info!("Export jobs enabled in {}", dir.display());
+        let jobs = Arc::new(jobs);
+        jobs.spawn_cleanup();
+        builder = builder.jobs(jobs);
+    }
+
+    if config.expose_internal_errors {
+        tracing::warn!(
+            "--expose-internal-errors sends storage paths and backend errors to clients"
+        );
+    }
+
+    if let Some(prefix) = &config.path_prefix {
+        builder = builder.path_prefix(prefix);
+    }
+
+    if config.data_mode == DataMode::Redirect && config.storage != StorageType::S3 {
+        tracing::warn!("--data-mode redirect only applies to S3 storage; proxying data");
+    }
+
+    if config.ticket_cache_ttl > 0 {
+        builder = builder.ticket_cache(
+            Duration::from_secs(config.ticket_cache_ttl),
+            config.ticket_cache_size,
+        );
+    }
+
+    if config.ticket_url_ttl > 0 {
+        builder = builder.ticket_url_ttl(Duration::from_secs(config.ticket_url_ttl));
+    }
+
+    builder = builder.max_regions(config.max_regions);
+    if config.ticket_page_size > 0 {
+        builder = builder.ticket_page_size(config.ticket_page_size);
+    }
+
+    if config.request_timeout > 0 {
+        builder = builder.request_timeout(Duration::from_secs(config.request_timeout));
+    }
+
+    if let Some(cache_control) = &config.cache_control {
+        builder = builder.cache_control(cache_control.clone());
+    }
+
+    if let Some(options) = config.cors_options() {
+        builder = builder.cors_options(options);
+    }
+
+    if let Some(proxies) = &config.trusted_proxies {
+        builder = builder.trusted_proxies(proxies.parse()?);
+    }
+
+    #[cfg(feature = "jws")]
+    if let Some(signer) = config.ticket_signer()? {
+        tracing::info!(
+            "Signing tickets with {} key {} ({})",
+            signer.algorithm(),
+            signer.key_id(),
+            config.ticket_signature
+        );
+        builder = builder.sign_tickets(signer);
+    }
 
-    // Add auth middleware if enabled
     #[cfg(feature = "auth")]
-    let app = if config.auth_enabled {
-        let auth_config = Arc::new(build_auth_config(&config, url_signer)?);
-        // Extension must be added before middleware so middleware can extract it
-        app.layer(axum::Extension(auth_config))
-            .layer(axum::middleware::from_fn(
-                |req: axum::extract::Request, next: axum::middleware::Next| async move {
-                    auth_middleware(req, next).await
-                },
-            ))
+    let builder = if config.auth_enabled {
+        builder.auth(AuthOptions {
+            issuer: config.auth_issuer.clone(),
+            audience: config.auth_audience.clone(),
+            jwks_url: config.auth_jwks_url.clone(),
+            public_key_pem: config.auth_public_key.clone(),
+            hmac_secret: config
+                .auth_hmac_secret
+                .as_ref()
+                .map(|s| s.as_bytes().to_vec()),
+            algorithms: config
+                .auth_algorithms
+                .as_deref()
+                .map(|s| s.split(',').map(str::to_string).collect())
+                .unwrap_or_default(),
+            public_endpoints: config
+                .auth_public_endpoints
+                .split(',')
+                .map(str::to_string)
+                .collect(),
+            data_url_secret: match &config.data_url_secret_file {
+                Some(path) => Some(htsgetr::auth::UrlSigner::secret_from_file(path)?),
+                None => config
+                    .data_url_secret
+                    .as_ref()
+                    .map(|s| s.as_bytes().to_vec()),
+            },
+            data_url_key_id: config.data_url_key_id.clone(),
+            data_url_previous_secret: config
+                .data_url_previous_secret
+                .as_ref()
+                .map(|s| s.as_bytes().to_vec()),
+            data_url_previous_key_id: config.data_url_previous_key_id.clone(),
+            require_data_url_secret: config.is_multi_replica(),
+            data_url_rotation_grace: config.data_url_rotation_grace,
+            data_url_expiry: config.data_url_expiry,
+            introspection_url: config.auth_introspection_url.clone(),
+            client_id: config.auth_client_id.clone(),
+            client_secret: config.auth_client_secret.clone(),
+            introspection_cache_ttl: config.auth_introspection_cache_ttl,
+            policy: config
+                .auth_policy
+                .as_deref()
+                .map(htsgetr::auth::Policy::from_file)
+                .transpose()?,
+        })
     } else {
-        app
+        builder
     };
 
-    let app = app.layer(TraceLayer::new_for_http());
+    #[cfg(feature = "auth")]
+    let builder = match shared {
+        Some(cache) => builder.shared_cache(cache),
+        None => builder,
+    };
+
+    // Build refget checksum index from local FASTA files
+    #[cfg(feature = "refget")]
+    let builder = match config.storage {
+        StorageType::Local => builder.refget(Arc::new(
+            htsgetr::refget::RefgetIndex::build(refget_storage.as_ref()).await?,
+        )),
+        _ => {
+            tracing::warn!("refget endpoints are only supported with local storage");
+            builder
+        }
+    };
+
+    let app = builder.build()?;
 
-    let app = if config.cors {
-        app.layer(CorsLayer::permissive())
+    #[cfg(feature = "http")]
+    let app = if config.storage == StorageType::Http && config.http_forward_headers.is_some() {
+        app.layer(axum::middleware::from_fn(forward_request_headers))
     } else {
         app
     };
 
-    let addr = format!("{}:{}", config.host, config.port);
-    tracing::info!("Starting htsgetr server on {}", addr);
-    tracing::info!("Data directory: {:?}", config.data_dir);
+    Ok(app)
+}
 
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+/// Make the client's request headers available to HTTP storage, which
+/// forwards the configured ones to the origin.
+#[cfg(feature = "http")]
+async fn forward_request_headers(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let headers = request.headers().clone();
+    htsgetr::storage::with_request_headers(headers, next.run(request)).await
+}
+
+/// Validate the data directory (`htsgetr check`).
+async fn run_check(config: &Config, args: &CheckArgs) -> anyhow::Result<()> {
+    use htsgetr::check::CheckSummary;
+
+    let dir = args.path.as_ref().unwrap_or(&config.data_dir);
+    let checks = htsgetr::check::check_directory(dir).await?;
+
+    for check in &checks {
+        let status = if check.is_ok() { "ok" } else { "FAIL" };
+        println!("{:<5} {:?} {}", status, check.format, check.path.display());
+
+        for error in &check.errors {
+            println!("      error: {}", error);
+        }
+        for warning in &check.warnings {
+            println!("      warning: {}", warning);
+        }
+    }
+
+    let summary = CheckSummary::from_checks(&checks);
+    println!("{}", summary);
+
+    if summary.failed > 0 {
+        anyhow::bail!("data directory check failed");
+    }
 
     Ok(())
 }
 
-/// Build AuthConfig from Config settings.
-#[cfg(feature = "auth")]
-fn build_auth_config(config: &Config, url_signer: Option<UrlSigner>) -> anyhow::Result<AuthConfig> {
-    use htsgetr::auth::{KeyProvider, StaticKeyProvider, jwks::JwksKeyProvider};
-    use std::collections::HashSet;
-
-    // Determine key provider
-    let key_provider: Arc<dyn KeyProvider> = if let Some(ref pem) = config.auth_public_key {
-        // Static PEM key
-        tracing::info!("Using static public key for JWT validation");
-        Arc::new(StaticKeyProvider::from_rsa_pem(pem.as_bytes())?)
-    } else if let Some(ref jwks_url) = config.auth_jwks_url {
-        // Explicit JWKS URL
-        tracing::info!("Using JWKS endpoint: {}", jwks_url);
-        Arc::new(JwksKeyProvider::new(jwks_url.clone()))
-    } else if let Some(ref issuer) = config.auth_issuer {
-        // Derive JWKS URL from issuer
-        tracing::info!("Using JWKS from issuer: {}", issuer);
-        Arc::new(JwksKeyProvider::from_issuer(issuer))
-    } else {
+/// Validate local data files before serving (`HTSGET_PREFLIGHT=true`).
+async fn preflight(config: &Config) -> anyhow::Result<()> {
+    use htsgetr::check::CheckSummary;
+
+    if config.storage != StorageType::Local {
+        tracing::warn!("preflight checks are only supported with local storage; skipping");
+        return Ok(());
+    }
+
+    let checks = htsgetr::check::check_directory(&config.data_dir).await?;
+
+    for check in &checks {
+        for error in &check.errors {
+            tracing::error!("{}: {}", check.path.display(), error);
+        }
+        for warning in &check.warnings {
+            tracing::warn!("{}: {}", check.path.display(), warning);
+        }
+    }
+
+    let summary = CheckSummary::from_checks(&checks);
+    tracing::info!("Preflight: {}", summary);
+
+    if summary.failed > 0 {
         anyhow::bail!(
-            "Auth enabled but no key source configured. Set HTSGET_AUTH_ISSUER, \
-             HTSGET_AUTH_JWKS_URL, or HTSGET_AUTH_PUBLIC_KEY"
+            "preflight failed for {} file(s) in {:?}",
+            summary.failed,
+            config.data_dir
         );
+    }
+
+    Ok(())
+}
+
+/// Measure ticket latency through the full router (`htsgetr bench`).
+async fn run_bench(config: &Config, args: &BenchArgs) -> anyhow::Result<()> {
+    use axum::body::Body;
+    use axum::http::Request;
+    use htsgetr::types::Region;
+    use std::time::Instant;
+    use tower::ServiceExt;
+
+    let app = build_app(config).await?;
+
+    let mut params = url::form_urlencoded::Serializer::new(String::new());
+    if let Some(region) = &args.region {
+        let region: Region = region.parse()?;
+        params.append_pair("referenceName", &region.reference_name);
+        if let Some(start) = region.start {
+            params.append_pair("start", &start.to_string());
+        }
+        if let Some(end) = region.end {
+            params.append_pair("end", &end.to_string());
+        }
+    }
+    let uri = format!("/{}/{}?{}", args.endpoint, args.id, params.finish());
+
+    let mut timings: Vec<Duration> = Vec::with_capacity(args.iterations);
+    for _ in 0..args.iterations {
+        let request = Request::builder().uri(&uri).body(Body::empty())?;
+
+        let started = Instant::now();
+        let response = app.clone().oneshot(request).await?;
+        timings.push(started.elapsed());
+
+        if !response.status().is_success() {
+            anyhow::bail!("{} returned {}", uri, response.status());
+        }
+    }
+
+    if timings.is_empty() {
+        return Ok(());
+    }
+
+    timings.sort();
+    let percentile = |p: f64| timings[((timings.len() - 1) as f64 * p).round() as usize];
+    let mean = timings.iter().sum::<Duration>() / timings.len() as u32;
+
+    println!("{} requests to {}", timings.len(), uri);
+    println!("  min  {:?}", timings[0]);
+    println!("  mean {:?}", mean);
+    println!("  p50  {:?}", percentile(0.50));
+    println!("  p95  {:?}", percentile(0.95));
+    println!("  p99  {:?}", percentile(0.99));
+    println!("  max  {:?}", timings[timings.len() - 1]);
+
+    Ok(())
+}
+
+/// Generate missing indexes for a file or directory (`htsgetr index`).
+async fn run_index(args: &IndexArgs) -> anyhow::Result<()> {
+    use htsgetr::indexer::{IndexOutcome, index_directory, index_file};
+
+    let results = if args.path.is_dir() {
+        index_directory(&args.path, args.force).await?
+    } else {
+        vec![(args.path.clone(), index_file(&args.path, args.force).await)]
+    };
+
+    let mut failed = 0;
+    for (path, outcome) in results {
+        match outcome {
+            Ok(IndexOutcome::Created(index)) => println!("created  {}", index.display()),
+            Ok(IndexOutcome::Exists(index)) => println!("exists   {}", index.display()),
+            Ok(IndexOutcome::Unsupported(reason)) => {
+                println!("skipped  {} ({})", path.display(), reason)
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("failed   {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} file(s) failed to index", failed);
+    }
+
+    Ok(())
+}
+
+/// Write an example config file (`htsgetr init`).
+fn run_init(args: &InitArgs) -> anyhow::Result<()> {
+    let example = ConfigFile::example();
+    match &args.output {
+        Some(path) if path.exists() && !args.force => {
+            anyhow::bail!("{} exists; pass --force to overwrite it", path.display())
+        }
+        Some(path) => {
+            std::fs::write(path, example)?;
+            eprintln!("wrote {}", path.display());
+        }
+        None => print!("{}", example),
+    }
+    Ok(())
+}
+
+/// Print shell completions (`htsgetr completions`).
+fn run_completions(args: &CompletionsArgs) -> anyhow::Result<()> {
+    clap_complete::generate(
+        args.shell,
+        &mut Config::command(),
+        "htsgetr",
+        &mut std::io::stdout(),
+    );
+    Ok(())
+}
+
+/// Download a slice from an htsget server (`htsgetr fetch`).
+#[cfg(feature = "client")]
+async fn run_fetch(args: &FetchArgs) -> anyhow::Result<()> {
+    use htsgetr::client::{HtsgetClient, TicketRequest};
+    use htsgetr::types::DataClass;
+
+    let mut client = HtsgetClient::try_new(&args.url)?.with_retries(args.retries);
+    if let Some(token) = &args.token {
+        client = client.with_bearer_token(token);
+    }
+
+    let request = TicketRequest {
+        format: args.format.as_deref().map(str::parse).transpose()?,
+        class: args.header_only.then_some(DataClass::Header),
+        region: args.region.as_deref().map(str::parse).transpose()?,
+    };
+
+    let written = match &args.output {
+        Some(path) => {
+            let mut file = tokio::fs::File::create(path).await?;
+            client
+                .download(&args.endpoint, &args.id, &request, &mut file)
+                .await?
+        }
+        None => {
+            let mut stdout = tokio::io::stdout();
+            client
+                .download(&args.endpoint, &args.id, &request, &mut stdout)
+                .await?
+        }
     };
 
-    // Parse public endpoints
-    let public_paths: HashSet<String> = config
-        .auth_public_endpoints
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .collect();
-
-    tracing::info!("Public endpoints (no auth required): {:?}", public_paths);
-
-    Ok(AuthConfig {
-        enabled: true,
-        key_provider,
-        issuer: config.auth_issuer.clone(),
-        audience: config.auth_audience.clone(),
-        public_paths,
-        url_signer,
-    })
+    tracing::info!("Fetched {} bytes for {}", written, args.id);
+
+    Ok(())
+}
+
+#[cfg(not(feature = "client"))]
+async fn run_fetch(_args: &FetchArgs) -> anyhow::Result<()> {
+    anyhow::bail!(
+        "fetch requires the 'client' feature to be enabled. Rebuild with: cargo build --features client"
+    )
+}
+
+/// Run htsget conformance cases (`htsgetr conformance`).
+#[cfg(feature = "client")]
+async fn run_conformance(config: &Config, args: &ConformanceArgs) -> anyhow::Result<()> {
+    use htsgetr::conformance::{Conformance, Fixtures};
+
+    let url = match &args.url {
+        Some(url) => url.clone(),
+        None => {
+            // Serve the configured storage locally; tickets must point at the
+            // ephemeral port, so the base URL is derived from it
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+            let mut local = config.clone();
+            local.host = "127.0.0.1".to_string();
+            local.port = listener.local_addr()?.port();
+            local.base_url = None;
+            local.tls_cert = None;
+            local.tls_key = None;
+
+            let app = build_app(&local).await?;
+            tokio::spawn(async move {
+                axum::serve(
+                    listener,
+                    app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+                )
+                .await
+            });
+            local.effective_base_url()
+        }
+    };
+
+    let fixtures = Fixtures {
+        reads_id: Some(args.reads_id.clone()),
+        cram_id: args.cram_id.clone(),
+        variants_id: Some(args.variants_id.clone()),
+        reference_name: args.reference_name.clone(),
+    };
+    let mut conformance = Conformance::new(&url, fixtures)?;
+    if let Some(token) = &args.token {
+        conformance = conformance.with_bearer_token(token);
+    }
+
+    tracing::info!("Running conformance cases against {}", url);
+    let report = conformance.run().await;
+    println!("{}", report);
+
+    if report.failed() > 0 {
+        anyhow::bail!("{} conformance case(s) failed", report.failed());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "client"))]
+async fn run_conformance(_config: &Config, _args: &ConformanceArgs) -> anyhow::Result<()> {
+    anyhow::bail!(
+        "conformance requires the 'client' feature to be enabled. Rebuild with: cargo build --features client"
+    )
 }
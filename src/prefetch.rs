@@ -0,0 +1,111 @@
+//! Index cache warming.
+//!
+//! Remote backends download an index the first time a sample is queried,
+//! which can dominate that query's latency. [`prefetch_indexes`] lists the
+//! storage backend and fetches and parses the indexes of the most recently
+//! modified samples ahead of time. The server runs it in the background at
+//! startup when `HTSGET_PREFETCH_INDEXES=true`.
+
+use crate::Result;
+use crate::formats;
+use crate::storage::Storage;
+use crate::types::Format;
+use futures::stream::{self, StreamExt};
+
+/// Indexes fetched concurrently
+const PREFETCH_CONCURRENCY: usize = 4;
+
+/// Outcome of a prefetch run
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PrefetchSummary {
+    /// Samples whose index is cached and parses
+    pub prefetched: usize,
+    /// Samples without an index
+    pub missing: usize,
+    /// Samples whose index couldn't be fetched or parsed
+    pub failed: usize,
+}
+
+impl std::fmt::Display for PrefetchSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} index(es) prefetched, {} missing, {} failed",
+            self.prefetched, self.missing, self.failed
+        )
+    }
+}
+
+/// Fetch and parse the indexes of the `limit` most recently modified data
+/// files in `storage`. Failures are logged and counted, not returned; only
+/// listing the storage can fail.
+pub async fn prefetch_indexes(storage: &dyn Storage, limit: usize) -> Result<PrefetchSummary> {
+    let mut files = storage.list().await?;
+    files.retain(|file| file.format != Format::Fastq);
+    // Newest first; files without a modification time last
+    files.sort_by(|a, b| b.modified.cmp(&a.modified));
+    files.truncate(limit);
+
+    let results: Vec<_> = stream::iter(files)
+        .map(|file| async move {
+            let result = match storage.index_path(&file.id, file.format).await {
+                Ok(Some(index_path)) => formats::read_index(file.format, &index_path)
+                    .await
+                    .map(|()| true),
+                Ok(None) => Ok(false),
+                Err(e) => Err(e),
+            };
+            (file, result)
+        })
+        .buffer_unordered(PREFETCH_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut summary = PrefetchSummary::default();
+    for (file, result) in results {
+        match result {
+            Ok(true) => summary.prefetched += 1,
+            Ok(false) => summary.missing += 1,
+            Err(e) => {
+                tracing::warn!(id = %file.id, format = ?file.format, "index prefetch failed: {}", e);
+                summary.failed += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalStorage;
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn test_prefetch_local_indexes() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+        for name in ["sample.bam", "sample.bam.bai", "sample.vcf.gz", "mt.bam"] {
+            std::fs::copy(data.join(name), dir.path().join(name)).unwrap();
+        }
+        // An index that doesn't parse
+        std::fs::copy(data.join("sample.vcf.gz"), dir.path().join("broken.vcf.gz")).unwrap();
+        std::fs::write(dir.path().join("broken.vcf.gz.tbi"), b"not an index").unwrap();
+
+        let storage = LocalStorage::new(dir.path().to_path_buf(), "http://localhost".into());
+
+        let summary = prefetch_indexes(&storage, 10).await.unwrap();
+        assert_eq!(
+            summary,
+            PrefetchSummary {
+                prefetched: 1,
+                missing: 2,
+                failed: 1,
+            }
+        );
+
+        let summary = prefetch_indexes(&storage, 0).await.unwrap();
+        assert_eq!(summary, PrefetchSummary::default());
+    }
+}
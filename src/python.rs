@@ -20,9 +20,79 @@ use crate::storage::Storage;
 fn htsgetr(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<HtsgetServer>()?;
     m.add_class::<HtsgetClient>()?;
+    m.add_class::<AsyncHtsgetClient>()?;
+    m.add_function(wrap_pyfunction!(query_ranges, m)?)?;
     Ok(())
 }
 
+/// Compute the byte ranges for a region of a local file, without running a server
+///
+/// Returns a dict with `format`, `header` (a `(start, end)` tuple) and `ranges`
+/// (a list of `(start, end)` tuples; `end` is exclusive and `None` means end of
+/// file; empty when no region is given, meaning the whole file). The format is detected from the file name unless given, and the index
+/// is located next to the file unless `index_path` is set.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (path, format=None, region=None, index_path=None))]
+fn query_ranges(
+    py: Python<'_>,
+    path: PathBuf,
+    format: Option<String>,
+    region: Option<String>,
+    index_path: Option<PathBuf>,
+) -> PyResult<PyObject> {
+    use crate::types::{Format, Region};
+    use pyo3::types::PyDict;
+
+    let to_py_err = |e: crate::Error| match e {
+        crate::Error::NotFound(_) => pyo3::exceptions::PyFileNotFoundError::new_err(e.to_string()),
+        e => pyo3::exceptions::PyValueError::new_err(e.to_string()),
+    };
+
+    let format: Option<Format> = format
+        .as_deref()
+        .map(str::parse)
+        .transpose()
+        .map_err(to_py_err)?;
+    let regions: Vec<Region> = region
+        .as_deref()
+        .map(str::parse)
+        .transpose()
+        .map_err(to_py_err)?
+        .into_iter()
+        .collect();
+
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?;
+    let (format, ranges) = py
+        .allow_threads(|| {
+            rt.block_on(crate::formats::query_file(
+                &path,
+                format,
+                index_path.as_deref(),
+                &regions,
+            ))
+        })
+        .map_err(to_py_err)?;
+
+    let result = PyDict::new_bound(py);
+    result.set_item("format", format!("{:?}", format).to_uppercase())?;
+    result.set_item(
+        "header",
+        (ranges.header_range.start, ranges.header_range.end),
+    )?;
+    result.set_item(
+        "ranges",
+        ranges
+            .data_ranges
+            .iter()
+            .map(|r| (r.start, r.end))
+            .collect::<Vec<_>>(),
+    )?;
+
+    Ok(result.into())
+}
+
 /// htsget server that can be started from Python
 ///
 /// Supports local filesystem, S3, and HTTP storage backends.
@@ -44,16 +114,35 @@ pub struct HtsgetServer {
     // Common options
     cache_dir: PathBuf,
     presigned_url_expiry: u64,
+    // Authentication (requires the `auth` feature)
+    #[cfg(feature = "auth")]
+    auth: Option<crate::server::AuthOptions>,
+    // Set while started in the background
+    running: Option<RunningServer>,
 }
 
 #[cfg(feature = "python")]
 #[pymethods]
 impl HtsgetServer {
     /// Create a new htsget server with local storage
+    ///
+    /// Setting `issuer`, `jwks_url` or `public_key_pem` enables JWT auth and
+    /// data URL signing (requires the `auth` feature).
     #[new]
-    #[pyo3(signature = (data_dir, host="0.0.0.0".to_string(), port=8080))]
-    fn new(data_dir: String, host: String, port: u16) -> Self {
-        Self {
+    #[pyo3(signature = (data_dir, host="0.0.0.0".to_string(), port=8080, issuer=None, jwks_url=None, public_key_pem=None, audience=None, public_endpoints=None, data_url_secret=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        data_dir: String,
+        host: String,
+        port: u16,
+        issuer: Option<String>,
+        jwks_url: Option<String>,
+        public_key_pem: Option<String>,
+        audience: Option<String>,
+        public_endpoints: Option<Vec<String>>,
+        data_url_secret: Option<String>,
+    ) -> PyResult<Self> {
+        let mut server = Self {
             host,
             port,
             data_dir: Some(PathBuf::from(data_dir)),
@@ -65,7 +154,23 @@ impl HtsgetServer {
             http_index_base_url: None,
             cache_dir: PathBuf::from("/tmp/htsgetr-cache"),
             presigned_url_expiry: 3600,
+            #[cfg(feature = "auth")]
+            auth: None,
+            running: None,
+        };
+
+        if issuer.is_some() || jwks_url.is_some() || public_key_pem.is_some() {
+            server.enable_auth(
+                issuer,
+                jwks_url,
+                public_key_pem,
+                audience,
+                public_endpoints,
+                data_url_secret,
+            )?;
         }
+
+        Ok(server)
     }
 
     /// Create a new htsget server with S3 storage
@@ -93,6 +198,9 @@ impl HtsgetServer {
             http_index_base_url: None,
             cache_dir: PathBuf::from(cache_dir),
             presigned_url_expiry,
+            #[cfg(feature = "auth")]
+            auth: None,
+            running: None,
         }
     }
 
@@ -118,120 +226,142 @@ impl HtsgetServer {
             http_index_base_url: index_base_url,
             cache_dir: PathBuf::from(cache_dir),
             presigned_url_expiry: 3600,
+            #[cfg(feature = "auth")]
+            auth: None,
+            running: None,
+        }
+    }
+
+    /// Enable JWT auth and data URL signing (requires the `auth` feature).
+    ///
+    /// Use this for servers created with `with_s3()` / `with_http()`; must be
+    /// called before `start()` or `run()`.
+    #[pyo3(signature = (issuer=None, jwks_url=None, public_key_pem=None, audience=None, public_endpoints=None, data_url_secret=None))]
+    fn enable_auth(
+        &mut self,
+        issuer: Option<String>,
+        jwks_url: Option<String>,
+        public_key_pem: Option<String>,
+        audience: Option<String>,
+        public_endpoints: Option<Vec<String>>,
+        data_url_secret: Option<String>,
+    ) -> PyResult<()> {
+        #[cfg(feature = "auth")]
+        {
+            let defaults = crate::server::AuthOptions::default();
+            self.auth = Some(crate::server::AuthOptions {
+                issuer,
+                audience,
+                jwks_url,
+                public_key_pem,
+                public_endpoints: public_endpoints.unwrap_or(defaults.public_endpoints),
+                data_url_secret: data_url_secret.map(String::into_bytes),
+                ..defaults
+            });
+            Ok(())
+        }
+        #[cfg(not(feature = "auth"))]
+        {
+            let _ = (
+                issuer,
+                jwks_url,
+                public_key_pem,
+                audience,
+                public_endpoints,
+                data_url_secret,
+            );
+            Err(pyo3::exceptions::PyRuntimeError::new_err(
+                "authentication requires the 'auth' feature to be enabled",
+            ))
         }
     }
 
     /// Start the server (blocking)
-    fn run(&self) -> PyResult<()> {
-        use tower_http::{cors::CorsLayer, trace::TraceLayer};
+    fn run(&mut self, py: Python<'_>) -> PyResult<()> {
+        self.start()?;
+        let running = self.running.take().expect("server was just started");
 
-        use crate::handlers::{AppState, create_router};
-        use crate::storage::LocalStorage;
+        py.allow_threads(|| running.runtime.block_on(running.handle))
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+    }
 
-        let rt = tokio::runtime::Runtime::new()
+    /// Start the server in the background, returning once the listener is bound.
+    ///
+    /// With `port=0` an ephemeral port is chosen; `url()` and `port` reflect it afterwards.
+    fn start(&mut self) -> PyResult<()> {
+        if self.running.is_some() {
+            return Err(pyo3::exceptions::PyRuntimeError::new_err(
+                "server is already running",
+            ));
+        }
+
+        let runtime = tokio::runtime::Runtime::new()
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?;
 
-        let host = self.host.clone();
-        let port = self.port;
-        let base_url = format!("http://{}:{}", host, port);
-
-        // Clone config for async block
-        let data_dir = self.data_dir.clone();
-        let s3_bucket = self.s3_bucket.clone();
-        let s3_region = self.s3_region.clone();
-        let s3_prefix = self.s3_prefix.clone();
-        let s3_endpoint = self.s3_endpoint.clone();
-        let http_base_url = self.http_base_url.clone();
-        let http_index_base_url = self.http_index_base_url.clone();
-        let cache_dir = self.cache_dir.clone();
-        let presigned_url_expiry = self.presigned_url_expiry;
-
-        rt.block_on(async move {
-            // Initialize tracing (basic)
-            let _ = tracing_subscriber::fmt::try_init();
-
-            // Create storage backend based on configuration
-            let storage: Arc<dyn Storage> = if let Some(bucket) = s3_bucket {
-                #[cfg(feature = "s3")]
-                {
-                    use crate::storage::S3Storage;
-                    tracing::info!("Using S3 storage backend: bucket={}", bucket);
-                    Arc::new(
-                        S3Storage::new(
-                            bucket,
-                            s3_prefix,
-                            cache_dir,
-                            presigned_url_expiry,
-                            s3_region,
-                            s3_endpoint,
-                        )
-                        .await
-                        .map_err(|e| {
-                            pyo3::exceptions::PyRuntimeError::new_err(format!(
-                                "Failed to create S3 storage: {}",
-                                e
-                            ))
-                        })?,
-                    )
-                }
-                #[cfg(not(feature = "s3"))]
-                {
-                    return Err(pyo3::exceptions::PyRuntimeError::new_err(
-                        "S3 storage requires the 's3' feature to be enabled",
-                    ));
-                }
-            } else if let Some(base_url_http) = http_base_url {
-                #[cfg(feature = "http")]
-                {
-                    use crate::storage::HttpStorage;
-                    tracing::info!("Using HTTP storage backend: base_url={}", base_url_http);
-                    Arc::new(
-                        HttpStorage::new(base_url_http, http_index_base_url, cache_dir)
-                            .await
-                            .map_err(|e| {
-                                pyo3::exceptions::PyRuntimeError::new_err(format!(
-                                    "Failed to create HTTP storage: {}",
-                                    e
-                                ))
-                            })?,
-                    )
-                }
-                #[cfg(not(feature = "http"))]
-                {
-                    return Err(pyo3::exceptions::PyRuntimeError::new_err(
-                        "HTTP storage requires the 'http' feature to be enabled",
-                    ));
-                }
-            } else if let Some(data_dir) = data_dir {
-                tracing::info!("Using local storage backend: {:?}", data_dir);
-                Arc::new(LocalStorage::new(data_dir, base_url.clone()))
-            } else {
-                return Err(pyo3::exceptions::PyRuntimeError::new_err(
-                    "Either data_dir, s3_bucket, or http_base_url must be specified",
-                ));
-            };
+        let (listener, app) = runtime.block_on(self.bind())?;
+        let (shutdown, rx) = tokio::sync::oneshot::channel::<()>();
+
+        let handle = runtime.spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+            )
+            .with_graceful_shutdown(async {
+                let _ = rx.await;
+            })
+            .await
+        });
+
+        self.running = Some(RunningServer {
+            runtime,
+            shutdown,
+            handle,
+        });
+
+        Ok(())
+    }
 
-            let state = AppState {
-                storage,
-                base_url: base_url.clone(),
-            };
+    /// Stop a server started with `start()`, waiting for in-flight requests to finish
+    fn stop(&mut self, py: Python<'_>) -> PyResult<()> {
+        let Some(running) = self.running.take() else {
+            return Ok(());
+        };
 
-            // Build router using centralized definition
-            let app = create_router(state)
-                .layer(TraceLayer::new_for_http())
-                .layer(CorsLayer::permissive());
+        let _ = running.shutdown.send(());
+        py.allow_threads(|| running.runtime.block_on(running.handle))
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+    }
 
-            let addr = format!("{}:{}", host, port);
-            tracing::info!("Starting htsgetr server on {}", addr);
+    /// Check if the server was started with `start()` and not yet stopped
+    fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
 
-            let listener = tokio::net::TcpListener::bind(&addr)
-                .await
-                .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?;
+    /// The port the server listens on (the bound port once started with `port=0`)
+    #[getter]
+    fn port(&self) -> u16 {
+        self.port
+    }
 
-            axum::serve(listener, app)
-                .await
-                .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
-        })
+    fn __enter__(mut slf: PyRefMut<'_, Self>) -> PyResult<PyRefMut<'_, Self>> {
+        if slf.running.is_none() {
+            slf.start()?;
+        }
+        Ok(slf)
+    }
+
+    #[pyo3(signature = (_exc_type=None, _exc_value=None, _traceback=None))]
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        _exc_type: Option<PyObject>,
+        _exc_value: Option<PyObject>,
+        _traceback: Option<PyObject>,
+    ) -> PyResult<bool> {
+        self.stop(py)?;
+        Ok(false)
     }
 
     /// Get the server URL
@@ -250,6 +380,121 @@ impl HtsgetServer {
     }
 }
 
+/// A server started with `start()`, running on its own tokio runtime
+#[cfg(feature = "python")]
+struct RunningServer {
+    runtime: tokio::runtime::Runtime,
+    shutdown: tokio::sync::oneshot::Sender<()>,
+    handle: tokio::task::JoinHandle<std::io::Result<()>>,
+}
+
+#[cfg(feature = "python")]
+impl HtsgetServer {
+    /// Bind the listener, then build the router.
+    ///
+    /// Binding first lets `port=0` resolve to the real port before ticket
+    /// base URLs are derived from it.
+    async fn bind(&mut self) -> PyResult<(tokio::net::TcpListener, axum::Router)> {
+        use crate::server::ServerBuilder;
+        use crate::storage::LocalStorage;
+
+        // Initialize tracing (basic)
+        let _ = tracing_subscriber::fmt::try_init();
+
+        let addr = format!("{}:{}", self.host, self.port);
+        let listener = tokio::net::TcpListener::bind(&addr)
+            .await
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?;
+        self.port = listener
+            .local_addr()
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?
+            .port();
+
+        let base_url = self.url();
+
+        // Create storage backend based on configuration
+        let storage: Arc<dyn Storage> = if let Some(bucket) = self.s3_bucket.clone() {
+            #[cfg(feature = "s3")]
+            {
+                use crate::storage::S3Storage;
+                tracing::info!("Using S3 storage backend: bucket={}", bucket);
+                Arc::new(
+                    S3Storage::new(
+                        bucket,
+                        self.s3_prefix.clone(),
+                        self.cache_dir.clone(),
+                        self.presigned_url_expiry,
+                        self.s3_region.clone(),
+                        self.s3_endpoint.clone(),
+                    )
+                    .await
+                    .map_err(|e| {
+                        pyo3::exceptions::PyRuntimeError::new_err(format!(
+                            "Failed to create S3 storage: {}",
+                            e
+                        ))
+                    })?,
+                )
+            }
+            #[cfg(not(feature = "s3"))]
+            {
+                return Err(pyo3::exceptions::PyRuntimeError::new_err(
+                    "S3 storage requires the 's3' feature to be enabled",
+                ));
+            }
+        } else if let Some(base_url_http) = self.http_base_url.clone() {
+            #[cfg(feature = "http")]
+            {
+                use crate::storage::HttpStorage;
+                tracing::info!("Using HTTP storage backend: base_url={}", base_url_http);
+                Arc::new(
+                    HttpStorage::new(
+                        base_url_http,
+                        self.http_index_base_url.clone(),
+                        self.cache_dir.clone(),
+                    )
+                    .await
+                    .map_err(|e| {
+                        pyo3::exceptions::PyRuntimeError::new_err(format!(
+                            "Failed to create HTTP storage: {}",
+                            e
+                        ))
+                    })?,
+                )
+            }
+            #[cfg(not(feature = "http"))]
+            {
+                return Err(pyo3::exceptions::PyRuntimeError::new_err(
+                    "HTTP storage requires the 'http' feature to be enabled",
+                ));
+            }
+        } else if let Some(data_dir) = self.data_dir.clone() {
+            tracing::info!("Using local storage backend: {:?}", data_dir);
+            Arc::new(LocalStorage::new(data_dir, base_url.clone()))
+        } else {
+            return Err(pyo3::exceptions::PyRuntimeError::new_err(
+                "Either data_dir, s3_bucket, or http_base_url must be specified",
+            ));
+        };
+
+        let builder = ServerBuilder::new(storage, base_url);
+
+        #[cfg(feature = "auth")]
+        let builder = match &self.auth {
+            Some(options) => builder.auth(options.clone()),
+            None => builder,
+        };
+
+        let app = builder
+            .build()
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?;
+
+        tracing::info!("Starting htsgetr server on {}:{}", self.host, self.port);
+
+        Ok((listener, app))
+    }
+}
+
 /// Client for making htsget requests
 #[cfg(feature = "python")]
 #[pyclass]
@@ -290,10 +535,108 @@ impl HtsgetClient {
     ) -> PyResult<String> {
         self.fetch_endpoint("variants", id, reference_name, start, end, format)
     }
+
+    /// Fetch reads data by following the ticket.
+    ///
+    /// Returns the assembled bytes, or writes them to `output_path` and returns `None`.
+    #[pyo3(signature = (id, reference_name=None, start=None, end=None, format=None, output_path=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn reads_data(
+        &self,
+        py: Python<'_>,
+        id: String,
+        reference_name: Option<String>,
+        start: Option<u64>,
+        end: Option<u64>,
+        format: Option<String>,
+        output_path: Option<PathBuf>,
+    ) -> PyResult<PyObject> {
+        self.fetch_data(
+            py,
+            "reads",
+            id,
+            reference_name,
+            start,
+            end,
+            format,
+            output_path,
+        )
+    }
+
+    /// Fetch variants data by following the ticket.
+    ///
+    /// Returns the assembled bytes, or writes them to `output_path` and returns `None`.
+    #[pyo3(signature = (id, reference_name=None, start=None, end=None, format=None, output_path=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn variants_data(
+        &self,
+        py: Python<'_>,
+        id: String,
+        reference_name: Option<String>,
+        start: Option<u64>,
+        end: Option<u64>,
+        format: Option<String>,
+        output_path: Option<PathBuf>,
+    ) -> PyResult<PyObject> {
+        self.fetch_data(
+            py,
+            "variants",
+            id,
+            reference_name,
+            start,
+            end,
+            format,
+            output_path,
+        )
+    }
 }
 
 #[cfg(feature = "python")]
 impl HtsgetClient {
+    /// Request a ticket and follow its URLs using the Rust client, which
+    /// handles `Range` headers, `data:` URIs, retries and md5 verification.
+    #[allow(clippy::too_many_arguments)]
+    fn fetch_data(
+        &self,
+        py: Python<'_>,
+        endpoint: &str,
+        id: String,
+        reference_name: Option<String>,
+        start: Option<u64>,
+        end: Option<u64>,
+        format: Option<String>,
+        output_path: Option<PathBuf>,
+    ) -> PyResult<PyObject> {
+        use crate::client::HtsgetClient as Client;
+
+        let request = ticket_request(reference_name, start, end, format)?;
+        let client = Client::try_new(&self.base_url).map_err(to_py_err)?;
+
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?;
+
+        let data = py
+            .allow_threads(|| {
+                rt.block_on(async {
+                    let ticket = client.ticket(endpoint, &id, &request).await?;
+                    match &output_path {
+                        Some(path) => {
+                            let mut file = tokio::fs::File::create(path).await?;
+                            client.fetch_to(&ticket, &mut file).await?;
+                            Ok(None)
+                        }
+                        None => client.fetch(&ticket).await.map(Some),
+                    }
+                })
+            })
+            .map_err(to_py_err)?;
+
+        Ok(match data {
+            Some(bytes) => pyo3::types::PyBytes::new_bound(py, &bytes).into(),
+            None => py.None(),
+        })
+    }
+
     fn fetch_endpoint(
         &self,
         endpoint: &str,
@@ -334,3 +677,155 @@ impl HtsgetClient {
         })
     }
 }
+
+#[cfg(feature = "python")]
+fn to_py_err(e: crate::Error) -> PyErr {
+    pyo3::exceptions::PyRuntimeError::new_err(e.to_string())
+}
+
+/// Build a ticket request from the keyword arguments shared by the clients
+#[cfg(feature = "python")]
+fn ticket_request(
+    reference_name: Option<String>,
+    start: Option<u64>,
+    end: Option<u64>,
+    format: Option<String>,
+) -> PyResult<crate::client::TicketRequest> {
+    use crate::types::Region;
+
+    Ok(crate::client::TicketRequest {
+        format: format
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(to_py_err)?,
+        class: None,
+        region: reference_name.map(|reference_name| Region {
+            reference_name,
+            start,
+            end,
+        }),
+    })
+}
+
+/// asyncio-compatible client for making htsget requests
+///
+/// Methods return awaitables that run on a shared tokio runtime, so they don't
+/// block the event loop.
+#[cfg(feature = "python")]
+#[pyclass]
+pub struct AsyncHtsgetClient {
+    client: crate::client::HtsgetClient,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl AsyncHtsgetClient {
+    #[new]
+    fn new(base_url: String) -> PyResult<Self> {
+        let client = crate::client::HtsgetClient::try_new(&base_url).map_err(to_py_err)?;
+        Ok(Self { client })
+    }
+
+    /// Fetch the reads ticket for a given ID (JSON string)
+    #[pyo3(signature = (id, reference_name=None, start=None, end=None, format=None))]
+    fn reads<'py>(
+        &self,
+        py: Python<'py>,
+        id: String,
+        reference_name: Option<String>,
+        start: Option<u64>,
+        end: Option<u64>,
+        format: Option<String>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = ticket_request(reference_name, start, end, format)?;
+        self.ticket(py, "reads", id, request)
+    }
+
+    /// Fetch the variants ticket for a given ID (JSON string)
+    #[pyo3(signature = (id, reference_name=None, start=None, end=None, format=None))]
+    fn variants<'py>(
+        &self,
+        py: Python<'py>,
+        id: String,
+        reference_name: Option<String>,
+        start: Option<u64>,
+        end: Option<u64>,
+        format: Option<String>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = ticket_request(reference_name, start, end, format)?;
+        self.ticket(py, "variants", id, request)
+    }
+
+    /// Fetch reads data by following the ticket (bytes)
+    #[pyo3(signature = (id, reference_name=None, start=None, end=None, format=None))]
+    fn reads_data<'py>(
+        &self,
+        py: Python<'py>,
+        id: String,
+        reference_name: Option<String>,
+        start: Option<u64>,
+        end: Option<u64>,
+        format: Option<String>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = ticket_request(reference_name, start, end, format)?;
+        self.data(py, "reads", id, request)
+    }
+
+    /// Fetch variants data by following the ticket (bytes)
+    #[pyo3(signature = (id, reference_name=None, start=None, end=None, format=None))]
+    fn variants_data<'py>(
+        &self,
+        py: Python<'py>,
+        id: String,
+        reference_name: Option<String>,
+        start: Option<u64>,
+        end: Option<u64>,
+        format: Option<String>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = ticket_request(reference_name, start, end, format)?;
+        self.data(py, "variants", id, request)
+    }
+}
+
+#[cfg(feature = "python")]
+impl AsyncHtsgetClient {
+    fn ticket<'py>(
+        &self,
+        py: Python<'py>,
+        endpoint: &'static str,
+        id: String,
+        request: crate::client::TicketRequest,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let client = self.client.clone();
+
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            let ticket = client
+                .ticket(endpoint, &id, &request)
+                .await
+                .map_err(to_py_err)?;
+            serde_json::to_string(&ticket)
+                .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+        })
+    }
+
+    fn data<'py>(
+        &self,
+        py: Python<'py>,
+        endpoint: &'static str,
+        id: String,
+        request: crate::client::TicketRequest,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let client = self.client.clone();
+
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            let ticket = client
+                .ticket(endpoint, &id, &request)
+                .await
+                .map_err(to_py_err)?;
+            let bytes = client.fetch(&ticket).await.map_err(to_py_err)?;
+
+            Python::with_gil(|py| Ok(pyo3::types::PyBytes::new_bound(py, &bytes).unbind()))
+        })
+    }
+}
@@ -0,0 +1,491 @@
+//! Per-subject quotas on the data endpoint.
+//!
+//! Shared servers can cap how much each caller fetches. A [`Quota`] counts
+//! the requests and bytes each authenticated subject is served from `/data`,
+//! `/download` and export job results in fixed windows (e.g. per day), and
+//! refuses further data requests with `PermissionDenied` once a limit is
+//! reached. Subjects come from the signed data URLs of their tickets;
+//! unauthenticated requests aren't counted.
+//!
+//! Counters are kept in a [`QuotaStore`]:
+//!
+//! | Store | Spec | Scope |
+//! |-------|------|-------|
+//! | Memory | `memory` | Per process, reset on restart |
+//! | Redis | `redis://host:6379` | Shared by all replicas (requires `redis` feature) |
+//!
+//! Limits are checked before a request is served, so the request that crosses
+//! the byte limit is still served in full. Streamed responses without a
+//! `Content-Length`, such as precise slices, are counted as they're sent. In
+//! redirect mode the bytes come straight from storage, and only requests are
+//! counted. If the store can't
+//! be reached, requests are served and the error is logged.
+
+use crate::audit::Subject;
+use crate::jobs::JOBS_PATH;
+#[cfg(feature = "redis")]
+use crate::shared::{KEY_PREFIX, Redis};
+use crate::{Error, Result};
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{Method, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Limits applied to each subject per window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaLimits {
+    /// Data requests per window
+    pub requests: Option<u64>,
+    /// Bytes served per window
+    pub bytes: Option<u64>,
+    /// Window length; windows are aligned to the Unix epoch
+    pub window: Duration,
+}
+
+impl QuotaLimits {
+    /// Why `usage` is over these limits, if it is.
+    fn exceeded(&self, usage: Usage) -> Option<String> {
+        let window = self.window.as_secs();
+        if let Some(limit) = self.requests.filter(|&limit| usage.requests >= limit) {
+            return Some(format!(
+                "request quota of {} per {}s exceeded",
+                limit, window
+            ));
+        }
+        if let Some(limit) = self.bytes.filter(|&limit| usage.bytes >= limit) {
+            return Some(format!("byte quota of {} per {}s exceeded", limit, window));
+        }
+        None
+    }
+}
+
+/// A subject's usage in one window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub requests: u64,
+    pub bytes: u64,
+}
+
+/// Where usage counters are kept
+#[async_trait]
+pub trait QuotaStore: Send + Sync {
+    /// Usage of `subject` in window number `window`.
+    async fn usage(&self, subject: &str, window: u64) -> Result<Usage>;
+
+    /// Add one request of `bytes` to `subject`'s usage in window number
+    /// `window`, which lasts `length`.
+    async fn record(&self, subject: &str, window: u64, length: Duration, bytes: u64) -> Result<()>;
+}
+
+/// Counters in process memory. Each subject keeps only its current window.
+#[derive(Debug, Default)]
+pub struct MemoryQuotaStore {
+    usage: Mutex<HashMap<String, (u64, Usage)>>,
+}
+
+#[async_trait]
+impl QuotaStore for MemoryQuotaStore {
+    async fn usage(&self, subject: &str, window: u64) -> Result<Usage> {
+        let usage = self.usage.lock().unwrap();
+        Ok(usage
+            .get(subject)
+            .filter(|(w, _)| *w == window)
+            .map(|(_, usage)| *usage)
+            .unwrap_or_default())
+    }
+
+    async fn record(
+        &self,
+        subject: &str,
+        window: u64,
+        _length: Duration,
+        bytes: u64,
+    ) -> Result<()> {
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage
+            .entry(subject.to_string())
+            .or_insert((window, Usage::default()));
+        if entry.0 != window {
+            *entry = (window, Usage::default());
+        }
+        entry.1.requests += 1;
+        entry.1.bytes = entry.1.bytes.saturating_add(bytes);
+        Ok(())
+    }
+}
+
+/// Counters in Redis, shared by every replica. Each subject's window is a
+/// hash that expires with the window.
+#[cfg(feature = "redis")]
+#[derive(Clone)]
+pub struct RedisQuotaStore {
+    redis: Redis,
+}
+
+#[cfg(feature = "redis")]
+impl RedisQuotaStore {
+    /// Count usage on the server `redis` is connected to.
+    pub fn new(redis: Redis) -> Self {
+        Self { redis }
+    }
+
+    fn key(subject: &str, window: u64) -> String {
+        format!("{}:quota:{}:{}", KEY_PREFIX, window, subject)
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl QuotaStore for RedisQuotaStore {
+    async fn usage(&self, subject: &str, window: u64) -> Result<Usage> {
+        let (requests, bytes): (Option<u64>, Option<u64>) = redis::cmd("HMGET")
+            .arg(Self::key(subject, window))
+            .arg("requests")
+            .arg("bytes")
+            .query_async(&mut self.redis.connection())
+            .await
+            .map_err(|e| Error::Internal(format!("quota Redis HMGET failed: {}", e)))?;
+
+        Ok(Usage {
+            requests: requests.unwrap_or_default(),
+            bytes: bytes.unwrap_or_default(),
+        })
+    }
+
+    async fn record(&self, subject: &str, window: u64, length: Duration, bytes: u64) -> Result<()> {
+        let key = Self::key(subject, window);
+        redis::pipe()
+            .atomic()
+            .hincr(&key, "requests", 1)
+            .ignore()
+            .hincr(&key, "bytes", bytes)
+            .ignore()
+            .expire(&key, length.as_secs() as i64)
+            .ignore()
+            .query_async::<()>(&mut self.redis.connection())
+            .await
+            .map_err(|e| Error::Internal(format!("quota Redis update failed: {}", e)))
+    }
+}
+
+/// Store spec: `memory` or a `redis://` URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuotaBackend {
+    Memory,
+    #[cfg(feature = "redis")]
+    Redis(String),
+}
+
+impl FromStr for QuotaBackend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s == "memory" {
+            return Ok(QuotaBackend::Memory);
+        }
+        if s.starts_with("redis://") || s.starts_with("rediss://") {
+            #[cfg(feature = "redis")]
+            return Ok(QuotaBackend::Redis(s.to_string()));
+            #[cfg(not(feature = "redis"))]
+            return Err(Error::InvalidInput(
+                "Redis quota storage requires the redis feature".to_string(),
+            ));
+        }
+
+        Err(Error::InvalidInput(format!(
+            "invalid quota store: {} (expected memory or a redis:// URL)",
+            s
+        )))
+    }
+}
+
+/// Per-subject limits and the store counting usage against them
+#[derive(Clone)]
+pub struct Quota {
+    limits: QuotaLimits,
+    store: Arc<dyn QuotaStore>,
+}
+
+impl Quota {
+    /// Enforce `limits` with counters kept in `backend`. A Redis backend is
+    /// connected to here, so a bad URL fails at startup.
+    pub async fn new(backend: QuotaBackend, limits: QuotaLimits) -> Result<Self> {
+        let store: Arc<dyn QuotaStore> = match backend {
+            QuotaBackend::Memory => Arc::new(MemoryQuotaStore::default()),
+            #[cfg(feature = "redis")]
+            QuotaBackend::Redis(url) => Arc::new(RedisQuotaStore::new(Redis::connect(&url).await?)),
+        };
+        Ok(Self::with_store(store, limits))
+    }
+
+    /// Enforce `limits` with counters kept in `store`.
+    pub fn with_store(store: Arc<dyn QuotaStore>, limits: QuotaLimits) -> Self {
+        Self { limits, store }
+    }
+
+    /// Fail with `PermissionDenied` if `subject` has used up its quota,
+    /// logging which limit was reached.
+    pub async fn check(&self, subject: &str) -> Result<()> {
+        let usage = self.store.usage(subject, self.window()).await?;
+        match self.limits.exceeded(usage) {
+            Some(reason) => {
+                tracing::info!(subject = %subject, "data request refused: {}", reason);
+                Err(Error::PermissionDenied)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Count a served request of `bytes` against `subject`.
+    pub async fn record(&self, subject: &str, bytes: u64) -> Result<()> {
+        self.store
+            .record(subject, self.window(), self.limits.window, bytes)
+            .await
+    }
+
+    /// Number of the current window since the Unix epoch.
+    fn window(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        now / self.limits.window.as_secs().max(1)
+    }
+}
+
+/// Enforce `quota` on data requests from authenticated subjects. Sits inside
+/// authentication, which sets the subject.
+pub async fn quota_middleware(quota: Arc<Quota>, req: Request, next: Next) -> Response {
+    let is_data = req.method() == Method::GET && is_data_path(req.uri().path());
+    let subject = req
+        .extensions()
+        .get::<Subject>()
+        .filter(|_| is_data)
+        .map(|Subject(subject)| subject.clone());
+    let Some(subject) = subject else {
+        return next.run(req).await;
+    };
+
+    match quota.check(&subject).await {
+        Err(e @ Error::PermissionDenied) => return e.into_response(),
+        Err(e) => tracing::warn!("quota check failed, serving anyway: {}", e),
+        Ok(()) => {}
+    }
+
+    let response = next.run(req).await;
+
+    if !response.status().is_success() && !response.status().is_redirection() {
+        return response;
+    }
+
+    let length = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse().ok());
+    if response.status().is_success() && length.is_none() {
+        let (parts, body) = response.into_parts();
+        let mut sent = SentBytes {
+            quota,
+            subject,
+            bytes: 0,
+        };
+        let body = body.into_data_stream().map(move |chunk| {
+            if let Ok(chunk) = &chunk {
+                sent.bytes += chunk.len() as u64;
+            }
+            chunk
+        });
+        return Response::from_parts(parts, Body::from_stream(body));
+    }
+
+    let bytes = length
+        .filter(|_| response.status().is_success())
+        .unwrap_or(0);
+    if let Err(e) = quota.record(&subject, bytes).await {
+        tracing::warn!("failed to record quota usage: {}", e);
+    }
+
+    response
+}
+
+/// Bytes of a streamed response sent to `subject`, recorded once the body
+/// is dropped: when it's finished, or when the client goes away.
+struct SentBytes {
+    quota: Arc<Quota>,
+    subject: String,
+    bytes: u64,
+}
+
+impl Drop for SentBytes {
+    fn drop(&mut self) {
+        let quota = self.quota.clone();
+        let subject = std::mem::take(&mut self.subject);
+        let bytes = self.bytes;
+        tokio::spawn(async move {
+            if let Err(e) = quota.record(&subject, bytes).await {
+                tracing::warn!("failed to record quota usage: {}", e);
+            }
+        });
+    }
+}
+
+/// Whether `path` serves data: `/data`, `/download` or an export job's result
+fn is_data_path(path: &str) -> bool {
+    path.starts_with("/data/")
+        || path.starts_with("/download/")
+        || path
+            .strip_prefix(JOBS_PATH)
+            .is_some_and(|rest| rest.starts_with('/') && rest.ends_with("/result"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(requests: Option<u64>, bytes: Option<u64>) -> QuotaLimits {
+        QuotaLimits {
+            requests,
+            bytes,
+            window: Duration::from_secs(3600),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_limit() {
+        let quota = Quota::new(QuotaBackend::Memory, limits(Some(2), None))
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            quota.check("alice").await.unwrap();
+            quota.record("alice", 100).await.unwrap();
+        }
+        assert!(matches!(
+            quota.check("alice").await,
+            Err(Error::PermissionDenied)
+        ));
+        // Other subjects have their own counters
+        quota.check("bob").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_byte_limit() {
+        let quota = Quota::new(QuotaBackend::Memory, limits(None, Some(1000)))
+            .await
+            .unwrap();
+
+        quota.record("alice", 999).await.unwrap();
+        quota.check("alice").await.unwrap();
+        quota.record("alice", 999).await.unwrap();
+        assert!(matches!(
+            quota.check("alice").await,
+            Err(Error::PermissionDenied)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_resets_each_window() {
+        let store = MemoryQuotaStore::default();
+        let length = Duration::from_secs(60);
+        store.record("alice", 1, length, 10).await.unwrap();
+        store.record("alice", 1, length, 10).await.unwrap();
+        assert_eq!(
+            store.usage("alice", 1).await.unwrap(),
+            Usage {
+                requests: 2,
+                bytes: 20
+            }
+        );
+
+        assert_eq!(store.usage("alice", 2).await.unwrap(), Usage::default());
+        store.record("alice", 2, length, 5).await.unwrap();
+        assert_eq!(
+            store.usage("alice", 2).await.unwrap(),
+            Usage {
+                requests: 1,
+                bytes: 5
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_streamed_bytes_are_counted() {
+        use axum::{Router, routing::get};
+        use tower::ServiceExt;
+
+        let store = Arc::new(MemoryQuotaStore::default());
+        let quota = Arc::new(Quota::with_store(store.clone(), limits(None, Some(1000))));
+        let window = quota.window();
+
+        // No Content-Length, like a precise slice
+        let app = Router::new()
+            .route(
+                "/data/BAM/sample1",
+                get(|| async {
+                    let chunks = ["abc", "defg"].map(Ok::<_, std::convert::Infallible>);
+                    Body::from_stream(futures::stream::iter(chunks))
+                }),
+            )
+            .layer(axum::middleware::from_fn(
+                move |req: Request, next: Next| quota_middleware(quota.clone(), req, next),
+            ));
+
+        let request = axum::http::Request::builder()
+            .uri("/data/BAM/sample1")
+            .extension(Subject("alice".to_string()))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body.len(), 7);
+
+        // Recorded in the background once the body is dropped
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(
+            store.usage("alice", window).await.unwrap(),
+            Usage {
+                requests: 1,
+                bytes: 7
+            }
+        );
+    }
+
+    #[test]
+    fn test_backend_parsing() {
+        assert_eq!(
+            "memory".parse::<QuotaBackend>().unwrap(),
+            QuotaBackend::Memory
+        );
+        assert!("memcached://localhost".parse::<QuotaBackend>().is_err());
+        #[cfg(feature = "redis")]
+        assert_eq!(
+            "redis://localhost:6379".parse::<QuotaBackend>().unwrap(),
+            QuotaBackend::Redis("redis://localhost:6379".to_string())
+        );
+        #[cfg(not(feature = "redis"))]
+        assert!("redis://localhost:6379".parse::<QuotaBackend>().is_err());
+    }
+
+    #[test]
+    fn test_data_paths() {
+        assert!(is_data_path("/data/BAM/sample1"));
+        assert!(is_data_path("/download/reads/sample1"));
+        assert!(is_data_path("/jobs/0123abcd/result"));
+        assert!(!is_data_path("/jobs/0123abcd"));
+        assert!(!is_data_path("/jobs"));
+        assert!(!is_data_path("/reads/sample1"));
+    }
+}
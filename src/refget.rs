@@ -0,0 +1,319 @@
+//! GA4GH refget sequence lookup.
+//!
+//! This module builds an in-memory lookup table from checksum digests to the
+//! FASTA records that back them, so the refget v2 endpoints can resolve
+//! `md5` and `ga4gh`/`TRUNC512` identifiers to a sequence in storage. FASTA
+//! files are found as tickets find them, with the storage's naming (e.g.
+//! `.fa`, `.fasta` or bgzipped `.fa.gz`).
+//!
+//! Enable with the `refget` feature flag.
+//!
+//! # Identifiers
+//!
+//! | Form | Example |
+//! |------|---------|
+//! | MD5 | `6aef897c3d6ff0c78aff06ac189178dd` or `md5:6aef...` |
+//! | ga4gh | `ga4gh:SQ.aKF498dAxcJAqme6QYQ7EZ07-fiw8Kw2` or `SQ.aKF4...` |
+//! | TRUNC512 | `68a178f7c740c5c240aa67ba41843b119d3bf9f8b0f0ac36` |
+
+use crate::storage::Storage;
+use crate::types::Format;
+use crate::{Error, Result};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use md5::{Digest, Md5};
+use noodles::{bgzf, fasta};
+use serde::Serialize;
+use sha2::Sha512;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A FASTA record addressable via refget.
+#[derive(Debug, Clone)]
+pub struct RefgetEntry {
+    /// Storage ID of the FASTA file containing the sequence.
+    pub id: String,
+    /// Sequence name within the FASTA file.
+    pub name: String,
+    /// Sequence length in bases.
+    pub length: u64,
+    /// Lowercase hex MD5 of the uppercased sequence.
+    pub md5: String,
+    /// Lowercase hex of the first 24 bytes of the SHA-512 digest.
+    pub trunc512: String,
+}
+
+impl RefgetEntry {
+    /// The ga4gh identifier (`SQ.` + base64url of the truncated SHA-512).
+    pub fn ga4gh(&self) -> String {
+        let bytes = decode_hex(&self.trunc512).unwrap_or_default();
+        format!("SQ.{}", URL_SAFE_NO_PAD.encode(bytes))
+    }
+}
+
+/// Digest-to-sequence lookup table.
+#[derive(Debug, Default)]
+pub struct RefgetIndex {
+    entries: Vec<RefgetEntry>,
+    by_md5: HashMap<String, usize>,
+    by_trunc512: HashMap<String, usize>,
+}
+
+impl RefgetIndex {
+    /// Build an index from every indexed FASTA file `storage` lists.
+    ///
+    /// Files without a FAI are skipped since sequence retrieval relies on its
+    /// offsets, as are bgzipped files without a GZI to find them by. Digests
+    /// are computed once at build time.
+    pub async fn build(storage: &dyn Storage) -> Result<Self> {
+        let mut index = Self::default();
+
+        for file in storage.list().await? {
+            if file.format != Format::Fasta {
+                continue;
+            }
+
+            let path = storage.file_path(&file.id, Format::Fasta);
+            if storage.index_path(&file.id, Format::Fasta).await?.is_none() {
+                tracing::debug!("skipping unindexed FASTA for refget: {:?}", path);
+                continue;
+            }
+            if is_bgzf(&path) && storage.gzi_path(&file.id, Format::Fasta).await?.is_none() {
+                tracing::debug!(
+                    "skipping bgzipped FASTA without a GZI for refget: {:?}",
+                    path
+                );
+                continue;
+            }
+
+            index.add_fasta(&file.id, &path)?;
+        }
+
+        tracing::info!("refget index built with {} sequences", index.len());
+
+        Ok(index)
+    }
+
+    /// Compute digests for every record in a FASTA file, plain or bgzipped,
+    /// and add them to the index.
+    pub fn add_fasta(&mut self, id: &str, path: &Path) -> Result<()> {
+        let file = std::fs::File::open(path)?;
+        let file: Box<dyn std::io::Read> = if is_bgzf(path) {
+            Box::new(bgzf::io::Reader::new(file))
+        } else {
+            Box::new(file)
+        };
+        let mut reader = fasta::io::Reader::new(std::io::BufReader::new(file));
+
+        for result in reader.records() {
+            let record = result
+                .map_err(|e| Error::Internal(format!("failed to read FASTA record: {}", e)))?;
+
+            let sequence = record.sequence().as_ref();
+            let (md5, trunc512) = sequence_digests(sequence);
+
+            self.insert(RefgetEntry {
+                id: id.to_string(),
+                name: String::from_utf8_lossy(record.name()).into_owned(),
+                length: sequence.len() as u64,
+                md5,
+                trunc512,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Add an entry to the index.
+    pub fn insert(&mut self, entry: RefgetEntry) {
+        let i = self.entries.len();
+        self.by_md5.insert(entry.md5.clone(), i);
+        self.by_trunc512.insert(entry.trunc512.clone(), i);
+        self.entries.push(entry);
+    }
+
+    /// Resolve a refget identifier to an entry.
+    pub fn lookup(&self, id: &str) -> Option<&RefgetEntry> {
+        let ga4gh = id.strip_prefix("ga4gh:").unwrap_or(id);
+
+        let i = if let Some(md5) = id.strip_prefix("md5:") {
+            self.by_md5.get(&md5.to_lowercase())
+        } else if let Some(sq) = ga4gh.strip_prefix("SQ.") {
+            let bytes = URL_SAFE_NO_PAD.decode(sq).ok()?;
+            self.by_trunc512.get(&encode_hex(&bytes))
+        } else {
+            let id = id.strip_prefix("TRUNC512:").unwrap_or(id).to_lowercase();
+            match id.len() {
+                32 => self.by_md5.get(&id),
+                48 => self.by_trunc512.get(&id),
+                _ => None,
+            }
+        }?;
+
+        self.entries.get(*i)
+    }
+
+    /// Number of indexed sequences.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the index has no sequences.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Refget metadata response (`/sequence/{id}/metadata`).
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefgetMetadataResponse {
+    pub metadata: RefgetMetadata,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefgetMetadata {
+    pub md5: String,
+    pub trunc512: String,
+    pub ga4gh: String,
+    pub length: u64,
+    pub aliases: Vec<RefgetAlias>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RefgetAlias {
+    pub alias: String,
+    pub naming_authority: String,
+}
+
+impl From<&RefgetEntry> for RefgetMetadataResponse {
+    fn from(entry: &RefgetEntry) -> Self {
+        Self {
+            metadata: RefgetMetadata {
+                md5: entry.md5.clone(),
+                trunc512: entry.trunc512.clone(),
+                ga4gh: entry.ga4gh(),
+                length: entry.length,
+                aliases: vec![RefgetAlias {
+                    alias: entry.name.clone(),
+                    naming_authority: "unknown".to_string(),
+                }],
+            },
+        }
+    }
+}
+
+/// Compute the (md5, trunc512) hex digests of a sequence.
+///
+/// Per the refget spec, digests are computed over the uppercased sequence.
+pub fn sequence_digests(sequence: &[u8]) -> (String, String) {
+    let upper = sequence.to_ascii_uppercase();
+
+    let md5 = encode_hex(&Md5::digest(&upper));
+    let sha512 = Sha512::digest(&upper);
+    let trunc512 = encode_hex(&sha512[..24]);
+
+    (md5, trunc512)
+}
+
+/// Whether a FASTA file is bgzipped, by its extension as in
+/// [`crate::formats::FastaIndexReader`]
+fn is_bgzf(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| s.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_entry() -> RefgetEntry {
+        let (md5, trunc512) = sequence_digests(b"ACGT");
+        RefgetEntry {
+            id: "ref".to_string(),
+            name: "chr1".to_string(),
+            length: 4,
+            md5,
+            trunc512,
+        }
+    }
+
+    #[test]
+    fn test_sequence_digests_case_insensitive() {
+        assert_eq!(sequence_digests(b"acgt"), sequence_digests(b"ACGT"));
+        let (md5, trunc512) = sequence_digests(b"ACGT");
+        assert_eq!(md5, "f1f8f4bf413b16ad135722aa4591043e");
+        assert_eq!(trunc512.len(), 48);
+    }
+
+    #[test]
+    fn test_lookup_by_all_identifier_forms() {
+        let entry = test_entry();
+        let ga4gh = entry.ga4gh();
+        let md5 = entry.md5.clone();
+        let trunc512 = entry.trunc512.clone();
+
+        let mut index = RefgetIndex::default();
+        index.insert(entry);
+
+        assert!(index.lookup(&md5).is_some());
+        assert!(index.lookup(&format!("md5:{}", md5)).is_some());
+        assert!(index.lookup(&trunc512).is_some());
+        assert!(index.lookup(&format!("TRUNC512:{}", trunc512)).is_some());
+        assert!(index.lookup(&ga4gh).is_some());
+        assert!(index.lookup(&format!("ga4gh:{}", ga4gh)).is_some());
+        assert!(index.lookup("unknown").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_build_uses_storage_naming() {
+        use crate::storage::{LocalStorage, naming::Naming};
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+
+        std::fs::write(path("plain.fasta"), ">chr1\nACGT\n").unwrap();
+        std::fs::write(path("plain.fasta.fai"), "chr1\t4\t6\t4\t5\n").unwrap();
+
+        let mut writer =
+            bgzf::io::Writer::new(std::fs::File::create(path("packed.fa.gz")).unwrap());
+        writer.write_all(b">chr2\nGGCC\n").unwrap();
+        writer.finish().unwrap();
+        std::fs::write(path("packed.fa.gz.fai"), "chr2\t4\t6\t4\t5\n").unwrap();
+        // One block, so no entries
+        std::fs::write(path("packed.fa.gz.gzi"), 0u64.to_le_bytes()).unwrap();
+
+        std::fs::write(path("unindexed.fa"), ">chr3\nTT\n").unwrap();
+
+        let naming = Naming::default()
+            .with_data_extensions("fasta:fasta")
+            .unwrap();
+        let storage = LocalStorage::new(dir.path().to_path_buf(), "http://localhost".to_string())
+            .with_naming(naming);
+        let index = RefgetIndex::build(&storage).await.unwrap();
+        assert_eq!(index.len(), 2);
+
+        let (md5, _) = sequence_digests(b"ACGT");
+        assert_eq!(index.lookup(&md5).unwrap().id, "plain");
+        let (md5, _) = sequence_digests(b"GGCC");
+        assert_eq!(index.lookup(&md5).unwrap().id, "packed");
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        let bytes = vec![0x00, 0x7f, 0xff];
+        assert_eq!(decode_hex(&encode_hex(&bytes)), Some(bytes));
+    }
+}
@@ -0,0 +1,1227 @@
+//! Application assembly.
+//!
+//! [`ServerBuilder`] turns a storage backend and options into the fully
+//! layered axum [`Router`] (state, routes, quotas, auth, audit, timeouts,
+//! tracing, CORS).
+//! The CLI and the Python bindings both build their servers through it so
+//! features stay in sync between them.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use htsgetr::server::ServerBuilder;
+//! use htsgetr::storage::LocalStorage;
+//! use std::sync::Arc;
+//!
+//! # async fn example() -> htsgetr::Result<()> {
+//! let base_url = "http://localhost:8080";
+//! let storage = Arc::new(LocalStorage::new("./data".into(), base_url.to_string()));
+//! let app = ServerBuilder::new(storage, base_url).cors(true).build()?;
+//!
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await?;
+//! axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::admin::ConfigReport;
+use crate::audit::{AuditLog, audit_middleware};
+use crate::error::ErrorDetail;
+use crate::formats::{HeaderParsing, IndexReader, IndexReaders, ReferenceAliases};
+use crate::forwarded::TrustedProxies;
+use crate::handlers::{
+    AppState, DataMode, DefaultFormats, Endpoints, HeaderCache, HeaderMode, Precompressed,
+    RequestObserver, TicketCache, TicketLimits, create_router_with,
+};
+use crate::jobs::Jobs;
+use crate::quota::{Quota, quota_middleware};
+use crate::storage::Storage;
+use crate::types::Format;
+use crate::usage::UsageStats;
+use crate::{Error, Result};
+use axum::{
+    Router,
+    extract::Request,
+    http::{Method, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tower_http::{
+    compression::{CompressionLayer, DefaultPredicate, Predicate},
+    cors::{AllowHeaders, AllowOrigin, CorsLayer},
+    trace::TraceLayer,
+};
+
+#[cfg(feature = "auth")]
+use crate::auth::{AuthConfig, Policy, UrlSigner};
+#[cfg(feature = "auth")]
+use crate::shared::SharedCache;
+
+#[cfg(feature = "jws")]
+use crate::jws::TicketSigner;
+
+#[cfg(feature = "refget")]
+use crate::refget::RefgetIndex;
+
+/// Compress JSON tickets and text data (VCF, FASTA, FASTQ), but never BGZF or
+/// binary payloads, or partial responses whose `Content-Range` refers to the
+/// uncompressed bytes.
+#[derive(Debug, Clone, Copy)]
+struct CompressiblePayload;
+
+impl Predicate for CompressiblePayload {
+    fn should_compress<B>(&self, response: &axum::http::Response<B>) -> bool
+    where
+        B: axum::body::HttpBody,
+    {
+        use axum::http::header::{CONTENT_RANGE, CONTENT_TYPE};
+
+        if response.extensions().get::<Precompressed>().is_some()
+            || response.headers().contains_key(CONTENT_RANGE)
+        {
+            return false;
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+
+        // Tickets are served as `application/vnd.ga4gh.htsget.v1.3.0+json`
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        essence == "application/json"
+            || essence.ends_with("+json")
+            || content_type.starts_with("text/")
+            || content_type.starts_with(crate::types::Format::Vcf.content_type())
+    }
+}
+
+/// Answer with an htsget `Timeout` error if the handler hasn't produced a
+/// response within `timeout`. Streaming bodies aren't cut off once started.
+async fn request_timeout(timeout: Duration, req: Request, next: Next) -> Response {
+    let path = req.uri().path().to_string();
+    match tokio::time::timeout(timeout, next.run(req)).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!(path, ?timeout, "request timed out");
+            Error::Timeout(format!("request for {} after {:?}", path, timeout)).into_response()
+        }
+    }
+}
+
+/// Rewrite htsget error bodies (see [`crate::error`]): with `detail`, add
+/// the optional fields, echoing the request's `X-Request-Id` as
+/// `requestId`; with `expose_internal`, send internal errors unsanitized.
+async fn error_body(detail: bool, expose_internal: bool, req: Request, next: Next) -> Response {
+    let request_id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let response = next.run(req).await;
+    let Some(ErrorDetail {
+        mut error,
+        internal,
+    }) = response.extensions().get::<ErrorDetail>().cloned()
+    else {
+        return response;
+    };
+
+    if let (true, Some((message, internal_detail))) = (expose_internal, internal) {
+        error.htsget.message = message;
+        error.htsget.detail = Some(internal_detail);
+    }
+    if detail {
+        error.htsget.request_id = request_id;
+    } else {
+        error.htsget.retryable = None;
+        error.htsget.detail = None;
+    }
+
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    (parts, axum::Json(error)).into_response()
+}
+
+/// Normalize a route prefix to `/a/b` form; `None` if it's empty or `/`.
+pub(crate) fn normalize_path_prefix(prefix: &str) -> Option<String> {
+    let prefix = prefix.trim().trim_matches('/');
+    (!prefix.is_empty()).then(|| format!("/{}", prefix))
+}
+
+/// Explicit CORS policy, for deployments that can't use the permissive default
+/// (e.g. browsers sending credentials, which `*` origins don't allow).
+#[derive(Debug, Clone, Default)]
+pub struct CorsOptions {
+    /// Allowed origins (e.g. `https://app.example.com`); empty or `*` allows any
+    pub origins: Vec<String>,
+    /// Allowed methods; defaults to `GET, HEAD, POST, OPTIONS`
+    pub methods: Vec<String>,
+    /// Allowed request headers; defaults to those the preflight asks for
+    pub headers: Vec<String>,
+    /// How long browsers may cache preflight responses, in seconds
+    pub max_age: Option<u64>,
+    /// Send `Access-Control-Allow-Credentials: true` (requires explicit origins)
+    pub allow_credentials: bool,
+}
+
+impl CorsOptions {
+    /// Build the CORS layer; preflight (`OPTIONS`) requests, including those
+    /// for the POST ticket endpoints, are answered before authentication.
+    pub fn layer(&self) -> Result<CorsLayer> {
+        let invalid = |what: &str, value: &str| {
+            Error::InvalidInput(format!("invalid CORS {}: {}", what, value))
+        };
+
+        let any_origin = self.origins.is_empty() || self.origins.iter().any(|o| o == "*");
+        if any_origin && self.allow_credentials {
+            return Err(Error::InvalidInput(
+                "CORS credentials require explicit allowed origins".to_string(),
+            ));
+        }
+
+        let origin = if any_origin {
+            AllowOrigin::any()
+        } else {
+            AllowOrigin::list(
+                self.origins
+                    .iter()
+                    .map(|o| o.parse().map_err(|_| invalid("origin", o)))
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        };
+
+        let methods = if self.methods.is_empty() {
+            vec![Method::GET, Method::HEAD, Method::POST, Method::OPTIONS]
+        } else {
+            self.methods
+                .iter()
+                .map(|m| m.to_uppercase().parse().map_err(|_| invalid("method", m)))
+                .collect::<Result<Vec<Method>>>()?
+        };
+
+        let headers = if self.headers.is_empty() {
+            AllowHeaders::mirror_request()
+        } else {
+            AllowHeaders::list(
+                self.headers
+                    .iter()
+                    .map(|h| h.parse().map_err(|_| invalid("header", h)))
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        };
+
+        let mut layer = CorsLayer::new()
+            .allow_origin(origin)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .allow_credentials(self.allow_credentials);
+
+        if let Some(max_age) = self.max_age {
+            layer = layer.max_age(std::time::Duration::from_secs(max_age));
+        }
+
+        Ok(layer)
+    }
+}
+
+/// Authentication options (requires `auth` feature)
+#[cfg(feature = "auth")]
+#[derive(Debug, Clone)]
+pub struct AuthOptions {
+    /// JWT issuer; JWKS is fetched from `{issuer}/.well-known/jwks.json` unless
+    /// `jwks_url` or `public_key_pem` is set
+    pub issuer: Option<String>,
+    /// Expected `aud` claim
+    pub audience: Option<String>,
+    /// Explicit JWKS URL
+    pub jwks_url: Option<String>,
+    /// Static RSA or EC public key (PEM)
+    pub public_key_pem: Option<String>,
+    /// Shared secret for HMAC-signed (HS256) tokens
+    pub hmac_secret: Option<Vec<u8>>,
+    /// Accepted JWT algorithms (e.g. `RS256`, `HS256`); defaults to HS256 with
+    /// an HMAC secret, otherwise RS256 and ES256
+    pub algorithms: Vec<String>,
+    /// Paths that don't require authentication
+    pub public_endpoints: Vec<String>,
+    /// HMAC secret for signing data URLs (random if unset)
+    pub data_url_secret: Option<Vec<u8>>,
+    /// ID of `data_url_secret`, added to signed URLs
+    pub data_url_key_id: Option<String>,
+    /// Previous data URL secret, still accepted during `data_url_rotation_grace`
+    pub data_url_previous_secret: Option<Vec<u8>>,
+    /// ID of `data_url_previous_secret`
+    pub data_url_previous_key_id: Option<String>,
+    /// Refuse to generate a random data URL secret: replicas must share one,
+    /// or data URLs signed by one fail on the others
+    pub require_data_url_secret: bool,
+    /// Seconds the previous data URL secret remains valid after startup
+    pub data_url_rotation_grace: u64,
+    /// Signed data URL TTL in seconds
+    pub data_url_expiry: u64,
+    /// RFC 7662 introspection endpoint for opaque tokens
+    pub introspection_url: Option<String>,
+    /// Client ID for the introspection endpoint
+    pub client_id: Option<String>,
+    /// Client secret for the introspection endpoint
+    pub client_secret: Option<String>,
+    /// How long introspection results are cached, in seconds
+    pub introspection_cache_ttl: u64,
+    /// Per-dataset authorization policy
+    pub policy: Option<Policy>,
+}
+
+#[cfg(feature = "auth")]
+impl Default for AuthOptions {
+    fn default() -> Self {
+        Self {
+            issuer: None,
+            audience: None,
+            jwks_url: None,
+            public_key_pem: None,
+            hmac_secret: None,
+            algorithms: Vec::new(),
+            public_endpoints: [
+                "/",
+                "/service-info",
+                "/reads/service-info",
+                "/variants/service-info",
+                "/sequences/service-info",
+                "/ui",
+                "/openapi.json",
+                "/swagger-ui",
+                "/.well-known/jwks.json",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+            data_url_secret: None,
+            data_url_key_id: None,
+            data_url_previous_secret: None,
+            data_url_previous_key_id: None,
+            require_data_url_secret: false,
+            data_url_rotation_grace: 3600,
+            data_url_expiry: 3600,
+            introspection_url: None,
+            client_id: None,
+            client_secret: None,
+            introspection_cache_ttl: 60,
+            policy: None,
+        }
+    }
+}
+
+#[cfg(feature = "auth")]
+impl AuthOptions {
+    /// Create the data URL signer, generating a random secret if none is
+    /// configured and one isn't required.
+    fn url_signer(&self) -> Result<UrlSigner> {
+        let secret = match &self.data_url_secret {
+            Some(secret) => secret.clone(),
+            None if self.require_data_url_secret => {
+                return Err(Error::InvalidInput(
+                    "auth is enabled on multiple replicas but no data URL secret is configured; \
+                     set the same data URL secret on every replica so tickets signed by one \
+                     are accepted by the others"
+                        .to_string(),
+                ));
+            }
+            None => {
+                tracing::info!("Generating random data URL signing secret");
+                UrlSigner::generate_secret()
+            }
+        };
+        let mut signer = UrlSigner::new(secret, self.data_url_expiry);
+
+        if let Some(key_id) = &self.data_url_key_id {
+            signer = signer.with_key_id(key_id);
+        }
+        if let Some(previous) = &self.data_url_previous_secret {
+            signer = signer.with_previous_secret(
+                previous.clone(),
+                std::time::Duration::from_secs(self.data_url_rotation_grace),
+            );
+        }
+        if let Some(key_id) = &self.data_url_previous_key_id {
+            signer = signer.with_previous_key_id(key_id);
+        }
+        Ok(signer)
+    }
+
+    fn into_auth_config(
+        self,
+        url_signer: Option<UrlSigner>,
+        shared: Option<Arc<dyn SharedCache>>,
+    ) -> Result<AuthConfig> {
+        use crate::auth::{
+            DEFAULT_ALGORITHMS, HmacKeyProvider, KeyProvider, NoKeyProvider, StaticKeyProvider,
+            TokenIntrospector, jwks::JwksKeyProvider, parse_algorithms,
+        };
+
+        let algorithms = if !self.algorithms.is_empty() {
+            parse_algorithms(&self.algorithms)?
+        } else if self.hmac_secret.is_some() {
+            vec![jsonwebtoken::Algorithm::HS256]
+        } else {
+            DEFAULT_ALGORITHMS.to_vec()
+        };
+
+        let jwks = |provider: JwksKeyProvider| -> Arc<dyn KeyProvider> {
+            match &shared {
+                Some(cache) => Arc::new(provider.with_shared_cache(cache.clone())),
+                None => Arc::new(provider),
+            }
+        };
+
+        // Determine key provider
+        let key_provider: Arc<dyn KeyProvider> = if let Some(ref secret) = self.hmac_secret {
+            // Shared secret
+            tracing::info!("Using HMAC shared secret for JWT validation");
+            Arc::new(HmacKeyProvider::new(secret))
+        } else if let Some(ref pem) = self.public_key_pem {
+            // Static PEM key
+            tracing::info!("Using static public key for JWT validation");
+            let provider = StaticKeyProvider::from_rsa_pem(pem.as_bytes())
+                .or_else(|_| StaticKeyProvider::from_ec_pem(pem.as_bytes()))?;
+            Arc::new(provider)
+        } else if let Some(ref jwks_url) = self.jwks_url {
+            // Explicit JWKS URL
+            tracing::info!("Using JWKS endpoint: {}", jwks_url);
+            jwks(JwksKeyProvider::new(jwks_url.clone()))
+        } else if let Some(ref issuer) = self.issuer {
+            // Derive JWKS URL from issuer
+            tracing::info!("Using JWKS from issuer: {}", issuer);
+            jwks(JwksKeyProvider::from_issuer(issuer))
+        } else if self.introspection_url.is_some() {
+            // Opaque tokens only
+            Arc::new(NoKeyProvider)
+        } else {
+            return Err(Error::InvalidInput(
+                "auth enabled but no key source configured; set an issuer, JWKS URL, public key, \
+                 HMAC secret, or introspection URL"
+                    .to_string(),
+            ));
+        };
+
+        let introspector = self.introspection_url.map(|url| {
+            tracing::info!("Using token introspection endpoint: {}", url);
+            Arc::new(TokenIntrospector::new(
+                url,
+                self.client_id,
+                self.client_secret,
+                std::time::Duration::from_secs(self.introspection_cache_ttl),
+            ))
+        });
+
+        let public_paths: std::collections::HashSet<String> = self
+            .public_endpoints
+            .iter()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        tracing::info!("Public endpoints (no auth required): {:?}", public_paths);
+
+        Ok(AuthConfig {
+            enabled: true,
+            key_provider,
+            algorithms,
+            issuer: self.issuer,
+            audience: self.audience,
+            public_paths,
+            url_signer,
+            introspector,
+            policy: self.policy.map(Arc::new),
+            #[cfg(feature = "refget")]
+            refget: None,
+        })
+    }
+}
+
+/// Builds the htsget application router
+pub struct ServerBuilder {
+    storage: Arc<dyn Storage>,
+    base_url: String,
+    cors: bool,
+    cors_options: Option<CorsOptions>,
+    compression: bool,
+    cache_control: Option<String>,
+    data_mode: DataMode,
+    header_mode: HeaderMode,
+    endpoints: Endpoints,
+    default_formats: DefaultFormats,
+    ticket_cache: Option<TicketCache>,
+    plain_json_tickets: bool,
+    ticket_url_ttl: Option<Duration>,
+    ticket_limits: TicketLimits,
+    index_readers: IndexReaders,
+    reference_aliases: Option<Arc<ReferenceAliases>>,
+    routes: Router<AppState>,
+    observers: Vec<Arc<dyn RequestObserver>>,
+    path_prefix: Option<String>,
+    request_timeout: Option<Duration>,
+    error_detail: bool,
+    expose_internal_errors: bool,
+    audit: Option<Arc<AuditLog>>,
+    quota: Option<Arc<Quota>>,
+    trusted_proxies: Option<TrustedProxies>,
+    #[cfg(feature = "auth")]
+    auth: Option<AuthOptions>,
+    /// Routes that check an admin token of their own instead
+    #[cfg(feature = "auth")]
+    admin_paths: Vec<String>,
+    #[cfg(feature = "auth")]
+    shared_cache: Option<Arc<dyn SharedCache>>,
+    #[cfg(feature = "refget")]
+    refget: Option<Arc<RefgetIndex>>,
+    #[cfg(feature = "jws")]
+    ticket_signer: Option<Arc<TicketSigner>>,
+}
+
+impl ServerBuilder {
+    /// Start a builder for `storage`, with ticket URLs rooted at `base_url`.
+    /// CORS is enabled by default.
+    pub fn new(storage: Arc<dyn Storage>, base_url: impl Into<String>) -> Self {
+        Self {
+            storage,
+            base_url: base_url.into(),
+            cors: true,
+            cors_options: None,
+            compression: true,
+            cache_control: None,
+            data_mode: DataMode::Proxy,
+            header_mode: HeaderMode::Range,
+            endpoints: Endpoints::default(),
+            default_formats: DefaultFormats::default(),
+            ticket_cache: None,
+            plain_json_tickets: false,
+            ticket_url_ttl: None,
+            ticket_limits: TicketLimits::default(),
+            index_readers: IndexReaders::default(),
+            reference_aliases: None,
+            routes: Router::new(),
+            observers: Vec::new(),
+            path_prefix: None,
+            request_timeout: None,
+            error_detail: false,
+            expose_internal_errors: false,
+            audit: None,
+            quota: None,
+            trusted_proxies: None,
+            #[cfg(feature = "auth")]
+            auth: None,
+            #[cfg(feature = "auth")]
+            admin_paths: Vec::new(),
+            #[cfg(feature = "auth")]
+            shared_cache: None,
+            #[cfg(feature = "refget")]
+            refget: None,
+            #[cfg(feature = "jws")]
+            ticket_signer: None,
+        }
+    }
+
+    /// Enable or disable CORS (permissive unless [`cors_options`](Self::cors_options) is set).
+    pub fn cors(mut self, enabled: bool) -> Self {
+        self.cors = enabled;
+        self
+    }
+
+    /// Enable or disable response compression (gzip, br, or zstd, as the client
+    /// accepts). Enabled by default; BGZF and binary data are never recompressed.
+    pub fn compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    /// `Cache-Control` header for data responses (e.g. `public, max-age=3600`).
+    pub fn cache_control(mut self, value: impl Into<String>) -> Self {
+        self.cache_control = Some(value.into());
+        self
+    }
+
+    /// Stream data through the server (default) or redirect to storage URLs.
+    pub fn data_mode(mut self, mode: DataMode) -> Self {
+        self.data_mode = mode;
+        self
+    }
+
+    /// Point `class=header` tickets at the header's byte range (default) or
+    /// at a re-compressed standalone copy.
+    pub fn header_mode(mut self, mode: HeaderMode) -> Self {
+        self.header_mode = mode;
+        self
+    }
+
+    /// Serve only these endpoints (every datatype, but no ticket refresh, by
+    /// default).
+    pub fn endpoints(mut self, endpoints: Endpoints) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    /// Formats ticket endpoints serve when a request doesn't name one
+    /// (BAM, VCF and FASTA by default), and whether to detect them per ID.
+    pub fn default_formats(mut self, formats: DefaultFormats) -> Self {
+        self.default_formats = formats;
+        self
+    }
+
+    /// Reuse index query results for up to `ttl` (up to `capacity` of them)
+    /// while the queried file is unchanged. Off by default.
+    pub fn ticket_cache(mut self, ttl: Duration, capacity: u64) -> Self {
+        self.ticket_cache = Some(TicketCache::new(ttl, capacity));
+        self
+    }
+
+    /// Send tickets as plain `application/json` instead of
+    /// `application/vnd.ga4gh.htsget.v1.3.0+json`, for legacy clients that
+    /// reject the htsget media type. Off by default.
+    pub fn plain_json_tickets(mut self, enabled: bool) -> Self {
+        self.plain_json_tickets = enabled;
+        self
+    }
+
+    /// Say ticket URLs are valid for at most `ttl` (in `expiresAt` and
+    /// `expiresIn`), even when they don't expire, so clients re-request
+    /// tickets at least that often. Off by default.
+    pub fn ticket_url_ttl(mut self, ttl: Duration) -> Self {
+        self.ticket_url_ttl = Some(ttl);
+        self
+    }
+
+    /// Reject ticket requests naming more than `max` regions with
+    /// `InvalidInput`; [`DEFAULT_MAX_REGIONS`](crate::handlers::DEFAULT_MAX_REGIONS)
+    /// by default.
+    pub fn max_regions(mut self, max: usize) -> Self {
+        self.ticket_limits.max_regions = max;
+        self
+    }
+
+    /// Send tickets in pages of at most `size` URLs, each linking the next
+    /// in a `next` field. Off by default.
+    pub fn ticket_page_size(mut self, size: usize) -> Self {
+        self.ticket_limits.page_size = Some(size.max(1));
+        self
+    }
+
+    /// Build tickets for `format` with `reader` instead of the built-in
+    /// one, e.g. for indexes kept outside the data directory.
+    pub fn index_reader(mut self, format: Format, reader: impl IndexReader) -> Self {
+        self.index_readers = self.index_readers.with_reader(format, reader);
+        self
+    }
+
+    /// Register `reader` for the format named `key`, for [`routes`](Self::routes)
+    /// of the embedder's own to look up in `AppState::index_readers`.
+    pub fn keyed_index_reader(mut self, key: &str, reader: impl IndexReader) -> Self {
+        self.index_readers = self.index_readers.with_keyed_reader(key, reader);
+        self
+    }
+
+    /// Serve BAM, CRAM, VCF and BCF files whose headers have lines that
+    /// don't parse without those lines, or reject them (the default). Readers
+    /// set with [`index_reader`](Self::index_reader) are unaffected.
+    pub fn header_parsing(mut self, parsing: HeaderParsing) -> Self {
+        self.index_readers = self.index_readers.with_header_parsing(parsing);
+        self
+    }
+
+    /// Match requested reference names to the ones files declare through
+    /// `aliases` (e.g. `1` for `chr1`), rather than exactly. Off by default.
+    pub fn reference_aliases(mut self, aliases: ReferenceAliases) -> Self {
+        self.reference_aliases = Some(Arc::new(aliases));
+        self
+    }
+
+    /// Serve `routes` alongside the htsget endpoints. They share the
+    /// application state and sit behind the same prefix, quotas, auth,
+    /// audit log and timeouts. Paths must not overlap the built-in ones.
+    pub fn routes(mut self, routes: Router<AppState>) -> Self {
+        self.routes = self.routes.merge(routes);
+        self
+    }
+
+    /// Call `observer` around ticket and data requests, after any observers
+    /// added before it.
+    pub fn observer(mut self, observer: Arc<dyn RequestObserver>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Wrap the storage backend, e.g. with a caching or metrics decorator
+    /// that delegates to the backend it is given.
+    pub fn map_storage(mut self, wrap: impl FnOnce(Arc<dyn Storage>) -> Arc<dyn Storage>) -> Self {
+        self.storage = wrap(self.storage);
+        self
+    }
+
+    /// Mount every route under `prefix` (e.g. `/htsget/v1`). The base URL
+    /// must already end with it, as ticket URLs are built from the base URL.
+    pub fn path_prefix(mut self, prefix: &str) -> Self {
+        self.path_prefix = normalize_path_prefix(prefix);
+        self
+    }
+
+    /// Fail requests that haven't started responding within `timeout`
+    /// (504, htsget `Timeout` error). No limit by default.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Add `retryable`, `detail` and `requestId` to error bodies. Off by
+    /// default, so strict clients see only the spec's fields.
+    pub fn error_detail(mut self, enabled: bool) -> Self {
+        self.error_detail = enabled;
+        self
+    }
+
+    /// Send internal errors to clients as they are, storage paths, bucket
+    /// names and backend errors included, instead of a generic message.
+    /// For debugging; they are always logged in full.
+    pub fn expose_internal_errors(mut self, enabled: bool) -> Self {
+        self.expose_internal_errors = enabled;
+        self
+    }
+
+    /// Record who accessed which dataset and region in `log`.
+    pub fn audit(mut self, log: AuditLog) -> Self {
+        self.audit = Some(Arc::new(log));
+        self
+    }
+
+    /// Limit the data each authenticated subject may fetch with `quota`.
+    /// Needs authentication to identify subjects; without it nothing is
+    /// counted.
+    pub fn quota(mut self, quota: Quota) -> Self {
+        self.quota = Some(Arc::new(quota));
+        self
+    }
+
+    /// Count the bytes served per dataset and principal in `stats`, and
+    /// serve the counts at `/metrics` and `/admin/usage`.
+    pub fn usage_stats(mut self, stats: Arc<UsageStats>) -> Self {
+        self.observers.push(stats.clone());
+        self.routes = self.routes.merge(stats.routes());
+        #[cfg(feature = "auth")]
+        if stats.has_admin_token() {
+            use crate::usage::{METRICS_PATH, USAGE_REPORT_PATH};
+            self.admin_paths
+                .extend([METRICS_PATH, USAGE_REPORT_PATH].map(str::to_string));
+        }
+        self
+    }
+
+    /// Accept export jobs at `/jobs`, kept in `jobs`.
+    pub fn jobs(mut self, jobs: Arc<Jobs>) -> Self {
+        self.routes = self.routes.merge(jobs.routes());
+        self
+    }
+
+    /// Serve `report` at `/admin/config` to requests bearing `admin_token`.
+    pub fn config_report(mut self, report: Arc<ConfigReport>, admin_token: &str) -> Self {
+        self.routes = self.routes.merge(report.routes(admin_token));
+        #[cfg(feature = "auth")]
+        self.admin_paths
+            .push(crate::admin::CONFIG_REPORT_PATH.to_string());
+        self
+    }
+
+    /// Use an explicit CORS policy instead of the permissive default.
+    pub fn cors_options(mut self, options: CorsOptions) -> Self {
+        self.cors_options = Some(options);
+        self
+    }
+
+    /// Honor forwarding headers from these proxies when building ticket URLs.
+    ///
+    /// Serve the router with `into_make_service_with_connect_info::<SocketAddr>()`
+    /// so the peer address can be checked.
+    pub fn trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = (!proxies.is_empty()).then_some(proxies);
+        self
+    }
+
+    /// Require JWT authentication and sign data URLs.
+    #[cfg(feature = "auth")]
+    pub fn auth(mut self, options: AuthOptions) -> Self {
+        self.auth = Some(options);
+        self
+    }
+
+    /// Share the JWKS fetched for authentication with other replicas through
+    /// `cache`.
+    #[cfg(feature = "auth")]
+    pub fn shared_cache(mut self, cache: Arc<dyn SharedCache>) -> Self {
+        self.shared_cache = Some(cache);
+        self
+    }
+
+    /// Serve refget endpoints from the given checksum index.
+    #[cfg(feature = "refget")]
+    pub fn refget(mut self, index: Arc<RefgetIndex>) -> Self {
+        self.refget = Some(index);
+        self
+    }
+
+    /// Sign every ticket with `signer` and serve its public key at
+    /// `/.well-known/jwks.json`.
+    #[cfg(feature = "jws")]
+    pub fn sign_tickets(mut self, signer: TicketSigner) -> Self {
+        self.ticket_signer = Some(Arc::new(signer));
+        self
+    }
+
+    /// Build the layered router.
+    pub fn build(self) -> Result<Router> {
+        #[cfg(feature = "auth")]
+        let url_signer = self
+            .auth
+            .as_ref()
+            .map(|options| options.url_signer())
+            .transpose()?
+            .map(|signer| signer.with_base_url(&self.base_url));
+
+        #[cfg(all(feature = "auth", feature = "refget"))]
+        let refget = self.refget.clone();
+
+        let state = AppState {
+            storage: self.storage,
+            base_url: self.base_url,
+            #[cfg(feature = "auth")]
+            url_signer: url_signer.clone(),
+            #[cfg(feature = "refget")]
+            refget: self.refget,
+            trusted_proxies: self.trusted_proxies.map(Arc::new),
+            cache_control: self.cache_control,
+            data_mode: self.data_mode,
+            endpoints: self.endpoints,
+            path_prefix: self.path_prefix.clone(),
+            standalone_headers: (self.header_mode == HeaderMode::Standalone)
+                .then(HeaderCache::default),
+            default_formats: self.default_formats,
+            ticket_cache: self.ticket_cache,
+            plain_json_tickets: self.plain_json_tickets,
+            ticket_url_ttl: self.ticket_url_ttl,
+            ticket_limits: self.ticket_limits,
+            index_readers: Arc::new(self.index_readers),
+            reference_aliases: self.reference_aliases,
+            observers: self.observers.into(),
+            #[cfg(feature = "jws")]
+            ticket_signer: self.ticket_signer,
+            #[cfg(feature = "checksums")]
+            checksums: Default::default(),
+        };
+
+        let app = create_router_with(state, self.routes);
+
+        // Inside auth, which sets the subject quotas are counted against
+        let app = match self.quota {
+            Some(quota) => app.layer(axum::middleware::from_fn(
+                move |req: Request, next: Next| quota_middleware(quota.clone(), req, next),
+            )),
+            None => app,
+        };
+
+        #[cfg(feature = "auth")]
+        let app = match self.auth {
+            Some(mut options) => {
+                use crate::auth::auth_middleware;
+
+                // Admin routes check the admin token, which isn't a JWT
+                options.public_endpoints.extend(self.admin_paths);
+
+                let auth_config = options.into_auth_config(url_signer, self.shared_cache)?;
+                #[cfg(feature = "refget")]
+                let auth_config = AuthConfig {
+                    refget,
+                    ..auth_config
+                };
+                let auth_config = Arc::new(auth_config);
+                // Extension must be added before middleware so middleware can extract it
+                app.layer(axum::Extension(auth_config))
+                    .layer(axum::middleware::from_fn(
+                        |req: axum::extract::Request, next: axum::middleware::Next| async move {
+                            auth_middleware(req, next).await
+                        },
+                    ))
+            }
+            None => app,
+        };
+
+        // Nested inside auth so that public paths, policies and signed data
+        // URLs see paths relative to the prefix
+        let app = match &self.path_prefix {
+            Some(prefix) if prefix.contains([':', '*', '?', '#']) => {
+                return Err(Error::InvalidInput(format!(
+                    "invalid path prefix: {}",
+                    prefix
+                )));
+            }
+            Some(prefix) => Router::new().nest(prefix, app),
+            None => app,
+        };
+
+        // Outside auth, so denied requests are recorded with the rest
+        let app = match self.audit {
+            Some(log) => {
+                let prefix = self.path_prefix.clone();
+                app.layer(axum::middleware::from_fn(
+                    move |req: Request, next: Next| {
+                        audit_middleware(log.clone(), prefix.clone(), req, next)
+                    },
+                ))
+            }
+            None => app,
+        };
+
+        let app = if self.compression {
+            app.layer(
+                CompressionLayer::new()
+                    .compress_when(DefaultPredicate::new().and(CompressiblePayload)),
+            )
+        } else {
+            app
+        };
+
+        let app = match self.request_timeout {
+            Some(timeout) => app.layer(axum::middleware::from_fn(
+                move |req: Request, next: Next| request_timeout(timeout, req, next),
+            )),
+            None => app,
+        };
+
+        // Outside the timeout and auth layers, so their errors get detail too
+        let (detail, expose_internal) = (self.error_detail, self.expose_internal_errors);
+        let app = if detail || expose_internal {
+            app.layer(axum::middleware::from_fn(
+                move |req: Request, next: Next| error_body(detail, expose_internal, req, next),
+            ))
+        } else {
+            app
+        };
+
+        let app = app.layer(TraceLayer::new_for_http());
+
+        // CORS is outermost so preflight requests never reach auth
+        let app = match (self.cors, &self.cors_options) {
+            (false, _) => app,
+            (true, Some(options)) => app.layer(options.layer()?),
+            (true, None) => app.layer(CorsLayer::permissive()),
+        };
+
+        Ok(app)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalStorage;
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn test_auth_requires_key_source() {
+        let storage = Arc::new(LocalStorage::new(
+            "./data".into(),
+            "http://localhost".into(),
+        ));
+        let result = ServerBuilder::new(storage, "http://localhost")
+            .auth(AuthOptions::default())
+            .build();
+
+        assert!(result.is_err());
+    }
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn test_multi_replica_auth_requires_data_url_secret() {
+        let storage: Arc<dyn Storage> = Arc::new(LocalStorage::new(
+            "./data".into(),
+            "http://localhost".into(),
+        ));
+        let options = AuthOptions {
+            hmac_secret: Some(b"shared-secret".to_vec()),
+            require_data_url_secret: true,
+            ..Default::default()
+        };
+
+        let result = ServerBuilder::new(storage.clone(), "http://localhost")
+            .auth(options.clone())
+            .build();
+        assert!(matches!(result, Err(Error::InvalidInput(_))));
+
+        let result = ServerBuilder::new(storage, "http://localhost")
+            .auth(AuthOptions {
+                data_url_secret: Some(b"data-url-secret".to_vec()),
+                data_url_key_id: Some("2025-03".to_string()),
+                ..options
+            })
+            .build();
+        assert!(result.is_ok());
+    }
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn test_auth_with_hmac_secret() {
+        let storage = Arc::new(LocalStorage::new(
+            "./data".into(),
+            "http://localhost".into(),
+        ));
+        let result = ServerBuilder::new(storage, "http://localhost")
+            .auth(AuthOptions {
+                hmac_secret: Some(b"shared-secret".to_vec()),
+                ..Default::default()
+            })
+            .build();
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_cors_options() {
+        let options = CorsOptions {
+            origins: vec!["https://app.example.com".to_string()],
+            methods: vec!["get".to_string(), "POST".to_string()],
+            headers: vec!["authorization".to_string(), "content-type".to_string()],
+            max_age: Some(600),
+            allow_credentials: true,
+        };
+        assert!(options.layer().is_ok());
+
+        // Credentials can't be combined with any-origin
+        let options = CorsOptions {
+            allow_credentials: true,
+            ..Default::default()
+        };
+        assert!(options.layer().is_err());
+
+        let options = CorsOptions {
+            methods: vec!["NOT A METHOD".to_string()],
+            ..Default::default()
+        };
+        assert!(options.layer().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        use axum::body::Body;
+        use axum::http::StatusCode;
+        use axum::routing::get;
+        use tower::ServiceExt;
+
+        let app = Router::new()
+            .route("/fast", get(|| async { "ok" }))
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    "late"
+                }),
+            )
+            .layer(axum::middleware::from_fn(|req: Request, next: Next| {
+                request_timeout(Duration::from_millis(20), req, next)
+            }));
+
+        let request = |uri| {
+            axum::http::Request::builder()
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(request("/fast")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.oneshot(request("/slow")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("\"error\":\"Timeout\""));
+    }
+
+    #[tokio::test]
+    async fn test_error_detail() {
+        use axum::body::Body;
+        use axum::http::StatusCode;
+        use tower::ServiceExt;
+
+        let storage = Arc::new(LocalStorage::new(
+            "./data".into(),
+            "http://localhost".into(),
+        ));
+        let request = || {
+            axum::http::Request::builder()
+                .uri("/reads/missing")
+                .header("x-request-id", "req-42")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let body = |response: Response| async move {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+
+        let app = ServerBuilder::new(storage.clone(), "http://localhost")
+            .build()
+            .unwrap();
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let json = body(response).await;
+        assert_eq!(json["htsget"].as_object().unwrap().len(), 2);
+
+        let app = ServerBuilder::new(storage, "http://localhost")
+            .error_detail(true)
+            .build()
+            .unwrap();
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let json = body(response).await;
+        assert_eq!(json["htsget"]["error"], "NotFound");
+        assert_eq!(json["htsget"]["retryable"], false);
+        assert_eq!(json["htsget"]["requestId"], "req-42");
+        assert!(json["htsget"]["detail"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_expose_internal_errors() {
+        use axum::body::Body;
+        use axum::routing::get;
+        use tower::ServiceExt;
+
+        let app = |expose_internal| {
+            Router::new()
+                .route(
+                    "/fail",
+                    get(|| async { Error::Internal("failed to read /srv/data/a.bam".into()) }),
+                )
+                .layer(axum::middleware::from_fn(
+                    move |req: Request, next: Next| error_body(false, expose_internal, req, next),
+                ))
+        };
+        let message = |app: Router| async move {
+            let request = axum::http::Request::builder()
+                .uri("/fail")
+                .body(Body::empty())
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert!(json["htsget"].get("detail").is_none());
+            json["htsget"]["message"].as_str().unwrap().to_string()
+        };
+
+        assert_eq!(
+            message(app(false)).await,
+            "internal error: see the server log"
+        );
+        assert_eq!(
+            message(app(true)).await,
+            "internal error: failed to read /srv/data/a.bam"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight_for_post() {
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+        use tower::ServiceExt;
+
+        let storage = Arc::new(LocalStorage::new(
+            "./data".into(),
+            "http://localhost".into(),
+        ));
+        let app = ServerBuilder::new(storage, "http://localhost")
+            .cors_options(CorsOptions {
+                origins: vec!["https://app.example.com".to_string()],
+                allow_credentials: true,
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+
+        let request = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/reads/sample1")
+            .header("origin", "https://app.example.com")
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "content-type")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(
+            headers["access-control-allow-origin"],
+            "https://app.example.com"
+        );
+        assert_eq!(headers["access-control-allow-credentials"], "true");
+        assert!(
+            headers["access-control-allow-methods"]
+                .to_str()
+                .unwrap()
+                .contains("POST")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compression_skips_binary_data() {
+        use axum::body::Body;
+        use axum::http::{Request, header};
+        use tower::ServiceExt;
+
+        let data_dir = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+        if !data_dir.join("mt.bam").exists() {
+            return;
+        }
+
+        let storage = Arc::new(LocalStorage::new(data_dir, "http://localhost".into()));
+        let app = ServerBuilder::new(storage, "http://localhost")
+            .build()
+            .unwrap();
+
+        let get = |uri: &str| {
+            Request::builder()
+                .uri(uri)
+                .header(header::ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let ticket = app.clone().oneshot(get("/reads/mt")).await.unwrap();
+        assert!(
+            ticket.headers()[header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with(crate::types::HTSGET_MEDIA_TYPE)
+        );
+        assert_eq!(ticket.headers()[header::CONTENT_ENCODING], "gzip");
+
+        let data = app.oneshot(get("/data/reads/mt")).await.unwrap();
+        assert!(data.headers().get(header::CONTENT_ENCODING).is_none());
+    }
+
+    #[test]
+    fn test_build_without_auth() {
+        let storage = Arc::new(LocalStorage::new(
+            "./data".into(),
+            "http://localhost".into(),
+        ));
+        assert!(
+            ServerBuilder::new(storage, "http://localhost")
+                .build()
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! State shared between replicas.
+//!
+//! Replicas behind a load balancer each keep their own caches and counters,
+//! which diverge: every replica fetches the same JWKS and object metadata,
+//! and each enforces quotas on its own share of a subject's requests. With
+//! the `redis` feature and `HTSGET_REDIS_URL` set, they share them instead:
+//!
+//! | State | Used by | Keys |
+//! |-------|---------|------|
+//! | S3 object metadata | `S3Storage` | `htsgetr:s3:<bucket>/<key>` |
+//! | JWKS | `JwksKeyProvider` | `htsgetr:jwks:<url>` |
+//! | Quota usage | [`RedisQuotaStore`](crate::quota) | `htsgetr:quota:<window>:<subject>` |
+//!
+//! In-process caches stay in front of the [`SharedCache`], so it is only
+//! consulted on a local miss. A shared cache that can't be reached behaves
+//! like an empty one: errors are logged and never fail a request.
+
+use async_trait::async_trait;
+use std::time::Duration;
+
+#[cfg(feature = "redis")]
+use crate::{Error, Result};
+
+/// Key prefix for everything htsgetr stores in a shared backend
+pub const KEY_PREFIX: &str = "htsgetr";
+
+/// A key-value cache shared by every replica. Values are opaque bytes,
+/// serialized by the caller.
+#[async_trait]
+pub trait SharedCache: Send + Sync {
+    /// The value stored under `key`, if any (and if the cache is reachable).
+    async fn get(&self, key: &str) -> Option<Vec<u8>>;
+
+    /// Store `value` under `key` for `ttl`.
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Duration);
+}
+
+/// Connection to a Redis server, shared by the caches and quota store.
+/// Reconnects automatically after connection failures.
+#[cfg(feature = "redis")]
+#[derive(Clone)]
+pub struct Redis {
+    connection: redis::aio::ConnectionManager,
+}
+
+#[cfg(feature = "redis")]
+impl Redis {
+    /// Connect to the Redis server at `url` (`redis://host:port/db`).
+    pub async fn connect(url: &str) -> Result<Self> {
+        let connection = redis::Client::open(url)
+            .map_err(|e| Error::InvalidInput(format!("invalid Redis URL {}: {}", url, e)))?
+            .get_connection_manager()
+            .await
+            .map_err(|e| Error::Internal(format!("failed to connect to Redis: {}", e)))?;
+        Ok(Self { connection })
+    }
+
+    /// A handle on the connection for running commands.
+    pub(crate) fn connection(&self) -> redis::aio::ConnectionManager {
+        self.connection.clone()
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl SharedCache for Redis {
+    async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        redis::cmd("GET")
+            .arg(key)
+            .query_async::<Option<Vec<u8>>>(&mut self.connection())
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("shared cache GET {} failed: {}", key, e);
+                None
+            })
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Duration) {
+        let result = redis::cmd("SET")
+            .arg(key)
+            .arg(value)
+            .arg("PX")
+            .arg(ttl.as_millis().max(1) as u64)
+            .query_async::<()>(&mut self.connection())
+            .await;
+        if let Err(e) = result {
+            tracing::warn!("shared cache SET {} failed: {}", key, e);
+        }
+    }
+}
@@ -0,0 +1,1143 @@
+//! Serving objects stored encrypted at rest as
+//! [Crypt4GH](https://samtools.github.io/hts-specs/crypt4gh.pdf).
+//!
+//! [`DecryptingStorage`] wraps another backend. IDs under a configured
+//! prefix are stored encrypted to this server, which holds the X25519
+//! private key for them; reads of those IDs return plaintext. A Crypt4GH
+//! file starts with a header of packets, each sealed to one recipient's
+//! public key. The ones sealed to this server carry the session keys the
+//! data is encrypted with, and optionally an edit list of plaintext spans to
+//! skip and keep. Then follow 64 KiB plaintext segments, each sealed with
+//! ChaCha20-Poly1305 as a 12-byte nonce, the ciphertext and a 16-byte tag,
+//! so a plaintext slice is served by decrypting only the segments it spans.
+//!
+//! Tickets for encrypted IDs always point at this server's `/data`
+//! endpoint, never at storage. Header tickets and metadata read a decrypted
+//! copy of just the file header, kept in the cache directory under a hashed
+//! name and replaced when the stored object changes. Records are never
+//! written out decrypted, so indexes (which locate records through the data
+//! file) aren't used, and region queries return the whole object.
+
+use super::{ByteRange, DataReader, FileInfo, ListedFile, Storage, data_endpoint_url};
+use crate::{
+    Error, Result,
+    formats::IndexReaders,
+    types::{Checksums, Format},
+};
+use async_trait::async_trait;
+use base64::Engine;
+use blake2::{Blake2b512, Digest};
+use bytes::{Bytes, BytesMut};
+use futures::stream;
+use moka::future::Cache;
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, Nonce, UnboundKey};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio_util::io::StreamReader;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// Plaintext bytes per segment
+const SEGMENT_SIZE: u64 = 64 * 1024;
+
+const NONCE_LEN: u64 = 12;
+const TAG_LEN: u64 = 16;
+
+/// Stored bytes per segment
+const SEALED_SEGMENT_SIZE: u64 = NONCE_LEN + SEGMENT_SIZE + TAG_LEN;
+
+/// Bytes read to parse the Crypt4GH header, which holds a few small packets
+const HEADER_READ: u64 = 64 * 1024;
+
+/// Plaintext read at first to find the end of the file header, doubled
+/// until the header fits, up to [`MAX_HEADER_COPY`]
+const HEADER_COPY_READ: u64 = 1024 * 1024;
+
+/// Largest file header copied for header tickets
+const MAX_HEADER_COPY: u64 = 64 * 1024 * 1024;
+
+/// Segments read from storage per request when streaming
+const SEGMENTS_PER_READ: u64 = 64;
+
+/// Header packets and data encrypted with X25519 and ChaCha20-Poly1305, the
+/// only method the specification defines
+const METHOD_CHACHA20_POLY1305: u32 = 0;
+
+/// Header packet carrying a session key
+const PACKET_DATA_KEY: u32 = 0;
+
+/// Header packet carrying an edit list
+const PACKET_EDIT_LIST: u32 = 1;
+
+const PRIVATE_KEY_BEGIN: &str = "-----BEGIN CRYPT4GH PRIVATE KEY-----";
+const PRIVATE_KEY_END: &str = "-----END CRYPT4GH PRIVATE KEY-----";
+
+/// Encrypted IDs starting with `id_prefix` (every ID, if empty), whose
+/// files are encrypted to the private key in `key_file`.
+///
+/// Routes are loaded from a TOML file of `[[route]]` tables (see
+/// [`DecryptionRoute::from_file`]). Key files are Crypt4GH private keys
+/// (as written by `crypt4gh-keygen`, without a passphrase), or hold the
+/// 32-byte X25519 key raw, as hex, or as base64.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DecryptionRoute {
+    pub id_prefix: String,
+    pub key_file: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DecryptionRoutes {
+    #[serde(default, rename = "route")]
+    routes: Vec<DecryptionRoute>,
+}
+
+impl DecryptionRoute {
+    /// Parse routes from TOML.
+    pub fn from_toml(s: &str) -> Result<Vec<Self>> {
+        let routes: DecryptionRoutes = toml::from_str(s)
+            .map_err(|e| Error::InvalidInput(format!("invalid decryption routes: {}", e)))?;
+        Ok(routes.routes)
+    }
+
+    /// Load routes from a TOML file.
+    pub fn from_file(path: &Path) -> Result<Vec<Self>> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::from_toml(&contents)
+    }
+
+    /// The route's private key
+    pub fn key(&self) -> Result<[u8; 32]> {
+        let contents = std::fs::read(&self.key_file).map_err(|e| {
+            Error::InvalidInput(format!(
+                "failed to read decryption key {}: {}",
+                self.key_file.display(),
+                e
+            ))
+        })?;
+        parse_private_key(&contents).map_err(|reason| {
+            Error::InvalidInput(format!("{}: {}", self.key_file.display(), reason))
+        })
+    }
+}
+
+/// The X25519 key of a Crypt4GH private key file, or of a 32-byte key given
+/// raw, as hex, or as base64
+fn parse_private_key(contents: &[u8]) -> std::result::Result<[u8; 32], &'static str> {
+    const INVALID: &str = "invalid Crypt4GH private key";
+
+    let text = std::str::from_utf8(contents).unwrap_or_default().trim();
+    let Some(body) = text.strip_prefix(PRIVATE_KEY_BEGIN) else {
+        return parse_key(contents)
+            .ok_or("not a Crypt4GH private key or a 32-byte key (raw, hex or base64)");
+    };
+    let body: String = body
+        .strip_suffix(PRIVATE_KEY_END)
+        .ok_or(INVALID)?
+        .split_whitespace()
+        .collect();
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(body)
+        .map_err(|_| INVALID)?;
+
+    // Magic, then the KDF, its parameters if any, the cipher and the key,
+    // each a length-prefixed string
+    let mut rest = decoded.strip_prefix(b"c4gh-v1").ok_or(INVALID)?;
+    if take_string(&mut rest).ok_or(INVALID)? != b"none" {
+        return Err("passphrase-protected Crypt4GH private keys are not supported");
+    }
+    if take_string(&mut rest).ok_or(INVALID)? != b"none" {
+        return Err(INVALID);
+    }
+    take_string(&mut rest)
+        .and_then(|key| key.try_into().ok())
+        .ok_or(INVALID)
+}
+
+/// The next big-endian `u16` length-prefixed string of a private key file
+fn take_string<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = u16::from_be_bytes(rest.get(..2)?.try_into().ok()?) as usize;
+    let string = rest.get(2..2 + len)?;
+    *rest = &rest[2 + len..];
+    Some(string)
+}
+
+/// A 32-byte key, raw or as hex or base64 text
+fn parse_key(contents: &[u8]) -> Option<[u8; 32]> {
+    if let Ok(key) = contents.try_into() {
+        return Some(key);
+    }
+
+    let text = std::str::from_utf8(contents).ok()?.trim();
+    let bytes = if text.len() == 64 && text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        (0..64)
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .ok()?
+    } else {
+        base64::engine::general_purpose::STANDARD
+            .decode(text)
+            .ok()?
+    };
+    bytes.try_into().ok()
+}
+
+/// Key of a header packet from `writer` to `reader`: the first half of
+/// BLAKE2b-512 over their X25519 shared secret and public keys, as
+/// libsodium's `crypto_kx` derives it
+fn shared_key(secret: &[u8; 32], reader: &PublicKey, writer: &PublicKey) -> [u8; 32] {
+    let digest = Blake2b512::new()
+        .chain_update(secret)
+        .chain_update(reader.as_bytes())
+        .chain_update(writer.as_bytes())
+        .finalize();
+    let mut key = [0; 32];
+    key.copy_from_slice(&digest[..32]);
+    key
+}
+
+fn chacha20_key(key: &[u8]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, key).expect("32-byte key"))
+}
+
+/// Open a header packet's writer public key, nonce and sealed payload with
+/// `secret`, or `None` if it is sealed to another recipient
+fn open_packet(secret: &StaticSecret, packet: &[u8]) -> Option<Vec<u8>> {
+    let writer = PublicKey::from(<[u8; 32]>::try_from(packet.get(..32)?).ok()?);
+    let reader = PublicKey::from(secret);
+    let dh = secret.diffie_hellman(&writer);
+    let key = chacha20_key(&shared_key(dh.as_bytes(), &reader, &writer));
+
+    let nonce = Nonce::try_assume_unique_for_key(packet.get(32..44)?).ok()?;
+    let mut payload = packet.get(44..)?.to_vec();
+    let opened = key.open_in_place(nonce, Aad::empty(), &mut payload).ok()?;
+    Some(opened.to_vec())
+}
+
+/// What the header packets sealed to this server say
+struct Header {
+    /// Bytes before the first segment
+    len: u64,
+    /// Session keys the segments may be encrypted with
+    keys: Vec<LessSafeKey>,
+    /// Lengths of plaintext to skip and keep in turn, if there is an edit
+    /// list
+    edits: Option<Vec<u64>>,
+}
+
+/// Parse a Crypt4GH header: magic, version, packet count, then packets that
+/// each start with their length and encryption method
+fn parse_header(bytes: &[u8], secret: &StaticSecret) -> Result<Header> {
+    let not_crypt4gh = || Error::Internal("object is not a Crypt4GH file".to_string());
+    let too_long = || {
+        Error::Internal(format!(
+            "Crypt4GH header is longer than {} bytes",
+            HEADER_READ
+        ))
+    };
+    let u32_at = |bytes: &[u8], offset: usize| -> Option<u32> {
+        Some(u32::from_le_bytes(
+            bytes.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+
+    if bytes.get(..8) != Some(b"crypt4gh") {
+        return Err(not_crypt4gh());
+    }
+    if u32_at(bytes, 8) != Some(1) {
+        return Err(Error::Internal(
+            "unsupported Crypt4GH version (expected 1)".to_string(),
+        ));
+    }
+
+    let packets = u32_at(bytes, 12).ok_or_else(not_crypt4gh)?;
+    let mut offset = 16;
+    let mut header = Header {
+        len: 0,
+        keys: Vec::new(),
+        edits: None,
+    };
+    for _ in 0..packets {
+        let packet_len = u32_at(bytes, offset).ok_or_else(too_long)? as usize;
+        if packet_len < 8 {
+            return Err(not_crypt4gh());
+        }
+        let packet = bytes
+            .get(offset + 4..offset + packet_len)
+            .ok_or_else(too_long)?;
+        offset += packet_len;
+
+        // Packets sealed some other way, or to other recipients, aren't
+        // for this server
+        if u32_at(packet, 0) != Some(METHOD_CHACHA20_POLY1305) {
+            continue;
+        }
+        let Some(payload) = open_packet(secret, &packet[4..]) else {
+            continue;
+        };
+
+        match u32_at(&payload, 0) {
+            Some(PACKET_DATA_KEY) => {
+                if u32_at(&payload, 4) != Some(METHOD_CHACHA20_POLY1305) {
+                    return Err(Error::Internal(
+                        "unsupported Crypt4GH data encryption method".to_string(),
+                    ));
+                }
+                let key = payload.get(8..40).ok_or_else(not_crypt4gh)?;
+                header.keys.push(chacha20_key(key));
+            }
+            Some(PACKET_EDIT_LIST) => {
+                if header.edits.is_some() {
+                    return Err(Error::Internal(
+                        "Crypt4GH header has more than one edit list".to_string(),
+                    ));
+                }
+                let count = u32_at(&payload, 4).ok_or_else(not_crypt4gh)? as usize;
+                let lengths = payload
+                    .get(8..)
+                    .filter(|lengths| lengths.len() == count * 8)
+                    .ok_or_else(not_crypt4gh)?;
+                header.edits = Some(
+                    lengths
+                        .chunks(8)
+                        .map(|length| u64::from_le_bytes(length.try_into().unwrap()))
+                        .collect(),
+                );
+            }
+            // Packet types added after version 1
+            _ => {}
+        }
+    }
+
+    if header.keys.is_empty() {
+        return Err(Error::Internal(
+            "no Crypt4GH header packet is encrypted to this server's key".to_string(),
+        ));
+    }
+    header.len = offset as u64;
+    Ok(header)
+}
+
+/// Decrypt whole sealed segments, each with whichever session key opens it
+fn open_segments(keys: &[LessSafeKey], sealed: &[u8]) -> Result<Vec<u8>> {
+    let mut plaintext = Vec::with_capacity(sealed.len());
+    for segment in sealed.chunks(SEALED_SEGMENT_SIZE as usize) {
+        if segment.len() <= (NONCE_LEN + TAG_LEN) as usize {
+            return Err(Error::Internal("truncated Crypt4GH segment".to_string()));
+        }
+        let (nonce, ciphertext) = segment.split_at(NONCE_LEN as usize);
+
+        let opened = keys
+            .iter()
+            .find_map(|key| {
+                let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+                let mut buf = ciphertext.to_vec();
+                let opened = key.open_in_place(nonce, Aad::empty(), &mut buf).ok()?;
+                Some(opened.to_vec())
+            })
+            .ok_or_else(|| {
+                Error::Internal(
+                    "failed to decrypt Crypt4GH segment (wrong key or corrupt data)".to_string(),
+                )
+            })?;
+        plaintext.extend_from_slice(&opened);
+    }
+    Ok(plaintext)
+}
+
+/// Where an encrypted object's segments are, and which plaintext it serves
+#[derive(Clone)]
+struct Layout {
+    header: Arc<Header>,
+    /// Stored size
+    size: u64,
+    /// Version of the stored object
+    version: String,
+}
+
+impl Layout {
+    /// Plaintext bytes in the segments, before any edit list
+    fn segments_size(&self) -> u64 {
+        let sealed = self.size.saturating_sub(self.header.len);
+        let last = sealed % SEALED_SEGMENT_SIZE;
+        sealed / SEALED_SEGMENT_SIZE * SEGMENT_SIZE + last.saturating_sub(NONCE_LEN + TAG_LEN)
+    }
+
+    /// Spans of the segments' plaintext the edit list keeps, in order
+    fn kept(&self) -> Vec<Range<u64>> {
+        let total = self.segments_size();
+        let Some(edits) = &self.header.edits else {
+            return vec![0..total];
+        };
+
+        // Skip, keep, skip, ...; with no length after the last skip, the
+        // rest is kept
+        let mut spans = Vec::new();
+        let mut pos = 0u64;
+        for lengths in edits.chunks(2) {
+            pos = pos.saturating_add(lengths[0]).min(total);
+            let end = match lengths.get(1) {
+                Some(&keep) => pos.saturating_add(keep).min(total),
+                None => total,
+            };
+            if end > pos {
+                spans.push(pos..end);
+            }
+            pos = end;
+        }
+        spans
+    }
+
+    /// Size of the plaintext served
+    fn plaintext_size(&self) -> u64 {
+        self.kept().iter().map(|span| span.end - span.start).sum()
+    }
+
+    /// Spans of the segments' plaintext holding served bytes `start..end`
+    fn segment_spans(&self, start: u64, end: u64) -> Vec<Range<u64>> {
+        let mut offset = 0;
+        let mut spans = Vec::new();
+        for span in self.kept() {
+            let len = span.end - span.start;
+            let (from, to) = (start.max(offset), end.min(offset + len));
+            if from < to {
+                spans.push(span.start + from - offset..span.start + to - offset);
+            }
+            offset += len;
+        }
+        spans
+    }
+
+    /// Index of the segment holding plaintext `offset`, and the stored
+    /// range of the segments from there through the one holding `end - 1`
+    fn sealed_range(&self, start: u64, end: u64) -> (u64, ByteRange) {
+        let first = start / SEGMENT_SIZE;
+        let last = (end - 1) / SEGMENT_SIZE;
+        let range = ByteRange {
+            start: self.header.len + first * SEALED_SEGMENT_SIZE,
+            end: Some((self.header.len + (last + 1) * SEALED_SEGMENT_SIZE).min(self.size)),
+        };
+        (first, range)
+    }
+}
+
+/// Plaintext bytes `start..end` of an encrypted object, `end` clamped to
+/// its size
+async fn read_plaintext(
+    storage: &dyn Storage,
+    layout: &Layout,
+    id: &str,
+    format: Format,
+    start: u64,
+    end: u64,
+) -> Result<Bytes> {
+    let end = end.min(layout.plaintext_size());
+    if start >= end {
+        return Ok(Bytes::new());
+    }
+
+    let mut plaintext = BytesMut::new();
+    for span in layout.segment_spans(start, end) {
+        let (first, range) = layout.sealed_range(span.start, span.end);
+        let sealed = storage.read_bytes(id, format, Some(range)).await?;
+        let opened = open_segments(&layout.header.keys, &sealed)?;
+
+        let offset = (span.start - first * SEGMENT_SIZE) as usize;
+        let len = (span.end - span.start) as usize;
+        let bytes = opened.get(offset..offset + len).ok_or_else(|| {
+            Error::Internal(format!("Crypt4GH object {} is shorter than its size", id))
+        })?;
+        plaintext.extend_from_slice(bytes);
+    }
+    Ok(plaintext.freeze())
+}
+
+/// Storage serving Crypt4GH-encrypted IDs of another backend as plaintext.
+pub struct DecryptingStorage {
+    inner: Arc<dyn Storage>,
+    /// Longest prefix first
+    routes: Vec<(String, StaticSecret)>,
+    base_url: String,
+    cache_dir: PathBuf,
+    /// Parsed header per object version
+    headers: Cache<(String, Format, String), Arc<Header>>,
+    /// Object version each header copy was made from
+    header_copies: Cache<PathBuf, String>,
+    /// Locks held while copying each object's header, by copy path, so
+    /// concurrent requests copy it once without waiting on other objects
+    copying: Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl DecryptingStorage {
+    /// Decrypt the IDs `routes` match, reading them from `inner`. Ticket
+    /// URLs for them are rooted at `base_url`, and their file headers are
+    /// copied under `cache_dir` for header tickets.
+    pub fn new(
+        inner: Arc<dyn Storage>,
+        routes: &[DecryptionRoute],
+        base_url: String,
+        cache_dir: PathBuf,
+    ) -> Result<Self> {
+        let mut keys = routes
+            .iter()
+            .map(|route| Ok((route.id_prefix.clone(), StaticSecret::from(route.key()?))))
+            .collect::<Result<Vec<_>>>()?;
+        keys.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+
+        Ok(Self {
+            inner,
+            routes: keys,
+            base_url,
+            cache_dir,
+            headers: Cache::new(10_000),
+            header_copies: Cache::new(10_000),
+            copying: Mutex::default(),
+        })
+    }
+
+    /// The private key `id` is encrypted to, if it is stored encrypted
+    fn key(&self, id: &str) -> Option<&StaticSecret> {
+        self.routes
+            .iter()
+            .find(|(prefix, _)| id.starts_with(prefix.as_str()))
+            .map(|(_, key)| key)
+    }
+
+    /// Where the header copy of `id` goes: named by a hash of the ID, so
+    /// no ID reaches outside `cache_dir`
+    fn header_path(&self, id: &str, format: Format) -> PathBuf {
+        let digest = Blake2b512::digest(id.as_bytes());
+        let name: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+        self.cache_dir
+            .join(format!("{}.{}", name, format.data_extensions()[0]))
+    }
+
+    async fn layout(&self, id: &str, format: Format, secret: &StaticSecret) -> Result<Layout> {
+        let info = self.inner.file_info(id, format).await?;
+        let version = info.version();
+
+        let cache_key = (id.to_string(), format, version.clone());
+        let header = match self.headers.get(&cache_key).await {
+            Some(header) => header,
+            None => {
+                let range = ByteRange {
+                    start: 0,
+                    end: Some(HEADER_READ.min(info.size)),
+                };
+                let bytes = self.inner.read_bytes(id, format, Some(range)).await?;
+                let header = Arc::new(parse_header(&bytes, secret)?);
+                self.headers.insert(cache_key, header.clone()).await;
+                header
+            }
+        };
+
+        Ok(Layout {
+            header,
+            size: info.size,
+            version,
+        })
+    }
+
+    /// Copy the decrypted file header of `id` to the cache, unless the copy
+    /// there is current or its format has no header
+    async fn copy_header(&self, id: &str, format: Format, secret: &StaticSecret) -> Result<()> {
+        let reader = IndexReaders::default().get(format)?;
+        if !reader.has_header() {
+            return Ok(());
+        }
+        let layout = self.layout(id, format, secret).await?;
+        let path = self.header_path(id, format);
+
+        let lock = self
+            .copying
+            .lock()
+            .unwrap()
+            .entry(path.clone())
+            .or_default()
+            .clone();
+        let result = {
+            let _copying = lock.lock().await;
+            // Another request may have copied it while this one waited
+            if self.header_copies.get(&path).await.as_ref() == Some(&layout.version)
+                && path.exists()
+            {
+                Ok(())
+            } else {
+                self.write_header_copy(id, format, &layout, &path).await
+            }
+        };
+        drop(lock);
+        // Forget locks no other request holds
+        self.copying
+            .lock()
+            .unwrap()
+            .retain(|_, lock| Arc::strong_count(lock) > 1);
+        result
+    }
+
+    /// Decrypt a growing prefix of `id` until its header parses, then keep
+    /// only the header's byte range at `path`
+    async fn write_header_copy(
+        &self,
+        id: &str,
+        format: Format,
+        layout: &Layout,
+        path: &Path,
+    ) -> Result<()> {
+        let reader = IndexReaders::default().get(format)?;
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        tokio::fs::create_dir_all(&self.cache_dir).await?;
+
+        let limit = layout.plaintext_size().min(MAX_HEADER_COPY);
+        let mut len = HEADER_COPY_READ.min(limit);
+        let header_end = loop {
+            let bytes = read_plaintext(self.inner.as_ref(), layout, id, format, 0, len).await?;
+            tokio::fs::write(&partial, &bytes).await?;
+            match reader.header_range(&partial).await {
+                Ok(range) => {
+                    let end = range.end.unwrap_or(len).min(len) as usize;
+                    tokio::fs::write(&partial, &bytes[..end]).await?;
+                    break end;
+                }
+                Err(_) if len < limit => len = (len * 2).min(limit),
+                Err(e) => {
+                    let _ = tokio::fs::remove_file(&partial).await;
+                    return Err(e);
+                }
+            }
+        };
+
+        tracing::info!(
+            "copied the {}-byte header of {} to {}",
+            header_end,
+            id,
+            path.display()
+        );
+        tokio::fs::rename(&partial, path).await?;
+        self.header_copies
+            .insert(path.to_path_buf(), layout.version.clone())
+            .await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Storage for DecryptingStorage {
+    async fn exists(&self, id: &str, format: Format) -> Result<bool> {
+        if !self.inner.exists(id, format).await? {
+            return Ok(false);
+        }
+        // Header tickets read the header from disk; whole-object tickets
+        // don't need it
+        if let Some(secret) = self.key(id) {
+            if let Err(e) = self.copy_header(id, format, secret).await {
+                tracing::warn!("failed to copy the header of {}: {}", id, e);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn file_info(&self, id: &str, format: Format) -> Result<FileInfo> {
+        let mut info = self.inner.file_info(id, format).await?;
+        if let Some(secret) = self.key(id) {
+            info.size = self.layout(id, format, secret).await?.plaintext_size();
+            info.has_index = false;
+            // Still changes with the object, but is no longer its MD5
+            info.etag = info
+                .etag
+                .map(|etag| format!("c4gh-{}", etag.trim_matches('"')));
+        }
+        Ok(info)
+    }
+
+    async fn stored_checksums(&self, id: &str, format: Format) -> Result<Checksums> {
+        if self.key(id).is_none() {
+            return self.inner.stored_checksums(id, format).await;
+        }
+        // Stored checksums are of the ciphertext
+        Ok(Checksums {
+            etag: self.file_info(id, format).await?.etag,
+            ..Default::default()
+        })
+    }
+
+    async fn data_url(&self, id: &str, format: Format, range: Option<ByteRange>) -> Result<String> {
+        if self.key(id).is_some() {
+            return Ok(data_endpoint_url(
+                &self.base_url,
+                id,
+                format,
+                range.as_ref(),
+            ));
+        }
+        self.inner.data_url(id, format, range).await
+    }
+
+    async fn ticket_data_url(
+        &self,
+        id: &str,
+        format: Format,
+        range: Option<ByteRange>,
+        ticket_urls: usize,
+    ) -> Result<String> {
+        if self.key(id).is_some() {
+            return Ok(data_endpoint_url(
+                &self.base_url,
+                id,
+                format,
+                range.as_ref(),
+            ));
+        }
+        self.inner
+            .ticket_data_url(id, format, range, ticket_urls)
+            .await
+    }
+
+    fn url_headers(&self, range: Option<&ByteRange>) -> Option<HashMap<String, String>> {
+        self.inner.url_headers(range)
+    }
+
+    fn ticket_url_headers(
+        &self,
+        id: &str,
+        format: Format,
+        range: Option<&ByteRange>,
+    ) -> Option<HashMap<String, String>> {
+        if self.key(id).is_some() {
+            return None;
+        }
+        self.inner.ticket_url_headers(id, format, range)
+    }
+
+    fn url_expiry(&self, ticket_urls: usize) -> Option<std::time::Duration> {
+        self.inner.url_expiry(ticket_urls)
+    }
+
+    async fn read_bytes(
+        &self,
+        id: &str,
+        format: Format,
+        range: Option<ByteRange>,
+    ) -> Result<Bytes> {
+        let Some(secret) = self.key(id) else {
+            return self.inner.read_bytes(id, format, range).await;
+        };
+
+        let layout = self.layout(id, format, secret).await?;
+        let (start, end) = range.map_or((0, u64::MAX), |r| (r.start, r.end.unwrap_or(u64::MAX)));
+        read_plaintext(self.inner.as_ref(), &layout, id, format, start, end).await
+    }
+
+    async fn read_stream(
+        &self,
+        id: &str,
+        format: Format,
+        range: Option<ByteRange>,
+    ) -> Result<DataReader> {
+        let Some(secret) = self.key(id) else {
+            return self.inner.read_stream(id, format, range).await;
+        };
+
+        let layout = self.layout(id, format, secret).await?;
+        let (start, end) = range.map_or((0, u64::MAX), |r| (r.start, r.end.unwrap_or(u64::MAX)));
+        let end = end.min(layout.plaintext_size());
+
+        // A batch of segments per storage read
+        let (inner, id) = (self.inner.clone(), id.to_string());
+        let chunk = SEGMENTS_PER_READ * SEGMENT_SIZE;
+        let chunks = stream::try_unfold(start, move |pos| {
+            let (inner, layout, id) = (inner.clone(), layout.clone(), id.clone());
+            async move {
+                if pos >= end {
+                    return Ok(None);
+                }
+                let next = ((pos / chunk + 1) * chunk).min(end);
+                let bytes = read_plaintext(inner.as_ref(), &layout, &id, format, pos, next)
+                    .await
+                    .map_err(|e| std::io::Error::other(e.to_string()))?;
+                Ok::<_, std::io::Error>(Some((bytes, next)))
+            }
+        });
+        Ok(Box::pin(StreamReader::new(Box::pin(chunks))))
+    }
+
+    async fn redirect_url(&self, id: &str, format: Format) -> Result<Option<String>> {
+        if self.key(id).is_some() {
+            return Ok(None);
+        }
+        self.inner.redirect_url(id, format).await
+    }
+
+    async fn list(&self) -> Result<Vec<ListedFile>> {
+        self.inner.list().await
+    }
+
+    async fn index_path(&self, id: &str, format: Format) -> Result<Option<PathBuf>> {
+        // Index queries read record blocks from the data file, which is
+        // only decrypted in memory
+        if self.key(id).is_some() {
+            return Ok(None);
+        }
+        self.inner.index_path(id, format).await
+    }
+
+    async fn gzi_path(&self, id: &str, format: Format) -> Result<Option<PathBuf>> {
+        if self.key(id).is_some() {
+            return Ok(None);
+        }
+        self.inner.gzi_path(id, format).await
+    }
+
+    fn file_path(&self, id: &str, format: Format) -> PathBuf {
+        if self.key(id).is_some() {
+            return self.header_path(id, format);
+        }
+        self.inner.file_path(id, format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalStorage;
+    use tokio::io::AsyncReadExt;
+
+    /// This server's private key
+    const KEY: [u8; 32] = [7; 32];
+
+    const SESSION_KEY: [u8; 32] = [9; 32];
+
+    /// A header packet of `payload`, sealed from a writer to `recipient`
+    fn packet(payload: &[u8], recipient: [u8; 32]) -> Vec<u8> {
+        let writer = StaticSecret::from([3; 32]);
+        let (writer_pk, reader_pk) = (
+            PublicKey::from(&writer),
+            PublicKey::from(&StaticSecret::from(recipient)),
+        );
+        let dh = writer.diffie_hellman(&reader_pk);
+        let key = chacha20_key(&shared_key(dh.as_bytes(), &reader_pk, &writer_pk));
+
+        let nonce = [1; 12];
+        let mut sealed = payload.to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut sealed,
+        )
+        .unwrap();
+
+        let mut packet = ((4 + 4 + 32 + 12 + sealed.len()) as u32)
+            .to_le_bytes()
+            .to_vec();
+        packet.extend_from_slice(&METHOD_CHACHA20_POLY1305.to_le_bytes());
+        packet.extend_from_slice(writer_pk.as_bytes());
+        packet.extend_from_slice(&nonce);
+        packet.extend_from_slice(&sealed);
+        packet
+    }
+
+    /// A Crypt4GH file of `plaintext` encrypted to `KEY`, with a packet for
+    /// another recipient first and the given edit list
+    fn encrypt(plaintext: &[u8], edits: Option<&[u64]>) -> Vec<u8> {
+        let mut data_key = PACKET_DATA_KEY.to_le_bytes().to_vec();
+        data_key.extend_from_slice(&METHOD_CHACHA20_POLY1305.to_le_bytes());
+        data_key.extend_from_slice(&SESSION_KEY);
+        let mut packets = vec![packet(&data_key, [5; 32]), packet(&data_key, KEY)];
+        if let Some(edits) = edits {
+            let mut edit_list = PACKET_EDIT_LIST.to_le_bytes().to_vec();
+            edit_list.extend_from_slice(&(edits.len() as u32).to_le_bytes());
+            for length in edits {
+                edit_list.extend_from_slice(&length.to_le_bytes());
+            }
+            packets.push(packet(&edit_list, KEY));
+        }
+
+        let mut file = b"crypt4gh".to_vec();
+        file.extend_from_slice(&1u32.to_le_bytes());
+        file.extend_from_slice(&(packets.len() as u32).to_le_bytes());
+        file.extend(packets.concat());
+
+        let key = chacha20_key(&SESSION_KEY);
+        for (i, segment) in plaintext.chunks(SEGMENT_SIZE as usize).enumerate() {
+            let mut nonce = [0u8; 12];
+            nonce[..8].copy_from_slice(&(i as u64).to_le_bytes());
+            let mut sealed = segment.to_vec();
+            key.seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut sealed,
+            )
+            .unwrap();
+            file.extend_from_slice(&nonce);
+            file.extend_from_slice(&sealed);
+        }
+        file
+    }
+
+    fn plaintext(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn storage(dir: &Path, key: [u8; 32]) -> DecryptingStorage {
+        let key_file = dir.join("secret.key");
+        std::fs::write(&key_file, key).unwrap();
+        let inner = Arc::new(LocalStorage::new(
+            dir.to_path_buf(),
+            "http://localhost".to_string(),
+        ));
+        let routes = [DecryptionRoute {
+            id_prefix: "secret/".to_string(),
+            key_file,
+        }];
+        DecryptingStorage::new(
+            inner,
+            &routes,
+            "http://localhost".to_string(),
+            dir.join("cache"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_private_key() {
+        let hex = "07".repeat(32);
+        assert_eq!(parse_private_key(&KEY), Ok(KEY));
+        assert_eq!(parse_private_key(format!("{}\n", hex).as_bytes()), Ok(KEY));
+        let base64 = base64::engine::general_purpose::STANDARD.encode(KEY);
+        assert_eq!(parse_private_key(base64.as_bytes()), Ok(KEY));
+        assert!(parse_private_key(b"too short").is_err());
+
+        // As crypt4gh-keygen writes it, with and without a passphrase
+        let key_file = |kdf: &[u8], cipher: &[u8]| {
+            let mut blob = b"c4gh-v1".to_vec();
+            for string in [kdf, cipher, &KEY[..]] {
+                blob.extend_from_slice(&(string.len() as u16).to_be_bytes());
+                blob.extend_from_slice(string);
+            }
+            format!(
+                "{}\n{}\n{}\n",
+                PRIVATE_KEY_BEGIN,
+                base64::engine::general_purpose::STANDARD.encode(blob),
+                PRIVATE_KEY_END
+            )
+        };
+        assert_eq!(
+            parse_private_key(key_file(b"none", b"none").as_bytes()),
+            Ok(KEY)
+        );
+        let protected = key_file(b"scrypt", b"chacha20_poly1305");
+        assert!(
+            parse_private_key(protected.as_bytes())
+                .unwrap_err()
+                .contains("passphrase")
+        );
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let routes = DecryptionRoute::from_toml(
+            r#"
+            [[route]]
+            id_prefix = "controlled/"
+            key_file = "/etc/htsgetr/controlled.sec"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].id_prefix, "controlled/");
+        assert!(DecryptionRoute::from_toml("[[route]]\nid_prefix = \"a/\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let file = encrypt(&plaintext(150_000), Some(&[10, 20]));
+        let header = parse_header(&file, &StaticSecret::from(KEY)).unwrap();
+        assert_eq!(header.keys.len(), 1);
+        assert_eq!(header.edits, Some(vec![10, 20]));
+
+        let layout = Layout {
+            size: file.len() as u64,
+            version: String::new(),
+            header: Arc::new(header),
+        };
+        assert_eq!(layout.segments_size(), 150_000);
+        assert_eq!(layout.plaintext_size(), 20);
+
+        let (first, range) = layout.sealed_range(70_000, 70_001);
+        assert_eq!(first, 1);
+        assert_eq!(range.start, layout.header.len + SEALED_SEGMENT_SIZE);
+        assert_eq!(range.end, Some(layout.header.len + 2 * SEALED_SEGMENT_SIZE));
+
+        // Only the other recipient's packets
+        assert!(parse_header(&file, &StaticSecret::from([8; 32])).is_err());
+        assert!(parse_header(b"BAM\x01", &StaticSecret::from(KEY)).is_err());
+    }
+
+    #[test]
+    fn test_edit_list() {
+        let layout = |edits: Option<Vec<u64>>| Layout {
+            header: Arc::new(Header {
+                len: 0,
+                keys: Vec::new(),
+                edits,
+            }),
+            size: 1000 + NONCE_LEN + TAG_LEN,
+            version: String::new(),
+        };
+
+        assert_eq!(layout(None).kept(), vec![0..1000]);
+        // A trailing skip keeps the rest
+        let edited = layout(Some(vec![100, 200, 300]));
+        assert_eq!(edited.kept(), vec![100..300, 600..1000]);
+        assert_eq!(edited.plaintext_size(), 600);
+        assert_eq!(edited.segment_spans(150, 250), vec![250..300, 600..650]);
+        assert_eq!(layout(Some(vec![0, 2000])).kept(), vec![0..1000]);
+    }
+
+    #[tokio::test]
+    async fn test_reads_decrypt() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("secret")).unwrap();
+        let source = plaintext(200_000);
+        std::fs::write(dir.path().join("secret/sample.bam"), encrypt(&source, None)).unwrap();
+        std::fs::write(dir.path().join("open.bam"), &source).unwrap();
+        let storage = storage(dir.path(), KEY);
+
+        let info = storage
+            .file_info("secret/sample", Format::Bam)
+            .await
+            .unwrap();
+        assert_eq!(info.size, 200_000);
+
+        // Slices spanning segment boundaries
+        for (start, end) in [
+            (0, 10),
+            (65_530, 65_550),
+            (1_000, 140_000),
+            (199_990, 250_000),
+        ] {
+            let range = ByteRange {
+                start,
+                end: Some(end),
+            };
+            let bytes = storage
+                .read_bytes("secret/sample", Format::Bam, Some(range))
+                .await
+                .unwrap();
+            assert_eq!(
+                &bytes[..],
+                &source[start as usize..end.min(200_000) as usize]
+            );
+        }
+
+        let mut reader = storage
+            .read_stream("secret/sample", Format::Bam, None)
+            .await
+            .unwrap();
+        let mut streamed = Vec::new();
+        reader.read_to_end(&mut streamed).await.unwrap();
+        assert_eq!(streamed, source);
+
+        // Other IDs pass through, as do their URLs
+        let bytes = storage.read_bytes("open", Format::Bam, None).await.unwrap();
+        assert_eq!(&bytes[..], &source[..]);
+        assert_eq!(
+            storage.file_path("open", Format::Bam),
+            dir.path().join("open.bam")
+        );
+        let url = storage
+            .data_url("secret/sample", Format::Bam, None)
+            .await
+            .unwrap();
+        assert!(url.starts_with("http://localhost/data/reads/secret/sample"));
+    }
+
+    #[tokio::test]
+    async fn test_reads_apply_edit_list() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("secret")).unwrap();
+        let source = plaintext(200_000);
+        let file = encrypt(&source, Some(&[1_000, 70_000, 50_000]));
+        std::fs::write(dir.path().join("secret/sample.bam"), file).unwrap();
+        let storage = storage(dir.path(), KEY);
+
+        let edited = [&source[1_000..71_000], &source[121_000..]].concat();
+        let info = storage
+            .file_info("secret/sample", Format::Bam)
+            .await
+            .unwrap();
+        assert_eq!(info.size, edited.len() as u64);
+
+        let range = ByteRange {
+            start: 69_000,
+            end: Some(72_000),
+        };
+        let bytes = storage
+            .read_bytes("secret/sample", Format::Bam, Some(range))
+            .await
+            .unwrap();
+        assert_eq!(&bytes[..], &edited[69_000..72_000]);
+    }
+
+    #[tokio::test]
+    async fn test_header_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("secret")).unwrap();
+        let bam = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/sample.bam");
+        let source = std::fs::read(&bam).unwrap();
+        std::fs::write(dir.path().join("secret/sample.bam"), encrypt(&source, None)).unwrap();
+        std::fs::write(
+            dir.path().join("secret/notes.bam"),
+            encrypt(&plaintext(1_000), None),
+        )
+        .unwrap();
+        let storage = storage(dir.path(), KEY);
+
+        // Hashed, so IDs can't name paths outside the cache
+        let path = storage.file_path("secret/../../etc/passwd", Format::Bam);
+        assert_eq!(path.parent(), Some(dir.path().join("cache").as_path()));
+        assert_ne!(path, storage.file_path("secret/sample", Format::Bam));
+
+        // Just the header's blocks
+        assert!(storage.exists("secret/sample", Format::Bam).await.unwrap());
+        let header_range = crate::formats::BamIndexReader::header_range(&bam)
+            .await
+            .unwrap();
+        let copy = std::fs::read(storage.file_path("secret/sample", Format::Bam)).unwrap();
+        assert_eq!(copy.len() as u64, header_range.end.unwrap());
+        assert_eq!(&copy[..], &source[..copy.len()]);
+        assert!(storage.copying.lock().unwrap().is_empty());
+
+        // Indexes aren't used, and a header that doesn't parse isn't copied
+        assert_eq!(
+            storage
+                .index_path("secret/sample", Format::Bam)
+                .await
+                .unwrap(),
+            None
+        );
+        assert!(storage.exists("secret/notes", Format::Bam).await.unwrap());
+        assert!(!storage.file_path("secret/notes", Format::Bam).exists());
+    }
+
+    #[tokio::test]
+    async fn test_wrong_key_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("secret")).unwrap();
+        std::fs::write(
+            dir.path().join("secret/sample.bam"),
+            encrypt(&plaintext(1_000), None),
+        )
+        .unwrap();
+        let storage = storage(dir.path(), [8; 32]);
+
+        let result = storage.read_bytes("secret/sample", Format::Bam, None).await;
+        assert!(matches!(result, Err(Error::Internal(_))));
+    }
+}
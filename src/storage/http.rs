@@ -6,17 +6,90 @@
 //! # Features
 //!
 //! - Direct URL access for data (clients fetch from remote server)
-//! - Local caching of index files for efficient repeated queries
-//! - Support for HTTP Range requests
-
-use super::{ByteRange, FileInfo, Storage};
+//! - Local caching of index files for efficient repeated queries, downloaded
+//!   in concurrent ranged requests (see [`ChunkedDownload`])
+//! - Support for HTTP Range requests, streamed without buffering whole ranges
+//! - Retries of transient failures (see [`RetryPolicy`]) and per-request
+//!   timeouts
+//! - Static headers on every upstream request (e.g. an `Authorization` bearer
+//!   token or API key for an internal object gateway), optionally passed on
+//!   to clients in ticket `headers`
+//! - Forwarding of selected client request headers upstream (see
+//!   [`with_request_headers`])
+//! - URL templates for origins that don't name files `{base}/{id}.{ext}`
+//!   (see [`HttpStorage::with_url_template`])
+//! - Redirects followed up to a configurable limit, and origins that only
+//!   answer GET, such as presigned URLs they redirect to
+
+use super::naming::{Naming, ResolvedExtensions};
+use super::retry::{DEFAULT_TIMEOUT, Failure, RetryClass, RetryPolicy, with_timeout};
+use super::{
+    ByteRange, ChunkedDownload, DataReader, FileInfo, Storage, is_empty_range, range_header,
+};
 use crate::{Error, Result, types::Format};
 use async_trait::async_trait;
 use bytes::Bytes;
+use futures::TryStreamExt;
 use reqwest::Client;
-use std::path::PathBuf;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
+use tokio_util::io::StreamReader;
+
+/// Upstream URL of a file when no template is configured
+pub const DEFAULT_URL_TEMPLATE: &str = "{base}/{id}.{ext}";
+
+/// Redirects followed per upstream request when no limit is configured
+pub const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+tokio::task_local! {
+    /// Headers of the client request being served
+    static REQUEST_HEADERS: HeaderMap;
+}
+
+/// Run `f` as part of serving a client request with `headers`, so that
+/// [`HttpStorage`] forwards the ones it is configured to
+/// ([`HttpStorage::with_forwarded_headers`]) on the upstream requests `f`
+/// makes. Work spawned onto other tasks doesn't see them.
+pub async fn with_request_headers<F: Future>(headers: HeaderMap, f: F) -> F::Output {
+    REQUEST_HEADERS.scope(headers, f).await
+}
+
+/// Parse comma-separated `Name: value` headers, e.g.
+/// `Authorization: Bearer abc123,X-Api-Key: secret`.
+pub fn parse_headers(spec: &str) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for header in spec.split(',').filter(|h| !h.trim().is_empty()) {
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| Error::InvalidInput(format!("expected Name: value, got {}", header)))?;
+        let name: HeaderName = name
+            .trim()
+            .parse()
+            .map_err(|_| Error::InvalidInput(format!("invalid header name {}", name.trim())))?;
+        let mut value = HeaderValue::from_str(value.trim())
+            .map_err(|_| Error::InvalidInput(format!("invalid value for header {}", name)))?;
+        // Keep credentials out of debug output
+        value.set_sensitive(true);
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+/// Parse comma-separated header names, e.g. `Authorization,X-Request-Id`.
+pub fn parse_header_names(spec: &str) -> Result<Vec<HeaderName>> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            name.parse()
+                .map_err(|_| Error::InvalidInput(format!("invalid header name {}", name)))
+        })
+        .collect()
+}
 
 /// HTTP/HTTPS storage backend for genomic data files.
 pub struct HttpStorage {
@@ -24,6 +97,20 @@ pub struct HttpStorage {
     base_url: String,
     index_base_url: Option<String>,
     cache_dir: PathBuf,
+    retry: RetryPolicy,
+    timeout: Duration,
+    index_download: ChunkedDownload,
+    naming: Naming,
+    /// Data extension each file was found under
+    resolved: ResolvedExtensions,
+    /// Sent on every upstream request
+    headers: HeaderMap,
+    /// Client request headers sent on upstream requests
+    forwarded_headers: Vec<HeaderName>,
+    /// Of `headers`, those clients must send too, put in ticket entries
+    ticket_headers: Vec<HeaderName>,
+    /// Upstream URL of a file, with `{base}`, `{id}` and `{ext}` placeholders
+    url_template: String,
 }
 
 impl HttpStorage {
@@ -39,9 +126,7 @@ impl HttpStorage {
         index_base_url: Option<String>,
         cache_dir: PathBuf,
     ) -> Result<Self> {
-        let client = Client::builder()
-            .build()
-            .map_err(|e| Error::Internal(format!("failed to create HTTP client: {}", e)))?;
+        let client = http_client(DEFAULT_MAX_REDIRECTS)?;
 
         // Ensure cache directory exists
         fs::create_dir_all(&cache_dir)
@@ -53,146 +138,285 @@ impl HttpStorage {
             base_url: base_url.trim_end_matches('/').to_string(),
             index_base_url: index_base_url.map(|u| u.trim_end_matches('/').to_string()),
             cache_dir,
+            retry: RetryPolicy::default(),
+            timeout: DEFAULT_TIMEOUT,
+            index_download: ChunkedDownload::default(),
+            naming: Naming::default(),
+            resolved: ResolvedExtensions::default(),
+            headers: HeaderMap::new(),
+            forwarded_headers: Vec::new(),
+            ticket_headers: Vec::new(),
+            url_template: DEFAULT_URL_TEMPLATE.to_string(),
         })
     }
 
-    /// Construct the URL for a data file.
-    fn file_url(&self, id: &str, format: Format) -> String {
-        let ext = Self::file_extension(format);
-        format!("{}/{}.{}", self.base_url, id, ext)
+    /// Follow at most `max` redirects per upstream request (`0` to follow
+    /// none). Ticket URLs still point at the origin; clients follow its
+    /// redirects themselves.
+    pub fn with_max_redirects(mut self, max: usize) -> Result<Self> {
+        self.client = http_client(max)?;
+        Ok(self)
     }
 
-    /// Construct the URL for an index file.
-    fn index_url(&self, id: &str, format: Format, appended: bool) -> Option<String> {
-        let idx_ext = Self::index_extension(format)?;
-        let data_ext = Self::file_extension(format);
-        let base = self.index_base_url.as_ref().unwrap_or(&self.base_url);
+    /// Locate files with `template` instead of [`DEFAULT_URL_TEMPLATE`],
+    /// e.g. `{base}/{id}/{id}.{ext}`. `{base}` is the data or index base
+    /// URL, and `{ext}` the data extension or, for indexes, the index name
+    /// after the ID (`bam.bai`, `bai`).
+    pub fn with_url_template(mut self, template: impl Into<String>) -> Self {
+        self.url_template = template.into();
+        self
+    }
 
-        Some(if appended {
-            // e.g., sample.bam.bai
-            format!("{}/{}.{}.{}", base, id, data_ext, idx_ext)
-        } else {
-            // e.g., sample.bai
-            format!("{}/{}.{}", base, id, idx_ext)
-        })
+    /// Retry transient request failures with `policy`.
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
     }
 
-    fn file_extension(format: Format) -> &'static str {
-        match format {
-            Format::Bam => "bam",
-            Format::Cram => "cram",
-            Format::Vcf => "vcf.gz",
-            Format::Bcf => "bcf",
-            Format::Fasta => "fa",
-            Format::Fastq => "fq.gz",
-        }
+    /// Give up on a request attempt that hasn't responded within `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Fetch index files in ranged requests split according to `download`.
+    /// The origin must honor `Range` headers.
+    pub fn with_index_download(mut self, download: ChunkedDownload) -> Self {
+        self.index_download = download;
+        self
+    }
+
+    /// Use `naming` for data and index file extensions.
+    pub fn with_naming(mut self, naming: Naming) -> Self {
+        self.naming = naming;
+        self
     }
 
-    fn index_extension(format: Format) -> Option<&'static str> {
-        match format {
-            Format::Bam => Some("bai"),
-            Format::Cram => Some("crai"),
-            Format::Vcf => Some("tbi"),
-            Format::Bcf => Some("csi"),
-            Format::Fasta => Some("fai"),
-            Format::Fastq => None,
+    /// Send `headers` on every upstream request, e.g. credentials for an
+    /// origin that requires them.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Copy the headers named `names` from the client request being served
+    /// (see [`with_request_headers`]) onto upstream requests, e.g. to pass
+    /// a user's own token to the origin.
+    pub fn with_forwarded_headers(mut self, names: Vec<HeaderName>) -> Self {
+        self.forwarded_headers = names;
+        self
+    }
+
+    /// Put the headers named `names`, of those given to
+    /// [`with_headers`](Self::with_headers), in ticket entries, for origins
+    /// that clients can only fetch from with them.
+    pub fn with_ticket_headers(mut self, names: Vec<HeaderName>) -> Self {
+        self.ticket_headers = names;
+        self
+    }
+
+    /// Add the static and forwarded headers to an upstream request.
+    fn upstream(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let mut headers = self.headers.clone();
+        if !self.forwarded_headers.is_empty() {
+            REQUEST_HEADERS
+                .try_with(|request_headers| {
+                    for name in &self.forwarded_headers {
+                        for value in request_headers.get_all(name) {
+                            headers.append(name.clone(), value.clone());
+                        }
+                    }
+                })
+                .ok();
         }
+        request.headers(headers)
+    }
+
+    /// Send the request built by `request`, retrying transient failures
+    /// (connection errors, timeouts, 429 and 5xx responses). The timeout
+    /// covers each attempt up to the response headers, not the body.
+    async fn send(
+        &self,
+        operation: &str,
+        request: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        self.retry
+            .run(operation, || async {
+                let response = with_timeout(operation, self.timeout, async {
+                    self.upstream(request())
+                        .send()
+                        .await
+                        .map_err(request_failure)
+                })
+                .await?;
+                let status = response.status();
+
+                match RetryClass::from_status(status.as_u16()) {
+                    Some(class) => Err(Failure::transient(
+                        Error::Internal(format!("HTTP {} from {}", status, response.url())),
+                        class,
+                    )),
+                    None => Ok(response),
+                }
+            })
+            .await
+    }
+
+    /// Data extension of a file: the one it was last found under, or the
+    /// format's primary extension.
+    fn data_extension(&self, id: &str, format: Format) -> String {
+        self.resolved
+            .get(id, format)
+            .unwrap_or_else(|| self.naming.data_extensions(format)[0].clone())
     }
 
-    /// Get the local cache path for an index file.
-    fn index_cache_path(&self, id: &str, format: Format, appended: bool) -> PathBuf {
-        let ext = Self::file_extension(format);
-        let idx_ext = Self::index_extension(format).unwrap_or("idx");
+    /// Fill in the URL template.
+    fn url(&self, base: &str, id: &str, ext: &str) -> String {
+        self.url_template
+            .replace("{base}", base)
+            .replace("{id}", id)
+            .replace("{ext}", ext)
+    }
 
-        if appended {
-            self.cache_dir.join(format!("{}.{}.{}", id, ext, idx_ext))
-        } else {
-            self.cache_dir.join(format!("{}.{}", id, idx_ext))
+    /// Construct the URL for a data file.
+    fn file_url(&self, id: &str, format: Format) -> String {
+        self.url(&self.base_url, id, &self.data_extension(id, format))
+    }
+
+    /// The URL of a data file, probing each candidate extension with HEAD
+    /// requests the first time. Falls back to the primary extension if
+    /// none exists.
+    async fn data_file_url(&self, id: &str, format: Format) -> String {
+        let exts = self.naming.data_extensions(format);
+        if exts.len() > 1 && self.resolved.get(id, format).is_none() {
+            for ext in exts {
+                let url = self.url(&self.base_url, id, ext);
+                if self.url_exists(&url).await {
+                    self.resolved.insert(id, format, ext);
+                    return url;
+                }
+            }
         }
+        self.file_url(id, format)
+    }
+
+    /// Candidate index names after the ID (e.g. `bam.bai`, then `bai`) and
+    /// their URLs, in the order to probe them.
+    fn index_urls(&self, id: &str, format: Format) -> Vec<(String, String)> {
+        let base = self.index_base_url.as_ref().unwrap_or(&self.base_url);
+        self.naming
+            .index_names("", &self.data_extension(id, format), format)
+            .into_iter()
+            .map(|name| {
+                let ext = name.trim_start_matches('.').to_string();
+                let url = self.url(base, id, &ext);
+                (ext, url)
+            })
+            .collect()
+    }
+
+    /// Get the local cache path for an index, e.g. `sample.bam.bai`.
+    fn index_cache_path(&self, id: &str, ext: &str) -> PathBuf {
+        self.cache_dir.join(format!("{}.{}", id, ext))
     }
 
     /// Get the local cache path for a data file (used for header reading).
     fn data_cache_path(&self, id: &str, format: Format) -> PathBuf {
-        let ext = Self::file_extension(format);
+        let ext = self.data_extension(id, format);
         self.cache_dir.join(format!("{}.{}", id, ext))
     }
 
+    /// HEAD request for a URL. Origins that refuse HEAD, such as presigned
+    /// GET URLs an origin redirects to, are asked for the first byte instead.
+    async fn head(&self, url: &str) -> Result<reqwest::Response> {
+        let response = self.send("HEAD", || self.client.head(url)).await?;
+        match response.status() {
+            reqwest::StatusCode::FORBIDDEN | reqwest::StatusCode::METHOD_NOT_ALLOWED => {
+                self.send("GET", || {
+                    self.client
+                        .get(url)
+                        .header(reqwest::header::RANGE, "bytes=0-0")
+                })
+                .await
+            }
+            _ => Ok(response),
+        }
+    }
+
     /// Check if a URL exists via HEAD request.
     async fn url_exists(&self, url: &str) -> bool {
-        self.client
-            .head(url)
-            .send()
+        self.head(url)
             .await
             .map(|r| r.status().is_success())
             .unwrap_or(false)
     }
 
-    /// Get the content length of a URL via HEAD request.
-    async fn get_content_length(&self, url: &str) -> Result<u64> {
-        let response = self
-            .client
-            .head(url)
-            .send()
-            .await
-            .map_err(|e| Error::Internal(format!("HTTP HEAD request failed: {}", e)))?;
+    /// Get the content length, Last-Modified and ETag of a URL via HEAD request.
+    async fn head_metadata(
+        &self,
+        url: &str,
+    ) -> Result<(u64, Option<std::time::SystemTime>, Option<String>)> {
+        let response = self.head(url).await?;
 
         if !response.status().is_success() {
             return Err(Error::NotFound(url.to_string()));
         }
 
-        response
-            .headers()
-            .get(reqwest::header::CONTENT_LENGTH)
-            .and_then(|v| v.to_str().ok())
-            .and_then(|v| v.parse().ok())
-            .ok_or_else(|| Error::Internal("missing Content-Length header".to_string()))
-    }
-
-    /// Download a URL to a local file.
-    async fn download_to_cache(&self, url: &str, cache_path: &PathBuf) -> Result<()> {
-        let response = self
-            .client
-            .get(url)
-            .send()
-            .await
-            .map_err(|e| Error::Internal(format!("HTTP GET request failed: {}", e)))?;
+        let header = |name: reqwest::header::HeaderName| {
+            response.headers().get(name).and_then(|v| v.to_str().ok())
+        };
 
-        if !response.status().is_success() {
-            return Err(Error::NotFound(url.to_string()));
+        // A ranged GET's size is in its Content-Range
+        let size = match response.status() {
+            reqwest::StatusCode::PARTIAL_CONTENT => {
+                header(reqwest::header::CONTENT_RANGE).and_then(content_range_size)
+            }
+            _ => header(reqwest::header::CONTENT_LENGTH).and_then(|v| v.parse().ok()),
         }
+        .ok_or_else(|| Error::Internal("missing Content-Length header".to_string()))?;
+        let modified =
+            header(reqwest::header::LAST_MODIFIED).and_then(|v| httpdate::parse_http_date(v).ok());
+        let etag = header(reqwest::header::ETAG).map(str::to_string);
 
-        let bytes = response
-            .bytes()
-            .await
-            .map_err(|e| Error::Internal(format!("failed to read HTTP response: {}", e)))?;
-
-        let mut file = fs::File::create(cache_path)
-            .await
-            .map_err(|e| Error::Internal(format!("failed to create cache file: {}", e)))?;
+        Ok((size, modified, etag))
+    }
 
-        file.write_all(&bytes)
+    /// Download a URL of `size` bytes to a local file.
+    async fn download_to_cache(&self, url: &str, size: u64, cache_path: &Path) -> Result<()> {
+        self.index_download
+            .download(size, cache_path, move |range| async move {
+                self.download_range(url, Some(&range)).await
+            })
             .await
-            .map_err(|e| Error::Internal(format!("failed to write cache file: {}", e)))?;
-
-        Ok(())
     }
 
     /// Download a byte range from a URL.
     async fn download_range(&self, url: &str, range: Option<&ByteRange>) -> Result<Bytes> {
-        let mut request = self.client.get(url);
-
-        if let Some(r) = range {
-            let range_header = match r.end {
-                Some(end) => format!("bytes={}-{}", r.start, end),
-                None => format!("bytes={}-", r.start),
-            };
-            request = request.header(reqwest::header::RANGE, range_header);
+        if is_empty_range(range) {
+            return Ok(Bytes::new());
         }
 
-        let response = request
-            .send()
+        self.get_range(url, range)
+            .await?
+            .bytes()
             .await
-            .map_err(|e| Error::Internal(format!("HTTP GET request failed: {}", e)))?;
+            .map_err(|e| Error::Internal(format!("failed to read HTTP response: {}", e)))
+    }
+
+    /// Request a byte range from a URL, returning the response before its
+    /// body is read.
+    async fn get_range(&self, url: &str, range: Option<&ByteRange>) -> Result<reqwest::Response> {
+        let range_value = range.and_then(range_header);
+
+        let response = self
+            .send("GET", || {
+                let request = self.client.get(url);
+                match &range_value {
+                    Some(range) => request.header(reqwest::header::RANGE, range),
+                    None => request,
+                }
+            })
+            .await?;
 
         if !response.status().is_success()
             && response.status() != reqwest::StatusCode::PARTIAL_CONTENT
@@ -200,49 +424,92 @@ impl HttpStorage {
             return Err(Error::NotFound(url.to_string()));
         }
 
-        response
-            .bytes()
-            .await
-            .map_err(|e| Error::Internal(format!("failed to read HTTP response: {}", e)))
+        Ok(response)
+    }
+}
+
+/// HTTP client following at most `max_redirects` redirects.
+fn http_client(max_redirects: usize) -> Result<Client> {
+    let policy = match max_redirects {
+        0 => reqwest::redirect::Policy::none(),
+        max => reqwest::redirect::Policy::limited(max),
+    };
+    Client::builder()
+        .redirect(policy)
+        .build()
+        .map_err(|e| Error::Internal(format!("failed to create HTTP client: {}", e)))
+}
+
+/// Full size from a `Content-Range: bytes 0-0/1234` header.
+fn content_range_size(value: &str) -> Option<u64> {
+    value.rsplit_once('/')?.1.parse().ok()
+}
+
+fn request_failure(e: reqwest::Error) -> Failure {
+    let error = Error::Internal(format!("HTTP request failed: {}", e));
+    if e.is_timeout() {
+        Failure::transient(error, RetryClass::Timeout)
+    } else if e.is_connect() || e.is_request() {
+        Failure::transient(error, RetryClass::Connect)
+    } else {
+        Failure::permanent(error)
     }
 }
 
 #[async_trait]
 impl Storage for HttpStorage {
     async fn exists(&self, id: &str, format: Format) -> Result<bool> {
-        let url = self.file_url(id, format);
+        let url = self.data_file_url(id, format).await;
         Ok(self.url_exists(&url).await)
     }
 
     async fn file_info(&self, id: &str, format: Format) -> Result<FileInfo> {
-        let url = self.file_url(id, format);
-        let size = self.get_content_length(&url).await?;
-
-        // Check if index exists (try both naming conventions)
-        let has_index = if let Some(appended_url) = self.index_url(id, format, true) {
-            if self.url_exists(&appended_url).await {
-                true
-            } else if let Some(replaced_url) = self.index_url(id, format, false) {
-                self.url_exists(&replaced_url).await
-            } else {
-                false
+        let url = self.data_file_url(id, format).await;
+        let (size, modified, etag) = self.head_metadata(&url).await?;
+
+        // Check if an index exists under any naming convention
+        let mut has_index = false;
+        for (_, index_url) in self.index_urls(id, format) {
+            if self.url_exists(&index_url).await {
+                has_index = true;
+                break;
             }
-        } else {
-            false
-        };
+        }
 
         Ok(FileInfo {
             id: id.to_string(),
             format,
             size,
             has_index,
+            modified,
+            etag,
         })
     }
 
-    fn data_url(&self, id: &str, format: Format, _range: Option<ByteRange>) -> String {
-        // Return the direct HTTP URL
-        // htsget clients will use Range headers when fetching
-        self.file_url(id, format)
+    async fn data_url(
+        &self,
+        id: &str,
+        format: Format,
+        _range: Option<ByteRange>,
+    ) -> Result<String> {
+        // Return the direct HTTP URL; the range goes in the ticket's
+        // headers (see url_headers)
+        Ok(self.file_url(id, format))
+    }
+
+    fn url_headers(&self, range: Option<&ByteRange>) -> Option<HashMap<String, String>> {
+        let mut headers: HashMap<String, String> = self
+            .ticket_headers
+            .iter()
+            .filter_map(|name| {
+                let value = self.headers.get(name)?.to_str().ok()?;
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect();
+        if let Some(range) = range.and_then(range_header) {
+            headers.insert("Range".to_string(), range);
+        }
+        (!headers.is_empty()).then_some(headers)
     }
 
     async fn read_bytes(
@@ -251,37 +518,42 @@ impl Storage for HttpStorage {
         format: Format,
         range: Option<ByteRange>,
     ) -> Result<Bytes> {
-        let url = self.file_url(id, format);
+        let url = self.data_file_url(id, format).await;
         self.download_range(&url, range.as_ref()).await
     }
 
-    async fn index_path(&self, id: &str, format: Format) -> Result<Option<PathBuf>> {
-        // Try appended index first (e.g., sample.bam.bai)
-        if let Some(url) = self.index_url(id, format, true) {
-            let cache_path = self.index_cache_path(id, format, true);
+    async fn read_stream(
+        &self,
+        id: &str,
+        format: Format,
+        range: Option<ByteRange>,
+    ) -> Result<DataReader> {
+        if is_empty_range(range.as_ref()) {
+            return Ok(Box::pin(tokio::io::empty()));
+        }
 
-            // Check cache first
-            if cache_path.exists() {
-                return Ok(Some(cache_path));
-            }
+        let url = self.data_file_url(id, format).await;
+        let response = self.get_range(&url, range.as_ref()).await?;
+        let body = response.bytes_stream().map_err(std::io::Error::other);
 
-            // Check if exists remotely and download
-            if self.url_exists(&url).await {
-                self.download_to_cache(&url, &cache_path).await?;
-                return Ok(Some(cache_path));
-            }
-        }
+        Ok(Box::pin(StreamReader::new(body)))
+    }
 
-        // Try replaced extension (e.g., sample.bai)
-        if let Some(url) = self.index_url(id, format, false) {
-            let cache_path = self.index_cache_path(id, format, false);
+    async fn index_path(&self, id: &str, format: Format) -> Result<Option<PathBuf>> {
+        self.data_file_url(id, format).await;
+
+        // e.g. sample.bam.bai, then sample.bai
+        for (ext, url) in self.index_urls(id, format) {
+            let cache_path = self.index_cache_path(id, &ext);
 
+            // Check cache first
             if cache_path.exists() {
                 return Ok(Some(cache_path));
             }
 
-            if self.url_exists(&url).await {
-                self.download_to_cache(&url, &cache_path).await?;
+            // Check if exists remotely and download
+            if let Ok((size, _, _)) = self.head_metadata(&url).await {
+                self.download_to_cache(&url, size, &cache_path).await?;
                 return Ok(Some(cache_path));
             }
         }
@@ -308,24 +580,112 @@ mod tests {
         assert_eq!(url, "https://example.com/data/sample1.bam");
     }
 
+    #[tokio::test]
+    async fn test_url_template() {
+        let cache = tempfile::tempdir().unwrap();
+        let storage = HttpStorage::new(
+            "https://example.com/data/".to_string(),
+            Some("https://example.com/indexes".to_string()),
+            cache.path().to_path_buf(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            storage.file_url("sample1", Format::Bam),
+            "https://example.com/data/sample1.bam"
+        );
+
+        let storage = storage.with_url_template("{base}/{id}/{id}.{ext}?download=1");
+        assert_eq!(
+            storage.file_url("NA12878", Format::Bam),
+            "https://example.com/data/NA12878/NA12878.bam?download=1"
+        );
+        let index_urls = storage.index_urls("NA12878", Format::Bam);
+        assert_eq!(
+            index_urls[0],
+            (
+                "bam.bai".to_string(),
+                "https://example.com/indexes/NA12878/NA12878.bam.bai?download=1".to_string()
+            )
+        );
+        assert_eq!(index_urls[1].0, "bai");
+        assert_eq!(
+            storage.index_cache_path("NA12878", "bam.bai"),
+            cache.path().join("NA12878.bam.bai")
+        );
+    }
+
     #[test]
-    fn test_file_extensions() {
-        assert_eq!(HttpStorage::file_extension(Format::Bam), "bam");
-        assert_eq!(HttpStorage::file_extension(Format::Cram), "cram");
-        assert_eq!(HttpStorage::file_extension(Format::Vcf), "vcf.gz");
-        assert_eq!(HttpStorage::file_extension(Format::Bcf), "bcf");
-        assert_eq!(HttpStorage::file_extension(Format::Fasta), "fa");
-        assert_eq!(HttpStorage::file_extension(Format::Fastq), "fq.gz");
+    fn test_content_range_size() {
+        assert_eq!(content_range_size("bytes 0-0/1234"), Some(1234));
+        assert_eq!(content_range_size("bytes 0-0/*"), None);
     }
 
     #[test]
-    fn test_index_extensions() {
-        assert_eq!(HttpStorage::index_extension(Format::Bam), Some("bai"));
-        assert_eq!(HttpStorage::index_extension(Format::Cram), Some("crai"));
-        assert_eq!(HttpStorage::index_extension(Format::Vcf), Some("tbi"));
-        assert_eq!(HttpStorage::index_extension(Format::Bcf), Some("csi"));
-        assert_eq!(HttpStorage::index_extension(Format::Fasta), Some("fai"));
-        assert_eq!(HttpStorage::index_extension(Format::Fastq), None);
+    fn test_parse_headers() {
+        let headers = parse_headers("Authorization: Bearer abc123, X-Api-Key:secret").unwrap();
+        assert_eq!(headers["authorization"], "Bearer abc123");
+        assert_eq!(headers["x-api-key"], "secret");
+        assert!(parse_headers("").unwrap().is_empty());
+        assert!(parse_headers("Authorization").is_err());
+        assert!(parse_headers("Bad Name: value").is_err());
+
+        let names = parse_header_names("Authorization, X-Request-Id").unwrap();
+        assert_eq!(names, ["authorization", "x-request-id"]);
+        assert!(parse_header_names("Bad Name").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_url_headers() {
+        let cache = tempfile::tempdir().unwrap();
+        let storage = HttpStorage::new(
+            "https://example.com/data".to_string(),
+            None,
+            cache.path().to_path_buf(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(storage.url_headers(None), None);
+
+        let storage = storage
+            .with_headers(parse_headers("Authorization: Bearer abc123,X-Internal: 1").unwrap())
+            .with_ticket_headers(parse_header_names("Authorization").unwrap());
+        let headers = storage
+            .url_headers(Some(&ByteRange {
+                start: 0,
+                end: Some(1024),
+            }))
+            .unwrap();
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers["authorization"], "Bearer abc123");
+        assert_eq!(headers["Range"], "bytes=0-1023");
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_headers() {
+        let cache = tempfile::tempdir().unwrap();
+        let storage = HttpStorage::new(
+            "https://example.com/data".to_string(),
+            None,
+            cache.path().to_path_buf(),
+        )
+        .await
+        .unwrap()
+        .with_forwarded_headers(parse_header_names("Authorization").unwrap());
+        let upstream = || {
+            storage
+                .upstream(storage.client.get("https://example.com/data/a.bam"))
+                .build()
+                .unwrap()
+        };
+        assert!(upstream().headers().get("authorization").is_none());
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert("authorization", HeaderValue::from_static("Bearer user"));
+        request_headers.insert("cookie", HeaderValue::from_static("session=1"));
+        let request = with_request_headers(request_headers, async { upstream() }).await;
+        assert_eq!(request.headers()["authorization"], "Bearer user");
+        assert!(request.headers().get("cookie").is_none());
     }
 
     #[test]
@@ -1,49 +1,156 @@
-use super::{ByteRange, FileInfo, Storage};
+use super::naming::Naming;
+use super::{ByteRange, DataReader, FileInfo, ListedFile, Storage, data_endpoint_url};
 use crate::{Error, Result, types::Format};
 use async_trait::async_trait;
 use bytes::Bytes;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tokio::fs;
 use tokio::io::{AsyncReadExt, AsyncSeekExt};
 
 pub struct LocalStorage {
     data_dir: PathBuf,
     base_url: String,
+    naming: Naming,
+    vcf_cache: Option<PathBuf>,
+    /// Locks held while converting each plain VCF, by converted path, so
+    /// concurrent requests convert a file once without waiting on others
+    converting: Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>,
 }
 
 impl LocalStorage {
     pub fn new(data_dir: PathBuf, base_url: String) -> Self {
-        Self { data_dir, base_url }
+        Self {
+            data_dir,
+            base_url,
+            naming: Naming::default(),
+            vcf_cache: None,
+            converting: Mutex::default(),
+        }
+    }
+
+    /// Use `naming` for data and index file extensions
+    pub fn with_naming(mut self, naming: Naming) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// Bgzip and tabix-index plain `.vcf` files into `cache_dir` on first
+    /// request, and serve the converted copy, so they support header-only
+    /// and region queries. Without this, plain VCF is served as a whole file.
+    pub fn with_vcf_conversion(mut self, cache_dir: PathBuf) -> Self {
+        self.vcf_cache = Some(cache_dir);
+        self
     }
 
     fn make_file_path(&self, id: &str, format: Format) -> PathBuf {
-        let ext = match format {
-            Format::Bam => "bam",
-            Format::Cram => "cram",
-            Format::Vcf => "vcf.gz",
-            Format::Bcf => "bcf",
-            Format::Fasta => "fa",
-            Format::Fastq => "fq.gz",
+        let exts = self.naming.data_extensions(format);
+
+        // Prefer the first candidate that exists, falling back to the primary extension
+        let path = exts
+            .iter()
+            .map(|ext| self.data_dir.join(format!("{}.{}", id, ext)))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| self.data_dir.join(format!("{}.{}", id, exts[0])));
+
+        // A plain VCF is served from its converted copy once that is current
+        match self.converted_vcf(id, &path) {
+            Some(converted) if is_newer(&converted, &path) => converted,
+            _ => path,
+        }
+    }
+
+    /// The data file's path, first converting a plain VCF whose converted
+    /// copy is missing or stale.
+    async fn resolve(&self, id: &str, format: Format) -> Result<PathBuf> {
+        let path = self.make_file_path(id, format);
+        let Some(converted) = self.converted_vcf(id, &path).filter(|_| path.exists()) else {
+            return Ok(path);
+        };
+
+        let lock = self
+            .converting
+            .lock()
+            .unwrap()
+            .entry(converted.clone())
+            .or_default()
+            .clone();
+        let result = {
+            let _converting = lock.lock().await;
+            // Another request may have converted it while this one waited
+            if is_newer(&converted, &path) {
+                Ok(())
+            } else {
+                tracing::info!("converting {} to {}", path.display(), converted.display());
+                // On the blocking pool, so other requests go on meanwhile
+                crate::indexer::compress_vcf(&path, &converted).await
+            }
         };
-        self.data_dir.join(format!("{}.{}", id, ext))
+        drop(lock);
+        // Forget locks no other request holds
+        self.converting
+            .lock()
+            .unwrap()
+            .retain(|_, lock| Arc::strong_count(lock) > 1);
+        result.map(|()| converted)
+    }
+
+    /// Where a plain VCF's bgzipped copy goes, if `path` is one and
+    /// conversion is enabled. IDs come from request paths, so those that
+    /// could leave the cache directory (`..`, absolute paths) aren't
+    /// converted.
+    fn converted_vcf(&self, id: &str, path: &Path) -> Option<PathBuf> {
+        let cache_dir = self.vcf_cache.as_ref()?;
+        let plain = path.extension().is_some_and(|ext| ext == "vcf");
+        let contained = Path::new(id)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        (plain && contained).then(|| cache_dir.join(format!("{}.vcf.gz", id)))
     }
 
-    fn index_extension(format: Format) -> Option<&'static str> {
-        match format {
-            Format::Bam => Some("bai"),
-            Format::Cram => Some("crai"),
-            Format::Vcf => Some("tbi"),
-            Format::Bcf => Some("csi"),
-            Format::Fasta => Some("fai"),
-            Format::Fastq => None,
+    /// Open the data file positioned at the start of `range`, returning it
+    /// with the number of bytes to read (clamped to the end of the file).
+    async fn open_range(
+        &self,
+        id: &str,
+        format: Format,
+        range: Option<ByteRange>,
+    ) -> Result<(fs::File, u64)> {
+        let path = self.make_file_path(id, format);
+        let mut file = fs::File::open(&path)
+            .await
+            .map_err(|_| Error::NotFound(id.to_string()))?;
+        let size = file.metadata().await?.len();
+
+        let (start, end) = match range {
+            Some(r) => (r.start, r.end.unwrap_or(size).min(size)),
+            None => (0, size),
+        };
+        if start > 0 {
+            file.seek(std::io::SeekFrom::Start(start)).await?;
         }
+
+        Ok((file, end.saturating_sub(start)))
+    }
+
+    /// The first existing index for the data file at `path`
+    fn find_index(&self, path: &Path, format: Format) -> Option<PathBuf> {
+        let name = path.file_name()?.to_str()?;
+        let (stem, ext) = self.naming.split(name, format)?;
+        let stem = path.with_file_name(stem);
+        self.naming
+            .index_names(&stem.to_string_lossy(), ext, format)
+            .into_iter()
+            .map(PathBuf::from)
+            .find(|idx| idx.exists())
     }
 }
 
 #[async_trait]
 impl Storage for LocalStorage {
     async fn exists(&self, id: &str, format: Format) -> Result<bool> {
-        let path = self.make_file_path(id, format);
+        let path = self.resolve(id, format).await?;
         Ok(path.exists())
     }
 
@@ -53,82 +160,94 @@ impl Storage for LocalStorage {
             .await
             .map_err(|_| Error::NotFound(id.to_string()))?;
 
-        let has_index = if let Some(idx_ext) = Self::index_extension(format) {
-            // Check both appended (file.bam.bai) and replaced (file.bai) conventions
-            let appended_idx = PathBuf::from(format!("{}.{}", path.display(), idx_ext));
-            let replaced_idx = path.with_extension(idx_ext);
-            appended_idx.exists() || replaced_idx.exists()
-        } else {
-            false
-        };
+        let has_index = self.find_index(&path, format).is_some();
 
         Ok(FileInfo {
             id: id.to_string(),
             format,
             size: metadata.len(),
             has_index,
+            modified: metadata.modified().ok(),
+            etag: None,
         })
     }
 
-    fn data_url(&self, id: &str, format: Format, range: Option<ByteRange>) -> String {
-        let base = format!("{}/data/{}/{}", self.base_url, format_path(format), id);
-        let format_param = format!("format={:?}", format); // e.g., "format=Cram"
+    async fn data_url(&self, id: &str, format: Format, range: Option<ByteRange>) -> Result<String> {
+        Ok(data_endpoint_url(
+            &self.base_url,
+            id,
+            format,
+            range.as_ref(),
+        ))
+    }
 
-        let mut params = vec![format_param];
+    async fn read_bytes(
+        &self,
+        id: &str,
+        format: Format,
+        range: Option<ByteRange>,
+    ) -> Result<Bytes> {
+        let (mut file, len) = self.open_range(id, format, range).await?;
 
-        if let Some(r) = range {
-            params.push(format!("start={}", r.start));
-            if let Some(end) = r.end {
-                params.push(format!("end={}", end));
-            }
-        }
+        let mut buf = vec![0u8; len as usize];
+        file.read_exact(&mut buf).await?;
 
-        format!("{}?{}", base, params.join("&"))
+        Ok(Bytes::from(buf))
     }
 
-    async fn read_bytes(
+    async fn read_stream(
         &self,
         id: &str,
         format: Format,
         range: Option<ByteRange>,
-    ) -> Result<Bytes> {
-        let path = self.make_file_path(id, format);
-        let mut file = fs::File::open(&path)
-            .await
-            .map_err(|_| Error::NotFound(id.to_string()))?;
+    ) -> Result<DataReader> {
+        let (file, len) = self.open_range(id, format, range).await?;
+        Ok(Box::pin(file.take(len)))
+    }
 
-        let bytes = match range {
-            Some(r) => {
-                file.seek(std::io::SeekFrom::Start(r.start)).await?;
-                let len = r.end.map(|e| e - r.start).unwrap_or(u64::MAX) as usize;
-                let mut buf = vec![0u8; len.min(10 * 1024 * 1024)]; // Cap at 10MB
-                let n = file.read(&mut buf).await?;
-                buf.truncate(n);
-                Bytes::from(buf)
-            }
-            None => {
-                let mut buf = Vec::new();
-                file.read_to_end(&mut buf).await?;
-                Bytes::from(buf)
+    async fn list(&self) -> Result<Vec<ListedFile>> {
+        let mut entries = fs::read_dir(&self.data_dir).await.map_err(|e| {
+            Error::Internal(format!(
+                "failed to read data dir {:?}: {}",
+                self.data_dir, e
+            ))
+        })?;
+
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let Some((id, format)) = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|name| self.naming.parse(name))
+            else {
+                continue;
+            };
+
+            let metadata = entry.metadata().await?;
+            if metadata.is_file() {
+                files.push(ListedFile {
+                    id,
+                    format,
+                    modified: metadata.modified().ok(),
+                });
             }
-        };
+        }
 
-        Ok(bytes)
+        Ok(files)
     }
 
     async fn index_path(&self, id: &str, format: Format) -> Result<Option<PathBuf>> {
-        let path = self.make_file_path(id, format);
-        if let Some(idx_ext) = Self::index_extension(format) {
-            // Try appended index first (e.g., file.bam.bai)
-            let appended_idx = PathBuf::from(format!("{}.{}", path.display(), idx_ext));
-            if appended_idx.exists() {
-                return Ok(Some(appended_idx));
-            }
+        let path = self.resolve(id, format).await?;
+        Ok(self.find_index(&path, format))
+    }
 
-            // Try replaced extension (e.g., file.bai)
-            let replaced_idx = path.with_extension(idx_ext);
-            if replaced_idx.exists() {
-                return Ok(Some(replaced_idx));
+    async fn gzi_path(&self, id: &str, format: Format) -> Result<Option<PathBuf>> {
+        let path = self.make_file_path(id, format);
+        if path.extension().is_some_and(|ext| ext == "gz") {
+            let gzi = PathBuf::from(format!("{}.gzi", path.display()));
+            if gzi.exists() {
+                return Ok(Some(gzi));
             }
         }
         Ok(None)
@@ -139,10 +258,209 @@ impl Storage for LocalStorage {
     }
 }
 
-fn format_path(format: Format) -> &'static str {
-    match format {
-        Format::Bam | Format::Cram => "reads",
-        Format::Vcf | Format::Bcf => "variants",
-        Format::Fasta | Format::Fastq => "sequences",
+/// Whether `a` was modified no earlier than `b`
+fn is_newer(a: &Path, b: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(a), modified(b)) {
+        (Some(a), Some(b)) => a >= b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage_with_file(len: usize) -> (tempfile::TempDir, LocalStorage, Vec<u8>) {
+        let dir = tempfile::tempdir().unwrap();
+        let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        std::fs::write(dir.path().join("big.bam"), &data).unwrap();
+        let storage = LocalStorage::new(dir.path().to_path_buf(), "http://localhost".to_string());
+        (dir, storage, data)
+    }
+
+    #[tokio::test]
+    async fn test_read_bytes_exact_range() {
+        let (_dir, storage, data) = storage_with_file(11 * 1024 * 1024);
+
+        // Larger than the old 10MB cap
+        let bytes = storage.read_bytes("big", Format::Bam, None).await.unwrap();
+        assert_eq!(bytes.as_ref(), data.as_slice());
+
+        let range = ByteRange {
+            start: 100,
+            end: Some(10_500_000),
+        };
+        let bytes = storage
+            .read_bytes("big", Format::Bam, Some(range))
+            .await
+            .unwrap();
+        assert_eq!(bytes.as_ref(), &data[100..10_500_000]);
+
+        // Ranges past the end of the file are clamped
+        let range = ByteRange {
+            start: data.len() as u64 - 10,
+            end: Some(data.len() as u64 + 100),
+        };
+        let bytes = storage
+            .read_bytes("big", Format::Bam, Some(range))
+            .await
+            .unwrap();
+        assert_eq!(bytes.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_read_stream() {
+        let (_dir, storage, data) = storage_with_file(64 * 1024);
+
+        let range = ByteRange {
+            start: 1000,
+            end: Some(50_000),
+        };
+        let mut reader = storage
+            .read_stream("big", Format::Bam, Some(range))
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, &data[1000..50_000]);
+
+        assert!(matches!(
+            storage.read_stream("missing", Format::Bam, None).await,
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.bam", "a.bam.bai", "b.vcf.gz", "c.fa.gz", "notes.txt"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let storage = LocalStorage::new(dir.path().to_path_buf(), "http://localhost".to_string());
+
+        let mut files: Vec<_> = storage
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|f| (f.id, f.format))
+            .collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(
+            files,
+            vec![
+                ("a".to_string(), Format::Bam),
+                ("b".to_string(), Format::Vcf),
+                ("c".to_string(), Format::Fasta),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_custom_naming() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "ref.fasta",
+            "ref.fasta.fai",
+            "calls.vcf.bgz",
+            "calls.vcf.tbi",
+        ] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let naming = Naming::default()
+            .with_data_extensions("fasta:fasta,vcf:vcf.bgz")
+            .unwrap();
+        let storage = LocalStorage::new(dir.path().to_path_buf(), "http://localhost".to_string())
+            .with_naming(naming);
+
+        assert!(storage.exists("ref", Format::Fasta).await.unwrap());
+        assert_eq!(
+            storage.index_path("ref", Format::Fasta).await.unwrap(),
+            Some(dir.path().join("ref.fasta.fai"))
+        );
+        assert_eq!(
+            storage.file_path("calls", Format::Vcf),
+            dir.path().join("calls.vcf.bgz")
+        );
+        assert_eq!(
+            storage.index_path("calls", Format::Vcf).await.unwrap(),
+            Some(dir.path().join("calls.vcf.tbi"))
+        );
+        assert_eq!(storage.list().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_plain_vcf() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = "##fileformat=VCFv4.3\n##contig=<ID=chr1>\n\
+            #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+            chr1\t10\t.\tA\tG\t.\t.\t.\n";
+        std::fs::write(dir.path().join("small.vcf"), text).unwrap();
+
+        // Served as is
+        let storage = LocalStorage::new(dir.path().to_path_buf(), "http://localhost".to_string());
+        assert!(storage.exists("small", Format::Vcf).await.unwrap());
+        let bytes = storage
+            .read_bytes("small", Format::Vcf, None)
+            .await
+            .unwrap();
+        assert_eq!(bytes.as_ref(), text.as_bytes());
+        assert_eq!(
+            storage.index_path("small", Format::Vcf).await.unwrap(),
+            None
+        );
+
+        // Converted on first request
+        let cache = dir.path().join("cache");
+        let storage = LocalStorage::new(dir.path().to_path_buf(), "http://localhost".to_string())
+            .with_vcf_conversion(cache.clone());
+        assert!(storage.exists("small", Format::Vcf).await.unwrap());
+        assert_eq!(
+            storage.file_path("small", Format::Vcf),
+            cache.join("small.vcf.gz")
+        );
+        assert_eq!(
+            storage.index_path("small", Format::Vcf).await.unwrap(),
+            Some(cache.join("small.vcf.gz.tbi"))
+        );
+        let bytes = storage
+            .read_bytes("small", Format::Vcf, None)
+            .await
+            .unwrap();
+        assert!(bytes.starts_with(&[0x1f, 0x8b]));
+
+        assert!(!storage.exists("missing", Format::Vcf).await.unwrap());
+
+        // Never written outside the cache
+        let nested = dir.path().join("nested");
+        std::fs::create_dir(&nested).unwrap();
+        std::fs::write(nested.join("escape.vcf"), text).unwrap();
+        let storage_in_nested = LocalStorage::new(nested.clone(), "http://localhost".to_string())
+            .with_vcf_conversion(cache.clone());
+        assert!(
+            storage_in_nested
+                .exists("../nested/escape", Format::Vcf)
+                .await
+                .unwrap()
+        );
+        assert!(!dir.path().join("nested/escape.vcf.gz").exists());
+        assert_eq!(
+            storage_in_nested.file_path("../nested/escape", Format::Vcf),
+            nested.join("../nested/escape.vcf")
+        );
+
+        // Files convert independently, each once however many ask for it
+        std::fs::write(dir.path().join("other.vcf"), text).unwrap();
+        std::fs::remove_file(cache.join("small.vcf.gz")).unwrap();
+        let (a, b, c) = tokio::join!(
+            storage.exists("small", Format::Vcf),
+            storage.exists("small", Format::Vcf),
+            storage.exists("other", Format::Vcf),
+        );
+        assert!(a.unwrap() && b.unwrap() && c.unwrap());
+        assert!(cache.join("other.vcf.gz.tbi").exists());
+        assert!(storage.converting.lock().unwrap().is_empty());
     }
 }
@@ -8,6 +8,12 @@
 //!
 //! - [`LocalStorage`] - Local filesystem storage
 //!
+//! [`DecryptingStorage`] (feature `crypt4gh`) wraps any of them to serve
+//! Crypt4GH-encrypted objects as plaintext.
+//!
+//! File extensions are shared by all backends and configurable; see
+//! [`naming`].
+//!
 //! # Example
 //!
 //! ```no_run
@@ -21,7 +27,10 @@
 //! );
 //! ```
 
+mod chunked;
 mod local;
+pub mod naming;
+pub mod retry;
 
 #[cfg(feature = "s3")]
 mod s3;
@@ -29,17 +38,30 @@ mod s3;
 #[cfg(feature = "http")]
 mod http;
 
+#[cfg(feature = "crypt4gh")]
+mod crypt4gh;
+
+pub use chunked::ChunkedDownload;
 pub use local::LocalStorage;
+pub use naming::Naming;
+pub use retry::{RetryClass, RetryPolicy};
 
 #[cfg(feature = "s3")]
-pub use s3::S3Storage;
+pub use s3::{BucketRoute, S3Credentials, S3Storage, S3Upload};
 
 #[cfg(feature = "http")]
-pub use http::HttpStorage;
+pub use http::{HttpStorage, parse_header_names, parse_headers, with_request_headers};
 
-use crate::{Result, types::Format};
+#[cfg(feature = "crypt4gh")]
+pub use crypt4gh::{DecryptingStorage, DecryptionRoute};
+
+use crate::{
+    Result,
+    types::{Checksums, Format},
+};
 use async_trait::async_trait;
 use bytes::Bytes;
+use std::collections::HashMap;
 
 /// Byte range within a file
 #[derive(Debug, Clone)]
@@ -55,8 +77,97 @@ pub struct FileInfo {
     pub format: Format,
     pub size: u64,
     pub has_index: bool,
+    /// Last modification time, if the backend reports one
+    pub modified: Option<std::time::SystemTime>,
+    /// Backend version identifier (e.g. S3 or HTTP ETag), if any
+    pub etag: Option<String>,
+}
+
+/// A data file found by [`Storage::list`]
+#[derive(Debug, Clone)]
+pub struct ListedFile {
+    pub id: String,
+    pub format: Format,
+    /// Last modification time, if the backend reports one
+    pub modified: Option<std::time::SystemTime>,
+}
+
+impl FileInfo {
+    /// Opaque identifier that changes whenever the file content does:
+    /// the backend ETag if available, otherwise size and modification time.
+    pub fn version(&self) -> String {
+        if let Some(etag) = &self.etag {
+            return etag.trim_matches('"').to_string();
+        }
+
+        let modified = self
+            .modified
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos());
+        format!("{}-{}", self.size, modified)
+    }
+
+    /// MD5 of the content, when the backend ETag is one (as for S3 objects
+    /// not uploaded in parts).
+    pub fn md5(&self) -> Option<String> {
+        let etag = self.etag.as_deref()?.trim_matches('"');
+        (etag.len() == 32 && etag.bytes().all(|b| b.is_ascii_hexdigit())).then(|| etag.to_string())
+    }
+}
+
+/// URL of this server's `/data` endpoint for a file, e.g.
+/// `{base_url}/data/reads/sample1?format=BAM&start=0&end=1024`. The ID is
+/// percent-encoded, as it may contain `/`.
+/// The concrete format is always given, as the datatype path alone would
+/// mean BAM, VCF or FASTA.
+pub(crate) fn data_endpoint_url(
+    base_url: &str,
+    id: &str,
+    format: Format,
+    range: Option<&ByteRange>,
+) -> String {
+    let format_path = match format {
+        Format::Bam | Format::Cram => "reads",
+        Format::Vcf | Format::Bcf => "variants",
+        Format::Fasta | Format::Fastq => "sequences",
+    };
+    let base = format!(
+        "{}/data/{}/{}",
+        base_url,
+        format_path,
+        crate::handlers::percent_encode(id)
+    );
+    let format_param = format!("format={}", format); // e.g., "format=CRAM"
+
+    let mut params = vec![format_param];
+
+    if let Some(r) = range {
+        params.push(format!("start={}", r.start));
+        if let Some(end) = r.end {
+            params.push(format!("end={}", end));
+        }
+    }
+
+    format!("{}?{}", base, params.join("&"))
+}
+
+/// HTTP `Range` header for a [`ByteRange`] (whose end is exclusive; HTTP's
+/// is inclusive). `None` for an empty range, which HTTP can't express.
+pub(crate) fn range_header(range: &ByteRange) -> Option<String> {
+    match range.end {
+        Some(end) if end > range.start => Some(format!("bytes={}-{}", range.start, end - 1)),
+        Some(_) => None,
+        None => Some(format!("bytes={}-", range.start)),
+    }
+}
+
+pub(crate) fn is_empty_range(range: Option<&ByteRange>) -> bool {
+    range.is_some_and(|r| r.end.is_some_and(|end| end <= r.start))
 }
 
+/// Reader returned by [`Storage::read_stream`]
+pub type DataReader = std::pin::Pin<Box<dyn tokio::io::AsyncRead + Send>>;
+
 /// Storage backend trait for accessing genomic data files
 #[async_trait]
 pub trait Storage: Send + Sync {
@@ -66,19 +177,147 @@ pub trait Storage: Send + Sync {
     /// Get file metadata
     async fn file_info(&self, id: &str, format: Format) -> Result<FileInfo>;
 
+    /// Whole-file checksums the backend has without reading the file, such
+    /// as S3 checksum metadata. Defaults to the MD5 an ETag may be.
+    async fn stored_checksums(&self, id: &str, format: Format) -> Result<Checksums> {
+        let info = self.file_info(id, format).await?;
+        Ok(Checksums {
+            md5: info.md5(),
+            etag: info.etag,
+            ..Default::default()
+        })
+    }
+
     /// Get URL for accessing a byte range of the file
-    /// Returns a URL that can be used to fetch the data
-    fn data_url(&self, id: &str, format: Format, range: Option<ByteRange>) -> String;
+    /// Returns a URL that can be used to fetch the data. Fails if no usable
+    /// URL can be made (e.g. presigning failed), rather than returning one
+    /// clients can't fetch.
+    async fn data_url(&self, id: &str, format: Format, range: Option<ByteRange>) -> Result<String>;
+
+    /// Like [`Storage::data_url`], for one of the `ticket_urls` data URLs of
+    /// a ticket. Backends whose URLs expire may give larger tickets, which
+    /// take longer to consume, more time.
+    async fn ticket_data_url(
+        &self,
+        id: &str,
+        format: Format,
+        range: Option<ByteRange>,
+        _ticket_urls: usize,
+    ) -> Result<String> {
+        self.data_url(id, format, range).await
+    }
+
+    /// Headers clients must send with the data URL for `range`, such as a
+    /// `Range` header when the URL covers the whole file, or credentials
+    /// the origin requires. `None` if the URL suffices on its own.
+    fn url_headers(&self, _range: Option<&ByteRange>) -> Option<HashMap<String, String>> {
+        None
+    }
+
+    /// Like [`Storage::url_headers`], for the ticket URL of `range` of a
+    /// particular file.
+    fn ticket_url_headers(
+        &self,
+        _id: &str,
+        _format: Format,
+        range: Option<&ByteRange>,
+    ) -> Option<HashMap<String, String>> {
+        self.url_headers(range)
+    }
+
+    /// How long the URLs of a ticket with `ticket_urls` data URLs stay
+    /// valid; `None` if they don't expire.
+    fn url_expiry(&self, _ticket_urls: usize) -> Option<std::time::Duration> {
+        None
+    }
 
     /// Read bytes directly (for small inline responses)
     async fn read_bytes(&self, id: &str, format: Format, range: Option<ByteRange>)
     -> Result<Bytes>;
 
+    /// Stream a byte range without buffering it in memory.
+    ///
+    /// Defaults to [`Storage::read_bytes`]; backends that can read
+    /// incrementally should override it.
+    async fn read_stream(
+        &self,
+        id: &str,
+        format: Format,
+        range: Option<ByteRange>,
+    ) -> Result<DataReader> {
+        let bytes = self.read_bytes(id, format, range).await?;
+        Ok(Box::pin(std::io::Cursor::new(bytes)))
+    }
+
+    /// Direct URL for the whole object that the data endpoint can redirect
+    /// clients to instead of proxying bytes (e.g. a presigned S3 URL).
+    /// `None` if the backend has no such URL.
+    async fn redirect_url(&self, _id: &str, _format: Format) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Data files the backend holds. Backends that can't enumerate their
+    /// contents (e.g. HTTP) return an empty list.
+    async fn list(&self) -> Result<Vec<ListedFile>> {
+        Ok(Vec::new())
+    }
+
     /// Get index file path if available
     async fn index_path(&self, id: &str, format: Format) -> Result<Option<std::path::PathBuf>>;
 
+    /// Get the GZI index path for a BGZF-compressed FASTA/FASTQ file, if available
+    async fn gzi_path(&self, _id: &str, _format: Format) -> Result<Option<std::path::PathBuf>> {
+        Ok(None)
+    }
+
     /// Get the actual file path for direct access
     /// For local storage, returns the local path.
     /// For remote storage, may download to a temp file and return that path.
     fn file_path(&self, id: &str, format: Format) -> std::path::PathBuf;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_endpoint_url() {
+        let range = ByteRange {
+            start: 0,
+            end: Some(1024),
+        };
+        assert_eq!(
+            data_endpoint_url(
+                "http://localhost:8080",
+                "sample1",
+                Format::Bam,
+                Some(&range)
+            ),
+            "http://localhost:8080/data/reads/sample1?format=BAM&start=0&end=1024"
+        );
+        assert_eq!(
+            data_endpoint_url("http://localhost:8080", "1000g/NA12878", Format::Cram, None),
+            "http://localhost:8080/data/reads/1000g%2FNA12878?format=CRAM"
+        );
+    }
+
+    #[test]
+    fn test_range_header() {
+        let range = |start, end| ByteRange { start, end };
+
+        // ByteRange ends are exclusive, HTTP Range ends inclusive
+        assert_eq!(
+            range_header(&range(0, Some(1024))).as_deref(),
+            Some("bytes=0-1023")
+        );
+        assert_eq!(
+            range_header(&range(100, None)).as_deref(),
+            Some("bytes=100-")
+        );
+        assert_eq!(range_header(&range(10, Some(10))), None);
+
+        assert!(is_empty_range(Some(&range(10, Some(10)))));
+        assert!(!is_empty_range(Some(&range(10, Some(11)))));
+        assert!(!is_empty_range(None));
+    }
+}
@@ -0,0 +1,244 @@
+//! File naming conventions shared by the storage backends.
+//!
+//! A dataset `id` is stored as `{id}.{ext}` under the first of its format's
+//! data extensions that exists, and its index under one of
+//! `{id}.{ext}.{index ext}` (`sample.bam.bai`), `{id}.{index ext}`
+//! (`sample.bai`) or, for two-part extensions, `sample.vcf.tbi`. The defaults
+//! cover the usual extensions; deployments can add others, such as `fasta`,
+//! `fastq.gz` or `vcf.bgz`.
+
+use crate::types::Format;
+use crate::{Error, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Data and index file extensions per format, in order of preference
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Naming {
+    data: HashMap<Format, Vec<String>>,
+    index: HashMap<Format, Vec<String>>,
+}
+
+/// The extensions from [`Format::data_extensions`] and
+/// [`Format::index_extensions`]
+impl Default for Naming {
+    fn default() -> Self {
+        let table = |exts: fn(&Format) -> &'static [&'static str]| {
+            Format::ALL
+                .into_iter()
+                .map(|format| {
+                    (
+                        format,
+                        exts(&format).iter().map(|e| e.to_string()).collect(),
+                    )
+                })
+                .collect()
+        };
+
+        Self {
+            data: table(Format::data_extensions),
+            index: table(Format::index_extensions),
+        }
+    }
+}
+
+impl Naming {
+    /// Data file extensions for `format`, without the leading dot
+    pub fn data_extensions(&self, format: Format) -> &[String] {
+        &self.data[&format]
+    }
+
+    /// Index file extensions for `format`, without the leading dot
+    pub fn index_extensions(&self, format: Format) -> &[String] {
+        &self.index[&format]
+    }
+
+    /// Also recognize data files ending in `ext`, after the existing extensions
+    pub fn add_data_extension(&mut self, format: Format, ext: &str) {
+        add(self.data.get_mut(&format).unwrap(), ext);
+    }
+
+    /// Also look for indexes ending in `ext`, after the existing extensions
+    pub fn add_index_extension(&mut self, format: Format, ext: &str) {
+        add(self.index.get_mut(&format).unwrap(), ext);
+    }
+
+    /// Add data extensions from a comma-separated list of `format:ext`
+    /// pairs, e.g. `fasta:fasta,fastq:fastq.gz,vcf:vcf.bgz`.
+    pub fn with_data_extensions(mut self, spec: &str) -> Result<Self> {
+        for (format, ext) in parse_spec(spec)? {
+            self.add_data_extension(format, ext);
+        }
+        Ok(self)
+    }
+
+    /// Add index extensions from a comma-separated list of `format:ext`
+    /// pairs, e.g. `bam:csi`.
+    pub fn with_index_extensions(mut self, spec: &str) -> Result<Self> {
+        for (format, ext) in parse_spec(spec)? {
+            self.add_index_extension(format, ext);
+        }
+        Ok(self)
+    }
+
+    /// Candidate index names for a data file `{stem}.{data_ext}`, in the
+    /// order to probe them. `stem` may carry a directory, key prefix or URL.
+    pub fn index_names(&self, stem: &str, data_ext: &str, format: Format) -> Vec<String> {
+        let mut names = Vec::new();
+        for idx in self.index_extensions(format) {
+            let mut candidates = vec![
+                format!("{}.{}.{}", stem, data_ext, idx),
+                format!("{}.{}", stem, idx),
+            ];
+            // `sample.vcf.gz` may also be indexed as `sample.vcf.tbi`
+            if let Some((first, _)) = data_ext.rsplit_once('.') {
+                candidates.push(format!("{}.{}.{}", stem, first, idx));
+            }
+            for name in candidates {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+
+    /// Split a data file name into its stem and data extension, matching
+    /// the longest extension of `format`.
+    pub fn split<'a>(&self, name: &'a str, format: Format) -> Option<(&'a str, &'a str)> {
+        self.data_extensions(format)
+            .iter()
+            .filter_map(|ext| {
+                let stem = name.strip_suffix(ext.as_str())?.strip_suffix('.')?;
+                (!stem.is_empty()).then(|| (stem, &name[stem.len() + 1..]))
+            })
+            .max_by_key(|(_, ext)| ext.len())
+    }
+
+    /// ID and format of a data file name, if it has a data extension.
+    /// The longest matching extension wins, so `a.vcf.gz` is VCF `a`.
+    pub fn parse(&self, name: &str) -> Option<(String, Format)> {
+        Format::ALL
+            .into_iter()
+            .filter_map(|format| {
+                let (stem, ext) = self.split(name, format)?;
+                Some((stem, ext.len(), format))
+            })
+            .max_by_key(|(_, len, _)| *len)
+            .map(|(stem, _, format)| (stem.to_string(), format))
+    }
+
+    /// Format of a data file from its name.
+    pub fn detect(&self, path: &Path) -> Option<Format> {
+        let name = path.file_name()?.to_str()?;
+        self.parse(name).map(|(_, format)| format)
+    }
+}
+
+fn add(exts: &mut Vec<String>, ext: &str) {
+    let ext = ext.trim().trim_start_matches('.');
+    if !ext.is_empty() && !exts.iter().any(|e| e == ext) {
+        exts.push(ext.to_string());
+    }
+}
+
+fn parse_spec(spec: &str) -> Result<Vec<(Format, &str)>> {
+    spec.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (format, ext) = pair.split_once(':').ok_or_else(|| {
+                Error::InvalidInput(format!("expected format:extension, got {}", pair))
+            })?;
+            Ok((format.trim().parse()?, ext))
+        })
+        .collect()
+}
+
+/// Data extensions found by probing a remote backend, so synchronous
+/// lookups (`data_url`, `file_path`) agree with the last probe
+#[cfg(any(feature = "s3", feature = "http"))]
+#[derive(Debug, Default)]
+pub(crate) struct ResolvedExtensions(std::sync::Mutex<HashMap<(String, Format), String>>);
+
+#[cfg(any(feature = "s3", feature = "http"))]
+impl ResolvedExtensions {
+    pub(crate) fn get(&self, id: &str, format: Format) -> Option<String> {
+        let resolved = self.0.lock().unwrap();
+        resolved.get(&(id.to_string(), format)).cloned()
+    }
+
+    pub(crate) fn insert(&self, id: &str, format: Format, ext: &str) {
+        let mut resolved = self.0.lock().unwrap();
+        resolved.insert((id.to_string(), format), ext.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let naming = Naming::default();
+        for format in Format::ALL {
+            assert_eq!(naming.data_extensions(format), format.data_extensions());
+            assert_eq!(naming.index_extensions(format), format.index_extensions());
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let naming = Naming::default();
+        assert_eq!(
+            naming.parse("a.vcf.gz"),
+            Some(("a".to_string(), Format::Vcf))
+        );
+        assert_eq!(naming.parse("a.vcf"), Some(("a".to_string(), Format::Vcf)));
+        assert_eq!(
+            naming.parse("a.b.bam"),
+            Some(("a.b".to_string(), Format::Bam))
+        );
+        assert_eq!(naming.parse("a.bam.bai"), None);
+        assert_eq!(naming.parse(".bam"), None);
+        assert_eq!(naming.parse("a.fasta"), None);
+
+        let naming = naming
+            .with_data_extensions("fasta:fasta, FASTQ:.fastq.gz,vcf:vcf.bgz")
+            .unwrap();
+        assert_eq!(
+            naming.parse("a.fasta"),
+            Some(("a".to_string(), Format::Fasta))
+        );
+        assert_eq!(
+            naming.parse("a.fastq.gz"),
+            Some(("a".to_string(), Format::Fastq))
+        );
+        assert_eq!(
+            naming.data_extensions(Format::Vcf),
+            ["vcf.gz", "vcf", "vcf.bgz"]
+        );
+
+        assert!(Naming::default().with_data_extensions("fasta").is_err());
+        assert!(Naming::default().with_data_extensions("txt:txt").is_err());
+    }
+
+    #[test]
+    fn test_index_names() {
+        let naming = Naming::default().with_index_extensions("bam:csi").unwrap();
+
+        assert_eq!(
+            naming.index_names("dir/sample", "bam", Format::Bam),
+            [
+                "dir/sample.bam.bai",
+                "dir/sample.bai",
+                "dir/sample.bam.csi",
+                "dir/sample.csi"
+            ]
+        );
+        assert_eq!(
+            naming.index_names("sample", "vcf.gz", Format::Vcf),
+            ["sample.vcf.gz.tbi", "sample.tbi", "sample.vcf.tbi"]
+        );
+        assert!(naming.index_names("reads", "fq", Format::Fastq).is_empty());
+    }
+}
@@ -0,0 +1,394 @@
+//! Retry policy for remote storage operations.
+//!
+//! S3 and HTTP backends wrap each request in [`RetryPolicy::run`], so transient
+//! failures (dropped connections, timeouts, throttling, 5xx) are retried with
+//! exponential backoff instead of surfacing as 500s. Which failures count as
+//! transient is configurable per [`RetryClass`]; retries and exhausted
+//! attempts are counted in [`RetryMetrics`]. Attempts that hang are cut off
+//! by [`with_timeout`] and retried like any other timeout. Throttling and
+//! timeouts that outlast the retries are reported as [`Error::Unavailable`]
+//! (503 with `Retry-After`), so clients know to try again.
+
+use crate::{Error, Result};
+use std::collections::HashSet;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Default per-attempt timeout for remote storage operations
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Kinds of transient failure a policy may retry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RetryClass {
+    /// Connection could not be established or was reset
+    Connect,
+    /// Request timed out
+    Timeout,
+    /// Backend asked us to slow down (429, 503, S3 `SlowDown`)
+    Throttled,
+    /// Other 5xx responses
+    ServerError,
+}
+
+impl FromStr for RetryClass {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "connect" => Ok(RetryClass::Connect),
+            "timeout" => Ok(RetryClass::Timeout),
+            "throttled" | "throttle" => Ok(RetryClass::Throttled),
+            "server" | "server_error" | "5xx" => Ok(RetryClass::ServerError),
+            _ => Err(Error::InvalidInput(format!(
+                "unknown retry class: {} (expected connect, timeout, throttled, or server)",
+                s
+            ))),
+        }
+    }
+}
+
+impl RetryClass {
+    /// Classify an HTTP status; `None` if it isn't transient.
+    pub fn from_status(status: u16) -> Option<Self> {
+        match status {
+            429 | 503 => Some(RetryClass::Throttled),
+            500..=599 => Some(RetryClass::ServerError),
+            _ => None,
+        }
+    }
+}
+
+/// Bound a single attempt by `timeout`; overruns fail as [`RetryClass::Timeout`].
+pub async fn with_timeout<T>(
+    operation: &str,
+    timeout: Duration,
+    attempt: impl Future<Output = std::result::Result<T, Failure>>,
+) -> std::result::Result<T, Failure> {
+    tokio::time::timeout(timeout, attempt)
+        .await
+        .unwrap_or_else(|_| {
+            Err(Failure::transient(
+                Error::Timeout(format!("{} after {:?}", operation, timeout)),
+                RetryClass::Timeout,
+            ))
+        })
+}
+
+/// A failed attempt: the error to report, and its retry class if transient.
+#[derive(Debug)]
+pub struct Failure {
+    pub error: Error,
+    pub class: Option<RetryClass>,
+}
+
+impl Failure {
+    /// A failure that is never retried.
+    pub fn permanent(error: Error) -> Self {
+        Self { error, class: None }
+    }
+
+    /// A failure that is retried if the policy covers `class`.
+    pub fn transient(error: Error, class: RetryClass) -> Self {
+        Self {
+            error,
+            class: Some(class),
+        }
+    }
+
+    /// The error to report once no more attempts will be made.
+    fn into_error(self) -> Error {
+        match self.class {
+            Some(RetryClass::Throttled | RetryClass::Timeout) => {
+                Error::Unavailable(self.error.detail())
+            }
+            _ => self.error,
+        }
+    }
+}
+
+/// Retry counters, shared by every operation using a policy.
+#[derive(Debug, Default)]
+pub struct RetryMetrics {
+    retries: AtomicU64,
+    exhausted: AtomicU64,
+}
+
+impl RetryMetrics {
+    /// Attempts that failed transiently and were retried.
+    pub fn retries(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// Operations that still failed after the last attempt.
+    pub fn exhausted(&self) -> u64 {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+}
+
+/// Retry policy with capped exponential backoff.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first (1 disables retries)
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles on each subsequent one
+    pub initial_backoff: Duration,
+    /// Upper bound on any single delay
+    pub max_backoff: Duration,
+    /// Failure classes that are retried
+    pub retry_on: HashSet<RetryClass>,
+    metrics: Arc<RetryMetrics>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            retry_on: [
+                RetryClass::Connect,
+                RetryClass::Timeout,
+                RetryClass::Throttled,
+                RetryClass::ServerError,
+            ]
+            .into_iter()
+            .collect(),
+            metrics: Arc::default(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that makes a single attempt.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Retry counters for operations run under this policy (and its clones).
+    pub fn metrics(&self) -> &RetryMetrics {
+        &self.metrics
+    }
+
+    /// Run `attempt` until it succeeds, fails permanently, or attempts run out.
+    /// `operation` names the request in logs.
+    pub async fn run<T, F, Fut>(&self, operation: &str, mut attempt: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = std::result::Result<T, Failure>>,
+    {
+        let mut tries = 1;
+        loop {
+            let failure = match attempt().await {
+                Ok(value) => return Ok(value),
+                Err(failure) => failure,
+            };
+
+            let retryable = failure
+                .class
+                .is_some_and(|class| self.retry_on.contains(&class));
+            if !retryable {
+                return Err(failure.into_error());
+            }
+            if tries >= self.max_attempts {
+                self.metrics.exhausted.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(
+                    operation,
+                    attempts = tries,
+                    "giving up after transient failures: {}",
+                    failure.error
+                );
+                return Err(failure.into_error());
+            }
+
+            let delay = self.backoff(tries);
+            self.metrics.retries.fetch_add(1, Ordering::Relaxed);
+            tracing::debug!(
+                operation,
+                attempt = tries,
+                class = ?failure.class,
+                delay_ms = delay.as_millis() as u64,
+                "retrying after transient failure: {}",
+                failure.error
+            );
+
+            tokio::time::sleep(delay).await;
+            tries += 1;
+        }
+    }
+
+    /// Delay before retry number `retry` (1-based): exponential, capped, with
+    /// the upper half jittered so concurrent retries don't synchronize.
+    fn backoff(&self, retry: u32) -> Duration {
+        let exp = self
+            .initial_backoff
+            .saturating_mul(1u32 << retry.saturating_sub(1).min(16));
+        let capped = exp.min(self.max_backoff);
+        let half = capped / 2;
+        let jitter = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        let nanos = half.as_nanos() as u64;
+
+        half + Duration::from_nanos(if nanos == 0 { 0 } else { jitter % nanos })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_failures() {
+        let policy = fast_policy(3);
+        let calls = AtomicU32::new(0);
+
+        let result = policy
+            .run("test", || async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(Failure::transient(
+                        Error::Internal("throttled".to_string()),
+                        RetryClass::Throttled,
+                    )),
+                    _ => Ok(42),
+                }
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(policy.metrics().retries(), 1);
+        assert_eq!(policy.metrics().exhausted(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let policy = fast_policy(3);
+        let calls = AtomicU32::new(0);
+
+        let result: Result<()> = policy
+            .run("test", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(Failure::transient(
+                    Error::Internal("unavailable".to_string()),
+                    RetryClass::ServerError,
+                ))
+            })
+            .await;
+
+        assert!(matches!(result, Err(Error::Internal(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(policy.metrics().retries(), 2);
+        assert_eq!(policy.metrics().exhausted(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_and_excluded_failures_are_not_retried() {
+        let mut policy = fast_policy(3);
+        policy.retry_on = [RetryClass::Timeout].into_iter().collect();
+        let calls = AtomicU32::new(0);
+
+        let result: Result<()> = policy
+            .run("test", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(Failure::transient(
+                    Error::Internal("bad gateway".to_string()),
+                    RetryClass::ServerError,
+                ))
+            })
+            .await;
+        assert!(result.is_err());
+
+        let result: Result<()> = policy
+            .run("test", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(Failure::permanent(Error::NotFound("x".to_string())))
+            })
+            .await;
+        assert!(matches!(result, Err(Error::NotFound(_))));
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(policy.metrics().retries(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_attempts_are_retried() {
+        let policy = fast_policy(2);
+        let calls = AtomicU32::new(0);
+
+        let result: Result<()> = policy
+            .run("test", || {
+                with_timeout("test", Duration::from_millis(5), async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    std::future::pending().await
+                })
+            })
+            .await;
+
+        assert!(matches!(result, Err(Error::Unavailable(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_throttling_is_unavailable() {
+        let policy = fast_policy(2);
+
+        let result: Result<()> = policy
+            .run("test", || async {
+                Err(Failure::transient(
+                    Error::Internal("S3 get_object failed: SlowDown".to_string()),
+                    RetryClass::Throttled,
+                ))
+            })
+            .await;
+
+        match result {
+            Err(Error::Unavailable(message)) => {
+                assert_eq!(message, "S3 get_object failed: SlowDown")
+            }
+            other => panic!("expected Unavailable, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(300),
+            ..RetryPolicy::default()
+        };
+
+        let first = policy.backoff(1);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+        let later = policy.backoff(10);
+        assert!(later >= Duration::from_millis(150) && later <= Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert_eq!(RetryClass::from_status(429), Some(RetryClass::Throttled));
+        assert_eq!(RetryClass::from_status(502), Some(RetryClass::ServerError));
+        assert_eq!(RetryClass::from_status(404), None);
+        assert_eq!(
+            "throttled".parse::<RetryClass>().unwrap(),
+            RetryClass::Throttled
+        );
+        assert!("sometimes".parse::<RetryClass>().is_err());
+    }
+}
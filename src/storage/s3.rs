@@ -5,28 +5,346 @@
 //!
 //! # Features
 //!
-//! - Presigned URLs for direct client-to-S3 data access
-//! - Local caching of index files for efficient repeated queries
+//! - Presigned URLs for direct client-to-S3 data access, or proxying through
+//!   the server's `/data` endpoint when the bucket can't be exposed
+//! - Local caching of index files for efficient repeated queries, downloaded
+//!   in concurrent ranged requests (see [`ChunkedDownload`])
+//! - Short-lived caching of object metadata, so a ticket request costs one
+//!   `HeadObject` per object rather than one per lookup; optionally shared
+//!   between replicas (see [`crate::shared`])
+//! - Retries of transient failures and per-request timeouts, so a hung
+//!   endpoint can't stall requests indefinitely
 //! - Support for custom S3 endpoints (MinIO, LocalStack, etc.)
+//! - Several buckets behind one deployment, chosen by ID prefix (see
+//!   [`BucketRoute`])
+//! - Named credential profiles and IAM role assumption, directly or through
+//!   a web identity token, for buckets in other accounts (see
+//!   [`S3Credentials`])
+//! - Unsigned access and plain object URLs for public buckets, such as open
+//!   datasets (see [`S3Storage::with_public_urls`])
 
-use super::{ByteRange, FileInfo, Storage};
-use crate::{Error, Result, types::Format};
+use super::naming::{Naming, ResolvedExtensions};
+use super::retry::{DEFAULT_TIMEOUT, Failure, RetryClass, RetryPolicy, with_timeout};
+use super::{
+    ByteRange, ChunkedDownload, DataReader, FileInfo, ListedFile, Storage, data_endpoint_url,
+    is_empty_range, range_header,
+};
+use crate::shared::{KEY_PREFIX, SharedCache};
+use crate::{
+    Error, Result,
+    types::{Checksums, Format},
+};
 use async_trait::async_trait;
+use aws_config::sts::AssumeRoleProvider;
+use aws_config::web_identity_token::{StaticConfiguration, WebIdentityTokenCredentialsProvider};
 use aws_sdk_s3::Client;
+use aws_sdk_s3::config::SharedCredentialsProvider;
+use aws_sdk_s3::config::http::HttpResponse;
+use aws_sdk_s3::config::retry::RetryConfig;
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::operation::get_object::{GetObjectError, GetObjectOutput};
 use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::types::{ChecksumMode, ChecksumType};
+use base64::Engine;
 use bytes::Bytes;
-use std::path::PathBuf;
-use std::time::Duration;
+use moka::future::Cache;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
 
-/// S3 storage backend for genomic data files.
-pub struct S3Storage {
+/// How long `HeadObject` results (including misses) are reused
+const METADATA_TTL: Duration = Duration::from_secs(30);
+
+/// Longest a SigV4 presigned URL can be valid
+const MAX_PRESIGN_EXPIRY: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Object metadata from `HeadObject`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ObjectMetadata {
+    size: u64,
+    modified: Option<SystemTime>,
+    etag: Option<String>,
+    /// Full-object checksums stored with the object, as hex
+    #[serde(default)]
+    sha256: Option<String>,
+    #[serde(default)]
+    crc32c: Option<String>,
+}
+
+/// Hex of a base64 `x-amz-checksum-*` value. Multipart uploads may have
+/// composite checksums (checksums of the parts' checksums, suffixed with
+/// `-<parts>`), which aren't checksums of the content and are dropped.
+fn full_object_checksum(
+    value: Option<&str>,
+    checksum_type: Option<&ChecksumType>,
+) -> Option<String> {
+    if matches!(checksum_type, Some(ChecksumType::Composite)) {
+        return None;
+    }
+    let value = value?;
+    if value.contains('-') {
+        return None;
+    }
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(value)
+        .ok()?;
+    Some(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// How long presigned URLs are valid.
+#[derive(Debug, Clone, Copy)]
+struct PresignExpiry {
+    base: Duration,
+    /// Added for each data URL of a ticket
+    per_url: Duration,
+    /// Tolerated difference between this server's clock and S3's
+    clock_skew: Duration,
+}
+
+impl PresignExpiry {
+    /// Validity of each URL in a ticket with `ticket_urls` data URLs, from
+    /// the time it is signed. Capped so that, with the clock skew headroom
+    /// added, URLs stay within the SigV4 limit.
+    fn for_ticket(&self, ticket_urls: usize) -> Duration {
+        let per_url = self
+            .per_url
+            .saturating_mul(u32::try_from(ticket_urls).unwrap_or(u32::MAX));
+        self.base
+            .saturating_add(per_url)
+            .min(MAX_PRESIGN_EXPIRY.saturating_sub(self.clock_skew.saturating_mul(2)))
+    }
+}
+
+/// Session name of assumed roles when none is configured
+const DEFAULT_ROLE_SESSION_NAME: &str = "htsgetr";
+
+/// Where the S3 backend gets its AWS credentials, beyond the SDK's default
+/// chain (environment, shared config files, container and instance roles).
+#[derive(Debug, Clone, Default)]
+pub struct S3Credentials {
+    /// Named profile from the shared AWS config and credentials files
+    pub profile: Option<String>,
+    /// Role to assume, e.g. one granting access to another account's bucket
+    pub role_arn: Option<String>,
+    /// Session name of the assumed role (default `htsgetr`)
+    pub role_session_name: Option<String>,
+    /// External ID the role's trust policy requires
+    pub external_id: Option<String>,
+    /// OIDC token file to assume `role_arn` with (`AssumeRoleWithWebIdentity`)
+    /// instead of the profile's or default chain's credentials
+    pub web_identity_token_file: Option<PathBuf>,
+    /// Send requests unsigned, for buckets anyone can read; the other
+    /// fields are ignored
+    pub anonymous: bool,
+}
+
+impl S3Credentials {
+    /// SDK configuration for `region` with these credentials.
+    async fn sdk_config(&self, region: Option<String>) -> aws_config::SdkConfig {
+        let mut loader = aws_config::from_env();
+        if let Some(region) = region {
+            loader = loader.region(aws_config::Region::new(region));
+        }
+        if self.anonymous {
+            return loader.no_credentials().load().await;
+        }
+        if let Some(profile) = &self.profile {
+            loader = loader.profile_name(profile);
+        }
+        let sdk_config = loader.load().await;
+
+        let Some(role_arn) = &self.role_arn else {
+            return sdk_config;
+        };
+        let session_name = self
+            .role_session_name
+            .clone()
+            .unwrap_or_else(|| DEFAULT_ROLE_SESSION_NAME.to_string());
+
+        let provider = match &self.web_identity_token_file {
+            Some(token_file) => {
+                tracing::info!("Assuming {} with web identity token", role_arn);
+                let provider_config = aws_config::provider_config::ProviderConfig::default()
+                    .with_region(sdk_config.region().cloned());
+                SharedCredentialsProvider::new(
+                    WebIdentityTokenCredentialsProvider::builder()
+                        .static_configuration(StaticConfiguration {
+                            web_identity_token_file: token_file.clone(),
+                            role_arn: role_arn.clone(),
+                            session_name,
+                        })
+                        .configure(&provider_config)
+                        .build(),
+                )
+            }
+            None => {
+                // Source credentials are the profile's or the default chain's
+                tracing::info!("Assuming {}", role_arn);
+                let mut builder = AssumeRoleProvider::builder(role_arn)
+                    .session_name(session_name)
+                    .configure(&sdk_config);
+                if let Some(external_id) = &self.external_id {
+                    builder = builder.external_id(external_id);
+                }
+                SharedCredentialsProvider::new(builder.build().await)
+            }
+        };
+
+        sdk_config
+            .into_builder()
+            .credentials_provider(provider)
+            .build()
+    }
+}
+
+/// IDs starting with `id_prefix` served from another bucket, e.g.
+/// `1000g/NA12878` from `NA12878.bam` in `s3://1000genomes`.
+///
+/// Routes are loaded from a TOML file of `[[route]]` tables (see
+/// [`BucketRoute::from_file`]); `bucket` is a bucket name or an
+/// `s3://bucket/prefix` URI.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BucketRoute {
+    /// Start of the IDs this route serves (e.g. `1000g/`); removed to form
+    /// the key
+    pub id_prefix: String,
+    /// Bucket name, or `s3://bucket/prefix`
+    pub bucket: String,
+    /// Key prefix within the bucket
+    #[serde(default)]
+    pub prefix: String,
+    /// Region of the bucket, if not the default one
+    pub region: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BucketRoutes {
+    #[serde(default, rename = "route")]
+    routes: Vec<BucketRoute>,
+}
+
+impl BucketRoute {
+    /// Parse routes from TOML.
+    pub fn from_toml(s: &str) -> Result<Vec<Self>> {
+        let routes: BucketRoutes = toml::from_str(s)
+            .map_err(|e| Error::InvalidInput(format!("invalid bucket routes: {}", e)))?;
+
+        for route in &routes.routes {
+            if route.id_prefix.is_empty() {
+                return Err(Error::InvalidInput(
+                    "invalid bucket routes: id_prefix must not be empty".to_string(),
+                ));
+            }
+            if route.location().0.is_empty() {
+                return Err(Error::InvalidInput(format!(
+                    "invalid bucket routes: no bucket for {}",
+                    route.id_prefix
+                )));
+            }
+        }
+
+        Ok(routes.routes)
+    }
+
+    /// Load routes from a TOML file.
+    pub fn from_file(path: &Path) -> Result<Vec<Self>> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::from_toml(&contents)
+    }
+
+    /// Bucket name and key prefix, splitting an `s3://` URI
+    fn location(&self) -> (String, String) {
+        match self.bucket.strip_prefix("s3://") {
+            Some(uri) => {
+                let (bucket, prefix) = uri.split_once('/').unwrap_or((uri, ""));
+                let prefix = [prefix.trim_matches('/'), self.prefix.trim_matches('/')]
+                    .into_iter()
+                    .filter(|p| !p.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                (bucket.to_string(), prefix)
+            }
+            None => (self.bucket.clone(), self.prefix.clone()),
+        }
+    }
+}
+
+/// A bucket and key prefix objects are stored under, and the IDs it serves.
+struct Bucket {
     client: Client,
-    bucket: String,
+    name: String,
     prefix: String,
+    /// Start of the IDs served from this bucket; empty for the default one
+    id_prefix: String,
+}
+
+impl Bucket {
+    /// Bucket serving `id`
+    fn bucket(&self, id: &str) -> &Bucket {
+        self.buckets
+            .iter()
+            .find(|bucket| id.starts_with(&bucket.id_prefix))
+            .expect("default bucket serves every ID")
+    }
+
+    /// Key of `id` without its extension: the key prefix, then the ID
+    /// without the route's ID prefix.
+    fn stem(&self, id: &str) -> String {
+        let id = id.strip_prefix(&self.id_prefix).unwrap_or(id);
+        format!("{}{}", self.key_prefix(), id)
+    }
+
+    /// ID and format of the data file stored under `key`, if it is one.
+    fn parse_key(&self, naming: &Naming, key: &str) -> Option<(String, Format)> {
+        let name = key.strip_prefix(&self.key_prefix())?;
+        let (id, format) = naming.parse(name)?;
+        Some((format!("{}{}", self.id_prefix, id), format))
+    }
+}
+
+/// An object in one of the storage's buckets
+struct Object<'a> {
+    bucket: &'a Bucket,
+    key: String,
+}
+
+impl Object<'_> {
+    /// Identifies the object across buckets, for metadata caches
+    fn cache_key(&self) -> String {
+        format!("{}/{}", self.bucket.name, self.key)
+    }
+}
+
+/// S3 storage backend for genomic data files.
+pub struct S3Storage {
+    /// Routed buckets, longest ID prefix first, then the default bucket
+    buckets: Vec<Bucket>,
+    /// SDK configuration and endpoint for the clients of routed buckets
+    sdk_config: aws_config::SdkConfig,
+    endpoint: Option<String>,
     cache_dir: PathBuf,
-    presign_expiry: Duration,
+    presign_expiry: PresignExpiry,
+    /// Server base URL when ticket URLs point at `/data` instead of S3
+    proxy_base_url: Option<String>,
+    /// Put unsigned object URLs in tickets rather than presigned ones
+    public_urls: bool,
+    /// `HeadObject` results by bucket and key; `None` for objects that don't exist
+    metadata: Cache<String, Option<ObjectMetadata>>,
+    /// Metadata cache shared with other replicas, behind `metadata`
+    shared: Option<Arc<dyn SharedCache>>,
+    /// Retries for transient failures, including throttling (`SlowDown`)
+    retry: RetryPolicy,
+    /// Bound on each request attempt, up to the response headers
+    timeout: Duration,
+    /// How index files are split into ranged requests
+    index_download: ChunkedDownload,
+    naming: Naming,
+    /// Data extension each object was found under
+    resolved: ResolvedExtensions,
 }
 
 impl S3Storage {
@@ -48,22 +366,32 @@ impl S3Storage {
         region: Option<String>,
         endpoint: Option<String>,
     ) -> Result<Self> {
-        // Build AWS config
-        let mut config_loader = aws_config::from_env();
-
-        if let Some(region) = region {
-            config_loader = config_loader.region(aws_config::Region::new(region));
-        }
-
-        let sdk_config = config_loader.load().await;
-
-        // Build S3 client with optional custom endpoint
-        let mut s3_config = aws_sdk_s3::config::Builder::from(&sdk_config);
-        if let Some(endpoint) = endpoint {
-            s3_config = s3_config.endpoint_url(endpoint).force_path_style(true);
-        }
+        Self::with_credentials(
+            bucket,
+            prefix,
+            cache_dir,
+            presign_expiry_secs,
+            region,
+            endpoint,
+            &S3Credentials::default(),
+        )
+        .await
+    }
 
-        let client = Client::from_conf(s3_config.build());
+    /// Create a new S3Storage instance whose credentials come from a named
+    /// profile or an assumed role, as [`S3Credentials`] describes; otherwise
+    /// as [`new`](Self::new).
+    pub async fn with_credentials(
+        bucket: String,
+        prefix: String,
+        cache_dir: PathBuf,
+        presign_expiry_secs: u64,
+        region: Option<String>,
+        endpoint: Option<String>,
+        credentials: &S3Credentials,
+    ) -> Result<Self> {
+        let sdk_config = credentials.sdk_config(region).await;
+        let client = s3_client(&sdk_config, endpoint.as_deref(), None);
 
         // Ensure cache directory exists
         fs::create_dir_all(&cache_dir)
@@ -71,233 +399,626 @@ impl S3Storage {
             .map_err(|e| Error::Internal(format!("failed to create cache dir: {}", e)))?;
 
         Ok(Self {
-            client,
-            bucket,
-            prefix,
+            buckets: vec![Bucket {
+                client,
+                name: bucket,
+                prefix,
+                id_prefix: String::new(),
+            }],
+            sdk_config,
+            endpoint,
             cache_dir,
-            presign_expiry: Duration::from_secs(presign_expiry_secs),
+            presign_expiry: PresignExpiry {
+                base: Duration::from_secs(presign_expiry_secs),
+                per_url: Duration::ZERO,
+                clock_skew: Duration::ZERO,
+            },
+            proxy_base_url: None,
+            public_urls: false,
+            metadata: Cache::builder()
+                .max_capacity(10_000)
+                .time_to_live(METADATA_TTL)
+                .build(),
+            shared: None,
+            retry: RetryPolicy::default(),
+            timeout: DEFAULT_TIMEOUT,
+            index_download: ChunkedDownload::default(),
+            naming: Naming::default(),
+            resolved: ResolvedExtensions::default(),
         })
     }
 
-    /// Construct the S3 key for a data file.
-    fn s3_key(&self, id: &str, format: Format) -> String {
-        let ext = Self::file_extension(format);
-        if self.prefix.is_empty() {
-            format!("{}.{}", id, ext)
-        } else {
-            let prefix = self.prefix.trim_end_matches('/');
-            format!("{}/{}.{}", prefix, id, ext)
+    /// Serve IDs starting with each route's ID prefix from its bucket rather
+    /// than the default one. The longest matching prefix wins.
+    pub fn with_bucket_routes(mut self, routes: Vec<BucketRoute>) -> Self {
+        let default = self.buckets.pop().expect("default bucket");
+        for route in routes {
+            let (name, prefix) = route.location();
+            let client = match &route.region {
+                Some(region) => s3_client(&self.sdk_config, self.endpoint.as_deref(), Some(region)),
+                None => default.client.clone(),
+            };
+            tracing::info!(
+                "Serving IDs under {} from s3://{}/{}",
+                route.id_prefix,
+                name,
+                prefix
+            );
+            self.buckets.push(Bucket {
+                client,
+                name,
+                prefix,
+                id_prefix: route.id_prefix,
+            });
         }
+        self.buckets
+            .sort_by(|a, b| b.id_prefix.len().cmp(&a.id_prefix.len()));
+        self.buckets.push(default);
+        self
     }
 
-    /// Construct the S3 key for an index file.
-    fn s3_index_key(&self, id: &str, format: Format, appended: bool) -> Option<String> {
-        let idx_ext = Self::index_extension(format)?;
-        let data_ext = Self::file_extension(format);
+    /// Share `HeadObject` results with other replicas through `cache`.
+    pub fn with_shared_cache(mut self, cache: Arc<dyn SharedCache>) -> Self {
+        self.shared = Some(cache);
+        self
+    }
 
-        let prefix = if self.prefix.is_empty() {
-            String::new()
-        } else {
-            format!("{}/", self.prefix.trim_end_matches('/'))
-        };
+    /// Give presigned URLs `per_url` longer for each data URL in their
+    /// ticket, so that slow clients can consume large tickets before they
+    /// expire.
+    pub fn with_presign_expiry_per_url(mut self, per_url: Duration) -> Self {
+        self.presign_expiry.per_url = per_url;
+        self
+    }
 
-        Some(if appended {
-            // e.g., sample.bam.bai
-            format!("{}{}.{}.{}", prefix, id, data_ext, idx_ext)
-        } else {
-            // e.g., sample.bai
-            format!("{}{}.{}", prefix, id, idx_ext)
-        })
+    /// Tolerate up to `skew` between this server's clock and S3's:
+    /// presigned URLs are dated `skew` early and stay valid `skew` past
+    /// their nominal expiry.
+    pub fn with_clock_skew(mut self, skew: Duration) -> Self {
+        self.presign_expiry.clock_skew = skew;
+        self
+    }
+
+    /// Retry transient S3 failures with `policy`.
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Give up on an S3 request attempt that hasn't responded within `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Fetch index files in ranged requests split according to `download`.
+    pub fn with_index_download(mut self, download: ChunkedDownload) -> Self {
+        self.index_download = download;
+        self
+    }
+
+    /// Use `naming` for data and index object extensions.
+    pub fn with_naming(mut self, naming: Naming) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// Point ticket URLs at this server's `/data` endpoint (rooted at
+    /// `base_url`), which streams objects from S3, instead of presigned URLs.
+    /// For deployments where clients can't reach the bucket.
+    pub fn with_proxy(mut self, base_url: impl Into<String>) -> Self {
+        self.proxy_base_url = Some(base_url.into());
+        self
+    }
+
+    /// Put plain object URLs, which don't expire, in tickets rather than
+    /// presigned ones. For public buckets, e.g. open datasets, that clients
+    /// can read anonymously; pair with [`S3Credentials::anonymous`].
+    pub fn with_public_urls(mut self) -> Self {
+        self.public_urls = true;
+        self
+    }
+
+    /// Fetch an object, or the given byte range of it.
+    async fn get_object(
+        &self,
+        id: &str,
+        object: &Object<'_>,
+        range: Option<&ByteRange>,
+    ) -> Result<GetObjectOutput> {
+        let range = range.and_then(range_header);
+
+        self.retry
+            .run("GetObject", || {
+                with_timeout("GetObject", self.timeout, async {
+                    object
+                        .bucket
+                        .client
+                        .get_object()
+                        .bucket(&object.bucket.name)
+                        .key(&object.key)
+                        .set_range(range.clone())
+                        .send()
+                        .await
+                        .map_err(|e| Failure {
+                            class: retry_class(&e),
+                            error: get_object_error(id, e),
+                        })
+                })
+            })
+            .await
+    }
+
+    /// Data extension of an object: the one it was last found under, or
+    /// the format's primary extension.
+    fn data_extension(&self, id: &str, format: Format) -> String {
+        self.resolved
+            .get(id, format)
+            .unwrap_or_else(|| self.naming.data_extensions(format)[0].clone())
     }
 
-    fn file_extension(format: Format) -> &'static str {
-        match format {
-            Format::Bam => "bam",
-            Format::Cram => "cram",
-            Format::Vcf => "vcf.gz",
-            Format::Bcf => "bcf",
-            Format::Fasta => "fa",
-            Format::Fastq => "fq.gz",
+    /// The S3 object of a data file.
+    fn s3_object(&self, id: &str, format: Format) -> Object<'_> {
+        let bucket = self.bucket(id);
+        Object {
+            key: format!("{}.{}", bucket.stem(id), self.data_extension(id, format)),
+            bucket,
         }
     }
 
-    fn index_extension(format: Format) -> Option<&'static str> {
-        match format {
-            Format::Bam => Some("bai"),
-            Format::Cram => Some("crai"),
-            Format::Vcf => Some("tbi"),
-            Format::Bcf => Some("csi"),
-            Format::Fasta => Some("fai"),
-            Format::Fastq => None,
+    /// The S3 object of a data file, probing each candidate extension the
+    /// first time. Falls back to the primary extension if none exists.
+    async fn data_object(&self, id: &str, format: Format) -> Result<Object<'_>> {
+        let exts = self.naming.data_extensions(format);
+        if exts.len() > 1 && self.resolved.get(id, format).is_none() {
+            let bucket = self.bucket(id);
+            for ext in exts {
+                let object = Object {
+                    bucket,
+                    key: format!("{}.{}", bucket.stem(id), ext),
+                };
+                if self.head(&object).await?.is_some() {
+                    self.resolved.insert(id, format, ext);
+                    return Ok(object);
+                }
+            }
         }
+        Ok(self.s3_object(id, format))
     }
 
-    /// Get the local cache path for an index file.
-    fn index_cache_path(&self, id: &str, format: Format, appended: bool) -> PathBuf {
-        let ext = Self::file_extension(format);
-        let idx_ext = Self::index_extension(format).unwrap_or("idx");
+    /// Candidate S3 objects for a data file's index, in the order to probe
+    /// them.
+    fn s3_index_objects(&self, id: &str, format: Format) -> Vec<Object<'_>> {
+        let bucket = self.bucket(id);
+        self.naming
+            .index_names(&bucket.stem(id), &self.data_extension(id, format), format)
+            .into_iter()
+            .map(|key| Object { bucket, key })
+            .collect()
+    }
 
-        if appended {
-            self.cache_dir.join(format!("{}.{}.{}", id, ext, idx_ext))
-        } else {
-            self.cache_dir.join(format!("{}.{}", id, idx_ext))
-        }
+    /// Get the local cache path for an index object: its key relative to
+    /// the bucket's prefix, under the bucket's ID prefix.
+    fn index_cache_path(&self, object: &Object<'_>) -> PathBuf {
+        let bucket = object.bucket;
+        let name = object
+            .key
+            .strip_prefix(&bucket.key_prefix())
+            .unwrap_or(&object.key);
+        self.cache_dir.join(format!("{}{}", bucket.id_prefix, name))
     }
 
     /// Get the local cache path for a data file header.
     fn data_cache_path(&self, id: &str, format: Format) -> PathBuf {
-        let ext = Self::file_extension(format);
+        let ext = self.data_extension(id, format);
         self.cache_dir.join(format!("{}.{}", id, ext))
     }
 
+    /// Object metadata, or `None` if it doesn't exist. Results are cached
+    /// for [`METADATA_TTL`]; errors other than not-found are not.
+    async fn head(&self, object: &Object<'_>) -> Result<Option<ObjectMetadata>> {
+        let (cache_key, key) = (object.cache_key(), &object.key);
+        if let Some(cached) = self.metadata.get(&cache_key).await {
+            return Ok(cached);
+        }
+
+        let shared_key = format!("{}:s3:{}", KEY_PREFIX, cache_key);
+        let shared = match &self.shared {
+            Some(shared) => shared.get(&shared_key).await,
+            None => None,
+        };
+        let shared =
+            shared.and_then(|bytes| serde_json::from_slice::<Option<ObjectMetadata>>(&bytes).ok());
+        if let Some(metadata) = shared {
+            self.metadata.insert(cache_key, metadata.clone()).await;
+            return Ok(metadata);
+        }
+
+        let metadata = self
+            .retry
+            .run("HeadObject", || {
+                with_timeout("HeadObject", self.timeout, async {
+                    match object
+                        .bucket
+                        .client
+                        .head_object()
+                        .bucket(&object.bucket.name)
+                        .key(key)
+                        .checksum_mode(ChecksumMode::Enabled)
+                        .send()
+                        .await
+                    {
+                        Ok(head) => Ok(Some(ObjectMetadata {
+                            size: head.content_length().unwrap_or(0) as u64,
+                            modified: head
+                                .last_modified()
+                                .and_then(|t| SystemTime::try_from(*t).ok()),
+                            etag: head.e_tag().map(str::to_string),
+                            sha256: full_object_checksum(
+                                head.checksum_sha256(),
+                                head.checksum_type(),
+                            ),
+                            crc32c: full_object_checksum(
+                                head.checksum_crc32_c(),
+                                head.checksum_type(),
+                            ),
+                        })),
+                        Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => {
+                            Ok(None)
+                        }
+                        Err(e) => Err(Failure {
+                            class: retry_class(&e),
+                            error: Error::Internal(format!(
+                                "S3 head_object failed for {}: {}",
+                                key, e
+                            )),
+                        }),
+                    }
+                })
+            })
+            .await?;
+
+        self.metadata.insert(cache_key, metadata.clone()).await;
+        if let (Some(shared), Ok(bytes)) = (&self.shared, serde_json::to_vec(&metadata)) {
+            shared.set(&shared_key, bytes, METADATA_TTL).await;
+        }
+        Ok(metadata)
+    }
+
     /// Check if an S3 object exists.
-    async fn object_exists(&self, key: &str) -> bool {
-        self.client
-            .head_object()
-            .bucket(&self.bucket)
-            .key(key)
-            .send()
+    async fn object_exists(&self, object: &Object<'_>) -> bool {
+        matches!(self.head(object).await, Ok(Some(_)))
+    }
+
+    /// Download an S3 object of `size` bytes to a local file.
+    async fn download_object(
+        &self,
+        object: &Object<'_>,
+        size: u64,
+        cache_path: &Path,
+    ) -> Result<()> {
+        self.index_download
+            .download(size, cache_path, move |range| async move {
+                let response = self.get_object(&object.key, object, Some(&range)).await?;
+                let body = response
+                    .body
+                    .collect()
+                    .await
+                    .map_err(|e| Error::Internal(format!("S3 read body failed: {}", e)))?;
+
+                Ok(body.into_bytes())
+            })
             .await
-            .is_ok()
     }
 
-    /// Download an S3 object to a local file.
-    async fn download_object(&self, s3_key: &str, cache_path: &PathBuf) -> Result<()> {
-        let response = self
+    /// Generate a presigned URL for an S3 object, valid for at least `expiry`.
+    /// Signing needs credentials, which may have to be fetched (e.g. from
+    /// STS or instance metadata); failures are retried under the storage's
+    /// retry policy, then fail the request rather than yield a bad URL.
+    async fn generate_presigned_url(
+        &self,
+        object: &Object<'_>,
+        range: Option<&ByteRange>,
+        expiry: Duration,
+    ) -> Result<String> {
+        let skew = self.presign_expiry.clock_skew;
+        let presign_config = PresigningConfig::builder()
+            .start_time(SystemTime::now() - skew)
+            .expires_in(expiry + skew * 2)
+            .build()
+            .map_err(|e| Error::Internal(format!("presign config error: {}", e)))?;
+
+        let mut request = object
+            .bucket
             .client
             .get_object()
+            .bucket(&object.bucket.name)
+            .key(&object.key);
+
+        // Add Range header if byte range specified
+        if let Some(range_header) = range.and_then(range_header) {
+            request = request.range(range_header);
+        }
+
+        let presigned = self
+            .retry
+            .run("presign", || {
+                with_timeout("presign", self.timeout, async {
+                    request
+                        .clone()
+                        .presigned(presign_config.clone())
+                        .await
+                        .map_err(|e| {
+                            Failure::transient(
+                                Error::Internal(format!("presign failed: {}", e)),
+                                RetryClass::Connect,
+                            )
+                        })
+                })
+            })
+            .await?;
+
+        Ok(presigned.uri().to_string())
+    }
+}
+
+/// Bucket and key prefix that files are uploaded to, such as the results
+/// of export jobs (see [`crate::jobs`]).
+pub struct S3Upload {
+    client: Client,
+    bucket: String,
+    prefix: String,
+    presign_expiry: Duration,
+}
+
+impl S3Upload {
+    /// Upload to `bucket` under `prefix`, with `credentials` as for
+    /// [`S3Storage::with_credentials`]. Uploads are handed out as URLs
+    /// presigned for `presign_expiry_secs`.
+    pub async fn new(
+        bucket: String,
+        prefix: String,
+        region: Option<String>,
+        endpoint: Option<String>,
+        credentials: &S3Credentials,
+        presign_expiry_secs: u64,
+    ) -> Self {
+        let sdk_config = credentials.sdk_config(region).await;
+        Self {
+            client: s3_client(&sdk_config, endpoint.as_deref(), None),
+            bucket,
+            prefix,
+            presign_expiry: Duration::from_secs(presign_expiry_secs),
+        }
+    }
+
+    /// Upload the file at `path` as `name` under the prefix, returning its
+    /// `s3://` URL. Uploads are tried once.
+    pub async fn put(&self, name: &str, path: &Path) -> Result<String> {
+        let key = format!("{}{}", self.prefix, name);
+        let body = aws_sdk_s3::primitives::ByteStream::from_path(path)
+            .await
+            .map_err(|e| Error::Internal(format!("failed to read {}: {}", path.display(), e)))?;
+        self.client
+            .put_object()
             .bucket(&self.bucket)
-            .key(s3_key)
+            .key(&key)
+            .body(body)
             .send()
             .await
-            .map_err(|e| Error::Internal(format!("S3 get_object failed: {}", e)))?;
-
-        let body = response
-            .body
-            .collect()
-            .await
-            .map_err(|e| Error::Internal(format!("S3 read body failed: {}", e)))?;
+            .map_err(|e| Error::Internal(format!("S3 upload failed: {}", e)))?;
+        Ok(format!("s3://{}/{}", self.bucket, key))
+    }
 
-        let mut file = fs::File::create(cache_path)
+    /// Presigned HTTPS URL of the upload at `url`, an `s3://` URL `put`
+    /// returned
+    pub async fn presign(&self, url: &str) -> Result<String> {
+        let presign_config = PresigningConfig::expires_in(self.presign_expiry)
+            .map_err(|e| Error::Internal(format!("presign config error: {}", e)))?;
+        let presigned = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.key(url)?)
+            .presigned(presign_config)
             .await
-            .map_err(|e| Error::Internal(format!("create cache file failed: {}", e)))?;
+            .map_err(|e| Error::Internal(format!("presign failed: {}", e)))?;
+        Ok(presigned.uri().to_string())
+    }
 
-        file.write_all(&body.into_bytes())
+    /// Delete the upload at `url`
+    pub async fn delete(&self, url: &str) -> Result<()> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(self.key(url)?)
+            .send()
             .await
-            .map_err(|e| Error::Internal(format!("write cache file failed: {}", e)))?;
-
+            .map_err(|e| Error::Internal(format!("S3 delete failed: {}", e)))?;
         Ok(())
     }
 
-    /// Generate a presigned URL for an S3 object.
-    async fn generate_presigned_url(&self, key: &str, range: Option<&ByteRange>) -> Result<String> {
-        let presign_config = PresigningConfig::builder()
-            .expires_in(self.presign_expiry)
-            .build()
-            .map_err(|e| Error::Internal(format!("presign config error: {}", e)))?;
+    /// Key of the upload at `url`, which must be in this bucket
+    fn key<'a>(&self, url: &'a str) -> Result<&'a str> {
+        url.strip_prefix("s3://")
+            .and_then(|url| url.strip_prefix(self.bucket.as_str()))
+            .and_then(|url| url.strip_prefix('/'))
+            .ok_or_else(|| Error::Internal(format!("not an upload of this bucket: {}", url)))
+    }
+}
 
-        let mut request = self.client.get_object().bucket(&self.bucket).key(key);
+/// S3 client with an optional custom endpoint and region. SDK retries are
+/// off: `RetryPolicy` retries instead, as it does for the HTTP backend.
+fn s3_client(
+    sdk_config: &aws_config::SdkConfig,
+    endpoint: Option<&str>,
+    region: Option<&str>,
+) -> Client {
+    let mut s3_config =
+        aws_sdk_s3::config::Builder::from(sdk_config).retry_config(RetryConfig::disabled());
+    if let Some(endpoint) = endpoint {
+        s3_config = s3_config.endpoint_url(endpoint).force_path_style(true);
+    }
+    if let Some(region) = region {
+        s3_config = s3_config.region(aws_config::Region::new(region.to_string()));
+    }
+    Client::from_conf(s3_config.build())
+}
 
-        // Add Range header if byte range specified
-        if let Some(r) = range {
-            let range_header = match r.end {
-                Some(end) => format!("bytes={}-{}", r.start, end),
-                None => format!("bytes={}-", r.start),
-            };
-            request = request.range(range_header);
+/// Unsigned URL of an object: path-style under a custom endpoint or for
+/// bucket names with dots (which don't match S3's TLS certificate),
+/// otherwise virtual-hosted in the bucket's region.
+fn public_url(endpoint: Option<&str>, object: &Object<'_>) -> String {
+    let name = &object.bucket.name;
+    let host = match object.bucket.client.config().region() {
+        Some(region) => format!("s3.{}.amazonaws.com", region),
+        None => "s3.amazonaws.com".to_string(),
+    };
+    let base = match endpoint {
+        Some(endpoint) => format!("{}/{}", endpoint.trim_end_matches('/'), name),
+        None if name.contains('.') => format!("https://{}/{}", host, name),
+        None => format!("https://{}.{}", name, host),
+    };
+
+    match url::Url::parse(&base) {
+        Ok(mut url) => {
+            url.path_segments_mut()
+                .map(|mut segments| {
+                    segments.pop_if_empty().extend(object.key.split('/'));
+                })
+                .ok();
+            url.to_string()
         }
+        Err(_) => format!("{}/{}", base, object.key),
+    }
+}
 
-        let presigned = request
-            .presigned(presign_config)
-            .await
-            .map_err(|e| Error::Internal(format!("presign failed: {}", e)))?;
+/// Retry class of a failed S3 call; `None` for permanent failures.
+fn retry_class<E>(e: &SdkError<E, HttpResponse>) -> Option<RetryClass> {
+    match e {
+        SdkError::TimeoutError(_) => Some(RetryClass::Timeout),
+        SdkError::DispatchFailure(f) if f.is_timeout() => Some(RetryClass::Timeout),
+        SdkError::DispatchFailure(f) if f.is_io() => Some(RetryClass::Connect),
+        SdkError::ResponseError(_) => Some(RetryClass::ServerError),
+        // S3 throttles with 503 SlowDown
+        SdkError::ServiceError(e) => RetryClass::from_status(e.raw().status().as_u16()),
+        _ => None,
+    }
+}
 
-        Ok(presigned.uri().to_string())
+fn get_object_error(id: &str, e: SdkError<GetObjectError>) -> Error {
+    if e.as_service_error().is_some_and(|e| e.is_no_such_key()) {
+        Error::NotFound(id.to_string())
+    } else {
+        Error::Internal(format!("S3 get_object failed for {}: {}", id, e))
     }
 }
 
 #[async_trait]
 impl Storage for S3Storage {
     async fn exists(&self, id: &str, format: Format) -> Result<bool> {
-        let key = self.s3_key(id, format);
-        Ok(self.object_exists(&key).await)
+        let object = self.data_object(id, format).await?;
+        Ok(self.head(&object).await?.is_some())
     }
 
     async fn file_info(&self, id: &str, format: Format) -> Result<FileInfo> {
-        let key = self.s3_key(id, format);
+        let object = self.data_object(id, format).await?;
 
         let head = self
-            .client
-            .head_object()
-            .bucket(&self.bucket)
-            .key(&key)
-            .send()
-            .await
-            .map_err(|_| Error::NotFound(id.to_string()))?;
-
-        let size = head.content_length().unwrap_or(0) as u64;
-
-        // Check if index exists (try both naming conventions)
-        let has_index = if let Some(appended_key) = self.s3_index_key(id, format, true) {
-            if self.object_exists(&appended_key).await {
-                true
-            } else if let Some(replaced_key) = self.s3_index_key(id, format, false) {
-                self.object_exists(&replaced_key).await
-            } else {
-                false
+            .head(&object)
+            .await?
+            .ok_or_else(|| Error::NotFound(id.to_string()))?;
+
+        // Check if an index exists under any naming convention
+        let mut has_index = false;
+        for index_object in self.s3_index_objects(id, format) {
+            if self.object_exists(&index_object).await {
+                has_index = true;
+                break;
             }
-        } else {
-            false
-        };
+        }
 
         Ok(FileInfo {
             id: id.to_string(),
             format,
-            size,
+            size: head.size,
             has_index,
+            modified: head.modified,
+            etag: head.etag,
         })
     }
 
-    fn data_url(&self, id: &str, format: Format, range: Option<ByteRange>) -> String {
-        // Generate presigned URL for direct S3 access
-        // This is synchronous in the trait but we need async AWS SDK
-        // Use block_in_place to call async from sync context
-        let key = self.s3_key(id, format);
+    async fn stored_checksums(&self, id: &str, format: Format) -> Result<Checksums> {
+        let object = self.data_object(id, format).await?;
+        let head = self
+            .head(&object)
+            .await?
+            .ok_or_else(|| Error::NotFound(id.to_string()))?;
 
-        tokio::task::block_in_place(|| {
-            tokio::runtime::Handle::current().block_on(async {
-                self.generate_presigned_url(&key, range.as_ref())
-                    .await
-                    .unwrap_or_else(|e| {
-                        tracing::error!("Failed to generate presigned URL: {}", e);
-                        format!("error://presign-failed?reason={}", e)
-                    })
-            })
+        let info = FileInfo {
+            id: id.to_string(),
+            format,
+            size: head.size,
+            has_index: false,
+            modified: head.modified,
+            etag: head.etag,
+        };
+        Ok(Checksums {
+            md5: info.md5(),
+            sha256: head.sha256,
+            crc32c: head.crc32c,
+            etag: info.etag,
         })
     }
 
-    async fn read_bytes(
+    async fn data_url(&self, id: &str, format: Format, range: Option<ByteRange>) -> Result<String> {
+        self.ticket_data_url(id, format, range, 1).await
+    }
+
+    async fn ticket_data_url(
         &self,
         id: &str,
         format: Format,
         range: Option<ByteRange>,
-    ) -> Result<Bytes> {
-        let key = self.s3_key(id, format);
+        ticket_urls: usize,
+    ) -> Result<String> {
+        if let Some(base_url) = &self.proxy_base_url {
+            return Ok(data_endpoint_url(base_url, id, format, range.as_ref()));
+        }
 
-        let mut request = self.client.get_object().bucket(&self.bucket).key(&key);
+        let object = self.s3_object(id, format);
+        if self.public_urls {
+            return Ok(public_url(self.endpoint.as_deref(), &object));
+        }
 
-        if let Some(ref r) = range {
-            let range_header = match r.end {
-                Some(end) => format!("bytes={}-{}", r.start, end),
-                None => format!("bytes={}-", r.start),
-            };
-            request = request.range(range_header);
+        // Generate presigned URL for direct S3 access
+        self.generate_presigned_url(
+            &object,
+            range.as_ref(),
+            self.presign_expiry.for_ticket(ticket_urls),
+        )
+        .await
+    }
+
+    fn url_expiry(&self, ticket_urls: usize) -> Option<Duration> {
+        if self.proxy_base_url.is_some() || self.public_urls {
+            return None;
         }
+        Some(self.presign_expiry.for_ticket(ticket_urls))
+    }
 
-        let response = request
-            .send()
-            .await
-            .map_err(|_| Error::NotFound(id.to_string()))?;
+    async fn read_bytes(
+        &self,
+        id: &str,
+        format: Format,
+        range: Option<ByteRange>,
+    ) -> Result<Bytes> {
+        if is_empty_range(range.as_ref()) {
+            return Ok(Bytes::new());
+        }
+
+        let object = self.data_object(id, format).await?;
+        let response = self.get_object(id, &object, range.as_ref()).await?;
 
         let body = response
             .body
@@ -308,33 +1029,109 @@ impl Storage for S3Storage {
         Ok(body.into_bytes())
     }
 
-    async fn index_path(&self, id: &str, format: Format) -> Result<Option<PathBuf>> {
-        // Try appended index first (e.g., sample.bam.bai)
-        if let Some(s3_key) = self.s3_index_key(id, format, true) {
-            let cache_path = self.index_cache_path(id, format, true);
+    async fn read_stream(
+        &self,
+        id: &str,
+        format: Format,
+        range: Option<ByteRange>,
+    ) -> Result<DataReader> {
+        if is_empty_range(range.as_ref()) {
+            return Ok(Box::pin(tokio::io::empty()));
+        }
 
-            // Check cache first
-            if cache_path.exists() {
-                return Ok(Some(cache_path));
-            }
+        let object = self.data_object(id, format).await?;
+        let response = self.get_object(id, &object, range.as_ref()).await?;
 
-            // Check if exists in S3 and download
-            if self.object_exists(&s3_key).await {
-                self.download_object(&s3_key, &cache_path).await?;
-                return Ok(Some(cache_path));
+        Ok(Box::pin(response.body.into_async_read()))
+    }
+
+    async fn redirect_url(&self, id: &str, format: Format) -> Result<Option<String>> {
+        let object = self.data_object(id, format).await?;
+        if self.public_urls {
+            return Ok(Some(public_url(self.endpoint.as_deref(), &object)));
+        }
+        let expiry = self.presign_expiry.for_ticket(1);
+        Ok(Some(
+            self.generate_presigned_url(&object, None, expiry).await?,
+        ))
+    }
+
+    async fn list(&self) -> Result<Vec<ListedFile>> {
+        let mut files = Vec::new();
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            let prefix = Some(bucket.key_prefix()).filter(|p| !p.is_empty());
+
+            let mut continuation = None;
+            loop {
+                let page = self
+                    .retry
+                    .run("ListObjectsV2", || {
+                        with_timeout("ListObjectsV2", self.timeout, async {
+                            bucket
+                                .client
+                                .list_objects_v2()
+                                .bucket(&bucket.name)
+                                .set_prefix(prefix.clone())
+                                .set_continuation_token(continuation.clone())
+                                .send()
+                                .await
+                                .map_err(|e| Failure {
+                                    class: retry_class(&e),
+                                    error: Error::Internal(format!(
+                                        "S3 list_objects_v2 failed: {}",
+                                        e
+                                    )),
+                                })
+                        })
+                    })
+                    .await?;
+
+                for object in page.contents() {
+                    let Some((id, format)) = object
+                        .key()
+                        .and_then(|key| bucket.parse_key(&self.naming, key))
+                    else {
+                        continue;
+                    };
+                    // Skip objects whose ID another route serves
+                    if !std::ptr::eq(self.bucket(&id), &self.buckets[i]) {
+                        continue;
+                    }
+                    files.push(ListedFile {
+                        id,
+                        format,
+                        modified: object
+                            .last_modified()
+                            .and_then(|t| SystemTime::try_from(*t).ok()),
+                    });
+                }
+
+                continuation = page.next_continuation_token().map(str::to_string);
+                if continuation.is_none() {
+                    break;
+                }
             }
         }
 
-        // Try replaced extension (e.g., sample.bai)
-        if let Some(s3_key) = self.s3_index_key(id, format, false) {
-            let cache_path = self.index_cache_path(id, format, false);
+        Ok(files)
+    }
+
+    async fn index_path(&self, id: &str, format: Format) -> Result<Option<PathBuf>> {
+        self.data_object(id, format).await?;
 
+        // e.g. sample.bam.bai, then sample.bai
+        for object in self.s3_index_objects(id, format) {
+            let cache_path = self.index_cache_path(&object);
+
+            // Check cache first
             if cache_path.exists() {
                 return Ok(Some(cache_path));
             }
 
-            if self.object_exists(&s3_key).await {
-                self.download_object(&s3_key, &cache_path).await?;
+            // Check if exists in S3 and download
+            if let Ok(Some(head)) = self.head(&object).await {
+                self.download_object(&object, head.size, &cache_path)
+                    .await?;
                 return Ok(Some(cache_path));
             }
         }
@@ -369,23 +1166,156 @@ mod tests {
     }
 
     #[test]
-    fn test_file_extensions() {
-        assert_eq!(S3Storage::file_extension(Format::Bam), "bam");
-        assert_eq!(S3Storage::file_extension(Format::Cram), "cram");
-        assert_eq!(S3Storage::file_extension(Format::Vcf), "vcf.gz");
-        assert_eq!(S3Storage::file_extension(Format::Bcf), "bcf");
-        assert_eq!(S3Storage::file_extension(Format::Fasta), "fa");
-        assert_eq!(S3Storage::file_extension(Format::Fastq), "fq.gz");
+    fn test_presign_expiry_for_ticket() {
+        let expiry = PresignExpiry {
+            base: Duration::from_secs(3600),
+            per_url: Duration::from_secs(10),
+            clock_skew: Duration::from_secs(300),
+        };
+        assert_eq!(expiry.for_ticket(0), Duration::from_secs(3600));
+        assert_eq!(expiry.for_ticket(60), Duration::from_secs(4200));
+
+        // Capped below the SigV4 limit, leaving room for the skew headroom
+        assert_eq!(
+            expiry.for_ticket(usize::MAX),
+            MAX_PRESIGN_EXPIRY - Duration::from_secs(600)
+        );
+    }
+
+    fn test_bucket(name: &str, prefix: &str, id_prefix: &str) -> Bucket {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(aws_sdk_s3::config::BehaviorVersion::latest())
+            .build();
+        Bucket {
+            client: Client::from_conf(config),
+            name: name.to_string(),
+            prefix: prefix.to_string(),
+            id_prefix: id_prefix.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_upload_key() {
+        let bucket = test_bucket("exports", "", "");
+        let upload = S3Upload {
+            client: bucket.client,
+            bucket: "exports".to_string(),
+            prefix: "jobs/".to_string(),
+            presign_expiry: Duration::from_secs(3600),
+        };
+        assert_eq!(
+            upload.key("s3://exports/jobs/0a1b/sample1.sam.gz").unwrap(),
+            "jobs/0a1b/sample1.sam.gz"
+        );
+        assert!(
+            upload
+                .key("s3://exports-2/jobs/0a1b/sample1.sam.gz")
+                .is_err()
+        );
+        assert!(upload.key("https://exports/jobs/0a1b").is_err());
+    }
+
+    #[test]
+    fn test_bucket_routes() {
+        let routes = BucketRoute::from_toml(
+            r#"
+            [[route]]
+            id_prefix = "1000g/"
+            bucket = "s3://1000genomes/phase3/"
+            region = "us-east-1"
+
+            [[route]]
+            id_prefix = "internal/"
+            bucket = "our-bucket"
+            prefix = "aligned"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(
+            routes[0].location(),
+            ("1000genomes".to_string(), "phase3".to_string())
+        );
+        assert_eq!(routes[0].region.as_deref(), Some("us-east-1"));
+        assert_eq!(
+            routes[1].location(),
+            ("our-bucket".to_string(), "aligned".to_string())
+        );
+
+        assert!(BucketRoute::from_toml("[[route]]\nid_prefix = \"\"\nbucket = \"b\"").is_err());
+        assert!(
+            BucketRoute::from_toml("[[route]]\nid_prefix = \"a/\"\nbucket = \"s3://\"").is_err()
+        );
+        assert!(BucketRoute::from_toml("[[route]]\nid_prefix = \"a/\"\nbuckt = \"b\"").is_err());
+    }
+
+    #[test]
+    fn test_bucket_keys() {
+        let naming = Naming::default();
+        let routed = test_bucket("1000genomes", "phase3/", "1000g/");
+        assert_eq!(routed.stem("1000g/NA12878"), "phase3/NA12878");
+        assert_eq!(
+            routed.parse_key(&naming, "phase3/NA12878.bam"),
+            Some(("1000g/NA12878".to_string(), Format::Bam))
+        );
+        assert_eq!(routed.parse_key(&naming, "other/NA12878.bam"), None);
+
+        let default = test_bucket("our-bucket", "", "");
+        assert_eq!(default.stem("sample1"), "sample1");
+        assert_eq!(
+            Object {
+                bucket: &default,
+                key: "sample1.bam".to_string()
+            }
+            .cache_key(),
+            "our-bucket/sample1.bam"
+        );
+    }
+
+    #[test]
+    fn test_public_url() {
+        let bucket = test_bucket("1000genomes", "phase3", "");
+        let object = Object {
+            bucket: &bucket,
+            key: "phase3/NA12878 v2.bam".to_string(),
+        };
+        assert_eq!(
+            public_url(None, &object),
+            "https://1000genomes.s3.amazonaws.com/phase3/NA12878%20v2.bam"
+        );
+        assert_eq!(
+            public_url(Some("http://localhost:9000/"), &object),
+            "http://localhost:9000/1000genomes/phase3/NA12878%20v2.bam"
+        );
+
+        let dotted = test_bucket("open.data", "", "");
+        let object = Object {
+            bucket: &dotted,
+            key: "sample1.bam".to_string(),
+        };
+        assert_eq!(
+            public_url(None, &object),
+            "https://s3.amazonaws.com/open.data/sample1.bam"
+        );
     }
 
     #[test]
-    fn test_index_extensions() {
-        assert_eq!(S3Storage::index_extension(Format::Bam), Some("bai"));
-        assert_eq!(S3Storage::index_extension(Format::Cram), Some("crai"));
-        assert_eq!(S3Storage::index_extension(Format::Vcf), Some("tbi"));
-        assert_eq!(S3Storage::index_extension(Format::Bcf), Some("csi"));
-        assert_eq!(S3Storage::index_extension(Format::Fasta), Some("fai"));
-        assert_eq!(S3Storage::index_extension(Format::Fastq), None);
+    fn test_full_object_checksum() {
+        // CRC32C of "hello world"
+        assert_eq!(
+            full_object_checksum(Some("yZRlqg=="), Some(&ChecksumType::FullObject)),
+            Some("c99465aa".to_string())
+        );
+        assert_eq!(
+            full_object_checksum(Some("yZRlqg=="), None),
+            Some("c99465aa".to_string())
+        );
+        assert_eq!(full_object_checksum(Some("yZRlqg==-3"), None), None);
+        assert_eq!(
+            full_object_checksum(Some("yZRlqg=="), Some(&ChecksumType::Composite)),
+            None
+        );
+        assert_eq!(full_object_checksum(None, None), None);
     }
 
     #[test]
@@ -0,0 +1,238 @@
+//! TLS serving with optional client certificate (mTLS) authentication.
+//!
+//! [`serve`] is a TLS-terminating replacement for `axum::serve`. When a client
+//! CA bundle is configured, clients present certificates signed by that CA and
+//! the verified subject is attached to each request as a [`ClientCertificate`]
+//! extension, which the auth middleware treats as the authenticated principal.
+//!
+//! Enable with the `tls` feature.
+
+use crate::{Error, Result};
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use rustls::ServerConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+
+/// Wait after failing to accept a connection, as `axum::serve` does
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// TLS listener configuration
+#[derive(Debug, Clone)]
+pub struct TlsOptions {
+    /// Server certificate chain (PEM)
+    pub cert: PathBuf,
+    /// Server private key (PEM)
+    pub key: PathBuf,
+    /// CA bundle for verifying client certificates (PEM); enables mTLS
+    pub client_ca: Option<PathBuf>,
+    /// Reject connections without a client certificate
+    pub require_client_cert: bool,
+}
+
+/// Verified client certificate, available as a request extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    /// Certificate subject distinguished name (e.g. `CN=pipeline,O=Example`)
+    pub subject: String,
+}
+
+impl ClientCertificate {
+    /// Parse the subject from a DER-encoded certificate.
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        Some(Self {
+            subject: cert.subject().to_string(),
+        })
+    }
+}
+
+impl TlsOptions {
+    /// Build the rustls server configuration.
+    pub fn server_config(&self) -> Result<Arc<ServerConfig>> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?;
+
+        let builder = match &self.client_ca {
+            Some(ca) => {
+                let mut roots = rustls::RootCertStore::empty();
+                for cert in load_certs(ca)? {
+                    roots.add(cert).map_err(tls_error)?;
+                }
+
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                let verifier = if self.require_client_cert {
+                    verifier.build()
+                } else {
+                    verifier.allow_unauthenticated().build()
+                }
+                .map_err(tls_error)?;
+
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut config = builder
+            .with_single_cert(load_certs(&self.cert)?, load_key(&self.key)?)
+            .map_err(tls_error)?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(Arc::new(config))
+    }
+}
+
+/// Serve `app` over TLS on `listener`. Failing to accept a connection
+/// doesn't stop the server: like `axum::serve`, it logs the error and, unless
+/// only that connection was lost, waits a moment (e.g. for file descriptors
+/// to free up) before accepting again.
+pub async fn serve(listener: TcpListener, app: Router, config: Arc<ServerConfig>) -> Result<()> {
+    let acceptor = TlsAcceptor::from(config);
+
+    loop {
+        let (stream, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) if is_connection_error(&e) => continue,
+            Err(e) => {
+                tracing::error!("failed to accept connection: {}", e);
+                tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!("TLS handshake with {} failed: {}", remote, e);
+                    return;
+                }
+            };
+
+            let client_cert = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| ClientCertificate::from_der(cert.as_ref()));
+            if let Some(cert) = &client_cert {
+                tracing::debug!("client {} authenticated as {}", remote, cert.subject);
+            }
+
+            let service = hyper::service::service_fn(
+                move |mut request: hyper::Request<hyper::body::Incoming>| {
+                    request
+                        .extensions_mut()
+                        .insert(axum::extract::ConnectInfo(remote));
+                    if let Some(cert) = &client_cert {
+                        request.extensions_mut().insert(cert.clone());
+                    }
+                    app.clone().oneshot(request)
+                },
+            );
+
+            if let Err(e) = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!("connection from {} closed with error: {}", remote, e);
+            }
+        });
+    }
+}
+
+/// Whether an accept error only concerns the connection being accepted
+fn is_connection_error(e: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    matches!(
+        e.kind(),
+        ErrorKind::ConnectionRefused | ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset
+    )
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<std::io::Result<Vec<_>>>()?;
+
+    if certs.is_empty() {
+        return Err(Error::InvalidInput(format!(
+            "no certificates found in {}",
+            path.display()
+        )));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| Error::InvalidInput(format!("no private key found in {}", path.display())))
+}
+
+fn tls_error(e: impl std::fmt::Display) -> Error {
+    Error::InvalidInput(format!("invalid TLS configuration: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_cert_file() {
+        let options = TlsOptions {
+            cert: "missing.pem".into(),
+            key: "missing.key".into(),
+            client_ca: None,
+            require_client_cert: false,
+        };
+        assert!(matches!(options.server_config(), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_empty_cert_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert = dir.path().join("cert.pem");
+        std::fs::write(&cert, "").unwrap();
+
+        let options = TlsOptions {
+            cert: cert.clone(),
+            key: cert,
+            client_ca: None,
+            require_client_cert: false,
+        };
+        assert!(matches!(
+            options.server_config(),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_accept_errors() {
+        use std::io::{Error, ErrorKind};
+
+        assert!(is_connection_error(&Error::from(
+            ErrorKind::ConnectionAborted
+        )));
+        // EMFILE: the listener is fine, and accepts again after a pause
+        assert!(!is_connection_error(&Error::from_raw_os_error(24)));
+    }
+
+    #[test]
+    fn test_client_certificate_from_invalid_der() {
+        assert_eq!(ClientCertificate::from_der(b"not a certificate"), None);
+    }
+}
@@ -15,6 +15,16 @@
 //! - [`VariantsQuery`] / [`VariantsPostBody`] - Parameters for variants endpoint
 //! - [`Region`] - Genomic region specification
 //!
+//! # Extension Types
+//!
+//! - [`MetadataResponse`] - File metadata from `/meta/:endpoint/:id`
+//! - [`ChecksumsResponse`] - Whole-file checksums from `/checksums/:endpoint/:id`
+//! - [`ReferencesResponse`] - Header contigs from `/reads/:id/references`
+//! - [`VariantStatsResponse`] - Index-estimated record counts from `/variants/:id/stats`
+//! - [`CoverageResponse`] - Read depth from `/reads/:id/coverage`
+//! - [`ReadFeature`] / [`VariantFeature`] - Genome browser features from
+//!   `/reads/:id/features` and `/variants/:id/features`
+//!
 //! # Formats
 //!
 //! - [`Format`] - Data format enum (BAM, CRAM, VCF, BCF, FASTA, FASTQ)
@@ -22,21 +32,39 @@
 
 use serde::{Deserialize, Serialize};
 
+/// Media type of ticket responses, for the protocol version implemented
+pub const HTSGET_MEDIA_TYPE: &str = "application/vnd.ga4gh.htsget.v1.3.0+json";
+
 /// htsget response format per spec 1.3.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct HtsgetResponse {
     pub htsget: HtsgetResponseBody,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct HtsgetResponseBody {
     pub format: Format,
     pub urls: Vec<UrlEntry>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub md5: Option<String>,
+    /// When the first of `urls` expires (RFC 3339); extension, absent when
+    /// the URLs don't expire
+    #[serde(rename = "expiresAt", skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    /// Seconds from the response until `expiresAt`, for clients whose
+    /// clocks disagree with the server's; extension
+    #[serde(rename = "expiresIn", skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<u64>,
+    /// Ticket URL of the next page of `urls`, when the ticket is paged;
+    /// extension
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct UrlEntry {
     pub url: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -46,7 +74,8 @@ pub struct UrlEntry {
 }
 
 /// Data formats supported by htsget
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "UPPERCASE")]
 pub enum Format {
     #[default]
@@ -60,6 +89,40 @@ pub enum Format {
 }
 
 impl Format {
+    /// Every format, in declaration order
+    pub const ALL: [Format; 6] = [
+        Format::Bam,
+        Format::Cram,
+        Format::Vcf,
+        Format::Bcf,
+        Format::Fasta,
+        Format::Fastq,
+    ];
+
+    /// Default data file extensions (without the dot), in order of preference
+    pub fn data_extensions(&self) -> &'static [&'static str] {
+        match self {
+            Format::Bam => &["bam"],
+            Format::Cram => &["cram"],
+            Format::Vcf => &["vcf.gz", "vcf"],
+            Format::Bcf => &["bcf"],
+            Format::Fasta => &["fa", "fa.gz"],
+            Format::Fastq => &["fq.gz", "fq"],
+        }
+    }
+
+    /// Default index file extensions (without the dot), in order of preference
+    pub fn index_extensions(&self) -> &'static [&'static str] {
+        match self {
+            Format::Bam => &["bai"],
+            Format::Cram => &["crai"],
+            Format::Vcf => &["tbi"],
+            Format::Bcf => &["csi"],
+            Format::Fasta => &["fai"],
+            Format::Fastq => &[],
+        }
+    }
+
     pub fn content_type(&self) -> &'static str {
         match self {
             Format::Bam => "application/vnd.ga4gh.bam",
@@ -82,10 +145,54 @@ impl Format {
     pub fn is_sequences(&self) -> bool {
         matches!(self, Format::Fasta | Format::Fastq)
     }
+
+    /// Formats of the same datatype, starting with this one
+    pub fn alternatives(&self) -> [Format; 2] {
+        match self {
+            Format::Bam => [Format::Bam, Format::Cram],
+            Format::Cram => [Format::Cram, Format::Bam],
+            Format::Vcf => [Format::Vcf, Format::Bcf],
+            Format::Bcf => [Format::Bcf, Format::Vcf],
+            Format::Fasta => [Format::Fasta, Format::Fastq],
+            Format::Fastq => [Format::Fastq, Format::Fasta],
+        }
+    }
+}
+
+/// The format's name as htsget writes it (`BAM`, `CRAM`, ...).
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Format::Bam => "BAM",
+            Format::Cram => "CRAM",
+            Format::Vcf => "VCF",
+            Format::Bcf => "BCF",
+            Format::Fasta => "FASTA",
+            Format::Fastq => "FASTQ",
+        })
+    }
+}
+
+/// Parse a format name case-insensitively (`bam`, `VCF`, ...).
+impl std::str::FromStr for Format {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "BAM" => Ok(Format::Bam),
+            "CRAM" => Ok(Format::Cram),
+            "VCF" => Ok(Format::Vcf),
+            "BCF" => Ok(Format::Bcf),
+            "FASTA" => Ok(Format::Fasta),
+            "FASTQ" => Ok(Format::Fastq),
+            _ => Err(crate::Error::UnsupportedFormat(s.to_string())),
+        }
+    }
 }
 
 /// Data class - header only or full data
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum DataClass {
     #[default]
@@ -93,11 +200,33 @@ pub enum DataClass {
     Header,
 }
 
+impl std::str::FromStr for DataClass {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "body" => Ok(DataClass::Body),
+            "header" => Ok(DataClass::Header),
+            _ => Err(crate::Error::InvalidInput(format!(
+                "unknown class: {} (expected header or body)",
+                s
+            ))),
+        }
+    }
+}
+
 /// Query parameters for GET requests
 #[derive(Debug, Deserialize, Default)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
 pub struct ReadsQuery {
     pub format: Option<Format>,
-    pub class: Option<DataClass>,
+    /// `header` or `body`; parsed by the handler so that other values are
+    /// reported as `InvalidInput`
+    pub class: Option<String>,
     #[serde(rename = "referenceName")]
     pub reference_name: Option<String>,
     pub start: Option<u64>,
@@ -105,12 +234,20 @@ pub struct ReadsQuery {
     pub fields: Option<String>,
     pub tags: Option<String>,
     pub notags: Option<String>,
+    /// `true` for only the records overlapping the region, decoded and
+    /// re-compressed by the server (BAM only; extension)
+    pub precise: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, Default)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
 pub struct VariantsQuery {
     pub format: Option<Format>,
-    pub class: Option<DataClass>,
+    pub class: Option<String>,
     #[serde(rename = "referenceName")]
     pub reference_name: Option<String>,
     pub start: Option<u64>,
@@ -119,32 +256,359 @@ pub struct VariantsQuery {
 
 /// POST request body for multiple regions
 #[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct ReadsPostBody {
     pub format: Option<Format>,
-    pub class: Option<DataClass>,
+    pub class: Option<String>,
     pub regions: Option<Vec<Region>>,
     pub fields: Option<Vec<String>>,
     pub tags: Option<Vec<String>>,
     pub notags: Option<Vec<String>>,
+    /// As the `precise` query parameter
+    pub precise: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct VariantsPostBody {
     pub format: Option<Format>,
-    pub class: Option<DataClass>,
+    pub class: Option<String>,
     pub regions: Option<Vec<Region>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Region {
     #[serde(rename = "referenceName")]
     pub reference_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub start: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<u64>,
+}
+
+impl Region {
+    /// A region from query parameters, in htsget's 0-based half-open
+    /// coordinates. Per the spec `start` defaults to the start of the
+    /// reference sequence and `end` to its end, so any combination of the two
+    /// is valid as long as `start` is not past `end`.
+    pub fn new(
+        reference_name: impl Into<String>,
+        start: Option<u64>,
+        end: Option<u64>,
+    ) -> crate::Result<Self> {
+        let region = Self {
+            reference_name: reference_name.into(),
+            start,
+            end,
+        };
+        region.validate()?;
+        Ok(region)
+    }
+
+    /// Check that the region names a reference sequence and `start` is not
+    /// past `end`
+    pub fn validate(&self) -> crate::Result<()> {
+        if self.reference_name.is_empty() {
+            return Err(crate::Error::InvalidInput(
+                "referenceName is empty".to_string(),
+            ));
+        }
+        if let (start, Some(end)) = self.bounds() {
+            if start > end {
+                return Err(crate::Error::InvalidRange(format!(
+                    "start {} is greater than end {}",
+                    start, end
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The 0-based half-open bounds, with `start` defaulted; `None` ends at
+    /// the end of the reference sequence
+    pub fn bounds(&self) -> (u64, Option<u64>) {
+        (self.start.unwrap_or(0), self.end)
+    }
+
+    /// Whether the region covers no bases (e.g. `end=0`, or `start == end`)
+    pub fn is_empty(&self) -> bool {
+        matches!(self.bounds(), (start, Some(end)) if start >= end)
+    }
+}
+
+/// Parse a samtools-style region string (`chr1`, `chr1:1001-2000`, `chr1:1001-`).
+///
+/// Region strings are 1-based and inclusive; they are converted to htsget's
+/// 0-based half-open coordinates (`chr1:1001-2000` becomes start=1000, end=2000).
+/// Reference names may contain `:` (e.g. `HLA-A*01:01:01:01`): only a suffix
+/// that looks like an interval is parsed as one.
+impl std::str::FromStr for Region {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || crate::Error::InvalidInput(format!("invalid region: {}", s));
+
+        let (name, interval) = match s.rsplit_once(':') {
+            Some((name, "")) => (name, None),
+            Some((name, interval)) if is_interval(interval) => (name, Some(interval)),
+            _ => (s, None),
+        };
+
+        if name.is_empty() {
+            return Err(invalid());
+        }
+
+        let (start, end) = match interval {
+            None => (None, None),
+            Some(interval) => {
+                let interval = interval.replace(',', "");
+                let (start, end) = interval.split_once('-').unwrap_or((interval.as_str(), ""));
+                let start: u64 = start.parse().map_err(|_| invalid())?;
+                let end = match end {
+                    "" => None,
+                    e => Some(e.parse::<u64>().map_err(|_| invalid())?),
+                };
+                (Some(start.saturating_sub(1)), end)
+            }
+        };
+
+        Ok(Region {
+            reference_name: name.to_string(),
+            start,
+            end,
+        })
+    }
+}
+
+/// Whether `s` is a region string's interval: `<digits>[-[<digits>]]`, with
+/// commas allowed as thousands separators
+fn is_interval(s: &str) -> bool {
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit() || b == b',');
+    let (start, end) = s.split_once('-').unwrap_or((s, ""));
+    start.bytes().any(|b| b.is_ascii_digit()) && digits(start) && digits(end)
+}
+
+/// Response of the `/meta/:endpoint/:id` extension endpoint
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MetadataResponse {
+    pub htsget: FileMetadata,
+}
+
+/// What a client needs to know about a file before slicing it
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FileMetadata {
+    pub id: String,
+    pub format: Format,
+    /// Size in bytes
+    pub size: u64,
+    /// HTTP-date of the last modification, if the backend reports one
+    #[serde(rename = "lastModified", skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// Available index types (e.g. `BAI`, `TBI`, `GZI`)
+    pub indexes: Vec<String>,
+    /// Reference sequences declared in the header (or FASTA index)
+    #[serde(rename = "referenceSequences")]
+    pub reference_sequences: Vec<ReferenceSequence>,
+    pub checksums: Checksums,
+}
+
+/// Response of the `/reads/:id/references` and `/variants/:id/references`
+/// extension endpoints
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReferencesResponse {
+    pub htsget: References,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct References {
+    pub format: Format,
+    #[serde(rename = "referenceSequences")]
+    pub reference_sequences: Vec<ReferenceSequence>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReferenceSequence {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<u64>,
+}
+
+/// Response of the `/variants/:id/stats` extension endpoint
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct VariantStatsResponse {
+    pub htsget: VariantStats,
+}
+
+/// Record counts estimated from the index, without reading data
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct VariantStats {
+    pub format: Format,
+    #[serde(rename = "referenceName")]
+    pub reference_name: String,
+    /// Records the index maps to the reference sequence
+    #[serde(rename = "totalRecords")]
+    pub total_records: u64,
+    pub bins: Vec<StatsBin>,
+}
+
+/// One window of a stats request (0-based, half-open)
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StatsBin {
+    pub start: u64,
+    /// Absent when the window runs to the end of a reference of unknown length
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub end: Option<u64>,
+    #[serde(rename = "estimatedRecords")]
+    pub estimated_records: u64,
+}
+
+/// Response of the `/reads/:id/coverage` extension endpoint
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CoverageResponse {
+    pub htsget: Coverage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Coverage {
+    pub format: Format,
+    #[serde(rename = "referenceName")]
+    pub reference_name: String,
+    pub method: CoverageMethod,
+    pub bins: Vec<CoverageBin>,
+}
+
+/// How coverage was computed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum CoverageMethod {
+    /// Counted from decoded alignments
+    Records,
+    /// Estimated from the index
+    Index,
+}
+
+/// One window of a coverage request (0-based, half-open)
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CoverageBin {
+    pub start: u64,
+    pub end: u64,
+    /// Alignments overlapping the window
+    pub reads: u64,
+    /// Aligned bases per reference base
+    #[serde(rename = "meanDepth")]
+    pub mean_depth: f64,
+}
+
+/// An alignment as an igv.js JSON feature (`/reads/:id/features`, requires
+/// `explore` feature). Coordinates are 0-based, half-open.
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReadFeature {
+    pub chr: String,
+    pub start: u64,
+    pub end: u64,
+    /// Read name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// `+` or `-`
+    pub strand: char,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mapq: Option<u8>,
+    pub cigar: String,
+    /// SAM flags
+    pub flags: u16,
+}
+
+/// A variant as an igv.js JSON feature (`/variants/:id/features`, requires
+/// `explore` feature). Coordinates are 0-based, half-open.
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct VariantFeature {
+    pub chr: String,
+    pub start: u64,
+    pub end: u64,
+    /// Variant IDs (`ID` column), if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(rename = "ref")]
+    pub reference_bases: String,
+    pub alt: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qual: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+}
+
+/// Whole-file checksums, as lowercase hex. In `/meta` responses, those the
+/// backend can report without reading the whole file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Checksums {
+    /// MD5 of the content, when the backend ETag is one (e.g. single-part S3 uploads)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    /// SHA-256 of the content (e.g. S3 `x-amz-checksum-sha256` metadata)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// CRC32C of the content, big-endian (e.g. S3 `x-amz-checksum-crc32c`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crc32c: Option<String>,
+    /// Backend version identifier; changes whenever the content does
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+}
+
+impl Checksums {
+    /// Whether every checksum (not the ETag) is known
+    pub fn is_complete(&self) -> bool {
+        self.md5.is_some() && self.sha256.is_some() && self.crc32c.is_some()
+    }
+
+    /// Fill in the checksums missing here from `other`
+    pub fn or(self, other: &Checksums) -> Checksums {
+        Checksums {
+            md5: self.md5.or_else(|| other.md5.clone()),
+            sha256: self.sha256.or_else(|| other.sha256.clone()),
+            crc32c: self.crc32c.or_else(|| other.crc32c.clone()),
+            etag: self.etag.or_else(|| other.etag.clone()),
+        }
+    }
+}
+
+/// Response of the `/checksums/:endpoint/:id` extension endpoint
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ChecksumsResponse {
+    pub htsget: FileChecksums,
+}
+
+/// Checksums for verifying a full-file download
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FileChecksums {
+    pub id: String,
+    pub format: Format,
+    /// Size in bytes
+    pub size: u64,
+    pub checksums: Checksums,
 }
 
 /// Service info response (GA4GH service-info spec)
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct ServiceInfo {
     pub id: String,
     pub name: String,
@@ -156,6 +620,7 @@ pub struct ServiceInfo {
 }
 
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct ServiceType {
     pub group: String,
     pub artifact: String,
@@ -163,12 +628,14 @@ pub struct ServiceType {
 }
 
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Organization {
     pub name: String,
     pub url: String,
 }
 
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct HtsgetCapabilities {
     pub datatype: String,
     pub formats: Vec<Format>,
@@ -227,6 +694,35 @@ mod tests {
         assert!(Format::Fastq.is_sequences());
     }
 
+    #[test]
+    fn test_format_alternatives() {
+        for format in Format::ALL {
+            let [first, other] = format.alternatives();
+            assert_eq!(first, format);
+            assert_ne!(other, format);
+            assert_eq!(other.alternatives(), [other, format]);
+            assert_eq!(format.is_reads(), other.is_reads());
+            assert_eq!(format.is_variants(), other.is_variants());
+        }
+    }
+
+    #[test]
+    fn test_format_extensions() {
+        assert_eq!(Format::Bam.data_extensions(), ["bam"]);
+        assert_eq!(Format::Cram.data_extensions(), ["cram"]);
+        assert_eq!(Format::Vcf.data_extensions(), ["vcf.gz", "vcf"]);
+        assert_eq!(Format::Bcf.data_extensions(), ["bcf"]);
+        assert_eq!(Format::Fasta.data_extensions(), ["fa", "fa.gz"]);
+        assert_eq!(Format::Fastq.data_extensions(), ["fq.gz", "fq"]);
+
+        assert_eq!(Format::Bam.index_extensions(), ["bai"]);
+        assert_eq!(Format::Cram.index_extensions(), ["crai"]);
+        assert_eq!(Format::Vcf.index_extensions(), ["tbi"]);
+        assert_eq!(Format::Bcf.index_extensions(), ["csi"]);
+        assert_eq!(Format::Fasta.index_extensions(), ["fai"]);
+        assert!(Format::Fastq.index_extensions().is_empty());
+    }
+
     #[test]
     fn test_data_class_default() {
         assert_eq!(DataClass::default(), DataClass::Body);
@@ -270,14 +766,20 @@ mod tests {
                     class: Some(DataClass::Body),
                 }],
                 md5: None,
+                expires_at: None,
+                expires_in: None,
+                next: None,
             },
         };
         let json = serde_json::to_string(&response).unwrap();
         assert!(json.contains("\"format\":\"BAM\""));
         assert!(json.contains("\"url\":\"http://example.com/data\""));
         assert!(json.contains("\"class\":\"body\""));
-        // md5 should be omitted when None
+        // md5, expiresAt, expiresIn and next should be omitted when None
         assert!(!json.contains("\"md5\""));
+        assert!(!json.contains("\"expiresAt\""));
+        assert!(!json.contains("\"expiresIn\""));
+        assert!(!json.contains("\"next\""));
     }
 
     #[test]
@@ -290,6 +792,87 @@ mod tests {
         assert_eq!(query.end, Some(200));
     }
 
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!("bam".parse::<Format>().unwrap(), Format::Bam);
+        assert_eq!("VCF".parse::<Format>().unwrap(), Format::Vcf);
+        assert!("sam".parse::<Format>().is_err());
+
+        for format in Format::ALL {
+            assert_eq!(format.to_string().parse::<Format>().unwrap(), format);
+            assert_eq!(
+                serde_json::to_string(&format).unwrap(),
+                format!("\"{}\"", format)
+            );
+        }
+    }
+
+    #[test]
+    fn test_region_from_str() {
+        let region: Region = "chr1:1001-2,000".parse().unwrap();
+        assert_eq!(region.reference_name, "chr1");
+        assert_eq!(region.start, Some(1000));
+        assert_eq!(region.end, Some(2000));
+
+        let region: Region = "chrM".parse().unwrap();
+        assert_eq!(region.reference_name, "chrM");
+        assert_eq!(region.start, None);
+        assert_eq!(region.end, None);
+
+        let region: Region = "HLA-A*01:01:01:01:100-".parse().unwrap();
+        assert_eq!(region.reference_name, "HLA-A*01:01:01:01");
+        assert_eq!(region.start, Some(99));
+        assert_eq!(region.end, None);
+
+        let region: Region = "HLA-A*01:01:01:01".parse().unwrap();
+        assert_eq!(region.reference_name, "HLA-A*01:01:01:01");
+        assert_eq!(region.start, None);
+        assert_eq!(region.end, None);
+
+        let region: Region = "HLA-A*01:01:01:01:1-2".parse().unwrap();
+        assert_eq!(region.reference_name, "HLA-A*01:01:01:01");
+        assert_eq!(region.start, Some(0));
+        assert_eq!(region.end, Some(2));
+
+        let region: Region = "chr1:abc".parse().unwrap();
+        assert_eq!(region.reference_name, "chr1:abc");
+        assert_eq!(region.start, None);
+
+        let region: Region = "chr1:".parse().unwrap();
+        assert_eq!(region.reference_name, "chr1");
+        assert_eq!(region.start, None);
+        assert_eq!(region.end, None);
+
+        assert!(":1-2".parse::<Region>().is_err());
+        assert!(":".parse::<Region>().is_err());
+        assert!("chr1:99999999999999999999-".parse::<Region>().is_err());
+    }
+
+    #[test]
+    fn test_region_bounds() {
+        let region = |start, end| Region::new("chr1", start, end).unwrap();
+
+        assert_eq!(region(None, None).bounds(), (0, None));
+        assert_eq!(region(Some(100), None).bounds(), (100, None));
+        assert_eq!(region(None, Some(5000)).bounds(), (0, Some(5000)));
+        assert_eq!(region(Some(100), Some(200)).bounds(), (100, Some(200)));
+
+        assert!(!region(None, None).is_empty());
+        assert!(!region(Some(u64::MAX), None).is_empty());
+        assert!(!region(None, Some(1)).is_empty());
+        assert!(region(None, Some(0)).is_empty());
+        assert!(region(Some(100), Some(100)).is_empty());
+
+        assert!(matches!(
+            Region::new("chr1", Some(200), Some(100)),
+            Err(crate::Error::InvalidRange(_))
+        ));
+        assert!(matches!(
+            Region::new("", None, Some(100)),
+            Err(crate::Error::InvalidInput(_))
+        ));
+    }
+
     #[test]
     fn test_region_deserialization() {
         let json = r#"{"referenceName":"chr1","start":0,"end":1000}"#;
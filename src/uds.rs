@@ -0,0 +1,105 @@
+//! Serving over a Unix domain socket, for deployments behind a reverse proxy
+//! on the same host.
+//!
+//! Socket peers have no IP address, so each request carries a loopback
+//! `ConnectInfo` instead: listing `127.0.0.1` in the trusted proxies lets the
+//! proxy's `Forwarded`/`X-Forwarded-*` headers set the ticket base URL.
+//!
+//! Enable with the `uds` feature (Unix only).
+
+use crate::{Error, Result};
+use axum::Router;
+use axum::extract::ConnectInfo;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use tokio::net::UnixListener;
+use tower::ServiceExt;
+
+/// Bind a Unix domain socket at `path`, replacing a stale socket left by a
+/// previous run. Any other existing file is an error.
+pub fn bind(path: &Path) -> Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(Error::InvalidInput(format!(
+                "{} exists and is not a socket",
+                path.display()
+            )));
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(UnixListener::bind(path)?)
+}
+
+/// Serve `app` on `listener`.
+pub async fn serve(listener: UnixListener, app: Router) -> Result<()> {
+    let peer = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let app = app.clone();
+
+        tokio::spawn(async move {
+            let service = hyper::service::service_fn(
+                move |mut request: hyper::Request<hyper::body::Incoming>| {
+                    request.extensions_mut().insert(ConnectInfo(peer));
+                    app.clone().oneshot(request)
+                },
+            );
+
+            if let Err(e) = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!("socket connection closed with error: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_serve() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("htsgetr.sock");
+
+        // A stale socket is replaced
+        drop(bind(&path).unwrap());
+        let listener = bind(&path).unwrap();
+
+        let app = Router::new().route(
+            "/",
+            get(|ConnectInfo(peer): ConnectInfo<SocketAddr>| async move { peer.ip().to_string() }),
+        );
+        tokio::spawn(serve(listener, app));
+
+        let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("127.0.0.1"), "{}", response);
+    }
+
+    #[test]
+    fn test_bind_refuses_regular_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bam");
+        std::fs::write(&path, b"").unwrap();
+
+        assert!(matches!(bind(&path), Err(Error::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,332 @@
+//! Bytes-served accounting for usage reporting.
+//!
+//! Data access committees often need to know how much of each dataset was
+//! released, and to whom. [`UsageStats`] observes every data response (see
+//! [`RequestObserver`]) and counts the requests and bytes served per dataset
+//! and per authenticated principal, and per principal within each dataset.
+//! Counts are kept in memory from startup.
+//!
+//! They are served in two forms:
+//!
+//! | Path | Format |
+//! |------|--------|
+//! | `GET /metrics` | Prometheus text exposition |
+//! | `GET /admin/usage` | JSON report |
+//!
+//! With an admin token, both require `Authorization: Bearer <token>`;
+//! otherwise they are as open as the rest of the server. Like quotas, only
+//! bytes that pass through the server are counted: redirects to storage,
+//! and tickets whose URLs point at storage directly, are not.
+
+use crate::admin::bearer_matches;
+use crate::handlers::{AppState, DataServed, RequestObserver, rfc3339};
+use crate::{Error, Result};
+use async_trait::async_trait;
+use axum::{
+    Json, Router,
+    http::{HeaderMap, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Route of the Prometheus metrics
+pub const METRICS_PATH: &str = "/metrics";
+
+/// Route of the JSON usage report
+pub const USAGE_REPORT_PATH: &str = "/admin/usage";
+
+/// Requests and bytes served
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Served {
+    pub requests: u64,
+    pub bytes: u64,
+}
+
+impl Served {
+    fn add(&mut self, bytes: u64) {
+        self.requests += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// What was served of one dataset
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DatasetUsage {
+    #[serde(flatten)]
+    pub served: Served,
+    /// Per authenticated principal; unauthenticated requests count only
+    /// toward the dataset
+    pub principals: BTreeMap<String, Served>,
+}
+
+/// Everything served since counting started
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    /// When counting started, as an RFC 3339 timestamp
+    pub since: String,
+    pub datasets: BTreeMap<String, DatasetUsage>,
+    pub principals: BTreeMap<String, Served>,
+}
+
+#[derive(Default)]
+struct Counters {
+    datasets: HashMap<String, DatasetUsage>,
+    principals: HashMap<String, Served>,
+}
+
+/// Counts of the data served per dataset and principal.
+pub struct UsageStats {
+    counters: Mutex<Counters>,
+    since: SystemTime,
+    admin_token: Option<String>,
+}
+
+impl Default for UsageStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UsageStats {
+    /// Start counting now. The report and metrics are open until an admin
+    /// token is set.
+    pub fn new() -> Self {
+        Self {
+            counters: Mutex::default(),
+            since: SystemTime::now(),
+            admin_token: None,
+        }
+    }
+
+    /// Require `Authorization: Bearer <token>` for the report and metrics.
+    pub fn with_admin_token(mut self, token: impl Into<String>) -> Self {
+        self.admin_token = Some(token.into());
+        self
+    }
+
+    /// Whether the report and metrics check a token of their own, rather
+    /// than relying on the server's authentication.
+    pub fn has_admin_token(&self) -> bool {
+        self.admin_token.is_some()
+    }
+
+    /// Count a response of `bytes` of dataset `id` served to `subject`.
+    pub fn record(&self, id: &str, subject: Option<&str>, bytes: u64) {
+        let mut counters = self.counters.lock().unwrap();
+        let dataset = counters.datasets.entry(id.to_string()).or_default();
+        dataset.served.add(bytes);
+        if let Some(subject) = subject {
+            dataset
+                .principals
+                .entry(subject.to_string())
+                .or_default()
+                .add(bytes);
+            counters
+                .principals
+                .entry(subject.to_string())
+                .or_default()
+                .add(bytes);
+        }
+    }
+
+    /// Everything counted so far
+    pub fn report(&self) -> UsageReport {
+        let counters = self.counters.lock().unwrap();
+        UsageReport {
+            since: rfc3339(self.since),
+            datasets: counters
+                .datasets
+                .iter()
+                .map(|(id, usage)| (id.clone(), usage.clone()))
+                .collect(),
+            principals: counters
+                .principals
+                .iter()
+                .map(|(subject, served)| (subject.clone(), *served))
+                .collect(),
+        }
+    }
+
+    /// The counts in the Prometheus text exposition format
+    pub fn prometheus(&self) -> String {
+        let report = self.report();
+        let mut out = String::new();
+
+        let mut family = |name: &str, help: &str, label: &str, values: Vec<(&String, u64)>| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (key, value) in values {
+                let _ = writeln!(
+                    out,
+                    "{}{{{}=\"{}\"}} {}",
+                    name,
+                    label,
+                    escape_label(key),
+                    value
+                );
+            }
+        };
+
+        let datasets = || report.datasets.iter();
+        family(
+            "htsget_dataset_bytes_served_total",
+            "Bytes of data responses per dataset",
+            "dataset",
+            datasets().map(|(id, u)| (id, u.served.bytes)).collect(),
+        );
+        family(
+            "htsget_dataset_requests_total",
+            "Data responses per dataset",
+            "dataset",
+            datasets().map(|(id, u)| (id, u.served.requests)).collect(),
+        );
+
+        let principals = || report.principals.iter();
+        family(
+            "htsget_principal_bytes_served_total",
+            "Bytes of data responses per authenticated principal",
+            "principal",
+            principals().map(|(sub, s)| (sub, s.bytes)).collect(),
+        );
+        family(
+            "htsget_principal_requests_total",
+            "Data responses per authenticated principal",
+            "principal",
+            principals().map(|(sub, s)| (sub, s.requests)).collect(),
+        );
+        out
+    }
+
+    /// Whether `headers` carry the admin token, if one is required
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        match &self.admin_token {
+            Some(token) => bearer_matches(headers, token),
+            None => true,
+        }
+    }
+
+    fn check(&self, headers: &HeaderMap) -> Result<()> {
+        if self.authorized(headers) {
+            Ok(())
+        } else {
+            Err(Error::InvalidAuthentication)
+        }
+    }
+
+    /// The report and metrics routes
+    pub fn routes(self: &Arc<Self>) -> Router<AppState> {
+        let (metrics, report) = (self.clone(), self.clone());
+        Router::new()
+            .route(
+                METRICS_PATH,
+                get(move |headers: HeaderMap| {
+                    let stats = metrics.clone();
+                    async move { stats.metrics_response(&headers) }
+                }),
+            )
+            .route(
+                USAGE_REPORT_PATH,
+                get(move |headers: HeaderMap| {
+                    let stats = report.clone();
+                    async move { stats.report_response(&headers) }
+                }),
+            )
+    }
+
+    /// `GET /metrics`
+    fn metrics_response(&self, headers: &HeaderMap) -> Result<Response> {
+        self.check(headers)?;
+        let content_type = [(header::CONTENT_TYPE, "text/plain; version=0.0.4")];
+        Ok((content_type, self.prometheus()).into_response())
+    }
+
+    /// `GET /admin/usage`
+    fn report_response(&self, headers: &HeaderMap) -> Result<Response> {
+        self.check(headers)?;
+        Ok(Json(self.report()).into_response())
+    }
+}
+
+#[async_trait]
+impl RequestObserver for UsageStats {
+    async fn on_data_served(&self, served: &DataServed) {
+        self.record(&served.id, served.subject.as_deref(), served.bytes);
+    }
+}
+
+/// Escape a Prometheus label value
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_record() {
+        let stats = UsageStats::new();
+        stats.record("prod/sample1", Some("alice"), 100);
+        stats.record("prod/sample1", Some("bob"), 50);
+        stats.record("prod/sample1", None, 10);
+        stats.record("prod/sample2", Some("alice"), 5);
+
+        let report = stats.report();
+        let sample1 = &report.datasets["prod/sample1"];
+        assert_eq!(
+            sample1.served,
+            Served {
+                requests: 3,
+                bytes: 160
+            }
+        );
+        assert_eq!(sample1.principals["alice"].bytes, 100);
+        assert_eq!(sample1.principals.len(), 2);
+        assert_eq!(
+            report.principals["alice"],
+            Served {
+                requests: 2,
+                bytes: 105
+            }
+        );
+    }
+
+    #[test]
+    fn test_prometheus() {
+        let stats = UsageStats::new();
+        stats.record("a\"b", Some("alice"), 7);
+
+        let text = stats.prometheus();
+        assert!(text.contains("# TYPE htsget_dataset_bytes_served_total counter"));
+        assert!(text.contains("htsget_dataset_bytes_served_total{dataset=\"a\\\"b\"} 7"));
+        assert!(text.contains("htsget_principal_requests_total{principal=\"alice\"} 1"));
+    }
+
+    #[test]
+    fn test_admin_token() {
+        let mut headers = HeaderMap::new();
+        assert!(UsageStats::new().authorized(&headers));
+
+        let stats = UsageStats::new().with_admin_token("secret");
+        assert!(!stats.authorized(&headers));
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer wrong"),
+        );
+        assert!(!stats.authorized(&headers));
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer secret"),
+        );
+        assert!(stats.authorized(&headers));
+    }
+}
@@ -0,0 +1,317 @@
+//! WebAssembly bindings for browser clients, such as igv.js-style genome
+//! viewers, using wasm-bindgen.
+//!
+//! Build without the server (which needs tokio's runtime and the
+//! filesystem) for `wasm32-unknown-unknown`:
+//!
+//! ```bash
+//! wasm-pack build --target web --no-default-features --features wasm
+//! ```
+//!
+//! The `HtsgetClient` class follows tickets like [`crate::client`]'s
+//! client, over the `fetch` API: it requests every page of a ticket, sends
+//! each URL's headers (e.g. `Range`), decodes inline `data:` URIs, and
+//! checks the ticket's `md5`. Methods return promises; rejected ones carry
+//! an `Error` with the htsget error message.
+//!
+//! ```js
+//! import init, { HtsgetClient } from "./pkg/htsgetr.js";
+//!
+//! await init();
+//! const client = new HtsgetClient("https://htsget.example.org");
+//! client.setBearerToken(token);
+//! const ticket = JSON.parse(await client.reads("NA12878", "chr1", 10000, 20000));
+//! const bam = await client.readsData("NA12878", "chr1", 10000, 20000); // Uint8Array
+//! ```
+
+use crate::client::{
+    TicketRequest, base_url, check_md5, decode_data_uri, resolve_url, server_error, ticket_accept,
+    ticket_url,
+};
+use crate::types::{HtsgetResponse, Region};
+use crate::{Error, Result};
+use md5::{Digest, Md5};
+use std::collections::HashMap;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{JsFuture, future_to_promise};
+
+#[wasm_bindgen]
+extern "C" {
+    /// The global `fetch`, in windows and workers alike
+    #[wasm_bindgen(js_name = fetch)]
+    fn fetch_request(request: &web_sys::Request) -> js_sys::Promise;
+}
+
+fn to_js_error(e: Error) -> JsValue {
+    JsError::new(&e.to_string()).into()
+}
+
+/// A JavaScript exception from `fetch` and friends, for a request to `url`
+fn request_error(url: &url::Url, e: JsValue) -> Error {
+    let message = e
+        .dyn_ref::<js_sys::Error>()
+        .map(|e| String::from(e.message()))
+        .or_else(|| e.as_string())
+        .unwrap_or_else(|| format!("{:?}", e));
+    Error::Internal(format!("request to {} failed: {}", url, message))
+}
+
+/// A ticket position from JavaScript, where numbers are doubles
+fn position(value: Option<f64>) -> Result<Option<u64>> {
+    value
+        .map(|value| {
+            if value >= 0.0 && value.fract() == 0.0 && value < u64::MAX as f64 {
+                Ok(value as u64)
+            } else {
+                Err(Error::InvalidInput(format!("invalid position: {}", value)))
+            }
+        })
+        .transpose()
+}
+
+/// Build a ticket request from the arguments shared by the methods
+fn ticket_request(
+    reference_name: Option<String>,
+    start: Option<f64>,
+    end: Option<f64>,
+    format: Option<String>,
+) -> Result<TicketRequest> {
+    let (start, end) = (position(start)?, position(end)?);
+    Ok(TicketRequest {
+        format: format.as_deref().map(str::parse).transpose()?,
+        class: None,
+        region: reference_name.map(|reference_name| Region {
+            reference_name,
+            start,
+            end,
+        }),
+    })
+}
+
+/// Client for htsget servers, for JavaScript
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct HtsgetClient {
+    base_url: url::Url,
+    bearer_token: Option<String>,
+}
+
+#[wasm_bindgen]
+impl HtsgetClient {
+    /// Create a client for the server at `base_url` (e.g.
+    /// `https://htsget.example.org`)
+    #[wasm_bindgen(constructor)]
+    pub fn new(base_url: &str) -> std::result::Result<HtsgetClient, JsError> {
+        Ok(Self {
+            base_url: self::base_url(base_url).map_err(|e| JsError::new(&e.to_string()))?,
+            bearer_token: None,
+        })
+    }
+
+    /// Send `Authorization: Bearer <token>` with ticket requests
+    #[wasm_bindgen(js_name = setBearerToken)]
+    pub fn set_bearer_token(&mut self, token: String) {
+        self.bearer_token = Some(token);
+    }
+
+    /// Fetch the reads ticket for a given ID (JSON string)
+    pub fn reads(
+        &self,
+        id: String,
+        reference_name: Option<String>,
+        start: Option<f64>,
+        end: Option<f64>,
+        format: Option<String>,
+    ) -> js_sys::Promise {
+        self.ticket_json(
+            "reads",
+            id,
+            ticket_request(reference_name, start, end, format),
+        )
+    }
+
+    /// Fetch the variants ticket for a given ID (JSON string)
+    pub fn variants(
+        &self,
+        id: String,
+        reference_name: Option<String>,
+        start: Option<f64>,
+        end: Option<f64>,
+        format: Option<String>,
+    ) -> js_sys::Promise {
+        self.ticket_json(
+            "variants",
+            id,
+            ticket_request(reference_name, start, end, format),
+        )
+    }
+
+    /// Fetch reads data by following the ticket (`Uint8Array`)
+    #[wasm_bindgen(js_name = readsData)]
+    pub fn reads_data(
+        &self,
+        id: String,
+        reference_name: Option<String>,
+        start: Option<f64>,
+        end: Option<f64>,
+        format: Option<String>,
+    ) -> js_sys::Promise {
+        self.data(
+            "reads",
+            id,
+            ticket_request(reference_name, start, end, format),
+        )
+    }
+
+    /// Fetch variants data by following the ticket (`Uint8Array`)
+    #[wasm_bindgen(js_name = variantsData)]
+    pub fn variants_data(
+        &self,
+        id: String,
+        reference_name: Option<String>,
+        start: Option<f64>,
+        end: Option<f64>,
+        format: Option<String>,
+    ) -> js_sys::Promise {
+        self.data(
+            "variants",
+            id,
+            ticket_request(reference_name, start, end, format),
+        )
+    }
+}
+
+impl HtsgetClient {
+    fn ticket_json(
+        &self,
+        endpoint: &'static str,
+        id: String,
+        request: Result<TicketRequest>,
+    ) -> js_sys::Promise {
+        let client = self.clone();
+
+        future_to_promise(async move {
+            let ticket = client
+                .ticket(endpoint, &id, &request.map_err(to_js_error)?)
+                .await
+                .map_err(to_js_error)?;
+            let json = serde_json::to_string(&ticket)
+                .map_err(|e| to_js_error(Error::Internal(e.to_string())))?;
+            Ok(JsValue::from_str(&json))
+        })
+    }
+
+    fn data(
+        &self,
+        endpoint: &'static str,
+        id: String,
+        request: Result<TicketRequest>,
+    ) -> js_sys::Promise {
+        let client = self.clone();
+
+        future_to_promise(async move {
+            let ticket = client
+                .ticket(endpoint, &id, &request.map_err(to_js_error)?)
+                .await
+                .map_err(to_js_error)?;
+            let bytes = client.fetch(&ticket).await.map_err(to_js_error)?;
+            Ok(js_sys::Uint8Array::from(&bytes[..]).into())
+        })
+    }
+
+    /// Request a ticket, with the URLs of all its pages
+    async fn ticket(
+        &self,
+        endpoint: &str,
+        id: &str,
+        request: &TicketRequest,
+    ) -> Result<HtsgetResponse> {
+        let url = ticket_url(&self.base_url, endpoint, id, request)?;
+
+        let mut ticket = self.ticket_page(&url).await?;
+        while let Some(next) = ticket.htsget.next.take() {
+            let url = resolve_url(&self.base_url, &next)?;
+            let page = self.ticket_page(&url).await?;
+            ticket.htsget.urls.extend(page.htsget.urls);
+            ticket.htsget.next = page.htsget.next;
+        }
+        Ok(ticket)
+    }
+
+    async fn ticket_page(&self, url: &url::Url) -> Result<HtsgetResponse> {
+        let mut headers = HashMap::from([("Accept".to_string(), ticket_accept())]);
+        if let Some(token) = &self.bearer_token {
+            headers.insert("Authorization".to_string(), format!("Bearer {}", token));
+        }
+
+        let response = get(url, &headers).await?;
+        let body = text(url, &response).await?;
+        if !response.ok() {
+            return Err(server_error(response.status(), &body));
+        }
+
+        serde_json::from_str(&body)
+            .map_err(|e| Error::Internal(format!("invalid ticket from {}: {}", url, e)))
+    }
+
+    /// Fetch every URL in a ticket and return the concatenated bytes
+    async fn fetch(&self, ticket: &HtsgetResponse) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        for entry in &ticket.htsget.urls {
+            if entry.url.starts_with("data:") {
+                buf.extend(decode_data_uri(&entry.url)?);
+                continue;
+            }
+
+            let url = resolve_url(&self.base_url, &entry.url)?;
+            let response = get(&url, entry.headers.as_ref().unwrap_or(&HashMap::new())).await?;
+            if !response.ok() {
+                return Err(Error::Internal(format!(
+                    "data request to {} returned {}",
+                    url,
+                    response.status()
+                )));
+            }
+            buf.extend(bytes(&url, &response).await?);
+        }
+
+        check_md5(ticket.htsget.md5.as_deref(), Md5::new_with_prefix(&buf))?;
+        Ok(buf)
+    }
+}
+
+/// `GET` `url` with `fetch`
+async fn get(url: &url::Url, headers: &HashMap<String, String>) -> Result<web_sys::Response> {
+    let error = |e| request_error(url, e);
+
+    let request_headers = web_sys::Headers::new().map_err(error)?;
+    for (name, value) in headers {
+        request_headers.set(name, value).map_err(error)?;
+    }
+    let init = web_sys::RequestInit::new();
+    init.set_method("GET");
+    init.set_headers(&request_headers);
+    let request = web_sys::Request::new_with_str_and_init(url.as_str(), &init).map_err(error)?;
+
+    JsFuture::from(fetch_request(&request))
+        .await
+        .and_then(|response| response.dyn_into())
+        .map_err(error)
+}
+
+async fn text(url: &url::Url, response: &web_sys::Response) -> Result<String> {
+    let error = |e| request_error(url, e);
+    let text = JsFuture::from(response.text().map_err(error)?)
+        .await
+        .map_err(error)?;
+    Ok(text.as_string().unwrap_or_default())
+}
+
+async fn bytes(url: &url::Url, response: &web_sys::Response) -> Result<Vec<u8>> {
+    let error = |e| request_error(url, e);
+    let buffer = JsFuture::from(response.array_buffer().map_err(error)?)
+        .await
+        .map_err(error)?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
@@ -0,0 +1,320 @@
+//! htsget spec conformance against a local server on the bundled test data.
+//!
+//! Unlike `integration_tests`, which exercise the router in-process, these
+//! cases go over real HTTP and follow ticket URLs, so the server's base URL
+//! must point at the bound address.
+
+#![cfg(all(feature = "server", feature = "client"))]
+
+use htsgetr::{
+    client::{HtsgetClient, TicketRequest},
+    conformance::{Conformance, Fixtures, validate},
+    handlers::{AppState, create_router},
+    storage::LocalStorage,
+    types::Format,
+};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+fn test_data_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data")
+}
+
+async fn spawn_server() -> String {
+    spawn_server_in(test_data_dir()).await
+}
+
+async fn spawn_server_in(data_dir: PathBuf) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let state = AppState {
+        storage: Arc::new(LocalStorage::new(data_dir, base_url.clone())),
+        base_url: base_url.clone(),
+        #[cfg(feature = "auth")]
+        url_signer: None,
+        #[cfg(feature = "refget")]
+        refget: None,
+        trusted_proxies: None,
+        cache_control: None,
+        data_mode: Default::default(),
+        endpoints: Default::default(),
+        path_prefix: None,
+        standalone_headers: None,
+        default_formats: Default::default(),
+        ticket_cache: None,
+        plain_json_tickets: false,
+        ticket_url_ttl: None,
+        ticket_limits: Default::default(),
+        index_readers: Default::default(),
+        reference_aliases: None,
+        observers: Default::default(),
+        #[cfg(feature = "jws")]
+        ticket_signer: None,
+        #[cfg(feature = "checksums")]
+        checksums: Default::default(),
+    };
+
+    tokio::spawn(async move { axum::serve(listener, create_router(state)).await });
+    base_url
+}
+
+#[tokio::test]
+async fn test_conformance() {
+    let base_url = spawn_server().await;
+
+    let report = Conformance::new(&base_url, Fixtures::default())
+        .unwrap()
+        .run()
+        .await;
+
+    assert!(!report.results.is_empty());
+    assert_eq!(report.failed(), 0, "\n{}", report);
+}
+
+/// Header and body slices concatenate into files `samtools quickcheck`
+/// accepts: whole blocks, ending with the EOF marker.
+#[tokio::test]
+async fn test_sliced_files_are_complete() {
+    let base_url = spawn_server().await;
+    let client = HtsgetClient::new(&base_url);
+
+    let samples = [
+        ("reads", "mt", Format::Bam),
+        ("reads", "sample", Format::Bam),
+        ("variants", "sample", Format::Vcf),
+    ];
+    for (endpoint, id, format) in samples {
+        for region in ["chr1", "chr1:1-1000", "chr1:5000-"] {
+            let request = TicketRequest {
+                format: Some(format),
+                class: None,
+                region: Some(region.parse().unwrap()),
+            };
+            let ticket = client.ticket(endpoint, id, &request).await.unwrap();
+            let data = client.fetch(&ticket).await.unwrap();
+
+            if let Err(e) = validate(format, &data, false) {
+                panic!("{}/{} {}: {}", endpoint, id, region, e);
+            }
+        }
+    }
+}
+
+/// A record's reference name, 0-based half-open span, and an identity used
+/// to match it between the original file and a slice
+type Placed = (String, u64, u64, String);
+
+/// Mapped records of a complete BAM, CRAM, VCF.gz or BCF file. CRAM
+/// records decode without the reference, as the bundled CRAM doesn't need
+/// one.
+fn placed_records(format: Format, data: &[u8]) -> Vec<Placed> {
+    use noodles::{bam, bcf, bgzf, cram, vcf};
+
+    let mut placed = Vec::new();
+    match format {
+        Format::Bam => {
+            let mut reader = bam::io::Reader::new(data);
+            let header = reader.read_header().unwrap();
+            for record in reader.records() {
+                let record = record.unwrap();
+                let (Some(id), Some(start), Some(span)) = (
+                    record.reference_sequence_id(),
+                    record.alignment_start(),
+                    record.alignment_span(),
+                ) else {
+                    continue;
+                };
+                let (name, _) = header.reference_sequences().get_index(id.unwrap()).unwrap();
+                let start = usize::from(start.unwrap()) as u64 - 1;
+                let end = start + (span.unwrap() as u64).max(1);
+                placed.push((name.to_string(), start, end, format!("{:?}", record)));
+            }
+        }
+        Format::Cram => {
+            let mut reader = cram::io::Reader::new(data);
+            let header = reader.read_header().unwrap();
+            for record in reader.records(&header) {
+                let record = record.unwrap();
+                let (Some(id), Some(start), Some(end)) = (
+                    record.reference_sequence_id(),
+                    record.alignment_start(),
+                    record.alignment_end(),
+                ) else {
+                    continue;
+                };
+                let (name, _) = header.reference_sequences().get_index(id).unwrap();
+                let start = usize::from(start) as u64 - 1;
+                let end = (usize::from(end) as u64).max(start + 1);
+                placed.push((name.to_string(), start, end, format!("{:?}", record)));
+            }
+        }
+        Format::Vcf => {
+            let mut reader = vcf::io::Reader::new(bgzf::io::Reader::new(data));
+            reader.read_header().unwrap();
+            for record in reader.records() {
+                let record = record.unwrap();
+                let Some(start) = record.variant_start() else {
+                    continue;
+                };
+                let start = usize::from(start.unwrap()) as u64 - 1;
+                let name = record.reference_sequence_name().to_string();
+                placed.push((name, start, start + 1, format!("{:?}", record)));
+            }
+        }
+        Format::Bcf => {
+            let mut reader = bcf::io::Reader::new(data);
+            let header = reader.read_header().unwrap();
+            for record in reader.records() {
+                let record = record.unwrap();
+                let Some(start) = record.variant_start() else {
+                    continue;
+                };
+                let start = usize::from(start.unwrap()) as u64 - 1;
+                let name = record
+                    .reference_sequence_name(header.string_maps())
+                    .unwrap()
+                    .to_string();
+                placed.push((name, start, start + 1, format!("{:?}", record)));
+            }
+        }
+        _ => unreachable!("no record checks for {:?}", format),
+    }
+    placed
+}
+
+/// Write the records of the VCF.gz at `src` as a BCF at `dst`, indexed
+/// as bcftools would (CSI, `dst.csi`)
+fn write_bcf(src: &Path, dst: &Path) {
+    use noodles::csi::binning_index::Indexer;
+    use noodles::csi::binning_index::index::reference_sequence::{bin::Chunk, index::BinnedIndex};
+    use noodles::vcf::variant::{Record as _, io::Write as _};
+    use noodles::{bcf, bgzf, csi, vcf};
+
+    let file = std::fs::File::open(src).unwrap();
+    let mut reader = vcf::io::Reader::new(bgzf::io::Reader::new(file));
+    let header = reader.read_header().unwrap();
+    let mut writer = bcf::io::Writer::new(std::fs::File::create(dst).unwrap());
+    writer.write_header(&header).unwrap();
+
+    let mut indexer = Indexer::<BinnedIndex>::default();
+    for record in reader.records() {
+        let record = record.unwrap();
+        let start_position = writer.get_ref().virtual_position();
+        writer.write_variant_record(&header, &record).unwrap();
+        let chunk = Chunk::new(start_position, writer.get_ref().virtual_position());
+
+        let id = header
+            .contigs()
+            .get_index_of(record.reference_sequence_name())
+            .unwrap();
+        let start = record.variant_start().unwrap().unwrap();
+        let end = record.variant_end(&header).unwrap();
+        indexer
+            .add_record(Some((id, start, end, true)), chunk)
+            .unwrap();
+    }
+    writer.try_finish().unwrap();
+
+    let index = indexer.build(header.contigs().len());
+    csi::write(format!("{}.csi", dst.display()), &index).unwrap();
+}
+
+fn overlaps(record: &Placed, region: &htsgetr::types::Region) -> bool {
+    let (name, start, end, _) = record;
+    *name == region.reference_name
+        && region.start.is_none_or(|s| *end > s)
+        && region.end.is_none_or(|e| *start < e)
+}
+
+/// The full protocol end to end: fetch every ticket URL, concatenate the
+/// blocks, and read the result back. Every record of the original file
+/// that overlaps the region must be in the slice, and the slice must hold
+/// nothing that isn't in the original (blocks may carry records outside
+/// the region, which clients filter).
+///
+/// No BCF is bundled, so one (with its CSI) is written from the VCF.
+#[tokio::test]
+async fn test_sliced_records_match_region() {
+    let data_dir = tempfile::tempdir().unwrap();
+    for entry in std::fs::read_dir(test_data_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        std::fs::copy(&path, data_dir.path().join(path.file_name().unwrap())).unwrap();
+    }
+    write_bcf(
+        &data_dir.path().join("sample.vcf.gz"),
+        &data_dir.path().join("sample.bcf"),
+    );
+    let base_url = spawn_server_in(data_dir.path().to_path_buf()).await;
+    let client = HtsgetClient::new(&base_url);
+
+    let samples = [
+        ("reads", "mt", Format::Bam, "bam"),
+        ("reads", "sample", Format::Bam, "bam"),
+        ("reads", "sample", Format::Cram, "cram"),
+        ("variants", "sample", Format::Vcf, "vcf.gz"),
+        ("variants", "sample", Format::Bcf, "bcf"),
+    ];
+    let regions = [
+        "chr1",
+        "chr1:1-200",
+        "chr1:150-250",
+        "chr1:1000000-",
+        "chr2",
+        "chrM",
+    ];
+
+    for (endpoint, id, format, ext) in samples {
+        let original = std::fs::read(data_dir.path().join(format!("{}.{}", id, ext))).unwrap();
+        let all = placed_records(format, &original);
+        assert!(!all.is_empty(), "no records in {}.{}", id, ext);
+
+        for region in regions {
+            let request = TicketRequest {
+                format: Some(format),
+                class: None,
+                region: Some(region.parse().unwrap()),
+            };
+            let region: htsgetr::types::Region = region.parse().unwrap();
+            let context = format!("{}/{} {:?} {:?}", endpoint, id, format, region);
+            let ticket = match client.ticket(endpoint, id, &request).await {
+                Ok(ticket) => ticket,
+                // References absent from the file's header
+                Err(htsgetr::Error::NotFound(_)) => {
+                    assert!(
+                        !all.iter().any(|r| r.0 == region.reference_name),
+                        "{}: not found",
+                        context
+                    );
+                    continue;
+                }
+                Err(e) => panic!("{}: {}", context, e),
+            };
+            let data = client.fetch(&ticket).await.unwrap();
+
+            if let Err(e) = validate(format, &data, false) {
+                panic!("{}: {}", context, e);
+            }
+
+            let sliced = placed_records(format, &data);
+            for record in all.iter().filter(|r| overlaps(r, &region)) {
+                assert!(
+                    sliced.contains(record),
+                    "{}: missing {} at {}",
+                    context,
+                    record.0,
+                    record.1 + 1
+                );
+            }
+            for record in &sliced {
+                assert!(
+                    all.contains(record),
+                    "{}: unexpected {} at {}",
+                    context,
+                    record.0,
+                    record.1 + 1
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+// htsgetr demo UI: request a ticket, show it, and download the slice it
+// describes. Everything is fetched relative to the page, so the UI works
+// when the server is mounted under a path prefix.
+
+"use strict";
+
+const api = location.pathname.replace(/\/ui\/.*$/, "");
+const form = document.getElementById("query");
+const statusLine = document.getElementById("status");
+const ticketView = document.getElementById("ticket");
+const downloadButton = document.getElementById("download");
+
+const extensions = {
+  BAM: "bam",
+  CRAM: "cram",
+  VCF: "vcf.gz",
+  BCF: "bcf",
+  FASTA: "fa",
+  FASTQ: "fq",
+};
+
+let ticket = null;
+
+function status(message, isError = false) {
+  statusLine.textContent = message;
+  statusLine.classList.toggle("error", isError);
+}
+
+function authHeaders() {
+  const token = form.elements.token.value.trim();
+  sessionStorage.setItem("htsgetr-token", token);
+  return token ? { Authorization: `Bearer ${token}` } : {};
+}
+
+// Only send the token to this server, never to presigned storage URLs
+function isSameOrigin(url) {
+  return new URL(url, location.href).origin === location.origin;
+}
+
+async function getJson(path) {
+  // Absolute URLs are the server's own, e.g. the next page of a ticket
+  const url = path.includes("://") ? path : api + path;
+  const response = await fetch(url, { headers: authHeaders() });
+  const body = await response.json().catch(() => null);
+  if (!response.ok) {
+    const message = body?.htsget?.message ?? response.statusText;
+    throw new Error(`${response.status}: ${message}`);
+  }
+  return body;
+}
+
+function datasetPath() {
+  const { endpoint, id } = form.elements;
+  return `/${endpoint.value}/${encodeURIComponent(id.value.trim())}`;
+}
+
+async function loadFormats() {
+  const endpoint = form.elements.endpoint.value;
+  const select = form.elements.format;
+  select.length = 1;
+  try {
+    const info = await getJson(`/${endpoint}/service-info`);
+    document.getElementById("service").textContent = `${info.name} ${info.version}`;
+    for (const format of info.htsget.formats) {
+      select.add(new Option(format, format));
+    }
+  } catch (e) {
+    status(`service-info: ${e.message}`, true);
+  }
+}
+
+async function loadReferences() {
+  const list = document.getElementById("references");
+  list.replaceChildren();
+  const { endpoint, id } = form.elements;
+  if (!id.value.trim() || endpoint.value === "sequences") {
+    return;
+  }
+  try {
+    const body = await getJson(`${datasetPath()}/references`);
+    for (const reference of body.htsget.referenceSequences) {
+      const option = new Option(reference.length ? `${reference.length} bp` : "");
+      option.value = reference.name;
+      list.append(option);
+    }
+  } catch {
+    // References are a convenience; the ticket request reports real errors
+  }
+}
+
+async function requestTicket(event) {
+  event.preventDefault();
+  ticket = null;
+  downloadButton.disabled = true;
+  ticketView.textContent = "";
+
+  const params = new URLSearchParams();
+  for (const name of ["format", "referenceName", "start", "end", "class"]) {
+    const value = form.elements[name].value.trim();
+    if (value) {
+      params.set(name, value);
+    }
+  }
+
+  status("Requesting ticket…");
+  try {
+    const query = params.toString();
+    const body = await getJson(datasetPath() + (query ? `?${query}` : ""));
+    ticket = body.htsget;
+    // Paged tickets: gather every page's URLs
+    while (ticket.next) {
+      status("Requesting ticket page…");
+      const page = (await getJson(ticket.next)).htsget;
+      ticket.urls.push(...page.urls);
+      ticket.next = page.next;
+    }
+    ticketView.textContent = JSON.stringify(body, null, 2);
+    downloadButton.disabled = false;
+    const expiry = ticket.expiresAt ? `, expires ${ticket.expiresAt}` : "";
+    status(`${ticket.urls.length} URL(s), ${ticket.format}${expiry}`);
+  } catch (e) {
+    status(e.message, true);
+  }
+}
+
+async function downloadSlice() {
+  if (!ticket) {
+    return;
+  }
+  downloadButton.disabled = true;
+  const parts = [];
+  try {
+    for (const [i, entry] of ticket.urls.entries()) {
+      status(`Fetching block ${i + 1} of ${ticket.urls.length}…`);
+      const headers = { ...(entry.headers ?? {}) };
+      if (isSameOrigin(entry.url)) {
+        Object.assign(headers, authHeaders());
+      }
+      const response = await fetch(entry.url, { headers });
+      if (!response.ok) {
+        throw new Error(`block ${i + 1}: ${response.status} ${response.statusText}`);
+      }
+      parts.push(await response.blob());
+    }
+
+    const blob = new Blob(parts);
+    const link = document.createElement("a");
+    const name = form.elements.id.value.trim().replaceAll("/", "_");
+    link.href = URL.createObjectURL(blob);
+    link.download = `${name}.${extensions[ticket.format] ?? "bin"}`;
+    link.click();
+    URL.revokeObjectURL(link.href);
+    status(`Downloaded ${blob.size} bytes`);
+  } catch (e) {
+    status(e.message, true);
+  } finally {
+    downloadButton.disabled = false;
+  }
+}
+
+form.elements.token.value = sessionStorage.getItem("htsgetr-token") ?? "";
+form.elements.endpoint.addEventListener("change", () => {
+  loadFormats();
+  loadReferences();
+});
+form.elements.id.addEventListener("change", loadReferences);
+form.addEventListener("submit", requestTicket);
+downloadButton.addEventListener("click", downloadSlice);
+loadFormats();